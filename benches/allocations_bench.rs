@@ -0,0 +1,87 @@
+// Counts the heap allocations done while running a few programs, to be able to
+// compare the memory behaviour of the engines between changes.
+//
+// This is not a criterion benchmark, it only prints the number of allocations
+// done by each engine for each program.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use chimpanzee::utils::{compile_program, execute_interpreter, execute_vm, parse_program};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const INTEGER_LOOP: &str = r"
+let a = 0;
+let even = 0;
+while (a < 1000) {
+    let even = if (a - (a / 2) * 2 == 0) { even + 1 } else { even };
+    let a = a + 1;
+}
+even;
+";
+
+const BOOLEAN_LOGIC: &str = r"
+let a = 0;
+let result = true;
+while (a < 1000) {
+    let result = (!result && ((a < 500) || (a >= 500))) == true;
+    let a = a + 1;
+}
+result;
+";
+
+const FIBONACCI_15: &str = r"
+let fibonacci = fn(x) {
+    if (x < 2) {
+        x
+    } else {
+        fibonacci(x - 1) + fibonacci(x - 2)
+    }
+};
+fibonacci(15);
+";
+
+fn count_allocations(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    f();
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+fn main() {
+    let programs = [
+        ("integer loop", INTEGER_LOOP),
+        ("boolean logic", BOOLEAN_LOGIC),
+        ("fibonacci 15", FIBONACCI_15),
+    ];
+
+    for (name, input) in programs {
+        let program = parse_program(input);
+        let interpreter = count_allocations(|| execute_interpreter(&program));
+
+        let compiler = compile_program(&program);
+        let vm = count_allocations(|| {
+            execute_vm(&compiler);
+        });
+
+        println!("{name}: interpreter {interpreter} allocations, vm {vm} allocations");
+    }
+}
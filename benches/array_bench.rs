@@ -17,7 +17,7 @@ push_n(a, 500);
 
 pub fn array_append_compiler_benchmark(c: &mut Criterion) {
     let program = parse_program(ARRAY_APPEND);
-    let compiler = compile_program(program);
+    let compiler = compile_program(&program);
     c.bench_function("Array append 100000 compiler", |b| {
         b.iter(|| execute_vm(black_box(&compiler)));
     });
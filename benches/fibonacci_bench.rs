@@ -1,6 +1,9 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 
-use chimpanzee::utils::{compile_program, execute_interpreter, execute_vm, parse_program};
+use chimpanzee::utils::{
+    compile_program, compile_register_program, execute_interpreter, execute_register_vm,
+    execute_vm, parse_program,
+};
 
 const FIBONACCI_20: &str = r"
         let fibonacci = fn(x) {
@@ -19,12 +22,20 @@ const FIBONACCI_20: &str = r"
 
 pub fn compiler_benchmark(c: &mut Criterion) {
     let program = parse_program(FIBONACCI_20);
-    let compiler = compile_program(program);
+    let compiler = compile_program(&program);
     c.bench_function("fibonacci 20 compiler", |b| {
         b.iter(|| execute_vm(black_box(&compiler)));
     });
 }
 
+pub fn register_benchmark(c: &mut Criterion) {
+    let program = parse_program(FIBONACCI_20);
+    let compiler = compile_register_program(&program);
+    c.bench_function("fibonacci 20 register", |b| {
+        b.iter(|| execute_register_vm(black_box(&compiler)));
+    });
+}
+
 pub fn interpreter_benchmark(c: &mut Criterion) {
     let program = parse_program(FIBONACCI_20);
     c.bench_function("fibonacci 20 interpreter", |b| {
@@ -32,5 +43,10 @@ pub fn interpreter_benchmark(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, compiler_benchmark, interpreter_benchmark);
+criterion_group!(
+    benches,
+    compiler_benchmark,
+    register_benchmark,
+    interpreter_benchmark
+);
 criterion_main!(benches);
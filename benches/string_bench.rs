@@ -0,0 +1,40 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use chimpanzee::utils::{compile_program, execute_interpreter, execute_vm, parse_program};
+
+// Every iteration copies the string in the environment, the array and the
+// arguments of `push`, on top of the concatenation itself
+const STRING_CONCATENATION: &str = r#"
+let line = "the quick brown fox jumps over the lazy dog";
+let lines = [];
+let text = "";
+let i = 0;
+while (i < 300) {
+    let text = text + line;
+    let lines = push(lines, line);
+    let i = i + 1;
+}
+len(text) + len(lines)
+"#;
+
+pub fn string_concatenation_compiler_benchmark(c: &mut Criterion) {
+    let program = parse_program(STRING_CONCATENATION);
+    let compiler = compile_program(&program);
+    c.bench_function("String concatenation compiler", |b| {
+        b.iter(|| execute_vm(black_box(&compiler)));
+    });
+}
+
+pub fn string_concatenation_interpreter_benchmark(c: &mut Criterion) {
+    let program = parse_program(STRING_CONCATENATION);
+    c.bench_function("String concatenation interpreter", |b| {
+        b.iter(|| execute_interpreter(black_box(&program)));
+    });
+}
+
+criterion_group!(
+    benches,
+    string_concatenation_compiler_benchmark,
+    string_concatenation_interpreter_benchmark
+);
+criterion_main!(benches);
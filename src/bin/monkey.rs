@@ -1,8 +1,15 @@
 use chimpanzee::repl::ReplCli;
 use clap::Parser;
-use std::error::Error;
+use std::process::ExitCode;
 
-fn main() -> Result<(), Box<dyn Error>> {
+fn main() -> ExitCode {
     let args = ReplCli::parse();
-    args.run()
+    match args.run() {
+        Ok(code) => code,
+        Err(error) => {
+            // Most errors already end with a line break
+            eprintln!("{}", error.to_string().trim_end());
+            ExitCode::FAILURE
+        }
+    }
 }
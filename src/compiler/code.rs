@@ -1,10 +1,21 @@
-use byteorder::{BigEndian, ByteOrder, WriteBytesExt};
+use byteorder::{BigEndian, ByteOrder, ReadBytesExt, WriteBytesExt};
 use enum_stringify::EnumStringify;
 use num_derive::{FromPrimitive, ToPrimitive};
 use num_traits::FromPrimitive;
-use std::fmt::Display;
+use std::{
+    fmt::{Display, Write},
+    io::Read,
+    rc::Rc,
+};
+
+use crate::{
+    compiler::{symbol_table::SymbolScope, Bytecode, Compiler},
+    object::{CompiledFunction, Object},
+    source::{Position, SourceMap},
+};
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Instructions {
     pub data: Vec<u8>,
 }
@@ -17,24 +28,22 @@ impl Default for Instructions {
 
 impl Display for Instructions {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut i = 0;
-        while i < self.data.len() {
-            let op = Opcode::from_u8(self.data[i])
-                .unwrap_or_else(|| panic!("ERROR: Unknown opcode: {}", self.data[i]));
-            let widths = op.lookup_widths();
-            let (operands, read) = Opcode::read_operands(&widths, &self.data[i + 1..]);
-            writeln!(
-                f,
-                "{:04} {}",
-                i,
-                self.format_instruction(op, &widths, &operands)
-            )?;
-            i += 1 + read as usize;
-        }
-        Ok(())
+        write!(f, "{}", self.disassemble(&DisasmOptions::default()))
     }
 }
 
+/// Options controlling the output of [`Instructions::disassemble`].
+///
+/// The default options produce the same output as the `Display` implementation.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct DisasmOptions {
+    /// Show the raw bytes of every instruction in a hex column.
+    pub hex: bool,
+
+    /// Label every jump target and annotate jumps with the label they go to.
+    pub jump_labels: bool,
+}
+
 impl Instructions {
     pub fn new(data: Vec<u8>) -> Instructions {
         Instructions { data }
@@ -51,6 +60,10 @@ impl Instructions {
         }
 
         match operand_count {
+            4 => format!(
+                "{operand} {} {} {} {}",
+                operands[0], operands[1], operands[2], operands[3]
+            ),
             2 => format!("{operand} {} {}", operands[0], operands[1]),
             1 => format!("{operand} {}", operands[0]),
             0 => format!("{operand}"),
@@ -58,12 +71,100 @@ impl Instructions {
         }
     }
 
+    pub fn disassemble(&self, options: &DisasmOptions) -> String {
+        self.disassemble_annotated(options, |_, _| None)
+    }
+
+    /// Disassembles the instructions like [`disassemble`], adding a comment
+    /// to every instruction for which `annotate` returns one. It is called with
+    /// the opcode and the operands of the instruction.
+    ///
+    /// [`disassemble`]: Instructions::disassemble
+    pub fn disassemble_annotated(
+        &self,
+        options: &DisasmOptions,
+        annotate: impl Fn(Opcode, &[i32]) -> Option<String>,
+    ) -> String {
+        let labels = if options.jump_labels {
+            self.jump_labels()
+        } else {
+            Vec::new()
+        };
+        let label_at = |offset: usize| {
+            labels
+                .iter()
+                .position(|target| *target == offset)
+                .map(|label| format!("L{label}:\n"))
+                .unwrap_or_default()
+        };
+
+        let mut output = String::new();
+        let mut i = 0;
+        while i < self.data.len() {
+            let op = Opcode::from_u8(self.data[i])
+                .unwrap_or_else(|| panic!("ERROR: Unknown opcode: {}", self.data[i]));
+            let widths = op.lookup_widths();
+            let (operands, read) = Opcode::read_operands(&widths, &self.data[i + 1..]);
+            let end = i + 1 + read as usize;
+
+            output.push_str(&label_at(i));
+            let _ = write!(output, "{i:04} ");
+            if options.hex {
+                let bytes = self.data[i..end]
+                    .iter()
+                    .map(|byte| format!("{byte:02x}"))
+                    .collect::<Vec<String>>()
+                    .join(" ");
+                let _ = write!(output, "{bytes:<11} ");
+            }
+            output.push_str(&self.format_instruction(op, &widths, &operands));
+            if let Some(target) = op.jump_operand().filter(|_| options.jump_labels) {
+                if let Some(label) = labels.iter().position(|t| *t == operands[target] as usize) {
+                    let _ = write!(output, " -> L{label}");
+                }
+            }
+            if let Some(annotation) = annotate(op, &operands) {
+                let _ = write!(output, " // {annotation}");
+            }
+            output.push('\n');
+            i = end;
+        }
+        // A jump can target the position right after the last instruction
+        output.push_str(&label_at(self.data.len()));
+        output
+    }
+
+    /// Iterates over the instructions, giving the position, the opcode and the
+    /// operands of each of them. The iteration stops at the first unknown
+    /// opcode.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, Opcode, Vec<i32>)> + '_ {
+        let mut i = 0;
+        std::iter::from_fn(move || {
+            let op = Opcode::from_u8(*self.data.get(i)?)?;
+            let (operands, read) = Opcode::read_operands(&op.lookup_widths(), &self.data[i + 1..]);
+            let position = i;
+            i += 1 + read as usize;
+            Some((position, op, operands))
+        })
+    }
+
+    /// Returns the sorted and deduplicated targets of every jump instruction.
+    fn jump_labels(&self) -> Vec<usize> {
+        let mut targets: Vec<usize> = self
+            .iter()
+            .filter_map(|(_, op, operands)| Some(operands[op.jump_operand()?] as usize))
+            .collect();
+        targets.sort_unstable();
+        targets.dedup();
+        targets
+    }
+
     pub fn append(&mut self, mut new_instructions: Instructions) {
         self.data.append(&mut new_instructions.data);
     }
 }
 
-#[derive(Debug, PartialEq, FromPrimitive, ToPrimitive, Clone, Copy, EnumStringify)]
+#[derive(Debug, PartialEq, Eq, Hash, FromPrimitive, ToPrimitive, Clone, Copy, EnumStringify)]
 #[enum_stringify(prefix = "Op")]
 pub enum Opcode {
     // Constants
@@ -121,6 +222,25 @@ pub enum Opcode {
 
     // Stack
     Pop,
+
+    // Jumps with a 4 bytes operand, used in functions larger than 64 KiB
+    JumpNotTruthyWide,
+    JumpWide,
+
+    // Index assignment, the operand is the number of indexes
+    SetIndex,
+
+    // Jump to one of the jumps following it, see `Compiler::compile_switch`
+    JumpTable,
+
+    // Superinstructions, see `superinstructions::fuse`
+    AddConstants,
+    CompareLocalConstantJump,
+    CompareConstantLocalJump,
+
+    // Global looked up by name when it runs, the operand is the constant of
+    // the name. It reads the globals `eval` defines, see `Compiler::late_globals`
+    GetGlobalByName,
 }
 
 impl Opcode {
@@ -131,6 +251,7 @@ impl Opcode {
             | Opcode::JumpNotTruthy
             | Opcode::SetGlobal
             | Opcode::GetGlobal
+            | Opcode::GetGlobalByName
             | Opcode::Array
             | Opcode::HashMap => vec![2],
 
@@ -138,13 +259,84 @@ impl Opcode {
             | Opcode::SetLocal
             | Opcode::GetLocal
             | Opcode::GetBuiltin
-            | Opcode::GetFree => vec![1],
+            | Opcode::GetFree
+            | Opcode::SetIndex => vec![1],
             Opcode::Closure => vec![2, 1],
+            Opcode::JumpTable | Opcode::AddConstants => vec![2, 2],
+            Opcode::CompareLocalConstantJump => vec![1, 2, 1, 2],
+            Opcode::CompareConstantLocalJump => vec![2, 1, 1, 2],
+            Opcode::JumpWide | Opcode::JumpNotTruthyWide => vec![4],
 
             _ => vec![],
         }
     }
 
+    pub fn is_jump(&self) -> bool {
+        matches!(
+            self,
+            Opcode::Jump | Opcode::JumpNotTruthy | Opcode::JumpWide | Opcode::JumpNotTruthyWide
+        )
+    }
+
+    /// Returns the index of the operand holding the target of the instruction,
+    /// if it can jump. The jumps have it first, the superinstructions ending
+    /// with a jump last.
+    pub fn jump_operand(&self) -> Option<usize> {
+        match self {
+            Opcode::CompareLocalConstantJump | Opcode::CompareConstantLocalJump => Some(3),
+            _ if self.is_jump() => Some(0),
+            _ => None,
+        }
+    }
+
+    /// Returns the indexes of the operands which are indexes of constants.
+    pub fn constant_operands(&self) -> &'static [usize] {
+        match self {
+            Opcode::Constant
+            | Opcode::Closure
+            | Opcode::CompareConstantLocalJump
+            | Opcode::GetGlobalByName => &[0],
+            Opcode::AddConstants => &[0, 1],
+            Opcode::CompareLocalConstantJump => &[1],
+            _ => &[],
+        }
+    }
+
+    /// Returns the same jump with a 2 bytes operand if `narrow`, with a 4 bytes
+    /// operand otherwise.
+    ///
+    /// # Panics
+    ///
+    /// If the opcode is not a jump.
+    #[must_use]
+    pub fn jump_with_width(&self, narrow: bool) -> Opcode {
+        match (self, narrow) {
+            (Opcode::Jump | Opcode::JumpWide, true) => Opcode::Jump,
+            (Opcode::Jump | Opcode::JumpWide, false) => Opcode::JumpWide,
+            (Opcode::JumpNotTruthy | Opcode::JumpNotTruthyWide, true) => Opcode::JumpNotTruthy,
+            (Opcode::JumpNotTruthy | Opcode::JumpNotTruthyWide, false) => Opcode::JumpNotTruthyWide,
+            _ => panic!("{self} is not a jump"),
+        }
+    }
+
+    /// Checks that the operands fit in their widths, `make` would truncate them
+    /// otherwise.
+    ///
+    /// # Errors
+    ///
+    /// If an operand does not fit in its width.
+    pub fn check_operands(&self, operands: &[i32]) -> Result<(), String> {
+        for (operand, width) in operands.iter().zip(self.lookup_widths()) {
+            let max = (1_i64 << (8 * width)) - 1;
+            if *operand < 0 || i64::from(*operand) > max {
+                return Err(format!(
+                    "{self} operand {operand} is too large, the limit is {max}"
+                ));
+            }
+        }
+        Ok(())
+    }
+
     #[allow(clippy::needless_pass_by_value)]
     pub fn make(&self, operands: Vec<i32>) -> Instructions {
         let widths = self.lookup_widths();
@@ -153,6 +345,9 @@ impl Opcode {
 
         for (operand, width) in operands.iter().zip(widths) {
             match width {
+                4 => instructions
+                    .write_u32::<BigEndian>(*operand as u32)
+                    .unwrap(),
                 2 => instructions
                     .write_u16::<BigEndian>(*operand as u16)
                     .unwrap(),
@@ -164,12 +359,16 @@ impl Opcode {
         Instructions::new(instructions)
     }
 
-    fn read_operands(widths: &Vec<u32>, ins: &[u8]) -> (Vec<i32>, i32) {
+    pub(crate) fn read_operands(widths: &Vec<u32>, ins: &[u8]) -> (Vec<i32>, i32) {
         let mut operands: Vec<i32> = Vec::new();
         let mut offset = 0;
 
         for width in widths {
             match width {
+                4 => {
+                    operands.push(read_u32(&ins[offset..offset + 4]) as i32);
+                    offset += 4;
+                }
                 2 => {
                     operands.push(i32::from(read_u16(&ins[offset..offset + 2])));
                     offset += 2;
@@ -213,9 +412,259 @@ pub fn read_u16(data: &[u8]) -> u16 {
     BigEndian::read_u16(data)
 }
 
+/// Reads a big endian u32 from a byte slice, like [`read_u16`].
+pub fn read_u32(data: &[u8]) -> u32 {
+    BigEndian::read_u32(data)
+}
+
+/// First bytes of every serialized bytecode.
+const MAGIC: &[u8; 4] = b"\x7fMKC";
+
+/// Version of the serialized format, to increase whenever it changes.
+const FORMAT_VERSION: u16 = 2;
+
+/// Tags of the constants in serialized bytecode.
+const INTEGER_TAG: u8 = 0;
+const STRING_TAG: u8 = 1;
+const BYTES_TAG: u8 = 2;
+const FUNCTION_TAG: u8 = 3;
+
+/// Serializes bytecode to the `.mkc` format, read back by [`deserialize`].
+///
+/// The format is a sequence of big endian fields. A header with a magic number,
+/// the version of the format and the [`opcodes_checksum`] comes first, then the
+/// instructions of the main program, the constants, and the names of the global symbols in the
+/// order of their indexes, which `eval` needs to compile code at runtime.
+/// Lengths and counts are `u32`, strings and byte arrays are prefixed by their
+/// length, and every constant starts with a tag giving its type. The source of
+/// the program is not kept, but the [`SourceMap`] of the main program and of
+/// every function follow their instructions, as a count and the instruction,
+/// line and column of every statement.
+///
+/// # Panics
+///
+/// If a constant cannot be stored in bytecode, which the compiler never
+/// produces.
+pub fn serialize(bytecode: &Bytecode) -> Vec<u8> {
+    let mut data = MAGIC.to_vec();
+    data.write_u16::<BigEndian>(FORMAT_VERSION).unwrap();
+    data.write_u32::<BigEndian>(opcodes_checksum()).unwrap();
+    write_bytes(&mut data, &bytecode.instructions.data);
+    write_source_map(&mut data, &bytecode.source_map);
+
+    write_length(&mut data, bytecode.constants.len());
+    for constant in bytecode.constants.iter() {
+        match constant {
+            Object::INTEGER(i) => {
+                data.push(INTEGER_TAG);
+                data.write_i64::<BigEndian>(*i).unwrap();
+            }
+            Object::STRING(s) => {
+                data.push(STRING_TAG);
+                write_bytes(&mut data, s.as_bytes());
+            }
+            Object::BYTES(b) => {
+                data.push(BYTES_TAG);
+                write_bytes(&mut data, b);
+            }
+            Object::COMPILEDFUNCTION(function) => {
+                data.push(FUNCTION_TAG);
+                write_bytes(&mut data, &function.instructions);
+                write_source_map(&mut data, &function.source_map);
+                write_length(&mut data, function.num_locals);
+                write_length(&mut data, function.num_parameters);
+                match &function.name {
+                    Some(name) => {
+                        data.push(1);
+                        write_bytes(&mut data, name.as_bytes());
+                    }
+                    None => data.push(0),
+                }
+            }
+            other => panic!("{} constants cannot be serialized", other.get_type()),
+        }
+    }
+
+    let mut globals: Vec<_> = bytecode
+        .symbol_table
+        .symbols()
+        .filter(|symbol| symbol.scope == SymbolScope::Global)
+        .collect();
+    globals.sort_by_key(|symbol| symbol.index);
+    write_length(&mut data, globals.len());
+    for symbol in globals {
+        write_bytes(&mut data, symbol.name.as_bytes());
+    }
+
+    data
+}
+
+/// Reads bytecode written by [`serialize`].
+///
+/// Bytecode written with another version of the format, or with other opcodes,
+/// is refused: its operands would be misread.
+///
+/// # Errors
+///
+/// If the data is not bytecode written by [`serialize`] with this version
+/// of the format and these opcodes.
+pub fn deserialize(data: &[u8]) -> Result<Bytecode, String> {
+    let mut reader = data;
+    match reader.get(..MAGIC.len()) {
+        Some(magic) if magic == MAGIC => reader = &reader[MAGIC.len()..],
+        _ => return Err("invalid bytecode: not a bytecode file".to_string()),
+    }
+    let version = reader.read_u16::<BigEndian>().map_err(end_of_data)?;
+    if version != FORMAT_VERSION {
+        return Err(format!(
+            "invalid bytecode: format version {version} is not supported, expected version {FORMAT_VERSION}"
+        ));
+    }
+    if reader.read_u32::<BigEndian>().map_err(end_of_data)? != opcodes_checksum() {
+        return Err(
+            "invalid bytecode: it was compiled with different opcodes, compile it again"
+                .to_string(),
+        );
+    }
+    let instructions = Instructions::new(read_bytes(&mut reader)?);
+    let source_map = read_source_map(&mut reader)?;
+
+    let constants = (0..read_length(&mut reader)?)
+        .map(|_| read_constant(&mut reader))
+        .collect::<Result<Vec<Object>, String>>()?;
+
+    // Defining the globals in the order of their indexes gives them back
+    // their indexes
+    let mut symbol_table = Compiler::new().symbol_table;
+    for _ in 0..read_length(&mut reader)? {
+        symbol_table.define(read_string(&mut reader)?);
+    }
+
+    if !reader.is_empty() {
+        return Err("invalid bytecode: unexpected data at the end".to_string());
+    }
+    Ok(Bytecode::new(
+        instructions,
+        Rc::new(constants),
+        symbol_table,
+        None,
+        source_map,
+    ))
+}
+
+/// Returns a checksum of the opcodes and of the widths of their operands, which
+/// changes whenever an opcode is added, removed, moved or given other operands.
+///
+/// It is the 32 bits FNV-1a hash of the names and widths, which unlike the
+/// hashers of the standard library is stable across releases.
+pub fn opcodes_checksum() -> u32 {
+    let mut hash: u32 = 0x811c_9dc5;
+    let mut add = |byte: u8| {
+        hash ^= u32::from(byte);
+        hash = hash.wrapping_mul(0x0100_0193);
+    };
+    for op in (0..=u8::MAX).map_while(Opcode::from_u8) {
+        op.to_string().bytes().for_each(&mut add);
+        for width in op.lookup_widths() {
+            add(width as u8);
+        }
+        add(0);
+    }
+    hash
+}
+
+fn write_length(data: &mut Vec<u8>, length: usize) {
+    let length = u32::try_from(length).expect("bytecode lengths must fit in 32 bits");
+    data.write_u32::<BigEndian>(length).unwrap();
+}
+
+fn write_bytes(data: &mut Vec<u8>, bytes: &[u8]) {
+    write_length(data, bytes.len());
+    data.extend_from_slice(bytes);
+}
+
+fn write_source_map(data: &mut Vec<u8>, source_map: &SourceMap) {
+    write_length(data, source_map.entries().len());
+    for (start, position) in source_map.entries() {
+        write_length(data, *start);
+        write_length(data, position.line);
+        write_length(data, position.column);
+    }
+}
+
+fn end_of_data<T>(_: T) -> String {
+    "invalid bytecode: unexpected end of the data".to_string()
+}
+
+fn read_length(reader: &mut &[u8]) -> Result<usize, String> {
+    Ok(reader.read_u32::<BigEndian>().map_err(end_of_data)? as usize)
+}
+
+fn read_bytes(reader: &mut &[u8]) -> Result<Vec<u8>, String> {
+    let length = read_length(reader)?;
+    // Checking the length first avoids allocating for corrupted lengths
+    if length > reader.len() {
+        return Err(end_of_data(()));
+    }
+    let mut bytes = vec![0; length];
+    reader.read_exact(&mut bytes).map_err(end_of_data)?;
+    Ok(bytes)
+}
+
+fn read_string(reader: &mut &[u8]) -> Result<String, String> {
+    String::from_utf8(read_bytes(reader)?)
+        .map_err(|_| "invalid bytecode: string is not valid UTF-8".to_string())
+}
+
+fn read_source_map(reader: &mut &[u8]) -> Result<SourceMap, String> {
+    let length = read_length(reader)?;
+    // Every entry takes 12 bytes
+    if length > reader.len() / 12 {
+        return Err(end_of_data(()));
+    }
+    let entries = (0..length)
+        .map(|_| {
+            let start = read_length(reader)?;
+            let line = read_length(reader)?;
+            let column = read_length(reader)?;
+            Ok((start, Position { line, column }))
+        })
+        .collect::<Result<_, String>>()?;
+    Ok(SourceMap::new(entries))
+}
+
+fn read_constant(reader: &mut &[u8]) -> Result<Object, String> {
+    match reader.read_u8().map_err(end_of_data)? {
+        INTEGER_TAG => Ok(Object::INTEGER(
+            reader.read_i64::<BigEndian>().map_err(end_of_data)?,
+        )),
+        STRING_TAG => Ok(Object::STRING(read_string(reader)?.into())),
+        BYTES_TAG => Ok(Object::BYTES(read_bytes(reader)?)),
+        FUNCTION_TAG => {
+            let instructions = read_bytes(reader)?;
+            let source_map = read_source_map(reader)?;
+            let num_locals = read_length(reader)?;
+            let num_parameters = read_length(reader)?;
+            let name = match reader.read_u8().map_err(end_of_data)? {
+                0 => None,
+                _ => Some(read_string(reader)?),
+            };
+            Ok(Object::COMPILEDFUNCTION(Rc::new(CompiledFunction {
+                instructions,
+                num_locals,
+                num_parameters,
+                name,
+                source_map,
+            })))
+        }
+        tag => Err(format!("invalid bytecode: unknown constant tag {tag}")),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{parser::parse, source::Source, vm::VM};
 
     #[test]
     fn test_make() {
@@ -236,6 +685,11 @@ mod tests {
                 vec![65534, 255],
                 vec![Opcode::Closure as u8, 255, 254, 255],
             ),
+            (
+                Opcode::JumpWide,
+                vec![65536],
+                vec![Opcode::JumpWide as u8, 0, 1, 0, 0],
+            ),
         ];
 
         for (op, operands, expected) in tests {
@@ -244,7 +698,7 @@ mod tests {
         }
     }
 
-    fn check_instruction(expected: &Vec<u8>, actual: &Instructions) {
+    fn check_instruction(expected: &[u8], actual: &Instructions) {
         let expected_len = expected.len();
         let actual_len = actual.data.len();
 
@@ -275,12 +729,73 @@ mod tests {
         assert_eq!(test_instruction.to_string(), expected);
     }
 
+    #[test]
+    fn test_instructions_disassemble() {
+        let instructions = vec![
+            Opcode::True.make(vec![]),
+            Opcode::JumpNotTruthy.make(vec![10]),
+            Opcode::Constant.make(vec![0]),
+            Opcode::Jump.make(vec![11]),
+            Opcode::Null.make(vec![]),
+            Opcode::Pop.make(vec![]),
+        ];
+
+        let mut test_instruction = Instructions::default();
+        for instruction in instructions {
+            test_instruction.append(instruction);
+        }
+
+        let options = DisasmOptions {
+            hex: true,
+            jump_labels: true,
+        };
+
+        let expected = format!(
+            "0000 {:02x}          OpTrue
+0001 {:02x} 00 0a    OpJumpNotTruthy 10 -> L0
+0004 {:02x} 00 00    OpConstant 0
+0007 {:02x} 00 0b    OpJump 11 -> L1
+L0:
+0010 {:02x}          OpNull
+L1:
+0011 {:02x}          OpPop
+",
+            Opcode::True as u8,
+            Opcode::JumpNotTruthy as u8,
+            Opcode::Constant as u8,
+            Opcode::Jump as u8,
+            Opcode::Null as u8,
+            Opcode::Pop as u8,
+        );
+
+        assert_eq!(test_instruction.disassemble(&options), expected);
+        assert_eq!(
+            test_instruction.disassemble(&DisasmOptions::default()),
+            test_instruction.to_string()
+        );
+    }
+
+    #[test]
+    fn test_check_operands() {
+        assert_eq!(Opcode::Constant.check_operands(&[65535]), Ok(()));
+        assert_eq!(
+            Opcode::Constant.check_operands(&[65536]),
+            Err("OpConstant operand 65536 is too large, the limit is 65535".to_string())
+        );
+        assert_eq!(
+            Opcode::Closure.check_operands(&[0, 256]),
+            Err("OpClosure operand 256 is too large, the limit is 255".to_string())
+        );
+        assert_eq!(Opcode::JumpWide.check_operands(&[i32::MAX]), Ok(()));
+    }
+
     #[test]
     fn test_read_operands() {
         let tests = vec![
             (Opcode::Constant, vec![65535], 2),
             (Opcode::GetLocal, vec![255], 1),
             (Opcode::Closure, vec![65535, 255], 3),
+            (Opcode::JumpNotTruthyWide, vec![70000], 4),
         ];
 
         for (op, operands, bytes_read) in tests {
@@ -293,4 +808,104 @@ mod tests {
             assert_eq!(got_operands, operands, "operands wrong");
         }
     }
+
+    #[test]
+    fn test_serialize_round_trip() {
+        let input = r#"
+        let greeting = "hello";
+        let adder = fn(x) { fn(y) { x + y } };
+        let add_two = adder(2);
+        let fibonacci = fn(n) { if (n < 2) { n } else { fibonacci(n - 1) + fibonacci(n - 2) } };
+        [add_two(fibonacci(10)), greeting, b"raw", eval("greeting")]
+        "#;
+        let mut compiler = Compiler::new();
+        let source = Source::new("test.monkey", input);
+        compiler.compile(&parse(input).with_source(source)).unwrap();
+        let bytecode = compiler.bytecode();
+        assert!(!bytecode.source_map.is_empty());
+
+        let deserialized = deserialize(&serialize(&bytecode)).unwrap();
+        assert_eq!(deserialized.instructions, bytecode.instructions);
+        assert_eq!(deserialized.source_map, bytecode.source_map);
+        assert_eq!(deserialized.constants, bytecode.constants);
+        assert_eq!(deserialized.symbol_table, bytecode.symbol_table);
+
+        let mut vm = VM::new(deserialized);
+        vm.run().unwrap();
+        assert_eq!(
+            vm.last_popped_stack_element().unwrap().as_ref(),
+            &Object::ARRAY(vec![
+                Object::INTEGER(57),
+                Object::STRING("hello".into()),
+                Object::BYTES(b"raw".to_vec()),
+                Object::STRING("hello".into()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_deserialize_errors() {
+        let mut compiler = Compiler::new();
+        compiler.compile(&parse(r#"let a = "monkey"; a"#)).unwrap();
+        let data = serialize(&compiler.bytecode());
+
+        for end in [8, 13, data.len() - 1] {
+            assert_eq!(
+                deserialize(&data[..end]).err(),
+                Some("invalid bytecode: unexpected end of the data".to_string())
+            );
+        }
+
+        let mut extra = data.clone();
+        extra.push(0);
+        assert_eq!(
+            deserialize(&extra).err(),
+            Some("invalid bytecode: unexpected data at the end".to_string())
+        );
+
+        // The tag of the only constant comes after the header, the
+        // instructions, their empty source map and the number of constants
+        let mut unknown_tag = data.clone();
+        let tag = 10 + 4 + compiler.bytecode().instructions.data.len() + 4 + 4;
+        unknown_tag[tag] = 42;
+        assert_eq!(
+            deserialize(&unknown_tag).err(),
+            Some("invalid bytecode: unknown constant tag 42".to_string())
+        );
+    }
+
+    #[test]
+    fn test_deserialize_header_errors() {
+        let mut compiler = Compiler::new();
+        compiler.compile(&parse("1")).unwrap();
+        let data = serialize(&compiler.bytecode());
+        assert_eq!(&data[..4], MAGIC);
+
+        for magic in [&b""[..], b"\x7fMK", b"puts(1);"] {
+            assert_eq!(
+                deserialize(magic).err(),
+                Some("invalid bytecode: not a bytecode file".to_string())
+            );
+        }
+
+        let mut version = data.clone();
+        version[5] = 42;
+        assert_eq!(
+            deserialize(&version).err(),
+            Some(
+                "invalid bytecode: format version 42 is not supported, expected version 2"
+                    .to_string()
+            )
+        );
+
+        let mut checksum = data.clone();
+        checksum[9] ^= 1;
+        assert_eq!(
+            deserialize(&checksum).err(),
+            Some(
+                "invalid bytecode: it was compiled with different opcodes, compile it again"
+                    .to_string()
+            )
+        );
+    }
 }
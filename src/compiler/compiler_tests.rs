@@ -2,12 +2,17 @@
 #[cfg(test)]
 pub mod tests {
 
+    use std::rc::Rc;
+
     use crate::{
         compiler::{
             code::Opcode,
             test_utils::{flatten_instructions, run_compiler, CompilerTestCase},
+            Compiler,
         },
         object::Object,
+        parser::parse,
+        source::{Position, Source},
     };
 
     #[test]
@@ -332,22 +337,63 @@ pub mod tests {
         run_compiler(tests);
     }
 
+    #[test]
+    fn test_too_many_globals() {
+        let mut compiler = Compiler::new();
+        compiler.set_max_globals(2);
+        assert_eq!(
+            compiler.compile(&parse("let a = 1; let b = 2; let a = 3; let c = 4;")),
+            Err("Too many global variables, the limit is 2".to_string())
+        );
+
+        // Locals are not globals
+        let mut compiler = Compiler::new();
+        compiler.set_max_globals(1);
+        assert_eq!(
+            compiler.compile(&parse("let f = fn() { let a = 1; let b = 2; a + b };")),
+            Ok(())
+        );
+    }
+
     #[test]
     fn test_string_expressions() {
         let tests = vec![
             CompilerTestCase {
                 input: r#""monkey""#.to_string(),
-                expected_constants: vec![Object::STRING("monkey".to_string())],
+                expected_constants: vec![Object::STRING("monkey".into())],
+                expected_instructions: flatten_instructions(vec![
+                    Opcode::Constant.make(vec![0]),
+                    Opcode::Pop.make(vec![]),
+                ]),
+            },
+            CompilerTestCase {
+                input: r#"b"monkey""#.to_string(),
+                expected_constants: vec![Object::BYTES(b"monkey".to_vec())],
+                expected_instructions: flatten_instructions(vec![
+                    Opcode::Constant.make(vec![0]),
+                    Opcode::Pop.make(vec![]),
+                ]),
+            },
+            CompilerTestCase {
+                input: r#""mon" + "key" + "mon""#.to_string(),
+                expected_constants: vec![
+                    Object::STRING("mon".into()),
+                    Object::STRING("key".into()),
+                ],
                 expected_instructions: flatten_instructions(vec![
                     Opcode::Constant.make(vec![0]),
+                    Opcode::Constant.make(vec![1]),
+                    Opcode::Add.make(vec![]),
+                    Opcode::Constant.make(vec![0]),
+                    Opcode::Add.make(vec![]),
                     Opcode::Pop.make(vec![]),
                 ]),
             },
             CompilerTestCase {
                 input: r#""mon" + "key""#.to_string(),
                 expected_constants: vec![
-                    Object::STRING("mon".to_string()),
-                    Object::STRING("key".to_string()),
+                    Object::STRING("mon".into()),
+                    Object::STRING("key".into()),
                 ],
                 expected_instructions: flatten_instructions(vec![
                     Opcode::Constant.make(vec![0]),
@@ -361,6 +407,72 @@ pub mod tests {
         run_compiler(tests);
     }
 
+    #[test]
+    fn test_string_constants_are_shared_with_previous_ones() {
+        let constants = vec![Object::INTEGER(1), Object::STRING("monkey".into())];
+        let mut compiler =
+            Compiler::new_with_state(Compiler::new().symbol_table, Rc::new(constants));
+        compiler.compile(&parse(r#""monkey""#)).unwrap();
+
+        assert_eq!(compiler.constants.len(), 2);
+        assert_eq!(
+            *compiler.bytecode().instructions,
+            flatten_instructions(vec![
+                Opcode::Constant.make(vec![1]),
+                Opcode::Pop.make(vec![]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_string_constants_are_indexed_after_dead_code_elimination() {
+        let mut compiler = Compiler::new();
+        compiler
+            .compile(&parse(r#"fn() { return 1; "a" }; "b""#))
+            .unwrap();
+        // "a" is unreachable and dropped, "b" is renumbered
+        assert_eq!(compiler.constants.len(), 3);
+        assert_eq!(compiler.constants[2], Object::STRING("b".into()));
+
+        // The next program reuses the index, like the next line of the REPL
+        let bytecode = compiler.into_bytecode();
+        let mut compiler = Compiler::new_with_indexed_state(
+            bytecode.symbol_table,
+            bytecode.constants,
+            bytecode.string_constants,
+        );
+        compiler.compile(&parse(r#""b"; "a""#)).unwrap();
+        assert_eq!(compiler.constants.len(), 4);
+        assert_eq!(compiler.constants[3], Object::STRING("a".into()));
+        assert_eq!(
+            *compiler.bytecode().instructions,
+            flatten_instructions(vec![
+                Opcode::Constant.make(vec![2]),
+                Opcode::Pop.make(vec![]),
+                Opcode::Constant.make(vec![3]),
+                Opcode::Pop.make(vec![]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_bytecode_shares_the_constants() {
+        let mut compiler = Compiler::new();
+        compiler.compile(&parse(r#""monkey"; 1"#)).unwrap();
+        let bytecode = compiler.bytecode();
+        assert!(Rc::ptr_eq(&bytecode.constants, &compiler.constants));
+
+        // The constants are copied if the bytecode still uses them
+        compiler.compile(&parse("2")).unwrap();
+        assert!(!Rc::ptr_eq(&bytecode.constants, &compiler.constants));
+        assert_eq!(bytecode.constants.len(), 2);
+        drop(bytecode);
+        let constants = Rc::as_ptr(&compiler.constants);
+        compiler.compile(&parse("3")).unwrap();
+        assert_eq!(Rc::as_ptr(&compiler.constants), constants);
+        assert_eq!(compiler.constants.len(), 4);
+    }
+
     #[test]
     fn test_array_expressions() {
         let tests = vec![
@@ -545,4 +657,211 @@ pub mod tests {
 
         run_compiler(tests);
     }
+
+    #[test]
+    fn test_jump_table() {
+        let tests = vec![CompilerTestCase {
+            input: "let x = 1; if (x == 1) { 10 } else { if (x == 3) { 30 } else { if (x == 2) { 20 } else { 0 } } }"
+                .to_string(),
+            expected_constants: vec![
+                Object::INTEGER(1),
+                Object::INTEGER(10),
+                Object::INTEGER(30),
+                Object::INTEGER(20),
+                Object::INTEGER(0),
+            ],
+            expected_instructions: flatten_instructions(vec![
+                Opcode::Constant.make(vec![0]),    // 0000
+                Opcode::SetGlobal.make(vec![0]),   // 0003
+                Opcode::GetGlobal.make(vec![0]),   // 0006
+                Opcode::JumpTable.make(vec![1, 3]), // 0009
+                Opcode::Jump.make(vec![26]),       // 0014
+                Opcode::Jump.make(vec![38]),       // 0017
+                Opcode::Jump.make(vec![32]),       // 0020
+                Opcode::Jump.make(vec![44]),       // 0023
+                Opcode::Constant.make(vec![1]),    // 0026
+                Opcode::Jump.make(vec![47]),       // 0029
+                Opcode::Constant.make(vec![2]),    // 0032
+                Opcode::Jump.make(vec![47]),       // 0035
+                Opcode::Constant.make(vec![3]),    // 0038
+                Opcode::Jump.make(vec![47]),       // 0041
+                Opcode::Constant.make(vec![4]),    // 0044
+                Opcode::Pop.make(vec![]),          // 0047
+            ]),
+        }];
+
+        run_compiler(tests);
+    }
+
+    #[test]
+    fn test_index_assignment() {
+        let tests = vec![CompilerTestCase {
+            input: "let a = [[1]]; a[0][0] = 2;".to_string(),
+            expected_constants: vec![
+                Object::INTEGER(1),
+                Object::INTEGER(0),
+                Object::INTEGER(0),
+                Object::INTEGER(2),
+            ],
+            expected_instructions: flatten_instructions(vec![
+                Opcode::Constant.make(vec![0]),
+                Opcode::Array.make(vec![1]),
+                Opcode::Array.make(vec![1]),
+                Opcode::SetGlobal.make(vec![0]),
+                Opcode::GetGlobal.make(vec![0]),
+                Opcode::Constant.make(vec![1]),
+                Opcode::Constant.make(vec![2]),
+                Opcode::Constant.make(vec![3]),
+                Opcode::SetIndex.make(vec![2]),
+                Opcode::SetGlobal.make(vec![0]),
+            ]),
+        }];
+
+        run_compiler(tests);
+    }
+
+    #[test]
+    fn test_source_map() {
+        let input = "let a = 1;\nlet b = if (a) {\n  a\n} else {\n  2\n} + 1;\nlet f = fn() {\n  return a;\n  b\n};\nf()";
+        let mut compiler = Compiler::new();
+        compiler
+            .compile(&parse(input).with_source(Source::new("test.monkey", input)))
+            .unwrap();
+        let bytecode = compiler.bytecode();
+
+        let position = |line, column| Position { line, column };
+        // The instructions following the blocks of the conditional belong to
+        // the `let` statement around it
+        assert_eq!(
+            bytecode.source_map.entries(),
+            [
+                (0, position(1, 1)),
+                (6, position(2, 1)),
+                (12, position(3, 3)),
+                (15, position(2, 1)),
+                (18, position(5, 3)),
+                (21, position(2, 1)),
+                (28, position(7, 1)),
+                (35, position(11, 1)),
+            ]
+        );
+        // The unreachable statement of the function is removed
+        match &bytecode.constants[3] {
+            Object::COMPILEDFUNCTION(function) => {
+                assert_eq!(function.source_map.entries(), [(0, position(8, 3))]);
+            }
+            constant => panic!("expected a function, got {constant}"),
+        }
+
+        // Without the source, no debug symbols are recorded
+        let mut compiler = Compiler::new();
+        compiler.compile(&parse(input)).unwrap();
+        assert!(compiler.bytecode().source_map.is_empty());
+    }
+
+    #[test]
+    fn test_shadowing_warnings() {
+        use crate::compiler::warning::CompilerWarning;
+
+        let input = "let a = 1;\nlet len = 2;\nlet f = fn(b) {\n  let a = b;\n  let c = fn() { let b = 3; b };\n  let f = 4;\n  c() + a\n};\nlet a = f(1);";
+        let program = parse(input).with_source(Source::new("test.monkey", input));
+        let mut compiler = Compiler::new();
+        compiler.compile(&program).unwrap();
+        assert_eq!(compiler.warnings(), []);
+
+        let position = |line, column| Some(Position { line, column });
+        let mut compiler = Compiler::new();
+        compiler.set_shadowing_warnings(true);
+        compiler.compile(&program).unwrap();
+        // Builtins are not reported, and the name of a function has no
+        // position
+        assert_eq!(
+            compiler.warnings(),
+            [
+                CompilerWarning::Shadowing {
+                    name: "a".to_string(),
+                    position: position(4, 3),
+                    defined_at: position(1, 1),
+                },
+                CompilerWarning::Shadowing {
+                    name: "b".to_string(),
+                    position: position(5, 18),
+                    defined_at: position(3, 1),
+                },
+                CompilerWarning::Shadowing {
+                    name: "f".to_string(),
+                    position: position(6, 3),
+                    defined_at: None,
+                },
+                CompilerWarning::Shadowing {
+                    name: "a".to_string(),
+                    position: position(9, 1),
+                    defined_at: position(1, 1),
+                },
+            ]
+        );
+        assert_eq!(
+            compiler.warnings()[0].to_string(),
+            "warning: `let` rebinds `a`, already defined at 1:1"
+        );
+    }
+
+    #[test]
+    fn test_strict_redefinitions() {
+        use crate::strict::StrictConfig;
+
+        let compile_strict = |input: &str| {
+            let mut compiler = Compiler::new();
+            compiler.set_strict(StrictConfig::strict());
+            compiler.compile(&parse(input).with_source(Source::new("test.monkey", input)))
+        };
+        assert_eq!(
+            compile_strict("let x = 1;\nlet x = 2;"),
+            Err("`x` is already defined at 1:1".to_string())
+        );
+        assert_eq!(
+            compile_strict("let f = fn(a) {\n  let a = 1;\n};"),
+            Err("`a` is already defined at 1:1".to_string())
+        );
+        // Index assignments, names of outer functions and builtins are not
+        // redefinitions
+        assert_eq!(
+            compile_strict(
+                "let x = [1]; x[0] = 2; let len = 1; let f = fn() { let x = 1; let f = 2; x }"
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            Compiler::new().compile(&parse("let x = 1; let x = 2;")),
+            Ok(())
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        use crate::{compiler::Bytecode, parser::ast::Program, vm::VM};
+
+        let input = r#"let f = fn(x) { x * 2 }; let h = {1: "a", "b": [f(2)]}; h["b"][0] + f(4)"#;
+        let program = parse(input).with_source(Source::new("test.monkey", input));
+        let json = serde_json::to_string(&program).unwrap();
+        let deserialized: Program = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, program);
+
+        let mut compiler = Compiler::new();
+        compiler.compile(&deserialized).unwrap();
+        let bytecode = compiler.bytecode();
+        let json = serde_json::to_string(&bytecode).unwrap();
+        let deserialized: Bytecode = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized.instructions, bytecode.instructions);
+        assert_eq!(deserialized.constants, bytecode.constants);
+        assert_eq!(deserialized.source_map, bytecode.source_map);
+
+        let mut vm = VM::new(deserialized);
+        vm.run().unwrap();
+        assert_eq!(
+            *vm.last_popped_stack_element().unwrap(),
+            Object::INTEGER(12)
+        );
+    }
 }
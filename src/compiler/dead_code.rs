@@ -0,0 +1,334 @@
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{
+    compiler::{
+        code::{Instructions, Opcode},
+        dedup_source_map,
+    },
+    object::Object,
+    source::Position,
+};
+
+/// Removes the instructions which can never run, such as the ones following a
+/// `return`, a `break` or a `continue`, and updates the jumps to the new
+/// positions of their targets.
+///
+/// An instruction is reachable from the start by falling through the previous
+/// instruction, unless it is a jump or a return, or by jumping to it.
+///
+/// This is also where jumps get their final width. The compiler emits wide
+/// jumps, since it does not know yet how far they go, and they are all made
+/// narrow when the trimmed instructions fit in the range of a 2 bytes operand.
+///
+/// The statements of `source_map` are moved along with their instructions.
+///
+/// It runs before the superinstructions are made, which it does not know.
+pub fn remove_unreachable(
+    instructions: &Instructions,
+    source_map: &mut Vec<(usize, Position)>,
+) -> Instructions {
+    let decoded: Vec<(usize, Opcode, Vec<i32>)> = instructions.iter().collect();
+    let index_of: HashMap<usize, usize> = decoded
+        .iter()
+        .enumerate()
+        .map(|(i, (position, _, _))| (*position, i))
+        .collect();
+
+    let mut reachable = vec![false; decoded.len()];
+    let mut pending = if decoded.is_empty() { vec![] } else { vec![0] };
+    while let Some(i) = pending.pop() {
+        if reachable[i] {
+            continue;
+        }
+        reachable[i] = true;
+
+        let (_, op, operands) = &decoded[i];
+        if *op == Opcode::JumpTable {
+            // The jumps of the table follow it, with the default one last
+            let entries = operands[1] as usize + 1;
+            pending.extend((i + 1..=i + entries).filter(|entry| *entry < decoded.len()));
+        }
+        if op.is_jump() {
+            // A jump can target the end of the instructions
+            if let Some(target) = index_of.get(&(operands[0] as usize)) {
+                pending.push(*target);
+            }
+        }
+        if !matches!(
+            op,
+            Opcode::Jump | Opcode::JumpWide | Opcode::ReturnValue | Opcode::Return
+        ) && i + 1 < decoded.len()
+        {
+            pending.push(i + 1);
+        }
+    }
+
+    // Every target is at most the length of the instructions
+    let mut narrow = true;
+    let mut new_positions = layout(&decoded, &reachable, instructions.data.len(), narrow);
+    if new_positions[&instructions.data.len()] > usize::from(u16::MAX) {
+        narrow = false;
+        new_positions = layout(&decoded, &reachable, instructions.data.len(), narrow);
+    }
+
+    // A removed statement starts where the next instruction kept does
+    let end = new_positions[&instructions.data.len()];
+    for entry in source_map.iter_mut() {
+        entry.0 = new_positions.get(&entry.0).copied().unwrap_or(end);
+    }
+    source_map.retain(|(start, _)| *start < end);
+    dedup_source_map(source_map);
+
+    let mut trimmed = Instructions::default();
+    for (i, (_, op, operands)) in decoded.into_iter().enumerate() {
+        if !reachable[i] {
+            continue;
+        }
+        if op.is_jump() {
+            let target = new_positions[&(operands[0] as usize)] as i32;
+            trimmed.append(op.jump_with_width(narrow).make(vec![target]));
+        } else {
+            trimmed.append(op.make(operands));
+        }
+    }
+    trimmed
+}
+
+/// Returns the new position of every old position, `end` included, once the
+/// unreachable instructions are removed and the jumps are made narrow or wide.
+fn layout(
+    decoded: &[(usize, Opcode, Vec<i32>)],
+    reachable: &[bool],
+    end: usize,
+    narrow: bool,
+) -> HashMap<usize, usize> {
+    let mut new_positions = HashMap::new();
+    let mut position = 0;
+    for (i, (old_position, op, _)) in decoded.iter().enumerate() {
+        new_positions.insert(*old_position, position);
+        if reachable[i] {
+            let op = if op.is_jump() {
+                op.jump_with_width(narrow)
+            } else {
+                *op
+            };
+            position += 1 + op
+                .lookup_widths()
+                .iter()
+                .map(|width| *width as usize)
+                .sum::<usize>();
+        }
+    }
+    new_positions.insert(end, position);
+    new_positions
+}
+
+/// Drops the constants from index `first` on which are not used by
+/// `instructions`, nor by the functions among the used constants, and
+/// renumbers the remaining ones.
+///
+/// The constants before `first` are always kept, the code compiled before, in
+/// the REPL for example, can still refer to them.
+pub fn remove_unused_constants(
+    instructions: &mut Instructions,
+    constants: &mut Vec<Object>,
+    first: usize,
+) {
+    let mut used = vec![false; constants.len()];
+    let mut pending = vec![instructions.clone()];
+    while let Some(instructions) = pending.pop() {
+        for index in constant_operands(&instructions) {
+            if index < first || used[index] {
+                continue;
+            }
+            used[index] = true;
+            if let Object::COMPILEDFUNCTION(function) = &constants[index] {
+                pending.push(Instructions::new(function.instructions.clone()));
+            }
+        }
+    }
+
+    let mut new_indexes: Vec<usize> = (0..constants.len()).collect();
+    let mut next = first;
+    for (index, new_index) in new_indexes.iter_mut().enumerate().skip(first) {
+        if used[index] {
+            *new_index = next;
+            next += 1;
+        }
+    }
+    if next == constants.len() {
+        return;
+    }
+
+    let mut index = 0;
+    constants.retain(|_| {
+        index += 1;
+        index - 1 < first || used[index - 1]
+    });
+    renumber_constants(instructions, &new_indexes);
+    for constant in constants.iter_mut().skip(first) {
+        if let Object::COMPILEDFUNCTION(function) = constant {
+            let function = Rc::make_mut(function);
+            let mut instructions = Instructions::new(std::mem::take(&mut function.instructions));
+            renumber_constants(&mut instructions, &new_indexes);
+            function.instructions = instructions.data;
+        }
+    }
+}
+
+/// Returns the indexes of the constants loaded by the instructions.
+fn constant_operands(instructions: &Instructions) -> Vec<usize> {
+    instructions
+        .iter()
+        .flat_map(|(_, op, operands)| {
+            op.constant_operands()
+                .iter()
+                .map(move |i| operands[*i] as usize)
+        })
+        .collect()
+}
+
+fn renumber_constants(instructions: &mut Instructions, new_indexes: &[usize]) {
+    let changes: Vec<(usize, Instructions)> = instructions
+        .iter()
+        .filter(|(_, op, _)| !op.constant_operands().is_empty())
+        .map(|(position, op, mut operands)| {
+            for i in op.constant_operands() {
+                operands[*i] = new_indexes[operands[*i] as usize] as i32;
+            }
+            (position, op.make(operands))
+        })
+        .collect();
+    // The operands keep their width, the instructions are replaced in place
+    for (position, instruction) in changes {
+        instructions.data[position..position + instruction.data.len()]
+            .copy_from_slice(&instruction.data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::{
+        compiler::{
+            code::Opcode,
+            test_utils::{
+                flatten_instructions, flatten_u8_instructions, run_compiler, CompilerTestCase,
+            },
+            Compiler,
+        },
+        object::{CompiledFunction, Object},
+        parser::parse,
+        source::SourceMap,
+    };
+
+    #[test]
+    fn test_code_after_return_is_removed() {
+        let tests = vec![
+            CompilerTestCase {
+                input: "fn() { return 1; 2 }".to_string(),
+                expected_constants: vec![
+                    Object::INTEGER(1),
+                    Object::COMPILEDFUNCTION(Rc::new(CompiledFunction {
+                        instructions: flatten_u8_instructions(vec![
+                            Opcode::Constant.make(vec![0]),
+                            Opcode::ReturnValue.make(vec![]),
+                        ]),
+                        num_locals: 0,
+                        num_parameters: 0,
+                        name: None,
+                        source_map: SourceMap::default(),
+                    })),
+                ],
+                expected_instructions: flatten_instructions(vec![
+                    Opcode::Closure.make(vec![1, 0]),
+                    Opcode::Pop.make(vec![]),
+                ]),
+            },
+            CompilerTestCase {
+                input: "fn() { if (true) { return 1 } else { return 2 }; 3 }".to_string(),
+                expected_constants: vec![
+                    Object::INTEGER(1),
+                    Object::INTEGER(2),
+                    Object::COMPILEDFUNCTION(Rc::new(CompiledFunction {
+                        instructions: flatten_u8_instructions(vec![
+                            Opcode::True.make(vec![]),           // 000
+                            Opcode::JumpNotTruthy.make(vec![8]), // 001
+                            Opcode::Constant.make(vec![0]),      // 004
+                            Opcode::ReturnValue.make(vec![]),    // 007
+                            Opcode::Constant.make(vec![1]),      // 008
+                            Opcode::ReturnValue.make(vec![]),    // 011
+                        ]),
+                        num_locals: 0,
+                        num_parameters: 0,
+                        name: None,
+                        source_map: SourceMap::default(),
+                    })),
+                ],
+                expected_instructions: flatten_instructions(vec![
+                    Opcode::Closure.make(vec![2, 0]),
+                    Opcode::Pop.make(vec![]),
+                ]),
+            },
+        ];
+
+        run_compiler(tests);
+    }
+
+    #[test]
+    fn test_jump_width() {
+        let jumps = |input: &str| {
+            let mut compiler = Compiler::new();
+            compiler.compile(&parse(input)).unwrap();
+            compiler
+                .bytecode()
+                .instructions
+                .iter()
+                .map(|(_, op, _)| op)
+                .filter(Opcode::is_jump)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            jumps("let a = 1; if (a) { a } else { 2 }"),
+            vec![Opcode::JumpNotTruthy, Opcode::Jump]
+        );
+
+        // Every statement takes 10 bytes, more than 65535 in total
+        let body = "let a = a + a;".repeat(7000);
+        assert_eq!(
+            jumps(&format!("let a = 1; if (a) {{ {body} }} else {{ 2 }}")),
+            vec![Opcode::JumpNotTruthyWide, Opcode::JumpWide]
+        );
+    }
+
+    #[test]
+    fn test_too_many_constants() {
+        let mut compiler = Compiler::new();
+        assert_eq!(
+            compiler.compile(&parse(&"1;".repeat(65537))),
+            Err("OpConstant operand 65536 is too large, the limit is 65535".to_string())
+        );
+    }
+
+    #[test]
+    fn test_previous_constants_are_kept() {
+        let mut compiler = Compiler::new_with_state(
+            Compiler::new().symbol_table,
+            Rc::new(vec![Object::INTEGER(7)]),
+        );
+        compiler.compile(&parse("fn() { return 1; 2 }")).unwrap();
+
+        assert_eq!(compiler.constants.len(), 3);
+        assert_eq!(compiler.constants[0], Object::INTEGER(7));
+        assert_eq!(compiler.constants[1], Object::INTEGER(1));
+        assert_eq!(
+            *compiler.bytecode().instructions,
+            flatten_instructions(vec![
+                Opcode::Closure.make(vec![2, 0]),
+                Opcode::Pop.make(vec![]),
+            ])
+        );
+    }
+}
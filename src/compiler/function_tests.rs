@@ -2,6 +2,8 @@
 #[cfg(test)]
 pub mod tests {
 
+    use std::rc::Rc;
+
     use crate::{
         compiler::{
             code::Opcode,
@@ -10,6 +12,7 @@ pub mod tests {
             },
         },
         object::{CompiledFunction, Object},
+        source::SourceMap,
     };
 
     #[test]
@@ -20,7 +23,7 @@ pub mod tests {
                 expected_constants: vec![
                     Object::INTEGER(5),
                     Object::INTEGER(10),
-                    Object::COMPILEDFUNCTION(CompiledFunction {
+                    Object::COMPILEDFUNCTION(Rc::new(CompiledFunction {
                         instructions: flatten_u8_instructions(vec![
                             Opcode::Constant.make(vec![0]),
                             Opcode::Constant.make(vec![1]),
@@ -29,7 +32,9 @@ pub mod tests {
                         ]),
                         num_locals: 0,
                         num_parameters: 0,
-                    }),
+                        name: None,
+                        source_map: SourceMap::default(),
+                    })),
                 ],
                 expected_instructions: flatten_instructions(vec![
                     Opcode::Closure.make(vec![2, 0]),
@@ -41,7 +46,7 @@ pub mod tests {
                 expected_constants: vec![
                     Object::INTEGER(5),
                     Object::INTEGER(10),
-                    Object::COMPILEDFUNCTION(CompiledFunction {
+                    Object::COMPILEDFUNCTION(Rc::new(CompiledFunction {
                         instructions: flatten_u8_instructions(vec![
                             Opcode::Constant.make(vec![0]),
                             Opcode::Constant.make(vec![1]),
@@ -50,7 +55,9 @@ pub mod tests {
                         ]),
                         num_locals: 0,
                         num_parameters: 0,
-                    }),
+                        name: None,
+                        source_map: SourceMap::default(),
+                    })),
                 ],
                 expected_instructions: flatten_instructions(vec![
                     Opcode::Closure.make(vec![2, 0]),
@@ -62,7 +69,7 @@ pub mod tests {
                 expected_constants: vec![
                     Object::INTEGER(1),
                     Object::INTEGER(2),
-                    Object::COMPILEDFUNCTION(CompiledFunction {
+                    Object::COMPILEDFUNCTION(Rc::new(CompiledFunction {
                         instructions: flatten_u8_instructions(vec![
                             Opcode::Constant.make(vec![0]),
                             Opcode::Pop.make(vec![]),
@@ -71,7 +78,9 @@ pub mod tests {
                         ]),
                         num_locals: 0,
                         num_parameters: 0,
-                    }),
+                        name: None,
+                        source_map: SourceMap::default(),
+                    })),
                 ],
                 expected_instructions: flatten_instructions(vec![
                     Opcode::Closure.make(vec![2, 0]),
@@ -82,14 +91,16 @@ pub mod tests {
                 input: "fn() { 24 }()".to_string(),
                 expected_constants: vec![
                     Object::INTEGER(24),
-                    Object::COMPILEDFUNCTION(CompiledFunction {
+                    Object::COMPILEDFUNCTION(Rc::new(CompiledFunction {
                         instructions: flatten_u8_instructions(vec![
                             Opcode::Constant.make(vec![0]),
                             Opcode::ReturnValue.make(vec![]),
                         ]),
                         num_locals: 0,
                         num_parameters: 0,
-                    }),
+                        name: None,
+                        source_map: SourceMap::default(),
+                    })),
                 ],
                 expected_instructions: flatten_instructions(vec![
                     Opcode::Closure.make(vec![1, 0]),
@@ -101,14 +112,16 @@ pub mod tests {
                 input: "let noArg = fn() { 24 }; noArg();".to_string(),
                 expected_constants: vec![
                     Object::INTEGER(24),
-                    Object::COMPILEDFUNCTION(CompiledFunction {
+                    Object::COMPILEDFUNCTION(Rc::new(CompiledFunction {
                         instructions: flatten_u8_instructions(vec![
                             Opcode::Constant.make(vec![0]),
                             Opcode::ReturnValue.make(vec![]),
                         ]),
                         num_locals: 0,
                         num_parameters: 0,
-                    }),
+                        name: Some("noArg".to_string()),
+                        source_map: SourceMap::default(),
+                    })),
                 ],
                 expected_instructions: flatten_instructions(vec![
                     Opcode::Closure.make(vec![1, 0]),
@@ -121,11 +134,13 @@ pub mod tests {
             CompilerTestCase {
                 input: "let oneArg = fn(a) {}; oneArg(24);".to_string(),
                 expected_constants: vec![
-                    Object::COMPILEDFUNCTION(CompiledFunction {
+                    Object::COMPILEDFUNCTION(Rc::new(CompiledFunction {
                         instructions: flatten_u8_instructions(vec![Opcode::Return.make(vec![0])]),
                         num_locals: 1,
                         num_parameters: 1,
-                    }),
+                        name: Some("oneArg".to_string()),
+                        source_map: SourceMap::default(),
+                    })),
                     Object::INTEGER(24),
                 ],
                 expected_instructions: flatten_instructions(vec![
@@ -140,11 +155,13 @@ pub mod tests {
             CompilerTestCase {
                 input: "let manyArg = fn(a, b, c) { }; manyArg(24, 25, 26);".to_string(),
                 expected_constants: vec![
-                    Object::COMPILEDFUNCTION(CompiledFunction {
+                    Object::COMPILEDFUNCTION(Rc::new(CompiledFunction {
                         instructions: flatten_u8_instructions(vec![Opcode::Return.make(vec![0])]),
                         num_locals: 3,
                         num_parameters: 3,
-                    }),
+                        name: Some("manyArg".to_string()),
+                        source_map: SourceMap::default(),
+                    })),
                     Object::INTEGER(24),
                     Object::INTEGER(25),
                     Object::INTEGER(26),
@@ -163,14 +180,16 @@ pub mod tests {
             CompilerTestCase {
                 input: "let oneArg = fn(a) { a; }; oneArg(24);".to_string(),
                 expected_constants: vec![
-                    Object::COMPILEDFUNCTION(CompiledFunction {
+                    Object::COMPILEDFUNCTION(Rc::new(CompiledFunction {
                         instructions: flatten_u8_instructions(vec![
                             Opcode::GetLocal.make(vec![0]),
                             Opcode::ReturnValue.make(vec![0]),
                         ]),
                         num_locals: 1,
                         num_parameters: 1,
-                    }),
+                        name: Some("oneArg".to_string()),
+                        source_map: SourceMap::default(),
+                    })),
                     Object::INTEGER(24),
                 ],
                 expected_instructions: flatten_instructions(vec![
@@ -185,7 +204,7 @@ pub mod tests {
             CompilerTestCase {
                 input: "let manyArg = fn(a, b, c) { a; b; c; }; manyArg(24, 25, 26);".to_string(),
                 expected_constants: vec![
-                    Object::COMPILEDFUNCTION(CompiledFunction {
+                    Object::COMPILEDFUNCTION(Rc::new(CompiledFunction {
                         instructions: flatten_u8_instructions(vec![
                             Opcode::GetLocal.make(vec![0]),
                             Opcode::Pop.make(vec![0]),
@@ -196,7 +215,9 @@ pub mod tests {
                         ]),
                         num_locals: 3,
                         num_parameters: 3,
-                    }),
+                        name: Some("manyArg".to_string()),
+                        source_map: SourceMap::default(),
+                    })),
                     Object::INTEGER(24),
                     Object::INTEGER(25),
                     Object::INTEGER(26),
@@ -221,11 +242,13 @@ pub mod tests {
     fn test_function_with_no_return_value() {
         let tests = vec![CompilerTestCase {
             input: "fn() { }".to_string(),
-            expected_constants: vec![Object::COMPILEDFUNCTION(CompiledFunction {
+            expected_constants: vec![Object::COMPILEDFUNCTION(Rc::new(CompiledFunction {
                 instructions: flatten_u8_instructions(vec![Opcode::Return.make(vec![])]),
                 num_locals: 0,
                 num_parameters: 0,
-            })],
+                name: None,
+                source_map: SourceMap::default(),
+            }))],
             expected_instructions: flatten_instructions(vec![
                 Opcode::Closure.make(vec![0, 0]),
                 Opcode::Pop.make(vec![]),
@@ -245,14 +268,16 @@ pub mod tests {
                     .to_string(),
                 expected_constants: vec![
                     Object::INTEGER(55),
-                    Object::COMPILEDFUNCTION(CompiledFunction {
+                    Object::COMPILEDFUNCTION(Rc::new(CompiledFunction {
                         instructions: flatten_u8_instructions(vec![
                             Opcode::GetGlobal.make(vec![0]),
                             Opcode::ReturnValue.make(vec![]),
                         ]),
                         num_locals: 0,
                         num_parameters: 0,
-                    }),
+                        name: None,
+                        source_map: SourceMap::default(),
+                    })),
                 ],
                 expected_instructions: flatten_instructions(vec![
                     Opcode::Constant.make(vec![0]),
@@ -270,7 +295,7 @@ pub mod tests {
                 .to_string(),
                 expected_constants: vec![
                     Object::INTEGER(55),
-                    Object::COMPILEDFUNCTION(CompiledFunction {
+                    Object::COMPILEDFUNCTION(Rc::new(CompiledFunction {
                         instructions: flatten_u8_instructions(vec![
                             Opcode::Constant.make(vec![0]),
                             Opcode::SetLocal.make(vec![0]),
@@ -279,7 +304,9 @@ pub mod tests {
                         ]),
                         num_locals: 1,
                         num_parameters: 0,
-                    }),
+                        name: None,
+                        source_map: SourceMap::default(),
+                    })),
                 ],
                 expected_instructions: flatten_instructions(vec![
                     Opcode::Closure.make(vec![1, 0]),
@@ -297,7 +324,7 @@ pub mod tests {
                 expected_constants: vec![
                     Object::INTEGER(55),
                     Object::INTEGER(77),
-                    Object::COMPILEDFUNCTION(CompiledFunction {
+                    Object::COMPILEDFUNCTION(Rc::new(CompiledFunction {
                         instructions: flatten_u8_instructions(vec![
                             Opcode::Constant.make(vec![0]),
                             Opcode::SetLocal.make(vec![0]),
@@ -310,7 +337,9 @@ pub mod tests {
                         ]),
                         num_locals: 2,
                         num_parameters: 0,
-                    }),
+                        name: None,
+                        source_map: SourceMap::default(),
+                    })),
                 ],
                 expected_instructions: flatten_instructions(vec![
                     Opcode::Closure.make(vec![2, 0]),
@@ -341,7 +370,7 @@ pub mod tests {
             },
             CompilerTestCase {
                 input: "fn() { len([]); }".to_string(),
-                expected_constants: vec![Object::COMPILEDFUNCTION(CompiledFunction {
+                expected_constants: vec![Object::COMPILEDFUNCTION(Rc::new(CompiledFunction {
                     instructions: flatten_u8_instructions(vec![
                         Opcode::GetBuiltin.make(vec![0]),
                         Opcode::Array.make(vec![0]),
@@ -350,7 +379,9 @@ pub mod tests {
                     ]),
                     num_locals: 0,
                     num_parameters: 0,
-                })],
+                    name: None,
+                    source_map: SourceMap::default(),
+                }))],
                 expected_instructions: flatten_instructions(vec![
                     Opcode::Closure.make(vec![0, 0]),
                     Opcode::Pop.make(vec![]),
@@ -374,7 +405,7 @@ pub mod tests {
                 }"
                 .to_string(),
                 expected_constants: vec![
-                    Object::COMPILEDFUNCTION(CompiledFunction {
+                    Object::COMPILEDFUNCTION(Rc::new(CompiledFunction {
                         instructions: flatten_u8_instructions(vec![
                             Opcode::GetFree.make(vec![0]),
                             Opcode::GetLocal.make(vec![0]),
@@ -383,8 +414,10 @@ pub mod tests {
                         ]),
                         num_locals: 1,
                         num_parameters: 1,
-                    }),
-                    Object::COMPILEDFUNCTION(CompiledFunction {
+                        name: None,
+                        source_map: SourceMap::default(),
+                    })),
+                    Object::COMPILEDFUNCTION(Rc::new(CompiledFunction {
                         instructions: flatten_u8_instructions(vec![
                             Opcode::GetLocal.make(vec![0]),
                             Opcode::Closure.make(vec![0, 1]),
@@ -392,7 +425,9 @@ pub mod tests {
                         ]),
                         num_locals: 1,
                         num_parameters: 1,
-                    }),
+                        name: None,
+                        source_map: SourceMap::default(),
+                    })),
                 ],
                 expected_instructions: flatten_instructions(vec![
                     Opcode::Closure.make(vec![1, 0]),
@@ -411,7 +446,7 @@ pub mod tests {
                 .to_string(),
 
                 expected_constants: vec![
-                    Object::COMPILEDFUNCTION(CompiledFunction {
+                    Object::COMPILEDFUNCTION(Rc::new(CompiledFunction {
                         instructions: flatten_u8_instructions(vec![
                             Opcode::GetFree.make(vec![0]),
                             Opcode::GetFree.make(vec![1]),
@@ -422,8 +457,10 @@ pub mod tests {
                         ]),
                         num_locals: 1,
                         num_parameters: 1,
-                    }),
-                    Object::COMPILEDFUNCTION(CompiledFunction {
+                        name: None,
+                        source_map: SourceMap::default(),
+                    })),
+                    Object::COMPILEDFUNCTION(Rc::new(CompiledFunction {
                         instructions: flatten_u8_instructions(vec![
                             Opcode::GetFree.make(vec![0]),
                             Opcode::GetLocal.make(vec![0]),
@@ -432,8 +469,10 @@ pub mod tests {
                         ]),
                         num_locals: 1,
                         num_parameters: 1,
-                    }),
-                    Object::COMPILEDFUNCTION(CompiledFunction {
+                        name: None,
+                        source_map: SourceMap::default(),
+                    })),
+                    Object::COMPILEDFUNCTION(Rc::new(CompiledFunction {
                         instructions: flatten_u8_instructions(vec![
                             Opcode::GetLocal.make(vec![0]),
                             Opcode::Closure.make(vec![1, 1]),
@@ -441,7 +480,9 @@ pub mod tests {
                         ]),
                         num_locals: 1,
                         num_parameters: 1,
-                    }),
+                        name: None,
+                        source_map: SourceMap::default(),
+                    })),
                 ],
                 expected_instructions: flatten_instructions(vec![
                     Opcode::Closure.make(vec![2, 0]),
@@ -468,7 +509,7 @@ pub mod tests {
                     Object::INTEGER(66),
                     Object::INTEGER(77),
                     Object::INTEGER(88),
-                    Object::COMPILEDFUNCTION(CompiledFunction {
+                    Object::COMPILEDFUNCTION(Rc::new(CompiledFunction {
                         instructions: flatten_u8_instructions(vec![
                             Opcode::Constant.make(vec![3]),
                             Opcode::SetLocal.make(vec![0]),
@@ -483,8 +524,10 @@ pub mod tests {
                         ]),
                         num_locals: 1,
                         num_parameters: 0,
-                    }),
-                    Object::COMPILEDFUNCTION(CompiledFunction {
+                        name: None,
+                        source_map: SourceMap::default(),
+                    })),
+                    Object::COMPILEDFUNCTION(Rc::new(CompiledFunction {
                         instructions: flatten_u8_instructions(vec![
                             Opcode::Constant.make(vec![2]),
                             Opcode::SetLocal.make(vec![0]),
@@ -495,8 +538,10 @@ pub mod tests {
                         ]),
                         num_locals: 1,
                         num_parameters: 0,
-                    }),
-                    Object::COMPILEDFUNCTION(CompiledFunction {
+                        name: None,
+                        source_map: SourceMap::default(),
+                    })),
+                    Object::COMPILEDFUNCTION(Rc::new(CompiledFunction {
                         instructions: flatten_u8_instructions(vec![
                             Opcode::Constant.make(vec![1]),
                             Opcode::SetLocal.make(vec![0]),
@@ -506,7 +551,9 @@ pub mod tests {
                         ]),
                         num_locals: 1,
                         num_parameters: 0,
-                    }),
+                        name: None,
+                        source_map: SourceMap::default(),
+                    })),
                 ],
                 expected_instructions: flatten_instructions(vec![
                     Opcode::Constant.make(vec![0]),
@@ -532,7 +579,7 @@ pub mod tests {
                     .to_string(),
                 expected_constants: vec![
                     Object::INTEGER(1),
-                    Object::COMPILEDFUNCTION(CompiledFunction {
+                    Object::COMPILEDFUNCTION(Rc::new(CompiledFunction {
                         instructions: flatten_u8_instructions(vec![
                             Opcode::CurrentClosure.make(vec![]),
                             Opcode::GetLocal.make(vec![0]),
@@ -543,7 +590,9 @@ pub mod tests {
                         ]),
                         num_locals: 1,
                         num_parameters: 1,
-                    }),
+                        name: Some("countDown".to_string()),
+                        source_map: SourceMap::default(),
+                    })),
                     Object::INTEGER(1),
                 ],
                 expected_instructions: flatten_instructions(vec![
@@ -568,7 +617,7 @@ pub mod tests {
                 .to_string(),
                 expected_constants: vec![
                     Object::INTEGER(1),
-                    Object::COMPILEDFUNCTION(CompiledFunction {
+                    Object::COMPILEDFUNCTION(Rc::new(CompiledFunction {
                         instructions: flatten_u8_instructions(vec![
                             Opcode::CurrentClosure.make(vec![]),
                             Opcode::GetLocal.make(vec![0]),
@@ -579,9 +628,11 @@ pub mod tests {
                         ]),
                         num_locals: 1,
                         num_parameters: 1,
-                    }),
+                        name: Some("countDown".to_string()),
+                        source_map: SourceMap::default(),
+                    })),
                     Object::INTEGER(1),
-                    Object::COMPILEDFUNCTION(CompiledFunction {
+                    Object::COMPILEDFUNCTION(Rc::new(CompiledFunction {
                         instructions: flatten_u8_instructions(vec![
                             Opcode::Closure.make(vec![1, 0]),
                             Opcode::SetLocal.make(vec![0]),
@@ -592,7 +643,9 @@ pub mod tests {
                         ]),
                         num_locals: 1,
                         num_parameters: 0,
-                    }),
+                        name: Some("wrapper".to_string()),
+                        source_map: SourceMap::default(),
+                    })),
                 ],
                 expected_instructions: flatten_instructions(vec![
                     Opcode::Closure.make(vec![3, 0]),
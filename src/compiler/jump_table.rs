@@ -0,0 +1,158 @@
+use crate::{
+    lexer::token::Token,
+    parser::ast::{BlockStatement, Conditional, Expression, Identifier, Primitive, Statement},
+};
+
+/// Fewest arms a chain of conditionals needs to be compiled to a jump table,
+/// the comparisons are as fast below.
+const MIN_ARMS: usize = 3;
+
+/// A chain of conditionals comparing a variable to integers, such as
+///
+/// ```monkey
+/// if (op == 0) { a } else { if (op == 1) { b } else { if (op == 2) { c } else { d } } }
+/// ```
+///
+/// which can jump straight to the arm of the value of the variable instead of
+/// comparing it to every integer in turn.
+#[derive(Debug, PartialEq)]
+pub struct Switch<'a> {
+    pub variable: &'a Identifier,
+    /// Smallest integer of the arms.
+    pub min: i64,
+    /// Arm of every integer from `min` on, `None` for the gaps between them.
+    pub table: Vec<Option<usize>>,
+    pub arms: Vec<&'a BlockStatement>,
+    pub default: Option<&'a BlockStatement>,
+}
+
+impl<'a> Switch<'a> {
+    /// Returns the switch made by `conditional`, if it is a chain of at least
+    /// [`MIN_ARMS`] comparisons of the same variable to integers, and if these
+    /// integers are dense enough for the table to stay small.
+    ///
+    /// An integer compared twice goes to its first arm, like the comparisons
+    /// would. The integers must fit in the 2 bytes operand of `OpJumpTable`.
+    pub fn from_conditional(conditional: &'a Conditional) -> Option<Switch<'a>> {
+        let mut variable = None;
+        let mut values = Vec::new();
+        let mut arms = Vec::new();
+        let mut conditional = conditional;
+        let default = loop {
+            let (name, value) = compared_integer(&conditional.condition)?;
+            if variable.get_or_insert(name).value != name.value {
+                return None;
+            }
+            values.push(value);
+            arms.push(&conditional.consequence);
+
+            match conditional.alternative.as_deref() {
+                Some(block) => match block.statements.as_slice() {
+                    [Statement::Expression(Expression::Conditional(next))]
+                        if compared_integer(&next.condition).is_some() =>
+                    {
+                        conditional = next;
+                    }
+                    _ => break Some(block),
+                },
+                None => break None,
+            }
+        };
+
+        let min = *values.iter().min()?;
+        let max = *values.iter().max()?;
+        if arms.len() < MIN_ARMS || min < 0 || max > i64::from(u16::MAX) {
+            return None;
+        }
+        let size = (max - min + 1) as usize;
+        if size > 2 * arms.len() {
+            return None;
+        }
+        let mut table = vec![None; size];
+        for (arm, value) in values.into_iter().enumerate() {
+            table[(value - min) as usize].get_or_insert(arm);
+        }
+
+        Some(Switch {
+            variable: variable?,
+            min,
+            table,
+            arms,
+            default,
+        })
+    }
+}
+
+/// Returns the variable and the integer of a condition like `x == 1` or
+/// `1 == x`.
+fn compared_integer(condition: &Expression) -> Option<(&Identifier, i64)> {
+    let Expression::Infix(infix) = condition else {
+        return None;
+    };
+    if infix.token != Token::Equal {
+        return None;
+    }
+    match (infix.left.as_ref(), infix.right.as_ref()) {
+        (Expression::Identifier(name), Expression::Primitive(Primitive::IntegerLiteral(value)))
+        | (Expression::Primitive(Primitive::IntegerLiteral(value)), Expression::Identifier(name)) => {
+            Some((name, *value))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn parse_conditional(input: &str) -> Conditional {
+        match parse(input).statements.remove(0) {
+            Statement::Expression(Expression::Conditional(conditional)) => conditional,
+            _ => panic!("It is not a conditional"),
+        }
+    }
+
+    #[test]
+    fn test_switch() {
+        let conditional = parse_conditional(
+            "if (x == 3) { 1 } else { if (5 == x) { 2 } else { if (x == 4) { 3 } else { if (x == 3) { 4 } } } }",
+        );
+        let found = Switch::from_conditional(&conditional).unwrap();
+        assert_eq!(&*found.variable.value, "x");
+        assert_eq!(found.min, 3);
+        assert_eq!(found.table, vec![Some(0), Some(2), Some(1)]);
+        assert_eq!(found.arms.len(), 4);
+        assert_eq!(found.default, None);
+
+        let conditional = parse_conditional(
+            "if (x == 0) { 1 } else { if (x == 1) { 2 } else { if (x == 3) { 3 } else { 4 } } }",
+        );
+        let found = Switch::from_conditional(&conditional).unwrap();
+        assert_eq!(found.table, vec![Some(0), Some(1), None, Some(2)]);
+        assert_eq!(found.default.unwrap().to_string(), "4\n");
+    }
+
+    #[test]
+    fn test_not_a_switch() {
+        let inputs = [
+            // Too few arms
+            "if (x == 0) { 1 } else { if (x == 1) { 2 } }",
+            // Different variables
+            "if (x == 0) { 1 } else { if (y == 1) { 2 } else { if (x == 2) { 3 } } }",
+            // Not an equality
+            "if (x < 0) { 1 } else { if (x == 1) { 2 } else { if (x == 2) { 3 } } }",
+            // Too sparse
+            "if (x == 0) { 1 } else { if (x == 10) { 2 } else { if (x == 20) { 3 } } }",
+            // Negative
+            "if (x == -1) { 1 } else { if (x == 0) { 2 } else { if (x == 1) { 3 } } }",
+        ];
+        for input in inputs {
+            assert_eq!(
+                Switch::from_conditional(&parse_conditional(input)),
+                None,
+                "{input}"
+            );
+        }
+    }
+}
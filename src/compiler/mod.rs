@@ -1,8 +1,13 @@
 pub mod code;
 mod compiler_tests;
+mod dead_code;
 mod function_tests;
+mod jump_table;
+pub(crate) mod string_constants;
+mod superinstructions;
 pub mod symbol_table;
 mod test_utils;
+pub mod warning;
 mod while_tests;
 
 use std::{cell::RefCell, rc::Rc};
@@ -10,7 +15,10 @@ use std::{cell::RefCell, rc::Rc};
 use crate::{
     compiler::{
         code::{Instructions, Opcode},
-        symbol_table::{Symbol, SymbolScope, SymbolTable},
+        jump_table::Switch,
+        string_constants::StringConstants,
+        symbol_table::{Definition, Symbol, SymbolScope, SymbolTable},
+        warning::CompilerWarning,
     },
     lexer::token::Token,
     object::{
@@ -18,9 +26,13 @@ use crate::{
         {CompiledFunction, Object},
     },
     parser::ast::{
-        BlockStatement, Conditional, Expression, FunctionLiteral, InfixOperator, LetStatement,
-        LoopStatement, Primitive, Program, Statement, WhileStatement,
+        walk_program, BlockStatement, Conditional, Expression, FunctionLiteral, Identifier,
+        IndexAssignment, InfixOperator, LetStatement, LoopStatement, Primitive, Program, Statement,
+        Visitor, WhileStatement,
     },
+    source::{LineIndex, Position, Source, SourceMap},
+    strict::{redefinition_error, StrictConfig},
+    vm::GLOBALS_SIZE,
 };
 
 use num_traits::FromPrimitive;
@@ -37,6 +49,14 @@ struct CompilerScope {
     last_instruction: Option<EmittedInstruction>,
     previous_instruction: Option<EmittedInstruction>,
     loop_scope: Option<Rc<RefCell<LoopScope>>>,
+
+    /// First instruction and position of the statements compiled so far, it
+    /// becomes the [`SourceMap`] of the function.
+    source_map: Vec<(usize, Position)>,
+
+    /// Name of the compiled function, `None` for the main program and the
+    /// anonymous functions.
+    function: Option<String>,
 }
 
 impl Default for CompilerScope {
@@ -52,6 +72,8 @@ impl CompilerScope {
             last_instruction: None,
             previous_instruction: None,
             loop_scope: None,
+            source_map: Vec::new(),
+            function: None,
         }
     }
 
@@ -93,12 +115,48 @@ impl LoopScope {
 }
 
 pub struct Compiler {
-    pub constants: Vec<Object>,
+    /// Constants of the program, shared with the bytecode, so that the REPL
+    /// and `eval` can keep compiling with them without copying them.
+    pub constants: Rc<Vec<Object>>,
+
+    /// Index of the string constants, shared with the bytecode like the
+    /// constants.
+    string_constants: Rc<StringConstants>,
 
     pub symbol_table: SymbolTable,
 
     scopes: Vec<CompilerScope>,
     scope_index: usize,
+
+    /// Number of globals the program can define, it must not exceed the size
+    /// of the global store of the VM running it.
+    max_globals: usize,
+
+    /// Whether the hot sequences of instructions are fused into
+    /// superinstructions, see [`superinstructions::fuse`].
+    superinstructions: bool,
+
+    source: Option<Source>,
+
+    /// Positions of the lines of the source, debug symbols are only recorded
+    /// when it is known.
+    lines: Option<LineIndex>,
+
+    /// Every symbol defined so far, in the order of definition.
+    definitions: Vec<Definition>,
+
+    /// Whether a `let` rebinding a name defined before is reported.
+    shadowing_warnings: bool,
+
+    warnings: Vec<CompilerWarning>,
+
+    strict: StrictConfig,
+
+    /// Whether the program calls `eval`, whose `let`s define globals only
+    /// known when it runs. Its undefined variables are then looked up by name
+    /// when they are read, with an `OpGetGlobalByName`, instead of being an
+    /// error.
+    late_globals: bool,
 }
 
 impl Default for Compiler {
@@ -116,67 +174,306 @@ impl Compiler {
         }
 
         Compiler {
-            constants: vec![],
+            constants: Rc::default(),
+            string_constants: Rc::default(),
 
             symbol_table,
 
             scopes: vec![main_scope],
             scope_index: 0,
+
+            max_globals: GLOBALS_SIZE,
+
+            superinstructions: true,
+
+            source: None,
+            lines: None,
+
+            definitions: Vec::new(),
+
+            shadowing_warnings: false,
+            warnings: Vec::new(),
+
+            strict: StrictConfig::default(),
+
+            late_globals: false,
         }
     }
 
-    pub fn new_with_state(symbol_table: SymbolTable, constants: Vec<Object>) -> Self {
+    pub fn new_with_state(symbol_table: SymbolTable, constants: Rc<Vec<Object>>) -> Self {
         let mut compiler = Compiler::new();
         compiler.symbol_table = symbol_table;
         compiler.constants = constants;
         compiler
     }
 
-    pub fn compile(&mut self, program: Program) -> Result<(), String> {
-        self.compile_statements(program.statements)
+    /// Returns a compiler like [`Compiler::new_with_state`], reusing the index
+    /// of the string constants of the bytecode they come from, so that only
+    /// the constants added since are indexed.
+    pub(crate) fn new_with_indexed_state(
+        symbol_table: SymbolTable,
+        constants: Rc<Vec<Object>>,
+        string_constants: Rc<StringConstants>,
+    ) -> Self {
+        let mut compiler = Compiler::new_with_state(symbol_table, constants);
+        compiler.string_constants = string_constants;
+        compiler
     }
 
-    fn compile_block_statement(&mut self, block: BlockStatement) -> Result<(), String> {
-        self.compile_statements(block.statements)
+    /// Sets the number of globals the program can define, at most
+    /// [`GLOBALS_SIZE`].
+    pub fn set_max_globals(&mut self, max_globals: usize) {
+        self.max_globals = max_globals.min(GLOBALS_SIZE);
     }
 
-    fn compile_statements(&mut self, statements: Vec<Statement>) -> Result<(), String> {
-        for statement in statements {
+    /// Sets whether the hot sequences of instructions are fused into
+    /// superinstructions, which is the default. The instructions are easier to
+    /// follow in a disassembly or a trace without them.
+    pub fn set_superinstructions(&mut self, enabled: bool) {
+        self.superinstructions = enabled;
+    }
+
+    /// Sets whether a `let` binding a name the same function or an outer one
+    /// already defined is reported in the [`Compiler::warnings`]. It is off
+    /// by default, since `let x = x + 1;` is how variables are updated.
+    pub fn set_shadowing_warnings(&mut self, enabled: bool) {
+        self.shadowing_warnings = enabled;
+    }
+
+    /// Sets the checks of the strict mode. The compiler only checks
+    /// redefinitions, the VM running the program checks the conditions and
+    /// undefined identifiers are always an error.
+    pub fn set_strict(&mut self, strict: StrictConfig) {
+        self.strict = strict;
+    }
+
+    /// Returns the warnings of the compiled programs, in the order of the
+    /// statements.
+    pub fn warnings(&self) -> &[CompilerWarning] {
+        &self.warnings
+    }
+
+    pub fn compile(&mut self, program: &Program) -> Result<(), String> {
+        self.source.clone_from(&program.source);
+        self.lines = self
+            .source
+            .as_ref()
+            .map(|source| LineIndex::new(&source.text));
+        let first_constant = self.constants.len();
+        Rc::make_mut(&mut self.string_constants).update(&self.constants);
+        self.late_globals = uses_eval(program);
+        self.compile_statements(&program.statements, &program.offsets)?;
+        self.eliminate_dead_code(first_constant);
+        Ok(())
+    }
+
+    /// Removes the unreachable instructions of the main program and fuses its
+    /// superinstructions, then drops the constants added since
+    /// `first_constant` which are no longer used. Functions are trimmed as
+    /// they are compiled.
+    fn eliminate_dead_code(&mut self, first_constant: usize) {
+        let scope = &mut self.scopes[self.scope_index];
+        scope.instructions =
+            dead_code::remove_unreachable(&scope.instructions, &mut scope.source_map);
+        if self.superinstructions {
+            scope.instructions =
+                superinstructions::fuse(&scope.instructions, &mut scope.source_map);
+        }
+
+        let mut emitted: Vec<EmittedInstruction> = scope
+            .instructions
+            .iter()
+            .map(|(position, opcode, _)| EmittedInstruction { opcode, position })
+            .collect();
+        scope.last_instruction = emitted.pop();
+        scope.previous_instruction = emitted.pop();
+
+        // The constants added may be renumbered, only they are indexed again
+        let string_constants = Rc::make_mut(&mut self.string_constants);
+        string_constants.forget_from(first_constant, &self.constants);
+        dead_code::remove_unused_constants(
+            &mut scope.instructions,
+            Rc::make_mut(&mut self.constants),
+            first_constant,
+        );
+        string_constants.update(&self.constants);
+    }
+
+    /// Compiles a program which returns the value of its last statement, if it
+    /// is an expression, instead of leaving it on the stack. The instructions
+    /// can then run as the body of a function, like `eval` does.
+    ///
+    /// # Errors
+    ///
+    /// If the program cannot be compiled, like [`Compiler::compile`].
+    pub fn compile_returning(&mut self, program: &Program) -> Result<(), String> {
+        self.compile(program)?;
+        if self.last_instruction_is(Opcode::Pop) {
+            self.replace_last_pop_with_return();
+        } else {
+            self.emit(Opcode::Return, vec![])?;
+        }
+        Ok(())
+    }
+
+    fn compile_block_statement(&mut self, block: &BlockStatement) -> Result<(), String> {
+        self.compile_statements(&block.statements, &block.offsets)
+    }
+
+    fn compile_statements(
+        &mut self,
+        statements: &[Statement],
+        offsets: &[usize],
+    ) -> Result<(), String> {
+        // The instructions following a block belong to the statement around it
+        let enclosing = self.scopes[self.scope_index]
+            .source_map
+            .last()
+            .map(|(_, position)| *position);
+
+        for (i, statement) in statements.iter().enumerate() {
+            if let (Some(offset), Some(lines)) = (offsets.get(i), &self.lines) {
+                let position = lines.position(*offset);
+                self.add_position(position);
+            }
             self.compile_statement(statement)?;
         }
 
+        if let Some(position) = enclosing {
+            self.add_position(position);
+        }
         Ok(())
     }
 
-    fn compile_statement(&mut self, statement: Statement) -> Result<(), String> {
+    /// Records that the next instructions come from the statement at
+    /// `position`.
+    fn add_position(&mut self, position: Position) {
+        let scope = &mut self.scopes[self.scope_index];
+        let start = scope.instructions.data.len();
+        match scope.source_map.last_mut() {
+            // The previous statement did not emit anything
+            Some(last) if last.0 == start => last.1 = position,
+            Some(last) if last.1 == position => {}
+            _ => scope.source_map.push((start, position)),
+        }
+    }
+
+    fn compile_statement(&mut self, statement: &Statement) -> Result<(), String> {
         match statement {
             Statement::Expression(s) => {
                 self.compile_expression(s)?;
-                self.emit(Opcode::Pop, vec![]);
+                self.emit(Opcode::Pop, vec![])?;
             }
             Statement::Let(s) => {
                 self.compiler_let_statement(s)?;
             }
             Statement::Return(r) => {
-                self.compile_expression(r.return_value)?;
-                self.emit(Opcode::ReturnValue, vec![]);
+                self.compile_expression(&r.return_value)?;
+                self.emit(Opcode::ReturnValue, vec![])?;
             }
             Statement::While(wh) => {
                 self.compile_while_statement(wh)?;
             }
 
-            Statement::LoopStatements(smt) => self.compile_loop_statement(&smt),
+            Statement::LoopStatements(smt) => self.compile_loop_statement(smt)?,
+            Statement::IndexAssignment(s) => self.compile_index_assignment(s)?,
         }
 
         Ok(())
     }
 
-    fn compiler_let_statement(&mut self, s: LetStatement) -> Result<(), String> {
+    fn compiler_let_statement(&mut self, s: &LetStatement) -> Result<(), String> {
+        if self.strict.no_redefinitions {
+            if let Some((_, position)) = self.symbol_table.lookup_variable(&s.name.value) {
+                let error = redefinition_error(&s.name.value);
+                return Err(match position {
+                    Some(position) => format!("{error} at {position}"),
+                    None => error,
+                });
+            }
+        }
+        if self.shadowing_warnings {
+            self.check_shadowing(&s.name.value);
+        }
+        let symbol = self.define_variable(&s.name.value)?;
+        self.compile_expression(&s.value)?;
+        self.emit_set_variable(&symbol)
+    }
+
+    /// Compiles `a[i][j] = v` to the collection of `a`, the indexes and `v`,
+    /// followed by an `OpSetIndex` replacing the element, and the collection
+    /// is then assigned to `a` like a `let` would.
+    fn compile_index_assignment(&mut self, s: &IndexAssignment) -> Result<(), String> {
+        let depth = s.indexes.len() as i32;
+        // The collection is read before the variable is defined, which makes
+        // a local one in a function
+        self.compile_identifier(&s.name)?;
+        for index in &s.indexes {
+            self.compile_expression(index)?;
+        }
+        self.compile_expression(&s.value)?;
+        let symbol = self.define_variable(&s.name.value)?;
+        // The VM expects the variable to be set right after
+        self.emit(Opcode::SetIndex, vec![depth])?;
+        self.emit_set_variable(&symbol)
+    }
+
+    /// Warns if a `let` of `name` would rebind a name defined by the program.
+    fn check_shadowing(&mut self, name: &str) {
+        if let Some((symbol, defined_at)) = self.symbol_table.lookup(name) {
+            if symbol.scope != SymbolScope::Builtin {
+                self.warnings.push(CompilerWarning::Shadowing {
+                    name: name.to_string(),
+                    position: self.current_position(),
+                    defined_at,
+                });
+            }
+        }
+    }
+
+    /// Defines `name` in the current symbol table and records its definition
+    /// at `position`.
+    fn define(&mut self, name: &str, position: Option<Position>) -> Symbol {
+        let symbol = self.symbol_table.define_at(name.to_string(), position);
+        self.record_definition(&symbol, position);
+        symbol
+    }
+
+    fn record_definition(&mut self, symbol: &Symbol, position: Option<Position>) {
+        let function = match (self.scope_index, &self.scopes[self.scope_index].function) {
+            (0, _) => "main".to_string(),
+            (_, Some(name)) => name.clone(),
+            (_, None) => "<anonymous>".to_string(),
+        };
+        self.definitions.push(Definition {
+            symbol: symbol.clone(),
+            function,
+            position,
+        });
+    }
+
+    /// Returns the position of the statement being compiled, if it is known.
+    fn current_position(&self) -> Option<Position> {
+        self.scopes[self.scope_index]
+            .source_map
+            .last()
+            .map(|(_, position)| *position)
+    }
+
+    /// Returns the symbols defined by the compiled programs, in the order of
+    /// definition, with the function and the statement defining them.
+    pub fn definitions(&self) -> &[Definition] {
+        &self.definitions
+    }
+
+    /// Returns the symbol a `let` of `name` assigns to, defining it if needed.
+    fn define_variable(&mut self, name: &str) -> Result<Symbol, String> {
         // This step is extremely important. If it is not done then when shadowing variables
         // and using the previous value we get an error. Because we would have assigned
         // a new index to the symbol and the GetGlobal instruction would get a NULL
         // value instead of the previous value. (corresponds to issue #8)
-        let symbol = match self.symbol_table.resolve(&s.name.value) {
+        let position = self.current_position();
+        let symbol = match self.symbol_table.resolve(name) {
             Some(symbol) => match symbol.scope {
                 SymbolScope::Global => {
                     // A Local variable should never replace a global one
@@ -184,7 +481,7 @@ impl Compiler {
                         // This means that the symbol will
                         // be local and not global, and thus not
                         // replace the global one
-                        self.symbol_table.define(s.name.value)
+                        self.define(name, position)
                     } else {
                         symbol
                     }
@@ -194,19 +491,26 @@ impl Compiler {
                 // We only want to do in in the case of "normal" variable assignation.
                 // The special cases should not be touched, since the program should not
                 // have access to them, only the compiler/vm
-                _ => self.symbol_table.define(s.name.value),
+                _ => self.define(name, position),
             },
-            None => self.symbol_table.define(s.name.value),
+            None => self.define(name, position),
         };
+        if symbol.scope == SymbolScope::Global && symbol.index >= self.max_globals {
+            return Err(format!(
+                "Too many global variables, the limit is {}",
+                self.max_globals
+            ));
+        }
+        Ok(symbol)
+    }
 
-        self.compile_expression(s.value)?;
-
+    fn emit_set_variable(&mut self, symbol: &Symbol) -> Result<(), String> {
         match symbol.scope {
             SymbolScope::Global => {
-                self.emit(Opcode::SetGlobal, vec![symbol.index as i32]);
+                self.emit(Opcode::SetGlobal, vec![symbol.index as i32])?;
             }
             SymbolScope::Local => {
-                self.emit(Opcode::SetLocal, vec![symbol.index as i32]);
+                self.emit(Opcode::SetLocal, vec![symbol.index as i32])?;
             }
             SymbolScope::Free => {
                 unreachable!(
@@ -228,91 +532,99 @@ impl Compiler {
         Ok(())
     }
 
-    fn compile_expression(&mut self, expression: Expression) -> Result<(), String> {
+    fn compile_expression(&mut self, expression: &Expression) -> Result<(), String> {
         match expression {
             Expression::Infix(infix) => match infix.token {
                 Token::LT | Token::LTE => self.compile_lt_and_lte(infix)?,
                 _ => {
-                    self.compile_expression(*infix.left)?;
-                    self.compile_expression(*infix.right)?;
+                    self.compile_expression(&infix.left)?;
+                    self.compile_expression(&infix.right)?;
                     self.compile_infix_operator(&infix.token)?;
                 }
             },
             Expression::Prefix(prefix) => {
-                self.compile_expression(*prefix.right)?;
+                self.compile_expression(&prefix.right)?;
                 self.compile_prefix_operator(&prefix.token)?;
             }
             Expression::Primitive(primitive) => self.compile_primitive(primitive)?,
             Expression::Conditional(conditional) => self.compile_conditional(conditional)?,
-            Expression::Identifier(ident) => {
-                let symbol = self.symbol_table.resolve(&ident.value);
-                match symbol {
-                    Some(symbol) => self.load_symbol(&symbol),
-                    None => {
-                        return Err(format!("Undefined variable: {}", ident.value));
-                    }
-                }
-            }
+            Expression::Identifier(ident) => self.compile_identifier(ident)?,
             Expression::ArrayLiteral(array) => {
                 let len = i32::from_usize(array.elements.len()).ok_or("Invalid array length")?;
-                for element in array.elements {
+                for element in &array.elements {
                     self.compile_expression(element)?;
                 }
-                self.emit(Opcode::Array, vec![len]);
+                self.emit(Opcode::Array, vec![len])?;
             }
 
             Expression::HashMapLiteral(hasmap) => {
                 let len = i32::from_usize(hasmap.pairs.len()).ok_or("Invalid hashmap length")?;
-                for (key, value) in hasmap.pairs {
+                for (key, value) in &hasmap.pairs {
                     self.compile_expression(key)?;
                     self.compile_expression(value)?;
                 }
-                self.emit(Opcode::HashMap, vec![len * 2]);
+                self.emit(Opcode::HashMap, vec![len * 2])?;
             }
             Expression::IndexExpression(index) => {
-                self.compile_expression(*index.left)?;
-                self.compile_expression(*index.index)?;
-                self.emit(Opcode::Index, vec![]);
+                self.compile_expression(&index.left)?;
+                self.compile_expression(&index.index)?;
+                self.emit(Opcode::Index, vec![])?;
             }
             Expression::FunctionLiteral(fun) => {
                 self.compile_function_literal(fun)?;
             }
             Expression::FunctionCall(call) => {
-                self.compile_expression(*call.function)?;
+                self.compile_expression(&call.function)?;
 
                 let args_length =
                     i32::from_usize(call.arguments.len()).ok_or("Invalid argument length")?;
 
-                for argument in call.arguments {
+                for argument in &call.arguments {
                     self.compile_expression(argument)?;
                 }
 
-                self.emit(Opcode::Call, vec![args_length]);
+                self.emit(Opcode::Call, vec![args_length])?;
             }
         }
 
         Ok(())
     }
 
-    fn compile_primitive(&mut self, primitive: Primitive) -> Result<(), String> {
+    fn compile_identifier(&mut self, ident: &Identifier) -> Result<(), String> {
+        match self.symbol_table.resolve(&ident.value) {
+            Some(symbol) => self.load_symbol(&symbol),
+            None if self.late_globals => {
+                let pos = self.add_string_constant(&ident.value)?;
+                self.emit(Opcode::GetGlobalByName, vec![pos])?;
+                Ok(())
+            }
+            None => Err(format!("Undefined variable: {}", ident.value)),
+        }
+    }
+
+    fn compile_primitive(&mut self, primitive: &Primitive) -> Result<(), String> {
         match primitive {
             Primitive::IntegerLiteral(i) => {
-                let integer = Object::INTEGER(i);
+                let integer = Object::INTEGER(*i);
                 let pos = self.add_constant(integer);
                 let pos = i32::from_usize(pos).ok_or("Invalid constant position")?;
-                self.emit(Opcode::Constant, vec![pos]);
+                self.emit(Opcode::Constant, vec![pos])?;
             }
             Primitive::BooleanLiteral(true) => {
-                self.emit(Opcode::True, vec![]);
+                self.emit(Opcode::True, vec![])?;
             }
             Primitive::BooleanLiteral(false) => {
-                self.emit(Opcode::False, vec![]);
+                self.emit(Opcode::False, vec![])?;
             }
             Primitive::StringLiteral(s) => {
-                let string = Object::STRING(s);
-                let pos = self.add_constant(string);
+                let pos = self.add_string_constant(s)?;
+                self.emit(Opcode::Constant, vec![pos])?;
+            }
+            Primitive::BytesLiteral(s) => {
+                let bytes = Object::BYTES(s.clone().into_bytes());
+                let pos = self.add_constant(bytes);
                 let pos = i32::from_usize(pos).ok_or("Invalid constant position")?;
-                self.emit(Opcode::Constant, vec![pos]);
+                self.emit(Opcode::Constant, vec![pos])?;
             }
         }
 
@@ -321,28 +633,28 @@ impl Compiler {
 
     fn compile_infix_operator(&mut self, operator: &Token) -> Result<(), String> {
         match operator {
-            Token::Plus => self.emit(Opcode::Add, vec![]),
-            Token::Minus => self.emit(Opcode::Sub, vec![]),
-            Token::Asterisk => self.emit(Opcode::Mul, vec![]),
-            Token::Slash => self.emit(Opcode::Div, vec![]),
-            Token::GT => self.emit(Opcode::GreaterThan, vec![]),
-            Token::GTE => self.emit(Opcode::GreaterEqualThan, vec![]),
-            Token::Equal => self.emit(Opcode::Equal, vec![]),
-            Token::NotEqual => self.emit(Opcode::NotEqual, vec![]),
-            Token::Or => self.emit(Opcode::Or, vec![]),
-            Token::And => self.emit(Opcode::And, vec![]),
-            Token::Modulo => self.emit(Opcode::Modulo, vec![]),
+            Token::Plus => self.emit(Opcode::Add, vec![])?,
+            Token::Minus => self.emit(Opcode::Sub, vec![])?,
+            Token::Asterisk => self.emit(Opcode::Mul, vec![])?,
+            Token::Slash => self.emit(Opcode::Div, vec![])?,
+            Token::GT => self.emit(Opcode::GreaterThan, vec![])?,
+            Token::GTE => self.emit(Opcode::GreaterEqualThan, vec![])?,
+            Token::Equal => self.emit(Opcode::Equal, vec![])?,
+            Token::NotEqual => self.emit(Opcode::NotEqual, vec![])?,
+            Token::Or => self.emit(Opcode::Or, vec![])?,
+            Token::And => self.emit(Opcode::And, vec![])?,
+            Token::Modulo => self.emit(Opcode::Modulo, vec![])?,
             _ => return Err(format!("Unknown operator: {operator}")),
         };
         Ok(())
     }
 
-    fn compile_lt_and_lte(&mut self, infix: InfixOperator) -> Result<(), String> {
-        self.compile_expression(*infix.right)?;
-        self.compile_expression(*infix.left)?;
-        match infix.token {
-            Token::LT => self.emit(Opcode::GreaterThan, vec![]),
-            Token::LTE => self.emit(Opcode::GreaterEqualThan, vec![]),
+    fn compile_lt_and_lte(&mut self, infix: &InfixOperator) -> Result<(), String> {
+        self.compile_expression(&infix.right)?;
+        self.compile_expression(&infix.left)?;
+        match &infix.token {
+            Token::LT => self.emit(Opcode::GreaterThan, vec![])?,
+            Token::LTE => self.emit(Opcode::GreaterEqualThan, vec![])?,
             tk => return Err(format!("Unknown operator: {tk}")),
         };
         Ok(())
@@ -350,36 +662,39 @@ impl Compiler {
 
     fn compile_prefix_operator(&mut self, operator: &Token) -> Result<(), String> {
         match operator {
-            Token::Bang => self.emit(Opcode::Bang, vec![]),
-            Token::Minus => self.emit(Opcode::Minus, vec![]),
+            Token::Bang => self.emit(Opcode::Bang, vec![])?,
+            Token::Minus => self.emit(Opcode::Minus, vec![])?,
             _ => return Err(format!("Unknown operator: {operator}")),
         };
         Ok(())
     }
 
-    fn compile_conditional(&mut self, conditional: Conditional) -> Result<(), String> {
-        self.compile_expression(*conditional.condition)?;
+    fn compile_conditional(&mut self, conditional: &Conditional) -> Result<(), String> {
+        if let Some(switch) = Switch::from_conditional(conditional) {
+            return self.compile_switch(switch);
+        }
+        self.compile_expression(&conditional.condition)?;
 
-        let jump_not_truthy_pos = self.emit(Opcode::JumpNotTruthy, vec![9999]); // We emit a dummy value for the jump offset
-                                                                                // and we will fix it later
-        self.compile_block_statement(conditional.consequence)?;
+        let jump_not_truthy_pos = self.emit(Opcode::JumpNotTruthyWide, vec![9999])?; // We emit a dummy value for the jump offset
+                                                                                     // and we will fix it later
+        self.compile_block_statement(&conditional.consequence)?;
         if self.last_instruction_is(Opcode::Pop) {
             self.remove_last_instruction();
         }
 
-        let jump_pos = self.emit(Opcode::Jump, vec![9999]); // We emit a dummy value for the jump offset
-                                                            // and we will fix it later
+        let jump_pos = self.emit(Opcode::JumpWide, vec![9999])?; // We emit a dummy value for the jump offset
+                                                                 // and we will fix it later
 
         let after_consequence_pos = self.current_instructions().data.len();
         self.change_operand(jump_not_truthy_pos, after_consequence_pos as i32)?;
 
-        if let Some(alternative) = conditional.alternative {
+        if let Some(alternative) = &conditional.alternative {
             self.compile_block_statement(alternative)?;
             if self.last_instruction_is(Opcode::Pop) {
                 self.remove_last_instruction();
             }
         } else {
-            self.emit(Opcode::Null, vec![]);
+            self.emit(Opcode::Null, vec![])?;
         }
 
         let after_alternative_pos = self.current_instructions().data.len();
@@ -388,66 +703,126 @@ impl Compiler {
         Ok(())
     }
 
-    fn compile_function_literal(&mut self, fun: FunctionLiteral) -> Result<(), String> {
+    /// Compiles a chain of conditionals comparing a variable to integers to an
+    /// `OpJumpTable`, which jumps to one of the jumps following it: the one to
+    /// the arm of every integer from the smallest one in turn, then the one to
+    /// the default arm.
+    fn compile_switch(&mut self, switch: Switch) -> Result<(), String> {
+        self.compile_identifier(switch.variable)?;
+        self.emit(
+            Opcode::JumpTable,
+            vec![switch.min as i32, switch.table.len() as i32],
+        )?;
+        let mut entries = Vec::new();
+        for _ in 0..=switch.table.len() {
+            entries.push(self.emit(Opcode::JumpWide, vec![9999])?);
+        }
+
+        let mut starts = Vec::new();
+        let mut jumps_to_end = Vec::new();
+        for arm in switch.arms {
+            starts.push(self.current_instructions().data.len());
+            self.compile_block_statement(arm)?;
+            if self.last_instruction_is(Opcode::Pop) {
+                self.remove_last_instruction();
+            }
+            jumps_to_end.push(self.emit(Opcode::JumpWide, vec![9999])?);
+        }
+        let default_start = self.current_instructions().data.len();
+        if let Some(default) = switch.default {
+            self.compile_block_statement(default)?;
+            if self.last_instruction_is(Opcode::Pop) {
+                self.remove_last_instruction();
+            }
+        } else {
+            self.emit(Opcode::Null, vec![])?;
+        }
+        let end = self.current_instructions().data.len();
+
+        let targets = switch
+            .table
+            .iter()
+            .map(|arm| arm.map_or(default_start, |arm| starts[arm]))
+            .chain(std::iter::once(default_start));
+        for (entry, target) in entries.into_iter().zip(targets) {
+            self.change_operand(entry, target as i32)?;
+        }
+        for jump in jumps_to_end {
+            self.change_operand(jump, end as i32)?;
+        }
+        Ok(())
+    }
+
+    fn compile_function_literal(&mut self, fun: &FunctionLiteral) -> Result<(), String> {
+        // The function and its parameters are defined by the statement holding it
+        let position = self.current_position();
         self.enter_scope();
+        self.scopes[self.scope_index].function.clone_from(&fun.name);
 
-        if let Some(name) = fun.name {
-            self.symbol_table.define_function_name(name);
+        if let Some(name) = &fun.name {
+            let symbol = self.symbol_table.define_function_name(name.clone());
+            self.record_definition(&symbol, position);
         }
 
         let num_parameters = fun.parameters.len();
 
-        for param in fun.parameters {
-            self.symbol_table.define(param.value);
+        for param in &fun.parameters {
+            self.define(&param.value, position);
         }
 
-        self.compile_block_statement(fun.body)?;
+        self.compile_block_statement(&fun.body)?;
 
         if self.last_instruction_is(Opcode::Pop) {
             self.replace_last_pop_with_return();
         }
         if !self.last_instruction_is(Opcode::ReturnValue) {
-            self.emit(Opcode::Return, vec![]);
+            self.emit(Opcode::Return, vec![])?;
         }
 
         let free_symbols = self.symbol_table.free_symbols.clone();
         let free_symbols_len = free_symbols.len();
 
         let num_locals = self.symbol_table.num_definitions;
-        let instructions = self.leave_scope().data;
+        let mut source_map = std::mem::take(&mut self.scopes[self.scope_index].source_map);
+        let mut instructions = dead_code::remove_unreachable(&self.leave_scope(), &mut source_map);
+        if self.superinstructions {
+            instructions = superinstructions::fuse(&instructions, &mut source_map);
+        }
 
         for symbol in free_symbols {
             // Te symbols must be loaded after the scope is left, but
             // we need to get them before leaving the scope.
-            self.load_symbol(&symbol);
+            self.load_symbol(&symbol)?;
         }
 
-        let compiled_function = Object::COMPILEDFUNCTION(CompiledFunction {
-            instructions,
+        let compiled_function = Object::COMPILEDFUNCTION(Rc::new(CompiledFunction {
+            instructions: instructions.data,
             num_locals,
             num_parameters,
-        });
+            name: fun.name.clone(),
+            source_map: SourceMap::new(source_map),
+        }));
 
         let operands =
             i32::from_usize(self.add_constant(compiled_function)).ok_or("Invalid integer type")?;
 
-        self.emit(Opcode::Closure, vec![operands, free_symbols_len as i32]);
+        self.emit(Opcode::Closure, vec![operands, free_symbols_len as i32])?;
 
         Ok(())
     }
 
-    fn compile_while_statement(&mut self, wh: WhileStatement) -> Result<(), String> {
+    fn compile_while_statement(&mut self, wh: &WhileStatement) -> Result<(), String> {
         let condition_pos = self.current_instructions().data.len();
         self.scopes[self.scope_index].enter_loop_scope(condition_pos);
 
-        self.compile_expression(wh.condition)?;
+        self.compile_expression(&wh.condition)?;
 
-        let jump_not_truthy_pos = self.emit(Opcode::JumpNotTruthy, vec![9999]); // We emit a dummy value for the jump offset
-                                                                                // and we will fix it later
-        self.compile_block_statement(wh.body)?;
+        let jump_not_truthy_pos = self.emit(Opcode::JumpNotTruthyWide, vec![9999])?; // We emit a dummy value for the jump offset
+                                                                                     // and we will fix it later
+        self.compile_block_statement(&wh.body)?;
 
-        self.emit(Opcode::Jump, vec![condition_pos as i32]); // We emit a dummy value for the jump offset
-                                                             // and we will fix it later
+        self.emit(Opcode::JumpWide, vec![condition_pos as i32])?; // We emit a dummy value for the jump offset
+                                                                  // and we will fix it later
 
         let after_body_pos = self.current_instructions().data.len();
         self.change_operand(jump_not_truthy_pos, after_body_pos as i32)?;
@@ -468,30 +843,24 @@ impl Compiler {
         Ok(())
     }
 
-    fn compile_loop_statement(&mut self, smt: &LoopStatement) {
+    fn compile_loop_statement(&mut self, smt: &LoopStatement) -> Result<(), String> {
+        // Loops do not extend into the functions defined in them
+        let Some(loop_scope) = self.scopes[self.scope_index].loop_scope.clone() else {
+            return Err(format!("`{smt}` outside of a loop"));
+        };
         match smt {
             LoopStatement::Break => {
-                let pos = self.emit(Opcode::Jump, vec![9999]); // We emit a dummy value for the jump offset
-                                                               // and we will fix it later
-                self.scopes[self.scope_index]
-                    .loop_scope
-                    .clone()
-                    .unwrap()
-                    .as_ref()
-                    .borrow_mut()
-                    .add_break(pos);
+                let pos = self.emit(Opcode::JumpWide, vec![9999])?; // We emit a dummy value for the jump offset
+                                                                    // and we will fix it later
+                loop_scope.borrow_mut().add_break(pos);
             }
             LoopStatement::Continue => {
-                let while_initial_pos = self.scopes[self.scope_index]
-                    .loop_scope
-                    .as_ref()
-                    .unwrap()
-                    .borrow()
-                    .start_position;
-
-                self.emit(Opcode::Jump, vec![while_initial_pos as i32]);
+                let while_initial_pos = loop_scope.borrow().start_position;
+
+                self.emit(Opcode::JumpWide, vec![while_initial_pos as i32])?;
             }
         }
+        Ok(())
     }
 
     fn last_instruction_is(&self, opcode: Opcode) -> bool {
@@ -510,19 +879,41 @@ impl Compiler {
 
             self.scopes[self.scope_index].instructions.data = new;
             self.scopes[self.scope_index].last_instruction = previous;
+
+            // The statements starting after the instruction now start where it was
+            let source_map = &mut self.scopes[self.scope_index].source_map;
+            for entry in source_map.iter_mut() {
+                entry.0 = entry.0.min(last.position);
+            }
+            dedup_source_map(source_map);
         }
     }
 
     fn add_constant(&mut self, obj: Object) -> usize {
-        self.constants.push(obj);
-        self.constants.len() - 1
+        let constants = Rc::make_mut(&mut self.constants);
+        constants.push(obj);
+        constants.len() - 1
     }
 
-    fn emit(&mut self, opcode: Opcode, operands: Vec<i32>) -> usize {
+    /// Adds a string constant, or returns the one with the same string, and
+    /// returns its position as an operand.
+    fn add_string_constant(&mut self, s: &Rc<str>) -> Result<i32, String> {
+        let pos = if let Some(pos) = self.string_constants.get(s) {
+            pos
+        } else {
+            let pos = self.add_constant(Object::STRING(Rc::clone(s)));
+            Rc::make_mut(&mut self.string_constants).insert(Rc::clone(s), pos);
+            pos
+        };
+        Ok(i32::from_usize(pos).ok_or("Invalid constant position")?)
+    }
+
+    fn emit(&mut self, opcode: Opcode, operands: Vec<i32>) -> Result<usize, String> {
+        opcode.check_operands(&operands)?;
         let instruction = opcode.make(operands);
         let pos = self.add_instruction(instruction);
         self.set_last_instruction(opcode, pos);
-        pos
+        Ok(pos)
     }
 
     fn add_instruction(&mut self, instruction: Instructions) -> usize {
@@ -548,6 +939,7 @@ impl Compiler {
             "Unknown opcode: {opcode}",
             opcode = self.current_instructions().data[pos]
         ))?;
+        op.check_operands(&[operand])?;
         let new_instruction = op.make(vec![operand]);
         self.replace_instruction(pos, &new_instruction);
         Ok(())
@@ -604,7 +996,7 @@ impl Compiler {
             .opcode = Opcode::ReturnValue;
     }
 
-    fn load_symbol(&mut self, symbol: &Symbol) {
+    fn load_symbol(&mut self, symbol: &Symbol) -> Result<(), String> {
         let opcode = match symbol.scope {
             SymbolScope::Global => Opcode::GetGlobal,
             SymbolScope::Local => Opcode::GetLocal,
@@ -613,24 +1005,107 @@ impl Compiler {
             SymbolScope::Function => Opcode::CurrentClosure,
         };
 
-        self.emit(opcode, vec![symbol.index as i32]);
+        self.emit(opcode, vec![symbol.index as i32])?;
+        Ok(())
     }
 
     pub fn bytecode(&self) -> Bytecode {
-        Bytecode::new(self.current_instructions(), self.constants.clone())
+        Bytecode {
+            string_constants: Rc::clone(&self.string_constants),
+            ..Bytecode::new(
+                self.current_instructions(),
+                Rc::clone(&self.constants),
+                self.symbol_table.clone(),
+                self.source.clone(),
+                SourceMap::new(self.scopes[self.scope_index].source_map.clone()),
+            )
+        }
+    }
+
+    /// Returns the bytecode like [`Compiler::bytecode`], moving the
+    /// instructions and the symbols instead of copying them.
+    pub fn into_bytecode(mut self) -> Bytecode {
+        let scope = self.scopes.swap_remove(self.scope_index);
+        Bytecode {
+            string_constants: self.string_constants,
+            ..Bytecode::new(
+                scope.instructions,
+                self.constants,
+                self.symbol_table,
+                self.source,
+                SourceMap::new(scope.source_map),
+            )
+        }
     }
 }
 
+/// Keeps only the last of the statements starting at the same instruction,
+/// the others did not emit any instruction of their own.
+fn dedup_source_map(source_map: &mut Vec<(usize, Position)>) {
+    source_map.dedup_by(|later, earlier| {
+        if later.0 == earlier.0 {
+            *earlier = *later;
+            true
+        } else {
+            false
+        }
+    });
+}
+
+/// Returns whether the program uses the `eval` builtin, calling it or passing
+/// it around.
+pub(crate) fn uses_eval(program: &Program) -> bool {
+    struct EvalFinder(bool);
+
+    impl Visitor for EvalFinder {
+        fn visit_identifier(&mut self, identifier: &Identifier) {
+            self.0 |= &*identifier.value == "eval";
+        }
+    }
+
+    let mut finder = EvalFinder(false);
+    walk_program(&mut finder, program);
+    finder.0
+}
+
+/// Compiled program. Its instructions and constants are shared, cloning it to
+/// run it again copies neither.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Bytecode {
-    pub instructions: Instructions,
-    pub constants: Vec<Object>,
+    pub instructions: Rc<Instructions>,
+    pub constants: Rc<Vec<Object>>,
+
+    /// Global symbols of the program, needed to compile code at runtime.
+    pub symbol_table: SymbolTable,
+
+    /// Source of the compiled program, if known.
+    pub source: Option<Source>,
+
+    /// Debug symbols of the main program, empty if the source is unknown.
+    pub source_map: SourceMap,
+
+    /// Index of the string constants, to compile the next program, such as a
+    /// line of the REPL, without indexing all the constants again.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) string_constants: Rc<StringConstants>,
 }
 
 impl Bytecode {
-    fn new(instructions: Instructions, constants: Vec<Object>) -> Self {
+    fn new(
+        instructions: Instructions,
+        constants: Rc<Vec<Object>>,
+        symbol_table: SymbolTable,
+        source: Option<Source>,
+        source_map: SourceMap,
+    ) -> Self {
         Bytecode {
-            instructions,
+            instructions: Rc::new(instructions),
             constants,
+            symbol_table,
+            source,
+            source_map,
+            string_constants: Rc::default(),
         }
     }
 }
@@ -650,12 +1125,12 @@ pub mod tests {
 
         let global_symbol_table = compiler.symbol_table.clone();
 
-        compiler.emit(Opcode::Mul, vec![]);
+        compiler.emit(Opcode::Mul, vec![]).unwrap();
 
         compiler.enter_scope();
         assert_eq!(compiler.scope_index, 1);
 
-        compiler.emit(Opcode::Sub, vec![]);
+        compiler.emit(Opcode::Sub, vec![]).unwrap();
         assert_eq!(
             compiler.scopes[compiler.scope_index]
                 .instructions
@@ -685,7 +1160,7 @@ pub mod tests {
             "Compiler did not restore global symbol table after leaving scope"
         );
 
-        compiler.emit(Opcode::Add, vec![]);
+        compiler.emit(Opcode::Add, vec![]).unwrap();
         assert_eq!(
             compiler.scopes[compiler.scope_index]
                 .instructions
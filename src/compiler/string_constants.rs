@@ -0,0 +1,54 @@
+use std::{collections::HashMap, rc::Rc};
+
+use crate::object::Object;
+
+/// Index of the string constants of a constant pool, so that identical strings
+/// share one constant, and one allocation once loaded by the VM.
+///
+/// It is kept with the constants from one program to the next, like the lines
+/// of the REPL, and only the constants added since the last program are
+/// indexed.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct StringConstants {
+    indexes: HashMap<Rc<str>, usize>,
+    /// Number of constants indexed.
+    len: usize,
+}
+
+impl StringConstants {
+    pub fn get(&self, s: &str) -> Option<usize> {
+        self.indexes.get(s).copied()
+    }
+
+    pub fn insert(&mut self, s: Rc<str>, index: usize) {
+        self.indexes.insert(s, index);
+    }
+
+    /// Indexes the string constants added since the last update. A pool
+    /// shorter than the indexed one is not the same pool, it is indexed from
+    /// scratch.
+    pub fn update(&mut self, constants: &[Object]) {
+        if constants.len() < self.len {
+            *self = Self::default();
+        }
+        for (i, constant) in constants.iter().enumerate().skip(self.len) {
+            if let Object::STRING(s) = constant {
+                self.indexes.entry(Rc::clone(s)).or_insert(i);
+            }
+        }
+        self.len = constants.len();
+    }
+
+    /// Forgets the string constants from index `first` on, before they are
+    /// dropped or renumbered. They are indexed again by the next update.
+    pub fn forget_from(&mut self, first: usize, constants: &[Object]) {
+        for (i, constant) in constants.iter().enumerate().skip(first) {
+            if let Object::STRING(s) = constant {
+                if self.indexes.get(s) == Some(&i) {
+                    self.indexes.remove(s);
+                }
+            }
+        }
+        self.len = self.len.min(first);
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    compiler::{
+        code::{Instructions, Opcode},
+        dedup_source_map,
+    },
+    source::Position,
+};
+
+/// Replaces the sequences of instructions which often run together by a
+/// superinstruction doing the work of the whole sequence, which saves the
+/// dispatch of the other instructions and the values pushed between them:
+///
+/// - `OpConstant a`, `OpConstant b`, `OpAdd` becomes `OpAddConstants a b`
+/// - `OpGetLocal l`, `OpConstant c`, a comparison, `OpJumpNotTruthy t` becomes
+///   `OpCompareLocalConstantJump l c comparison t`, like `if (n == 1)`
+/// - `OpConstant c`, `OpGetLocal l`, a comparison, `OpJumpNotTruthy t` becomes
+///   `OpCompareConstantLocalJump c l comparison t`, like `if (n < 2)`
+///
+/// The comparison is the opcode of the replaced comparison. A sequence is kept
+/// as it is when a jump targets one of its instructions but the first, and
+/// only narrow jumps are fused, the superinstructions have a 2 bytes target.
+///
+/// The jumps are updated to the new positions of their targets, and the
+/// statements of `source_map` are moved along with their instructions.
+pub fn fuse(instructions: &Instructions, source_map: &mut Vec<(usize, Position)>) -> Instructions {
+    let decoded: Vec<(usize, Opcode, Vec<i32>)> = instructions.iter().collect();
+    let targets: HashSet<usize> = decoded
+        .iter()
+        .filter_map(|(_, op, operands)| Some(operands[op.jump_operand()?] as usize))
+        .collect();
+
+    let mut fused = Vec::new();
+    let mut new_positions = HashMap::new();
+    let mut position = 0;
+    let mut i = 0;
+    while i < decoded.len() {
+        let (length, op, operands) = superinstruction(&decoded[i..])
+            .filter(|(length, _, _)| {
+                decoded[i + 1..i + length]
+                    .iter()
+                    .all(|(old_position, _, _)| !targets.contains(old_position))
+            })
+            .unwrap_or_else(|| (1, decoded[i].1, decoded[i].2.clone()));
+        for (old_position, _, _) in &decoded[i..i + length] {
+            new_positions.insert(*old_position, position);
+        }
+        position += 1 + op
+            .lookup_widths()
+            .iter()
+            .map(|width| *width as usize)
+            .sum::<usize>();
+        fused.push((op, operands));
+        i += length;
+    }
+    new_positions.insert(instructions.data.len(), position);
+
+    for entry in source_map.iter_mut() {
+        entry.0 = new_positions[&entry.0];
+    }
+    dedup_source_map(source_map);
+
+    let mut rewritten = Instructions::default();
+    for (op, mut operands) in fused {
+        if let Some(target) = op.jump_operand() {
+            operands[target] = new_positions[&(operands[target] as usize)] as i32;
+        }
+        rewritten.append(op.make(operands));
+    }
+    rewritten
+}
+
+/// Returns the number of instructions replaced, the opcode and the operands of
+/// the superinstruction starting the `instructions`, if there is one.
+fn superinstruction(
+    instructions: &[(usize, Opcode, Vec<i32>)],
+) -> Option<(usize, Opcode, Vec<i32>)> {
+    match instructions {
+        [(_, Opcode::Constant, left), (_, Opcode::Constant, right), (_, Opcode::Add, _), ..] => {
+            Some((3, Opcode::AddConstants, vec![left[0], right[0]]))
+        }
+        [(_, first, left), (_, second, right), (_, comparison, _), (_, Opcode::JumpNotTruthy, target), ..]
+            if matches!(
+                comparison,
+                Opcode::Equal | Opcode::NotEqual | Opcode::GreaterThan | Opcode::GreaterEqualThan
+            ) =>
+        {
+            let op = match (first, second) {
+                (Opcode::GetLocal, Opcode::Constant) => Opcode::CompareLocalConstantJump,
+                (Opcode::Constant, Opcode::GetLocal) => Opcode::CompareConstantLocalJump,
+                _ => return None,
+            };
+            Some((
+                4,
+                op,
+                vec![left[0], right[0], *comparison as i32, target[0]],
+            ))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::test_utils::flatten_instructions;
+
+    #[test]
+    fn test_fuse() {
+        let instructions = flatten_instructions(vec![
+            Opcode::GetLocal.make(vec![0]),       // 000
+            Opcode::Constant.make(vec![1]),       // 002
+            Opcode::Equal.make(vec![]),           // 005
+            Opcode::JumpNotTruthy.make(vec![16]), // 006
+            Opcode::Constant.make(vec![2]),       // 009
+            Opcode::Constant.make(vec![3]),       // 012
+            Opcode::Add.make(vec![]),             // 015
+            Opcode::Constant.make(vec![4]),       // 016
+            Opcode::GetLocal.make(vec![0]),       // 019
+            Opcode::GreaterThan.make(vec![]),     // 021
+            Opcode::JumpNotTruthy.make(vec![0]),  // 022
+            Opcode::ReturnValue.make(vec![]),     // 025
+        ]);
+        let mut source_map = vec![
+            (0, Position { line: 1, column: 1 }),
+            (9, Position { line: 2, column: 1 }),
+            (16, Position { line: 3, column: 1 }),
+        ];
+
+        let expected = flatten_instructions(vec![
+            Opcode::CompareLocalConstantJump.make(vec![0, 1, Opcode::Equal as i32, 12]), // 000
+            Opcode::AddConstants.make(vec![2, 3]),                                       // 007
+            Opcode::CompareConstantLocalJump.make(vec![4, 0, Opcode::GreaterThan as i32, 0]), // 012
+            Opcode::ReturnValue.make(vec![]),                                            // 019
+        ]);
+        assert_eq!(fuse(&instructions, &mut source_map), expected);
+        assert_eq!(
+            source_map,
+            vec![
+                (0, Position { line: 1, column: 1 }),
+                (7, Position { line: 2, column: 1 }),
+                (12, Position { line: 3, column: 1 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_jump_inside_is_not_fused() {
+        // The jump goes to the second constant
+        let instructions = flatten_instructions(vec![
+            Opcode::True.make(vec![]),               // 000
+            Opcode::JumpNotTruthy.make(vec![7]),     // 001
+            Opcode::Constant.make(vec![0]),          // 004
+            Opcode::Constant.make(vec![1]),          // 007
+            Opcode::Add.make(vec![]),                // 010
+            Opcode::GetLocal.make(vec![0]),          // 011
+            Opcode::Constant.make(vec![0]),          // 013
+            Opcode::Equal.make(vec![]),              // 016
+            Opcode::JumpNotTruthyWide.make(vec![0]), // 017
+        ]);
+
+        assert_eq!(fuse(&instructions, &mut vec![]), instructions);
+    }
+}
@@ -1,6 +1,9 @@
 use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
+use crate::source::Position;
+
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SymbolScope {
     Global,
     Local,
@@ -10,22 +13,52 @@ pub enum SymbolScope {
 }
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Symbol {
     pub name: String,
     pub scope: SymbolScope,
     pub index: usize,
 }
 
-#[derive(Default, Clone, Debug, PartialEq)]
+/// A symbol defined by a program, as recorded by the compiler.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Definition {
+    pub symbol: Symbol,
+
+    /// Name of the function defining the symbol, `main` for the program
+    /// itself and `<anonymous>` for the functions without a name.
+    pub function: String,
+
+    /// Position of the statement defining the symbol, if the source is known.
+    pub position: Option<Position>,
+}
+
+#[derive(Default, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SymbolTable {
     pub outer: Option<Rc<RefCell<Self>>>,
 
     store: HashMap<String, Symbol>,
     pub num_definitions: usize,
 
+    /// Position of the statement defining every symbol of this table, when it
+    /// is known.
+    positions: HashMap<String, Position>,
+
     pub free_symbols: Vec<Symbol>,
 }
 
+/// Tables are equal when they define the same symbols, wherever they were
+/// defined, since bytecode files do not keep the positions.
+impl PartialEq for SymbolTable {
+    fn eq(&self, other: &Self) -> bool {
+        self.outer == other.outer
+            && self.store == other.store
+            && self.num_definitions == other.num_definitions
+            && self.free_symbols == other.free_symbols
+    }
+}
+
 impl SymbolTable {
     pub fn new() -> Self {
         Self {
@@ -33,6 +66,7 @@ impl SymbolTable {
 
             store: HashMap::new(),
             num_definitions: 0,
+            positions: HashMap::new(),
 
             free_symbols: vec![],
         }
@@ -62,6 +96,20 @@ impl SymbolTable {
         symbol
     }
 
+    /// Defines `name` like [`SymbolTable::define`], remembering that the
+    /// statement at `position` defines it.
+    pub fn define_at(&mut self, name: String, position: Option<Position>) -> Symbol {
+        match position {
+            Some(position) => {
+                self.positions.insert(name.clone(), position);
+            }
+            None => {
+                self.positions.remove(&name);
+            }
+        }
+        self.define(name)
+    }
+
     pub fn define_builtin(&mut self, index: usize, name: String) -> Symbol {
         let sym = Symbol {
             name: name.clone(),
@@ -116,9 +164,38 @@ impl SymbolTable {
         symbol
     }
 
+    /// Returns the symbol `name` is bound to in this table or an outer one,
+    /// with the position of its definition, if known. Unlike
+    /// [`SymbolTable::resolve`], it does not capture the symbols of the outer
+    /// functions as free symbols.
+    pub fn lookup(&self, name: &str) -> Option<(Symbol, Option<Position>)> {
+        match self.store.get(name) {
+            Some(symbol) if symbol.scope != SymbolScope::Free => {
+                Some((symbol.clone(), self.positions.get(name).copied()))
+            }
+            _ => self.outer.as_ref()?.borrow().lookup(name),
+        }
+    }
+
+    /// Returns the variable `name` is bound to by this table, by a `let` or
+    /// as a parameter, with the position of its definition, if known. The
+    /// symbols of the outer tables, the builtins and the name of the function
+    /// are ignored.
+    pub fn lookup_variable(&self, name: &str) -> Option<(Symbol, Option<Position>)> {
+        self.store
+            .get(name)
+            .filter(|symbol| matches!(symbol.scope, SymbolScope::Global | SymbolScope::Local))
+            .map(|symbol| (symbol.clone(), self.positions.get(name).copied()))
+    }
+
     pub fn has_outer(&self) -> bool {
         self.outer.is_some()
     }
+
+    /// Iterates over the symbols defined in this table, ignoring the outer ones.
+    pub fn symbols(&self) -> impl Iterator<Item = &Symbol> {
+        self.store.values()
+    }
 }
 
 #[cfg(test)]
@@ -594,4 +671,32 @@ mod tests {
         assert!(result.is_some());
         assert_eq!(result.unwrap(), expected);
     }
+
+    #[test]
+    fn test_lookup() {
+        let global = Rc::new(RefCell::new(SymbolTable::new()));
+        global
+            .borrow_mut()
+            .define_at("a".to_string(), Some(Position { line: 1, column: 1 }));
+        let local = Rc::new(RefCell::new(SymbolTable::new_enclosed(Rc::clone(&global))));
+        local
+            .borrow_mut()
+            .define_at("b".to_string(), Some(Position { line: 2, column: 3 }));
+        let mut nested = SymbolTable::new_enclosed(local);
+        nested.resolve("b");
+
+        let (symbol, position) = nested.lookup("a").unwrap();
+        assert_eq!(symbol.scope, SymbolScope::Global);
+        assert_eq!(position, Some(Position { line: 1, column: 1 }));
+        // `b` is a free symbol of `nested`, defined by the outer function
+        let (symbol, position) = nested.lookup("b").unwrap();
+        assert_eq!(symbol.scope, SymbolScope::Local);
+        assert_eq!(position, Some(Position { line: 2, column: 3 }));
+        assert_eq!(nested.lookup("c"), None);
+
+        assert_eq!(nested.lookup_variable("a"), None);
+        assert_eq!(nested.lookup_variable("b"), None);
+        let (symbol, _) = global.borrow().lookup_variable("a").unwrap();
+        assert_eq!(symbol.index, 0);
+    }
 }
@@ -51,8 +51,9 @@ pub(crate) fn run_compiler(tests: Vec<CompilerTestCase>) {
         let program = parse(&test.input);
 
         let mut compiler = Compiler::new();
+        compiler.set_superinstructions(false);
 
-        match compiler.compile(program) {
+        match compiler.compile(&program) {
             Ok(()) => {
                 let bytecode = compiler.bytecode();
                 println!(
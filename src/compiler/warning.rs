@@ -0,0 +1,41 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::source::Position;
+
+/// A program which compiles but probably does not do what was intended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompilerWarning {
+    /// A `let` binding a name the same function or an outer one already
+    /// defined, at `defined_at` if it is known.
+    Shadowing {
+        name: String,
+        position: Option<Position>,
+        defined_at: Option<Position>,
+    },
+}
+
+impl CompilerWarning {
+    /// Returns the position of the statement the warning is about, if it is
+    /// known.
+    pub fn position(&self) -> Option<Position> {
+        match self {
+            CompilerWarning::Shadowing { position, .. } => *position,
+        }
+    }
+}
+
+impl Display for CompilerWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            CompilerWarning::Shadowing {
+                name, defined_at, ..
+            } => {
+                write!(f, "warning: `let` rebinds `{name}`")?;
+                match defined_at {
+                    Some(position) => write!(f, ", already defined at {position}"),
+                    None => write!(f, ", which is already defined"),
+                }
+            }
+        }
+    }
+}
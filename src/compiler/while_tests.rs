@@ -6,8 +6,10 @@ pub mod tests {
         compiler::{
             code::Opcode,
             test_utils::{flatten_instructions, run_compiler, CompilerTestCase},
+            Compiler,
         },
         object::Object,
+        parser::parse,
     };
 
     #[test]
@@ -19,7 +21,7 @@ pub mod tests {
                     }
                     "#
             .to_string(),
-            expected_constants: vec![Object::STRING("yes".to_string())],
+            expected_constants: vec![Object::STRING("yes".into())],
             expected_instructions: flatten_instructions(vec![
                 Opcode::True.make(vec![]),            // 000
                 Opcode::JumpNotTruthy.make(vec![15]), // 001
@@ -46,11 +48,10 @@ pub mod tests {
             .to_string(),
             expected_constants: vec![],
             expected_instructions: flatten_instructions(vec![
-                Opcode::True.make(vec![]),            // 000
-                Opcode::JumpNotTruthy.make(vec![10]), // 001
-                Opcode::Jump.make(vec![10]),          // 004
-                Opcode::Jump.make(vec![0]),           // 007
-                                                      // 010
+                Opcode::True.make(vec![]),           // 000
+                Opcode::JumpNotTruthy.make(vec![7]), // 001
+                Opcode::Jump.make(vec![7]),          // 004
+                                                     // 007
             ]),
         }];
 
@@ -72,14 +73,12 @@ pub mod tests {
             expected_constants: vec![],
             expected_instructions: flatten_instructions(vec![
                 Opcode::True.make(vec![]),            // 000
-                Opcode::JumpNotTruthy.make(vec![20]), // 001
+                Opcode::JumpNotTruthy.make(vec![14]), // 001
                 Opcode::True.make(vec![]),            // 004
-                Opcode::JumpNotTruthy.make(vec![14]), // 005
-                Opcode::Jump.make(vec![14]),          // 008
-                Opcode::Jump.make(vec![4]),           // 011
-                Opcode::Jump.make(vec![20]),          // 014
-                Opcode::Jump.make(vec![0]),           // 017
-                                                      // 020
+                Opcode::JumpNotTruthy.make(vec![11]), // 005
+                Opcode::Jump.make(vec![11]),          // 008
+                Opcode::Jump.make(vec![14]),          // 011
+                                                      // 014
             ]),
         }];
 
@@ -96,11 +95,10 @@ pub mod tests {
             .to_string(),
             expected_constants: vec![],
             expected_instructions: flatten_instructions(vec![
-                Opcode::True.make(vec![]),            // 000
-                Opcode::JumpNotTruthy.make(vec![10]), // 001
-                Opcode::Jump.make(vec![0]),           // 004
-                Opcode::Jump.make(vec![0]),           // 007
-                                                      // 010
+                Opcode::True.make(vec![]),           // 000
+                Opcode::JumpNotTruthy.make(vec![7]), // 001
+                Opcode::Jump.make(vec![0]),          // 004
+                                                     // 007
             ]),
         }];
 
@@ -122,14 +120,12 @@ pub mod tests {
             expected_constants: vec![],
             expected_instructions: flatten_instructions(vec![
                 Opcode::True.make(vec![]),            // 000
-                Opcode::JumpNotTruthy.make(vec![20]), // 001
+                Opcode::JumpNotTruthy.make(vec![14]), // 001
                 Opcode::True.make(vec![]),            // 004
-                Opcode::JumpNotTruthy.make(vec![14]), // 005
+                Opcode::JumpNotTruthy.make(vec![11]), // 005
                 Opcode::Jump.make(vec![4]),           // 008
-                Opcode::Jump.make(vec![4]),           // 011
-                Opcode::Jump.make(vec![0]),           // 014
-                Opcode::Jump.make(vec![0]),           // 017
-                                                      // 020
+                Opcode::Jump.make(vec![0]),           // 011
+                                                      // 014
             ]),
         }];
 
@@ -151,17 +147,31 @@ pub mod tests {
             expected_constants: vec![],
             expected_instructions: flatten_instructions(vec![
                 Opcode::True.make(vec![]),            // 000
-                Opcode::JumpNotTruthy.make(vec![20]), // 001
+                Opcode::JumpNotTruthy.make(vec![14]), // 001
                 Opcode::True.make(vec![]),            // 004
-                Opcode::JumpNotTruthy.make(vec![14]), // 005
+                Opcode::JumpNotTruthy.make(vec![11]), // 005
                 Opcode::Jump.make(vec![4]),           // 008
-                Opcode::Jump.make(vec![4]),           // 011
-                Opcode::Jump.make(vec![20]),          // 014
-                Opcode::Jump.make(vec![0]),           // 017
-                                                      // 020
+                Opcode::Jump.make(vec![14]),          // 011
+                                                      // 014
             ]),
         }];
 
         run_compiler(tests);
     }
+
+    #[test]
+    fn test_loop_statements_outside_of_loops() {
+        for (input, expected) in [
+            ("break;", "`break` outside of a loop"),
+            ("continue;", "`continue` outside of a loop"),
+            // The loop does not extend into the function
+            (
+                "while (true) { let f = fn() { break; }; }",
+                "`break` outside of a loop",
+            ),
+        ] {
+            let mut compiler = Compiler::new();
+            assert_eq!(compiler.compile(&parse(input)), Err(expected.to_string()));
+        }
+    }
 }
@@ -0,0 +1,150 @@
+use std::fmt::Write;
+
+use crate::parser::ast::{Expression, Program, Statement};
+
+/// Documentation of a function defined by a `let` at the top level of a
+/// program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionDoc {
+    pub name: String,
+    pub parameters: Vec<String>,
+    pub doc: Option<String>,
+}
+
+impl FunctionDoc {
+    /// Returns how the function is called, such as `add(a, b)`.
+    pub fn signature(&self) -> String {
+        format!("{}({})", self.name, self.parameters.join(", "))
+    }
+}
+
+/// Returns the functions defined at the top level of the program, in order,
+/// with their `///` comments.
+pub fn functions(program: &Program) -> Vec<FunctionDoc> {
+    program
+        .statements
+        .iter()
+        .filter_map(|statement| match statement {
+            Statement::Let(statement) => match &statement.value {
+                Expression::FunctionLiteral(function) => Some(FunctionDoc {
+                    name: statement.name.value.to_string(),
+                    parameters: function
+                        .parameters
+                        .iter()
+                        .map(|parameter| parameter.value.to_string())
+                        .collect(),
+                    doc: function.doc.clone(),
+                }),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns the documentation of the functions as Markdown, with a section
+/// for each of them, under the title `title`.
+pub fn markdown(title: &str, functions: &[FunctionDoc]) -> String {
+    let mut output = format!("# {title}\n");
+    for function in functions {
+        let _ = writeln!(output, "\n## `{}`", function.signature());
+        if let Some(doc) = &function.doc {
+            let _ = writeln!(output, "\n{}", doc.trim());
+        }
+    }
+    output
+}
+
+/// Returns the documentation of the functions as a standalone HTML page,
+/// each paragraph of their comments in its own `<p>`.
+pub fn html(title: &str, functions: &[FunctionDoc]) -> String {
+    let title = escape_html(title);
+    let mut output = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n</head>\n<body>\n<h1>{title}</h1>\n"
+    );
+    for function in functions {
+        let _ = writeln!(
+            output,
+            "<h2><code>{}</code></h2>",
+            escape_html(&function.signature())
+        );
+        let Some(doc) = &function.doc else {
+            continue;
+        };
+        for paragraph in doc.split("\n\n").map(str::trim) {
+            if !paragraph.is_empty() {
+                let _ = writeln!(output, "<p>{}</p>", escape_html(paragraph));
+            }
+        }
+    }
+    output.push_str("</body>\n</html>\n");
+    output
+}
+
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    const INPUT: &str = r#"/// Adds two numbers.
+///
+/// Both must be integers, `a < b` or not.
+let add = fn(a, b) { a + b };
+
+let answer = 42;
+
+let identity = fn(x) { x };
+"#;
+
+    #[test]
+    fn test_functions() {
+        assert_eq!(
+            functions(&parse(INPUT)),
+            vec![
+                FunctionDoc {
+                    name: "add".to_string(),
+                    parameters: vec!["a".to_string(), "b".to_string()],
+                    doc: Some(
+                        "Adds two numbers.\n\nBoth must be integers, `a < b` or not.".to_string()
+                    ),
+                },
+                FunctionDoc {
+                    name: "identity".to_string(),
+                    parameters: vec!["x".to_string()],
+                    doc: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_markdown() {
+        let expected = "# math.monkey
+
+## `add(a, b)`
+
+Adds two numbers.
+
+Both must be integers, `a < b` or not.
+
+## `identity(x)`
+";
+        assert_eq!(markdown("math.monkey", &functions(&parse(INPUT))), expected);
+    }
+
+    #[test]
+    fn test_html() {
+        let html = html("math.monkey", &functions(&parse(INPUT)));
+        assert!(html.contains("<title>math.monkey</title>"));
+        assert!(html.contains(
+            "<h2><code>add(a, b)</code></h2>\n<p>Adds two numbers.</p>\n<p>Both must be integers, `a &lt; b` or not.</p>\n<h2><code>identity(x)</code></h2>\n</body>"
+        ));
+    }
+}
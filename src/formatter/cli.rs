@@ -1,6 +1,17 @@
+use std::{
+    error::Error,
+    fs,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
 use clap_derive::Parser;
 
-use crate::formatter::Formatter;
+use crate::formatter::{
+    diff::{diff_lines, unified_diff, DiffStat},
+    Formatter,
+};
 
 trait Logger {
     fn log(&mut self, msg: &str) -> Result<(), Box<dyn std::error::Error>>;
@@ -59,6 +70,152 @@ impl FormatterCli {
     }
 }
 
+/// Returns the files of `paths`, with the directories replaced by the
+/// `.monkey` files they contain, recursively, sorted by path.
+///
+/// # Errors
+///
+/// If a path or a directory cannot be read.
+pub fn monkey_files(paths: &[String]) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    fn visit(path: &Path, files: &mut Vec<PathBuf>) -> Result<(), Box<dyn Error>> {
+        let mut entries = fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        entries.sort();
+        for entry in entries {
+            if entry.is_dir() {
+                visit(&entry, files)?;
+            } else if entry
+                .extension()
+                .is_some_and(|extension| extension == "monkey")
+            {
+                files.push(entry);
+            }
+        }
+        Ok(())
+    }
+
+    let mut files = Vec::new();
+    for path in paths {
+        let path = Path::new(path);
+        if path.is_dir() {
+            visit(path, &mut files)?;
+        } else {
+            files.push(path.to_path_buf());
+        }
+    }
+    Ok(files)
+}
+
+/// Formats every Monkey file of `paths` in place, printing the lines changed
+/// in each of them. The files which cannot be parsed are left untouched, and
+/// make it fail.
+///
+/// # Errors
+///
+/// If a path cannot be read, or a file cannot be written.
+pub fn format_in_place(paths: &[String]) -> Result<ExitCode, Box<dyn Error>> {
+    let files = monkey_files(paths)?;
+    let mut formatted = 0;
+    let mut failed = false;
+    for file in &files {
+        let input = fs::read_to_string(file)?;
+        match Formatter::try_format(&input) {
+            Ok(output) if output != input => {
+                fs::write(file, &output)?;
+                let stat = DiffStat::new(&diff_lines(&input, &output));
+                println!("{}: {stat}", file.display());
+                formatted += 1;
+            }
+            Ok(_) => {}
+            Err(errors) => {
+                eprintln!("{}: {}", file.display(), errors.to_string().trim_end());
+                failed = true;
+            }
+        }
+    }
+    println!("Formatted {formatted} of {} file(s)", files.len());
+    Ok(if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+/// Checks that every Monkey file of `paths` is formatted, printing the diff
+/// which would format the other ones without writing anything. It fails if
+/// a file is not formatted or cannot be parsed.
+///
+/// # Errors
+///
+/// If a path cannot be read.
+pub fn check_formatting(paths: &[String]) -> Result<ExitCode, Box<dyn Error>> {
+    let files = monkey_files(paths)?;
+    let mut unformatted = 0;
+    let mut failed = false;
+    for file in &files {
+        let input = fs::read_to_string(file)?;
+        match Formatter::try_format(&input) {
+            Ok(output) if output != input => {
+                let name = file.display().to_string();
+                print!(
+                    "{}",
+                    unified_diff(&name, &name, &diff_lines(&input, &output))
+                );
+                unformatted += 1;
+            }
+            Ok(_) => {}
+            Err(errors) => {
+                eprintln!("{}: {}", file.display(), errors.to_string().trim_end());
+                failed = true;
+            }
+        }
+    }
+    eprintln!(
+        "{unformatted} of {} file(s) would be formatted",
+        files.len()
+    );
+    Ok(if failed || unformatted > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+/// Formats the Monkey code read from `input` and writes it to `output`, or
+/// with `check`, writes the diff which would format it and fails if it is not
+/// formatted. Nothing is written if the code cannot be parsed.
+///
+/// # Errors
+///
+/// If `input` cannot be read or `output` cannot be written.
+pub fn format_stream(
+    input: &mut impl Read,
+    output: &mut impl Write,
+    check: bool,
+) -> Result<ExitCode, Box<dyn Error>> {
+    let mut code = String::new();
+    input.read_to_string(&mut code)?;
+    let formatted = match Formatter::try_format(&code) {
+        Ok(formatted) => formatted,
+        Err(errors) => {
+            eprintln!("<stdin>: {}", errors.to_string().trim_end());
+            return Ok(ExitCode::FAILURE);
+        }
+    };
+    if !check {
+        output.write_all(formatted.as_bytes())?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    let diff = unified_diff("<stdin>", "<stdin>", &diff_lines(&code, &formatted));
+    output.write_all(diff.as_bytes())?;
+    Ok(if diff.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -90,4 +247,64 @@ mod tests {
 
         assert_eq!(logger.msg, Formatter::format(&input));
     }
+
+    #[test]
+    fn test_format_in_place() {
+        let directory = std::env::temp_dir().join("chimpanzee_fmt_test");
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(directory.join("nested")).unwrap();
+        let unformatted = directory.join("nested/a.monkey");
+        fs::write(&unformatted, "let   x=1;").unwrap();
+        let formatted = directory.join("b.monkey");
+        fs::write(&formatted, "let y = 2;\n").unwrap();
+        fs::write(directory.join("notes.txt"), "let   z=3;").unwrap();
+
+        let paths = [directory.display().to_string()];
+        assert_eq!(
+            monkey_files(&paths).unwrap(),
+            vec![formatted.clone(), unformatted.clone()]
+        );
+        assert_eq!(check_formatting(&paths).unwrap(), ExitCode::FAILURE);
+        assert_eq!(fs::read_to_string(&unformatted).unwrap(), "let   x=1;");
+        assert_eq!(format_in_place(&paths).unwrap(), ExitCode::SUCCESS);
+        assert_eq!(fs::read_to_string(&unformatted).unwrap(), "let x = 1;\n");
+        assert_eq!(check_formatting(&paths).unwrap(), ExitCode::SUCCESS);
+        assert_eq!(fs::read_to_string(&formatted).unwrap(), "let y = 2;\n");
+
+        // Files which cannot be parsed are not touched
+        fs::write(&unformatted, "let x = ;").unwrap();
+        assert_eq!(format_in_place(&paths).unwrap(), ExitCode::FAILURE);
+        assert_eq!(check_formatting(&paths).unwrap(), ExitCode::FAILURE);
+        assert_eq!(fs::read_to_string(&unformatted).unwrap(), "let x = ;");
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_format_stream() {
+        let format = |input: &str, check| {
+            let mut output = Vec::new();
+            let code = format_stream(&mut input.as_bytes(), &mut output, check).unwrap();
+            (code, String::from_utf8(output).unwrap())
+        };
+
+        assert_eq!(
+            format("let   x=1; // one", false),
+            (ExitCode::SUCCESS, "let x = 1; // one\n".to_string())
+        );
+        assert_eq!(
+            format("let x = 1;\n", true),
+            (ExitCode::SUCCESS, String::new())
+        );
+        assert_eq!(
+            format("let   x=1;\n", true),
+            (
+                ExitCode::FAILURE,
+                "--- <stdin>\n+++ <stdin>\n@@ -1 +1 @@\n-let   x=1;\n+let x = 1;\n".to_string()
+            )
+        );
+        assert_eq!(
+            format("let x = ;", false),
+            (ExitCode::FAILURE, String::new())
+        );
+    }
 }
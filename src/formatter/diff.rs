@@ -0,0 +1,263 @@
+use std::fmt::Write;
+
+/// A line of a [`diff_lines`] edit script, with its line break unless it is
+/// the last line of a text which does not end with one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffLine<'a> {
+    Equal(&'a str),
+    Delete(&'a str),
+    Insert(&'a str),
+}
+
+/// Returns a shortest edit script turning the lines of `old` into the lines of
+/// `new`, computed with the algorithm of Myers.
+#[allow(clippy::many_single_char_names)] // the names of the paper
+pub fn diff_lines<'a>(old: &'a str, new: &'a str) -> Vec<DiffLine<'a>> {
+    let a: Vec<&str> = old.split_inclusive('\n').collect();
+    let b: Vec<&str> = new.split_inclusive('\n').collect();
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = n + m;
+    // `v[k + max]` is the furthest `x` reached on the diagonal `k = x - y`
+    let index = |k: isize| (k + max) as usize;
+    let mut v = vec![0; 2 * max as usize + 2];
+    let mut trace = Vec::new();
+
+    'search: for d in 0..=max {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+                v[index(k + 1)]
+            } else {
+                v[index(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[index(k)] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    // Walks back from the end, through the furthest points of every step
+    let mut lines = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let previous_k = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = v[index(previous_k)];
+        let previous_y = previous_x - previous_k;
+        while x > previous_x && y > previous_y {
+            lines.push(DiffLine::Equal(a[x as usize - 1]));
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            if x == previous_x {
+                lines.push(DiffLine::Insert(b[y as usize - 1]));
+            } else {
+                lines.push(DiffLine::Delete(a[x as usize - 1]));
+            }
+        }
+        x = previous_x;
+        y = previous_y;
+    }
+    lines.reverse();
+    lines
+}
+
+/// Number of lines inserted and deleted by an edit script.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DiffStat {
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+impl DiffStat {
+    pub fn new(lines: &[DiffLine]) -> Self {
+        let mut stat = Self::default();
+        for line in lines {
+            match line {
+                DiffLine::Insert(_) => stat.insertions += 1,
+                DiffLine::Delete(_) => stat.deletions += 1,
+                DiffLine::Equal(_) => {}
+            }
+        }
+        stat
+    }
+}
+
+impl std::fmt::Display for DiffStat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} insertion(s)(+), {} deletion(s)(-)",
+            self.insertions, self.deletions
+        )
+    }
+}
+
+/// Lines of context around the changes of a [`unified_diff`].
+const CONTEXT: usize = 3;
+
+/// Formats an edit script in the unified format of `diff -u`, with three
+/// lines of context, between files named `old_name` and `new_name`. Returns
+/// an empty string if the script has no changes.
+pub fn unified_diff(old_name: &str, new_name: &str, lines: &[DiffLine]) -> String {
+    let changes: Vec<usize> = (0..lines.len())
+        .filter(|&i| !matches!(lines[i], DiffLine::Equal(_)))
+        .collect();
+    let Some(&first) = changes.first() else {
+        return String::new();
+    };
+
+    // Ranges of the script shown by every hunk, merged when their context
+    // overlaps
+    let mut hunks = vec![(first.saturating_sub(CONTEXT), first + 1)];
+    for &change in &changes[1..] {
+        let last = hunks.last_mut().unwrap();
+        if change <= last.1 + 2 * CONTEXT {
+            last.1 = change + 1;
+        } else {
+            hunks.push((change - CONTEXT, change + 1));
+        }
+    }
+
+    let mut output = format!("--- {old_name}\n+++ {new_name}\n");
+    for (start, end) in hunks {
+        let end = (end + CONTEXT).min(lines.len());
+        let hunk = &lines[start..end];
+        let old_before = lines[..start]
+            .iter()
+            .filter(|line| !matches!(line, DiffLine::Insert(_)))
+            .count();
+        let new_before = lines[..start]
+            .iter()
+            .filter(|line| !matches!(line, DiffLine::Delete(_)))
+            .count();
+        let stat = DiffStat::new(hunk);
+        let equal = hunk.len() - stat.insertions - stat.deletions;
+        let _ = writeln!(
+            output,
+            "@@ -{} +{} @@",
+            hunk_range(old_before, equal + stat.deletions),
+            hunk_range(new_before, equal + stat.insertions)
+        );
+        for line in hunk {
+            let (prefix, text) = match line {
+                DiffLine::Equal(text) => (' ', text),
+                DiffLine::Delete(text) => ('-', text),
+                DiffLine::Insert(text) => ('+', text),
+            };
+            output.push(prefix);
+            output.push_str(text);
+            if !text.ends_with('\n') {
+                output.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+    }
+    output
+}
+
+/// Formats the lines of a hunk, `start,count`, the start being the line
+/// before the hunk when it is empty.
+fn hunk_range(before: usize, count: usize) -> String {
+    match count {
+        0 => format!("{before},0"),
+        1 => (before + 1).to_string(),
+        _ => format!("{},{count}", before + 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_lines() {
+        let old = "a\nb\nc\na\nb\nb\na\n";
+        let new = "c\nb\na\nb\na\nc\n";
+        let lines = diff_lines(old, new);
+        // Applying the script to `old` gives `new`
+        let kept_old: Vec<&str> = lines
+            .iter()
+            .filter_map(|line| match line {
+                DiffLine::Equal(line) | DiffLine::Delete(line) => Some(*line),
+                DiffLine::Insert(_) => None,
+            })
+            .collect();
+        let kept_new: Vec<&str> = lines
+            .iter()
+            .filter_map(|line| match line {
+                DiffLine::Equal(line) | DiffLine::Insert(line) => Some(*line),
+                DiffLine::Delete(_) => None,
+            })
+            .collect();
+        assert_eq!(kept_old, old.split_inclusive('\n').collect::<Vec<_>>());
+        assert_eq!(kept_new, new.split_inclusive('\n').collect::<Vec<_>>());
+        // The shortest script of this example has 5 edits
+        let stat = DiffStat::new(&lines);
+        assert_eq!(stat.insertions + stat.deletions, 5);
+
+        assert_eq!(diff_lines("", ""), vec![]);
+        assert_eq!(
+            diff_lines("a\nb", "a\nb\n"),
+            vec![
+                DiffLine::Equal("a\n"),
+                DiffLine::Delete("b"),
+                DiffLine::Insert("b\n")
+            ]
+        );
+        assert_eq!(
+            DiffStat::new(&diff_lines("a\nb\n", "a\nb\nc\n")).to_string(),
+            "1 insertion(s)(+), 0 deletion(s)(-)"
+        );
+    }
+
+    #[test]
+    fn test_unified_diff() {
+        let old: String = (1..=12).map(|i| format!("{i}\n")).collect();
+        let mut new: String = (1..=10)
+            .map(|i| match i {
+                2 => "two\n".to_string(),
+                i => format!("{i}\n"),
+            })
+            .collect();
+        new.push_str("12");
+        let expected = "\
+--- a.monkey
++++ b.monkey
+@@ -1,5 +1,5 @@
+ 1
+-2
++two
+ 3
+ 4
+ 5
+@@ -8,5 +8,4 @@
+ 8
+ 9
+ 10
+-11
+-12
++12
+\\ No newline at end of file
+";
+        assert_eq!(
+            unified_diff("a.monkey", "b.monkey", &diff_lines(&old, &new)),
+            expected
+        );
+        assert_eq!(unified_diff("a", "b", &diff_lines(&old, &old)), "");
+
+        let expected = "--- a\n+++ b\n@@ -0,0 +1 @@\n+x\n";
+        assert_eq!(unified_diff("a", "b", &diff_lines("", "x\n")), expected);
+    }
+}
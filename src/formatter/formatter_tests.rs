@@ -124,6 +124,14 @@ let d = !!true;
         assert_eq!(formatted, expected);
     }
 
+    #[test]
+    fn test_bytes_have_prefix() {
+        let input = r#"let x = b"hello";
+"#;
+
+        assert_eq!(format(input), input);
+    }
+
     #[test]
     fn test_fibonacci_it_formatting() {
         let input = r"
@@ -580,4 +588,53 @@ a(12);
 
         assert_eq!(format(input), expected);
     }
+
+    #[test]
+    fn test_index_assignment() {
+        let input = r#"let a = [[1], {}];
+        a[0][0]=2;
+        a[1]["b" ] = fn(x) { x[0] = 1; x };
+        "#;
+        let expected = r#"let a = [[1], {}];
+a[0][0] = 2;
+a[1]["b"] = fn (x) {
+    x[0] = 1;
+    x
+};
+"#;
+
+        assert_eq!(format(input), expected);
+    }
+
+    #[test]
+    fn test_comments() {
+        let input = r#"// Computes a factorial
+let fact = fn(n) { // recursive
+    // The base case
+    if (n < 2) { return 1; } // no else
+    n * fact(n - 1)
+    // unreachable
+};
+
+puts(fact(5)); // 120
+// the end"#;
+        let expected = r#"// Computes a factorial
+let fact = fn (n) { // recursive
+    // The base case
+    if (n < 2) {
+        return 1;
+    } // no else
+    n * fact(n - 1)
+    // unreachable
+};
+puts(fact(5)); // 120
+// the end
+"#;
+
+        assert_eq!(format(input), expected);
+        assert_eq!(format(expected), expected);
+
+        let input = "while (true) {\n// nothing\n}";
+        assert_eq!(format(input), "while (true) {\n    // nothing\n}\n");
+    }
 }
@@ -1,9 +1,20 @@
 pub mod cli;
+pub mod diff;
 mod formatter_tests;
 
-use crate::parser::{
-    ast::{BlockStatement, Expression, FunctionLiteral, Precedence, Program, Statement},
-    parse,
+use std::collections::VecDeque;
+
+use crate::{
+    lexer::{Comment, Lexer},
+    parser::{
+        ast::{
+            walk_block_statement, walk_program, BlockStatement, Expression, FunctionLiteral,
+            Precedence, Program, Statement, Visitor,
+        },
+        parse,
+        parser_errors::ParserErrors,
+        Parser,
+    },
 };
 
 /// A formatter function scope.
@@ -53,6 +64,9 @@ pub struct Formatter {
     /// The current formatter function scope.
     formatter_function_scope: Option<Box<FormatterFunctionScope>>,
 
+    /// The comments of the program which are not formatted yet.
+    comments: VecDeque<Comment>,
+
     /// The output buffer.
     output: String,
 }
@@ -64,6 +78,7 @@ impl Formatter {
             preference: Precedence::Lowest,
             last_expression: None,
             formatter_function_scope: None,
+            comments: VecDeque::new(),
             output: String::new(),
         }
     }
@@ -73,6 +88,22 @@ impl Formatter {
         Self::format_program(program)
     }
 
+    /// Formats `input`, or returns the errors of the parser if it is not a
+    /// valid program: the formatted code would lose what the parser skipped.
+    ///
+    /// # Errors
+    ///
+    /// If the parser reports errors.
+    pub fn try_format(input: &str) -> Result<String, ParserErrors> {
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse_program();
+        if parser.errors.is_empty() {
+            Ok(Self::format_program(program))
+        } else {
+            Err(parser.errors)
+        }
+    }
+
     pub fn format_program(program: Program) -> String {
         let mut formatter = Self::new();
 
@@ -80,18 +111,91 @@ impl Formatter {
         formatter.output.clone()
     }
 
-    fn visit_program(&mut self, program: Program) {
-        for stmt in program.statements {
-            self.visit_statement(&stmt);
+    fn visit_program(&mut self, mut program: Program) {
+        self.comments = std::mem::take(&mut program.comments).into();
+        walk_program(self, &program);
+        self.push_comments_before(Some(usize::MAX));
+    }
+
+    /// Pushes the comments which are before `offset` in the source. The ones
+    /// which followed a token are put at the end of the last line, and the
+    /// others on their own lines.
+    fn push_comments_before(&mut self, offset: Option<usize>) {
+        let Some(offset) = offset else {
+            return;
+        };
+        while self
+            .comments
+            .front()
+            .is_some_and(|comment| comment.offset < offset)
+        {
+            let comment = self.comments.pop_front().unwrap();
+            if !comment.own_line && self.output.ends_with('\n') {
+                self.output.pop();
+                self.push(" ");
+            } else {
+                self.push_indent();
+            }
+            self.push(&comment.text);
+            self.push("\n");
+        }
+    }
+
+    fn get_precedence(&self, exp: &Expression) -> Precedence {
+        match exp {
+            Expression::Infix(infix) => Precedence::from(&infix.token),
+            Expression::Prefix(prefix) => Precedence::from(&prefix.token),
+            _ => Precedence::Lowest,
+        }
+    }
+
+    fn enter_function(&mut self, function: &FunctionLiteral) {
+        self.formatter_function_scope = Some(Box::new(FormatterFunctionScope::new(
+            self.formatter_function_scope.clone(),
+            function.body.statements.len(),
+        )));
+
+        self.indent += 1;
+    }
+
+    fn leave_function(&mut self) {
+        self.indent -= 1;
+        if let Some(ref mut scope) = self.formatter_function_scope {
+            self.formatter_function_scope = scope.leave_scope();
+        }
+    }
+
+    fn push(&mut self, s: &str) {
+        self.output.push_str(s);
+    }
+
+    fn push_indent(&mut self) {
+        for _ in 0..self.indent {
+            self.push("    ");
+        }
+    }
+}
+
+impl Visitor for Formatter {
+    fn visit_statements(&mut self, statements: &[Statement], offsets: &[usize]) {
+        for (i, stmt) in statements.iter().enumerate() {
+            let offset = offsets.get(i).copied();
+            self.push_comments_before(offset);
+            self.visit_statement(stmt, offset);
         }
     }
 
-    fn visit_statement(&mut self, stmt: &Statement) {
+    fn visit_block_statement(&mut self, block: &BlockStatement) {
+        walk_block_statement(self, block);
+        self.push_comments_before(block.end);
+    }
+
+    fn visit_statement(&mut self, stmt: &Statement, _offset: Option<usize>) {
         self.push_indent();
         match stmt {
             Statement::Let(let_stmt) => {
                 self.push("let ");
-                self.push(let_stmt.name.value.as_str());
+                self.push(&let_stmt.name.value);
                 self.push(" = ");
                 self.visit_expression(&let_stmt.value);
                 self.push(";");
@@ -126,6 +230,17 @@ impl Formatter {
                 self.push(cf.to_string().as_str());
                 self.push(";");
             }
+            Statement::IndexAssignment(assignment) => {
+                self.push(&assignment.name.value);
+                for index in &assignment.indexes {
+                    self.push("[");
+                    self.visit_expression(index);
+                    self.push("]");
+                }
+                self.push(" = ");
+                self.visit_expression(&assignment.value);
+                self.push(";");
+            }
         }
         self.push("\n");
         self.last_expression = None;
@@ -134,7 +249,7 @@ impl Formatter {
     fn visit_expression(&mut self, exp: &Expression) {
         match exp {
             Expression::Identifier(ident) => {
-                self.push(ident.value.as_str());
+                self.push(&ident.value);
             }
             Expression::Primitive(primitive) => {
                 self.push(primitive.to_string().as_str());
@@ -153,12 +268,12 @@ impl Formatter {
                             self.push("(");
                             needs_parenthesis = true;
                         }
-                        Expression::Infix(last_infix) => {
-                            if Precedence::from(&last_infix.token) > Precedence::from(&infix.token)
-                            {
-                                self.push("(");
-                                needs_parenthesis = true;
-                            }
+                        Expression::Infix(last_infix)
+                            if Precedence::from(&last_infix.token)
+                                > Precedence::from(&infix.token) =>
+                        {
+                            self.push("(");
+                            needs_parenthesis = true;
                         }
                         _ => {}
                     }
@@ -251,53 +366,15 @@ impl Formatter {
         self.push("\n");
 
         self.enter_function(func);
-        for stmt in &func.body.statements {
-            self.visit_statement(stmt);
+        for (i, stmt) in func.body.statements.iter().enumerate() {
+            self.push_comments_before(func.body.offsets.get(i).copied());
+            self.visit_statement(stmt, func.body.offsets.get(i).copied());
             self.formatter_function_scope.as_mut().unwrap().next();
         }
+        self.push_comments_before(func.body.end);
         self.leave_function();
 
         self.push_indent();
         self.push("}");
     }
-
-    fn visit_block_statement(&mut self, block: &BlockStatement) {
-        for stmt in &block.statements {
-            self.visit_statement(stmt);
-        }
-    }
-
-    fn get_precedence(&self, exp: &Expression) -> Precedence {
-        match exp {
-            Expression::Infix(infix) => Precedence::from(&infix.token),
-            Expression::Prefix(prefix) => Precedence::from(&prefix.token),
-            _ => Precedence::Lowest,
-        }
-    }
-
-    fn enter_function(&mut self, function: &FunctionLiteral) {
-        self.formatter_function_scope = Some(Box::new(FormatterFunctionScope::new(
-            self.formatter_function_scope.clone(),
-            function.body.statements.len(),
-        )));
-
-        self.indent += 1;
-    }
-
-    fn leave_function(&mut self) {
-        self.indent -= 1;
-        if let Some(ref mut scope) = self.formatter_function_scope {
-            self.formatter_function_scope = scope.leave_scope();
-        }
-    }
-
-    fn push(&mut self, s: &str) {
-        self.output.push_str(s);
-    }
-
-    fn push_indent(&mut self) {
-        for _ in 0..self.indent {
-            self.push("    ");
-        }
-    }
 }
@@ -0,0 +1,279 @@
+use crate::{
+    doc::escape_html,
+    lexer::{token::Token, Lexer},
+    object::builtins::BuiltinFunction,
+};
+
+/// Kind of a piece of Monkey code, which decides its color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Highlight {
+    Keyword,
+    Boolean,
+    Number,
+    String,
+    /// An identifier naming a builtin function.
+    Builtin,
+    Identifier,
+    Operator,
+    Punctuation,
+    Comment,
+    /// A character the lexer does not know.
+    Illegal,
+}
+
+impl Highlight {
+    fn new(token: &Token) -> Self {
+        match token {
+            Token::Let
+            | Token::Function
+            | Token::If
+            | Token::Else
+            | Token::Return
+            | Token::While
+            | Token::Break
+            | Token::Continue => Highlight::Keyword,
+            Token::True | Token::False => Highlight::Boolean,
+            Token::Int(_) => Highlight::Number,
+            Token::String(_) | Token::Bytes(_) => Highlight::String,
+            Token::Ident(name) if BuiltinFunction::get_builtin(name).is_some() => {
+                Highlight::Builtin
+            }
+            Token::Ident(_) => Highlight::Identifier,
+            Token::Assign
+            | Token::Plus
+            | Token::Minus
+            | Token::Bang
+            | Token::Asterisk
+            | Token::Slash
+            | Token::LT
+            | Token::GT
+            | Token::LTE
+            | Token::GTE
+            | Token::Equal
+            | Token::NotEqual
+            | Token::And
+            | Token::Or
+            | Token::Modulo => Highlight::Operator,
+            Token::Illegal(_) => Highlight::Illegal,
+            _ => Highlight::Punctuation,
+        }
+    }
+
+    /// Returns the CSS class of the `<span>`s of [`html`].
+    pub fn css_class(self) -> &'static str {
+        match self {
+            Highlight::Keyword => "mk-keyword",
+            Highlight::Boolean => "mk-boolean",
+            Highlight::Number => "mk-number",
+            Highlight::String => "mk-string",
+            Highlight::Builtin => "mk-builtin",
+            Highlight::Identifier => "mk-identifier",
+            Highlight::Operator => "mk-operator",
+            Highlight::Punctuation => "mk-punctuation",
+            Highlight::Comment => "mk-comment",
+            Highlight::Illegal => "mk-illegal",
+        }
+    }
+
+    /// Returns the ANSI escape sequence setting the color of [`ansi`], if the
+    /// text is colored.
+    pub(crate) fn ansi_color(self) -> Option<&'static str> {
+        match self {
+            Highlight::Keyword => Some("\x1b[35m"),
+            Highlight::Boolean | Highlight::Number => Some("\x1b[33m"),
+            Highlight::String => Some("\x1b[32m"),
+            Highlight::Builtin => Some("\x1b[36m"),
+            Highlight::Comment => Some("\x1b[90m"),
+            Highlight::Illegal => Some("\x1b[31m"),
+            Highlight::Identifier | Highlight::Operator | Highlight::Punctuation => None,
+        }
+    }
+}
+
+pub(crate) const ANSI_RESET: &str = "\x1b[0m";
+
+/// Splits `text` into pieces with their kind, `None` for the whitespace
+/// between tokens. The pieces put together give back `text`, even if it does
+/// not parse.
+pub fn spans(text: &str) -> Vec<(Option<Highlight>, String)> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut spans = Vec::new();
+    let mut position = 0;
+    let mut lexer = Lexer::new(text);
+    loop {
+        let token = lexer.next_token();
+        if token == Token::Eof {
+            break;
+        }
+        let start = lexer.token_offset().max(position);
+        let end = lexer.token_end().max(start);
+        push_gap(&mut spans, &chars[position..start]);
+        spans.push((
+            Some(Highlight::new(&token)),
+            chars[start..end].iter().collect(),
+        ));
+        position = end;
+    }
+    push_gap(&mut spans, &chars[position..]);
+    spans
+}
+
+/// Adds the text between two tokens, whitespace and `//` comments.
+fn push_gap(spans: &mut Vec<(Option<Highlight>, String)>, gap: &[char]) {
+    let mut rest = gap;
+    while !rest.is_empty() {
+        let comment = rest.windows(2).position(|pair| pair == ['/', '/']);
+        let whitespace = comment.unwrap_or(rest.len());
+        if whitespace > 0 {
+            spans.push((None, rest[..whitespace].iter().collect()));
+        }
+        rest = &rest[whitespace..];
+        if !rest.is_empty() {
+            let end = rest.iter().position(|&c| c == '\n').unwrap_or(rest.len());
+            spans.push((Some(Highlight::Comment), rest[..end].iter().collect()));
+            rest = &rest[end..];
+        }
+    }
+}
+
+/// Returns the indexes in `spans` of the parentheses, brackets and braces
+/// which are not closed, or which close nothing.
+pub fn unmatched_delimiters(spans: &[(Option<Highlight>, String)]) -> Vec<usize> {
+    let mut unmatched = Vec::new();
+    let mut open: Vec<(usize, &str)> = Vec::new();
+    for (i, (highlight, text)) in spans.iter().enumerate() {
+        if *highlight != Some(Highlight::Punctuation) {
+            continue;
+        }
+        let closing = match text.as_str() {
+            "(" | "[" | "{" => {
+                open.push((i, text));
+                continue;
+            }
+            ")" => "(",
+            "]" => "[",
+            "}" => "{",
+            _ => continue,
+        };
+        // A delimiter closing an outer one leaves the inner ones unclosed
+        match open.iter().rposition(|(_, opening)| *opening == closing) {
+            Some(matching) => {
+                unmatched.extend(open.drain(matching..).skip(1).map(|(i, _)| i));
+            }
+            None => unmatched.push(i),
+        }
+    }
+    unmatched.extend(open.into_iter().map(|(i, _)| i));
+    unmatched.sort_unstable();
+    unmatched
+}
+
+/// Returns `text` colored with ANSI escape sequences, for a terminal.
+pub fn ansi(text: &str) -> String {
+    spans(text)
+        .into_iter()
+        .map(
+            |(highlight, text)| match highlight.and_then(Highlight::ansi_color) {
+                Some(color) => format!("{color}{text}{ANSI_RESET}"),
+                None => text,
+            },
+        )
+        .collect()
+}
+
+/// Returns `text` as an HTML `<pre>` block, every token in a `<span>` with
+/// the CSS class of its kind, such as `mk-keyword`.
+pub fn html(text: &str) -> String {
+    let code: String = spans(text)
+        .into_iter()
+        .map(|(highlight, text)| match highlight {
+            Some(highlight) => format!(
+                "<span class=\"{}\">{}</span>",
+                highlight.css_class(),
+                escape_html(&text)
+            ),
+            None => escape_html(&text),
+        })
+        .collect();
+    format!("<pre class=\"monkey\"><code>{code}</code></pre>\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spans() {
+        let input = "let s = len(\"a<b\"); // size\n  s >= 2 @";
+        let spans = spans(input);
+        assert_eq!(
+            spans
+                .iter()
+                .map(|(_, text)| text.as_str())
+                .collect::<String>(),
+            input
+        );
+        assert_eq!(
+            spans,
+            vec![
+                (Some(Highlight::Keyword), "let".to_string()),
+                (None, " ".to_string()),
+                (Some(Highlight::Identifier), "s".to_string()),
+                (None, " ".to_string()),
+                (Some(Highlight::Operator), "=".to_string()),
+                (None, " ".to_string()),
+                (Some(Highlight::Builtin), "len".to_string()),
+                (Some(Highlight::Punctuation), "(".to_string()),
+                (Some(Highlight::String), "\"a<b\"".to_string()),
+                (Some(Highlight::Punctuation), ")".to_string()),
+                (Some(Highlight::Punctuation), ";".to_string()),
+                (None, " ".to_string()),
+                (Some(Highlight::Comment), "// size".to_string()),
+                (None, "\n  ".to_string()),
+                (Some(Highlight::Identifier), "s".to_string()),
+                (None, " ".to_string()),
+                (Some(Highlight::Operator), ">=".to_string()),
+                (None, " ".to_string()),
+                (Some(Highlight::Number), "2".to_string()),
+                (None, " ".to_string()),
+                (Some(Highlight::Illegal), "@".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unmatched_delimiters() {
+        let unmatched = |text| {
+            let spans = spans(text);
+            unmatched_delimiters(&spans)
+                .into_iter()
+                .map(|i| spans[i].1.clone())
+                .collect::<Vec<_>>()
+        };
+        assert!(unmatched("f([1, {\"a\": 2}])").is_empty());
+        // Delimiters in strings and comments do not count
+        assert!(unmatched("\"(\" // {").is_empty());
+        assert_eq!(unmatched("fn(x) { x[0 }"), vec!["["]);
+        assert_eq!(unmatched("f(x]"), vec!["(", "]"]);
+        assert_eq!(unmatched("if (x) { y"), vec!["{"]);
+        assert_eq!(unmatched(") ("), vec![")", "("]);
+    }
+
+    #[test]
+    fn test_ansi() {
+        assert_eq!(
+            ansi("if (true) { x }"),
+            "\x1b[35mif\x1b[0m (\x1b[33mtrue\x1b[0m) { x }"
+        );
+    }
+
+    #[test]
+    fn test_html() {
+        assert_eq!(
+            html("x < \"<\"\n"),
+            "<pre class=\"monkey\"><code><span class=\"mk-identifier\">x</span> \
+             <span class=\"mk-operator\">&lt;</span> \
+             <span class=\"mk-string\">&quot;&lt;&quot;</span>\n</code></pre>\n"
+        );
+    }
+}
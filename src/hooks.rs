@@ -0,0 +1,167 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{compiler::code::Opcode, object::Object, parser::ast::Statement};
+
+/// Observer callbacks called by the execution engines ([`Evaluator`] and [`VM`]).
+///
+/// Every method has an empty default implementation, so implementors only need
+/// to override the events they care about. When no hooks are installed the
+/// engines only pay for a `None` check, and disabling the `hooks` feature
+/// removes the checks altogether.
+///
+/// [`Evaluator`]: crate::interpreter::evaluator::Evaluator
+/// [`VM`]: crate::vm::VM
+pub trait Hooks {
+    /// Called by the evaluator before a statement is evaluated.
+    fn on_statement(&mut self, _statement: &Statement) {}
+
+    /// Called by the VM before an instruction is executed.
+    fn on_instruction(&mut self, _ip: usize, _opcode: Opcode) {}
+
+    /// Called when a function (or builtin) is called, before its body runs.
+    fn on_call(&mut self, _function: &Object, _args: &[Object]) {}
+
+    /// Called when the last called function returns.
+    fn on_return(&mut self, _value: &Object) {}
+
+    /// Called when an error is produced.
+    fn on_error(&mut self, _error: &str) {}
+}
+
+/// Allows keeping a handle on the hooks installed in an engine, to inspect them
+/// once the execution is done.
+impl<T: Hooks> Hooks for Rc<RefCell<T>> {
+    fn on_statement(&mut self, statement: &Statement) {
+        self.borrow_mut().on_statement(statement);
+    }
+
+    fn on_instruction(&mut self, ip: usize, opcode: Opcode) {
+        self.borrow_mut().on_instruction(ip, opcode);
+    }
+
+    fn on_call(&mut self, function: &Object, args: &[Object]) {
+        self.borrow_mut().on_call(function, args);
+    }
+
+    fn on_return(&mut self, value: &Object) {
+        self.borrow_mut().on_return(value);
+    }
+
+    fn on_error(&mut self, error: &str) {
+        self.borrow_mut().on_error(error);
+    }
+}
+
+/// Calls a hook if one is installed in the engine. It expects the engine to
+/// have an `Option<Box<dyn Hooks>>` field named `hooks`.
+macro_rules! hook {
+    ($engine:ident . $method:ident ( $($arg:expr),* )) => {
+        #[cfg(feature = "hooks")]
+        if let Some(hooks) = $engine.hooks.as_mut() {
+            hooks.$method($($arg),*);
+        }
+    };
+}
+
+pub(crate) use hook;
+
+#[cfg(all(test, feature = "hooks"))]
+mod tests {
+    use super::*;
+    use crate::{compiler::Compiler, interpreter::evaluator::Evaluator, parser::parse, vm::VM};
+
+    #[derive(Default)]
+    struct Recorder {
+        statements: usize,
+        instructions: usize,
+        calls: Vec<String>,
+        returns: Vec<Object>,
+        errors: Vec<String>,
+    }
+
+    impl Hooks for Recorder {
+        fn on_statement(&mut self, _statement: &Statement) {
+            self.statements += 1;
+        }
+
+        fn on_instruction(&mut self, _ip: usize, _opcode: Opcode) {
+            self.instructions += 1;
+        }
+
+        fn on_call(&mut self, function: &Object, args: &[Object]) {
+            self.calls
+                .push(format!("{}/{}", function.get_type(), args.len()));
+        }
+
+        fn on_return(&mut self, value: &Object) {
+            self.returns.push(value.clone());
+        }
+
+        fn on_error(&mut self, error: &str) {
+            self.errors.push(error.to_string());
+        }
+    }
+
+    const INPUT: &str = r"
+    let add = fn(a, b) { a + b };
+    let x = add(1, 2);
+    len(1);
+    ";
+
+    #[test]
+    fn test_evaluator_hooks() {
+        let recorder = Rc::new(RefCell::new(Recorder::default()));
+        let mut evaluator = Evaluator::new();
+        evaluator.set_hooks(Box::new(Rc::clone(&recorder)));
+
+        evaluator.eval(parse(INPUT));
+
+        let recorder = recorder.borrow();
+        // 3 top level statements and the body of `add`
+        assert_eq!(recorder.statements, 4);
+        assert_eq!(recorder.instructions, 0);
+        assert_eq!(recorder.calls, vec!["FUNCTION/2", "BUILTIN/1"]);
+        assert_eq!(recorder.returns[0], Object::INTEGER(3));
+        assert_eq!(
+            recorder.errors,
+            vec!["argument to `len` not supported, got INTEGER"]
+        );
+    }
+
+    #[test]
+    fn test_evaluator_hooks_with_tail_calls() {
+        let recorder = Rc::new(RefCell::new(Recorder::default()));
+        let mut evaluator = Evaluator::new();
+        evaluator.set_hooks(Box::new(Rc::clone(&recorder)));
+
+        evaluator.eval(parse(
+            "let f = fn(n) { if (n == 0) { 5 } else { f(n - 1) } }; f(3)",
+        ));
+
+        // Every call returns, even those replaced by a tail call
+        let recorder = recorder.borrow();
+        assert_eq!(recorder.calls.len(), 4);
+        assert_eq!(recorder.returns, vec![Object::INTEGER(5); 4]);
+    }
+
+    #[test]
+    fn test_vm_hooks() {
+        let recorder = Rc::new(RefCell::new(Recorder::default()));
+        let mut compiler = Compiler::new();
+        compiler.compile(&parse(INPUT)).unwrap();
+        let mut vm = VM::new(compiler.bytecode());
+        vm.set_hooks(Box::new(Rc::clone(&recorder)));
+
+        vm.run().unwrap();
+
+        let recorder = recorder.borrow();
+        assert_eq!(recorder.statements, 0);
+        assert!(recorder.instructions > 0);
+        assert_eq!(recorder.calls, vec!["CLOSURE/2", "BUILTIN/1"]);
+        assert_eq!(recorder.returns[0], Object::INTEGER(3));
+        assert_eq!(
+            recorder.errors,
+            vec!["argument to `len` not supported, got INTEGER"]
+        );
+    }
+}
@@ -1,20 +1,152 @@
+#[cfg(feature = "hooks")]
+use crate::hooks::Hooks;
 use crate::{
+    hooks::hook,
     lexer::token::Token,
     object::{
-        builtins::BuiltinFunction,
+        builtins::{BuiltinContext, BuiltinFunction},
+        deadline::Deadline,
         enviroment::Environment,
-        {Function, Object, FALSE, NULL, TRUE},
+        error::{ErrorKind, ErrorObject},
+        interrupt::{Interrupt, INTERRUPTED},
+        random::Random,
+        style::OutputStyle,
+        {
+            max_depth_error, Function, Object, StackFrame, DEFAULT_MAX_DEPTH, FALSE,
+            FUEL_EXHAUSTED, NULL, TRUE,
+        },
     },
     parser::ast::{
-        BlockStatement, Conditional, Expression, HashMapLiteral, Identifier, IndexExpression,
-        Primitive, Program, Statement,
+        BlockStatement, Expression, Identifier, LoopStatement, Primitive, Program, Statement,
+        WhileStatement,
     },
+    source::{LineIndex, Source},
+    strict::{condition_error, StrictConfig},
 };
 
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use super::strict::StrictChecker;
+
+use std::{cell::RefCell, collections::HashMap, io::BufRead, rc::Rc, time::Duration};
+
+/// A function being called, with the offset of the statement it is at.
+#[derive(Debug, Clone)]
+struct Call {
+    name: Option<String>,
+    offset: Option<usize>,
+}
+
+impl Call {
+    fn main() -> Self {
+        Self {
+            name: None,
+            offset: None,
+        }
+    }
+}
+
+/// Work left to evaluate. The evaluator keeps it on a stack instead of
+/// recursing, so that deeply nested expressions and deep recursion cannot
+/// overflow the native stack.
+///
+/// The tasks evaluating an expression or a statement push its value on the
+/// stack of values, where the tasks continuing them find it.
+enum Task {
+    Statement(Statement),
+    Expression(Expression),
+    /// Statements left in a block, starting with the one at `index`. The
+    /// value of the previous one is on the stack.
+    Block {
+        statements: std::vec::IntoIter<Statement>,
+        offsets: Vec<usize>,
+        index: usize,
+    },
+    Let(Rc<str>),
+    Return,
+    /// Checks the condition of a loop, to evaluate its body.
+    Loop(WhileStatement),
+    /// Evaluates the condition of a loop again. The value of the loop is on
+    /// the stack, above the first `values` values.
+    NextIteration {
+        stm: WhileStatement,
+        values: usize,
+    },
+    /// Assigns the value on the stack, after the collection and `indexes`
+    /// indexes.
+    IndexAssignment {
+        name: Rc<str>,
+        indexes: usize,
+    },
+    Prefix(Token),
+    Infix(Token),
+    Conditional {
+        consequence: BlockStatement,
+        alternative: Option<BlockStatement>,
+    },
+    /// Calls the function on the stack with its arguments.
+    Call(usize),
+    /// Returns to the caller of a function, restoring its environment and
+    /// dropping the values above `values` if the body is interrupted.
+    /// `tail_calls` functions returned through the same task, from their
+    /// calls in tail position.
+    EndCall {
+        env: Rc<RefCell<Environment>>,
+        values: usize,
+        tail_calls: usize,
+    },
+    Array(usize),
+    Index,
+    /// Checks that the key on top of the stack can be used in a hash map.
+    HashKey,
+    /// Builds a hash map of the pairs of keys and values on the stack.
+    HashMap(usize),
+}
 
 pub struct Evaluator {
     env: Rc<RefCell<Environment>>,
+
+    /// Global environment, where `eval` runs its programs.
+    globals: Rc<RefCell<Environment>>,
+
+    output_style: OutputStyle,
+
+    /// Reader used by the input builtins, the standard input if it is `None`.
+    input: Option<Box<dyn BufRead>>,
+
+    random: Random,
+
+    /// Arguments of the program, returned by `argv`.
+    args: Vec<String>,
+
+    /// Number of functions being called.
+    depth: usize,
+    max_depth: usize,
+
+    /// Functions being called, starting with the main program.
+    calls: Vec<Call>,
+
+    tasks: Vec<Task>,
+    values: Vec<Object>,
+    /// Lengths of `tasks` and `values` when the running evaluation started.
+    base: (usize, usize),
+
+    /// Functions which were being called when the last error was raised.
+    failed_calls: Vec<Call>,
+
+    /// Source of the last program, to give the positions of the statements.
+    source: Option<Source>,
+
+    /// Number of statements and expressions left to evaluate, unlimited if it
+    /// is `None`.
+    fuel: Option<u64>,
+
+    deadline: Option<Deadline>,
+
+    interrupt: Option<Interrupt>,
+
+    strict: StrictConfig,
+
+    #[cfg(feature = "hooks")]
+    hooks: Option<Box<dyn Hooks>>,
 }
 
 impl Default for Evaluator {
@@ -25,127 +157,540 @@ impl Default for Evaluator {
 
 impl Evaluator {
     pub fn new() -> Self {
+        let env = Rc::new(RefCell::new(Environment::new()));
         Evaluator {
-            env: Rc::new(RefCell::new(Environment::new())),
+            globals: Rc::clone(&env),
+            env,
+
+            output_style: OutputStyle::default(),
+            input: None,
+            random: Random::default(),
+            args: Vec::new(),
+
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            calls: vec![Call::main()],
+            tasks: Vec::new(),
+            values: Vec::new(),
+            base: (0, 0),
+            failed_calls: Vec::new(),
+            source: None,
+            fuel: None,
+            deadline: None,
+            interrupt: None,
+            strict: StrictConfig::default(),
+
+            #[cfg(feature = "hooks")]
+            hooks: None,
         }
     }
 
+    #[cfg(feature = "hooks")]
+    pub fn set_hooks(&mut self, hooks: Box<dyn Hooks>) {
+        self.hooks = Some(hooks);
+    }
+
+    #[cfg(feature = "hooks")]
+    pub fn take_hooks(&mut self) -> Option<Box<dyn Hooks>> {
+        self.hooks.take()
+    }
+
+    pub fn set_output_style(&mut self, output_style: OutputStyle) {
+        self.output_style = output_style;
+    }
+
+    pub fn output_style(&self) -> &OutputStyle {
+        &self.output_style
+    }
+
+    /// Makes `input` and `read_all` read from `input` instead of the standard
+    /// input.
+    pub fn set_input(&mut self, input: Box<dyn BufRead>) {
+        self.input = Some(input);
+    }
+
+    /// Sets the number of nested calls a program can make, a deeper call
+    /// evaluates to an error naming the function.
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    /// Limits the number of statements and expressions the program can
+    /// evaluate, including those of `eval`. Once they are all used everything
+    /// evaluates to an error, so that untrusted programs always terminate.
+    pub fn set_fuel(&mut self, fuel: u64) {
+        self.fuel = Some(fuel);
+    }
+
+    /// Returns the number of statements and expressions left to evaluate, if
+    /// it is limited.
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    /// Removes the limit on the number of statements and expressions,
+    /// returning the ones which were left.
+    pub fn take_fuel(&mut self) -> Option<u64> {
+        self.fuel.take()
+    }
+
+    /// Makes the program evaluate to an error if it is still running `timeout`
+    /// from now.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.deadline = Some(Deadline::after(timeout));
+    }
+
+    /// Makes the program evaluate to an error once `interrupt` is set, until
+    /// it is cleared.
+    pub fn set_interrupt(&mut self, interrupt: Interrupt) {
+        self.interrupt = Some(interrupt);
+    }
+
+    /// Sets the arguments returned by `argv`.
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.args = args;
+    }
+
+    /// Sets the checks of the strict mode. The names defined by the programs
+    /// evaluated before count as defined.
+    pub fn set_strict(&mut self, strict: StrictConfig) {
+        self.strict = strict;
+    }
+
+    pub fn environment(&self) -> Rc<RefCell<Environment>> {
+        Rc::clone(&self.env)
+    }
+
+    /// Returns the bindings visible in the current environment, sorted by
+    /// name.
+    pub fn bindings(&self) -> Vec<(String, Object)> {
+        self.env.borrow().bindings()
+    }
+
     pub fn eval(&mut self, program: Program) -> Object {
-        let mut result = NULL;
-        for statement in program.statements {
-            result = self.eval_statement(statement);
-            match result {
-                Object::RETURN(x) => return *x,
-                Object::ERROR(x) => return Object::ERROR(x),
-                _ => (),
+        self.source = program.source;
+        self.calls = vec![Call::main()];
+        self.failed_calls.clear();
+
+        if self.strict.undefined_identifiers || self.strict.no_redefinitions {
+            let globals = self
+                .env
+                .borrow()
+                .iter()
+                .map(|(name, _)| Rc::clone(name))
+                .collect();
+            let checker = StrictChecker::new(self.strict, globals);
+            if let Err((mut error, offset)) = checker.check(&program.statements, &program.offsets) {
+                self.failed_calls = vec![Call { name: None, offset }];
+                error.position = self.stack_trace().last().and_then(|frame| frame.position);
+                hook!(self.on_error(&error.message));
+                return Object::ERROR(error);
+            }
+        }
+
+        let program = BlockStatement {
+            statements: program.statements,
+            offsets: program.offsets,
+            end: None,
+        };
+        match self.run(|evaluator| evaluator.start_block(program)) {
+            Object::RETURN(x) => *x,
+            Object::ERROR(mut error) => {
+                self.record_failed_calls();
+                if error.position.is_none() {
+                    error.position = self.stack_trace().last().and_then(|frame| frame.position);
+                }
+                hook!(self.on_error(&error.message));
+                Object::ERROR(error)
             }
+            result => result,
         }
-        result
     }
 
-    fn eval_block_statemet(&mut self, block: BlockStatement) -> Object {
-        let mut result = NULL;
-        for statement in block.statements {
-            result = self.eval_statement(statement);
-            match result {
-                Object::RETURN(_) | Object::ERROR(_) => return result,
+    /// Returns the function calls which were running when the last program
+    /// failed, from the main program to the function the error happened in,
+    /// with the statement each of them was at.
+    pub fn stack_trace(&self) -> Vec<StackFrame> {
+        let lines = self
+            .source
+            .as_ref()
+            .map(|source| LineIndex::new(&source.text));
+        self.failed_calls
+            .iter()
+            .enumerate()
+            .map(|(index, call)| StackFrame {
+                function: match (index, &call.name) {
+                    (0, _) => "main".to_string(),
+                    (_, Some(name)) => name.clone(),
+                    (_, None) => "<anonymous>".to_string(),
+                },
+                position: lines
+                    .as_ref()
+                    .zip(call.offset)
+                    .map(|(lines, offset)| lines.position(offset)),
+            })
+            .collect()
+    }
+
+    /// Runs the tasks pushed by `start` and returns the value they evaluate
+    /// to. Builtins calling functions run them from here too, nested in the
+    /// evaluation of the call to the builtin.
+    fn run(&mut self, start: impl FnOnce(&mut Self)) -> Object {
+        let base = std::mem::replace(&mut self.base, (self.tasks.len(), self.values.len()));
+        start(self);
+        while self.tasks.len() > self.base.0 {
+            let task = self.tasks.pop().unwrap();
+            self.execute(task);
+        }
+        let value = self.pop();
+        self.base = base;
+        value
+    }
+
+    fn pop(&mut self) -> Object {
+        self.values.pop().unwrap_or(NULL)
+    }
+
+    /// Pushes the value of an expression or a statement, or, if it returns
+    /// or interrupts the program, drops the tasks it skips.
+    fn produce(&mut self, value: Object) {
+        match value {
+            Object::RETURN(_)
+            | Object::BREAK
+            | Object::CONTINUE
+            | Object::ERROR(_)
+            | Object::EXIT(_) => self.unwind(value),
+            value => self.values.push(value),
+        }
+    }
+
+    /// Drops the tasks up to the loop `value` breaks or continues, the
+    /// function it returns from, or to the end of the evaluation if it is an
+    /// error or an exit.
+    fn unwind(&mut self, mut value: Object) {
+        while self.tasks.len() > self.base.0 {
+            match self.tasks.pop() {
+                Some(Task::Block { .. }) if matches!(value, Object::ERROR(_)) => {
+                    self.record_failed_calls();
+                }
+                Some(Task::NextIteration { stm, values })
+                    if matches!(value, Object::BREAK | Object::CONTINUE) =>
+                {
+                    self.values.truncate(values);
+                    self.values.push(NULL);
+                    if value == Object::CONTINUE {
+                        self.tasks.push(Task::NextIteration { stm, values });
+                    }
+                    return;
+                }
+                Some(Task::EndCall {
+                    env,
+                    values,
+                    tail_calls,
+                }) => {
+                    self.values.truncate(values);
+                    value = match value {
+                        Object::RETURN(x) => *x,
+                        x => x,
+                    };
+                    self.end_call(env, tail_calls, &value);
+                    if !value.interrupts() {
+                        self.values.push(value);
+                        return;
+                    }
+                }
                 _ => (),
             }
         }
-        result
+        self.values.truncate(self.base.1);
+        self.values.push(value);
     }
 
-    #[allow(clippy::match_wildcard_for_single_variants, unreachable_patterns)]
-    fn eval_statement(&mut self, statement: Statement) -> Object {
+    fn execute(&mut self, task: Task) {
+        match task {
+            Task::Statement(statement) => self.start_statement(statement),
+            Task::Expression(expression) => self.start_expression(expression),
+            Task::Block {
+                statements,
+                offsets,
+                index,
+            } => self.continue_block(statements, offsets, index),
+            Task::Let(name) => {
+                let value = self.pop();
+                self.env.borrow_mut().set(name, value);
+                self.values.push(NULL);
+            }
+            Task::Return => {
+                let value = self.pop();
+                self.produce(Object::RETURN(Box::new(value)));
+            }
+            Task::Loop(stm) => self.check_loop_condition(stm),
+            Task::NextIteration { stm, .. } => {
+                let condition = stm.condition.clone();
+                self.tasks.push(Task::Loop(stm));
+                self.tasks.push(Task::Expression(condition));
+            }
+            Task::IndexAssignment { name, indexes } => self.assign_index(name, indexes),
+            Task::Prefix(operator) => {
+                let right = self.pop();
+                self.produce(Self::eval_prefix_expression(&operator, &right));
+            }
+            Task::Infix(operator) => {
+                let right = self.pop();
+                let left = self.pop();
+                self.produce(Self::eval_infix_expression(&operator, left, right));
+            }
+            Task::Conditional {
+                consequence,
+                alternative,
+            } => {
+                let condition = self.pop();
+                if let Err(error) = self.check_condition(&condition) {
+                    return self.produce(Object::ERROR(error));
+                }
+                if condition.is_truthy() {
+                    self.start_block(consequence);
+                } else if let Some(alternative) = alternative {
+                    self.start_block(alternative);
+                } else {
+                    self.values.push(NULL);
+                }
+            }
+            Task::Call(args) => {
+                let args = self.values.split_off(self.values.len() - args);
+                let function = self.pop();
+                self.apply_function(function, args);
+            }
+            Task::EndCall {
+                env, tail_calls, ..
+            } => {
+                let value = self.pop();
+                self.end_call(env, tail_calls, &value);
+                self.values.push(value);
+            }
+            Task::Array(elements) => {
+                let elements = self.values.split_off(self.values.len() - elements);
+                self.values.push(Object::ARRAY(elements));
+            }
+            Task::Index => {
+                let index = self.pop();
+                let left = self.pop();
+                self.produce(Self::eval_index_expression(&left, &index));
+            }
+            Task::HashKey => {
+                if !self.values.last().is_some_and(Object::is_hashable) {
+                    let key = self.pop();
+                    self.produce(Object::ERROR(ErrorObject::new(
+                        ErrorKind::TypeMismatch,
+                        format!("unusable as hash key: {}", key.get_type()),
+                    )));
+                }
+            }
+            Task::HashMap(pairs) => {
+                let mut values = self
+                    .values
+                    .split_off(self.values.len() - 2 * pairs)
+                    .into_iter();
+                #[allow(clippy::mutable_key_type)]
+                let mut hashmap = HashMap::new();
+                while let (Some(key), Some(value)) = (values.next(), values.next()) {
+                    hashmap.insert(key, value);
+                }
+                self.values.push(Object::HASHMAP(hashmap));
+            }
+        }
+    }
+
+    fn continue_block(
+        &mut self,
+        mut statements: std::vec::IntoIter<Statement>,
+        offsets: Vec<usize>,
+        index: usize,
+    ) {
+        // The value of the block is the one of its last statement
+        if let Some(statement) = statements.next() {
+            self.values.pop();
+            self.enter_statement(offsets.get(index));
+            hook!(self.on_statement(&statement));
+            self.tasks.push(Task::Block {
+                statements,
+                offsets,
+                index: index + 1,
+            });
+            self.tasks.push(Task::Statement(statement));
+        }
+    }
+
+    fn check_loop_condition(&mut self, stm: WhileStatement) {
+        let condition = self.pop();
+        if let Err(error) = self.check_condition(&condition) {
+            return self.produce(Object::ERROR(error));
+        }
+        if condition.is_truthy() {
+            self.values.pop();
+            let body = stm.body.clone();
+            self.tasks.push(Task::NextIteration {
+                stm,
+                values: self.values.len(),
+            });
+            self.start_block(body);
+        }
+    }
+
+    fn assign_index(&mut self, name: Rc<str>, indexes: usize) {
+        let value = self.pop();
+        let indexes = self.values.split_off(self.values.len() - indexes);
+        let mut collection = self.pop();
+        match collection.set_index(&indexes, value) {
+            Ok(()) => {
+                self.env.borrow_mut().set(name, collection);
+                self.values.push(NULL);
+            }
+            Err(error) => self.produce(Object::ERROR(error)),
+        }
+    }
+
+    fn start_block(&mut self, block: BlockStatement) {
+        self.values.push(NULL);
+        self.tasks.push(Task::Block {
+            statements: block.statements.into_iter(),
+            offsets: block.offsets,
+            index: 0,
+        });
+    }
+
+    /// Pushes the tasks evaluating `expressions` in order.
+    fn push_expressions(&mut self, expressions: Vec<Expression>) {
+        self.tasks
+            .extend(expressions.into_iter().rev().map(Task::Expression));
+    }
+
+    /// Records the statement the running function is at.
+    fn enter_statement(&mut self, offset: Option<&usize>) {
+        if let (Some(call), Some(offset)) = (self.calls.last_mut(), offset) {
+            call.offset = Some(*offset);
+        }
+    }
+
+    /// Keeps the running calls for the stack trace, unless the error is
+    /// already going through the callers of the function which raised it.
+    fn record_failed_calls(&mut self) {
+        if self.failed_calls.is_empty() {
+            self.failed_calls.clone_from(&self.calls);
+        }
+    }
+
+    fn start_statement(&mut self, statement: Statement) {
+        if let Err(error) = self.check_limits() {
+            return self.produce(Object::error(error));
+        }
         match statement {
-            Statement::Expression(x) => self.eval_expression(x),
+            Statement::Expression(x) => self.start_expression(x),
             Statement::Return(x) => {
-                let value = self.eval_expression(x.return_value);
-                if Self::is_error(&value) {
-                    return value;
-                }
-                Object::RETURN(Box::new(value))
+                self.tasks.push(Task::Return);
+                self.tasks.push(Task::Expression(x.return_value));
             }
             Statement::Let(x) => {
-                let value = self.eval_expression(x.value);
-                if Self::is_error(&value) {
-                    return value;
-                }
-                self.env.borrow_mut().set(x.name.to_string(), value);
-                NULL
+                self.tasks.push(Task::Let(x.name.value));
+                self.tasks.push(Task::Expression(x.value));
             }
             Statement::While(stm) => {
-                let mut result = NULL;
-                while Self::is_truthy(&self.eval_expression(stm.condition.clone())) {
-                    result = self.eval_block_statemet(stm.body.clone());
-                    match result {
-                        Object::RETURN(_) | Object::ERROR(_) => return result,
-                        _ => (),
-                    }
+                self.tasks.push(Task::NextIteration {
+                    stm,
+                    values: self.values.len(),
+                });
+                self.values.push(NULL);
+            }
+            Statement::IndexAssignment(assignment) => {
+                self.tasks.push(Task::IndexAssignment {
+                    name: assignment.name.value.clone(),
+                    indexes: assignment.indexes.len(),
+                });
+                self.tasks.push(Task::Expression(assignment.value));
+                self.push_expressions(assignment.indexes);
+                self.tasks
+                    .push(Task::Expression(Expression::Identifier(assignment.name)));
+            }
+            Statement::LoopStatements(statement) => {
+                if !self.in_loop() {
+                    return self.produce(Object::error(format!("`{statement}` outside of a loop")));
                 }
-                result
+                self.produce(match statement {
+                    LoopStatement::Break => Object::BREAK,
+                    LoopStatement::Continue => Object::CONTINUE,
+                });
             }
-
-            _ => unimplemented!(), // I have decided not to implement the rest of the expressions,
-                                   // I will focus on the compiler
         }
     }
 
+    /// Returns whether the running function is in a loop.
+    fn in_loop(&self) -> bool {
+        self.tasks[self.base.0..]
+            .iter()
+            .rev()
+            .take_while(|task| !matches!(task, Task::EndCall { .. }))
+            .any(|task| matches!(task, Task::NextIteration { .. }))
+    }
+
     #[allow(clippy::match_wildcard_for_single_variants, unreachable_patterns)]
-    fn eval_expression(&mut self, expression: Expression) -> Object {
+    fn start_expression(&mut self, expression: Expression) {
+        if let Err(error) = self.check_limits() {
+            return self.produce(Object::error(error));
+        }
         match expression {
-            Expression::Primitive(x) => Self::eval_primitive_expression(x),
+            Expression::Primitive(x) => self.values.push(Self::eval_primitive_expression(x)),
             Expression::Prefix(operator) => {
-                let right = self.eval_expression(*operator.right);
-                if Self::is_error(&right) {
-                    return right;
-                }
-                Self::eval_prefix_expression(&operator.token, &right)
+                self.tasks.push(Task::Prefix(operator.token));
+                self.tasks.push(Task::Expression(*operator.right));
             }
             Expression::Infix(operator) => {
-                let left = self.eval_expression(*operator.left);
-                if Self::is_error(&left) {
-                    return left;
-                }
-                let right = self.eval_expression(*operator.right);
-                if Self::is_error(&right) {
-                    return right;
-                }
-                Self::eval_infix_expression(&operator.token, left, right)
+                self.tasks.push(Task::Infix(operator.token));
+                self.tasks.push(Task::Expression(*operator.right));
+                self.tasks.push(Task::Expression(*operator.left));
+            }
+            Expression::Conditional(conditional) => {
+                self.tasks.push(Task::Conditional {
+                    consequence: conditional.consequence,
+                    alternative: conditional.alternative.map(|alternative| *alternative),
+                });
+                self.tasks.push(Task::Expression(*conditional.condition));
+            }
+            Expression::Identifier(x) => {
+                let value = self.eval_identifier(&x);
+                self.produce(value);
             }
-            Expression::Conditional(conditional) => self.eval_conditional_expression(conditional),
-            Expression::Identifier(x) => self.eval_identifier(&x),
             Expression::FunctionLiteral(x) => {
                 let parameters = x.parameters;
                 let body = x.body;
-                Object::FUNCTION(Function {
+                self.values.push(Object::FUNCTION(Function {
+                    name: x.name,
                     parameters,
                     body,
                     environment: Rc::clone(&self.env),
-                })
+                }));
             }
             Expression::FunctionCall(x) => {
-                let function = self.eval_expression(*x.function);
-                if Self::is_error(&function) {
-                    return function;
-                }
-                let args = self.eval_expressions(x.arguments);
-                if args.len() == 1 && Self::is_error(&args[0]) {
-                    return args[0].clone();
-                }
-                self.apply_function(function, args)
+                self.tasks.push(Task::Call(x.arguments.len()));
+                self.push_expressions(x.arguments);
+                self.tasks.push(Task::Expression(*x.function));
             }
             Expression::ArrayLiteral(array) => {
-                let elements = self.eval_expressions(array.elements);
-                if elements.len() == 1 && Self::is_error(&elements[0]) {
-                    return elements[0].clone();
-                }
-                Object::ARRAY(elements)
+                self.tasks.push(Task::Array(array.elements.len()));
+                self.push_expressions(array.elements);
             }
             Expression::IndexExpression(index_expression) => {
-                self.eval_index_expression(index_expression)
+                self.tasks.push(Task::Index);
+                self.tasks.push(Task::Expression(*index_expression.index));
+                self.tasks.push(Task::Expression(*index_expression.left));
+            }
+            Expression::HashMapLiteral(hashmap) => {
+                self.tasks.push(Task::HashMap(hashmap.pairs.len()));
+                for (key, value) in hashmap.pairs.into_iter().rev() {
+                    self.tasks.push(Task::Expression(value));
+                    self.tasks.push(Task::HashKey);
+                    self.tasks.push(Task::Expression(key));
+                }
             }
-            Expression::HashMapLiteral(hashmap) => self.eval_hashmap_literal(hashmap),
             _ => unimplemented!(), // I have decided not to implement the rest of the expressions,
                                    // I will focus on the compiler
         }
@@ -162,6 +707,7 @@ impl Evaluator {
                 }
             }
             Primitive::StringLiteral(s) => Object::STRING(s),
+            Primitive::BytesLiteral(s) => Object::BYTES(s.into_bytes()),
         }
     }
 
@@ -169,12 +715,15 @@ impl Evaluator {
         match operator {
             Token::Bang => Self::eval_bang_operator_expression(right),
             Token::Minus => Self::eval_minus_operator_expression(right),
-            _ => Object::ERROR(format!("unknown operator: {operator}{right}")),
+            _ => Object::ERROR(ErrorObject::new(
+                ErrorKind::UnknownOperator,
+                format!("unknown operator: {operator}{}", right.inspect()),
+            )),
         }
     }
 
     fn eval_bang_operator_expression(right: &Object) -> Object {
-        if Self::is_truthy(right) {
+        if right.is_truthy() {
             FALSE
         } else {
             TRUE
@@ -184,7 +733,10 @@ impl Evaluator {
     fn eval_minus_operator_expression(right: &Object) -> Object {
         match right {
             Object::INTEGER(x) => Object::INTEGER(-x),
-            _ => Object::ERROR(format!("unknown operator: -{right}")),
+            _ => Object::ERROR(ErrorObject::new(
+                ErrorKind::UnknownOperator,
+                format!("unknown operator: -{}", right.inspect()),
+            )),
         }
     }
 
@@ -197,13 +749,16 @@ impl Evaluator {
                 Self::eval_boolean_infix_expression(operator, x, y)
             }
             (Object::STRING(x), Object::STRING(y)) => {
-                Self::eval_string_infix_expression(operator, x, &y)
+                Self::eval_string_infix_expression(operator, &x, &y)
             }
-            (left, right) => Object::ERROR(format!(
-                "type mismatch: {} {} {}",
-                left.get_type(),
-                operator,
-                right.get_type()
+            (left, right) => Object::ERROR(ErrorObject::new(
+                ErrorKind::TypeMismatch,
+                format!(
+                    "type mismatch: {} {} {}",
+                    left.get_type(),
+                    operator,
+                    right.get_type()
+                ),
             )),
         }
     }
@@ -220,7 +775,10 @@ impl Evaluator {
             Token::GTE => Object::BOOLEAN(left >= right),
             Token::Equal => Object::BOOLEAN(left == right),
             Token::NotEqual => Object::BOOLEAN(left != right),
-            _ => Object::ERROR(format!("unknown operator: INTEGER {operator} INTEGER")),
+            _ => Object::ERROR(ErrorObject::new(
+                ErrorKind::UnknownOperator,
+                format!("unknown operator: INTEGER {operator} INTEGER"),
+            )),
         }
     }
 
@@ -230,102 +788,161 @@ impl Evaluator {
             Token::NotEqual => Object::BOOLEAN(left != right),
             Token::And => Object::BOOLEAN(left && right),
             Token::Or => Object::BOOLEAN(left || right),
-            _ => Object::ERROR(format!("unknown operator: BOOLEAN {operator} BOOLEAN")),
+            _ => Object::ERROR(ErrorObject::new(
+                ErrorKind::UnknownOperator,
+                format!("unknown operator: BOOLEAN {operator} BOOLEAN"),
+            )),
         }
     }
 
-    fn eval_string_infix_expression(operator: &Token, mut left: String, right: &str) -> Object {
+    fn eval_string_infix_expression(operator: &Token, left: &str, right: &str) -> Object {
         match operator {
-            Token::Plus => {
-                left.push_str(right);
-                Object::STRING(left)
-            }
+            Token::Plus => Object::STRING([left, right].concat().into()),
 
-            _ => Object::ERROR(format!("unknown operator: STRING {operator} STRING")),
+            _ => Object::ERROR(ErrorObject::new(
+                ErrorKind::UnknownOperator,
+                format!("unknown operator: STRING {operator} STRING"),
+            )),
         }
     }
 
-    fn eval_conditional_expression(&mut self, conditional: Conditional) -> Object {
-        let condition = self.eval_expression(*conditional.condition);
-        if Self::is_error(&condition) {
-            return condition;
-        }
-        if Self::is_truthy(&condition) {
-            self.eval_block_statemet(conditional.consequence)
-        } else if let Some(alternative) = conditional.alternative {
-            self.eval_block_statemet(alternative)
-        } else {
-            NULL
+    fn eval_identifier(&self, identifier: &Identifier) -> Object {
+        match self.env.borrow().get(&identifier.value) {
+            Some(x) => x,
+            None => match BuiltinFunction::get_builtin(&identifier.value) {
+                Some(x) => x,
+                None => Object::ERROR(ErrorObject::new(
+                    ErrorKind::UndefinedIdentifier,
+                    format!("identifier not found: {identifier}"),
+                )),
+            },
         }
     }
 
-    fn is_truthy(object: &Object) -> bool {
-        match object {
-            Object::NULL => false,
-            Object::BOOLEAN(x) => *x,
-            _ => true,
+    /// Checks that the condition of an `if` or a `while` is a boolean, in
+    /// strict mode.
+    fn check_condition(&self, condition: &Object) -> Result<(), ErrorObject> {
+        match condition {
+            Object::BOOLEAN(_) => Ok(()),
+            _ if self.strict.boolean_conditions => Err(ErrorObject::new(
+                ErrorKind::TypeMismatch,
+                condition_error(&condition.get_type()),
+            )),
+            _ => Ok(()),
         }
     }
 
-    fn is_error(object: &Object) -> bool {
-        matches!(object, Object::ERROR(_))
+    /// Uses one unit of fuel and checks the deadline and the interrupt,
+    /// before evaluating a statement or an expression.
+    fn check_limits(&mut self) -> Result<(), String> {
+        if let Some(fuel) = &mut self.fuel {
+            if *fuel == 0 {
+                return Err(FUEL_EXHAUSTED.to_string());
+            }
+            *fuel -= 1;
+        }
+        if self
+            .interrupt
+            .as_ref()
+            .is_some_and(Interrupt::is_interrupted)
+        {
+            return Err(INTERRUPTED.to_string());
+        }
+        match &mut self.deadline {
+            Some(deadline) => deadline.check(),
+            None => Ok(()),
+        }
     }
 
-    fn eval_identifier(&self, identifier: &Identifier) -> Object {
-        match self.env.borrow().get(&identifier.to_string()) {
-            Some(x) => x,
-            None => match BuiltinFunction::get_builtin(&identifier.to_string()) {
-                Some(x) => x,
-                None => Object::ERROR(format!("identifier not found: {identifier}")),
-            },
-        }
+    /// Calls `function`, pushing the tasks evaluating its body if it is not a
+    /// builtin.
+    ///
+    /// A call in tail position replaces the running function instead, so that
+    /// it neither counts in the depth nor appears in the stack trace, and
+    /// recursive loops run in constant space.
+    fn apply_function(&mut self, function: Object, args: Vec<Object>) {
+        hook!(self.on_call(&function, &args));
+        let result = match function {
+            Object::FUNCTION(function) => {
+                if let Some(end) = self.tail_call() {
+                    self.tasks.truncate(end + 1);
+                    if let Some(Task::EndCall { tail_calls, .. }) = self.tasks.last_mut() {
+                        *tail_calls += 1;
+                    }
+                    let extended_env = Self::extend_function_env(&function, args);
+                    self.env = Rc::new(RefCell::new(extended_env));
+                    if let Some(call) = self.calls.last_mut() {
+                        *call = Call {
+                            name: function.name,
+                            offset: None,
+                        };
+                    }
+                    return self.start_block(function.body);
+                }
+                if self.depth >= self.max_depth {
+                    Object::error(max_depth_error(self.max_depth, function.name.as_deref()))
+                } else {
+                    let extended_env = Self::extend_function_env(&function, args);
+                    let env = std::mem::replace(&mut self.env, Rc::new(RefCell::new(extended_env)));
+                    self.depth += 1;
+                    self.calls.push(Call {
+                        name: function.name,
+                        offset: None,
+                    });
+                    self.tasks.push(Task::EndCall {
+                        env,
+                        values: self.values.len(),
+                        tail_calls: 0,
+                    });
+                    return self.start_block(function.body);
+                }
+            }
+            Object::BUILTIN(function) => function.call(args, self),
+            _ => Object::ERROR(ErrorObject::new(
+                ErrorKind::TypeMismatch,
+                format!("not a function: {}", function.inspect()),
+            )),
+        };
+        hook!(self.on_return(&result));
+        self.produce(result);
     }
 
-    fn eval_expressions(&mut self, expressions: Vec<Expression>) -> Vec<Object> {
-        let mut result = vec![];
-        for expression in expressions {
-            let evaluated = self.eval_expression(expression);
-            if Self::is_error(&evaluated) {
-                return vec![evaluated];
+    /// Returns the index of the task ending the running function if all
+    /// that is left to evaluate in it is a call, starting now.
+    fn tail_call(&self) -> Option<usize> {
+        for (index, task) in self.tasks.iter().enumerate().skip(self.base.0).rev() {
+            match task {
+                Task::EndCall { .. } => return Some(index),
+                Task::Block { statements, .. } if statements.as_slice().is_empty() => (),
+                Task::Return => (),
+                _ => return None,
             }
-            result.push(evaluated);
         }
-        result
+        None
     }
 
-    fn apply_function(&mut self, function: Object, args: Vec<Object>) -> Object {
-        match function {
-            Object::FUNCTION(function) => {
-                let extended_env = Self::extend_function_env(&function, args);
-                let env = Rc::clone(&self.env);
-                self.env = Rc::new(RefCell::new(extended_env));
-                let evaluated = self.eval_block_statemet(function.body);
-                self.env = env;
-                evaluated
-            }
-            Object::BUILTIN(function) => function.call(args),
-            _ => Object::ERROR(format!("not a function: {function}")),
+    /// Goes back to the caller of the running function, which returned
+    /// `value`, like the functions it replaced with its tail calls.
+    #[cfg_attr(not(feature = "hooks"), allow(unused_variables))]
+    fn end_call(&mut self, env: Rc<RefCell<Environment>>, tail_calls: usize, value: &Object) {
+        self.calls.pop();
+        self.depth -= 1;
+        self.env = env;
+        for _ in 0..=tail_calls {
+            hook!(self.on_return(value));
         }
     }
 
     fn extend_function_env(function: &Function, args: Vec<Object>) -> Environment {
         let mut env = Environment::new_enclosed_environment(Rc::clone(&function.environment));
         for (param, arg) in function.parameters.iter().zip(args) {
-            env.set(param.to_string(), arg);
+            env.set(param.value.clone(), arg);
         }
         env
     }
 
-    fn eval_index_expression(&mut self, index_expression: IndexExpression) -> Object {
-        let left = self.eval_expression(*index_expression.left);
-        if Self::is_error(&left) {
-            return left;
-        }
-        let index = self.eval_expression(*index_expression.index);
-        if Self::is_error(&index) {
-            return index;
-        }
-        match (&left, &index) {
+    fn eval_index_expression(left: &Object, index: &Object) -> Object {
+        match (left, index) {
             (Object::ARRAY(x), Object::INTEGER(y)) => {
                 if *y < 0 || *y >= x.len() as i64 {
                     return NULL;
@@ -333,41 +950,72 @@ impl Evaluator {
                 let index = usize::try_from(*y).unwrap();
                 x[index].clone()
             }
+            (Object::BYTES(x), Object::INTEGER(y)) => match usize::try_from(*y) {
+                Ok(index) if index < x.len() => Object::INTEGER(i64::from(x[index])),
+                _ => NULL,
+            },
             (Object::HASHMAP(x), _) => {
                 if !index.is_hashable() {
-                    return Object::ERROR(format!("unusable as hash key: {}", index.get_type()));
+                    return Object::ERROR(ErrorObject::new(
+                        ErrorKind::TypeMismatch,
+                        format!("unusable as hash key: {}", index.get_type()),
+                    ));
                 }
-                match x.get(&index) {
+                match x.get(index) {
                     Some(x) => x.clone(),
                     None => NULL,
                 }
             }
 
-            _ => Object::ERROR(format!(
-                "index operator not supported: {}[{}]",
-                left.get_type(),
-                index.get_type()
+            _ => Object::ERROR(ErrorObject::new(
+                ErrorKind::TypeMismatch,
+                format!(
+                    "index operator not supported: {}[{}]",
+                    left.get_type(),
+                    index.get_type()
+                ),
             )),
         }
     }
+}
+
+impl BuiltinContext for Evaluator {
+    fn apply(&mut self, function: &Object, args: Vec<Object>) -> Object {
+        self.run(|evaluator| evaluator.apply_function(function.clone(), args))
+    }
 
-    fn eval_hashmap_literal(&mut self, hashmap_pairs: HashMapLiteral) -> Object {
-        let mut hashmap = HashMap::new();
-        for (key, value) in hashmap_pairs.pairs {
-            let key = self.eval_expression(key);
-            if Self::is_error(&key) {
-                return key;
-            }
-            if !key.is_hashable() {
-                return Object::ERROR(format!("unusable as hash key: {}", key.get_type()));
-            }
+    fn output_style(&self) -> &OutputStyle {
+        &self.output_style
+    }
 
-            let value = self.eval_expression(value);
-            if Self::is_error(&value) {
-                return value;
-            }
-            hashmap.insert(key, value);
+    fn input(&mut self) -> Option<&mut dyn BufRead> {
+        self.input
+            .as_mut()
+            .map(|input| input.as_mut() as &mut dyn BufRead)
+    }
+
+    fn random(&mut self) -> &mut Random {
+        &mut self.random
+    }
+
+    fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    fn eval(&mut self, program: Program) -> Object {
+        let env = std::mem::replace(&mut self.env, Rc::clone(&self.globals));
+        // The offsets are in the evaluated string, not in the source of the
+        // program, the statement calling `eval` is kept in the stack trace
+        let program = BlockStatement {
+            statements: program.statements,
+            offsets: Vec::new(),
+            end: None,
+        };
+        let evaluated = self.run(|evaluator| evaluator.start_block(program));
+        self.env = env;
+        match evaluated {
+            Object::RETURN(x) => *x,
+            x => x,
         }
-        Object::HASHMAP(hashmap)
     }
 }
@@ -1,8 +1,15 @@
 #[cfg(test)]
 mod tests {
 
-    use crate::{interpreter::evaluator::Evaluator, lexer::Lexer, object::Object, parser::Parser};
-    use std::collections::HashMap;
+    use crate::{
+        interpreter::evaluator::Evaluator,
+        lexer::Lexer,
+        object::{error::ErrorKind, interrupt::Interrupt, Object, StackFrame},
+        parser::{parse, Parser},
+        source::{Position, Source},
+        strict::StrictConfig,
+    };
+    use std::{collections::HashMap, time::Duration};
 
     #[test]
     fn test_eval_integer_expression() {
@@ -138,6 +145,7 @@ mod tests {
             ("5 + true;", "type mismatch: INTEGER + BOOLEAN"),
             ("5 + true; 5;", "type mismatch: INTEGER + BOOLEAN"),
             ("-true", "unknown operator: -true"),
+            (r#"-"a""#, r#"unknown operator: -"a""#),
             ("true + false;", "unknown operator: BOOLEAN + BOOLEAN"),
             ("5; true + false; 5", "unknown operator: BOOLEAN + BOOLEAN"),
             (
@@ -168,6 +176,104 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_error_kinds() {
+        let tests = [
+            ("5 + true", ErrorKind::TypeMismatch),
+            ("-true", ErrorKind::UnknownOperator),
+            ("foobar", ErrorKind::UndefinedIdentifier),
+            ("[1][[]]", ErrorKind::TypeMismatch),
+            ("let a = [1]; a[3] = 1", ErrorKind::IndexOutOfBounds),
+            ("len(1)", ErrorKind::Custom),
+        ];
+        for (input, kind) in tests {
+            match test_eval(input) {
+                Object::ERROR(error) => assert_eq!(error.kind, kind, "input: {input}"),
+                other => panic!("expected an error for {input}, got {other}"),
+            }
+        }
+
+        let input = "let f = fn(x) {\n  x + true\n};\nf(1)";
+        let mut evaluator = Evaluator::new();
+        let program = parse(input).with_source(Source::new("test.monkey", input));
+        match evaluator.eval(program) {
+            Object::ERROR(error) => {
+                assert_eq!(error.position, Some(Position { line: 2, column: 3 }));
+            }
+            other => panic!("expected an error, got {other}"),
+        }
+    }
+
+    #[test]
+    fn test_strict_mode() {
+        let eval_strict = |input: &str| {
+            let mut evaluator = Evaluator::new();
+            evaluator.set_strict(StrictConfig::strict());
+            evaluator.eval(parse(input).with_source(Source::new("test.monkey", input)))
+        };
+        let errors = [
+            ("let x = 1;\nif (false) { y }", "identifier not found: y", 2),
+            (
+                "let f = fn() { g() };\nlet g = fn() { 1 };",
+                "identifier not found: g",
+                1,
+            ),
+            (
+                "if (1) { 2 }",
+                "condition must be a BOOLEAN, got INTEGER",
+                1,
+            ),
+            (
+                "while ([]) { 1 }",
+                "condition must be a BOOLEAN, got ARRAY",
+                1,
+            ),
+            ("let x = 1;\nlet x = 2;", "`x` is already defined", 2),
+            (
+                "let f = fn(a) { let a = 1; a };",
+                "`a` is already defined",
+                1,
+            ),
+        ];
+        for (input, message, line) in errors {
+            match eval_strict(input) {
+                Object::ERROR(error) => {
+                    assert_eq!(error.message, message, "input: {input}");
+                    assert_eq!(error.position.unwrap().line, line, "input: {input}");
+                }
+                other => panic!("expected an error for {input}, got {other}"),
+            }
+        }
+
+        // Functions can define variables named like the ones around them,
+        // and the `let`s of a loop run many times
+        let input = "let x = 1; let f = fn(n) { let x = n; let f = 2; x + f }; let sum = [0]; while (sum[0] < 9) { let y = f(sum[0]); sum[0] = y; } sum[0]";
+        assert_eq!(eval_strict(input), Object::INTEGER(10));
+        // Updating a variable with a `let` is a redefinition
+        let input = "let i = 0; while (i < 3) { let i = i + 1; } i";
+        assert!(matches!(eval_strict(input), Object::ERROR(_)));
+        let mut evaluator = Evaluator::new();
+        evaluator.set_strict(StrictConfig {
+            no_redefinitions: false,
+            ..StrictConfig::strict()
+        });
+        assert_eq!(evaluator.eval(parse(input)), Object::INTEGER(3));
+
+        // The names defined by the previous programs are known
+        let mut evaluator = Evaluator::new();
+        evaluator.set_strict(StrictConfig::strict());
+        evaluator.eval(parse("let a = 1;"));
+        assert_eq!(evaluator.eval(parse("a + 1")), Object::INTEGER(2));
+        assert!(matches!(
+            evaluator.eval(parse("let a = 2;")),
+            Object::ERROR(_)
+        ));
+
+        // Without the strict mode, nothing changes
+        assert_eq!(test_eval("if (false) { y } else { 1 }"), Object::INTEGER(1));
+        assert_eq!(test_eval("if (1) { 2 }"), Object::INTEGER(2));
+    }
+
     #[test]
     fn test_let_stateemtns() {
         let tests = vec![
@@ -407,6 +513,887 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_merge_function() {
+        let input = r#"merge({"a": 1, "b": 2}, {"b": 3, "c": 4})"#;
+
+        #[allow(clippy::mutable_key_type)]
+        let mut expected = HashMap::new();
+        expected.insert(Object::STRING("a".into()), Object::INTEGER(1));
+        expected.insert(Object::STRING("b".into()), Object::INTEGER(3));
+        expected.insert(Object::STRING("c".into()), Object::INTEGER(4));
+
+        assert_eq!(test_eval(input), Object::HASHMAP(expected));
+
+        test_error_object(
+            test_eval("merge([], {})"),
+            "argument to `merge` not supported, must be HASHMAP, got ARRAY".to_string(),
+        );
+        test_error_object(
+            test_eval("merge({})"),
+            "wrong number of arguments. got=1, want=2".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_keys_values_and_delete_functions() {
+        test_array_object(test_eval("keys({1: 2})"), vec![1]);
+        test_array_object(test_eval("values({1: 2})"), vec![2]);
+        test_array_object(test_eval("keys({})"), vec![]);
+        test_integer_object(test_eval(r#"len(keys({"a": 1, "b": 2, "c": 3}))"#), 3);
+
+        // keys and values are in the same order
+        let input = r#"
+        let h = {"a": 1, "b": 2, "c": 3};
+        let k = keys(h);
+        let v = values(h);
+        (h[k[0]] == v[0]) && (h[k[1]] == v[1]) && (h[k[2]] == v[2])
+        "#;
+        test_boolean_object(test_eval(input), true);
+
+        let input = r#"
+        let h = {"a": 1, "b": 2};
+        let d = delete(h, "a");
+        [len(keys(d)), d["b"], len(keys(h)), len(keys(delete(h, "z")))]
+        "#;
+        test_array_object(test_eval(input), vec![1, 2, 2, 2]);
+        test_null_object(test_eval(r#"delete({"a": 1}, "a")["a"]"#));
+
+        test_error_object(
+            test_eval("keys([])"),
+            "argument to `keys` not supported, must be HASHMAP, got ARRAY".to_string(),
+        );
+        test_error_object(
+            test_eval("delete({}, [])"),
+            "unusable as hash key: ARRAY".to_string(),
+        );
+        test_error_object(
+            test_eval("values({}, 1)"),
+            "wrong number of arguments. got=2, want=1".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_ord_and_chr_functions() {
+        test_integer_object(test_eval(r#"ord("A")"#), 65);
+        test_string_object(test_eval("chr(65)"), "A".to_string());
+        test_string_object(test_eval(r#"chr(ord("y") + 1)"#), "z".to_string());
+
+        test_error_object(
+            test_eval(r#"ord("")"#),
+            "argument to `ord` must be a single character, got \"\"".to_string(),
+        );
+        test_error_object(
+            test_eval("ord(1)"),
+            "argument to `ord` not supported, must be STRING, got INTEGER".to_string(),
+        );
+        test_error_object(
+            test_eval(r#"chr("a")"#),
+            "argument to `chr` not supported, must be INTEGER, got STRING".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_string_functions() {
+        assert_eq!(
+            test_eval(r#"split("a,b,c", ",")"#),
+            Object::ARRAY(vec![
+                Object::STRING("a".into()),
+                Object::STRING("b".into()),
+                Object::STRING("c".into()),
+            ])
+        );
+        assert_eq!(
+            test_eval(r#"split("ab", "")"#),
+            Object::ARRAY(vec![Object::STRING("a".into()), Object::STRING("b".into()),])
+        );
+        test_string_object(
+            test_eval(r#"join(split("a b c", " "), "-")"#),
+            "a-b-c".to_string(),
+        );
+        test_string_object(test_eval(r#"trim("  monkey ")"#), "monkey".to_string());
+        test_string_object(test_eval(r#"upper("Monkey")"#), "MONKEY".to_string());
+        test_string_object(test_eval(r#"lower("Monkey")"#), "monkey".to_string());
+        test_string_object(
+            test_eval(r#"replace("banana", "a", "o")"#),
+            "bonono".to_string(),
+        );
+        test_boolean_object(test_eval(r#"starts_with("monkey", "mon")"#), true);
+        test_boolean_object(test_eval(r#"ends_with("monkey", "mon")"#), false);
+        test_boolean_object(test_eval(r#"contains("monkey", "key")"#), true);
+
+        test_error_object(
+            test_eval(r#"split("a", 1)"#),
+            "argument to `split` not supported, must be STRING, got INTEGER".to_string(),
+        );
+        test_error_object(
+            test_eval(r#"join([1], ",")"#),
+            "argument to `join` not supported, must be ARRAY of STRING, got INTEGER element"
+                .to_string(),
+        );
+        test_error_object(
+            test_eval(r#"join("a", ",")"#),
+            "argument to `join` not supported, must be ARRAY, got STRING".to_string(),
+        );
+        test_error_object(
+            test_eval("upper(1)"),
+            "argument to `upper` not supported, must be STRING, got INTEGER".to_string(),
+        );
+        test_error_object(
+            test_eval(r#"replace("a", "b")"#),
+            "wrong number of arguments. got=2, want=3".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_math_functions() {
+        test_integer_object(test_eval("abs(-5)"), 5);
+        test_integer_object(test_eval("abs(5)"), 5);
+        test_integer_object(test_eval("min(3, -2)"), -2);
+        test_integer_object(test_eval("max(3, -2)"), 3);
+        test_integer_object(test_eval("pow(2, 10)"), 1024);
+        test_integer_object(test_eval("pow(5, 0)"), 1);
+        test_integer_object(test_eval("sqrt(17)"), 4);
+        test_integer_object(test_eval("floor(7)"), 7);
+        test_integer_object(test_eval("ceil(-7)"), -7);
+
+        test_error_object(
+            test_eval("pow(2, -1)"),
+            "exponent of `pow` must be a non negative integer, got -1".to_string(),
+        );
+        test_error_object(
+            test_eval("pow(2, 64)"),
+            "integer overflow in `pow`".to_string(),
+        );
+        test_error_object(
+            test_eval("sqrt(-4)"),
+            "argument to `sqrt` must be non negative, got -4".to_string(),
+        );
+        test_error_object(
+            test_eval(r#"max(1, "a")"#),
+            "argument to `max` not supported, must be INTEGER, got STRING".to_string(),
+        );
+        test_error_object(
+            test_eval("min(1)"),
+            "wrong number of arguments. got=1, want=2".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_type_function() {
+        let tests = vec![
+            ("type(1)", "INTEGER"),
+            ("type(true)", "BOOLEAN"),
+            (r#"type("a")"#, "STRING"),
+            ("type([])", "ARRAY"),
+            ("type({})", "HASHMAP"),
+            ("type(fn(x) { x })", "FUNCTION"),
+            ("type(len)", "BUILTIN"),
+            ("type(if (false) { 1 })", "NULL"),
+            ("type(type(1))", "STRING"),
+        ];
+
+        for (input, expected) in tests {
+            test_string_object(test_eval(input), expected.to_string());
+        }
+
+        test_error_object(
+            test_eval("type(1, 2)"),
+            "wrong number of arguments. got=2, want=1".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_int_and_str_functions() {
+        test_integer_object(test_eval(r#"int("42")"#), 42);
+        test_integer_object(test_eval(r#"int("-7")"#), -7);
+        test_integer_object(test_eval("int(true)"), 1);
+        test_integer_object(test_eval("int(false)"), 0);
+        test_integer_object(test_eval("int(3)"), 3);
+        test_string_object(test_eval("str(42)"), "42".to_string());
+        test_string_object(test_eval("str(true)"), "true".to_string());
+        test_string_object(test_eval(r#"str("a")"#), "a".to_string());
+        test_string_object(test_eval(r#"str([1, "a"])"#), r#"[1, "a"]"#.to_string());
+        test_integer_object(test_eval("int(str(12)) + 1"), 13);
+
+        test_error_object(
+            test_eval(r#"int("4a")"#),
+            "could not convert \"4a\" to INTEGER".to_string(),
+        );
+        test_error_object(
+            test_eval("int([])"),
+            "argument to `int` not supported, must be INTEGER, BOOLEAN or STRING, got ARRAY"
+                .to_string(),
+        );
+    }
+
+    #[test]
+    fn test_map_filter_and_reduce_functions() {
+        test_array_object(test_eval("map([1, 2, 3], fn(x) { x * 2 })"), vec![2, 4, 6]);
+        test_array_object(
+            test_eval("let factor = 3; map([1, 2], fn(x) { return x * factor; })"),
+            vec![3, 6],
+        );
+        test_array_object(
+            test_eval("filter([1, 2, 3, 4], fn(x) { x > 2 })"),
+            vec![3, 4],
+        );
+        test_integer_object(
+            test_eval("reduce([1, 2, 3, 4], 0, fn(acc, x) { acc + x })"),
+            10,
+        );
+        test_array_object(test_eval(r#"map(["a", "bc"], len)"#), vec![1, 2]);
+
+        test_error_object(
+            test_eval("map([1], fn(x) { x + true })"),
+            "type mismatch: INTEGER + BOOLEAN".to_string(),
+        );
+        test_error_object(
+            test_eval("filter(1, fn(x) { x })"),
+            "argument to `filter` not supported, must be ARRAY, got INTEGER".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_sort_function() {
+        test_array_object(test_eval("sort([3, 1, 2, 1])"), vec![1, 1, 2, 3]);
+        test_array_object(test_eval("sort([])"), vec![]);
+        test_array_object(
+            test_eval("sort([3, 1, 2], fn(a, b) { a > b })"),
+            vec![3, 2, 1],
+        );
+        assert_eq!(
+            test_eval(r#"sort(["b", "c", "a"])"#),
+            Object::ARRAY(vec![
+                Object::STRING("a".into()),
+                Object::STRING("b".into()),
+                Object::STRING("c".into()),
+            ])
+        );
+
+        // Elements comparing equal keep their order
+        let input = r"
+        let pairs = sort([[1, 1], [0, 2], [1, 3], [0, 4]], fn(a, b) { a[0] < b[0] });
+        map(pairs, fn(pair) { pair[1] })
+        ";
+        test_array_object(test_eval(input), vec![2, 4, 1, 3]);
+
+        // The original array is not modified
+        test_array_object(test_eval("let a = [2, 1]; sort(a); a"), vec![2, 1]);
+
+        test_error_object(
+            test_eval(r#"sort([1, "a"])"#),
+            "`sort` cannot compare STRING and INTEGER, use a comparator".to_string(),
+        );
+        test_error_object(
+            test_eval("sort([2, 1], fn(a, b) { a + true })"),
+            "type mismatch: INTEGER + BOOLEAN".to_string(),
+        );
+        test_error_object(
+            test_eval("sort(1)"),
+            "argument to `sort` not supported, must be ARRAY, got INTEGER".to_string(),
+        );
+        test_error_object(
+            test_eval("sort()"),
+            "wrong number of arguments. got=0, want=1 to 2".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_range_function() {
+        test_array_object(test_eval("range(5)"), vec![0, 1, 2, 3, 4]);
+        test_array_object(test_eval("range(3, 6)"), vec![3, 4, 5]);
+        test_array_object(test_eval("range(0, 10, 3)"), vec![0, 3, 6, 9]);
+        test_array_object(test_eval("range(3, 0, -1)"), vec![3, 2, 1]);
+        test_array_object(test_eval("range(5, 1)"), vec![]);
+        test_array_object(test_eval("range(-2)"), vec![]);
+        test_array_object(test_eval("range(0, 10, 20)"), vec![0]);
+        test_array_object(
+            test_eval("range(9223372036854775805, 9223372036854775807)"),
+            vec![9_223_372_036_854_775_805, 9_223_372_036_854_775_806],
+        );
+
+        test_error_object(
+            test_eval("range(0, 5, 0)"),
+            "step of `range` must not be zero".to_string(),
+        );
+        test_error_object(
+            test_eval("range(1000000000000)"),
+            "`range` of 1000000000000 elements is too large, the limit is 10000000".to_string(),
+        );
+        test_error_object(
+            test_eval("range(-9223372036854775807 - 1, 9223372036854775807, 2)"),
+            "`range` of 9223372036854775808 elements is too large, the limit is 10000000"
+                .to_string(),
+        );
+        test_error_object(
+            test_eval(r#"range("5")"#),
+            "argument to `range` not supported, must be INTEGER, got STRING".to_string(),
+        );
+        test_error_object(
+            test_eval("range()"),
+            "wrong number of arguments. got=0, want=1 to 3".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_argv_and_env() {
+        let mut evaluator = Evaluator::new();
+        evaluator.set_args(vec!["foo".to_string(), "--bar".to_string()]);
+        assert_eq!(
+            evaluator.eval(Parser::new(Lexer::new("argv()")).parse_program()),
+            Object::ARRAY(vec![
+                Object::STRING("foo".into()),
+                Object::STRING("--bar".into()),
+            ])
+        );
+        assert_eq!(test_eval("argv()"), Object::ARRAY(vec![]));
+
+        let path = std::env::var("PATH").unwrap();
+        test_string_object(test_eval(r#"env("PATH")"#), path);
+        test_null_object(test_eval(r#"env("CHIMPANZEE_UNSET_VARIABLE")"#));
+        test_error_object(
+            test_eval("env(1)"),
+            "argument to `env` not supported, must be STRING, got INTEGER".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_input_functions() {
+        let input = r#"
+        let name = input();
+        let rest = read_all();
+        [name, rest, input()]
+        "#;
+        let mut evaluator = Evaluator::new();
+        evaluator.set_input(Box::new("monkey\r\nline 2\nline 3".as_bytes()));
+        assert_eq!(
+            evaluator.eval(Parser::new(Lexer::new(input)).parse_program()),
+            Object::ARRAY(vec![
+                Object::STRING("monkey".into()),
+                Object::STRING("line 2\nline 3".into()),
+                Object::NULL,
+            ])
+        );
+
+        test_error_object(
+            test_eval("input(1)"),
+            "argument to `input` not supported, must be STRING, got INTEGER".to_string(),
+        );
+        test_error_object(
+            test_eval("read_all(1)"),
+            "wrong number of arguments. got=1, want=0".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_time_functions() {
+        // 2023-11-14, long before these tests were written
+        test_boolean_object(test_eval("time() > 1700000000"), true);
+        test_boolean_object(
+            test_eval("let start = clock(); let end = clock(); end >= start"),
+            true,
+        );
+        test_error_object(
+            test_eval("clock(1)"),
+            "wrong number of arguments. got=1, want=0".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_random_functions() {
+        let input = r"
+        let roll = fn(_) { rand_int(1, 6) };
+        seed(42);
+        let first = map(range(20), roll);
+        seed(42);
+        [first, map(range(20), roll), rand() >= 0]
+        ";
+        match test_eval(input) {
+            Object::ARRAY(results) => {
+                assert_eq!(results[0], results[1]);
+                match &results[0] {
+                    Object::ARRAY(rolls) => assert!(rolls
+                        .iter()
+                        .all(|roll| matches!(roll, Object::INTEGER(1..=6)))),
+                    _ => panic!("The rolls are not an array"),
+                }
+                test_boolean_object(results[2].clone(), true);
+            }
+            _ => panic!("The object is not an array"),
+        }
+
+        test_integer_object(test_eval("rand_int(3, 3)"), 3);
+        test_error_object(
+            test_eval("rand_int(3, 1)"),
+            "range of `rand_int` is empty, got 3 to 1".to_string(),
+        );
+        test_error_object(
+            test_eval("seed(true)"),
+            "argument to `seed` not supported, must be INTEGER, got BOOLEAN".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_exit_function() {
+        let tests = vec![
+            ("exit(); 1", Object::EXIT(0)),
+            ("exit(3); 1", Object::EXIT(3)),
+            ("let f = fn() { exit(2); 1 }; f() + 1", Object::EXIT(2)),
+            (
+                "map([1, 2, 3], fn(x) { if (x == 2) { exit(x) } x })",
+                Object::EXIT(2),
+            ),
+            (
+                "let i = 0; while (i < 10) { let i = i + 1; exit(5); }",
+                Object::EXIT(5),
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), expected, "{input}");
+        }
+
+        test_error_object(
+            test_eval(r#"exit("1")"#),
+            "argument to `exit` not supported, must be INTEGER, got STRING".to_string(),
+        );
+        test_error_object(
+            test_eval("exit(300)"),
+            "exit code 300 is not supported, it must be between 0 and 255".to_string(),
+        );
+        test_error_object(
+            test_eval("exit(-1)"),
+            "exit code -1 is not supported, it must be between 0 and 255".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_assert_function() {
+        test_null_object(test_eval("assert(1 < 2)"));
+        test_integer_object(test_eval(r#"assert(true, "never shown"); 5"#), 5);
+        test_error_object(
+            test_eval(r#"assert(1 > 2, "1 is not greater than 2"); 5"#),
+            "assertion failed: 1 is not greater than 2".to_string(),
+        );
+        test_error_object(
+            test_eval("let f = fn(x) { assert(x); x }; f(false)"),
+            "assertion failed".to_string(),
+        );
+        test_error_object(
+            test_eval("assert(false, 1)"),
+            "argument to `assert` not supported, must be STRING, got INTEGER".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_format_function() {
+        test_string_object(
+            test_eval(r#"let x = 1; let y = "two"; format("x = {}, y = {:>5}", x, y)"#),
+            "x = 1, y =   two".to_string(),
+        );
+        test_string_object(
+            test_eval(r#"format("{0}{0}{1:03}", "ab", 7)"#),
+            "abab007".to_string(),
+        );
+        test_error_object(
+            test_eval(r#"format("{} {}", 1)"#),
+            "format argument 1 is missing, got 1 arguments".to_string(),
+        );
+        test_error_object(
+            test_eval("format(1)"),
+            "argument to `format` not supported, must be STRING, got INTEGER".to_string(),
+        );
+        test_error_object(
+            test_eval("format()"),
+            "wrong number of arguments. got=0, want=1 or more".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_contains_and_index_of_functions() {
+        let tests = vec![
+            (r#"contains("monkey", "mon")"#, true),
+            (r#"contains("monkey", "")"#, true),
+            ("contains([1, 2, 3], 2)", true),
+            (r#"contains([1, 2, 3], "2")"#, false),
+            ("contains([[1], [2]], [2])", true),
+            (r#"contains({"a": 1}, "a")"#, true),
+            (r#"contains({"a": 1}, 1)"#, false),
+        ];
+        for (input, expected) in tests {
+            test_boolean_object(test_eval(input), expected);
+        }
+
+        let tests = vec![
+            (r#"index_of("monkey", "key")"#, 3),
+            (r#"index_of("été", "t")"#, 1),
+            (r#"index_of("monkey", "z")"#, -1),
+            (r#"index_of([1, "a", "a"], "a")"#, 1),
+            ("index_of([1, 2], 3)", -1),
+        ];
+        for (input, expected) in tests {
+            test_integer_object(test_eval(input), expected);
+        }
+
+        test_error_object(
+            test_eval(r#"contains("monkey", 1)"#),
+            "argument to `contains` not supported, must be STRING, got INTEGER".to_string(),
+        );
+        test_error_object(
+            test_eval("contains(1, 1)"),
+            "argument to `contains` not supported, must be STRING, ARRAY or HASHMAP, got INTEGER"
+                .to_string(),
+        );
+        test_error_object(
+            test_eval(r#"index_of({"a": 1}, "a")"#),
+            "argument to `index_of` not supported, must be STRING or ARRAY, got HASHMAP"
+                .to_string(),
+        );
+    }
+
+    #[test]
+    fn test_reverse_function() {
+        test_array_object(test_eval("reverse([1, 2, 3])"), vec![3, 2, 1]);
+        test_array_object(test_eval("reverse([])"), vec![]);
+        test_array_object(test_eval("let a = [1, 2]; reverse(a); a"), vec![1, 2]);
+        test_string_object(test_eval(r#"reverse("abc")"#), "cba".to_string());
+        test_string_object(test_eval(r#"reverse("été")"#), "été".to_string());
+        test_error_object(
+            test_eval("reverse(1)"),
+            "argument to `reverse` not supported, must be STRING or ARRAY, got INTEGER".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_slice_function() {
+        let tests = vec![
+            ("slice([1, 2, 3, 4], 1, 3)", vec![2, 3]),
+            ("slice([1, 2, 3, 4], 2)", vec![3, 4]),
+            // Negative indexes count from the end
+            ("slice([1, 2, 3, 4], -2)", vec![3, 4]),
+            ("slice([1, 2, 3, 4], 0, -1)", vec![1, 2, 3]),
+            // Indexes out of bounds are clamped
+            ("slice([1, 2, 3, 4], -10, 10)", vec![1, 2, 3, 4]),
+            ("slice([1, 2, 3, 4], 10)", vec![]),
+            // The slice is empty when the end is before the start
+            ("slice([1, 2, 3, 4], 3, 1)", vec![]),
+        ];
+        for (input, expected) in tests {
+            test_array_object(test_eval(input), expected);
+        }
+
+        test_string_object(test_eval(r#"slice("monkey", 1, 4)"#), "onk".to_string());
+        test_string_object(test_eval(r#"slice("été", -2)"#), "té".to_string());
+
+        test_error_object(
+            test_eval(r#"slice([1], "a")"#),
+            "argument to `slice` not supported, must be INTEGER, got STRING".to_string(),
+        );
+        test_error_object(
+            test_eval("slice(1, 0)"),
+            "argument to `slice` not supported, must be STRING or ARRAY, got INTEGER".to_string(),
+        );
+        test_error_object(
+            test_eval("slice([1])"),
+            "wrong number of arguments. got=1, want=2 to 3".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_output_functions() {
+        test_null_object(test_eval(r#"puts("a", 1)"#));
+        test_null_object(test_eval(r#"print("")"#));
+    }
+
+    #[test]
+    fn test_eval_function() {
+        let tests = vec![
+            (r#"eval("1 + 2")"#, 3),
+            (r#"let a = 5; eval("a * 2")"#, 10),
+            (r#"eval("let b = 4;"); b"#, 4),
+            (r#"eval("return 1; 2")"#, 1),
+            (r#"let f = eval("fn(x) { x + 1 }"); f(1)"#, 2),
+            // Programs run in the global environment, not in the caller's one
+            (r#"let x = 1; let f = fn(x) { eval("x") }; f(2)"#, 1),
+        ];
+        for (input, expected) in tests {
+            test_integer_object(test_eval(input), expected);
+        }
+
+        test_null_object(test_eval(r#"eval("let c = 1;")"#));
+        test_error_object(
+            test_eval(r#"eval("1 +")"#),
+            "could not parse the input of `eval`: There is no prefix parser for the token Eof"
+                .to_string(),
+        );
+        test_error_object(
+            test_eval(r#"eval("d")"#),
+            "identifier not found: d".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_bytes() {
+        let tests = vec![
+            (r#"b"abc""#, Object::BYTES(b"abc".to_vec())),
+            (r#"b"été""#, Object::BYTES("été".as_bytes().to_vec())),
+            (r#"b"abc"[1]"#, Object::INTEGER(98)),
+            (r#"b"abc"[3]"#, Object::NULL),
+            (r#"b"abc"[-1]"#, Object::NULL),
+            (r#"len(b"été")"#, Object::INTEGER(5)),
+            (r#"type(b"")"#, Object::STRING("BYTES".into())),
+            (r#"bytes("hi")"#, Object::BYTES(b"hi".to_vec())),
+            ("bytes([0, 255])", Object::BYTES(vec![0, 255])),
+            (r#"decode(b"hi")"#, Object::STRING("hi".into())),
+            (r#"{b"key": 1}[b"key"]"#, Object::INTEGER(1)),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), expected, "{input}");
+        }
+
+        test_error_object(
+            test_eval("bytes([256])"),
+            "argument to `bytes` not supported, elements must be integers from 0 to 255"
+                .to_string(),
+        );
+        test_error_object(
+            test_eval("decode(bytes([255]))"),
+            "could not decode the bytes: invalid utf-8 sequence of 1 bytes from index 0"
+                .to_string(),
+        );
+        test_error_object(
+            test_eval("bytes(1)"),
+            "argument to `bytes` not supported, must be STRING, ARRAY or BYTES, got INTEGER"
+                .to_string(),
+        );
+    }
+
+    #[test]
+    fn test_date_builtins() {
+        let tests = vec![
+            (
+                r#"format_time(1700000000, "%Y-%m-%d %H:%M:%S")"#,
+                Object::STRING("2023-11-14 22:13:20".into()),
+            ),
+            (
+                r#"parse_time("2023-11-14 22:13:20", "%Y-%m-%d %H:%M:%S")"#,
+                Object::INTEGER(1700000000),
+            ),
+            (r#"date(1700000000)["weekday"]"#, Object::INTEGER(2)),
+            ("timestamp(2023, 11, 14)", Object::INTEGER(1699920000)),
+            (
+                "timestamp(2023, 11, 14, 22, 13, 20)",
+                Object::INTEGER(1700000000),
+            ),
+            (
+                r#"let d = date(1700000000); format_time(timestamp(d["year"], d["month"] + 3, d["day"]), "%Y-%m-%d")"#,
+                Object::STRING("2024-02-14".into()),
+            ),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), expected, "{input}");
+        }
+        test_integer_object(test_eval(r#"date(0)["year"]"#), 1970);
+        assert!(matches!(test_eval("now()"), Object::INTEGER(i) if i > 1700000000));
+
+        test_error_object(
+            test_eval(r#"format_time("now", "%Y")"#),
+            "arguments to `format_time` not supported, must be INTEGER and STRING, got STRING and STRING"
+                .to_string(),
+        );
+        test_error_object(
+            test_eval(r#"parse_time("noon", "%H")"#),
+            "could not parse `noon` as a time with the format `%H`".to_string(),
+        );
+        test_error_object(
+            test_eval("timestamp(2023, 11)"),
+            "wrong number of arguments. got=2, want=3 to 6".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let input = "let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } };";
+        let run = |call: &str| {
+            let mut evaluator = Evaluator::new();
+            evaluator.set_max_depth(10);
+            evaluator.eval(Parser::new(Lexer::new(&format!("{input} {call}"))).parse_program())
+        };
+
+        test_integer_object(run("f(9)"), 9);
+        test_error_object(
+            run("f(10)"),
+            "Maximum call depth of 10 exceeded when calling `f`".to_string(),
+        );
+        test_error_object(
+            run("let g = fn() { 1 + fn() { g() }() }; g()"),
+            "Maximum call depth of 10 exceeded when calling an anonymous function".to_string(),
+        );
+        // The depth goes back down once the calls return
+        test_integer_object(run("f(9) + f(9)"), 18);
+    }
+
+    #[test]
+    fn test_tail_calls() {
+        let tests = vec![
+            (
+                "let count = fn(n, acc) { if (n == 0) { acc } else { count(n - 1, acc + 1) } }; count(100000, 0)",
+                100_000,
+            ),
+            (
+                "let f = fn(n) { if (n == 0) { return 7; } return f(n - 1); }; f(5000)",
+                7,
+            ),
+            (
+                "let even = fn(n) { if (n == 0) { 1 } else { odd(n - 1) } }; let odd = fn(n) { if (n == 0) { 0 } else { even(n - 1) } }; even(5001)",
+                0,
+            ),
+            // The caller of the first call still gets its value
+            (
+                "let f = fn(n) { if (n == 0) { 1 } else { f(n - 1) } }; let g = fn() { let x = f(3000); x + 1 }; g()",
+                2,
+            ),
+        ];
+        for (input, expected) in tests {
+            test_integer_object(test_eval(input), expected);
+        }
+
+        // The functions replaced by a tail call are not in the stack trace
+        let mut evaluator = Evaluator::new();
+        let input = "let f = fn(n) { if (n == 0) { n + true } else { f(n - 1) } };\nf(10)";
+        let program = parse(input).with_source(Source::new("test.monkey", input));
+        test_error_object(
+            evaluator.eval(program),
+            "type mismatch: INTEGER + BOOLEAN".to_string(),
+        );
+        let functions: Vec<String> = evaluator
+            .stack_trace()
+            .into_iter()
+            .map(|frame| frame.function)
+            .collect();
+        assert_eq!(functions, vec!["main", "f"]);
+    }
+
+    #[test]
+    fn test_deep_evaluation() {
+        // Neither the calls nor the nested expressions use the native stack
+        let mut evaluator = Evaluator::new();
+        evaluator.set_max_depth(200_000);
+        let input = "let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } }; f(100000)";
+        test_integer_object(
+            evaluator.eval(Parser::new(Lexer::new(input)).parse_program()),
+            100_000,
+        );
+
+        let input = format!("{}1{}", "[".repeat(500), "]".repeat(500));
+        let mut nested = test_eval(&input);
+        for _ in 0..500 {
+            nested = match nested {
+                Object::ARRAY(mut elements) if elements.len() == 1 => elements.remove(0),
+                x => panic!("The object is not a nested array, it is {x:#?}"),
+            };
+        }
+        test_integer_object(nested, 1);
+
+        // A return in a nested expression returns from the function
+        test_integer_object(
+            test_eval("let f = fn() { let x = if (true) { return 1; }; 2 }; f()"),
+            1,
+        );
+        test_error_object(
+            test_eval("let f = fn(x) { [1, x + true, 3] }; [f(1), 2]"),
+            "type mismatch: INTEGER + BOOLEAN".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_fuel() {
+        let run = |input: &str, fuel: u64| {
+            let mut evaluator = Evaluator::new();
+            evaluator.set_fuel(fuel);
+            let result = evaluator.eval(Parser::new(Lexer::new(input)).parse_program());
+            (result, evaluator.fuel())
+        };
+
+        // The statement and the three expressions
+        assert_eq!(run("1 + 2", 4), (Object::INTEGER(3), Some(0)));
+        assert_eq!(run("1 + 2", 10), (Object::INTEGER(3), Some(6)));
+        test_error_object(run("1 + 2", 3).0, "Fuel exhausted".to_string());
+        test_error_object(
+            run("while (true) { 1 }", 1000).0,
+            "Fuel exhausted".to_string(),
+        );
+        test_error_object(
+            run(r#"let f = fn() { eval("while (true) { 1 }") }; f()"#, 1000).0,
+            "Fuel exhausted".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_timeout() {
+        let mut evaluator = Evaluator::new();
+        evaluator.set_timeout(Duration::from_millis(10));
+        test_error_object(
+            evaluator.eval(Parser::new(Lexer::new("while (true) { 1 }")).parse_program()),
+            "Timeout: the program ran for more than 10ms".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let mut evaluator = Evaluator::new();
+        let interrupt = Interrupt::default();
+        evaluator.set_interrupt(interrupt.clone());
+        let handle = interrupt.clone();
+        let thread = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(10));
+            handle.interrupt();
+        });
+        test_error_object(
+            evaluator.eval(parse("while (true) { 1 }")),
+            "Interrupted".to_string(),
+        );
+        thread.join().unwrap();
+
+        interrupt.clear();
+        test_integer_object(evaluator.eval(parse("1 + 1")), 2);
+    }
+
+    #[test]
+    fn test_stack_trace() {
+        let input = "let check = fn(x) {\n  x + true\n};\nlet apply = fn(f) { let y = f(1); y };\napply(fn(x) { let y = check(x); y });";
+        let mut evaluator = Evaluator::new();
+        let program = parse(input).with_source(Source::new("test.monkey", input));
+        test_error_object(
+            evaluator.eval(program),
+            "type mismatch: INTEGER + BOOLEAN".to_string(),
+        );
+
+        let frame = |function: &str, line, column| StackFrame {
+            function: function.to_string(),
+            position: Some(Position { line, column }),
+        };
+        assert_eq!(
+            evaluator.stack_trace(),
+            vec![
+                frame("main", 5, 1),
+                frame("apply", 4, 21),
+                frame("<anonymous>", 5, 15),
+                frame("check", 2, 3),
+            ]
+        );
+
+        // The trace is the one of the last program
+        evaluator.eval(parse("1"));
+        assert_eq!(evaluator.stack_trace(), vec![]);
+    }
+
+    #[test]
+    fn test_return_value_is_unwrapped_at_call_site() {
+        test_integer_object(test_eval("let f = fn(x) { return x; }; f(1) + 1"), 2);
+    }
+
     #[test]
     fn test_array_functions_together() {
         let input = r"
@@ -444,10 +1431,11 @@ mod tests {
         }
         "#;
 
+        #[allow(clippy::mutable_key_type)]
         let mut expected = HashMap::new();
-        expected.insert(Object::STRING("one".to_string()), Object::INTEGER(1));
-        expected.insert(Object::STRING("two".to_string()), Object::INTEGER(2));
-        expected.insert(Object::STRING("three".to_string()), Object::INTEGER(3));
+        expected.insert(Object::STRING("one".into()), Object::INTEGER(1));
+        expected.insert(Object::STRING("two".into()), Object::INTEGER(2));
+        expected.insert(Object::STRING("three".into()), Object::INTEGER(3));
         expected.insert(Object::INTEGER(4), Object::INTEGER(4));
         expected.insert(Object::BOOLEAN(true), Object::INTEGER(5));
         expected.insert(Object::BOOLEAN(false), Object::INTEGER(6));
@@ -498,6 +1486,29 @@ mod tests {
                 Some(100),
             ),
             ("while (false) { 1 }", None),
+            ("let a = 1; while (a < 100) { let a = a + 1; } a", Some(100)),
+            ("let a = 1; while (a < 0) { let a = 100; } a", Some(1)),
+            ("let a = 1; while (false) { let a = 100; } a", Some(1)),
+            // The condition is truthy like the one of a conditional
+            ("let a = 3; let b = 0; while (a) { let b = b + 1; let a = if (a > 1) { a - 1 } }; b", Some(3)),
+            ("let a = 0; while (a) { let a = if (false) { 1 }; } 5", Some(5)),
+            (
+                "let a = 0; while (a < 10000) { let a = a + 1; a * 2; } a",
+                Some(10000),
+            ),
+            (
+                "let a = 0; let c = 0; while (a < 10) { let b = 0; while (b < 10) { let b = b + 1; let c = c + 1; } let a = a + 1; } c",
+                Some(100),
+            ),
+            ("let f = fn() { let i = 0; while (i < 5) { let i = i + 1; }; i }; f()", Some(5)),
+            (
+                "let f = fn() { let i = 0; while (true) { if (i == 3) { return i * 2; } let i = i + 1; } }; f()",
+                Some(6),
+            ),
+            (
+                "let a = [0, 0, 0]; let i = 0; while (i < 3) { a[i] = i * i; let i = i + 1; } a[2]",
+                Some(4),
+            ),
         ];
 
         for (input, expected) in tests {
@@ -509,6 +1520,129 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_break_and_continue() {
+        let tests = vec![
+            (
+                "let a = 0; while (a < 10) { if (a == 5) { break; } let a = a + 1; }; a",
+                5,
+            ),
+            (
+                "let a = 0; let c = 0; while (a < 10) { if (a == 5) { break; } let b = 0; while (b < 10) { if (b == 5) { break; } let b = b + 1; let c = c + 1; } let a = a + 1; }; c",
+                25,
+            ),
+            (
+                "let f = fn(a) { let c = 0; while (a < 10) { if (a == 5) { break; } let b = 0; while (b < 10) { if (b == 5) { break; } let b = b + 1; let c = c + 1; } let a = a + 1; } c }; f(0)",
+                25,
+            ),
+            (
+                "let a = 0; let c = 0; while (a < 10) { let a = a + 1; if (a == 5) { let c = c + 2; continue; } let c = c + 1; }; c",
+                11,
+            ),
+            (
+                "let a = 0; let c = 0; while (a < 10) { let f = fn() { let c = 0; let b = 0; while (b < 10) { let b = b + 1; if (b == 5) { let c = c + 3; continue; } let c = c + 1; } c }; let a = a + 1; let c = c + f(); }; c",
+                120,
+            ),
+            (
+                "let a = 0; let c = 0; while (a < 10) { let a = a + 1; if (a == 5) { let c = c + 3; continue; } if (a == 7) { break; } let c = c + 1; } c",
+                8,
+            ),
+        ];
+
+        for (input, expected) in tests {
+            println!("{input}");
+            test_integer_object(test_eval(input), expected);
+        }
+
+        test_null_object(test_eval("while (true) { 1; break; }"));
+        test_error_object(test_eval("break;"), "`break` outside of a loop".to_string());
+        // Loops do not continue the functions called in their body
+        test_error_object(
+            test_eval("while (true) { fn() { continue; }() }"),
+            "`continue` outside of a loop".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_while_statement_errors() {
+        let tests = vec![
+            ("while (x) { 1 }", "identifier not found: x"),
+            (
+                "let a = 0; while (a < 3) { let a = a + 1; if (a == 2) { a + true } } a",
+                "type mismatch: INTEGER + BOOLEAN",
+            ),
+            (
+                "let a = 0; while (a < true) { 1 }",
+                "type mismatch: INTEGER < BOOLEAN",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            test_error_object(test_eval(input), expected.to_string());
+        }
+    }
+
+    #[test]
+    fn test_index_assignment() {
+        let tests = vec![
+            ("let a = [1, 2, 3]; a[0] = 4; a", "[4, 2, 3]"),
+            // The other copies of the collection do not change
+            (
+                "let a = [1, 2]; let b = a; a[0] = 3; [a, b]",
+                "[[3, 2], [1, 2]]",
+            ),
+            (
+                r#"let h = {"a": 1}; h["b"] = 2; h["a"] = 3; [h["a"], h["b"]]"#,
+                "[3, 2]",
+            ),
+            (
+                "let grid = [[0, 0], [0, 0]]; grid[1][0] = 5; grid",
+                "[[0, 0], [5, 0]]",
+            ),
+            (
+                "let a = [0, 0, 0]; let i = 0; while (i < 3) { a[i] = i * i; let i = i + 1; } a",
+                "[0, 1, 4]",
+            ),
+            // Like a `let`, it defines a local variable in a function
+            (
+                "let a = [1]; let f = fn() { a[0] = 2; a }; [f(), a]",
+                "[[2], [1]]",
+            ),
+            (
+                "let f = fn(a) { a[0] = a[0] + 1; a }; let a = [1]; [f(a), a]",
+                "[[2], [1]]",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            println!("{input}");
+            assert_eq!(test_eval(input).to_string(), expected);
+        }
+
+        for (input, kind, message) in [
+            (
+                "let a = [1]; a[1] = 2",
+                ErrorKind::IndexOutOfBounds,
+                "index 1 out of range for an array of length 1",
+            ),
+            (
+                "let a = {}; a[[]] = 2",
+                ErrorKind::TypeMismatch,
+                "unusable as hash key: ARRAY",
+            ),
+            (
+                "let a = 1; a[0] = 2",
+                ErrorKind::TypeMismatch,
+                "index assignment not supported: INTEGER[INTEGER]",
+            ),
+        ] {
+            let Object::ERROR(error) = test_eval(input) else {
+                panic!("expected an error for {input}");
+            };
+            assert_eq!((error.kind, error.message.as_str()), (kind, message));
+        }
+    }
+
     fn test_eval(input: &str) -> Object {
         let lexer = Lexer::new(input);
         let mut parser = Parser::new(lexer);
@@ -541,7 +1675,7 @@ mod tests {
 
     fn test_error_object(object: Object, expected: String) {
         match object {
-            Object::ERROR(x) => assert_eq!(x, expected),
+            Object::ERROR(x) => assert_eq!(x.message, expected),
             _ => panic!("The object is not an  error"),
         }
     }
@@ -1,2 +1,3 @@
 pub mod evaluator;
 mod evaluator_tests;
+mod strict;
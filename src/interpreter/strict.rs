@@ -0,0 +1,182 @@
+use std::rc::Rc;
+
+use crate::{
+    object::{
+        builtins::BuiltinFunction,
+        error::{ErrorKind, ErrorObject},
+    },
+    parser::ast::{Expression, FunctionLiteral, Statement},
+    strict::{redefinition_error, StrictConfig},
+};
+
+/// Names a function can use: its parameters, the variables it defines and
+/// its own name.
+#[derive(Default)]
+struct CheckerScope {
+    /// Names bound by parameters and `let`s, which cannot be bound again.
+    variables: Vec<Rc<str>>,
+    function: Option<String>,
+}
+
+/// Checks, before a program runs, the identifiers it uses and the names it
+/// defines, resolving them like the compiler does: a name must be defined
+/// before the statement using it, or by a function around it.
+pub(crate) struct StrictChecker {
+    config: StrictConfig,
+    scopes: Vec<CheckerScope>,
+    /// Offset of the top level statement being checked.
+    offset: Option<usize>,
+}
+
+impl StrictChecker {
+    /// Creates a checker of the programs run in an environment which already
+    /// defines `globals`.
+    pub fn new(config: StrictConfig, globals: Vec<Rc<str>>) -> Self {
+        Self {
+            config,
+            scopes: vec![CheckerScope {
+                variables: globals,
+                function: None,
+            }],
+            offset: None,
+        }
+    }
+
+    /// Returns the first error of the program, with the offset of the top
+    /// level statement it is in.
+    pub fn check(
+        mut self,
+        statements: &[Statement],
+        offsets: &[usize],
+    ) -> Result<(), (ErrorObject, Option<usize>)> {
+        for (i, statement) in statements.iter().enumerate() {
+            self.offset = offsets.get(i).copied();
+            self.check_statement(statement)
+                .map_err(|error| (error, self.offset))?;
+        }
+        Ok(())
+    }
+
+    fn define(&mut self, name: &Rc<str>) -> Result<(), ErrorObject> {
+        let scope = self.scopes.last_mut().expect("the checker has no scope");
+        if scope.variables.contains(name) {
+            if self.config.no_redefinitions {
+                return Err(ErrorObject::custom(redefinition_error(name)));
+            }
+        } else {
+            scope.variables.push(Rc::clone(name));
+        }
+        Ok(())
+    }
+
+    fn resolve(&self, name: &str) -> Result<(), ErrorObject> {
+        let defined = self.scopes.iter().any(|scope| {
+            scope.variables.iter().any(|variable| &**variable == name)
+                || scope.function.as_deref() == Some(name)
+        });
+        if defined
+            || !self.config.undefined_identifiers
+            || BuiltinFunction::get_builtin(name).is_some()
+        {
+            Ok(())
+        } else {
+            Err(ErrorObject::new(
+                ErrorKind::UndefinedIdentifier,
+                format!("identifier not found: {name}"),
+            ))
+        }
+    }
+
+    fn check_statements(&mut self, statements: &[Statement]) -> Result<(), ErrorObject> {
+        statements
+            .iter()
+            .try_for_each(|statement| self.check_statement(statement))
+    }
+
+    fn check_statement(&mut self, statement: &Statement) -> Result<(), ErrorObject> {
+        match statement {
+            Statement::Let(statement) => {
+                // Functions can call themselves, so their name is defined first
+                if let Expression::FunctionLiteral(_) = statement.value {
+                    self.define(&statement.name.value)?;
+                    self.check_expression(&statement.value)
+                } else {
+                    self.check_expression(&statement.value)?;
+                    self.define(&statement.name.value)
+                }
+            }
+            Statement::Return(statement) => self.check_expression(&statement.return_value),
+            Statement::Expression(expression) => self.check_expression(expression),
+            Statement::While(statement) => {
+                self.check_expression(&statement.condition)?;
+                self.check_statements(&statement.body.statements)
+            }
+            Statement::LoopStatements(_) => Ok(()),
+            Statement::IndexAssignment(assignment) => {
+                self.resolve(&assignment.name.value)?;
+                for index in &assignment.indexes {
+                    self.check_expression(index)?;
+                }
+                self.check_expression(&assignment.value)
+            }
+        }
+    }
+
+    fn check_expression(&mut self, expression: &Expression) -> Result<(), ErrorObject> {
+        match expression {
+            Expression::Identifier(identifier) => self.resolve(&identifier.value),
+            Expression::Primitive(_) => Ok(()),
+            Expression::Prefix(prefix) => self.check_expression(&prefix.right),
+            Expression::Infix(infix) => {
+                self.check_expression(&infix.left)?;
+                self.check_expression(&infix.right)
+            }
+            Expression::Conditional(conditional) => {
+                self.check_expression(&conditional.condition)?;
+                self.check_statements(&conditional.consequence.statements)?;
+                match &conditional.alternative {
+                    Some(alternative) => self.check_statements(&alternative.statements),
+                    None => Ok(()),
+                }
+            }
+            Expression::FunctionLiteral(function) => {
+                let FunctionLiteral {
+                    name,
+                    parameters,
+                    body,
+                    ..
+                } = &**function;
+                self.scopes.push(CheckerScope {
+                    variables: Vec::new(),
+                    function: name.clone(),
+                });
+                let result = parameters
+                    .iter()
+                    .try_for_each(|parameter| self.define(&parameter.value))
+                    .and_then(|()| self.check_statements(&body.statements));
+                self.scopes.pop();
+                result
+            }
+            Expression::FunctionCall(call) => {
+                self.check_expression(&call.function)?;
+                call.arguments
+                    .iter()
+                    .try_for_each(|argument| self.check_expression(argument))
+            }
+            Expression::ArrayLiteral(array) => array
+                .elements
+                .iter()
+                .try_for_each(|element| self.check_expression(element)),
+            Expression::HashMapLiteral(hashmap) => {
+                hashmap.pairs.iter().try_for_each(|(key, value)| {
+                    self.check_expression(key)?;
+                    self.check_expression(value)
+                })
+            }
+            Expression::IndexExpression(index) => {
+                self.check_expression(&index.left)?;
+                self.check_expression(&index.index)
+            }
+        }
+    }
+}
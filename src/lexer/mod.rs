@@ -1,11 +1,31 @@
 pub mod token;
 use crate::lexer::token::Token;
 
+/// A `//` comment, which runs until the end of its line. The lexer skips
+/// them, keeping them aside for the formatter.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Comment {
+    /// Text of the comment, with its `//` and without trailing whitespace.
+    pub text: String,
+
+    /// Offset, in characters, of the `//`.
+    pub offset: usize,
+
+    /// Whether the comment is alone on its line, instead of following a token.
+    pub own_line: bool,
+}
+
 pub struct Lexer {
     input: Vec<char>,
     position: usize,      // current position in input (points to current char)
     read_position: usize, // current reading position in input (after current char)
     ch: char,             // current char under examination
+    token_start: usize,   // position of the first char of the last token
+    comments: Vec<Comment>,
+    new_line: bool,   // whether no token was read since the last line break
+    doc: Vec<String>, // lines of the `///` comments since the last token
+    token_doc: Option<String>,
 }
 
 impl Lexer {
@@ -15,12 +35,26 @@ impl Lexer {
             position: 0,
             read_position: 0,
             ch: '\0',
+            token_start: 0,
+            comments: Vec::new(),
+            new_line: true,
+            doc: Vec::new(),
+            token_doc: None,
         };
 
         lexer.read_char();
         lexer
     }
 
+    /// Creates a lexer of `input` which skips the characters before `offset`.
+    /// The offsets of its tokens and comments are still the ones in `input`.
+    pub fn starting_at(input: &str, offset: usize) -> Lexer {
+        let mut lexer = Lexer::new(input);
+        lexer.read_position = offset;
+        lexer.read_char();
+        lexer
+    }
+
     fn read_char(&mut self) {
         if self.read_position >= self.input.len() {
             self.ch = '\0';
@@ -33,6 +67,9 @@ impl Lexer {
 
     pub fn next_token(&mut self) -> Token {
         self.skip_whitespace();
+        self.token_start = self.position;
+        self.new_line = false;
+        self.token_doc = (!self.doc.is_empty()).then(|| std::mem::take(&mut self.doc).join("\n"));
         let token = match self.ch {
             '=' => {
                 if self.peek_char() == '=' {
@@ -98,9 +135,13 @@ impl Lexer {
             ':' => Token::Colon,
             '"' => {
                 let string = self.read_string();
-                Token::String(string)
+                Token::String(string.into())
             }
             '\0' => Token::Eof,
+            'b' if self.peek_char() == '"' => {
+                self.read_char();
+                Token::Bytes(self.read_string())
+            }
             'a'..='z' | 'A'..='Z' | '_' => {
                 let ident_string = self.read_identifier();
                 return match ident_string.as_str() {
@@ -114,7 +155,7 @@ impl Lexer {
                     "while" => Token::While,
                     "break" => Token::Break,
                     "continue" => Token::Continue,
-                    _ => Token::Ident(ident_string),
+                    _ => Token::Ident(ident_string.into()),
                 };
             }
             '0'..='9' => return Token::Int(self.read_number()),
@@ -124,10 +165,66 @@ impl Lexer {
         token
     }
 
+    /// Returns the offset, in characters, of the last token returned by
+    /// [`Lexer::next_token`]. It can be turned into a line and column with
+    /// [`Source::position`](crate::source::Source::position).
+    pub fn token_offset(&self) -> usize {
+        self.token_start
+    }
+
+    /// Returns the offset of the character after the last token returned by
+    /// [`Lexer::next_token`].
+    pub fn token_end(&self) -> usize {
+        self.position.min(self.input.len())
+    }
+
+    /// Returns the documentation of the last token returned by
+    /// [`Lexer::next_token`]: the lines of the `///` comments right before
+    /// it, without their `///` and the space after it.
+    pub fn take_token_doc(&mut self) -> Option<String> {
+        self.token_doc.take()
+    }
+
+    /// Returns the comments skipped so far, in the order of the input.
+    pub fn take_comments(&mut self) -> Vec<Comment> {
+        std::mem::take(&mut self.comments)
+    }
+
     fn skip_whitespace(&mut self) {
-        while self.ch.is_whitespace() {
+        loop {
+            if self.ch == '/' && self.peek_char() == '/' {
+                self.read_comment();
+            } else if self.ch.is_whitespace() {
+                if self.ch == '\n' {
+                    self.new_line = true;
+                }
+                self.read_char();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn read_comment(&mut self) {
+        let position = self.position;
+        while self.ch != '\n' && self.ch != '\0' {
             self.read_char();
         }
+        let text: String = self.input[position..self.position].iter().collect();
+        // `////` starts a regular comment, like in Rust
+        match text.strip_prefix("///") {
+            Some(line) if self.new_line && !line.starts_with('/') => {
+                let line = line.trim_end();
+                self.doc
+                    .push(line.strip_prefix(' ').unwrap_or(line).to_string());
+            }
+            _ => self.doc.clear(),
+        }
+        self.comments.push(Comment {
+            text: text.trim_end().to_string(),
+            offset: position,
+            own_line: self.new_line,
+        });
     }
 
     fn peek_char(&self) -> char {
@@ -238,40 +335,40 @@ mod tests {
 
         let expected = vec![
             Token::Let,
-            Token::Ident(String::from("five")),
+            Token::Ident("five".into()),
             Token::Assign,
             Token::Int(String::from("5")),
             Token::Semicolon,
             Token::Let,
-            Token::Ident(String::from("ten")),
+            Token::Ident("ten".into()),
             Token::Assign,
             Token::Int(String::from("10")),
             Token::Semicolon,
             Token::Let,
-            Token::Ident(String::from("add")),
+            Token::Ident("add".into()),
             Token::Assign,
             Token::Function,
             Token::LParen,
-            Token::Ident(String::from("x")),
+            Token::Ident("x".into()),
             Token::Comma,
-            Token::Ident(String::from("y")),
+            Token::Ident("y".into()),
             Token::RParen,
             Token::LSquirly,
-            Token::Ident(String::from("x")),
+            Token::Ident("x".into()),
             Token::Plus,
-            Token::Ident(String::from("y")),
+            Token::Ident("y".into()),
             Token::Semicolon,
             Token::RSquirly,
             Token::Semicolon,
             //
             Token::Let,
-            Token::Ident(String::from("result")),
+            Token::Ident("result".into()),
             Token::Assign,
-            Token::Ident(String::from("add")),
+            Token::Ident("add".into()),
             Token::LParen,
-            Token::Ident(String::from("five")),
+            Token::Ident("five".into()),
             Token::Comma,
-            Token::Ident(String::from("ten")),
+            Token::Ident("ten".into()),
             Token::RParen,
             Token::Semicolon,
             Token::Bang,
@@ -314,8 +411,8 @@ mod tests {
             Token::Int(String::from("9")),
             Token::Semicolon,
             //
-            Token::String(String::from("foobar")),
-            Token::String(String::from("foo bar")),
+            Token::String("foobar".into()),
+            Token::String("foo bar".into()),
             //
             Token::LSquare,
             Token::Int(String::from("1")),
@@ -325,9 +422,9 @@ mod tests {
             Token::Semicolon,
             //
             Token::LSquirly,
-            Token::String(String::from("foo")),
+            Token::String("foo".into()),
             Token::Colon,
-            Token::String(String::from("bar")),
+            Token::String("bar".into()),
             Token::RSquirly,
             //
             Token::True,
@@ -379,4 +476,100 @@ mod tests {
             assert_eq!(token, expected_token);
         }
     }
+
+    #[test]
+    fn test_bytes_literal() {
+        let input = r#"b"bytes" b + "b""#;
+        let expected = vec![
+            Token::Bytes("bytes".to_string()),
+            Token::Ident("b".into()),
+            Token::Plus,
+            Token::String("b".into()),
+            Token::Eof,
+        ];
+
+        let mut lexer = Lexer::new(input);
+        for expected_token in expected {
+            assert_eq!(lexer.next_token(), expected_token);
+        }
+    }
+
+    #[test]
+    fn test_token_offset() {
+        let input = "let a = \"hi\";\n  a";
+        let expected = vec![
+            (Token::Let, 0),
+            (Token::Ident("a".into()), 4),
+            (Token::Assign, 6),
+            (Token::String("hi".into()), 8),
+            (Token::Semicolon, 12),
+            (Token::Ident("a".into()), 16),
+            (Token::Eof, 17),
+        ];
+
+        let mut lexer = Lexer::new(input);
+        for (expected_token, expected_offset) in expected {
+            let token = lexer.next_token();
+            assert_eq!(token, expected_token);
+            assert_eq!(lexer.token_offset(), expected_offset);
+        }
+
+        let mut lexer = Lexer::new(input);
+        let mut ends = Vec::new();
+        loop {
+            let token = lexer.next_token();
+            ends.push(lexer.token_end());
+            if token == Token::Eof {
+                break;
+            }
+        }
+        assert_eq!(ends, vec![3, 5, 7, 12, 13, 17, 17]);
+    }
+
+    #[test]
+    fn test_comments() {
+        let input = "// header\nlet a = 1; // one  \n  // alone\na / 2 //";
+        let expected = vec![
+            Token::Let,
+            Token::Ident("a".into()),
+            Token::Assign,
+            Token::Int("1".to_string()),
+            Token::Semicolon,
+            Token::Ident("a".into()),
+            Token::Slash,
+            Token::Int("2".to_string()),
+            Token::Eof,
+        ];
+
+        let mut lexer = Lexer::new(input);
+        for expected_token in expected {
+            assert_eq!(lexer.next_token(), expected_token);
+        }
+        let comment = |text: &str, offset, own_line| Comment {
+            text: text.to_string(),
+            offset,
+            own_line,
+        };
+        assert_eq!(
+            lexer.take_comments(),
+            vec![
+                comment("// header", 0, true),
+                comment("// one", 21, false),
+                comment("// alone", 32, true),
+                comment("//", 47, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_doc_comments() {
+        let input = "/// Adds\n///   two numbers\nlet add;\n/// Lost\n// regular\nlet a; x; /// not a doc\nlet b;\n//// not a doc\nlet c;";
+        let mut lexer = Lexer::new(input);
+        let mut docs = Vec::new();
+        while lexer.next_token() != Token::Eof {
+            docs.push(lexer.take_token_doc());
+        }
+        assert_eq!(docs[0].as_deref(), Some("Adds\n  two numbers"));
+        assert!(docs[1..].iter().all(Option::is_none));
+    }
 }
@@ -1,16 +1,18 @@
-use std::fmt::Display;
+use std::{fmt::Display, rc::Rc};
 
 #[allow(dead_code)]
 #[derive(Debug, PartialEq, Clone)] // I should find a way of avoiding this thanks to lifetimes, but
-                                   // not for now (the issue is with the parser...)
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+// not for now (the issue is with the parser...)
 pub enum Token {
     Illegal(String),
     Eof,
 
     // Identifiers + literals
-    Ident(String), // add, foobar, x, y, ...
+    Ident(Rc<str>), // add, foobar, x, y, ...
     Int(String),
-    String(String),
+    String(Rc<str>),
+    Bytes(String), // b"..."
 
     // Operators
     Assign,
@@ -57,7 +59,8 @@ pub enum Token {
 impl Display for Token {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Token::Ident(x) | Token::Int(x) | Token::String(x) => write!(f, "{x}"),
+            Token::Ident(x) | Token::String(x) => write!(f, "{x}"),
+            Token::Int(x) | Token::Bytes(x) => write!(f, "{x}"),
             Token::Illegal(s) => write!(f, "Illegal: {s}"),
             Token::Eof => write!(f, "Eof"),
             Token::Assign => write!(f, "="),
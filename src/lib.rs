@@ -1,10 +1,16 @@
 pub mod compiler;
+pub mod doc;
 pub mod formatter;
+pub mod highlight;
+pub mod hooks;
 pub mod interpreter;
 pub mod lexer;
+pub mod linter;
 pub mod object;
 pub mod parser;
 pub mod repl;
+pub mod source;
+pub mod strict;
 pub mod vm;
 
 pub mod utils;
@@ -0,0 +1,143 @@
+#[cfg(test)]
+mod tests {
+    use crate::{
+        linter::{Diagnostic, LintConfig, Linter, Rule, Severity},
+        parser::parse,
+        source::Source,
+    };
+
+    fn lint_with(input: &str, config: LintConfig) -> Vec<Diagnostic> {
+        Linter::new(config).lint(&parse(input))
+    }
+
+    /// Returns the rule and the `line:column` of every diagnostic.
+    fn lint(input: &str) -> Vec<(Rule, String)> {
+        let source = Source::new("test.monkey", input);
+        lint_with(input, LintConfig::default())
+            .into_iter()
+            .map(|diagnostic| {
+                (
+                    diagnostic.rule,
+                    source.position(diagnostic.offset.unwrap()).to_string(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_clean_program() {
+        let input = r#"
+let fibonacci = fn(n) {
+    if (n < 2) {
+        return n;
+    }
+    fibonacci(n - 1) + fibonacci(n - 2)
+};
+let i = 0;
+while (true) {
+    let i = i + 1;
+    if (i > 10) {
+        break;
+    }
+}
+let array = [1, 2];
+array[0] = 3;
+let _ignored = 1;
+puts(fibonacci(10));
+"#;
+        assert_eq!(lint(input), vec![]);
+    }
+
+    #[test]
+    fn test_unused_variables() {
+        let input = "let x = 1;\nlet y = 2;\nlet f = fn() {\n  let z = y;\n  3\n};\nf()";
+        assert_eq!(
+            lint(input),
+            vec![
+                (Rule::UnusedVariable, "1:1".to_string()),
+                (Rule::UnusedVariable, "4:3".to_string()),
+            ]
+        );
+
+        // Closures use the variables of the functions around them
+        let input = "let f = fn() { let x = 1; fn() { x } }; f()";
+        assert_eq!(lint(input), vec![]);
+    }
+
+    #[test]
+    fn test_unused_parameters() {
+        let input = "let f = fn(a, b, _c) {\n  a\n};\nf(1, 2, 3)";
+        assert_eq!(
+            lint(input),
+            vec![(Rule::UnusedParameter, "1:1".to_string())]
+        );
+        let diagnostics = lint_with(input, LintConfig::default());
+        assert_eq!(diagnostics[0].message, "unused parameter `b`");
+    }
+
+    #[test]
+    fn test_unreachable_code() {
+        let input = "let f = fn() {\n  return 1;\n  puts(2);\n  puts(3);\n};\nf();\nwhile (true) { break; puts(4); }";
+        assert_eq!(
+            lint(input),
+            vec![
+                (Rule::UnreachableCode, "3:3".to_string()),
+                (Rule::UnreachableCode, "7:23".to_string()),
+            ]
+        );
+        let diagnostics = lint_with(input, LintConfig::default());
+        assert_eq!(diagnostics[0].message, "unreachable code after `return`");
+        assert_eq!(diagnostics[1].message, "unreachable code after `break`");
+    }
+
+    #[test]
+    fn test_constant_conditions() {
+        let input = "if (1 < 2) { 1 }\nwhile (false) { 2 }\nlet x = 1;\nif (x < 2) { 3 }";
+        assert_eq!(
+            lint(input),
+            vec![
+                (Rule::ConstantCondition, "1:1".to_string()),
+                (Rule::ConstantCondition, "2:1".to_string()),
+            ]
+        );
+        let diagnostics = lint_with(input, LintConfig::default());
+        assert_eq!(
+            diagnostics[0].message,
+            "the condition `(1 < 2)` of this `if` is constant"
+        );
+    }
+
+    #[test]
+    fn test_shadowed_builtins() {
+        let input = "let len = fn(puts) { puts };\nlen(1)";
+        assert_eq!(
+            lint(input),
+            vec![
+                (Rule::ShadowedBuiltin, "1:1".to_string()),
+                (Rule::ShadowedBuiltin, "1:1".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_severities() {
+        let input = "let x = 1;\nif (true) { x }\nlet y = 2;";
+        let mut config = LintConfig::default();
+        config.set(Rule::UnusedVariable, Severity::Error);
+        config.set(Rule::ConstantCondition, Severity::Allow);
+        let diagnostics = lint_with(input, config);
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic {
+                rule: Rule::UnusedVariable,
+                severity: Severity::Error,
+                message: "unused variable `y`".to_string(),
+                offset: Some(27),
+            }]
+        );
+        assert_eq!(
+            diagnostics[0].render(Some(&Source::new("test.monkey", input))),
+            "error: unused variable `y` [unused-variable]\ntest.monkey:3:1\n  |\n3 | let y = 2;\n  | ^"
+        );
+    }
+}
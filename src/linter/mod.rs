@@ -0,0 +1,317 @@
+mod linter_tests;
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+    rc::Rc,
+};
+
+use clap_derive::ValueEnum;
+
+use crate::{
+    object::builtins::BuiltinFunction,
+    parser::ast::{
+        walk_expression, walk_program, walk_statement, Expression, FunctionLiteral, Identifier,
+        LetStatement, Primitive, Program, Statement, Visitor, WhileStatement,
+    },
+    source::Source,
+};
+
+/// A check of the linter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ValueEnum)]
+pub enum Rule {
+    /// A variable defined with `let` which is never read
+    UnusedVariable,
+    /// A parameter the body of its function never reads
+    UnusedParameter,
+    /// Statements following a `return`, a `break` or a `continue`
+    UnreachableCode,
+    /// The condition of an `if` or a `while` which only depends on literals
+    ConstantCondition,
+    /// A variable or a parameter named like a builtin, which hides it
+    ShadowedBuiltin,
+}
+
+impl Rule {
+    /// Returns the name of the rule, as given on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rule::UnusedVariable => "unused-variable",
+            Rule::UnusedParameter => "unused-parameter",
+            Rule::UnreachableCode => "unreachable-code",
+            Rule::ConstantCondition => "constant-condition",
+            Rule::ShadowedBuiltin => "shadowed-builtin",
+        }
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// How a rule is reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The rule is not checked.
+    Allow,
+    Warning,
+    /// The rule is checked and makes the lint fail.
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Allow => f.write_str("allow"),
+            Severity::Warning => f.write_str("warning"),
+            Severity::Error => f.write_str("error"),
+        }
+    }
+}
+
+/// The severity of every rule. All of them are warnings by default.
+#[derive(Debug, Clone, Default)]
+pub struct LintConfig {
+    severities: HashMap<Rule, Severity>,
+}
+
+impl LintConfig {
+    pub fn set(&mut self, rule: Rule, severity: Severity) {
+        self.severities.insert(rule, severity);
+    }
+
+    pub fn severity(&self, rule: Rule) -> Severity {
+        self.severities
+            .get(&rule)
+            .copied()
+            .unwrap_or(Severity::Warning)
+    }
+}
+
+/// A problem found by the linter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub rule: Rule,
+    pub severity: Severity,
+    pub message: String,
+    /// Offset, in characters, of the statement the problem is in, if the
+    /// program was parsed.
+    pub offset: Option<usize>,
+}
+
+impl Diagnostic {
+    /// Returns the diagnostic followed by the line it points to, when the
+    /// source of the program is known.
+    pub fn render(&self, source: Option<&Source>) -> String {
+        match (source, self.offset) {
+            (Some(source), Some(offset)) => format!("{self}\n{}", source.snippet(offset)),
+            _ => self.to_string(),
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} [{}]", self.severity, self.message, self.rule)
+    }
+}
+
+/// A variable of a [`LinterScope`].
+struct Binding {
+    name: Rc<str>,
+    parameter: bool,
+    used: bool,
+    /// Offset of the statement defining the variable.
+    offset: Option<usize>,
+}
+
+/// The variables of a function, or of the whole program. Blocks do not have
+/// their own scope, like in the evaluator and the compiler.
+#[derive(Default)]
+struct LinterScope {
+    bindings: Vec<Binding>,
+}
+
+/// Walks a program and reports the problems found by the rules of its
+/// [`LintConfig`].
+pub struct Linter {
+    config: LintConfig,
+    scopes: Vec<LinterScope>,
+    /// Offset of the statement being checked.
+    offset: Option<usize>,
+    builtins: Vec<String>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Linter {
+    pub fn new(config: LintConfig) -> Self {
+        Self {
+            config,
+            scopes: Vec::new(),
+            offset: None,
+            builtins: BuiltinFunction::get_builtins_names(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Returns the problems of the program, sorted by position.
+    pub fn lint(&mut self, program: &Program) -> Vec<Diagnostic> {
+        self.diagnostics.clear();
+        self.scopes = vec![LinterScope::default()];
+        self.offset = None;
+        walk_program(self, program);
+        self.leave_scope();
+        let mut diagnostics = std::mem::take(&mut self.diagnostics);
+        diagnostics.sort_by_key(|diagnostic| diagnostic.offset);
+        diagnostics
+    }
+
+    fn report(&mut self, rule: Rule, message: String, offset: Option<usize>) {
+        let severity = self.config.severity(rule);
+        if severity != Severity::Allow {
+            self.diagnostics.push(Diagnostic {
+                rule,
+                severity,
+                message,
+                offset,
+            });
+        }
+    }
+
+    fn leave_scope(&mut self) {
+        let Some(scope) = self.scopes.pop() else {
+            return;
+        };
+        for binding in scope.bindings {
+            if binding.used || binding.name.starts_with('_') {
+                continue;
+            }
+            if binding.parameter {
+                let message = format!("unused parameter `{}`", binding.name);
+                self.report(Rule::UnusedParameter, message, binding.offset);
+            } else {
+                let message = format!("unused variable `{}`", binding.name);
+                self.report(Rule::UnusedVariable, message, binding.offset);
+            }
+        }
+    }
+
+    /// Defines a variable in the current scope. Defining it again, like
+    /// `let i = i + 1;` does, updates the same variable.
+    fn define(&mut self, name: &Rc<str>, parameter: bool) {
+        if self.builtins.iter().any(|builtin| **builtin == **name) {
+            let message = format!("`{name}` shadows the builtin of the same name");
+            self.report(Rule::ShadowedBuiltin, message, self.offset);
+        }
+        let offset = self.offset;
+        let scope = self.scopes.last_mut().expect("the linter has no scope");
+        if !scope.bindings.iter().any(|binding| binding.name == *name) {
+            scope.bindings.push(Binding {
+                name: Rc::clone(name),
+                parameter,
+                used: false,
+                offset,
+            });
+        }
+    }
+
+    fn resolve(&mut self, name: &str) {
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(binding) = scope
+                .bindings
+                .iter_mut()
+                .find(|binding| &*binding.name == name)
+            {
+                binding.used = true;
+                return;
+            }
+        }
+    }
+
+    fn check_condition(&mut self, condition: &Expression, keyword: &str) {
+        if is_constant(condition) {
+            let message = format!("the condition `{condition}` of this `{keyword}` is constant");
+            self.report(Rule::ConstantCondition, message, self.offset);
+        }
+    }
+}
+
+impl Visitor for Linter {
+    fn visit_statements(&mut self, statements: &[Statement], offsets: &[usize]) {
+        let mut unreachable_reported = false;
+        for (i, statement) in statements.iter().enumerate() {
+            let offset = offsets.get(i).copied().or(self.offset);
+            if i > 0 && !unreachable_reported {
+                let exit = match &statements[i - 1] {
+                    Statement::Return(_) => Some("return".to_string()),
+                    Statement::LoopStatements(statement) => Some(statement.to_string()),
+                    _ => None,
+                };
+                if let Some(exit) = exit {
+                    let message = format!("unreachable code after `{exit}`");
+                    self.report(Rule::UnreachableCode, message, offset);
+                    unreachable_reported = true;
+                }
+            }
+            self.visit_statement(statement, offset);
+        }
+    }
+
+    fn visit_statement(&mut self, statement: &Statement, offset: Option<usize>) {
+        let outer = self.offset;
+        self.offset = offset;
+        match statement {
+            Statement::Let(LetStatement { name, value }) => {
+                // Functions can call themselves, so their name is defined first
+                if let Expression::FunctionLiteral(_) = value {
+                    self.define(&name.value, false);
+                    self.visit_expression(value);
+                } else {
+                    self.visit_expression(value);
+                    self.define(&name.value, false);
+                }
+            }
+            Statement::While(WhileStatement { condition, .. }) => {
+                // `while (true)` is how loops left with `break` are written
+                if *condition != Expression::Primitive(Primitive::BooleanLiteral(true)) {
+                    self.check_condition(condition, "while");
+                }
+                walk_statement(self, statement);
+            }
+            _ => walk_statement(self, statement),
+        }
+        self.offset = outer;
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        if let Expression::Conditional(conditional) = expression {
+            self.check_condition(&conditional.condition, "if");
+        }
+        walk_expression(self, expression);
+    }
+
+    fn visit_function_literal(&mut self, function: &FunctionLiteral) {
+        self.scopes.push(LinterScope::default());
+        for parameter in &function.parameters {
+            self.define(&parameter.value, true);
+        }
+        self.visit_block_statement(&function.body);
+        self.leave_scope();
+    }
+
+    fn visit_identifier(&mut self, identifier: &Identifier) {
+        self.resolve(&identifier.value);
+    }
+}
+
+/// Returns whether the expression is made of literals and operators only.
+fn is_constant(expression: &Expression) -> bool {
+    match expression {
+        Expression::Primitive(_) => true,
+        Expression::Prefix(prefix) => is_constant(&prefix.right),
+        Expression::Infix(infix) => is_constant(&infix.left) && is_constant(&infix.right),
+        _ => false,
+    }
+}
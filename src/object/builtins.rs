@@ -1,14 +1,35 @@
 use enum_stringify::EnumStringify;
 use num_derive::{FromPrimitive, ToPrimitive};
 use num_traits::FromPrimitive;
-use std::cmp::Ordering;
+use std::{
+    cmp::Ordering,
+    io::{self, BufRead, Read, Write},
+    sync::OnceLock,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
 use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
 
-use crate::object::{Object, NULL};
+use crate::{
+    lexer::Lexer,
+    object::{
+        date::{self, DateTime},
+        format,
+        random::Random,
+        style::OutputStyle,
+        Object, NULL,
+    },
+    parser::{ast::Program, Parser},
+};
+
+/// The largest array `range` builds; bigger ranges would abort the process on
+/// allocation before any fuel or timeout limit could stop them.
+pub const MAX_RANGE_LENGTH: usize = 10_000_000;
 
 #[derive(Debug, PartialEq, Clone, FromPrimitive, ToPrimitive, EnumIter, EnumStringify)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[enum_stringify(case = "lower")]
+#[allow(non_camel_case_types)] // the variant names are the builtin names
 pub enum BuiltinFunction {
     LEN,
     FIRST,
@@ -16,6 +37,84 @@ pub enum BuiltinFunction {
     REST,
     PUSH,
     PUTS,
+    MERGE,
+    ORD,
+    CHR,
+    MAP,
+    FILTER,
+    REDUCE,
+    SPLIT,
+    JOIN,
+    TRIM,
+    UPPER,
+    LOWER,
+    REPLACE,
+    STARTS_WITH,
+    ENDS_WITH,
+    CONTAINS,
+    ABS,
+    MIN,
+    MAX,
+    POW,
+    SQRT,
+    FLOOR,
+    CEIL,
+    TYPE,
+    INT,
+    STR,
+    KEYS,
+    VALUES,
+    DELETE,
+    SORT,
+    RANGE,
+    INPUT,
+    READ_ALL,
+    TIME,
+    CLOCK,
+    RAND,
+    RAND_INT,
+    SEED,
+    EXIT,
+    ASSERT,
+    FORMAT,
+    INDEX_OF,
+    REVERSE,
+    SLICE,
+    PRINT,
+    EVAL,
+    BYTES,
+    DECODE,
+    NOW,
+    FORMAT_TIME,
+    PARSE_TIME,
+    DATE,
+    TIMESTAMP,
+    ARGV,
+    ENV,
+    FETCH,
+}
+
+/// Gives builtins access to the engine (evaluator or VM) calling them.
+pub trait BuiltinContext {
+    /// Calls a function (user defined or builtin) with the given arguments and
+    /// returns its result.
+    fn apply(&mut self, function: &Object, args: Vec<Object>) -> Object;
+
+    /// Style used to print objects.
+    fn output_style(&self) -> &OutputStyle;
+
+    /// Reader used by `input` and `read_all`, `None` means the standard input.
+    fn input(&mut self) -> Option<&mut dyn BufRead>;
+
+    /// Random number generator used by `rand`, `rand_int` and `seed`.
+    fn random(&mut self) -> &mut Random;
+
+    /// Arguments given to the program, returned by `argv`.
+    fn args(&self) -> &[String];
+
+    /// Runs a program in the global environment, for `eval`. Returns the value
+    /// of its last statement if it is an expression, null otherwise.
+    fn eval(&mut self, program: Program) -> Object;
 }
 
 #[allow(clippy::needless_pass_by_value)] // false positive
@@ -32,14 +131,77 @@ impl BuiltinFunction {
         BuiltinFunction::iter().map(|f| f.to_string()).collect()
     }
 
-    pub fn call(&self, args: Vec<Object>) -> Object {
+    pub fn call(&self, args: Vec<Object>, context: &mut dyn BuiltinContext) -> Object {
         match self {
             BuiltinFunction::LEN => Self::call_len(args),
             BuiltinFunction::FIRST => Self::call_first(args),
             BuiltinFunction::LAST => Self::call_last(args),
             BuiltinFunction::REST => Self::call_rest(args),
             BuiltinFunction::PUSH => Self::call_push(args),
-            BuiltinFunction::PUTS => Self::call_puts(args),
+            BuiltinFunction::PUTS => Self::call_puts(args, context),
+            BuiltinFunction::MERGE => Self::call_merge(args),
+            BuiltinFunction::ORD => Self::call_ord(args),
+            BuiltinFunction::CHR => Self::call_chr(args),
+            BuiltinFunction::MAP => Self::call_map(args, context),
+            BuiltinFunction::FILTER => Self::call_filter(args, context),
+            BuiltinFunction::REDUCE => Self::call_reduce(args, context),
+            BuiltinFunction::SPLIT => Self::call_split(args),
+            BuiltinFunction::JOIN => Self::call_join(args),
+            BuiltinFunction::TRIM => Self::call_string_map("trim", args, |s| s.trim().to_string()),
+            BuiltinFunction::UPPER => Self::call_string_map("upper", args, str::to_uppercase),
+            BuiltinFunction::LOWER => Self::call_string_map("lower", args, str::to_lowercase),
+            BuiltinFunction::REPLACE => Self::call_replace(args),
+            BuiltinFunction::STARTS_WITH => {
+                Self::call_string_predicate("starts_with", args, |s, p| s.starts_with(p))
+            }
+            BuiltinFunction::ENDS_WITH => {
+                Self::call_string_predicate("ends_with", args, |s, p| s.ends_with(p))
+            }
+            BuiltinFunction::CONTAINS => Self::call_contains(args),
+            BuiltinFunction::ABS => Self::call_abs(args),
+            BuiltinFunction::MIN => Self::call_min_max("min", args, std::cmp::min),
+            BuiltinFunction::MAX => Self::call_min_max("max", args, std::cmp::max),
+            BuiltinFunction::POW => Self::call_pow(args),
+            BuiltinFunction::SQRT => Self::call_sqrt(args),
+            // Integers are already rounded, these will do more once floats exist
+            BuiltinFunction::FLOOR => Self::call_rounding("floor", args),
+            BuiltinFunction::CEIL => Self::call_rounding("ceil", args),
+            BuiltinFunction::TYPE => Self::call_type(args),
+            BuiltinFunction::INT => Self::call_int(args),
+            BuiltinFunction::STR => Self::call_str(args),
+            BuiltinFunction::KEYS => {
+                Self::call_hashmap_entries("keys", args, |(key, _)| key.clone())
+            }
+            BuiltinFunction::VALUES => {
+                Self::call_hashmap_entries("values", args, |(_, value)| value.clone())
+            }
+            BuiltinFunction::DELETE => Self::call_delete(args),
+            BuiltinFunction::SORT => Self::call_sort(args, context),
+            BuiltinFunction::RANGE => Self::call_range(args),
+            BuiltinFunction::INPUT => Self::call_input(args, context),
+            BuiltinFunction::READ_ALL => Self::call_read_all(args, context),
+            BuiltinFunction::TIME | BuiltinFunction::NOW => Self::call_time(args),
+            BuiltinFunction::CLOCK => Self::call_clock(args),
+            BuiltinFunction::RAND => Self::call_rand(args, context),
+            BuiltinFunction::RAND_INT => Self::call_rand_int(args, context),
+            BuiltinFunction::SEED => Self::call_seed(args, context),
+            BuiltinFunction::EXIT => Self::call_exit(args),
+            BuiltinFunction::ASSERT => Self::call_assert(args),
+            BuiltinFunction::FORMAT => Self::call_format(args),
+            BuiltinFunction::INDEX_OF => Self::call_index_of(args),
+            BuiltinFunction::REVERSE => Self::call_reverse(args),
+            BuiltinFunction::SLICE => Self::call_slice(args),
+            BuiltinFunction::PRINT => Self::call_print(args, context),
+            BuiltinFunction::EVAL => Self::call_eval(args, context),
+            BuiltinFunction::BYTES => Self::call_bytes(args),
+            BuiltinFunction::DECODE => Self::call_decode(args),
+            BuiltinFunction::FORMAT_TIME => Self::call_format_time(args),
+            BuiltinFunction::PARSE_TIME => Self::call_parse_time(args),
+            BuiltinFunction::DATE => Self::call_date(args),
+            BuiltinFunction::TIMESTAMP => Self::call_timestamp(args),
+            BuiltinFunction::ARGV => Self::call_argv(args, context),
+            BuiltinFunction::ENV => Self::call_env(args),
+            BuiltinFunction::FETCH => Self::call_fetch(args),
         }
     }
 
@@ -47,7 +209,8 @@ impl BuiltinFunction {
         Self::handle_number_of_arguments(args.len(), 1).unwrap_or_else(|| match &args[0] {
             Object::STRING(s) => Object::INTEGER(s.len() as i64),
             Object::ARRAY(a) => Object::INTEGER(a.len() as i64),
-            _ => Object::ERROR(format!(
+            Object::BYTES(b) => Object::INTEGER(b.len() as i64),
+            _ => Object::error(format!(
                 "argument to `len` not supported, got {}",
                 args[0].get_type()
             )),
@@ -63,7 +226,7 @@ impl BuiltinFunction {
                     a[0].clone()
                 }
             }
-            _ => Object::ERROR(format!(
+            _ => Object::error(format!(
                 "argument to `first` not supported, must be ARRAY, got {}",
                 args[0].get_type()
             )),
@@ -80,7 +243,7 @@ impl BuiltinFunction {
                     NULL
                 }
             }
-            _ => Object::ERROR(format!(
+            _ => Object::error(format!(
                 "argument to `last` not supported, must be ARRAY, got {}",
                 args[0].get_type()
             )),
@@ -98,7 +261,7 @@ impl BuiltinFunction {
                     Ordering::Less => NULL,
                 }
             }
-            _ => Object::ERROR(format!(
+            _ => Object::error(format!(
                 "argument to `rest` not supported, must be ARRAY, got {}",
                 args[0].get_type()
             )),
@@ -112,26 +275,913 @@ impl BuiltinFunction {
                 new_array.push(args[1].clone());
                 Object::ARRAY(new_array)
             }
-            _ => Object::ERROR(format!(
+            _ => Object::error(format!(
                 "argument to `push` not supported, must be ARRAY, got {}",
                 args[0].get_type()
             )),
         })
     }
 
-    fn call_puts(args: Vec<Object>) -> Object {
+    fn call_puts(args: Vec<Object>, context: &dyn BuiltinContext) -> Object {
+        let style = context.output_style();
         for arg in args {
-            println!("{arg}");
+            println!("{}", style.display(&arg));
         }
         NULL
     }
 
+    /// Prints the arguments separated by spaces, without a newline.
+    fn call_print(args: Vec<Object>, context: &dyn BuiltinContext) -> Object {
+        let style = context.output_style();
+        let text: Vec<String> = args.iter().map(|arg| style.display(arg)).collect();
+        print!("{}", text.join(" "));
+        // Without a newline, the text would stay in the buffer
+        let _ = io::stdout().flush();
+        NULL
+    }
+
+    fn call_input(args: Vec<Object>, context: &mut dyn BuiltinContext) -> Object {
+        if let Some(error) = Self::handle_number_of_arguments_between(args.len(), 0, 1) {
+            return error;
+        }
+        match args.first() {
+            Some(Object::STRING(prompt)) => {
+                print!("{prompt}");
+                // The prompt has no newline, it would stay hidden in the buffer
+                let _ = io::stdout().flush();
+            }
+            Some(other) => {
+                return Object::error(format!(
+                    "argument to `input` not supported, must be STRING, got {}",
+                    other.get_type()
+                ))
+            }
+            None => {}
+        }
+
+        let mut line = String::new();
+        let read = match context.input() {
+            Some(reader) => reader.read_line(&mut line),
+            None => io::stdin().read_line(&mut line),
+        };
+        match read {
+            // End of input
+            Ok(0) => NULL,
+            Ok(_) => {
+                let len = line.trim_end_matches(['\n', '\r']).len();
+                line.truncate(len);
+                Object::STRING(line.into())
+            }
+            Err(error) => Self::read_error(&error),
+        }
+    }
+
+    fn call_read_all(args: Vec<Object>, context: &mut dyn BuiltinContext) -> Object {
+        Self::handle_number_of_arguments(args.len(), 0).unwrap_or_else(|| {
+            let mut text = String::new();
+            let read = match context.input() {
+                Some(reader) => reader.read_to_string(&mut text),
+                None => io::stdin().read_to_string(&mut text),
+            };
+            match read {
+                Ok(_) => Object::STRING(text.into()),
+                Err(error) => Self::read_error(&error),
+            }
+        })
+    }
+
+    fn read_error(error: &io::Error) -> Object {
+        Object::error(format!("could not read the input: {error}"))
+    }
+
+    fn call_time(args: Vec<Object>) -> Object {
+        Self::handle_number_of_arguments(args.len(), 0).unwrap_or_else(|| {
+            let seconds = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_secs());
+            Object::INTEGER(seconds as i64)
+        })
+    }
+
+    fn call_clock(args: Vec<Object>) -> Object {
+        // Instants are opaque, the clock counts from its first use
+        static START: OnceLock<Instant> = OnceLock::new();
+
+        Self::handle_number_of_arguments(args.len(), 0).unwrap_or_else(|| {
+            let start = START.get_or_init(Instant::now);
+            Object::INTEGER(start.elapsed().as_millis() as i64)
+        })
+    }
+
+    fn call_argv(args: Vec<Object>, context: &mut dyn BuiltinContext) -> Object {
+        Self::handle_number_of_arguments(args.len(), 0).unwrap_or_else(|| {
+            Object::ARRAY(
+                context
+                    .args()
+                    .iter()
+                    .map(|arg| Object::STRING(arg.as_str().into()))
+                    .collect(),
+            )
+        })
+    }
+
+    fn call_env(args: Vec<Object>) -> Object {
+        Self::handle_number_of_arguments(args.len(), 1).unwrap_or_else(|| {
+            match Self::string_arguments("env", &args) {
+                // Variables which are not valid unicode are treated as unset
+                Ok(names) => {
+                    std::env::var(names[0]).map_or(NULL, |value| Object::STRING(value.into()))
+                }
+                Err(error) => error,
+            }
+        })
+    }
+
+    fn call_fetch(args: Vec<Object>) -> Object {
+        Self::handle_number_of_arguments(args.len(), 1).unwrap_or_else(|| {
+            match Self::string_arguments("fetch", &args) {
+                #[cfg(feature = "http")]
+                Ok(urls) => match super::http::fetch(urls[0]) {
+                    Ok(body) => Object::STRING(body.into()),
+                    Err(error) => Object::error(error),
+                },
+                // The builtin always exists so that builtin indexes do not
+                // depend on the features
+                #[cfg(not(feature = "http"))]
+                Ok(_) => Object::error("`fetch` needs the `http` feature".to_string()),
+                Err(error) => error,
+            }
+        })
+    }
+
+    fn call_format_time(args: Vec<Object>) -> Object {
+        Self::handle_number_of_arguments(args.len(), 2).unwrap_or_else(|| match &args[..] {
+            [Object::INTEGER(timestamp), Object::STRING(format)] => {
+                match date::format_time(*timestamp, format) {
+                    Ok(formatted) => Object::STRING(formatted.into()),
+                    Err(error) => Object::error(error),
+                }
+            }
+            [first, second] => Object::error(format!(
+                "arguments to `format_time` not supported, must be INTEGER and STRING, got {} and {}",
+                first.get_type(),
+                second.get_type()
+            )),
+            _ => unreachable!(),
+        })
+    }
+
+    fn call_parse_time(args: Vec<Object>) -> Object {
+        Self::handle_number_of_arguments(args.len(), 2).unwrap_or_else(|| {
+            match Self::string_arguments("parse_time", &args) {
+                Ok(strings) => match date::parse_time(strings[0], strings[1]) {
+                    Ok(timestamp) => Object::INTEGER(timestamp),
+                    Err(error) => Object::error(error),
+                },
+                Err(error) => error,
+            }
+        })
+    }
+
+    fn call_date(args: Vec<Object>) -> Object {
+        Self::handle_number_of_arguments(args.len(), 1).unwrap_or_else(|| {
+            match Self::integer_arguments("date", &args) {
+                Ok(integers) => {
+                    let date = DateTime::from_timestamp(integers[0]);
+                    let fields = [
+                        ("year", date.year),
+                        ("month", date.month),
+                        ("day", date.day),
+                        ("hour", date.hour),
+                        ("minute", date.minute),
+                        ("second", date.second),
+                        ("weekday", date.weekday()),
+                    ];
+                    Object::HASHMAP(
+                        fields
+                            .into_iter()
+                            .map(|(key, value)| {
+                                (Object::STRING(key.into()), Object::INTEGER(value))
+                            })
+                            .collect(),
+                    )
+                }
+                Err(error) => error,
+            }
+        })
+    }
+
+    fn call_timestamp(args: Vec<Object>) -> Object {
+        Self::handle_number_of_arguments_between(args.len(), 3, 6).unwrap_or_else(|| {
+            match Self::integer_arguments("timestamp", &args) {
+                Ok(mut integers) => {
+                    // The time of day defaults to midnight
+                    integers.resize(6, 0);
+                    let date = DateTime {
+                        year: integers[0],
+                        month: integers[1],
+                        day: integers[2],
+                        hour: integers[3],
+                        minute: integers[4],
+                        second: integers[5],
+                    };
+                    date.to_timestamp()
+                        .map_or_else(|| Self::overflow_error("timestamp"), Object::INTEGER)
+                }
+                Err(error) => error,
+            }
+        })
+    }
+
+    fn call_rand(args: Vec<Object>, context: &mut dyn BuiltinContext) -> Object {
+        Self::handle_number_of_arguments(args.len(), 0)
+            .unwrap_or_else(|| Object::INTEGER((context.random().next_u64() >> 1) as i64))
+    }
+
+    fn call_rand_int(args: Vec<Object>, context: &mut dyn BuiltinContext) -> Object {
+        Self::handle_number_of_arguments(args.len(), 2).unwrap_or_else(|| {
+            match Self::integer_arguments("rand_int", &args) {
+                Ok(integers) if integers[0] > integers[1] => Object::error(format!(
+                    "range of `rand_int` is empty, got {} to {}",
+                    integers[0], integers[1]
+                )),
+                Ok(integers) => {
+                    Object::INTEGER(context.random().next_in_range(integers[0], integers[1]))
+                }
+                Err(error) => error,
+            }
+        })
+    }
+
+    fn call_seed(args: Vec<Object>, context: &mut dyn BuiltinContext) -> Object {
+        Self::handle_number_of_arguments(args.len(), 1).unwrap_or_else(|| {
+            match Self::integer_arguments("seed", &args) {
+                Ok(integers) => {
+                    context.random().seed(integers[0] as u64);
+                    NULL
+                }
+                Err(error) => error,
+            }
+        })
+    }
+
+    fn call_exit(args: Vec<Object>) -> Object {
+        if let Some(error) = Self::handle_number_of_arguments_between(args.len(), 0, 1) {
+            return error;
+        }
+        match Self::integer_arguments("exit", &args) {
+            Ok(integers) => match integers.first().copied().unwrap_or(0) {
+                code @ 0..=255 => Object::EXIT(code),
+                code => Object::error(format!(
+                    "exit code {code} is not supported, it must be between 0 and 255"
+                )),
+            },
+            Err(error) => error,
+        }
+    }
+
+    fn call_assert(args: Vec<Object>) -> Object {
+        if let Some(error) = Self::handle_number_of_arguments_between(args.len(), 1, 2) {
+            return error;
+        }
+        match (args[0].is_truthy(), args.get(1)) {
+            (true, _) => NULL,
+            (false, None) => Object::error("assertion failed".to_string()),
+            (false, Some(Object::STRING(message))) => {
+                Object::error(format!("assertion failed: {message}"))
+            }
+            (false, Some(other)) => Object::error(format!(
+                "argument to `assert` not supported, must be STRING, got {}",
+                other.get_type()
+            )),
+        }
+    }
+
+    fn call_eval(args: Vec<Object>, context: &mut dyn BuiltinContext) -> Object {
+        Self::handle_number_of_arguments(args.len(), 1).unwrap_or_else(|| match &args[0] {
+            Object::STRING(input) => {
+                let mut parser = Parser::new(Lexer::new(input));
+                let program = parser.parse_program();
+                if parser.errors.is_empty() {
+                    context.eval(program)
+                } else {
+                    Object::error(format!(
+                        "could not parse the input of `eval`: {}",
+                        parser.errors.errors.join(", ")
+                    ))
+                }
+            }
+            other => Self::string_argument_error("eval", other),
+        })
+    }
+
+    fn call_bytes(args: Vec<Object>) -> Object {
+        Self::handle_number_of_arguments(args.len(), 1).unwrap_or_else(|| match &args[0] {
+            Object::BYTES(b) => Object::BYTES(b.clone()),
+            Object::STRING(s) => Object::BYTES(s.as_bytes().to_vec()),
+            Object::ARRAY(a) => {
+                let bytes: Option<Vec<u8>> = a
+                    .iter()
+                    .map(|element| match element {
+                        Object::INTEGER(i) => u8::try_from(*i).ok(),
+                        _ => None,
+                    })
+                    .collect();
+                bytes.map_or_else(
+                    || {
+                        Object::error(
+                            "argument to `bytes` not supported, elements must be integers from 0 to 255"
+                                .to_string(),
+                        )
+                    },
+                    Object::BYTES,
+                )
+            }
+            other => Object::error(format!(
+                "argument to `bytes` not supported, must be STRING, ARRAY or BYTES, got {}",
+                other.get_type()
+            )),
+        })
+    }
+
+    fn call_decode(args: Vec<Object>) -> Object {
+        Self::handle_number_of_arguments(args.len(), 1).unwrap_or_else(|| match &args[0] {
+            Object::BYTES(b) => match String::from_utf8(b.clone()) {
+                Ok(s) => Object::STRING(s.into()),
+                Err(error) => Object::error(format!("could not decode the bytes: {error}")),
+            },
+            other => Object::error(format!(
+                "argument to `decode` not supported, must be BYTES, got {}",
+                other.get_type()
+            )),
+        })
+    }
+
+    fn call_merge(args: Vec<Object>) -> Object {
+        Self::handle_number_of_arguments(args.len(), 2).unwrap_or_else(|| {
+            match (&args[0], &args[1]) {
+                (Object::HASHMAP(left), Object::HASHMAP(right)) => {
+                    #[allow(clippy::mutable_key_type)]
+                    let mut merged = left.clone();
+                    merged.extend(right.iter().map(|(k, v)| (k.clone(), v.clone())));
+                    Object::HASHMAP(merged)
+                }
+                (Object::HASHMAP(_), other) | (other, _) => Object::error(format!(
+                    "argument to `merge` not supported, must be HASHMAP, got {}",
+                    other.get_type()
+                )),
+            }
+        })
+    }
+
+    /// Returns an array built from the entries of a hashmap. Entries are
+    /// visited in the same order by every call, so `keys(h)[i]` is the key of
+    /// `values(h)[i]`.
+    fn call_hashmap_entries(
+        name: &str,
+        args: Vec<Object>,
+        f: impl Fn((&Object, &Object)) -> Object,
+    ) -> Object {
+        Self::handle_number_of_arguments(args.len(), 1).unwrap_or_else(|| match &args[0] {
+            Object::HASHMAP(h) => Object::ARRAY(h.iter().map(f).collect()),
+            other => Object::error(format!(
+                "argument to `{name}` not supported, must be HASHMAP, got {}",
+                other.get_type()
+            )),
+        })
+    }
+
+    fn call_delete(args: Vec<Object>) -> Object {
+        Self::handle_number_of_arguments(args.len(), 2).unwrap_or_else(|| match &args[0] {
+            Object::HASHMAP(_) if !args[1].is_hashable() => {
+                Object::error(format!("unusable as hash key: {}", args[1].get_type()))
+            }
+            Object::HASHMAP(h) => {
+                #[allow(clippy::mutable_key_type)]
+                let mut deleted = h.clone();
+                deleted.remove(&args[1]);
+                Object::HASHMAP(deleted)
+            }
+            other => Object::error(format!(
+                "argument to `delete` not supported, must be HASHMAP, got {}",
+                other.get_type()
+            )),
+        })
+    }
+
+    fn call_ord(args: Vec<Object>) -> Object {
+        Self::handle_number_of_arguments(args.len(), 1).unwrap_or_else(|| match &args[0] {
+            Object::STRING(s) => {
+                let mut chars = s.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Object::INTEGER(i64::from(u32::from(c))),
+                    _ => Object::error(format!(
+                        "argument to `ord` must be a single character, got \"{s}\""
+                    )),
+                }
+            }
+            _ => Object::error(format!(
+                "argument to `ord` not supported, must be STRING, got {}",
+                args[0].get_type()
+            )),
+        })
+    }
+
+    fn call_chr(args: Vec<Object>) -> Object {
+        Self::handle_number_of_arguments(args.len(), 1).unwrap_or_else(|| match &args[0] {
+            Object::INTEGER(i) => match u32::try_from(*i).ok().and_then(char::from_u32) {
+                Some(c) => Object::STRING(c.to_string().into()),
+                None => Object::error(format!("argument to `chr` is not a valid codepoint: {i}")),
+            },
+            _ => Object::error(format!(
+                "argument to `chr` not supported, must be INTEGER, got {}",
+                args[0].get_type()
+            )),
+        })
+    }
+
+    fn call_map(args: Vec<Object>, context: &mut dyn BuiltinContext) -> Object {
+        Self::handle_number_of_arguments(args.len(), 2).unwrap_or_else(|| match &args[0] {
+            Object::ARRAY(a) => {
+                let mut mapped = Vec::with_capacity(a.len());
+                for element in a {
+                    let result = context.apply(&args[1], vec![element.clone()]);
+                    if result.interrupts() {
+                        return result;
+                    }
+                    mapped.push(result);
+                }
+                Object::ARRAY(mapped)
+            }
+            _ => Object::error(format!(
+                "argument to `map` not supported, must be ARRAY, got {}",
+                args[0].get_type()
+            )),
+        })
+    }
+
+    fn call_filter(args: Vec<Object>, context: &mut dyn BuiltinContext) -> Object {
+        Self::handle_number_of_arguments(args.len(), 2).unwrap_or_else(|| match &args[0] {
+            Object::ARRAY(a) => {
+                let mut filtered = Vec::new();
+                for element in a {
+                    let result = context.apply(&args[1], vec![element.clone()]);
+                    if result.interrupts() {
+                        return result;
+                    }
+                    if result.is_truthy() {
+                        filtered.push(element.clone());
+                    }
+                }
+                Object::ARRAY(filtered)
+            }
+            _ => Object::error(format!(
+                "argument to `filter` not supported, must be ARRAY, got {}",
+                args[0].get_type()
+            )),
+        })
+    }
+
+    fn call_reduce(args: Vec<Object>, context: &mut dyn BuiltinContext) -> Object {
+        Self::handle_number_of_arguments(args.len(), 3).unwrap_or_else(|| match &args[0] {
+            Object::ARRAY(a) => {
+                let mut accumulated = args[1].clone();
+                for element in a {
+                    accumulated = context.apply(&args[2], vec![accumulated, element.clone()]);
+                    if accumulated.interrupts() {
+                        return accumulated;
+                    }
+                }
+                accumulated
+            }
+            _ => Object::error(format!(
+                "argument to `reduce` not supported, must be ARRAY, got {}",
+                args[0].get_type()
+            )),
+        })
+    }
+
+    fn call_sort(args: Vec<Object>, context: &mut dyn BuiltinContext) -> Object {
+        if let Some(error) = Self::handle_number_of_arguments_between(args.len(), 1, 2) {
+            return error;
+        }
+        let array = match &args[0] {
+            Object::ARRAY(a) => a.clone(),
+            other => {
+                return Object::error(format!(
+                    "argument to `sort` not supported, must be ARRAY, got {}",
+                    other.get_type()
+                ))
+            }
+        };
+
+        let sorted = match args.get(1) {
+            Some(comparator) => merge_sort(array, &mut |a, b| match context
+                .apply(comparator, vec![a.clone(), b.clone()])
+            {
+                result if result.interrupts() => Err(result),
+                result => Ok(result.is_truthy()),
+            }),
+            None => merge_sort(array, &mut |a, b| match (a, b) {
+                (Object::INTEGER(a), Object::INTEGER(b)) => Ok(a < b),
+                (Object::STRING(a), Object::STRING(b)) => Ok(a < b),
+                (Object::BOOLEAN(a), Object::BOOLEAN(b)) => Ok(a < b),
+                _ => Err(Object::error(format!(
+                    "`sort` cannot compare {} and {}, use a comparator",
+                    a.get_type(),
+                    b.get_type()
+                ))),
+            }),
+        };
+
+        sorted.map_or_else(|error| error, Object::ARRAY)
+    }
+
+    fn call_split(args: Vec<Object>) -> Object {
+        Self::handle_number_of_arguments(args.len(), 2).unwrap_or_else(|| {
+            match Self::string_arguments("split", &args) {
+                Ok(strings) => {
+                    let (string, separator) = (strings[0], strings[1]);
+                    let parts: Vec<Object> = if separator.is_empty() {
+                        string
+                            .chars()
+                            .map(|c| Object::STRING(c.to_string().into()))
+                            .collect()
+                    } else {
+                        string
+                            .split(separator)
+                            .map(|part| Object::STRING(part.into()))
+                            .collect()
+                    };
+                    Object::ARRAY(parts)
+                }
+                Err(error) => error,
+            }
+        })
+    }
+
+    fn call_join(args: Vec<Object>) -> Object {
+        Self::handle_number_of_arguments(args.len(), 2).unwrap_or_else(|| {
+            let separator = match &args[1] {
+                Object::STRING(s) => s,
+                other => return Self::string_argument_error("join", other),
+            };
+            match &args[0] {
+                Object::ARRAY(a) => {
+                    let mut parts = Vec::with_capacity(a.len());
+                    for element in a {
+                        match element {
+                            Object::STRING(s) => parts.push(&**s),
+                            other => {
+                                return Object::error(format!(
+                                    "argument to `join` not supported, must be ARRAY of STRING, got {} element",
+                                    other.get_type()
+                                ))
+                            }
+                        }
+                    }
+                    Object::STRING(parts.join(separator).into())
+                }
+                other => Object::error(format!(
+                    "argument to `join` not supported, must be ARRAY, got {}",
+                    other.get_type()
+                )),
+            }
+        })
+    }
+
+    fn call_replace(args: Vec<Object>) -> Object {
+        Self::handle_number_of_arguments(args.len(), 3).unwrap_or_else(|| {
+            match Self::string_arguments("replace", &args) {
+                Ok(strings) => Object::STRING(strings[0].replace(strings[1], strings[2]).into()),
+                Err(error) => error,
+            }
+        })
+    }
+
+    /// Calls a builtin taking a single string and returning a new one.
+    fn call_string_map(name: &str, args: Vec<Object>, f: impl Fn(&str) -> String) -> Object {
+        Self::handle_number_of_arguments(args.len(), 1).unwrap_or_else(|| {
+            match Self::string_arguments(name, &args) {
+                Ok(strings) => Object::STRING(f(strings[0]).into()),
+                Err(error) => error,
+            }
+        })
+    }
+
+    /// Calls a builtin taking two strings and returning a boolean.
+    fn call_string_predicate(
+        name: &str,
+        args: Vec<Object>,
+        f: impl Fn(&str, &str) -> bool,
+    ) -> Object {
+        Self::handle_number_of_arguments(args.len(), 2).unwrap_or_else(|| {
+            match Self::string_arguments(name, &args) {
+                Ok(strings) => Object::BOOLEAN(f(strings[0], strings[1])),
+                Err(error) => error,
+            }
+        })
+    }
+
+    fn call_contains(args: Vec<Object>) -> Object {
+        Self::handle_number_of_arguments(args.len(), 2).unwrap_or_else(|| match &args[0] {
+            Object::STRING(s) => match &args[1] {
+                Object::STRING(substring) => Object::BOOLEAN(s.contains(&**substring)),
+                other => Self::string_argument_error("contains", other),
+            },
+            Object::ARRAY(a) => Object::BOOLEAN(a.contains(&args[1])),
+            Object::HASHMAP(h) => Object::BOOLEAN(h.contains_key(&args[1])),
+            other => Object::error(format!(
+                "argument to `contains` not supported, must be STRING, ARRAY or HASHMAP, got {}",
+                other.get_type()
+            )),
+        })
+    }
+
+    /// Returns the index of the first occurrence of an element in an array, or
+    /// of a substring in a string (counted in characters), -1 if there is none.
+    fn call_index_of(args: Vec<Object>) -> Object {
+        Self::handle_number_of_arguments(args.len(), 2).unwrap_or_else(|| {
+            let index = match &args[0] {
+                Object::STRING(s) => match &args[1] {
+                    Object::STRING(substring) => {
+                        s.find(&**substring).map(|byte| s[..byte].chars().count())
+                    }
+                    other => return Self::string_argument_error("index_of", other),
+                },
+                Object::ARRAY(a) => a.iter().position(|element| *element == args[1]),
+                other => {
+                    return Object::error(format!(
+                        "argument to `index_of` not supported, must be STRING or ARRAY, got {}",
+                        other.get_type()
+                    ))
+                }
+            };
+            Object::INTEGER(index.map_or(-1, |index| index as i64))
+        })
+    }
+
+    fn call_reverse(args: Vec<Object>) -> Object {
+        Self::handle_number_of_arguments(args.len(), 1).unwrap_or_else(|| match &args[0] {
+            Object::STRING(s) => Object::STRING(s.chars().rev().collect::<String>().into()),
+            Object::ARRAY(a) => Object::ARRAY(a.iter().rev().cloned().collect()),
+            other => Object::error(format!(
+                "argument to `reverse` not supported, must be STRING or ARRAY, got {}",
+                other.get_type()
+            )),
+        })
+    }
+
+    /// Returns the elements of an array, or the characters of a string, from
+    /// `start` included to `end` excluded. Negative indexes count from the end
+    /// and indexes out of bounds are clamped, like in Python.
+    fn call_slice(args: Vec<Object>) -> Object {
+        if let Some(error) = Self::handle_number_of_arguments_between(args.len(), 2, 3) {
+            return error;
+        }
+        let bounds = match Self::integer_arguments("slice", &args[1..]) {
+            Ok(bounds) => bounds,
+            Err(error) => return error,
+        };
+        let range = |len: usize| {
+            let clamp = |index: i64| {
+                let index = if index < 0 { index + len as i64 } else { index };
+                index.clamp(0, len as i64) as usize
+            };
+            let start = clamp(bounds[0]);
+            let end = bounds.get(1).map_or(len, |end| clamp(*end));
+            start..end.max(start)
+        };
+
+        match &args[0] {
+            Object::STRING(s) => {
+                let chars: Vec<char> = s.chars().collect();
+                Object::STRING(chars[range(chars.len())].iter().collect::<String>().into())
+            }
+            Object::ARRAY(a) => Object::ARRAY(a[range(a.len())].to_vec()),
+            other => Object::error(format!(
+                "argument to `slice` not supported, must be STRING or ARRAY, got {}",
+                other.get_type()
+            )),
+        }
+    }
+
+    /// Returns the arguments as string slices, or an error for the first one
+    /// which is not a string.
+    fn string_arguments<'a>(name: &str, args: &'a [Object]) -> Result<Vec<&'a str>, Object> {
+        args.iter()
+            .map(|arg| match arg {
+                Object::STRING(s) => Ok(&**s),
+                other => Err(Self::string_argument_error(name, other)),
+            })
+            .collect()
+    }
+
+    fn string_argument_error(name: &str, arg: &Object) -> Object {
+        Object::error(format!(
+            "argument to `{name}` not supported, must be STRING, got {}",
+            arg.get_type()
+        ))
+    }
+
+    fn call_abs(args: Vec<Object>) -> Object {
+        Self::handle_number_of_arguments(args.len(), 1).unwrap_or_else(|| {
+            match Self::integer_arguments("abs", &args) {
+                Ok(integers) => integers[0]
+                    .checked_abs()
+                    .map_or_else(|| Self::overflow_error("abs"), Object::INTEGER),
+                Err(error) => error,
+            }
+        })
+    }
+
+    fn call_min_max(name: &str, args: Vec<Object>, f: fn(i64, i64) -> i64) -> Object {
+        Self::handle_number_of_arguments(args.len(), 2).unwrap_or_else(|| {
+            match Self::integer_arguments(name, &args) {
+                Ok(integers) => Object::INTEGER(f(integers[0], integers[1])),
+                Err(error) => error,
+            }
+        })
+    }
+
+    fn call_pow(args: Vec<Object>) -> Object {
+        Self::handle_number_of_arguments(args.len(), 2).unwrap_or_else(|| {
+            match Self::integer_arguments("pow", &args) {
+                Ok(integers) => match u32::try_from(integers[1]) {
+                    Ok(exponent) => integers[0]
+                        .checked_pow(exponent)
+                        .map_or_else(|| Self::overflow_error("pow"), Object::INTEGER),
+                    Err(_) => Object::error(format!(
+                        "exponent of `pow` must be a non negative integer, got {}",
+                        integers[1]
+                    )),
+                },
+                Err(error) => error,
+            }
+        })
+    }
+
+    fn call_sqrt(args: Vec<Object>) -> Object {
+        Self::handle_number_of_arguments(args.len(), 1).unwrap_or_else(|| {
+            match Self::integer_arguments("sqrt", &args) {
+                Ok(integers) if integers[0] < 0 => Object::error(format!(
+                    "argument to `sqrt` must be non negative, got {}",
+                    integers[0]
+                )),
+                // Integer square root, rounded down
+                Ok(integers) => Object::INTEGER(integers[0].isqrt()),
+                Err(error) => error,
+            }
+        })
+    }
+
+    fn call_rounding(name: &str, args: Vec<Object>) -> Object {
+        Self::handle_number_of_arguments(args.len(), 1).unwrap_or_else(|| {
+            match Self::integer_arguments(name, &args) {
+                Ok(integers) => Object::INTEGER(integers[0]),
+                Err(error) => error,
+            }
+        })
+    }
+
+    fn call_range(args: Vec<Object>) -> Object {
+        if let Some(error) = Self::handle_number_of_arguments_between(args.len(), 1, 3) {
+            return error;
+        }
+        let (start, end, step) = match Self::integer_arguments("range", &args).as_deref() {
+            Ok(&[end]) => (0, end, 1),
+            Ok(&[start, end]) => (start, end, 1),
+            Ok(&[start, end, step]) => (start, end, step),
+            Ok(_) => unreachable!("the number of arguments was checked"),
+            Err(error) => return error.clone(),
+        };
+        if step == 0 {
+            return Object::error("step of `range` must not be zero".to_string());
+        }
+
+        // Computed in i128 so that no combination of arguments overflows
+        let span = (i128::from(end) - i128::from(start)) * i128::from(step.signum());
+        let length = if span > 0 {
+            (span - 1) / i128::from(step).abs() + 1
+        } else {
+            0
+        };
+        if length > MAX_RANGE_LENGTH as i128 {
+            return Object::error(format!(
+                "`range` of {length} elements is too large, the limit is {MAX_RANGE_LENGTH}"
+            ));
+        }
+
+        // The length is within the limit, so none of the elements overflow
+        let elements = (0..length as i64)
+            .map(|i| Object::INTEGER(start + i * step))
+            .collect();
+        Object::ARRAY(elements)
+    }
+
+    fn call_type(args: Vec<Object>) -> Object {
+        Self::handle_number_of_arguments(args.len(), 1)
+            .unwrap_or_else(|| Object::STRING(args[0].get_type().into()))
+    }
+
+    fn call_int(args: Vec<Object>) -> Object {
+        Self::handle_number_of_arguments(args.len(), 1).unwrap_or_else(|| match &args[0] {
+            Object::INTEGER(i) => Object::INTEGER(*i),
+            Object::BOOLEAN(b) => Object::INTEGER(i64::from(*b)),
+            Object::STRING(s) => match s.parse::<i64>() {
+                Ok(i) => Object::INTEGER(i),
+                Err(_) => Object::error(format!("could not convert \"{s}\" to INTEGER")),
+            },
+            other => Object::error(format!(
+                "argument to `int` not supported, must be INTEGER, BOOLEAN or STRING, got {}",
+                other.get_type()
+            )),
+        })
+    }
+
+    fn call_str(args: Vec<Object>) -> Object {
+        Self::handle_number_of_arguments(args.len(), 1)
+            .unwrap_or_else(|| Object::STRING(args[0].to_string().into()))
+    }
+
+    fn call_format(args: Vec<Object>) -> Object {
+        match args.split_first() {
+            Some((Object::STRING(template), args)) => match format::format(template, args) {
+                Ok(formatted) => Object::STRING(formatted.into()),
+                Err(error) => Object::error(error),
+            },
+            Some((other, _)) => Object::error(format!(
+                "argument to `format` not supported, must be STRING, got {}",
+                other.get_type()
+            )),
+            None => Object::error("wrong number of arguments. got=0, want=1 or more".to_string()),
+        }
+    }
+
+    /// Returns the arguments as integers, or an error for the first one which
+    /// is not an integer.
+    fn integer_arguments(name: &str, args: &[Object]) -> Result<Vec<i64>, Object> {
+        args.iter()
+            .map(|arg| match arg {
+                Object::INTEGER(i) => Ok(*i),
+                other => Err(Object::error(format!(
+                    "argument to `{name}` not supported, must be INTEGER, got {}",
+                    other.get_type()
+                ))),
+            })
+            .collect()
+    }
+
+    fn overflow_error(name: &str) -> Object {
+        Object::error(format!("integer overflow in `{name}`"))
+    }
+
+    fn handle_number_of_arguments_between(got: usize, min: usize, max: usize) -> Option<Object> {
+        if got < min || got > max {
+            return Some(Object::error(format!(
+                "wrong number of arguments. got={got}, want={min} to {max}"
+            )));
+        }
+        None
+    }
+
     fn handle_number_of_arguments(got: usize, expected: usize) -> Option<Object> {
         if got != expected {
-            return Some(Object::ERROR(format!(
+            return Some(Object::error(format!(
                 "wrong number of arguments. got={got}, want={expected}"
             )));
         }
         None
     }
 }
+
+/// Stable merge sort where `less` tells if its first argument must be placed
+/// before the second one. Unlike `slice::sort_by`, it accepts inconsistent
+/// orders, which user defined comparators can be, and stops on the first error.
+fn merge_sort(
+    mut items: Vec<Object>,
+    less: &mut dyn FnMut(&Object, &Object) -> Result<bool, Object>,
+) -> Result<Vec<Object>, Object> {
+    if items.len() <= 1 {
+        return Ok(items);
+    }
+
+    let right = items.split_off(items.len() / 2);
+    let left = merge_sort(items, less)?;
+    let right = merge_sort(right, less)?;
+
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+        // Taking from the left on ties keeps the sort stable
+        if less(r, l)? {
+            merged.extend(right.next());
+        } else {
+            merged.extend(left.next());
+        }
+    }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
+}
@@ -0,0 +1,99 @@
+use std::rc::Rc;
+
+use crate::object::{Object, FALSE, NULL, TRUE};
+
+/// Smallest integer allocated once and shared.
+pub const MIN_CACHED_INTEGER: i64 = -128;
+/// Largest integer allocated once and shared.
+pub const MAX_CACHED_INTEGER: i64 = 256;
+
+/// Objects allocated once per thread and shared by every `Rc` holding them:
+/// the small integers, the booleans and null, which loops compute the most.
+struct Cache {
+    integers: Vec<Rc<Object>>,
+    true_object: Rc<Object>,
+    false_object: Rc<Object>,
+    null: Rc<Object>,
+}
+
+impl Cache {
+    fn new() -> Self {
+        Self {
+            integers: (MIN_CACHED_INTEGER..=MAX_CACHED_INTEGER)
+                .map(|i| Rc::new(Object::INTEGER(i)))
+                .collect(),
+            true_object: Rc::new(TRUE),
+            false_object: Rc::new(FALSE),
+            null: Rc::new(NULL),
+        }
+    }
+}
+
+thread_local! {
+    static CACHE: Cache = Cache::new();
+}
+
+/// Returns `object` in an `Rc`, shared instead of allocated for the small
+/// integers, the booleans and null.
+pub fn share(object: Object) -> Rc<Object> {
+    match object {
+        Object::INTEGER(i) if (MIN_CACHED_INTEGER..=MAX_CACHED_INTEGER).contains(&i) => {
+            CACHE.with(|cache| Rc::clone(&cache.integers[(i - MIN_CACHED_INTEGER) as usize]))
+        }
+        Object::BOOLEAN(b) => boolean(b),
+        Object::NULL => null(),
+        object => Rc::new(object),
+    }
+}
+
+/// Returns the shared `true` or `false`.
+pub fn boolean(value: bool) -> Rc<Object> {
+    CACHE.with(|cache| {
+        Rc::clone(if value {
+            &cache.true_object
+        } else {
+            &cache.false_object
+        })
+    })
+}
+
+/// Returns the shared `null`.
+pub fn null() -> Rc<Object> {
+    CACHE.with(|cache| Rc::clone(&cache.null))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_small_objects_are_shared() {
+        for object in [
+            Object::INTEGER(MIN_CACHED_INTEGER),
+            Object::INTEGER(0),
+            Object::INTEGER(MAX_CACHED_INTEGER),
+            TRUE,
+            FALSE,
+            NULL,
+        ] {
+            let shared = share(object.clone());
+            assert_eq!(*shared, object);
+            assert!(Rc::ptr_eq(&shared, &share(object)));
+        }
+        assert!(Rc::ptr_eq(&boolean(true), &share(TRUE)));
+        assert!(Rc::ptr_eq(&null(), &share(NULL)));
+    }
+
+    #[test]
+    fn test_other_objects_are_allocated() {
+        for object in [
+            Object::INTEGER(MIN_CACHED_INTEGER - 1),
+            Object::INTEGER(MAX_CACHED_INTEGER + 1),
+            Object::STRING("monkey".into()),
+        ] {
+            let shared = share(object.clone());
+            assert_eq!(*shared, object);
+            assert!(!Rc::ptr_eq(&shared, &share(object)));
+        }
+    }
+}
@@ -0,0 +1,386 @@
+use std::fmt::Write;
+
+const SECONDS_PER_DAY: i64 = 86400;
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+const WEEKDAYS: [&str; 7] = [
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+    "Sunday",
+];
+
+/// A date and a time of day in UTC, as used by the date builtins.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DateTime {
+    pub year: i64,
+    /// From 1 (January) to 12.
+    pub month: i64,
+    pub day: i64,
+    pub hour: i64,
+    pub minute: i64,
+    pub second: i64,
+}
+
+impl DateTime {
+    /// Returns the date of a Unix timestamp, in seconds.
+    pub fn from_timestamp(timestamp: i64) -> Self {
+        let days = timestamp.div_euclid(SECONDS_PER_DAY);
+        let seconds = timestamp.rem_euclid(SECONDS_PER_DAY);
+        let (year, month, day) = civil_from_days(days);
+        DateTime {
+            year,
+            month,
+            day,
+            hour: seconds / 3600,
+            minute: seconds % 3600 / 60,
+            second: seconds % 60,
+        }
+    }
+
+    /// Returns the Unix timestamp of the date, or `None` if it does not fit
+    /// in an integer.
+    ///
+    /// Fields out of their usual range are carried over to the next ones, so
+    /// the 32nd of January is the 1st of February and the 13th month is the
+    /// January of the next year. This is what makes date arithmetic simple.
+    pub fn to_timestamp(&self) -> Option<i64> {
+        let month = i128::from(self.month) - 1;
+        let year = i128::from(self.year) + month.div_euclid(12);
+        let month = month.rem_euclid(12) + 1;
+        // Years this large overflow the timestamp anyway
+        if year.abs() > i128::from(i64::MAX / SECONDS_PER_DAY / 365) {
+            return None;
+        }
+        let days = days_from_civil(year as i64, month as i64, 1) + i128::from(self.day) - 1;
+        let seconds = days * i128::from(SECONDS_PER_DAY)
+            + i128::from(self.hour) * 3600
+            + i128::from(self.minute) * 60
+            + i128::from(self.second);
+        i64::try_from(seconds).ok()
+    }
+
+    /// Returns the day of the week, from 1 (Monday) to 7 (Sunday).
+    pub fn weekday(&self) -> i64 {
+        // The 1st of January 1970 was a Thursday
+        let days = days_from_civil(self.year, self.month, self.day);
+        (days + 3).rem_euclid(7) as i64 + 1
+    }
+
+    /// Returns the day of the year, from 1 to 366.
+    pub fn day_of_year(&self) -> i64 {
+        (days_from_civil(self.year, self.month, self.day) - days_from_civil(self.year, 1, 1)) as i64
+            + 1
+    }
+}
+
+/// Formats a Unix timestamp the way the `format_time` builtin does.
+///
+/// The format supports the `%Y`, `%m`, `%d`, `%H`, `%M`, `%S`, `%j`, `%a`,
+/// `%A`, `%b` and `%B` specifiers of `strftime`, and `%%` for a literal `%`.
+///
+/// # Errors
+///
+/// If `format` has an unknown specifier.
+pub fn format_time(timestamp: i64, format: &str) -> Result<String, String> {
+    let date = DateTime::from_timestamp(timestamp);
+    let mut output = String::new();
+    let mut chars = format.chars();
+
+    while let Some(ch) = chars.next() {
+        if ch != '%' {
+            output.push(ch);
+            continue;
+        }
+        // Writing to a `String` does not fail
+        let _ = match chars.next() {
+            Some('Y') => write!(output, "{:04}", date.year),
+            Some('m') => write!(output, "{:02}", date.month),
+            Some('d') => write!(output, "{:02}", date.day),
+            Some('H') => write!(output, "{:02}", date.hour),
+            Some('M') => write!(output, "{:02}", date.minute),
+            Some('S') => write!(output, "{:02}", date.second),
+            Some('j') => write!(output, "{:03}", date.day_of_year()),
+            Some('a') => output.write_str(&WEEKDAYS[date.weekday() as usize - 1][..3]),
+            Some('A') => output.write_str(WEEKDAYS[date.weekday() as usize - 1]),
+            Some('b') => output.write_str(&MONTHS[date.month as usize - 1][..3]),
+            Some('B') => output.write_str(MONTHS[date.month as usize - 1]),
+            Some('%') => output.write_char('%'),
+            other => return Err(specifier_error(other)),
+        };
+    }
+
+    Ok(output)
+}
+
+/// Parses a time written with a `format_time` format and returns its Unix
+/// timestamp. The fields missing from the format default to the 1st of
+/// January 1970 at midnight. `%j` is not supported.
+///
+/// # Errors
+///
+/// If `format` has an unknown specifier, or if `input` does not match it.
+pub fn parse_time(input: &str, format: &str) -> Result<i64, String> {
+    let error = || format!("could not parse `{input}` as a time with the format `{format}`");
+
+    let mut date = DateTime::from_timestamp(0);
+    let mut rest = input;
+    let mut chars = format.chars();
+
+    while let Some(ch) = chars.next() {
+        if ch != '%' {
+            rest = rest.strip_prefix(ch).ok_or_else(error)?;
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => {
+                let negative = rest.starts_with('-');
+                let (year, remaining) =
+                    parse_number(&rest[usize::from(negative)..], 18).ok_or_else(error)?;
+                date.year = if negative { -year } else { year };
+                rest = remaining;
+            }
+            Some(specifier @ ('m' | 'd' | 'H' | 'M' | 'S')) => {
+                let (value, remaining) = parse_number(rest, 2).ok_or_else(error)?;
+                match specifier {
+                    'm' => date.month = value,
+                    'd' => date.day = value,
+                    'H' => date.hour = value,
+                    'M' => date.minute = value,
+                    _ => date.second = value,
+                }
+                rest = remaining;
+            }
+            Some(specifier @ ('a' | 'A' | 'b' | 'B')) => {
+                let names: &[&str] = if specifier == 'a' || specifier == 'A' {
+                    &WEEKDAYS
+                } else {
+                    &MONTHS
+                };
+                let abbreviated = specifier.is_lowercase();
+                let (index, remaining) = names
+                    .iter()
+                    .enumerate()
+                    .find_map(|(i, name)| {
+                        let name = if abbreviated { &name[..3] } else { name };
+                        let prefix = rest.get(..name.len())?;
+                        prefix
+                            .eq_ignore_ascii_case(name)
+                            .then(|| (i as i64 + 1, &rest[name.len()..]))
+                    })
+                    .ok_or_else(error)?;
+                // The weekday follows from the date, it is only checked for
+                // its spelling
+                if names.len() == MONTHS.len() {
+                    date.month = index;
+                }
+                rest = remaining;
+            }
+            Some('%') => rest = rest.strip_prefix('%').ok_or_else(error)?,
+            other => return Err(specifier_error(other)),
+        }
+    }
+
+    let valid = rest.is_empty()
+        && (1..=12).contains(&date.month)
+        && (1..=days_in_month(date.year, date.month)).contains(&date.day)
+        && (0..24).contains(&date.hour)
+        && (0..60).contains(&date.minute)
+        && (0..60).contains(&date.second);
+    if !valid {
+        return Err(error());
+    }
+    date.to_timestamp().ok_or_else(error)
+}
+
+fn specifier_error(specifier: Option<char>) -> String {
+    let specifier = specifier.map(String::from).unwrap_or_default();
+    format!("invalid time format specifier: %{specifier}")
+}
+
+/// Parses up to `max_digits` digits at the start of `input`.
+fn parse_number(input: &str, max_digits: usize) -> Option<(i64, &str)> {
+    let digits = input
+        .bytes()
+        .take(max_digits)
+        .take_while(u8::is_ascii_digit)
+        .count();
+    let value = input[..digits].parse().ok()?;
+    Some((value, &input[digits..]))
+}
+
+fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// The two conversions below are the algorithms of Howard Hinnant, see
+// https://howardhinnant.github.io/date_algorithms.html
+
+/// Returns the number of days between the 1st of January 1970 and a date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i128 {
+    let year = i128::from(if month <= 2 { year - 1 } else { year });
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_index = i128::from((month + 9) % 12);
+    let day_of_year = (153 * month_index + 2) / 5 + i128::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Returns the year, month and day of a number of days since the 1st of
+/// January 1970.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_timestamp() {
+        let tests = vec![
+            (0, (1970, 1, 1, 0, 0, 0)),
+            (951782400, (2000, 2, 29, 0, 0, 0)),
+            (1700000000, (2023, 11, 14, 22, 13, 20)),
+            (-1, (1969, 12, 31, 23, 59, 59)),
+        ];
+
+        for (timestamp, (year, month, day, hour, minute, second)) in tests {
+            let date = DateTime {
+                year,
+                month,
+                day,
+                hour,
+                minute,
+                second,
+            };
+            assert_eq!(DateTime::from_timestamp(timestamp), date);
+            assert_eq!(date.to_timestamp(), Some(timestamp));
+        }
+    }
+
+    #[test]
+    fn test_to_timestamp_carries_over() {
+        let date = |year, month, day| DateTime {
+            year,
+            month,
+            day,
+            hour: 0,
+            minute: 0,
+            second: 0,
+        };
+
+        assert_eq!(
+            date(2023, 1, 32).to_timestamp(),
+            date(2023, 2, 1).to_timestamp()
+        );
+        assert_eq!(
+            date(2023, 13, 1).to_timestamp(),
+            date(2024, 1, 1).to_timestamp()
+        );
+        assert_eq!(
+            date(2023, 3, 0).to_timestamp(),
+            date(2023, 2, 28).to_timestamp()
+        );
+        assert_eq!(
+            date(2023, 0, 1).to_timestamp(),
+            date(2022, 12, 1).to_timestamp()
+        );
+        assert_eq!(date(i64::MAX, 1, 1).to_timestamp(), None);
+    }
+
+    #[test]
+    fn test_format_time() {
+        let tests = vec![
+            ("%Y-%m-%d %H:%M:%S", "2023-11-14 22:13:20"),
+            ("%a %d %b %Y", "Tue 14 Nov 2023"),
+            ("%A, %B %d", "Tuesday, November 14"),
+            ("day %j, 100%%", "day 318, 100%"),
+        ];
+
+        for (format, expected) in tests {
+            assert_eq!(format_time(1700000000, format), Ok(expected.to_string()));
+        }
+        assert_eq!(
+            format_time(0, "%Y %q"),
+            Err("invalid time format specifier: %q".to_string())
+        );
+        assert_eq!(
+            format_time(0, "100%"),
+            Err("invalid time format specifier: %".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_time() {
+        let tests = vec![
+            ("2023-11-14 22:13:20", "%Y-%m-%d %H:%M:%S", 1700000000),
+            (
+                "Tue 14 nov 2023 22:13:20",
+                "%a %d %b %Y %H:%M:%S",
+                1700000000,
+            ),
+            ("14 November 2023", "%d %B %Y", 1699920000),
+            ("1:2", "%H:%M", 3720),
+        ];
+
+        for (input, format, expected) in tests {
+            assert_eq!(parse_time(input, format), Ok(expected), "{input}");
+        }
+
+        for (input, format) in [
+            ("2023-02-30", "%Y-%m-%d"),
+            ("2023-11-14 ", "%Y-%m-%d"),
+            ("2023/11/14", "%Y-%m-%d"),
+            ("25:00", "%H:%M"),
+        ] {
+            assert_eq!(
+                parse_time(input, format),
+                Err(format!(
+                    "could not parse `{input}` as a time with the format `{format}`"
+                ))
+            );
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use std::time::{Duration, Instant};
+
+/// Number of steps between two readings of the clock, which is much slower
+/// than running an instruction.
+const CHECK_INTERVAL: u32 = 1024;
+
+/// Time limit of a program, checked cooperatively by the evaluator and the VM
+/// as they run it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Deadline {
+    at: Instant,
+    timeout: Duration,
+    steps: u32,
+}
+
+impl Deadline {
+    /// Returns a deadline `timeout` from now.
+    pub fn after(timeout: Duration) -> Self {
+        Self {
+            at: Instant::now() + timeout,
+            timeout,
+            steps: 0,
+        }
+    }
+
+    /// Counts one step of the program, and fails if the deadline has passed.
+    /// The clock is only read every few steps, but every step fails once the
+    /// deadline has been seen passing.
+    ///
+    /// # Errors
+    ///
+    /// Once the deadline has passed.
+    pub fn check(&mut self) -> Result<(), String> {
+        self.steps += 1;
+        if self.steps < CHECK_INTERVAL {
+            return Ok(());
+        }
+        if Instant::now() < self.at {
+            self.steps = 0;
+            Ok(())
+        } else {
+            self.steps = CHECK_INTERVAL;
+            Err(format!(
+                "Timeout: the program ran for more than {:?}",
+                self.timeout
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deadline() {
+        let mut deadline = Deadline::after(Duration::from_secs(60));
+        for _ in 0..10 * CHECK_INTERVAL {
+            assert_eq!(deadline.check(), Ok(()));
+        }
+
+        let error = Err("Timeout: the program ran for more than 0ns".to_string());
+        let mut deadline = Deadline::after(Duration::ZERO);
+        for _ in 1..CHECK_INTERVAL {
+            assert_eq!(deadline.check(), Ok(()));
+        }
+        assert_eq!(deadline.check(), error);
+        assert_eq!(deadline.check(), error);
+    }
+}
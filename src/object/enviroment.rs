@@ -1,10 +1,14 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    rc::Rc,
+};
 
 use crate::object::Object;
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Environment {
-    store: HashMap<String, Object>,
+    store: HashMap<Rc<str>, Object>,
     outer: Option<Rc<RefCell<Environment>>>,
 }
 
@@ -39,7 +43,62 @@ impl Environment {
         }
     }
 
-    pub fn set(&mut self, name: String, value: Object) {
-        self.store.insert(name, value);
+    pub fn set(&mut self, name: impl Into<Rc<str>>, value: Object) {
+        self.store.insert(name.into(), value);
+    }
+
+    /// Iterates over the bindings defined in this scope, ignoring the outer ones.
+    pub fn iter(&self) -> impl Iterator<Item = (&Rc<str>, &Object)> {
+        self.store.iter()
+    }
+
+    /// Returns the bindings visible from this scope, including the ones of the
+    /// outer scopes which are not shadowed, sorted by name.
+    pub fn bindings(&self) -> Vec<(String, Object)> {
+        let mut bindings = BTreeMap::new();
+        self.collect_bindings(&mut bindings);
+        bindings.into_iter().collect()
+    }
+
+    fn collect_bindings(&self, bindings: &mut BTreeMap<String, Object>) {
+        for (name, value) in &self.store {
+            bindings
+                .entry(name.to_string())
+                .or_insert_with(|| value.clone());
+        }
+        if let Some(outer) = &self.outer {
+            outer.borrow().collect_bindings(bindings);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bindings() {
+        let outer = Rc::new(RefCell::new(Environment::new()));
+        outer.borrow_mut().set("b".to_string(), Object::INTEGER(1));
+        outer.borrow_mut().set("a".to_string(), Object::INTEGER(2));
+        let mut inner = Environment::new_enclosed_environment(Rc::clone(&outer));
+        inner.set("b".to_string(), Object::BOOLEAN(true));
+        inner.set("c".to_string(), Object::NULL);
+
+        assert_eq!(
+            inner.bindings(),
+            vec![
+                ("a".to_string(), Object::INTEGER(2)),
+                ("b".to_string(), Object::BOOLEAN(true)),
+                ("c".to_string(), Object::NULL),
+            ]
+        );
+        assert_eq!(
+            outer.borrow().bindings(),
+            vec![
+                ("a".to_string(), Object::INTEGER(2)),
+                ("b".to_string(), Object::INTEGER(1)),
+            ]
+        );
     }
 }
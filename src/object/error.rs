@@ -0,0 +1,83 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::source::Position;
+
+/// What went wrong in an [`ErrorObject`], for the programs handling errors
+/// without parsing their message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ErrorKind {
+    /// An operator, an index or a builtin was given values of the wrong type.
+    TypeMismatch,
+    /// An operator which does not exist for the types of its operands.
+    UnknownOperator,
+    UndefinedIdentifier,
+    /// An index out of the elements of an array.
+    IndexOutOfBounds,
+    /// Any other error, like the ones raised by builtins.
+    Custom,
+}
+
+/// Error raised by a Monkey program, held by [`Object::ERROR`].
+///
+/// It is displayed as its message only, like the string errors it replaces.
+///
+/// [`Object::ERROR`]: crate::object::Object::ERROR
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ErrorObject {
+    pub kind: ErrorKind,
+    pub message: String,
+    /// Position of the statement which raised the error, if it is known.
+    pub position: Option<Position>,
+}
+
+impl ErrorObject {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            position: None,
+        }
+    }
+
+    pub fn custom(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Custom, message)
+    }
+}
+
+impl Display for ErrorObject {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl From<String> for ErrorObject {
+    fn from(message: String) -> Self {
+        Self::custom(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::Object;
+
+    #[test]
+    fn test_display() {
+        let error = ErrorObject {
+            kind: ErrorKind::UndefinedIdentifier,
+            message: "identifier not found: x".to_string(),
+            position: Some(Position { line: 2, column: 1 }),
+        };
+        assert_eq!(error.to_string(), "identifier not found: x");
+        assert_eq!(
+            Object::ERROR(error).to_string(),
+            "ERROR: identifier not found: x"
+        );
+        assert_eq!(
+            Object::error("boom"),
+            Object::ERROR(ErrorObject::custom("boom"))
+        );
+    }
+}
@@ -0,0 +1,216 @@
+use crate::object::Object;
+
+/// Alignment of a value padded to a width.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+/// A `{}` placeholder, like `{}`, `{1}`, `{:>8}` or `{0:*^5}`.
+#[derive(Debug, PartialEq)]
+struct Placeholder {
+    index: Option<usize>,
+    fill: char,
+    align: Option<Align>,
+    zero: bool,
+    width: usize,
+}
+
+/// Formats `template` the way the `format` builtin does, replacing every
+/// placeholder by the display form of an argument.
+///
+/// Placeholders without an index take the arguments in order. A placeholder
+/// can be followed by a width, with an optional fill character and alignment
+/// (`<`, `>` or `^`), or a `0` to pad integers with zeros. `{{` and `}}` are
+/// literal braces. Every argument must be used.
+///
+/// # Errors
+///
+/// If a placeholder is malformed or refers to a missing argument, or if an
+/// argument is not used.
+pub fn format(template: &str, args: &[Object]) -> Result<String, String> {
+    let mut output = String::new();
+    let mut used = vec![false; args.len()];
+    let mut next = 0;
+    let mut chars = template.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                output.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                output.push('}');
+            }
+            '{' => {
+                let mut spec = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(ch) => spec.push(ch),
+                        None => return Err("unmatched `{` in format string".to_string()),
+                    }
+                }
+                let placeholder = parse_placeholder(&spec)?;
+                let index = placeholder.index.unwrap_or_else(|| {
+                    next += 1;
+                    next - 1
+                });
+                let arg = args.get(index).ok_or_else(|| {
+                    format!(
+                        "format argument {index} is missing, got {} arguments",
+                        args.len()
+                    )
+                })?;
+                used[index] = true;
+                pad(&mut output, arg, &placeholder);
+            }
+            '}' => return Err("unmatched `}` in format string".to_string()),
+            ch => output.push(ch),
+        }
+    }
+
+    match used.iter().position(|used| !used) {
+        Some(index) => Err(format!("format argument {index} is never used")),
+        None => Ok(output),
+    }
+}
+
+fn parse_placeholder(spec: &str) -> Result<Placeholder, String> {
+    let invalid = || format!("invalid format placeholder: {{{spec}}}");
+
+    let (index, format) = spec.split_once(':').unwrap_or((spec, ""));
+    let index = if index.is_empty() {
+        None
+    } else {
+        Some(index.parse().map_err(|_| invalid())?)
+    };
+
+    let mut placeholder = Placeholder {
+        index,
+        fill: ' ',
+        align: None,
+        zero: false,
+        width: 0,
+    };
+
+    let chars: Vec<char> = format.chars().collect();
+    let mut rest = &chars[..];
+    match rest {
+        [fill, align, ..] if parse_align(*align).is_some() => {
+            placeholder.fill = *fill;
+            placeholder.align = parse_align(*align);
+            rest = &rest[2..];
+        }
+        [align, ..] if parse_align(*align).is_some() => {
+            placeholder.align = parse_align(*align);
+            rest = &rest[1..];
+        }
+        _ => {}
+    }
+    if let ['0', ..] = rest {
+        placeholder.zero = true;
+        rest = &rest[1..];
+    }
+    if !rest.is_empty() {
+        let width: String = rest.iter().collect();
+        placeholder.width = width.parse().map_err(|_| invalid())?;
+    }
+
+    Ok(placeholder)
+}
+
+fn parse_align(ch: char) -> Option<Align> {
+    match ch {
+        '<' => Some(Align::Left),
+        '>' => Some(Align::Right),
+        '^' => Some(Align::Center),
+        _ => None,
+    }
+}
+
+fn pad(output: &mut String, arg: &Object, placeholder: &Placeholder) {
+    let text = arg.to_string();
+    let padding = placeholder.width.saturating_sub(text.chars().count());
+
+    // Zeros go after the sign of integers
+    if let (true, Object::INTEGER(i)) = (placeholder.zero, arg) {
+        if *i < 0 {
+            output.push('-');
+        }
+        output.extend(std::iter::repeat_n('0', padding));
+        output.push_str(&i.unsigned_abs().to_string());
+        return;
+    }
+
+    // Like in Rust, numbers are aligned to the right and the rest to the left
+    let align = placeholder.align.unwrap_or(match arg {
+        Object::INTEGER(_) => Align::Right,
+        _ => Align::Left,
+    });
+    let (before, after) = match align {
+        Align::Left => (0, padding),
+        Align::Right => (padding, 0),
+        Align::Center => (padding / 2, padding - padding / 2),
+    };
+    output.extend(std::iter::repeat_n(placeholder.fill, before));
+    output.push_str(&text);
+    output.extend(std::iter::repeat_n(placeholder.fill, after));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args() -> Vec<Object> {
+        vec![
+            Object::INTEGER(-42),
+            Object::STRING("monkey".into()),
+            Object::ARRAY(vec![Object::BOOLEAN(true), Object::STRING("a".into())]),
+        ]
+    }
+
+    #[test]
+    fn test_format() {
+        let tests = vec![
+            ("{} {} {}", "-42 monkey [true, \"a\"]"),
+            ("{1} {0} {1} {2}", "monkey -42 monkey [true, \"a\"]"),
+            ("[{:6}] [{:8}] {2}", "[   -42] [monkey  ] [true, \"a\"]"),
+            ("[{:<6}] [{:>8}] {2}", "[-42   ] [  monkey] [true, \"a\"]"),
+            (
+                "[{:*^7}] [{:-^9}] {2}",
+                "[**-42**] [-monkey--] [true, \"a\"]",
+            ),
+            ("[{:06}] {} {}", "[-00042] monkey [true, \"a\"]"),
+            ("{{{}}} {} {}", "{-42} monkey [true, \"a\"]"),
+            ("[{:2}] {} {}", "[-42] monkey [true, \"a\"]"),
+        ];
+
+        for (template, expected) in tests {
+            assert_eq!(format(template, &args()), Ok(expected.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_format_errors() {
+        let tests = vec![
+            ("{} {", "unmatched `{` in format string"),
+            ("} {} {} {}", "unmatched `}` in format string"),
+            (
+                "{} {} {} {}",
+                "format argument 3 is missing, got 3 arguments",
+            ),
+            ("{} {}", "format argument 2 is never used"),
+            ("{:x} {} {}", "invalid format placeholder: {:x}"),
+            ("{a} {} {}", "invalid format placeholder: {a}"),
+        ];
+
+        for (template, expected) in tests {
+            assert_eq!(format(template, &args()), Err(expected.to_string()));
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+/// Time after which a silent server is given up on.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Sends a blocking GET request and returns the body of the response, for the
+/// `fetch` builtin.
+///
+/// Only plain `http://` URLs are supported. The request uses HTTP/1.0, so the
+/// server closes the connection at the end of the body and never chunks it.
+/// Statuses other than 2xx are errors.
+pub fn fetch(url: &str) -> Result<String, String> {
+    let error = |reason: String| format!("could not fetch `{url}`: {reason}");
+
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| error("only http:// URLs are supported".to_string()))?;
+    let (authority, path) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (
+            host,
+            port.parse::<u16>()
+                .map_err(|_| error(format!("invalid port {port}")))?,
+        ),
+        None => (authority, 80),
+    };
+    if host.is_empty() {
+        return Err(error("missing host".to_string()));
+    }
+
+    let mut stream = TcpStream::connect((host, port)).map_err(|e| error(e.to_string()))?;
+    stream
+        .set_read_timeout(Some(TIMEOUT))
+        .map_err(|e| error(e.to_string()))?;
+    let request =
+        format!("GET {path} HTTP/1.0\r\nHost: {authority}\r\nUser-Agent: chimpanzee\r\n\r\n");
+    stream
+        .write_all(request.as_bytes())
+        .map_err(|e| error(e.to_string()))?;
+
+    let mut response = Vec::new();
+    stream
+        .read_to_end(&mut response)
+        .map_err(|e| error(e.to_string()))?;
+
+    parse_response(&response).map_err(error)
+}
+
+/// Returns the body of a raw HTTP response if its status is a success.
+fn parse_response(response: &[u8]) -> Result<String, String> {
+    let invalid = || "invalid response".to_string();
+
+    let header_end = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(invalid)?;
+    let head = std::str::from_utf8(&response[..header_end]).map_err(|_| invalid())?;
+    let status_line = head.lines().next().ok_or_else(invalid)?;
+    // The status line looks like `HTTP/1.1 200 OK`
+    let status = status_line.split_once(' ').ok_or_else(invalid)?.1;
+    if !status.starts_with('2') {
+        return Err(format!("status {status}"));
+    }
+
+    String::from_utf8(response[header_end + 4..].to_vec())
+        .map_err(|_| "the body is not valid UTF-8".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, thread};
+
+    use super::*;
+
+    /// Serves a single request with `response` and returns the URL to fetch.
+    fn serve(response: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request).unwrap();
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+        format!("http://{address}/path")
+    }
+
+    #[test]
+    fn test_fetch() {
+        let url = serve("HTTP/1.0 200 OK\r\nContent-Type: text/plain\r\n\r\nhello\r\nmonkey");
+        assert_eq!(fetch(&url), Ok("hello\r\nmonkey".to_string()));
+    }
+
+    #[test]
+    fn test_fetch_errors() {
+        let url = serve("HTTP/1.0 404 Not Found\r\n\r\nnothing here");
+        assert_eq!(
+            fetch(&url),
+            Err(format!("could not fetch `{url}`: status 404 Not Found"))
+        );
+
+        assert_eq!(
+            fetch("https://example.com"),
+            Err(
+                "could not fetch `https://example.com`: only http:// URLs are supported"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            fetch("http://localhost:port/"),
+            Err("could not fetch `http://localhost:port/`: invalid port port".to_string())
+        );
+    }
+}
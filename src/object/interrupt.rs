@@ -0,0 +1,49 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// Error of a program stopped by [`Interrupt::interrupt`].
+pub const INTERRUPTED: &str = "Interrupted";
+
+/// Flag stopping a running program when it is set from elsewhere, such as a
+/// Ctrl-C handler or another thread. The evaluator and the VM check it as they
+/// run, and fail at every step once it is set, until it is cleared.
+#[derive(Debug, Clone, Default)]
+pub struct Interrupt {
+    flag: Arc<AtomicBool>,
+}
+
+impl Interrupt {
+    /// Stops the programs checking the flag.
+    pub fn interrupt(&self) {
+        self.flag.store(true, Ordering::Relaxed);
+    }
+
+    /// Lets the programs run again, before running the next one.
+    pub fn clear(&self) {
+        self.flag.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_interrupted(&self) -> bool {
+        self.flag.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interrupt() {
+        let interrupt = Interrupt::default();
+        let handle = interrupt.clone();
+        assert!(!interrupt.is_interrupted());
+        std::thread::spawn(move || handle.interrupt())
+            .join()
+            .unwrap();
+        assert!(interrupt.is_interrupted());
+        interrupt.clear();
+        assert!(!interrupt.is_interrupted());
+    }
+}
@@ -1,5 +1,15 @@
 pub mod builtins;
+pub mod cache;
+pub mod date;
+pub mod deadline;
 pub mod enviroment;
+pub mod error;
+pub mod format;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod interrupt;
+pub mod random;
+pub mod style;
 pub mod test_utils;
 
 use std::{
@@ -11,72 +21,121 @@ use std::{
 };
 
 use crate::parser::ast::{BlockStatement, Identifier};
+use crate::source::{Position, SourceMap};
 
-use crate::object::{builtins::BuiltinFunction, enviroment::Environment};
+use crate::object::{
+    builtins::BuiltinFunction,
+    enviroment::Environment,
+    error::{ErrorKind, ErrorObject},
+    style::OutputStyle,
+};
 
 pub const TRUE: Object = Object::BOOLEAN(true);
 pub const FALSE: Object = Object::BOOLEAN(false);
 pub const NULL: Object = Object::NULL;
 
+/// Number of nested function calls the evaluator and the VM allow, unless it
+/// is set with their `set_max_depth`.
+pub const DEFAULT_MAX_DEPTH: usize = 1024;
+
+/// Error of a program which used all the fuel given to the evaluator or to the
+/// VM with their `set_fuel`.
+pub const FUEL_EXHAUSTED: &str = "Fuel exhausted";
+
+/// Error of a call going deeper than `max_depth`, to the function `name`.
+pub fn max_depth_error(max_depth: usize, name: Option<&str>) -> String {
+    let function = match name {
+        Some(name) => format!("`{name}`"),
+        None => "an anonymous function".to_string(),
+    };
+    format!("Maximum call depth of {max_depth} exceeded when calling {function}")
+}
+
+/// A function call which was running when a program failed, as returned by
+/// the `stack_trace` of the evaluator and of the VM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StackFrame {
+    /// Name of the function, `main` for the program itself and `<anonymous>`
+    /// for the functions without a name.
+    pub function: String,
+
+    /// Position of the statement running in the function, if it is known.
+    pub position: Option<Position>,
+}
+
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Object {
     INTEGER(i64),
     BOOLEAN(bool),
-    STRING(String),
+    /// The text is shared by the clones of the string, which copy no bytes.
+    STRING(Rc<str>),
+    BYTES(Vec<u8>),
     RETURN(Box<Object>),
-    ERROR(String),
+    /// Raised by `break` and `continue` in the evaluator, up to the loop
+    /// running them, like `RETURN` up to the function.
+    BREAK,
+    CONTINUE,
+    ERROR(ErrorObject),
     FUNCTION(Function),
-    COMPILEDFUNCTION(CompiledFunction),
+    COMPILEDFUNCTION(Rc<CompiledFunction>),
     CLOSURE(Closure),
     BUILTIN(BuiltinFunction),
     ARRAY(Vec<Object>),
-    HASHMAP(HashMap<Object, Object>),
+    HASHMAP(#[cfg_attr(feature = "serde", serde(with = "pairs"))] HashMap<Object, Object>),
     NULL,
+    /// Raised by the `exit` builtin with the exit code of the program. Like an
+    /// error, it stops the execution wherever it happens.
+    EXIT(i64),
 }
 
+/// Shows a string as is, like `puts` and the conversions to strings do. The
+/// REPL shows [`Object::inspect`] instead.
 impl Display for Object {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Object::INTEGER(i) => write!(f, "{i}"),
-            Object::BOOLEAN(b) => write!(f, "{b}"),
-            Object::STRING(s) => write!(f, "\"{s}\""),
-            Object::RETURN(o) => write!(f, "{o}",),
-            Object::FUNCTION(o) => write!(f, "{o}"),
-            Object::COMPILEDFUNCTION(o) => write!(f, "{o}"),
-            Object::CLOSURE(o) => write!(f, "{o}"),
-            Object::BUILTIN(o) => write!(f, "{o}"),
-            Object::ERROR(s) => write!(f, "ERROR: {s}"),
-            Object::ARRAY(a) => Self::format_array(f, a),
-            Object::HASHMAP(h) => {
-                let mut values: Vec<String> = h.iter().map(|(k, v)| format!("{k}: {v}")).collect();
-                values.sort();
-                write!(f, "{{{}}}", values.join(", "))
-            }
-            Object::NULL => write!(f, "null"),
-        }
+        OutputStyle::DEFAULT.write_display(f, self)
     }
 }
 
 impl Eq for Object {}
 
+/// Clippy's `mutable_key_type` flags the maps keyed by objects, since some
+/// variants hold a `RefCell`. Only the hashable ones, which have no interior
+/// mutability, are ever used as keys, so the maps allow the lint.
 impl Hash for Object {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         match self {
             Object::INTEGER(i) => i.hash(state),
             Object::BOOLEAN(b) => b.hash(state),
             Object::STRING(s) => s.hash(state),
+            Object::BYTES(b) => b.hash(state),
             _ => "".hash(state),
         }
     }
 }
 
 impl Object {
+    /// Returns an error of kind [`ErrorKind::Custom`] with `message`.
+    pub fn error(message: impl Into<String>) -> Object {
+        Object::ERROR(ErrorObject::custom(message))
+    }
+
+    /// Returns the form of the object shown by the REPL, which quotes and
+    /// escapes the strings.
+    pub fn inspect(&self) -> String {
+        OutputStyle::DEFAULT.format(self)
+    }
+
     pub fn get_type(&self) -> String {
         match self {
             Object::INTEGER(_) => String::from("INTEGER"),
             Object::BOOLEAN(_) => String::from("BOOLEAN"),
             Object::STRING(_) => String::from("STRING"),
+            Object::BYTES(_) => String::from("BYTES"),
             Object::RETURN(_) => String::from("RETURN"),
+            Object::BREAK => String::from("BREAK"),
+            Object::CONTINUE => String::from("CONTINUE"),
             Object::ERROR(_) => String::from("ERROR"),
             Object::FUNCTION(_) => String::from("FUNCTION"),
             Object::COMPILEDFUNCTION(_) => String::from("COMPILEDFUNCTION"),
@@ -85,26 +144,105 @@ impl Object {
             Object::ARRAY(_) => String::from("ARRAY"),
             Object::HASHMAP(_) => String::from("HASHMAP"),
             Object::NULL => String::from("NULL"),
+            Object::EXIT(_) => String::from("EXIT"),
         }
     }
 
-    fn format_array(f: &mut std::fmt::Formatter<'_>, array: &[Object]) -> std::fmt::Result {
-        let values: Vec<String> = array.iter().map(ToString::to_string).collect();
-        write!(f, "[{}]", values.join(", "))
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Object::NULL => false,
+            Object::BOOLEAN(b) => *b,
+            _ => true,
+        }
+    }
+
+    /// Returns whether the object stops the execution of the program, which is
+    /// the case of errors and exits.
+    pub fn interrupts(&self) -> bool {
+        matches!(self, Object::ERROR(_) | Object::EXIT(_))
     }
 
     pub fn is_hashable(&self) -> bool {
         matches!(
             self,
-            Object::INTEGER(_) | Object::BOOLEAN(_) | Object::STRING(_)
+            Object::INTEGER(_) | Object::BOOLEAN(_) | Object::STRING(_) | Object::BYTES(_)
         )
     }
+
+    /// Replaces the element at `indexes`, the indexes of the nested
+    /// collections from this one in, by `value`. The element of an array must
+    /// exist, while a hash gets a new key if needed. Nothing changes if it
+    /// fails.
+    ///
+    /// # Errors
+    ///
+    /// If a collection cannot be indexed by its index, or if the element of an
+    /// array does not exist.
+    pub fn set_index(&mut self, indexes: &[Object], value: Object) -> Result<(), ErrorObject> {
+        let Some((index, rest)) = indexes.split_first() else {
+            *self = value;
+            return Ok(());
+        };
+        let element = match (&mut *self, index) {
+            (Object::ARRAY(elements), Object::INTEGER(i)) => {
+                let length = elements.len();
+                usize::try_from(*i)
+                    .ok()
+                    .and_then(|i| elements.get_mut(i))
+                    .ok_or_else(|| {
+                        ErrorObject::new(
+                            ErrorKind::IndexOutOfBounds,
+                            format!("index {i} out of range for an array of length {length}"),
+                        )
+                    })?
+            }
+            (Object::HASHMAP(pairs), _) => {
+                if !index.is_hashable() {
+                    return Err(ErrorObject::new(
+                        ErrorKind::TypeMismatch,
+                        format!("unusable as hash key: {}", index.get_type()),
+                    ));
+                }
+                if rest.is_empty() {
+                    pairs.insert(index.clone(), value);
+                    return Ok(());
+                }
+                // A missing element is null, which has no elements to set
+                pairs.get_mut(index).ok_or_else(|| {
+                    ErrorObject::new(
+                        ErrorKind::TypeMismatch,
+                        format!(
+                            "index assignment not supported: NULL[{}]",
+                            rest[0].get_type()
+                        ),
+                    )
+                })?
+            }
+            (target, _) => {
+                return Err(ErrorObject::new(
+                    ErrorKind::TypeMismatch,
+                    format!(
+                        "index assignment not supported: {}[{}]",
+                        target.get_type(),
+                        index.get_type()
+                    ),
+                ))
+            }
+        };
+        element.set_index(rest, value)
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Function {
+    /// Name of the binding the function was defined with, if any.
+    pub name: Option<String>,
     pub parameters: Vec<Identifier>,
     pub body: BlockStatement,
+    /// Not serialized, since it can hold the function itself: a deserialized
+    /// function gets an empty environment.
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub environment: Rc<RefCell<Environment>>,
 }
 
@@ -120,10 +258,17 @@ impl Display for Function {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CompiledFunction {
     pub instructions: Vec<u8>,
     pub num_locals: usize,
     pub num_parameters: usize,
+
+    /// Name of the binding the function was defined with, if any.
+    pub name: Option<String>,
+
+    /// Debug symbols of the function, empty if its source is unknown.
+    pub source_map: SourceMap,
 }
 
 impl Display for CompiledFunction {
@@ -137,8 +282,10 @@ impl Display for CompiledFunction {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Closure {
-    pub function: CompiledFunction,
+    /// Shared with the constant the closure was created from.
+    pub function: Rc<CompiledFunction>,
     pub free: Vec<Object>,
 }
 
@@ -153,7 +300,7 @@ impl Display for Closure {
 }
 
 impl Closure {
-    pub fn new(function: CompiledFunction) -> Self {
+    pub fn new(function: Rc<CompiledFunction>) -> Self {
         Self {
             function,
             free: Vec::new(),
@@ -169,6 +316,33 @@ impl Closure {
     }
 }
 
+/// Serializes hashmaps as lists of pairs, since formats like JSON only allow
+/// strings as keys.
+#[cfg(feature = "serde")]
+mod pairs {
+    use std::collections::HashMap;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::Object;
+
+    #[allow(clippy::mutable_key_type)]
+    pub fn serialize<S: Serializer>(
+        map: &HashMap<Object, Object>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(map)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<Object, Object>, D::Error> {
+        Ok(Vec::<(Object, Object)>::deserialize(deserializer)?
+            .into_iter()
+            .collect())
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -176,13 +350,14 @@ mod tests {
 
     #[test]
     fn test_hashing_objects() {
+        #[allow(clippy::mutable_key_type)]
         let mut map = HashMap::new();
         let one = Object::INTEGER(1);
         let two = Object::INTEGER(2);
         let one_again = Object::INTEGER(1);
-        let string_1 = Object::STRING("one".to_string());
-        let string_2 = Object::STRING("two".to_string());
-        let string_1_again = Object::STRING("one".to_string());
+        let string_1 = Object::STRING("one".into());
+        let string_2 = Object::STRING("two".into());
+        let string_1_again = Object::STRING("one".into());
         let true_1 = Object::BOOLEAN(true);
         let false_1 = Object::BOOLEAN(false);
         let true_2 = Object::BOOLEAN(true);
@@ -213,8 +388,8 @@ mod tests {
     fn tests_is_hashable() {
         let one = Object::INTEGER(1);
         let two = Object::INTEGER(2);
-        let string_1 = Object::STRING("one".to_string());
-        let string_2 = Object::STRING("two".to_string());
+        let string_1 = Object::STRING("one".into());
+        let string_2 = Object::STRING("two".into());
         let true_1 = Object::BOOLEAN(true);
         let false_1 = Object::BOOLEAN(false);
         let return_object = Object::RETURN(Box::new(Object::INTEGER(1)));
@@ -227,4 +402,120 @@ mod tests {
         assert!(false_1.is_hashable());
         assert!(!return_object.is_hashable());
     }
+
+    #[test]
+    fn test_set_index() {
+        let mut grid = Object::ARRAY(vec![
+            Object::ARRAY(vec![Object::INTEGER(1), Object::INTEGER(2)]),
+            Object::HASHMAP(HashMap::new()),
+        ]);
+        grid.set_index(
+            &[Object::INTEGER(0), Object::INTEGER(1)],
+            Object::INTEGER(3),
+        )
+        .unwrap();
+        grid.set_index(
+            &[Object::INTEGER(1), Object::STRING("a".into())],
+            Object::BOOLEAN(true),
+        )
+        .unwrap();
+        assert_eq!(
+            grid,
+            Object::ARRAY(vec![
+                Object::ARRAY(vec![Object::INTEGER(1), Object::INTEGER(3)]),
+                Object::HASHMAP(HashMap::from([(
+                    Object::STRING("a".into()),
+                    Object::BOOLEAN(true)
+                )])),
+            ])
+        );
+
+        assert_eq!(
+            grid.set_index(&[Object::INTEGER(2)], NULL),
+            Err(ErrorObject::new(
+                ErrorKind::IndexOutOfBounds,
+                "index 2 out of range for an array of length 2"
+            ))
+        );
+        assert_eq!(
+            grid.set_index(&[Object::INTEGER(1), Object::ARRAY(vec![])], NULL),
+            Err(ErrorObject::new(
+                ErrorKind::TypeMismatch,
+                "unusable as hash key: ARRAY"
+            ))
+        );
+        let before = grid.clone();
+        assert_eq!(
+            grid.set_index(
+                &[Object::INTEGER(1), Object::INTEGER(2), Object::INTEGER(3)],
+                NULL
+            ),
+            Err(ErrorObject::new(
+                ErrorKind::TypeMismatch,
+                "index assignment not supported: NULL[INTEGER]"
+            ))
+        );
+        assert_eq!(grid, before);
+        assert_eq!(
+            grid.set_index(
+                &[Object::INTEGER(0), Object::INTEGER(0), Object::INTEGER(0)],
+                NULL
+            ),
+            Err(ErrorObject::new(
+                ErrorKind::TypeMismatch,
+                "index assignment not supported: INTEGER[INTEGER]"
+            ))
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let objects = [
+            Object::ARRAY(vec![
+                Object::INTEGER(-3),
+                Object::STRING("a".into()),
+                Object::BYTES(vec![0, 255]),
+                Object::NULL,
+            ]),
+            Object::HASHMAP(HashMap::from([
+                (Object::INTEGER(1), Object::BOOLEAN(true)),
+                (
+                    Object::STRING("b".into()),
+                    Object::BUILTIN(BuiltinFunction::LEN),
+                ),
+            ])),
+            Object::ERROR(ErrorObject {
+                kind: ErrorKind::UndefinedIdentifier,
+                message: "identifier not found: x".to_string(),
+                position: Some(Position { line: 1, column: 2 }),
+            }),
+        ];
+        for object in objects {
+            let json = serde_json::to_string(&object).unwrap();
+            assert_eq!(serde_json::from_str::<Object>(&json).unwrap(), object);
+        }
+
+        // The environment of a function, which can hold the function itself, is dropped
+        let environment = Rc::new(RefCell::new(Environment::new()));
+        let function = Object::FUNCTION(Function {
+            name: Some("f".to_string()),
+            parameters: Vec::new(),
+            body: BlockStatement {
+                statements: Vec::new(),
+                offsets: Vec::new(),
+                end: None,
+            },
+            environment: Rc::clone(&environment),
+        });
+        environment
+            .borrow_mut()
+            .set("f".to_string(), function.clone());
+        let json = serde_json::to_string(&function).unwrap();
+        let Object::FUNCTION(deserialized) = serde_json::from_str(&json).unwrap() else {
+            panic!("expected a function");
+        };
+        assert_eq!(deserialized.name.as_deref(), Some("f"));
+        assert_eq!(deserialized.environment.borrow().bindings(), Vec::new());
+    }
 }
@@ -0,0 +1,76 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Pseudo random number generator used by the `rand` builtins (`SplitMix64`).
+///
+/// It is not cryptographically secure, but it is fast, small and gives the
+/// same numbers for the same seed, which makes seeded programs reproducible.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Random {
+    state: u64,
+}
+
+impl Default for Random {
+    /// Seeds the generator from the current time.
+    fn default() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_nanos());
+        Self::new(nanos as u64)
+    }
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn seed(&mut self, seed: u64) {
+        self.state = seed;
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns an integer between `min` and `max`, both included.
+    pub fn next_in_range(&mut self, min: i64, max: i64) -> i64 {
+        debug_assert!(min <= max);
+        // The span can be 2^64, which only fits in 128 bits
+        let span = (i128::from(max) - i128::from(min) + 1) as u128;
+        let offset = (u128::from(self.next_u64()) * span) >> 64;
+        (i128::from(min) + offset as i128) as i64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_numbers() {
+        let mut a = Random::new(42);
+        let mut b = Random::new(7);
+        b.seed(42);
+
+        for _ in 0..10 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+    }
+
+    #[test]
+    fn test_next_in_range() {
+        let mut random = Random::new(0);
+
+        for _ in 0..1000 {
+            let n = random.next_in_range(-3, 3);
+            assert!((-3..=3).contains(&n));
+        }
+        assert_eq!(random.next_in_range(5, 5), 5);
+        // Does not overflow on the widest range
+        random.next_in_range(i64::MIN, i64::MAX);
+    }
+}
@@ -0,0 +1,242 @@
+use std::{
+    borrow::Cow,
+    fmt::{self, Write},
+};
+
+use crate::object::Object;
+
+/// Controls how objects are rendered by `puts` and by the REPL echo.
+///
+/// Both engines hold an `OutputStyle`, so embedders can match the conventions of
+/// their host application. The default style is the one used by the `Display`
+/// implementation of [`Object`] and by [`Object::inspect`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputStyle {
+    /// Surround strings with double quotes, escaping the quotes, backslashes
+    /// and control characters they contain.
+    pub quote_strings: bool,
+
+    /// Separator between the elements of arrays and the entries of hashmaps.
+    pub separator: Cow<'static, str>,
+
+    pub null: Cow<'static, str>,
+    pub true_literal: Cow<'static, str>,
+    pub false_literal: Cow<'static, str>,
+
+    /// Character inserted between every group of three digits of integers,
+    /// such as `,` to render `1,000,000`.
+    pub digit_separator: Option<char>,
+}
+
+impl Default for OutputStyle {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl OutputStyle {
+    pub const DEFAULT: OutputStyle = OutputStyle {
+        quote_strings: true,
+        separator: Cow::Borrowed(", "),
+        null: Cow::Borrowed("null"),
+        true_literal: Cow::Borrowed("true"),
+        false_literal: Cow::Borrowed("false"),
+        digit_separator: None,
+    };
+
+    pub fn format(&self, object: &Object) -> String {
+        let mut output = String::new();
+        // Writing to a String never fails
+        let _ = self.write(&mut output, object);
+        output
+    }
+
+    /// Formats an object the way `puts` and `print` show it. Unlike [`format`],
+    /// which gives the inspect form used by the REPL, a string is shown as is,
+    /// without quotes. Strings nested in arrays and hashmaps are still quoted.
+    ///
+    /// [`format`]: OutputStyle::format
+    pub fn display(&self, object: &Object) -> String {
+        let mut output = String::new();
+        let _ = self.write_display(&mut output, object);
+        output
+    }
+
+    /// Writes the form of [`display`](OutputStyle::display).
+    ///
+    /// # Errors
+    ///
+    /// If `f` fails.
+    pub fn write_display(&self, f: &mut impl Write, object: &Object) -> fmt::Result {
+        match object {
+            Object::STRING(s) => f.write_str(s),
+            other => self.write(f, other),
+        }
+    }
+
+    /// Writes the form of [`format`](OutputStyle::format).
+    ///
+    /// # Errors
+    ///
+    /// If `f` fails.
+    pub fn write(&self, f: &mut impl Write, object: &Object) -> fmt::Result {
+        match object {
+            Object::INTEGER(i) => self.write_integer(f, *i),
+            Object::BOOLEAN(true) => f.write_str(&self.true_literal),
+            Object::BOOLEAN(false) => f.write_str(&self.false_literal),
+            Object::STRING(s) if self.quote_strings => write_quoted(f, s),
+            Object::STRING(s) => f.write_str(s),
+            Object::BYTES(b) => {
+                f.write_str("b\"")?;
+                for byte in b {
+                    // Quotes and backslashes are escaped to keep the output unambiguous
+                    match byte {
+                        b'"' | b'\\' => write!(f, "\\x{byte:02x}")?,
+                        b' '..=b'~' => f.write_char(char::from(*byte))?,
+                        _ => write!(f, "\\x{byte:02x}")?,
+                    }
+                }
+                f.write_char('"')
+            }
+            Object::RETURN(o) => self.write(f, o),
+            Object::ARRAY(a) => {
+                f.write_char('[')?;
+                for (i, element) in a.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(&self.separator)?;
+                    }
+                    self.write(f, element)?;
+                }
+                f.write_char(']')
+            }
+            Object::HASHMAP(h) => {
+                let mut entries: Vec<String> = h
+                    .iter()
+                    .map(|(k, v)| format!("{}: {}", self.format(k), self.format(v)))
+                    .collect();
+                entries.sort();
+                write!(f, "{{{}}}", entries.join(&self.separator))
+            }
+            Object::NULL => f.write_str(&self.null),
+            Object::FUNCTION(o) => write!(f, "{o}"),
+            Object::COMPILEDFUNCTION(o) => write!(f, "{o}"),
+            Object::CLOSURE(o) => write!(f, "{o}"),
+            Object::BUILTIN(o) => write!(f, "{o}"),
+            Object::ERROR(s) => write!(f, "ERROR: {s}"),
+            Object::EXIT(code) => write!(f, "EXIT: {code}"),
+            Object::BREAK => f.write_str("break"),
+            Object::CONTINUE => f.write_str("continue"),
+        }
+    }
+
+    fn write_integer(&self, f: &mut impl Write, i: i64) -> fmt::Result {
+        let Some(separator) = self.digit_separator else {
+            return write!(f, "{i}");
+        };
+
+        if i < 0 {
+            f.write_char('-')?;
+        }
+        let digits = i.unsigned_abs().to_string();
+        for (n, digit) in digits.chars().enumerate() {
+            if n > 0 && (digits.len() - n) % 3 == 0 {
+                f.write_char(separator)?;
+            }
+            f.write_char(digit)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes `s` between double quotes, with the characters which would make it
+/// ambiguous or span several lines escaped.
+fn write_quoted(f: &mut impl Write, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if c.is_control() => write!(f, "\\u{{{:x}}}", u32::from(c))?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn sample() -> Object {
+        Object::ARRAY(vec![
+            Object::INTEGER(-1234567),
+            Object::STRING("a".into()),
+            Object::BOOLEAN(true),
+            Object::NULL,
+            Object::HASHMAP(HashMap::from([(
+                Object::INTEGER(1000),
+                Object::BOOLEAN(false),
+            )])),
+        ])
+    }
+
+    #[test]
+    fn test_default_style_is_inspect() {
+        let object = sample();
+        assert_eq!(OutputStyle::default().format(&object), object.inspect());
+        assert_eq!(
+            object.inspect(),
+            r#"[-1234567, "a", true, null, {1000: false}]"#
+        );
+        assert_eq!(object.to_string(), object.inspect());
+    }
+
+    #[test]
+    fn test_inspect_escapes_strings() {
+        let string = Object::STRING("a \"b\"\\\n\t\u{7}".into());
+        assert_eq!(string.inspect(), r#""a \"b\"\\\n\t\u{7}""#);
+        assert_eq!(string.to_string(), "a \"b\"\\\n\t\u{7}");
+    }
+
+    #[test]
+    fn test_display() {
+        let style = OutputStyle::default();
+        assert_eq!(Object::STRING("hi".into()).to_string(), "hi");
+        assert_eq!(style.display(&Object::STRING("hi".into())), "hi");
+        assert_eq!(
+            style.display(&Object::ARRAY(vec![Object::STRING("hi".into())])),
+            r#"["hi"]"#
+        );
+        assert_eq!(style.display(&Object::INTEGER(1)), "1");
+    }
+
+    #[test]
+    fn test_bytes() {
+        let bytes = Object::BYTES(vec![b'a', b'"', b'\\', 0, 0xff, b' ']);
+        assert_eq!(bytes.to_string(), r#"b"a\x22\x5c\x00\xff ""#);
+    }
+
+    #[test]
+    fn test_custom_style() {
+        let style = OutputStyle {
+            quote_strings: false,
+            separator: Cow::Borrowed("; "),
+            null: Cow::Borrowed("nil"),
+            true_literal: Cow::Borrowed("yes"),
+            false_literal: Cow::Borrowed("no"),
+            digit_separator: Some(','),
+        };
+
+        assert_eq!(
+            style.format(&sample()),
+            "[-1,234,567; a; yes; nil; {1,000: no}]"
+        );
+        assert_eq!(style.format(&Object::INTEGER(100)), "100");
+        assert_eq!(style.format(&Object::INTEGER(100000)), "100,000");
+    }
+}
@@ -1,31 +1,55 @@
 use enum_stringify::EnumStringify;
 
-use crate::{lexer::token::Token, parser::Parser};
-use std::fmt::Display;
+use crate::{
+    lexer::{token::Token, Comment},
+    parser::Parser,
+    source::Source,
+};
+use std::{fmt::Display, rc::Rc};
 
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Program {
     pub statements: Vec<Statement>,
+
+    /// Offset, in characters, of the first token of every statement. It can be
+    /// empty for programs which were not parsed.
+    pub offsets: Vec<usize>,
+
+    /// Where the program was parsed from, if known.
+    pub source: Option<Source>,
+
+    /// Comments of the source, in order, which the formatter puts back
+    /// between the statements.
+    pub comments: Vec<Comment>,
+}
+
+impl Program {
+    #[must_use]
+    pub fn with_source(mut self, source: Source) -> Self {
+        self.source = Some(source);
+        self
+    }
 }
 
 impl Display for Program {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut program = String::new();
         for statement in &self.statements {
-            program.push_str(&format!("{statement}\n"));
+            writeln!(f, "{statement}")?;
         }
-        write!(f, "{program}")
+        Ok(())
     }
 }
 
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Expression {
     Identifier(Identifier),
     Primitive(Primitive),
     Prefix(PrefixOperator),
     Infix(InfixOperator),
     Conditional(Conditional),
-    FunctionLiteral(FunctionLiteral),
+    FunctionLiteral(Box<FunctionLiteral>),
     FunctionCall(FunctionCall),
     ArrayLiteral(ArrayLiteral),
     HashMapLiteral(HashMapLiteral),
@@ -53,13 +77,14 @@ impl Expression {
     pub fn parse(parser: &mut Parser, precedence: Precedence) -> Result<Self, String> {
         let mut left_exp = match parser.current_token.clone() {
             Token::Ident(_) => (Identifier::parse(parser)).map(Expression::Identifier),
-            Token::Int(_) | Token::False | Token::True | Token::String(_) => {
+            Token::Int(_) | Token::False | Token::True | Token::String(_) | Token::Bytes(_) => {
                 Primitive::parse(parser).map(Expression::Primitive)
             }
             Token::Bang | Token::Minus => PrefixOperator::parse(parser).map(Expression::Prefix),
             Token::LParen => Self::parse_grouped_expression(parser),
             Token::If => Conditional::parse(parser).map(Expression::Conditional),
-            Token::Function => FunctionLiteral::parse(parser).map(Expression::FunctionLiteral),
+            Token::Function => FunctionLiteral::parse(parser)
+                .map(|function| Expression::FunctionLiteral(Box::new(function))),
             Token::LSquare => ArrayLiteral::parse(parser).map(Expression::ArrayLiteral),
             Token::LSquirly => HashMapLiteral::parse(parser).map(Expression::HashMapLiteral),
 
@@ -138,10 +163,13 @@ impl Expression {
 }
 
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Primitive {
     IntegerLiteral(i64),
     BooleanLiteral(bool),
-    StringLiteral(String),
+    StringLiteral(Rc<str>),
+    /// The text of a `b"..."` literal, its value is the UTF-8 encoding of the text.
+    BytesLiteral(String),
 }
 
 impl Primitive {
@@ -154,6 +182,7 @@ impl Primitive {
             Token::True => Ok(Primitive::BooleanLiteral(true)),
             Token::False => Ok(Primitive::BooleanLiteral(false)),
             Token::String(x) => Ok(Primitive::StringLiteral(x)),
+            Token::Bytes(x) => Ok(Primitive::BytesLiteral(x)),
             _ => Err(format!(
                 "There is no primitive parser for the token {}",
                 parser.current_token
@@ -168,11 +197,13 @@ impl Display for Primitive {
             Primitive::IntegerLiteral(x) => write!(f, "{x}"),
             Primitive::BooleanLiteral(x) => write!(f, "{x}"),
             Primitive::StringLiteral(x) => write!(f, "\"{x}\""),
+            Primitive::BytesLiteral(x) => write!(f, "b\"{x}\""),
         }
     }
 }
 
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PrefixOperator {
     pub token: Token,
     pub right: Box<Expression>,
@@ -199,6 +230,7 @@ impl Display for PrefixOperator {
 }
 
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct InfixOperator {
     pub token: Token,
     pub left: Box<Expression>,
@@ -230,10 +262,11 @@ impl Display for InfixOperator {
 }
 
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Conditional {
     pub condition: Box<Expression>,
     pub consequence: BlockStatement,
-    pub alternative: Option<BlockStatement>,
+    pub alternative: Option<Box<BlockStatement>>,
 }
 
 impl Display for Conditional {
@@ -273,7 +306,7 @@ impl Conditional {
                 return Err(String::new());
             }
 
-            alternative = Some(BlockStatement::parse(parser));
+            alternative = Some(Box::new(BlockStatement::parse(parser)));
         }
 
         Ok(Conditional {
@@ -285,8 +318,15 @@ impl Conditional {
 }
 
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BlockStatement {
     pub statements: Vec<Statement>,
+
+    /// Offset of every statement, like [`Program::offsets`].
+    pub offsets: Vec<usize>,
+
+    /// Offset of the closing brace, if the block was parsed.
+    pub end: Option<usize>,
 }
 
 impl Display for BlockStatement {
@@ -303,21 +343,33 @@ impl BlockStatement {
     pub(crate) fn parse(parser: &mut Parser) -> Self {
         parser.next_token();
         let mut statements: Vec<Statement> = Vec::new();
+        let mut offsets = Vec::new();
         while !parser.current_token_is(&Token::RSquirly) && !parser.current_token_is(&Token::Eof) {
+            let offset = parser.current_offset();
             if let Some(x) = parser.parse_statement() {
                 statements.push(x);
+                offsets.push(offset);
             }
             parser.next_token();
         }
-        BlockStatement { statements }
+        BlockStatement {
+            statements,
+            offsets,
+            end: Some(parser.current_offset()),
+        }
     }
 }
 
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FunctionLiteral {
     pub name: Option<String>,
     pub parameters: Vec<Identifier>,
     pub body: BlockStatement,
+
+    /// Documentation of the function, from the `///` comments right before
+    /// the `let` defining it, without their `///`.
+    pub doc: Option<String>,
 }
 
 impl Display for FunctionLiteral {
@@ -345,6 +397,7 @@ impl FunctionLiteral {
             name: None,
             parameters,
             body,
+            doc: None,
         })
     }
 
@@ -358,14 +411,12 @@ impl FunctionLiteral {
 
         parser.next_token();
 
-        let mut identifier = Identifier::new(parser.current_token.clone());
-        identifiers.push(identifier);
+        identifiers.push(Identifier::parse(parser)?);
 
         while parser.peek_token_is(&Token::Comma) {
             parser.next_token();
             parser.next_token();
-            identifier = Identifier::new(parser.current_token.clone());
-            identifiers.push(identifier);
+            identifiers.push(Identifier::parse(parser)?);
         }
 
         if !parser.expect_peek(&Token::RParen) {
@@ -377,6 +428,7 @@ impl FunctionLiteral {
 }
 
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FunctionCall {
     pub function: Box<Expression>,
     pub arguments: Vec<Expression>,
@@ -405,12 +457,14 @@ impl FunctionCall {
 }
 
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Statement {
     Let(LetStatement),
     Return(ReturnStatement),
     Expression(Expression),
     While(WhileStatement),
     LoopStatements(LoopStatement),
+    IndexAssignment(IndexAssignment),
 }
 
 impl Display for Statement {
@@ -421,11 +475,13 @@ impl Display for Statement {
             Statement::Expression(expression) => write!(f, "{expression}"),
             Statement::While(statement) => write!(f, "{statement}"),
             Statement::LoopStatements(statement) => write!(f, "{statement}"),
+            Statement::IndexAssignment(statement) => write!(f, "{statement}"),
         }
     }
 }
 
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LetStatement {
     pub name: Identifier,
     pub value: Expression,
@@ -437,10 +493,73 @@ impl Display for LetStatement {
     }
 }
 
+/// Assignment to an element of a collection held by a variable, such as
+/// `a[0] = 1` or `grid[y][x] = 1`. Like a `let`, it defines the variable in the
+/// current scope, with the updated collection.
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IndexAssignment {
+    pub name: Identifier,
+    /// Indexes of the element, from the outermost collection in.
+    pub indexes: Vec<Expression>,
+    pub value: Expression,
+}
+
+impl Display for IndexAssignment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        for index in &self.indexes {
+            write!(f, "[{index}]")?;
+        }
+        write!(f, " = {};", self.value)
+    }
+}
+
+impl IndexAssignment {
+    /// Parses the assignment to `target`, the current token being its last
+    /// `]` and the next one the `=`.
+    ///
+    /// # Errors
+    ///
+    /// If the value cannot be parsed, or if the target is not an element of a
+    /// variable.
+    pub fn parse(parser: &mut Parser, target: Expression) -> Result<Self, String> {
+        // The value is parsed even if the target is invalid, for the parser to
+        // carry on after it
+        parser.next_token();
+        parser.next_token();
+        let value = Expression::parse(parser, Precedence::Lowest)?;
+
+        let mut indexes = Vec::new();
+        let mut target = target;
+        let name = loop {
+            match target {
+                Expression::IndexExpression(index) => {
+                    indexes.push(*index.index);
+                    target = *index.left;
+                }
+                Expression::Identifier(name) if !indexes.is_empty() => break name,
+                target => {
+                    return Err(format!(
+                        "Cannot assign to {target}, only the elements of a variable can be, like `a[0]`"
+                    ))
+                }
+            }
+        };
+        indexes.reverse();
+        Ok(IndexAssignment {
+            name,
+            indexes,
+            value,
+        })
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Identifier {
     pub token: Token,
-    pub value: String,
+    pub value: Rc<str>,
 }
 
 impl Display for Identifier {
@@ -450,15 +569,6 @@ impl Display for Identifier {
 }
 
 impl Identifier {
-    fn new(token: Token) -> Self {
-        match token.clone() {
-            Token::Ident(s) => Identifier { token, value: s },
-            _ => panic!(
-                "This should be a Token::Ident; if not, the function has not been properly called."
-            ),
-        }
-    }
-
     fn parse(parser: &mut Parser) -> Result<Self, String> {
         match parser.current_token.clone() {
             Token::Ident(s) => Ok(Identifier {
@@ -474,6 +584,7 @@ impl Identifier {
 }
 
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ReturnStatement {
     pub return_value: Expression,
 }
@@ -485,6 +596,7 @@ impl Display for ReturnStatement {
 }
 
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct WhileStatement {
     pub condition: Expression,
     pub body: BlockStatement,
@@ -497,6 +609,7 @@ impl Display for WhileStatement {
 }
 
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ArrayLiteral {
     pub elements: Vec<Expression>,
 }
@@ -522,6 +635,7 @@ impl ArrayLiteral {
 }
 
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct IndexExpression {
     pub left: Box<Expression>,
     pub index: Box<Expression>,
@@ -548,6 +662,7 @@ impl IndexExpression {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct HashMapLiteral {
     pub pairs: Vec<(Expression, Expression)>,
 }
@@ -592,6 +707,7 @@ impl HashMapLiteral {
 }
 
 #[derive(PartialEq, Debug, Clone, EnumStringify)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[enum_stringify(case = "lower")]
 pub enum LoopStatement {
     Break,
@@ -637,6 +753,123 @@ impl From<&Token> for Precedence {
     }
 }
 
+/// Traversal of a syntax tree, started by [`walk_program`]. Every method
+/// visits a node, by default by visiting its children with the `walk_`
+/// function of the same name, which an implementation overriding the method
+/// can call to keep going down.
+pub trait Visitor {
+    /// Visits a list of statements, the `offsets` being the ones of
+    /// [`Program::offsets`] or [`BlockStatement::offsets`].
+    fn visit_statements(&mut self, statements: &[Statement], offsets: &[usize]) {
+        walk_statements(self, statements, offsets);
+    }
+
+    /// Visits a statement, with its offset if it is known.
+    fn visit_statement(&mut self, statement: &Statement, _offset: Option<usize>) {
+        walk_statement(self, statement);
+    }
+
+    fn visit_block_statement(&mut self, block: &BlockStatement) {
+        walk_block_statement(self, block);
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        walk_expression(self, expression);
+    }
+
+    fn visit_function_literal(&mut self, function: &FunctionLiteral) {
+        walk_function_literal(self, function);
+    }
+
+    /// Visits an identifier used as a value, or as the collection of an
+    /// index assignment. The names bound by `let`s and by parameters are not
+    /// visited.
+    fn visit_identifier(&mut self, _identifier: &Identifier) {}
+}
+
+pub fn walk_program<V: Visitor + ?Sized>(visitor: &mut V, program: &Program) {
+    visitor.visit_statements(&program.statements, &program.offsets);
+}
+
+pub fn walk_statements<V: Visitor + ?Sized>(
+    visitor: &mut V,
+    statements: &[Statement],
+    offsets: &[usize],
+) {
+    for (i, statement) in statements.iter().enumerate() {
+        visitor.visit_statement(statement, offsets.get(i).copied());
+    }
+}
+
+pub fn walk_block_statement<V: Visitor + ?Sized>(visitor: &mut V, block: &BlockStatement) {
+    visitor.visit_statements(&block.statements, &block.offsets);
+}
+
+pub fn walk_statement<V: Visitor + ?Sized>(visitor: &mut V, statement: &Statement) {
+    match statement {
+        Statement::Let(statement) => visitor.visit_expression(&statement.value),
+        Statement::Return(statement) => visitor.visit_expression(&statement.return_value),
+        Statement::Expression(expression) => visitor.visit_expression(expression),
+        Statement::While(statement) => {
+            visitor.visit_expression(&statement.condition);
+            visitor.visit_block_statement(&statement.body);
+        }
+        Statement::LoopStatements(_) => {}
+        Statement::IndexAssignment(assignment) => {
+            visitor.visit_identifier(&assignment.name);
+            for index in &assignment.indexes {
+                visitor.visit_expression(index);
+            }
+            visitor.visit_expression(&assignment.value);
+        }
+    }
+}
+
+pub fn walk_expression<V: Visitor + ?Sized>(visitor: &mut V, expression: &Expression) {
+    match expression {
+        Expression::Identifier(identifier) => visitor.visit_identifier(identifier),
+        Expression::Primitive(_) => {}
+        Expression::Prefix(prefix) => visitor.visit_expression(&prefix.right),
+        Expression::Infix(infix) => {
+            visitor.visit_expression(&infix.left);
+            visitor.visit_expression(&infix.right);
+        }
+        Expression::Conditional(conditional) => {
+            visitor.visit_expression(&conditional.condition);
+            visitor.visit_block_statement(&conditional.consequence);
+            if let Some(alternative) = &conditional.alternative {
+                visitor.visit_block_statement(alternative);
+            }
+        }
+        Expression::FunctionLiteral(function) => visitor.visit_function_literal(function),
+        Expression::FunctionCall(call) => {
+            visitor.visit_expression(&call.function);
+            for argument in &call.arguments {
+                visitor.visit_expression(argument);
+            }
+        }
+        Expression::ArrayLiteral(array) => {
+            for element in &array.elements {
+                visitor.visit_expression(element);
+            }
+        }
+        Expression::HashMapLiteral(hashmap) => {
+            for (key, value) in &hashmap.pairs {
+                visitor.visit_expression(key);
+                visitor.visit_expression(value);
+            }
+        }
+        Expression::IndexExpression(index) => {
+            visitor.visit_expression(&index.left);
+            visitor.visit_expression(&index.index);
+        }
+    }
+}
+
+pub fn walk_function_literal<V: Visitor + ?Sized>(visitor: &mut V, function: &FunctionLiteral) {
+    visitor.visit_block_statement(&function.body);
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -647,21 +880,24 @@ mod tests {
             statements: vec![
                 Statement::Let(LetStatement {
                     name: Identifier {
-                        token: Token::Ident("myVar".to_string()),
-                        value: "myVar".to_string(),
+                        token: Token::Ident("myVar".into()),
+                        value: "myVar".into(),
                     },
                     value: Expression::Identifier(Identifier {
-                        token: Token::Ident("anotherVar".to_string()),
-                        value: "anotherVar".to_string(),
+                        token: Token::Ident("anotherVar".into()),
+                        value: "anotherVar".into(),
                     }),
                 }),
                 Statement::Return(ReturnStatement {
                     return_value: Expression::Identifier(Identifier {
-                        token: Token::Ident("myVar".to_string()),
-                        value: "myVar".to_string(),
+                        token: Token::Ident("myVar".into()),
+                        value: "myVar".into(),
                     }),
                 }),
             ],
+            offsets: vec![],
+            source: None,
+            comments: vec![],
         };
 
         assert_eq!(
@@ -669,4 +905,54 @@ mod tests {
             "let myVar = anotherVar;\nreturn myVar;\n"
         );
     }
+
+    #[test]
+    fn test_visitor() {
+        /// Collects the identifiers used, and the offsets of the statements.
+        #[derive(Default)]
+        struct Collector {
+            identifiers: Vec<String>,
+            offsets: Vec<Option<usize>>,
+            functions: usize,
+        }
+
+        impl Visitor for Collector {
+            fn visit_statement(&mut self, statement: &Statement, offset: Option<usize>) {
+                self.offsets.push(offset);
+                walk_statement(self, statement);
+            }
+
+            fn visit_function_literal(&mut self, function: &FunctionLiteral) {
+                self.functions += 1;
+                walk_function_literal(self, function);
+            }
+
+            fn visit_identifier(&mut self, identifier: &Identifier) {
+                self.identifiers.push(identifier.value.to_string());
+            }
+        }
+
+        let program = crate::parser::parse(
+            "let f = fn(x) { if (x) { g(x) } else { [h, {k: -l}] } };\na[i] = f(b);\nwhile (c) { break; }",
+        );
+        let mut collector = Collector::default();
+        walk_program(&mut collector, &program);
+        assert_eq!(
+            collector.identifiers,
+            vec!["x", "g", "x", "h", "k", "l", "a", "i", "f", "b", "c"]
+        );
+        assert_eq!(
+            collector.offsets,
+            vec![
+                Some(0),
+                Some(16),
+                Some(25),
+                Some(39),
+                Some(57),
+                Some(70),
+                Some(82)
+            ]
+        );
+        assert_eq!(collector.functions, 1);
+    }
 }
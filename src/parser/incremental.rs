@@ -0,0 +1,326 @@
+use std::ops::Range;
+
+use crate::{
+    lexer::{token::Token, Lexer},
+    parser::{
+        ast::{BlockStatement, Expression, Program, Statement},
+        parser_errors::ParserErrors,
+        Parser,
+    },
+};
+
+/// A change of a source: the characters of `range` are replaced by `text`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    /// Offsets, in characters, of the replaced text in the source before the
+    /// edit.
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+impl TextEdit {
+    /// Returns `source` after the edit.
+    pub fn apply(&self, source: &str) -> String {
+        let mut chars: Vec<char> = source.chars().collect();
+        chars.splice(self.range.clone(), self.text.chars());
+        chars.into_iter().collect()
+    }
+}
+
+/// Parses `text`, which is the source of `program` after `edit`, reusing the
+/// statements of `program` the edit cannot change. `program` must have been
+/// parsed from the source before the edit without errors. The result, or the
+/// errors, are the same as parsing `text` from scratch.
+///
+/// The top level statements are the checkpoints of the parser. It starts at
+/// the statement before the edited one, as its end depends on the first token
+/// of the edited one. It stops once a statement starts after the edit, at the
+/// next statement which also started a statement in `program`. The parser and
+/// the lexer keep no state from one top level statement to the next, so the
+/// rest of the program would be parsed the same way again.
+///
+/// # Errors
+///
+/// If `text` cannot be parsed, with the errors of the parser.
+pub fn reparse(program: &Program, text: &str, edit: &TextEdit) -> Result<Program, ParserErrors> {
+    reparse_statements(program, text, edit).map(|(program, _)| program)
+}
+
+/// Does [`reparse`], also returning the range of the statements which were
+/// parsed again.
+fn reparse_statements(
+    program: &Program,
+    text: &str,
+    edit: &TextEdit,
+) -> Result<(Program, Range<usize>), ParserErrors> {
+    let edit_end = edit.range.start + edit.text.chars().count();
+    let delta = edit_end as isize - edit.range.end as isize;
+
+    let edited = program
+        .offsets
+        .iter()
+        .rposition(|&offset| offset < edit.range.start);
+    let first = edited.map_or(0, |i| i.saturating_sub(1));
+    // The statement before the first one is lexed again for the `///`
+    // comments before the first one, and then dropped
+    let (start, mut keep) = match first {
+        0 => (0, true),
+        _ => (program.offsets[first - 1], false),
+    };
+
+    let mut parser = Parser::new(Lexer::starting_at(text, start));
+    let mut statements = program.statements[..first].to_vec();
+    let mut offsets = program.offsets[..first].to_vec();
+    // The old statement where the parse stopped, with its new offset
+    let mut reused = None;
+    let mut after_edit = false;
+    while parser.current_token != Token::Eof {
+        let offset = parser.current_offset;
+        if after_edit {
+            let old_offset = offset.wrapping_add_signed(-delta);
+            // The comments before the statement must be after the edit in
+            // both sources, for its documentation
+            match program.offsets.binary_search(&old_offset) {
+                Ok(i) if i > 0 && program.offsets[i - 1] >= edit.range.end => {
+                    reused = Some((i, offset));
+                    break;
+                }
+                _ => {}
+            }
+        }
+        after_edit = offset >= edit_end;
+        if let Some(statement) = parser.parse_statement() {
+            if keep {
+                statements.push(statement);
+                offsets.push(offset);
+            }
+        }
+        keep = true;
+        parser.next_token();
+    }
+    if !parser.errors.is_empty() {
+        return Err(parser.errors);
+    }
+    let reparsed = first..statements.len();
+
+    // The comments before the first statement belong to it
+    let comments_start = program.offsets.get(first).filter(|_| first > 0);
+    let before = |offset| comments_start.is_some_and(|start| offset < *start);
+    let mut comments: Vec<_> = program
+        .comments
+        .iter()
+        .filter(|comment| before(comment.offset))
+        .cloned()
+        .collect();
+    let mut new_comments = parser.lexer.take_comments();
+    new_comments.retain(|comment| !before(comment.offset));
+    if let Some((i, offset)) = reused {
+        // The lexer already read the comments after the first reused token
+        new_comments.retain(|comment| comment.offset < offset);
+        comments.extend(new_comments);
+        for comment in &program.comments {
+            if comment.offset >= program.offsets[i] {
+                let mut comment = comment.clone();
+                comment.offset = comment.offset.wrapping_add_signed(delta);
+                comments.push(comment);
+            }
+        }
+        for (statement, &old_offset) in program.statements[i..].iter().zip(&program.offsets[i..]) {
+            let mut statement = statement.clone();
+            shift_statement(&mut statement, delta);
+            statements.push(statement);
+            offsets.push(old_offset.wrapping_add_signed(delta));
+        }
+    } else {
+        comments.extend(new_comments);
+    }
+
+    let program = Program {
+        statements,
+        offsets,
+        source: None,
+        comments,
+    };
+    Ok((program, reparsed))
+}
+
+/// Moves the offsets kept in a statement by `delta` characters.
+fn shift_statement(statement: &mut Statement, delta: isize) {
+    match statement {
+        Statement::Let(statement) => shift_expression(&mut statement.value, delta),
+        Statement::Return(statement) => shift_expression(&mut statement.return_value, delta),
+        Statement::Expression(expression) => shift_expression(expression, delta),
+        Statement::While(statement) => {
+            shift_expression(&mut statement.condition, delta);
+            shift_block(&mut statement.body, delta);
+        }
+        Statement::LoopStatements(_) => {}
+        Statement::IndexAssignment(assignment) => {
+            for index in &mut assignment.indexes {
+                shift_expression(index, delta);
+            }
+            shift_expression(&mut assignment.value, delta);
+        }
+    }
+}
+
+fn shift_block(block: &mut BlockStatement, delta: isize) {
+    for offset in &mut block.offsets {
+        *offset = offset.wrapping_add_signed(delta);
+    }
+    if let Some(end) = &mut block.end {
+        *end = end.wrapping_add_signed(delta);
+    }
+    for statement in &mut block.statements {
+        shift_statement(statement, delta);
+    }
+}
+
+fn shift_expression(expression: &mut Expression, delta: isize) {
+    match expression {
+        Expression::Identifier(_) | Expression::Primitive(_) => {}
+        Expression::Prefix(prefix) => shift_expression(&mut prefix.right, delta),
+        Expression::Infix(infix) => {
+            shift_expression(&mut infix.left, delta);
+            shift_expression(&mut infix.right, delta);
+        }
+        Expression::Conditional(conditional) => {
+            shift_expression(&mut conditional.condition, delta);
+            shift_block(&mut conditional.consequence, delta);
+            if let Some(alternative) = &mut conditional.alternative {
+                shift_block(alternative, delta);
+            }
+        }
+        Expression::FunctionLiteral(function) => shift_block(&mut function.body, delta),
+        Expression::FunctionCall(call) => {
+            shift_expression(&mut call.function, delta);
+            for argument in &mut call.arguments {
+                shift_expression(argument, delta);
+            }
+        }
+        Expression::ArrayLiteral(array) => {
+            for element in &mut array.elements {
+                shift_expression(element, delta);
+            }
+        }
+        Expression::HashMapLiteral(hashmap) => {
+            for (key, value) in &mut hashmap.pairs {
+                shift_expression(key, delta);
+                shift_expression(value, delta);
+            }
+        }
+        Expression::IndexExpression(index) => {
+            shift_expression(&mut index.left, delta);
+            shift_expression(&mut index.index, delta);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    const SOURCE: &str = "// Computes things
+let add = fn(a, b) {
+    a + b // sums
+};
+let x = add(1, 2);
+/// Doubles
+let double = fn(n) { if (n > 0) { n * 2 } else { 0 } };
+x;
+puts(double(x));
+while (x < 10) { let x = x + 1; }
+";
+
+    /// Returns the edit replacing the first occurrence of `old` by `new`.
+    fn edit(old: &str, new: &str) -> TextEdit {
+        let start = SOURCE.find(old).unwrap();
+        TextEdit {
+            range: start..start + old.len(),
+            text: new.to_string(),
+        }
+    }
+
+    /// Checks that reparsing after the edit gives the same result as parsing
+    /// from scratch, and returns the range of the statements parsed again.
+    fn check(edit: &TextEdit) -> Range<usize> {
+        let program = parse(SOURCE);
+        let text = edit.apply(SOURCE);
+        let mut parser = Parser::new(Lexer::new(&text));
+        let expected = parser.parse_program();
+        match reparse_statements(&program, &text, edit) {
+            Ok((program, reparsed)) => {
+                assert!(parser.errors.is_empty(), "{text}");
+                assert_eq!(program, expected, "{text}");
+                reparsed
+            }
+            Err(errors) => {
+                assert_eq!(errors.errors, parser.errors.errors, "{text}");
+                0..0
+            }
+        }
+    }
+
+    #[test]
+    fn test_apply() {
+        let edit = TextEdit {
+            range: 1..3,
+            text: "éé".to_string(),
+        };
+        assert_eq!(edit.apply("aébc"), "aééc");
+    }
+
+    #[test]
+    fn test_reparse_reuses_statements() {
+        // Only the statements around the edit are parsed again
+        assert_eq!(check(&edit("add(1, 2)", "add(10, 20)")), 0..3);
+        assert_eq!(check(&edit("double(x)", "x")), 3..6);
+        assert_eq!(check(&edit("n * 2", "n * 3")), 1..4);
+        assert_eq!(check(&edit("x < 10", "x < 100")), 4..6);
+        // Edits at the start of a statement can change the end of the one
+        // before it
+        assert_eq!(check(&edit("puts", "put")), 2..6);
+        // The offsets of the following statements move
+        assert_eq!(check(&edit("a + b", "a+b")), 0..2);
+    }
+
+    #[test]
+    fn test_reparse_changes_boundaries() {
+        // `x` and the line after it become a call
+        check(&edit("x;", "x"));
+        check(&edit("puts", "(puts)"));
+        // Edits of the comments and of the documentation
+        check(&edit("/// Doubles", "/// Triples"));
+        check(&edit("/// Doubles", ""));
+        check(&edit("// sums", "// sums\n    // twice"));
+        check(&edit("let x", "// let x"));
+        check(&edit("", "let first = 1;\n"));
+        check(&edit("x;", "x;\nlet y = 2;"));
+        check(&edit(SOURCE, ""));
+        check(&edit("while", "x; while"));
+    }
+
+    #[test]
+    fn test_reparse_every_edit() {
+        let length = SOURCE.chars().count();
+        for start in 0..=length {
+            for text in ["", ";", "\n", "x", "(", "}", "\"", "//", "///", "fn"] {
+                for end in [start, (start + 1).min(length)] {
+                    check(&TextEdit {
+                        range: start..end,
+                        text: text.to_string(),
+                    });
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_reparse_errors() {
+        // An open string or block swallows the end of the program
+        check(&edit("(1, 2)", "(\"1, 2)"));
+        check(&edit("};\nlet x", "\nlet x"));
+        check(&edit("let double", "let"));
+    }
+}
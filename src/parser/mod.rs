@@ -1,11 +1,13 @@
 pub mod ast;
+pub mod incremental;
 pub mod parser_errors;
 mod parser_tests;
 
 use crate::{
     lexer::{token::Token, Lexer},
     parser::ast::{
-        Expression, Identifier, LetStatement, Precedence, Program, ReturnStatement, Statement,
+        Expression, Identifier, IndexAssignment, LetStatement, Precedence, Program,
+        ReturnStatement, Statement,
     },
 };
 
@@ -20,6 +22,14 @@ pub struct Parser {
     pub errors: ParserErrors,
     pub current_token: Token,
     pub peek_token: Token,
+
+    /// Offsets, in characters, of the current and of the peek tokens.
+    current_offset: usize,
+    peek_offset: usize,
+
+    /// Documentation comments right before the current and the peek tokens.
+    current_doc: Option<String>,
+    peek_doc: Option<String>,
 }
 
 impl Parser {
@@ -29,6 +39,10 @@ impl Parser {
             errors: ParserErrors::new(),
             current_token: Token::Illegal(String::new()),
             peek_token: Token::Illegal(String::new()),
+            current_offset: 0,
+            peek_offset: 0,
+            current_doc: None,
+            peek_doc: None,
         };
 
         parser.next_token();
@@ -40,19 +54,34 @@ impl Parser {
     pub fn next_token(&mut self) {
         self.current_token = self.peek_token.clone();
         self.peek_token = self.lexer.next_token();
+        self.current_offset = self.peek_offset;
+        self.peek_offset = self.lexer.token_offset();
+        self.current_doc = self.peek_doc.take();
+        self.peek_doc = self.lexer.take_token_doc();
+    }
+
+    /// Returns the offset, in characters, of the current token.
+    pub fn current_offset(&self) -> usize {
+        self.current_offset
     }
 
     pub fn parse_program(&mut self) -> Program {
         let mut program = Program {
             statements: Vec::new(),
+            offsets: Vec::new(),
+            source: None,
+            comments: Vec::new(),
         };
 
         while self.current_token != Token::Eof {
+            let offset = self.current_offset;
             if let Some(statement) = self.parse_statement() {
                 program.statements.push(statement);
+                program.offsets.push(offset);
             }
             self.next_token();
         }
+        program.comments = self.lexer.take_comments();
 
         program
     }
@@ -65,12 +94,13 @@ impl Parser {
             Token::Break | Token::Continue => {
                 self.parse_loop_statement().map(Statement::LoopStatements)
             }
-            _ => self.parse_expression_statement().map(Statement::Expression),
+            _ => self.parse_expression_statement(),
         }
     }
 
     fn parse_let_statement(&mut self) -> Option<LetStatement> {
-        if !self.expect_peek(&Token::Ident(String::new())) {
+        let doc = self.current_doc.take();
+        if !self.expect_peek(&Token::Ident("".into())) {
             return None;
         }
 
@@ -98,6 +128,7 @@ impl Parser {
 
         if let Expression::FunctionLiteral(literal) = &mut value {
             literal.name = Some(name.token.to_string());
+            literal.doc = doc;
         };
 
         if self.peek_token_is(&Token::Semicolon) {
@@ -151,14 +182,20 @@ impl Parser {
         smt
     }
 
-    fn parse_expression_statement(&mut self) -> Option<Expression> {
-        let expression = Expression::parse(self, Precedence::Lowest);
+    fn parse_expression_statement(&mut self) -> Option<Statement> {
+        let statement = Expression::parse(self, Precedence::Lowest).and_then(|expression| {
+            if self.peek_token_is(&Token::Assign) {
+                IndexAssignment::parse(self, expression).map(Statement::IndexAssignment)
+            } else {
+                Ok(Statement::Expression(expression))
+            }
+        });
         if self.peek_token_is(&Token::Semicolon) {
             self.next_token();
         }
 
-        match expression {
-            Ok(expression) => Some(expression),
+        match statement {
+            Ok(statement) => Some(statement),
             Err(s) => {
                 self.push_error(s);
                 None
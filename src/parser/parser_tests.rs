@@ -21,29 +21,29 @@ mod tests {
         ";
 
         let program = generate_program(input);
-        let expected_statemets = vec![
+        let expected_statemets = [
             Statement::Let(LetStatement {
                 name: Identifier {
-                    token: Token::Ident("x".to_string()),
-                    value: "x".to_string(),
+                    token: Token::Ident("x".into()),
+                    value: "x".into(),
                 },
                 value: Expression::Primitive(Primitive::IntegerLiteral(5)),
             }),
             Statement::Let(LetStatement {
                 name: Identifier {
-                    token: Token::Ident("y".to_string()),
-                    value: "y".to_string(),
+                    token: Token::Ident("y".into()),
+                    value: "y".into(),
                 },
                 value: Expression::Primitive(Primitive::BooleanLiteral(true)),
             }),
             Statement::Let(LetStatement {
                 name: Identifier {
-                    token: Token::Ident("foobar".to_string()),
-                    value: "foobar".to_string(),
+                    token: Token::Ident("foobar".into()),
+                    value: "foobar".into(),
                 },
                 value: Expression::Identifier(Identifier {
-                    token: Token::Ident("y".to_string()),
-                    value: "y".to_string(),
+                    token: Token::Ident("y".into()),
+                    value: "y".into(),
                 }),
             }),
         ];
@@ -65,7 +65,7 @@ mod tests {
         ";
 
         let program = generate_program(input);
-        let expected = vec![
+        let expected = [
             Statement::Return(ReturnStatement {
                 return_value: Expression::Primitive(Primitive::IntegerLiteral(5)),
             }),
@@ -74,8 +74,8 @@ mod tests {
             }),
             Statement::Return(ReturnStatement {
                 return_value: Expression::Identifier(Identifier {
-                    token: Token::Ident("y".to_string()),
-                    value: "y".to_string(),
+                    token: Token::Ident("y".into()),
+                    value: "y".into(),
                 }),
             }),
         ];
@@ -112,6 +112,15 @@ mod tests {
         parser.parse_program();
 
         assert_ne!(parser.errors.len(), 0);
+
+        for (input, error) in [
+            ("fn(1) { 1 }", "Expected an identifier, got 1"),
+            ("fn(a, \"b\") { a }", "Expected an identifier, got b"),
+        ] {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse_program();
+            assert_eq!(parser.errors.errors[0], error);
+        }
     }
 
     #[test]
@@ -125,8 +134,8 @@ mod tests {
         assert_eq!(
             statement,
             &Statement::Expression(Expression::Identifier(Identifier {
-                token: Token::Ident("foobar".to_string()),
-                value: "foobar".to_string(),
+                token: Token::Ident("foobar".into()),
+                value: "foobar".into(),
             }))
         );
     }
@@ -383,6 +392,20 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_bytes_literal_expression() {
+        let program = generate_program(r#"b"hello";"#);
+
+        assert_eq!(program.statements.len(), 1);
+        match &program.statements[0] {
+            Statement::Expression(Expression::Primitive(Primitive::BytesLiteral(s))) => {
+                assert_eq!(s, "hello");
+            }
+            _ => panic!("It is not a bytes literal"),
+        }
+        assert_eq!(program.to_string(), "b\"hello\"\n");
+    }
+
     #[test]
     fn test_array_literal() {
         let input = "[1,2*2,3+3]";
@@ -423,6 +446,40 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parsing_index_assignment() {
+        let tests = vec![
+            ("a[0] = 1", "a[0] = 1;"),
+            ("grid[y][x + 1] = true;", "grid[y][(x + 1)] = true;"),
+            (r#"h["a"] = -b; h"#, r#"h["a"] = (-b);"#),
+        ];
+
+        for (input, expected) in tests {
+            let program = generate_program(input);
+            match &program.statements[0] {
+                Statement::IndexAssignment(assignment) => {
+                    assert_eq!(assignment.to_string(), expected);
+                }
+                _ => panic!("It is not an index assignment"),
+            }
+        }
+
+        for (input, error) in [
+            (
+                "a = 1",
+                "Cannot assign to a, only the elements of a variable can be, like `a[0]`",
+            ),
+            (
+                "f()[0] = 1",
+                "Cannot assign to f(), only the elements of a variable can be, like `a[0]`",
+            ),
+        ] {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse_program();
+            assert_eq!(parser.errors.errors, vec![error.to_string()]);
+        }
+    }
+
     #[test]
     fn test_parsing_index_expression_string_conversion() {
         let tests = vec![
@@ -584,8 +641,8 @@ mod tests {
             condition: Expression::Infix(InfixOperator {
                 token: Token::LT,
                 left: Box::new(Expression::Identifier(Identifier {
-                    token: Token::Ident("x".to_string()),
-                    value: "x".to_string(),
+                    token: Token::Ident("x".into()),
+                    value: "x".into(),
                 })),
                 right: Box::new(Expression::Primitive(Primitive::IntegerLiteral(3))),
             }),
@@ -593,29 +650,31 @@ mod tests {
                 statements: vec![
                     Statement::Let(LetStatement {
                         name: Identifier {
-                            token: Token::Ident("x".to_string()),
-                            value: "x".to_string(),
+                            token: Token::Ident("x".into()),
+                            value: "x".into(),
                         },
                         value: Expression::Infix(InfixOperator {
                             token: Token::Plus,
                             left: Box::new(Expression::Identifier(Identifier {
-                                token: Token::Ident("x".to_string()),
-                                value: "x".to_string(),
+                                token: Token::Ident("x".into()),
+                                value: "x".into(),
                             })),
                             right: Box::new(Expression::Primitive(Primitive::IntegerLiteral(3))),
                         }),
                     }),
                     Statement::Expression(Expression::FunctionCall(FunctionCall {
                         function: Box::new(Expression::Identifier(Identifier {
-                            token: Token::Ident("puts".to_string()),
-                            value: "puts".to_string(),
+                            token: Token::Ident("puts".into()),
+                            value: "puts".into(),
                         })),
                         arguments: vec![Expression::Identifier(Identifier {
-                            token: Token::Ident("x".to_string()),
-                            value: "x".to_string(),
+                            token: Token::Ident("x".into()),
+                            value: "x".into(),
                         })],
                     })),
                 ],
+                offsets: vec![26, 53],
+                end: Some(70),
             },
         };
 
@@ -647,8 +706,8 @@ mod tests {
             condition: Expression::Infix(InfixOperator {
                 token: Token::LT,
                 left: Box::new(Expression::Identifier(Identifier {
-                    token: Token::Ident("x".to_string()),
-                    value: "x".to_string(),
+                    token: Token::Ident("x".into()),
+                    value: "x".into(),
                 })),
                 right: Box::new(Expression::Primitive(Primitive::IntegerLiteral(3))),
             }),
@@ -658,19 +717,25 @@ mod tests {
                         condition: Box::new(Expression::Infix(InfixOperator {
                             token: Token::Equal,
                             left: Box::new(Expression::Identifier(Identifier {
-                                token: Token::Ident("x".to_string()),
-                                value: "x".to_string(),
+                                token: Token::Ident("x".into()),
+                                value: "x".into(),
                             })),
                             right: Box::new(Expression::Primitive(Primitive::IntegerLiteral(2))),
                         })),
                         consequence: BlockStatement {
                             statements: vec![Statement::LoopStatements(LoopStatement::Break)],
+                            offsets: vec![47],
+                            end: Some(62),
                         },
-                        alternative: Some(BlockStatement {
+                        alternative: Some(Box::new(BlockStatement {
                             statements: vec![Statement::LoopStatements(LoopStatement::Continue)],
-                        }),
+                            offsets: vec![83],
+                            end: Some(101),
+                        })),
                     },
                 ))],
+                offsets: vec![22],
+                end: Some(107),
             },
         };
 
@@ -698,7 +763,7 @@ mod tests {
     }
 
     fn check_identifier(exp: &Identifier, value: &str) {
-        assert_eq!(exp.value, value);
+        assert_eq!(&*exp.value, value);
     }
 
     fn check_prefix_expression(exp: &Expression, operator: &str, right: &str) {
@@ -716,7 +781,8 @@ mod tests {
             Expression::Primitive(p) => match p {
                 Primitive::IntegerLiteral(i) => assert_eq!(i.to_string(), value),
                 Primitive::BooleanLiteral(b) => assert_eq!(b.to_string(), value),
-                Primitive::StringLiteral(s) => assert_eq!(s, value),
+                Primitive::StringLiteral(s) => assert_eq!(&**s, value),
+                Primitive::BytesLiteral(s) => assert_eq!(s, value),
             },
             _ => panic!("It is not a literal"),
         }
@@ -0,0 +1,99 @@
+use std::{fmt::Write, time::Duration};
+
+/// Wall times of the runs of a program by an engine.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timings {
+    pub engine: &'static str,
+    pub times: Vec<Duration>,
+}
+
+impl Timings {
+    pub fn new(engine: &'static str) -> Self {
+        Self {
+            engine,
+            times: Vec::new(),
+        }
+    }
+
+    pub fn mean(&self) -> Duration {
+        match u32::try_from(self.times.len()) {
+            Ok(0) | Err(_) => Duration::ZERO,
+            Ok(runs) => self.times.iter().sum::<Duration>() / runs,
+        }
+    }
+
+    pub fn min(&self) -> Duration {
+        self.times.iter().min().copied().unwrap_or_default()
+    }
+
+    pub fn max(&self) -> Duration {
+        self.times.iter().max().copied().unwrap_or_default()
+    }
+}
+
+/// Returns a table of the times of every engine, followed by how many times
+/// faster than the first engine the others are, on average.
+pub fn report(timings: &[Timings]) -> String {
+    let mut report = format!(
+        "{:<14} {:>6} {:>14} {:>14} {:>14}\n",
+        "engine", "runs", "mean", "min", "max"
+    );
+    for timing in timings {
+        let _ = writeln!(
+            report,
+            "{:<14} {:>6} {:>14} {:>14} {:>14}",
+            timing.engine,
+            timing.times.len(),
+            format!("{:.3?}", timing.mean()),
+            format!("{:.3?}", timing.min()),
+            format!("{:.3?}", timing.max())
+        );
+    }
+    if let Some((baseline, others)) = timings.split_first() {
+        for timing in others {
+            let speedup = baseline.mean().as_secs_f64() / timing.mean().as_secs_f64();
+            if speedup.is_finite() {
+                let _ = writeln!(
+                    report,
+                    "\n{} is {speedup:.2}x faster than {}",
+                    timing.engine, baseline.engine
+                );
+            }
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let millis = |times: &[u64]| times.iter().copied().map(Duration::from_millis).collect();
+        let interpreter = Timings {
+            engine: "interpreter",
+            times: millis(&[10, 14, 12]),
+        };
+        let vm = Timings {
+            engine: "vm",
+            times: millis(&[4, 3, 5]),
+        };
+        assert_eq!(interpreter.mean(), Duration::from_millis(12));
+        assert_eq!(vm.min(), Duration::from_millis(3));
+        assert_eq!(vm.max(), Duration::from_millis(5));
+        assert_eq!(
+            report(&[interpreter, vm]),
+            "\
+engine           runs           mean            min            max
+interpreter         3       12.000ms       10.000ms       14.000ms
+vm                  3        4.000ms        3.000ms        5.000ms
+
+vm is 3.00x faster than interpreter
+"
+        );
+
+        assert_eq!(Timings::new("vm").mean(), Duration::ZERO);
+        assert!(!report(&[Timings::new("a"), Timings::new("b")]).contains("faster"));
+    }
+}
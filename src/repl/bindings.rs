@@ -0,0 +1,225 @@
+use std::{
+    collections::HashMap,
+    fmt::{Display, Write},
+};
+
+use crate::{
+    compiler::symbol_table::{SymbolScope, SymbolTable},
+    object::{enviroment::Environment, Object},
+    vm::value::Value,
+};
+
+/// A snapshot of the bindings visible at the top level of a REPL session.
+///
+/// Values are stored with their string representation, so that two snapshots
+/// can be compared without walking the (possibly recursive) environments
+/// captured by functions.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Bindings {
+    values: HashMap<String, String>,
+}
+
+impl Bindings {
+    pub fn from_environment(env: &Environment) -> Self {
+        let values = env
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.inspect()))
+            .collect();
+        Self { values }
+    }
+
+    pub fn from_globals(symbol_table: &SymbolTable, globals: &[Value]) -> Self {
+        let values = global_bindings(symbol_table, globals)
+            .into_iter()
+            .map(|(name, value)| (name, value.inspect()))
+            .collect();
+        Self { values }
+    }
+
+    /// Returns the bindings that were added or changed in `after`, sorted by name.
+    pub fn diff(&self, after: &Bindings) -> Vec<BindingChange> {
+        let mut changes: Vec<BindingChange> = after
+            .values
+            .iter()
+            .filter_map(|(name, new)| match self.values.get(name) {
+                None => Some(BindingChange::Added {
+                    name: name.clone(),
+                    value: new.clone(),
+                }),
+                Some(old) if old != new => Some(BindingChange::Changed {
+                    name: name.clone(),
+                    old: old.clone(),
+                    new: new.clone(),
+                }),
+                Some(_) => None,
+            })
+            .collect();
+        changes.sort_by(|a, b| a.name().cmp(b.name()));
+        changes
+    }
+}
+
+/// Returns the globals defined in `symbol_table` with their values, sorted by
+/// name, like [`Environment::bindings`] for the compiler.
+pub fn global_bindings(symbol_table: &SymbolTable, globals: &[Value]) -> Vec<(String, Object)> {
+    let mut bindings: Vec<(String, Object)> = symbol_table
+        .symbols()
+        .filter(|symbol| symbol.scope == SymbolScope::Global)
+        .filter_map(|symbol| {
+            globals
+                .get(symbol.index)
+                .map(|value| (symbol.name.clone(), value.to_object()))
+        })
+        .collect();
+    bindings.sort_by(|(a, _), (b, _)| a.cmp(b));
+    bindings
+}
+
+/// Returns the `let` statements defining `bindings` again in another engine,
+/// followed by the names of the bindings whose values cannot be written as
+/// literals, such as functions.
+pub fn rebind(bindings: &[(String, Object)]) -> (String, Vec<&str>) {
+    let mut statements = String::new();
+    let mut skipped = Vec::new();
+    for (name, value) in bindings {
+        match literal(value) {
+            Some(literal) => {
+                let _ = writeln!(statements, "let {name} = {literal};");
+            }
+            None => skipped.push(name.as_str()),
+        }
+    }
+    (statements, skipped)
+}
+
+/// Returns the Monkey code evaluating to `value`, if it is an integer, a
+/// boolean, a string, or an array or hash of them. Strings have no escapes,
+/// so those containing a quote are left out.
+fn literal(value: &Object) -> Option<String> {
+    match value {
+        // The literal of the smallest integer overflows before its negation
+        Object::INTEGER(i64::MIN) => None,
+        Object::INTEGER(i) => Some(i.to_string()),
+        Object::BOOLEAN(b) => Some(b.to_string()),
+        Object::STRING(s) if !s.contains(['"', '\0']) => Some(format!("\"{s}\"")),
+        Object::ARRAY(elements) => {
+            let elements = elements.iter().map(literal).collect::<Option<Vec<_>>>()?;
+            Some(format!("[{}]", elements.join(", ")))
+        }
+        Object::HASHMAP(pairs) => {
+            let pairs = pairs
+                .iter()
+                .map(|(key, value)| Some(format!("{}: {}", literal(key)?, literal(value)?)))
+                .collect::<Option<Vec<_>>>()?;
+            Some(format!("{{{}}}", pairs.join(", ")))
+        }
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BindingChange {
+    Added {
+        name: String,
+        value: String,
+    },
+    Changed {
+        name: String,
+        old: String,
+        new: String,
+    },
+}
+
+impl BindingChange {
+    pub fn name(&self) -> &str {
+        match self {
+            BindingChange::Added { name, .. } | BindingChange::Changed { name, .. } => name,
+        }
+    }
+}
+
+impl Display for BindingChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BindingChange::Added { name, value } => write!(f, "+ {name} = {value}"),
+            BindingChange::Changed { name, old, new } => write!(f, "~ {name}: {old} -> {new}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bindings_diff() {
+        let mut env = Environment::new();
+        env.set("a".to_string(), Object::INTEGER(1));
+        env.set("b".to_string(), Object::INTEGER(2));
+        let before = Bindings::from_environment(&env);
+
+        env.set("b".to_string(), Object::INTEGER(3));
+        env.set("c".to_string(), Object::BOOLEAN(true));
+        let after = Bindings::from_environment(&env);
+
+        let changes = before.diff(&after);
+        assert_eq!(
+            changes,
+            vec![
+                BindingChange::Changed {
+                    name: "b".to_string(),
+                    old: "2".to_string(),
+                    new: "3".to_string(),
+                },
+                BindingChange::Added {
+                    name: "c".to_string(),
+                    value: "true".to_string(),
+                },
+            ]
+        );
+        assert_eq!(changes[0].to_string(), "~ b: 2 -> 3");
+        assert_eq!(changes[1].to_string(), "+ c = true");
+    }
+
+    #[test]
+    fn test_rebind() {
+        let bindings = vec![
+            ("a".to_string(), Object::INTEGER(-1)),
+            (
+                "b".to_string(),
+                Object::ARRAY(vec![Object::BOOLEAN(true), Object::STRING("x y".into())]),
+            ),
+            (
+                "c".to_string(),
+                Object::HASHMAP(HashMap::from([(Object::INTEGER(1), Object::ARRAY(vec![]))])),
+            ),
+            ("d".to_string(), Object::STRING("say \"hi\"".into())),
+            ("e".to_string(), Object::ARRAY(vec![Object::NULL])),
+            ("f".to_string(), Object::INTEGER(i64::MIN)),
+        ];
+        let (statements, skipped) = rebind(&bindings);
+        assert_eq!(
+            statements,
+            "let a = -1;\nlet b = [true, \"x y\"];\nlet c = {1: []};\n"
+        );
+        assert_eq!(skipped, vec!["d", "e", "f"]);
+    }
+
+    #[test]
+    fn test_bindings_from_globals() {
+        let mut symbol_table = SymbolTable::new();
+        symbol_table.define_builtin(0, "len".to_string());
+        symbol_table.define("a".to_string());
+        let globals = vec![Value::Integer(5)];
+
+        let bindings = Bindings::from_globals(&symbol_table, &globals);
+        let changes = Bindings::default().diff(&bindings);
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].to_string(), "+ a = 5");
+        assert_eq!(
+            global_bindings(&symbol_table, &globals),
+            vec![("a".to_string(), Object::INTEGER(5))]
+        );
+    }
+}
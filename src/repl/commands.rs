@@ -0,0 +1,228 @@
+use crate::repl::Mode;
+
+/// A REPL meta command, entered as a line starting with `:`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplCommand {
+    /// `:bindings on|off`, show the bindings changed by every evaluated line.
+    Bindings(bool),
+
+    /// `:env`, list the bindings defined in the session.
+    Env,
+
+    /// `:symbols`, list the symbols defined in the session.
+    Symbols,
+
+    /// `:disas <identifier>`, disassemble a function defined in the session.
+    Disas(String),
+
+    /// `:dis`, disassemble the last line compiled in the session.
+    Dis,
+
+    /// `:profile on|off`, collect call statistics of the evaluated lines.
+    Profile(bool),
+
+    /// `:profile report`, show the collected call statistics.
+    ProfileReport,
+
+    /// `:ast <code>`, show the syntax tree of some code without running it.
+    Ast(String),
+
+    /// `:load <file>`, run a file in the session, as if it was typed.
+    Load(String),
+
+    /// `:time <code>`, run some code and show how long it took.
+    Time(String),
+
+    /// `:mode interpreter|compiler`, switch the engine of the session.
+    Mode(Mode),
+}
+
+impl ReplCommand {
+    /// Parses a REPL line. Returns `None` if the line is not a command.
+    pub fn parse(line: &str) -> Option<Result<ReplCommand, String>> {
+        let line = line.trim();
+        let command = line.strip_prefix(':')?;
+        let mut words = command.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let args: Vec<&str> = words.collect();
+
+        Some(match name {
+            "bindings" => Self::parse_switch(name, &args).map(ReplCommand::Bindings),
+            "env" => match args[..] {
+                [] => Ok(ReplCommand::Env),
+                _ => Err("Usage: :env".to_string()),
+            },
+            "symbols" => match args[..] {
+                [] => Ok(ReplCommand::Symbols),
+                _ => Err("Usage: :symbols".to_string()),
+            },
+            "dis" => match args[..] {
+                [] => Ok(ReplCommand::Dis),
+                _ => Err("Usage: :dis".to_string()),
+            },
+            "disas" => match args[..] {
+                [identifier] => Ok(ReplCommand::Disas(identifier.to_string())),
+                _ => Err("Usage: :disas <identifier>".to_string()),
+            },
+            "ast" => match command.trim_start().strip_prefix(name) {
+                Some(code) if !code.trim().is_empty() => {
+                    Ok(ReplCommand::Ast(code.trim().to_string()))
+                }
+                _ => Err("Usage: :ast <code>".to_string()),
+            },
+            "time" => match command.trim_start().strip_prefix(name) {
+                Some(code) if !code.trim().is_empty() => {
+                    Ok(ReplCommand::Time(code.trim().to_string()))
+                }
+                _ => Err("Usage: :time <code>".to_string()),
+            },
+            "mode" => match args[..] {
+                ["interpreter"] => Ok(ReplCommand::Mode(Mode::Interpreter)),
+                ["compiler"] => Ok(ReplCommand::Mode(Mode::Compiler)),
+                _ => Err("Usage: :mode interpreter|compiler".to_string()),
+            },
+            "load" => match command.trim_start().strip_prefix(name) {
+                Some(path) if !path.trim().is_empty() => {
+                    Ok(ReplCommand::Load(path.trim().to_string()))
+                }
+                _ => Err("Usage: :load <file>".to_string()),
+            },
+            "profile" => match args[..] {
+                ["report"] => Ok(ReplCommand::ProfileReport),
+                _ => Self::parse_switch(name, &args)
+                    .map(ReplCommand::Profile)
+                    .map_err(|_| "Usage: :profile on|off|report".to_string()),
+            },
+            _ => Err(format!("Unknown command: {line}")),
+        })
+    }
+
+    fn parse_switch(name: &str, args: &[&str]) -> Result<bool, String> {
+        match args {
+            ["on"] => Ok(true),
+            ["off"] => Ok(false),
+            _ => Err(format!("Usage: :{name} on|off")),
+        }
+    }
+}
+
+/// Settings of a REPL session which can be changed through commands.
+#[derive(Debug, Default, Clone)]
+pub struct ReplSettings {
+    pub show_bindings: bool,
+    pub profile: bool,
+}
+
+impl ReplSettings {
+    pub fn apply(&mut self, command: &ReplCommand) {
+        match command {
+            ReplCommand::Bindings(on) => self.show_bindings = *on,
+            ReplCommand::Profile(on) => self.profile = *on,
+            ReplCommand::Env
+            | ReplCommand::Symbols
+            | ReplCommand::Disas(_)
+            | ReplCommand::Dis
+            | ReplCommand::ProfileReport
+            | ReplCommand::Ast(_)
+            | ReplCommand::Load(_)
+            | ReplCommand::Time(_)
+            | ReplCommand::Mode(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(ReplCommand::parse("let a = 1;"), None);
+        assert_eq!(
+            ReplCommand::parse(":bindings on"),
+            Some(Ok(ReplCommand::Bindings(true)))
+        );
+        assert_eq!(
+            ReplCommand::parse("  :bindings off "),
+            Some(Ok(ReplCommand::Bindings(false)))
+        );
+        assert_eq!(
+            ReplCommand::parse(":bindings"),
+            Some(Err("Usage: :bindings on|off".to_string()))
+        );
+        assert_eq!(ReplCommand::parse(":env"), Some(Ok(ReplCommand::Env)));
+        assert_eq!(
+            ReplCommand::parse(":env a"),
+            Some(Err("Usage: :env".to_string()))
+        );
+        assert_eq!(
+            ReplCommand::parse(":symbols"),
+            Some(Ok(ReplCommand::Symbols))
+        );
+        assert_eq!(
+            ReplCommand::parse(":symbols x"),
+            Some(Err("Usage: :symbols".to_string()))
+        );
+        assert_eq!(
+            ReplCommand::parse(":disas add"),
+            Some(Ok(ReplCommand::Disas("add".to_string())))
+        );
+        assert_eq!(
+            ReplCommand::parse(":disas"),
+            Some(Err("Usage: :disas <identifier>".to_string()))
+        );
+        assert_eq!(ReplCommand::parse(":dis"), Some(Ok(ReplCommand::Dis)));
+        assert_eq!(
+            ReplCommand::parse(":dis add"),
+            Some(Err("Usage: :dis".to_string()))
+        );
+        assert_eq!(
+            ReplCommand::parse(":profile on"),
+            Some(Ok(ReplCommand::Profile(true)))
+        );
+        assert_eq!(
+            ReplCommand::parse(":profile report"),
+            Some(Ok(ReplCommand::ProfileReport))
+        );
+        assert_eq!(
+            ReplCommand::parse(":profile"),
+            Some(Err("Usage: :profile on|off|report".to_string()))
+        );
+        assert_eq!(
+            ReplCommand::parse(":ast let a = [1, 2];"),
+            Some(Ok(ReplCommand::Ast("let a = [1, 2];".to_string())))
+        );
+        assert_eq!(
+            ReplCommand::parse(":ast "),
+            Some(Err("Usage: :ast <code>".to_string()))
+        );
+        assert_eq!(
+            ReplCommand::parse(":load lib/my utils.monkey "),
+            Some(Ok(ReplCommand::Load("lib/my utils.monkey".to_string())))
+        );
+        assert_eq!(
+            ReplCommand::parse(":load"),
+            Some(Err("Usage: :load <file>".to_string()))
+        );
+        assert_eq!(
+            ReplCommand::parse(":time fib(20)"),
+            Some(Ok(ReplCommand::Time("fib(20)".to_string())))
+        );
+        assert_eq!(
+            ReplCommand::parse(":time"),
+            Some(Err("Usage: :time <code>".to_string()))
+        );
+        assert_eq!(
+            ReplCommand::parse(":mode compiler"),
+            Some(Ok(ReplCommand::Mode(Mode::Compiler)))
+        );
+        assert_eq!(
+            ReplCommand::parse(":mode lexer"),
+            Some(Err("Usage: :mode interpreter|compiler".to_string()))
+        );
+        assert_eq!(
+            ReplCommand::parse(":foo"),
+            Some(Err("Unknown command: :foo".to_string()))
+        );
+    }
+}
@@ -0,0 +1,484 @@
+use std::io::{self, BufRead, Write};
+
+use serde_json::{json, Value as Json};
+
+use crate::{
+    repl::debugger::{DebugCommand, Debugger},
+    vm::{describe_value, VM},
+};
+
+/// The only thread of the programs, Monkey has no concurrency.
+const THREAD_ID: i64 = 1;
+
+/// Variables reference of the locals of the running function.
+const LOCALS_REFERENCE: i64 = 1;
+
+/// Variables reference of the global variables.
+const GLOBALS_REFERENCE: i64 = 2;
+
+/// Compiles the program at a path into a VM ready to run it.
+type Launcher<'a> = Box<dyn FnMut(&str) -> Result<VM, String> + 'a>;
+
+/// Reads a message of the Debug Adapter Protocol, a JSON object after a
+/// `Content-Length` header. Returns `None` at the end of the input.
+pub fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Json>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse().ok();
+            }
+        }
+    }
+    let Some(length) = length else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "message without a Content-Length header",
+        ));
+    };
+    let mut content = vec![0; length];
+    reader.read_exact(&mut content)?;
+    serde_json::from_slice(&content)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Writes a message of the Debug Adapter Protocol, with its header.
+pub fn write_message(writer: &mut impl Write, message: &Json) -> io::Result<()> {
+    let content = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{content}", content.len())?;
+    writer.flush()
+}
+
+/// Serves a client of the Debug Adapter Protocol, such as VS Code, running the
+/// programs it launches in a [`Debugger`].
+///
+/// `next` runs to the end of the statement, `stepIn` runs a single
+/// instruction and `continue` runs to the next breakpoint. The requests are
+/// handled one at a time, so a running program cannot be paused.
+pub struct DapServer<'a> {
+    launch: Launcher<'a>,
+
+    debugger: Option<Debugger>,
+
+    /// Path of the launched program.
+    program: String,
+
+    stop_on_entry: bool,
+
+    /// Sequence number of the last message sent.
+    seq: i64,
+}
+
+impl<'a> DapServer<'a> {
+    pub fn new(launch: impl FnMut(&str) -> Result<VM, String> + 'a) -> Self {
+        Self {
+            launch: Box::new(launch),
+            debugger: None,
+            program: String::new(),
+            stop_on_entry: false,
+            seq: 0,
+        }
+    }
+
+    /// Handles the requests of `reader` until the client disconnects, writing
+    /// the responses and the events to `writer`.
+    pub fn serve(&mut self, reader: &mut impl BufRead, writer: &mut impl Write) -> io::Result<()> {
+        while let Some(request) = read_message(reader)? {
+            let disconnect = request["command"] == "disconnect";
+            for message in self.handle(&request) {
+                write_message(writer, &message)?;
+            }
+            if disconnect {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the response to a request, followed by the events it caused.
+    pub fn handle(&mut self, request: &Json) -> Vec<Json> {
+        let command = request["command"].as_str().unwrap_or_default();
+        let arguments = &request["arguments"];
+        let mut events = Vec::new();
+        let result = match command {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsFunctionBreakpoints": true,
+            })),
+            // The breakpoints are set once the program is compiled
+            "launch" => self.launch(arguments).inspect(|_| {
+                events.push(self.event("initialized", json!({})));
+            }),
+            "setBreakpoints" => self.set_breakpoints(arguments),
+            "setFunctionBreakpoints" => self.set_function_breakpoints(arguments),
+            "setExceptionBreakpoints" => Ok(json!({ "breakpoints": [] })),
+            "configurationDone" => {
+                if self.stop_on_entry {
+                    events.push(self.stopped("entry", None));
+                } else {
+                    events.extend(self.resume(&DebugCommand::Continue, "breakpoint"));
+                }
+                Ok(json!({}))
+            }
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] })),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => self.scopes(arguments),
+            "variables" => self.variables(arguments),
+            "continue" => {
+                events.extend(self.resume(&DebugCommand::Continue, "breakpoint"));
+                Ok(json!({ "allThreadsContinued": true }))
+            }
+            "next" => {
+                events.extend(self.resume(&DebugCommand::Next, "step"));
+                Ok(json!({}))
+            }
+            "stepIn" => {
+                events.extend(self.resume(&DebugCommand::Step, "step"));
+                Ok(json!({}))
+            }
+            "disconnect" => {
+                self.debugger = None;
+                Ok(json!({}))
+            }
+            _ => Err(format!("Unsupported request: {command}")),
+        };
+
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": command,
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        let mut messages = vec![response];
+        messages.extend(events);
+        for message in &mut messages {
+            self.seq += 1;
+            message["seq"] = json!(self.seq);
+        }
+        messages
+    }
+
+    fn event(&self, event: &str, body: Json) -> Json {
+        json!({
+            "type": "event",
+            "event": event,
+            "body": body,
+        })
+    }
+
+    fn stopped(&self, reason: &str, hit: Option<usize>) -> Json {
+        let mut body = json!({
+            "reason": reason,
+            "threadId": THREAD_ID,
+            "allThreadsStopped": true,
+        });
+        if let Some(hit) = hit {
+            body["hitBreakpointIds"] = json!([hit]);
+        }
+        self.event("stopped", body)
+    }
+
+    fn debugger(&self) -> Result<&Debugger, String> {
+        self.debugger
+            .as_ref()
+            .ok_or_else(|| "No program is running".to_string())
+    }
+
+    fn launch(&mut self, arguments: &Json) -> Result<Json, String> {
+        let program = arguments["program"]
+            .as_str()
+            .ok_or("The launch request needs a `program`")?;
+        let vm = (self.launch)(program)?;
+        self.debugger = Some(Debugger::new(vm));
+        self.program = program.to_string();
+        self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+        Ok(json!({}))
+    }
+
+    fn set_breakpoints(&mut self, arguments: &Json) -> Result<Json, String> {
+        let lines: Vec<usize> = arguments["breakpoints"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|breakpoint| breakpoint["line"].as_u64())
+            .map(|line| line as usize)
+            .collect();
+        let debugger = self
+            .debugger
+            .as_mut()
+            .ok_or_else(|| "No program is running".to_string())?;
+        let ids = debugger.set_line_breakpoints(&lines);
+        let breakpoints: Vec<Json> = ids
+            .into_iter()
+            .zip(lines)
+            .map(|(id, line)| json!({ "id": id, "verified": true, "line": line }))
+            .collect();
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn set_function_breakpoints(&mut self, arguments: &Json) -> Result<Json, String> {
+        let names: Vec<String> = arguments["breakpoints"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|breakpoint| breakpoint["name"].as_str())
+            .map(str::to_string)
+            .collect();
+        let debugger = self
+            .debugger
+            .as_mut()
+            .ok_or_else(|| "No program is running".to_string())?;
+        let breakpoints: Vec<Json> = debugger
+            .set_function_breakpoints(&names)
+            .into_iter()
+            .map(|id| json!({ "id": id, "verified": true }))
+            .collect();
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    /// Runs the program and returns the events telling where it stopped, or
+    /// how it ended.
+    fn resume(&mut self, command: &DebugCommand, reason: &str) -> Vec<Json> {
+        let Some(debugger) = self.debugger.as_mut() else {
+            return Vec::new();
+        };
+        let hit = debugger.resume(command);
+        if !debugger.is_finished() {
+            let reason = if hit.is_some() { "breakpoint" } else { reason };
+            return vec![self.stopped(reason, hit)];
+        }
+
+        let mut events = Vec::new();
+        let exit_code = match (debugger.error(), debugger.vm().exit_code()) {
+            (Some(error), _) => {
+                let output = format!("The program stopped with an error: {error}\n");
+                events
+                    .push(self.event("output", json!({ "category": "stderr", "output": output })));
+                1
+            }
+            (None, Some(code)) => code,
+            (None, None) => 0,
+        };
+        events.push(self.event("exited", json!({ "exitCode": exit_code })));
+        events.push(self.event("terminated", json!({})));
+        events
+    }
+
+    /// Returns the function calls running, the running one first. The frames
+    /// are numbered from the main program, which is 0.
+    fn stack_trace(&self) -> Result<Json, String> {
+        let vm = self.debugger()?.vm();
+        let mut frames = vm.stack_trace();
+        // The running function is at its next instruction
+        if let (Some(frame), Some((ip, _, _))) = (frames.last_mut(), vm.next_instruction()) {
+            frame.position = vm.source_map().position(ip).or(frame.position);
+        }
+        let frames: Vec<Json> = frames
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(id, frame)| {
+                // Frames without debug symbols are at line 0
+                let (line, column) = frame
+                    .position
+                    .map_or((0, 0), |position| (position.line, position.column));
+                json!({
+                    "id": id,
+                    "name": frame.function,
+                    "source": { "path": self.program },
+                    "line": line,
+                    "column": column,
+                })
+            })
+            .collect();
+        Ok(json!({ "stackFrames": frames, "totalFrames": frames.len() }))
+    }
+
+    /// Returns the locals, for the running function only, and the globals.
+    fn scopes(&self, arguments: &Json) -> Result<Json, String> {
+        let vm = self.debugger()?.vm();
+        let mut scopes = Vec::new();
+        if arguments["frameId"].as_u64() == Some(vm.depth() as u64) && vm.depth() > 0 {
+            scopes.push(json!({
+                "name": "Locals",
+                "variablesReference": LOCALS_REFERENCE,
+                "expensive": false,
+            }));
+        }
+        scopes.push(json!({
+            "name": "Globals",
+            "variablesReference": GLOBALS_REFERENCE,
+            "expensive": false,
+        }));
+        Ok(json!({ "scopes": scopes }))
+    }
+
+    /// Returns the variables of a scope. The locals are named by their index,
+    /// the parameters first, as the compiled functions do not keep their
+    /// names.
+    fn variables(&self, arguments: &Json) -> Result<Json, String> {
+        let debugger = self.debugger()?;
+        let variables: Vec<(String, String)> = match arguments["variablesReference"].as_i64() {
+            Some(LOCALS_REFERENCE) => debugger
+                .vm()
+                .locals()
+                .iter()
+                .enumerate()
+                .map(|(i, value)| (i.to_string(), describe_value(value)))
+                .collect(),
+            Some(GLOBALS_REFERENCE) => debugger
+                .global_variables()
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+            _ => return Err("Unknown variables reference".to_string()),
+        };
+        let variables: Vec<Json> = variables
+            .into_iter()
+            .map(|(name, value)| json!({ "name": name, "value": value, "variablesReference": 0 }))
+            .collect();
+        Ok(json!({ "variables": variables }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compiler::Compiler, parser::parse, source::Source};
+
+    fn server() -> DapServer<'static> {
+        DapServer::new(|path: &str| {
+            let input = "let add = fn(a, b) {\n  a + b\n};\nlet x = add(1, 2);\nexit(x);\n";
+            let mut compiler = Compiler::new();
+            let program = parse(input).with_source(Source::new(path, input));
+            compiler.compile(&program).map_err(|err| err.to_string())?;
+            Ok(VM::new(compiler.bytecode()))
+        })
+    }
+
+    /// Handles a request and returns the body of its response and the names
+    /// of the events that followed it.
+    fn request(server: &mut DapServer, command: &str, arguments: Json) -> (Json, Vec<Json>) {
+        let mut messages = server.handle(&json!({
+            "seq": 1,
+            "type": "request",
+            "command": command,
+            "arguments": arguments,
+        }));
+        let response = messages.remove(0);
+        assert_eq!(response["type"], "response");
+        assert_eq!(response["success"], true, "{response}");
+        (response["body"].clone(), messages)
+    }
+
+    fn event_names(events: &[Json]) -> Vec<&str> {
+        events
+            .iter()
+            .map(|event| event["event"].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_messages() {
+        let mut output = Vec::new();
+        write_message(&mut output, &json!({ "seq": 1 })).unwrap();
+        assert_eq!(
+            String::from_utf8(output.clone()).unwrap(),
+            "Content-Length: 9\r\n\r\n{\"seq\":1}"
+        );
+        let mut reader = &output[..];
+        assert_eq!(
+            read_message(&mut reader).unwrap(),
+            Some(json!({ "seq": 1 }))
+        );
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+
+        let mut reader = &b"Content-Type: json\r\n\r\n{}"[..];
+        assert!(read_message(&mut reader).is_err());
+    }
+
+    #[test]
+    fn test_session() {
+        let mut server = server();
+        let (capabilities, _) = request(&mut server, "initialize", json!({}));
+        assert_eq!(capabilities["supportsFunctionBreakpoints"], true);
+        let (_, events) = request(&mut server, "launch", json!({ "program": "main.monkey" }));
+        assert_eq!(event_names(&events), vec!["initialized"]);
+        let (body, _) = request(
+            &mut server,
+            "setBreakpoints",
+            json!({ "source": { "path": "main.monkey" }, "breakpoints": [{ "line": 2 }] }),
+        );
+        assert_eq!(body["breakpoints"][0]["verified"], true);
+
+        let (_, events) = request(&mut server, "configurationDone", json!({}));
+        assert_eq!(event_names(&events), vec!["stopped"]);
+        assert_eq!(events[0]["body"]["reason"], "breakpoint");
+        assert_eq!(events[0]["body"]["hitBreakpointIds"], json!([1]));
+
+        let (body, _) = request(&mut server, "stackTrace", json!({ "threadId": 1 }));
+        let frames = &body["stackFrames"];
+        assert_eq!(frames[0]["name"], "add");
+        assert_eq!(frames[0]["line"], 2);
+        assert_eq!(frames[1]["name"], "main");
+        assert_eq!(frames[1]["line"], 4);
+
+        let (body, _) = request(&mut server, "scopes", json!({ "frameId": 1 }));
+        assert_eq!(body["scopes"][0]["name"], "Locals");
+        let (body, _) = request(
+            &mut server,
+            "variables",
+            json!({ "variablesReference": LOCALS_REFERENCE }),
+        );
+        assert_eq!(
+            body["variables"],
+            json!([
+                { "name": "0", "value": "1", "variablesReference": 0 },
+                { "name": "1", "value": "2", "variablesReference": 0 },
+            ])
+        );
+
+        // Returns from `add`, then sets `x`
+        for _ in 0..2 {
+            let (_, events) = request(&mut server, "next", json!({ "threadId": 1 }));
+            assert_eq!(events[0]["body"]["reason"], "step");
+        }
+        let (body, _) = request(
+            &mut server,
+            "variables",
+            json!({ "variablesReference": GLOBALS_REFERENCE }),
+        );
+        assert_eq!(body["variables"][1]["name"], "x");
+        assert_eq!(body["variables"][1]["value"], "3");
+
+        let (_, events) = request(&mut server, "continue", json!({ "threadId": 1 }));
+        assert_eq!(event_names(&events), vec!["exited", "terminated"]);
+        assert_eq!(events[0]["body"]["exitCode"], 3);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut server = server();
+        let response = server.handle(&json!({ "seq": 4, "command": "evaluate" }));
+        assert_eq!(response[0]["seq"], 1);
+        assert_eq!(response[0]["request_seq"], 4);
+        assert_eq!(response[0]["success"], false);
+        assert_eq!(response[0]["message"], "Unsupported request: evaluate");
+
+        let response = server.handle(&json!({ "seq": 5, "command": "stackTrace" }));
+        assert_eq!(response[0]["message"], "No program is running");
+    }
+}
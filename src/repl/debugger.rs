@@ -0,0 +1,611 @@
+use std::{
+    fmt::{self, Display, Formatter, Write},
+    path::Path,
+};
+
+use crate::{
+    compiler::{code::Instructions, code::Opcode, symbol_table::SymbolScope},
+    vm::{describe_value, error::VmError, value::Value, VM},
+};
+
+const HELP: &str = "\
+step, s       run the next instruction
+next, n       run until the end of the current statement
+continue, c   run until the end of the program
+break, b      stop at a function or a line, `break <name>|[<file>:]<line>`
+breakpoints   show the breakpoints
+delete, d     delete a breakpoint, `delete <number>`
+stack         show the stack, from the bottom to the top
+locals        show the locals of the running function
+globals       show the global variables
+constants     show the constants
+help, h       show this help
+quit, q       stop debugging
+An empty line repeats the last command.
+";
+
+/// A place where `next` and `continue` stop the program.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Breakpoint {
+    /// The start of every call to the functions bound to this name.
+    Function(String),
+
+    /// A line of a file, or of the debugged file if it is `None`.
+    Line { file: Option<String>, line: usize },
+}
+
+impl Display for Breakpoint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Breakpoint::Function(name) => write!(f, "fn {name}"),
+            Breakpoint::Line {
+                file: Some(file),
+                line,
+            } => write!(f, "{file}:{line}"),
+            Breakpoint::Line { file: None, line } => write!(f, "line {line}"),
+        }
+    }
+}
+
+impl Breakpoint {
+    /// Parses `name`, `line` or `file:line`.
+    fn parse(breakpoint: &str) -> Breakpoint {
+        if let Ok(line) = breakpoint.parse() {
+            return Breakpoint::Line { file: None, line };
+        }
+        match breakpoint
+            .rsplit_once(':')
+            .map(|(file, line)| (file, line.parse()))
+        {
+            Some((file, Ok(line))) => Breakpoint::Line {
+                file: Some(file.to_string()),
+                line,
+            },
+            _ => Breakpoint::Function(breakpoint.to_string()),
+        }
+    }
+}
+
+/// A command of the debugger.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DebugCommand {
+    Step,
+    Next,
+    Continue,
+    Break(Breakpoint),
+    Breakpoints,
+    Delete(usize),
+    Stack,
+    Locals,
+    Globals,
+    Constants,
+    Help,
+    Quit,
+}
+
+impl DebugCommand {
+    pub fn parse(line: &str) -> Result<DebugCommand, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words[..] {
+            ["step" | "s"] => Ok(DebugCommand::Step),
+            ["next" | "n"] => Ok(DebugCommand::Next),
+            ["continue" | "c"] => Ok(DebugCommand::Continue),
+            ["break" | "b", breakpoint] => Ok(DebugCommand::Break(Breakpoint::parse(breakpoint))),
+            ["break" | "b", ..] => Err("Usage: break <name>|<file>:<line>".to_string()),
+            ["breakpoints"] => Ok(DebugCommand::Breakpoints),
+            ["delete" | "d", number] => number
+                .parse()
+                .map(DebugCommand::Delete)
+                .map_err(|_| "Usage: delete <number>".to_string()),
+            ["stack"] => Ok(DebugCommand::Stack),
+            ["locals"] => Ok(DebugCommand::Locals),
+            ["globals"] => Ok(DebugCommand::Globals),
+            ["constants"] => Ok(DebugCommand::Constants),
+            ["help" | "h"] => Ok(DebugCommand::Help),
+            ["quit" | "q"] => Ok(DebugCommand::Quit),
+            _ => Err(format!(
+                "Unknown command: {}, type `help` to see the commands",
+                line.trim()
+            )),
+        }
+    }
+}
+
+/// Runs a program in a VM one instruction, or one statement, at a time and
+/// shows its state between them.
+pub struct Debugger {
+    vm: VM,
+
+    /// Error which stopped the program, it cannot run any further.
+    error: Option<VmError>,
+
+    /// Breakpoints by number, starting at 1. Deleted ones are `None`, so that
+    /// the others keep their number.
+    breakpoints: Vec<Option<Breakpoint>>,
+}
+
+impl Debugger {
+    pub fn new(vm: VM) -> Self {
+        Self {
+            vm,
+            error: None,
+            breakpoints: Vec::new(),
+        }
+    }
+
+    /// Returns the code given to `exit`, if the program called it.
+    pub fn exit_code(&self) -> Option<i64> {
+        self.vm.exit_code()
+    }
+
+    /// Executes a command and returns what it shows. `Quit` is left to the
+    /// caller.
+    pub fn execute(&mut self, command: &DebugCommand) -> String {
+        match command {
+            DebugCommand::Step => {
+                self.resume(command);
+                self.location()
+            }
+            DebugCommand::Next | DebugCommand::Continue => {
+                let hit = self.resume(command);
+                self.stop_message(hit)
+            }
+            DebugCommand::Break(breakpoint) => self.add_breakpoint(breakpoint),
+            DebugCommand::Breakpoints => self
+                .breakpoints
+                .iter()
+                .enumerate()
+                .filter_map(|(i, breakpoint)| {
+                    Some(format!("{}: {}\n", i + 1, breakpoint.as_ref()?))
+                })
+                .collect(),
+            DebugCommand::Delete(number) => {
+                match number
+                    .checked_sub(1)
+                    .and_then(|i| self.breakpoints.get_mut(i))
+                    .and_then(Option::take)
+                {
+                    Some(breakpoint) => format!("Deleted breakpoint {number}, {breakpoint}\n"),
+                    None => format!("There is no breakpoint {number}\n"),
+                }
+            }
+            DebugCommand::Stack => list(self.vm.stack()),
+            DebugCommand::Locals => list(self.vm.locals()),
+            DebugCommand::Globals => self.globals(),
+            DebugCommand::Constants => {
+                let constants: Vec<Value> = self
+                    .vm
+                    .constants()
+                    .iter()
+                    .cloned()
+                    .map(Value::from)
+                    .collect();
+                list(&constants)
+            }
+            DebugCommand::Help => HELP.to_string(),
+            DebugCommand::Quit => String::new(),
+        }
+    }
+
+    /// Runs the program for a `Step`, a `Next` or a `Continue`, and returns
+    /// the number of the breakpoint it stopped at, if any. The other commands
+    /// do nothing.
+    pub fn resume(&mut self, command: &DebugCommand) -> Option<usize> {
+        match command {
+            DebugCommand::Step => self.run_while(|_, _| false),
+            DebugCommand::Next => {
+                let depth = self.vm.depth();
+                self.run_while(|op, op_depth| op_depth > depth || !ends_statement(op))
+            }
+            DebugCommand::Continue => self.run_while(|_, _| true),
+            _ => None,
+        }
+    }
+
+    /// Returns the names and the values of the global variables, in the order
+    /// they were defined.
+    pub fn global_variables(&self) -> Vec<(&str, String)> {
+        let mut globals: Vec<(usize, &str)> = self
+            .vm
+            .symbol_table()
+            .symbols()
+            .filter(|symbol| symbol.scope == SymbolScope::Global)
+            .map(|symbol| (symbol.index, symbol.name.as_str()))
+            .collect();
+        globals.sort_unstable();
+        globals
+            .into_iter()
+            .filter_map(|(index, name)| {
+                let value = self.vm.globals.get(index)?;
+                Some((name, describe_value(value)))
+            })
+            .collect()
+    }
+
+    /// Returns where the program is stopped, with the instruction that runs
+    /// next.
+    pub fn location(&self) -> String {
+        if let Some(error) = &self.error {
+            return format!("The program stopped with an error: {error}\n");
+        }
+        match self.vm.next_instruction() {
+            Some((ip, op, operands)) => format!(
+                "{} {ip:04} {}\n",
+                self.vm.function_name(),
+                Instructions::default().format_instruction(op, &op.lookup_widths(), &operands)
+            ),
+            None => match self.vm.exit_code() {
+                Some(code) => format!("The program exited with code {code}\n"),
+                None => "The program finished\n".to_string(),
+            },
+        }
+    }
+
+    fn add_breakpoint(&mut self, breakpoint: &Breakpoint) -> String {
+        if let Breakpoint::Line { file, .. } = breakpoint {
+            let Some(source) = self.vm.source() else {
+                return "Line breakpoints need the source of the program\n".to_string();
+            };
+            match file {
+                Some(file) if !Path::new(&*source.name).ends_with(file) => {
+                    return format!("The program is not in {file}\n");
+                }
+                _ => {}
+            }
+        }
+        self.breakpoints.push(Some(breakpoint.clone()));
+        format!("Breakpoint {}, {breakpoint}\n", self.breakpoints.len())
+    }
+
+    /// Returns the location the program stopped at, after the breakpoint it
+    /// stopped at, if any.
+    fn stop_message(&self, hit: Option<usize>) -> String {
+        match hit {
+            Some(number) => format!("Breakpoint {number}, {}", self.location()),
+            None => self.location(),
+        }
+    }
+
+    /// Runs one instruction, then keeps running them while `keep_going`
+    /// returns true for the opcode and the call depth of the last one, until a
+    /// breakpoint is reached. Returns the number of that breakpoint.
+    fn run_while(&mut self, keep_going: impl Fn(Opcode, usize) -> bool) -> Option<usize> {
+        while self.error.is_none() {
+            let (_, op, _) = self.vm.next_instruction()?;
+            let depth = self.vm.depth();
+            if let Err(error) = self.vm.step() {
+                self.error = Some(error);
+                return None;
+            }
+            // A breakpoint on a function stops at the start of every call
+            if self.vm.depth() > depth {
+                if let Some(hit) = self.function_breakpoint() {
+                    return Some(hit);
+                }
+            }
+            if let Some(hit) = self.line_breakpoint() {
+                return Some(hit);
+            }
+            if !keep_going(op, depth) {
+                return None;
+            }
+        }
+        None
+    }
+
+    /// Returns the number of the breakpoint on the running function, if any.
+    fn function_breakpoint(&self) -> Option<usize> {
+        let name = self.vm.function_name();
+        self.breakpoints
+            .iter()
+            .position(|breakpoint| {
+                matches!(breakpoint, Some(Breakpoint::Function(function)) if function == name)
+            })
+            .map(|i| i + 1)
+    }
+
+    /// Returns the number of the breakpoint on the line of the statement which
+    /// starts with the next instruction, if any.
+    fn line_breakpoint(&self) -> Option<usize> {
+        let (ip, _, _) = self.vm.next_instruction()?;
+        let line = self.vm.source_map().statement_at(ip)?.line;
+        self.breakpoints
+            .iter()
+            .position(|breakpoint| {
+                matches!(breakpoint, Some(Breakpoint::Line { line: l, .. }) if *l == line)
+            })
+            .map(|i| i + 1)
+    }
+
+    fn globals(&self) -> String {
+        self.global_variables()
+            .into_iter()
+            .fold(String::new(), |mut output, (name, value)| {
+                let _ = writeln!(output, "{name} = {value}");
+                output
+            })
+    }
+}
+
+/// The primitives of the debug adapter, which inspects the program itself.
+#[cfg(feature = "serde")]
+impl Debugger {
+    /// Returns the VM running the program, to inspect it.
+    pub fn vm(&self) -> &VM {
+        &self.vm
+    }
+
+    /// Returns the error which stopped the program, if any.
+    pub fn error(&self) -> Option<&VmError> {
+        self.error.as_ref()
+    }
+
+    /// Returns true once the program cannot run any further, because it
+    /// finished, exited or failed.
+    pub fn is_finished(&self) -> bool {
+        self.error.is_some() || self.vm.is_finished()
+    }
+
+    /// Replaces the line breakpoints by breakpoints on `lines`, and returns
+    /// their numbers.
+    pub fn set_line_breakpoints(&mut self, lines: &[usize]) -> Vec<usize> {
+        self.replace_breakpoints(
+            |breakpoint| matches!(breakpoint, Breakpoint::Line { .. }),
+            lines
+                .iter()
+                .map(|&line| Breakpoint::Line { file: None, line }),
+        )
+    }
+
+    /// Replaces the function breakpoints by breakpoints on `names`, and
+    /// returns their numbers.
+    pub fn set_function_breakpoints(&mut self, names: &[String]) -> Vec<usize> {
+        self.replace_breakpoints(
+            |breakpoint| matches!(breakpoint, Breakpoint::Function(_)),
+            names.iter().cloned().map(Breakpoint::Function),
+        )
+    }
+
+    fn replace_breakpoints(
+        &mut self,
+        replaced: impl Fn(&Breakpoint) -> bool,
+        breakpoints: impl Iterator<Item = Breakpoint>,
+    ) -> Vec<usize> {
+        for breakpoint in &mut self.breakpoints {
+            if breakpoint.as_ref().is_some_and(&replaced) {
+                *breakpoint = None;
+            }
+        }
+        breakpoints
+            .map(|breakpoint| {
+                self.breakpoints.push(Some(breakpoint));
+                self.breakpoints.len()
+            })
+            .collect()
+    }
+}
+
+/// Returns true for the instructions which end a statement: the result of an
+/// expression is dropped, a variable is set or a function returns.
+fn ends_statement(op: Opcode) -> bool {
+    matches!(
+        op,
+        Opcode::Pop | Opcode::SetGlobal | Opcode::SetLocal | Opcode::ReturnValue | Opcode::Return
+    )
+}
+
+fn list(values: &[Value]) -> String {
+    if values.is_empty() {
+        return "<empty>\n".to_string();
+    }
+    values
+        .iter()
+        .enumerate()
+        .fold(String::new(), |mut output, (i, value)| {
+            let _ = writeln!(output, "{i}: {}", describe_value(value));
+            output
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compiler::Compiler, parser::parse, source::Source};
+
+    fn start(input: &str) -> Debugger {
+        let mut compiler = Compiler::new();
+        let program = parse(input).with_source(Source::new("main.monkey", input));
+        compiler.compile(&program).unwrap();
+        Debugger::new(VM::new(compiler.bytecode()))
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(DebugCommand::parse(" s "), Ok(DebugCommand::Step));
+        assert_eq!(DebugCommand::parse("next"), Ok(DebugCommand::Next));
+        assert_eq!(DebugCommand::parse("c"), Ok(DebugCommand::Continue));
+        assert_eq!(DebugCommand::parse("globals"), Ok(DebugCommand::Globals));
+        assert_eq!(
+            DebugCommand::parse("b add"),
+            Ok(DebugCommand::Break(Breakpoint::Function("add".to_string())))
+        );
+        assert_eq!(
+            DebugCommand::parse("break main.monkey:12"),
+            Ok(DebugCommand::Break(Breakpoint::Line {
+                file: Some("main.monkey".to_string()),
+                line: 12
+            }))
+        );
+        assert_eq!(
+            DebugCommand::parse("break 3"),
+            Ok(DebugCommand::Break(Breakpoint::Line {
+                file: None,
+                line: 3
+            }))
+        );
+        assert_eq!(
+            DebugCommand::parse("break"),
+            Err("Usage: break <name>|<file>:<line>".to_string())
+        );
+        assert_eq!(DebugCommand::parse("delete 2"), Ok(DebugCommand::Delete(2)));
+        assert_eq!(
+            DebugCommand::parse("delete all"),
+            Err("Usage: delete <number>".to_string())
+        );
+        assert_eq!(
+            DebugCommand::parse("jump"),
+            Err("Unknown command: jump, type `help` to see the commands".to_string())
+        );
+    }
+
+    #[test]
+    fn test_step_and_inspect() {
+        let mut debugger = start("let add = fn(a, b) { a + b }; let x = add(1, 2); x");
+        assert_eq!(debugger.location(), "main 0000 OpClosure 0 0\n");
+
+        assert_eq!(
+            debugger.execute(&DebugCommand::Step),
+            "main 0004 OpSetGlobal 0\n"
+        );
+        assert_eq!(debugger.execute(&DebugCommand::Stack), "0: fn add\n");
+        assert_eq!(
+            debugger.execute(&DebugCommand::Next),
+            "main 0007 OpGetGlobal 0\n"
+        );
+        // `x` is not set yet
+        assert_eq!(
+            debugger.execute(&DebugCommand::Globals),
+            "add = fn add\nx = null\n"
+        );
+
+        for _ in 0..4 {
+            debugger.execute(&DebugCommand::Step);
+        }
+        assert_eq!(debugger.location(), "add 0000 OpGetLocal 0\n");
+        assert_eq!(debugger.execute(&DebugCommand::Locals), "0: 1\n1: 2\n");
+
+        // Runs the body of the function, up to its return
+        assert_eq!(
+            debugger.execute(&DebugCommand::Next),
+            "main 0018 OpSetGlobal 1\n"
+        );
+        assert_eq!(debugger.execute(&DebugCommand::Stack), "0: 3\n");
+        assert_eq!(
+            debugger.execute(&DebugCommand::Next),
+            "main 0021 OpGetGlobal 1\n"
+        );
+        assert_eq!(
+            debugger.execute(&DebugCommand::Globals),
+            "add = fn add\nx = 3\n"
+        );
+        assert_eq!(
+            debugger.execute(&DebugCommand::Continue),
+            "The program finished\n"
+        );
+        assert_eq!(
+            debugger.execute(&DebugCommand::Step),
+            "The program finished\n"
+        );
+    }
+
+    #[test]
+    fn test_function_breakpoints() {
+        let mut debugger = start(
+            "let twice = fn(x) { x * 2 }; let add = fn(a, b) { twice(a) + b }; add(1, 2) + add(3, 4)",
+        );
+        let add = |name: &str| DebugCommand::Break(Breakpoint::Function(name.to_string()));
+        assert_eq!(debugger.execute(&add("twice")), "Breakpoint 1, fn twice\n");
+        assert_eq!(debugger.execute(&add("add")), "Breakpoint 2, fn add\n");
+        assert_eq!(
+            debugger.execute(&DebugCommand::Breakpoints),
+            "1: fn twice\n2: fn add\n"
+        );
+
+        assert_eq!(
+            debugger.execute(&DebugCommand::Continue),
+            "Breakpoint 2, add 0000 OpGetGlobal 0\n"
+        );
+        assert_eq!(debugger.execute(&DebugCommand::Locals), "0: 1\n1: 2\n");
+        // `next` stops in the functions it calls if they have a breakpoint
+        assert_eq!(
+            debugger.execute(&DebugCommand::Next),
+            "Breakpoint 1, twice 0000 OpGetLocal 0\n"
+        );
+
+        assert_eq!(
+            debugger.execute(&DebugCommand::Delete(1)),
+            "Deleted breakpoint 1, fn twice\n"
+        );
+        assert_eq!(
+            debugger.execute(&DebugCommand::Delete(1)),
+            "There is no breakpoint 1\n"
+        );
+        assert_eq!(
+            debugger.execute(&DebugCommand::Continue),
+            "Breakpoint 2, add 0000 OpGetGlobal 0\n"
+        );
+        assert_eq!(debugger.execute(&DebugCommand::Locals), "0: 3\n1: 4\n");
+        assert_eq!(
+            debugger.execute(&DebugCommand::Continue),
+            "The program finished\n"
+        );
+    }
+
+    #[test]
+    fn test_line_breakpoints() {
+        let mut debugger =
+            start("let add = fn(a, b) {\n  a + b\n};\nlet x = add(1, 2);\nadd(x, 3);\n");
+        let line = |file: Option<&str>, line| {
+            DebugCommand::Break(Breakpoint::Line {
+                file: file.map(str::to_string),
+                line,
+            })
+        };
+        assert_eq!(debugger.execute(&line(None, 2)), "Breakpoint 1, line 2\n");
+        assert_eq!(
+            debugger.execute(&line(Some("main.monkey"), 5)),
+            "Breakpoint 2, main.monkey:5\n"
+        );
+        assert_eq!(
+            debugger.execute(&line(Some("other.monkey"), 5)),
+            "The program is not in other.monkey\n"
+        );
+
+        assert_eq!(
+            debugger.execute(&DebugCommand::Continue),
+            "Breakpoint 1, add 0000 OpGetLocal 0\n"
+        );
+        assert_eq!(
+            debugger.execute(&DebugCommand::Continue),
+            "Breakpoint 2, main 0021 OpGetGlobal 0\n"
+        );
+        assert_eq!(
+            debugger.execute(&DebugCommand::Globals),
+            "add = fn add\nx = 3\n"
+        );
+        assert_eq!(
+            debugger.execute(&DebugCommand::Continue),
+            "Breakpoint 1, add 0000 OpGetLocal 0\n"
+        );
+        assert_eq!(
+            debugger.execute(&DebugCommand::Continue),
+            "The program finished\n"
+        );
+    }
+
+    #[test]
+    fn test_errors_and_exit() {
+        let mut debugger = start("1 + true; 2");
+        assert_eq!(
+            debugger.execute(&DebugCommand::Continue),
+            "The program stopped with an error: Unsupported types for binary operation\n"
+        );
+
+        let mut debugger = start("exit(3); 2");
+        assert_eq!(
+            debugger.execute(&DebugCommand::Continue),
+            "The program exited with code 3\n"
+        );
+        assert_eq!(debugger.exit_code(), Some(3));
+    }
+}
@@ -0,0 +1,216 @@
+use std::fmt::Write;
+
+use crate::{
+    compiler::{
+        code::{DisasmOptions, Instructions, Opcode},
+        symbol_table::{SymbolScope, SymbolTable},
+        Bytecode,
+    },
+    object::{builtins::BuiltinFunction, CompiledFunction, Object},
+    vm::value::Value,
+};
+
+/// Returns the disassembly of a whole program: its main instructions, then
+/// every compiled function among its constants. Instructions are annotated
+/// with the constants, globals and builtins they refer to.
+pub fn disassemble_bytecode(bytecode: &Bytecode) -> String {
+    disassemble_since(bytecode, 0)
+}
+
+/// Returns the disassembly of the main instructions of `bytecode`, then of the
+/// compiled functions among its constants from `first_constant` on, those
+/// added by the last line of the REPL.
+pub fn disassemble_since(bytecode: &Bytecode, first_constant: usize) -> String {
+    let options = DisasmOptions {
+        hex: false,
+        jump_labels: true,
+    };
+    let mut globals: Vec<Option<&str>> = Vec::new();
+    for symbol in bytecode.symbol_table.symbols() {
+        if symbol.scope == SymbolScope::Global {
+            if globals.len() <= symbol.index {
+                globals.resize(symbol.index + 1, None);
+            }
+            globals[symbol.index] = Some(&symbol.name);
+        }
+    }
+    let builtins = BuiltinFunction::get_builtins_names();
+
+    let annotate = |op: Opcode, operands: &[i32]| {
+        let operand = *operands.first()? as usize;
+        match op {
+            _ if !op.constant_operands().is_empty() => op
+                .constant_operands()
+                .iter()
+                .map(|i| {
+                    let constant = bytecode.constants.get(operands[*i] as usize)?;
+                    Some(describe_constant(constant))
+                })
+                .collect::<Option<Vec<String>>>()
+                .map(|constants| constants.join(", ")),
+            Opcode::GetGlobal | Opcode::SetGlobal => {
+                globals.get(operand).copied().flatten().map(String::from)
+            }
+            Opcode::GetBuiltin => builtins.get(operand).cloned(),
+            _ => None,
+        }
+    };
+
+    let mut output = format!(
+        "main:\n{}",
+        bytecode
+            .instructions
+            .disassemble_annotated(&options, annotate)
+    );
+    for (i, constant) in bytecode.constants.iter().enumerate().skip(first_constant) {
+        if let Object::COMPILEDFUNCTION(function) = constant {
+            let _ = write!(
+                output,
+                "\nconstant {i}, {}: {} parameters, {} locals\n{}",
+                describe_constant(constant),
+                function.num_parameters,
+                function.num_locals,
+                Instructions::new(function.instructions.clone())
+                    .disassemble_annotated(&options, annotate)
+            );
+        }
+    }
+    output
+}
+
+fn describe_constant(constant: &Object) -> String {
+    match constant {
+        Object::COMPILEDFUNCTION(function) => {
+            format!("fn {}", function.name.as_deref().unwrap_or("<anonymous>"))
+        }
+        other => other.inspect(),
+    }
+}
+
+/// Returns the annotated disassembly of the function bound to the global `name`.
+pub fn disassemble_global(
+    symbol_table: &SymbolTable,
+    globals: &[Value],
+    name: &str,
+) -> Result<String, String> {
+    let symbol = symbol_table
+        .symbols()
+        .find(|symbol| symbol.name == name)
+        .ok_or_else(|| format!("identifier not found: {name}"))?;
+
+    match symbol.scope {
+        SymbolScope::Global => {}
+        SymbolScope::Builtin => return Err(format!("`{name}` is a builtin function")),
+        _ => return Err(format!("`{name}` is not a global")),
+    }
+
+    match globals.get(symbol.index).map(Value::to_object).as_ref() {
+        Some(Object::CLOSURE(closure)) => Ok(disassemble_function(
+            name,
+            &closure.function,
+            closure.free.len(),
+        )),
+        Some(Object::COMPILEDFUNCTION(function)) => Ok(disassemble_function(name, function, 0)),
+        Some(other) => Err(format!(
+            "`{name}` is not a function, got {}",
+            other.get_type()
+        )),
+        None => Err(format!("identifier not found: {name}")),
+    }
+}
+
+fn disassemble_function(name: &str, function: &CompiledFunction, free: usize) -> String {
+    let options = DisasmOptions {
+        hex: true,
+        jump_labels: true,
+    };
+    format!(
+        "fn {name}: {} parameters, {} locals, {free} free variables\n{}",
+        function.num_parameters,
+        function.num_locals,
+        Instructions::new(function.instructions.clone()).disassemble(&options)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compiler::Compiler, parser::parse, vm::VM};
+
+    fn run(input: &str) -> (SymbolTable, Vec<Value>) {
+        let mut compiler = Compiler::new();
+        compiler.compile(&parse(input)).unwrap();
+        let mut vm = VM::new(compiler.bytecode());
+        vm.run().unwrap();
+        (compiler.symbol_table, vm.globals)
+    }
+
+    #[test]
+    fn test_disassemble_bytecode() {
+        let mut compiler = Compiler::new();
+        compiler
+            .compile(&parse(
+                r#"let greet = fn(name) { puts("hi " + name) }; greet("x");"#,
+            ))
+            .unwrap();
+
+        let expected = "main:
+0000 OpClosure 1 0 // fn greet
+0004 OpSetGlobal 0 // greet
+0007 OpGetGlobal 0 // greet
+0010 OpConstant 2 // \"x\"
+0013 OpCall 1
+0015 OpPop
+
+constant 1, fn greet: 1 parameters, 1 locals
+0000 OpGetBuiltin 5 // puts
+0002 OpConstant 0 // \"hi \"
+0005 OpGetLocal 0
+0007 OpAdd
+0008 OpCall 1
+0010 OpReturnValue
+";
+        assert_eq!(disassemble_bytecode(&compiler.bytecode()), expected);
+    }
+
+    #[test]
+    fn test_disassemble_since() {
+        let mut compiler = Compiler::new();
+        compiler.compile(&parse("let one = fn() { 1 };")).unwrap();
+        let first_constant = compiler.bytecode().constants.len();
+        compiler
+            .compile(&parse("let two = fn() { one() + 1 }; two()"))
+            .unwrap();
+
+        let disassembly = disassemble_since(&compiler.bytecode(), first_constant);
+        assert!(!disassembly.contains("fn one:"));
+        assert!(disassembly.contains("constant 3, fn two: 0 parameters, 0 locals\n"));
+        assert!(disassembly.contains("OpGetGlobal 0 // one\n"));
+    }
+
+    #[test]
+    fn test_disassemble_global() {
+        let (symbol_table, globals) = run("let add = fn(a, b) { a + b }; let x = 1;");
+
+        let disassembly = disassemble_global(&symbol_table, &globals, "add").unwrap();
+        let mut lines = disassembly.lines();
+        assert_eq!(
+            lines.next(),
+            Some("fn add: 2 parameters, 2 locals, 0 free variables")
+        );
+        assert!(lines.any(|line| line.ends_with("OpAdd")));
+
+        assert_eq!(
+            disassemble_global(&symbol_table, &globals, "x"),
+            Err("`x` is not a function, got INTEGER".to_string())
+        );
+        assert_eq!(
+            disassemble_global(&symbol_table, &globals, "len"),
+            Err("`len` is a builtin function".to_string())
+        );
+        assert_eq!(
+            disassemble_global(&symbol_table, &globals, "y"),
+            Err("identifier not found: y".to_string())
+        );
+    }
+}
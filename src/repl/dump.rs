@@ -0,0 +1,194 @@
+use clap_derive::ValueEnum;
+
+use crate::{
+    highlight,
+    lexer::{token::Token, Lexer},
+    parser::ast::Program,
+    source::LineIndex,
+};
+
+/// Format of the programs printed by `--dump-ast`, of the tokens printed by
+/// `--dump-tokens` and of the files printed by `highlight`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum DumpFormat {
+    /// Monkey code, as the parser mode prints it
+    Display,
+    /// Pretty printed JSON of the syntax tree, with the `serde` feature
+    Json,
+    /// Monkey code colored for a terminal
+    Ansi,
+    /// Monkey code in an HTML block, with a CSS class for every kind of token
+    Html,
+}
+
+/// Error of the JSON format in the builds without the `serde` feature.
+#[cfg(not(feature = "serde"))]
+pub const JSON_UNAVAILABLE: &str = "JSON output needs the `serde` feature";
+
+/// Returns the syntax tree of `program` in `format`.
+pub fn dump_ast(program: &Program, format: DumpFormat) -> Result<String, String> {
+    match format {
+        DumpFormat::Display => Ok(program.to_string()),
+        DumpFormat::Ansi => Ok(highlight::ansi(&program.to_string())),
+        DumpFormat::Html => Ok(highlight::html(&program.to_string())),
+        #[cfg(feature = "serde")]
+        DumpFormat::Json => serde_json::to_string_pretty(program)
+            .map(|json| json + "\n")
+            .map_err(|err| err.to_string()),
+        #[cfg(not(feature = "serde"))]
+        DumpFormat::Json => Err(JSON_UNAVAILABLE.to_string()),
+    }
+}
+
+/// A token with the offsets of its first character and of the character
+/// after it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Returns the tokens of `text`, up to and including the end of file.
+pub fn tokenize(text: &str) -> Vec<SpannedToken> {
+    let mut lexer = Lexer::new(text);
+    let mut tokens = Vec::new();
+    loop {
+        let token = lexer.next_token();
+        let end = token == Token::Eof;
+        tokens.push(SpannedToken {
+            token,
+            start: lexer.token_offset(),
+            end: lexer.token_end(),
+        });
+        if end {
+            return tokens;
+        }
+    }
+}
+
+/// Returns the tokens of `text` in `format`, with their kind, their text and
+/// their positions.
+pub fn dump_tokens(
+    text: &str,
+    tokens: &[SpannedToken],
+    format: DumpFormat,
+) -> Result<String, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let index = LineIndex::new(text);
+    let tokens = tokens.iter().map(|SpannedToken { token, start, end }| {
+        let kind = format!("{token:?}");
+        let kind = kind.split('(').next().unwrap_or_default().to_string();
+        let text: String = chars[*start..*end].iter().collect();
+        (kind, text, *start, *end)
+    });
+    match format {
+        DumpFormat::Display => Ok(tokens
+            .map(|(kind, text, start, end)| {
+                let span = format!("{}-{}", index.position(start), index.position(end));
+                let line = format!("{span:<12} {kind:<10} {text}");
+                line.trim_end().to_string() + "\n"
+            })
+            .collect()),
+        #[cfg(feature = "serde")]
+        DumpFormat::Json => {
+            let tokens: Vec<serde_json::Value> = tokens
+                .map(|(kind, text, start, end)| {
+                    let position = index.position(start);
+                    serde_json::json!({
+                        "kind": kind,
+                        "text": text,
+                        "start": start,
+                        "end": end,
+                        "line": position.line,
+                        "column": position.column,
+                    })
+                })
+                .collect();
+            serde_json::to_string_pretty(&tokens)
+                .map(|json| json + "\n")
+                .map_err(|err| err.to_string())
+        }
+        #[cfg(not(feature = "serde"))]
+        DumpFormat::Json => Err(JSON_UNAVAILABLE.to_string()),
+        DumpFormat::Ansi | DumpFormat::Html => {
+            Err("The tokens are printed in the display or json format".to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    #[test]
+    fn test_dump_ast() {
+        let program = parse("let a = 1 + 2;");
+        assert_eq!(
+            dump_ast(&program, DumpFormat::Display).unwrap(),
+            "let a = (1 + 2);\n"
+        );
+        assert_eq!(
+            dump_ast(&program, DumpFormat::Html).unwrap(),
+            highlight::html("let a = (1 + 2);\n")
+        );
+
+        #[cfg(feature = "serde")]
+        {
+            let json = dump_ast(&program, DumpFormat::Json).unwrap();
+            let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+            assert_eq!(value["statements"][0]["Let"]["name"]["value"], "a");
+            assert_eq!(value["offsets"][0], 0);
+        }
+        #[cfg(not(feature = "serde"))]
+        assert_eq!(
+            dump_ast(&program, DumpFormat::Json),
+            Err(JSON_UNAVAILABLE.to_string())
+        );
+    }
+
+    #[test]
+    fn test_dump_tokens() {
+        let input = "let s = \"hi\";\n  s";
+        let tokens = tokenize(input);
+        assert_eq!(
+            tokens[3],
+            SpannedToken {
+                token: Token::String("hi".into()),
+                start: 8,
+                end: 12,
+            }
+        );
+        let expected = "\
+1:1-1:4      Let        let
+1:5-1:6      Ident      s
+1:7-1:8      Assign     =
+1:9-1:13     String     \"hi\"
+1:13-1:14    Semicolon  ;
+2:3-2:4      Ident      s
+2:4-2:4      Eof\n";
+        assert_eq!(
+            dump_tokens(input, &tokens, DumpFormat::Display).unwrap(),
+            expected
+        );
+
+        #[cfg(feature = "serde")]
+        {
+            let json = dump_tokens(input, &tokens, DumpFormat::Json).unwrap();
+            let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+            assert_eq!(
+                value[5],
+                serde_json::json!({
+                    "kind": "Ident",
+                    "text": "s",
+                    "start": 16,
+                    "end": 17,
+                    "line": 2,
+                    "column": 3,
+                })
+            );
+        }
+        assert!(dump_tokens(input, &tokens, DumpFormat::Ansi).is_err());
+    }
+}
@@ -1,8 +1,11 @@
 use std::{
     error::Error,
     fmt::{Display, Formatter},
+    rc::Rc,
 };
 
+use crate::{object::StackFrame, source::Position};
+
 #[allow(clippy::module_name_repetitions)]
 #[derive(Debug)]
 pub struct LexerErrors {
@@ -63,21 +66,209 @@ impl Display for CompilerError {
 
 impl Error for CompilerError {}
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct RuntimeError {
     error: String,
+    file: Option<Rc<str>>,
+    position: Option<Position>,
+    stack_trace: Vec<StackFrame>,
 }
 
 impl RuntimeError {
     pub fn new(error: String) -> RuntimeError {
-        RuntimeError { error }
+        RuntimeError {
+            error,
+            file: None,
+            position: None,
+            stack_trace: Vec::new(),
+        }
+    }
+
+    /// Sets the file the error happened in.
+    pub fn in_file(mut self, file: Rc<str>) -> RuntimeError {
+        self.file = Some(file);
+        self
+    }
+
+    /// Sets the position of the statement the error happened in.
+    pub fn at(mut self, position: Position) -> RuntimeError {
+        self.position = Some(position);
+        self
+    }
+
+    /// Sets the function calls which were running, from the main program to
+    /// the function which failed. They are only shown if a function failed.
+    pub fn with_stack_trace(mut self, stack_trace: Vec<StackFrame>) -> RuntimeError {
+        self.stack_trace = stack_trace;
+        self
     }
 }
 
 impl Display for RuntimeError {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
-        writeln!(f, "Runtime error:\n\t{}", self.error)
+        match (&self.file, self.position) {
+            (Some(file), Some(position)) => {
+                writeln!(f, "Runtime error in {file}:{position}:\n\t{}", self.error)
+            }
+            (Some(file), None) => writeln!(f, "Runtime error in {file}:\n\t{}", self.error),
+            (None, Some(position)) => {
+                writeln!(f, "Runtime error at {position}:\n\t{}", self.error)
+            }
+            (None, None) => writeln!(f, "Runtime error:\n\t{}", self.error),
+        }?;
+        if self.stack_trace.len() < 2 {
+            return Ok(());
+        }
+        writeln!(f, "Stack trace, most recent call last:")?;
+        // Deep recursions would bury the error under identical frames, they
+        // are shown once with their number of repetitions
+        for frames in self.stack_trace.chunk_by(|a, b| a == b) {
+            let frame = &frames[0];
+            match (&self.file, frame.position) {
+                (Some(file), Some(position)) => {
+                    writeln!(f, "\tat {} ({file}:{position})", frame.function)
+                }
+                (None, Some(position)) => writeln!(f, "\tat {} ({position})", frame.function),
+                (_, None) => writeln!(f, "\tat {}", frame.function),
+            }?;
+            match frames.len() {
+                1 => {}
+                2 => writeln!(f, "\t... previous frame repeated 1 time")?,
+                n => writeln!(f, "\t... previous frame repeated {} times", n - 1)?,
+            }
+        }
+        Ok(())
     }
 }
 
 impl Error for RuntimeError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        interpreter::evaluator::Evaluator,
+        object::{Object, DEFAULT_MAX_DEPTH},
+        parser::parse,
+        source::Source,
+    };
+
+    #[test]
+    fn test_runtime_error_display() {
+        let error = RuntimeError::new("Division by zero".to_string());
+        assert_eq!(error.to_string(), "Runtime error:\n\tDivision by zero\n");
+
+        let error = error.in_file(Rc::from("main.monkey"));
+        assert_eq!(
+            error.to_string(),
+            "Runtime error in main.monkey:\n\tDivision by zero\n"
+        );
+
+        let position = Position { line: 3, column: 5 };
+        assert_eq!(
+            error.at(position).to_string(),
+            "Runtime error in main.monkey:3:5:\n\tDivision by zero\n"
+        );
+        assert_eq!(
+            RuntimeError::new("Division by zero".to_string())
+                .at(position)
+                .to_string(),
+            "Runtime error at 3:5:\n\tDivision by zero\n"
+        );
+    }
+
+    #[test]
+    fn test_stack_trace_display() {
+        let frame = |function: &str, line: Option<usize>| StackFrame {
+            function: function.to_string(),
+            position: line.map(|line| Position { line, column: 5 }),
+        };
+        let error = RuntimeError::new("Division by zero".to_string())
+            .in_file(Rc::from("main.monkey"))
+            .at(Position { line: 3, column: 5 });
+
+        // A failure in the main program has nothing more to show
+        let main = error.clone().with_stack_trace(vec![frame("main", Some(3))]);
+        assert_eq!(
+            main.to_string(),
+            "Runtime error in main.monkey:3:5:\n\tDivision by zero\n"
+        );
+
+        let nested = error.with_stack_trace(vec![
+            frame("main", Some(7)),
+            frame("<anonymous>", None),
+            frame("div", Some(3)),
+        ]);
+        assert_eq!(
+            nested.to_string(),
+            "Runtime error in main.monkey:3:5:
+\tDivision by zero
+Stack trace, most recent call last:
+\tat main (main.monkey:7:5)
+\tat <anonymous>
+\tat div (main.monkey:3:5)
+"
+        );
+    }
+
+    #[test]
+    fn test_repeated_frames_display() {
+        let frame = |function: &str, line: usize| StackFrame {
+            function: function.to_string(),
+            position: Some(Position { line, column: 5 }),
+        };
+        let error = RuntimeError::new("Division by zero".to_string()).with_stack_trace(vec![
+            frame("main", 7),
+            frame("f", 2),
+            frame("f", 2),
+            frame("f", 3),
+            frame("f", 3),
+            frame("f", 3),
+        ]);
+        assert_eq!(
+            error.to_string(),
+            "Runtime error:
+\tDivision by zero
+Stack trace, most recent call last:
+\tat main (7:5)
+\tat f (2:5)
+\t... previous frame repeated 1 time
+\tat f (3:5)
+\t... previous frame repeated 2 times
+"
+        );
+    }
+
+    #[test]
+    fn test_max_depth_display_is_bounded() {
+        let input = "let r = fn(n) { r(n + 1) + 1 };\nr(0);";
+        let mut evaluator = Evaluator::new();
+        let result = evaluator.eval(parse(input).with_source(Source::new("main.monkey", input)));
+        let Object::ERROR(error) = result else {
+            panic!("expected an error, got {result}");
+        };
+        let stack_trace = evaluator.stack_trace();
+        assert_eq!(stack_trace.len(), DEFAULT_MAX_DEPTH + 1);
+
+        let error = RuntimeError::new(error.message)
+            .in_file(Rc::from("main.monkey"))
+            .at(Position {
+                line: 1,
+                column: 17,
+            })
+            .with_stack_trace(stack_trace);
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "Runtime error in main.monkey:1:17:
+\tMaximum call depth of {DEFAULT_MAX_DEPTH} exceeded when calling `r`
+Stack trace, most recent call last:
+\tat main (main.monkey:2:1)
+\tat r (main.monkey:1:17)
+\t... previous frame repeated {} times
+",
+                DEFAULT_MAX_DEPTH - 1
+            )
+        );
+    }
+}
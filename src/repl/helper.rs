@@ -0,0 +1,97 @@
+use std::borrow::Cow;
+
+use rustyline::{
+    completion::Completer, highlight::Highlighter, hint::Hinter, history::DefaultHistory,
+    validate::Validator, Editor, Helper,
+};
+
+use crate::highlight::{spans, unmatched_delimiters, Highlight, ANSI_RESET};
+
+/// Line editor of the REPL, highlighting the code as it is typed.
+pub type ReplEditor = Editor<ReplHelper, DefaultHistory>;
+
+/// Colors the parentheses, brackets and braces which are not matched.
+const UNMATCHED_COLOR: &str = "\x1b[1;37;41m";
+
+/// Colors the line being edited in the REPL like the `highlight` subcommand,
+/// and flags its unmatched delimiters. Lines starting with `:` are commands,
+/// and are left as they are.
+#[derive(Debug, Default)]
+pub struct ReplHelper;
+
+impl ReplHelper {
+    /// Returns a line editor using the helper, which only colors the line if
+    /// the terminal supports it.
+    pub fn editor() -> rustyline::Result<ReplEditor> {
+        let mut editor = ReplEditor::new()?;
+        editor.set_helper(Some(ReplHelper));
+        Ok(editor)
+    }
+}
+
+/// Returns `line` colored with ANSI escape sequences.
+fn highlight_line(line: &str) -> String {
+    let spans = spans(line);
+    let unmatched = unmatched_delimiters(&spans);
+    spans
+        .into_iter()
+        .enumerate()
+        .map(|(i, (highlight, text))| {
+            let color = if unmatched.contains(&i) {
+                Some(UNMATCHED_COLOR)
+            } else {
+                highlight.and_then(Highlight::ansi_color)
+            };
+            match color {
+                Some(color) => format!("{color}{text}{ANSI_RESET}"),
+                None => text,
+            }
+        })
+        .collect()
+}
+
+impl Highlighter for ReplHelper {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        if line.starts_with(':') {
+            return Cow::Borrowed(line);
+        }
+        Cow::Owned(highlight_line(line))
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize, _forced: bool) -> bool {
+        // Any character can change the kind of the tokens after it, such as
+        // a quote
+        true
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight_line() {
+        assert_eq!(
+            highlight_line("let s = \"a\";"),
+            "\x1b[35mlet\x1b[0m s = \x1b[32m\"a\"\x1b[0m;"
+        );
+        assert_eq!(
+            highlight_line("f((1)"),
+            "f\x1b[1;37;41m(\x1b[0m(\x1b[33m1\x1b[0m)"
+        );
+        let helper = ReplHelper;
+        assert_eq!(helper.highlight(":env", 0), ":env");
+    }
+}
@@ -0,0 +1,35 @@
+use std::sync::OnceLock;
+
+use crate::object::interrupt::Interrupt;
+
+/// Interrupt set by Ctrl-C, once its handler is installed.
+static CTRL_C: OnceLock<Interrupt> = OnceLock::new();
+
+/// Returns the interrupt set when Ctrl-C is pressed while a line runs,
+/// installing the handler of `SIGINT` the first time, or `None` if it cannot
+/// be. While a line is typed, the terminal is in raw mode and the editor reads
+/// Ctrl-C itself.
+#[cfg(unix)]
+pub fn ctrl_c() -> Option<Interrupt> {
+    extern "C" fn handle(_: libc::c_int) {
+        // Setting an atomic flag is safe in a signal handler
+        if let Some(interrupt) = CTRL_C.get() {
+            interrupt.interrupt();
+        }
+    }
+
+    if let Some(interrupt) = CTRL_C.get() {
+        return Some(interrupt.clone());
+    }
+    let interrupt = CTRL_C.get_or_init(Interrupt::default);
+    let handler = handle as extern "C" fn(libc::c_int);
+    // SAFETY: the handler only reads the initialized `CTRL_C` and sets its flag
+    let previous = unsafe { libc::signal(libc::SIGINT, handler as libc::sighandler_t) };
+    (previous != libc::SIG_ERR).then(|| interrupt.clone())
+}
+
+/// Returns `None`, Ctrl-C is only handled on Unix.
+#[cfg(not(unix))]
+pub fn ctrl_c() -> Option<Interrupt> {
+    None
+}
@@ -1,41 +1,236 @@
+mod bench;
+mod bindings;
+mod commands;
+#[cfg(feature = "serde")]
+mod dap;
+mod debugger;
+mod disassemble;
+mod dump;
 mod errors;
+mod helper;
+mod interrupt;
+mod profile;
+mod symbols;
 
+#[cfg(feature = "hooks")]
+use crate::hooks::Hooks;
 use crate::{
     compiler::{
-        symbol_table::SymbolTable,
+        code,
+        symbol_table::Definition,
+        warning::CompilerWarning,
         {Bytecode, Compiler},
     },
+    doc,
+    formatter::cli::{check_formatting, format_in_place, format_stream},
+    highlight,
     interpreter::evaluator::Evaluator,
     lexer::{token::Token, Lexer},
-    object::{
-        builtins::BuiltinFunction,
-        {Object, NULL},
-    },
+    linter::{LintConfig, Linter, Rule, Severity},
+    object::{style::OutputStyle, Object, StackFrame, DEFAULT_MAX_DEPTH, NULL},
     parser::{parser_errors::ParserErrors, Parser},
-    repl::errors::{CompilerError, LexerErrors, RuntimeError},
-    vm::{GLOBALS_SIZE, VM},
+    repl::{
+        bench::Timings,
+        bindings::{global_bindings, rebind, Bindings},
+        commands::{ReplCommand, ReplSettings},
+        debugger::{DebugCommand, Debugger},
+        disassemble::{disassemble_bytecode, disassemble_global, disassemble_since},
+        dump::{dump_ast, dump_tokens, tokenize, DumpFormat},
+        errors::{CompilerError, LexerErrors, RuntimeError},
+        helper::{ReplEditor, ReplHelper},
+        interrupt::ctrl_c,
+        profile::Profiler,
+        symbols::dump_symbols,
+    },
+    source::Source,
+    strict::StrictConfig,
+    vm::{
+        error::VmError,
+        new_global_store,
+        register::{compiler::RegisterCompiler, RegisterVM},
+        DEFAULT_MAX_STACK_SIZE, GLOBALS_SIZE, VM,
+    },
 };
 
-use clap_derive::{Parser, ValueEnum};
+use clap_derive::{Parser, Subcommand, ValueEnum};
 use rustyline::{error::ReadlineError, DefaultEditor};
-use std::rc::Rc;
-use std::{error::Error, fs};
+use std::{
+    cell::RefCell,
+    process::ExitCode,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+use std::{error::Error, fs, io};
+#[cfg(feature = "serde")]
+use {
+    crate::repl::dap::DapServer,
+    std::{io::BufReader, net::TcpListener},
+};
+
+/// Name given to the source of the lines typed in the REPL.
+const REPL_SOURCE_NAME: &str = "<repl>";
+
+/// How a REPL session in the interpreter or in the compiler ended.
+enum SessionEnd {
+    Exit(ExitCode),
+    /// `:mode` switched to another engine, which gets the bindings of the
+    /// session.
+    Switch(Mode, Vec<(String, Object)>),
+}
+
+/// What a line typed in the REPL asks for.
+enum ReplInput {
+    /// Code to run, and whether `:time` reports how long it takes.
+    Code(Source, bool),
+    Command(ReplCommand),
+}
+
+/// How the execution of a program or of a REPL line ended.
+enum Outcome {
+    /// The program ran to the end, with the printed result of its last
+    /// expression.
+    Value(String),
+
+    /// The program called `exit` with this code.
+    Exit(i64),
+}
 
 enum InputType {
     File(String),
     Repl,
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 enum Mode {
     Lexer,
     Parser,
     Interpreter,
     Compiler,
+    /// Print the disassembly of the compiled program instead of running it
+    Disassemble,
+}
+
+/// Machine running the compiled programs.
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum Engine {
+    /// The stack VM
+    Stack,
+    /// The experimental register machine, which only runs files
+    Register,
+}
+
+/// Extension of the files containing compiled bytecode.
+const BYTECODE_EXTENSION: &str = ".mkc";
+
+#[derive(Subcommand)]
+enum Command {
+    /// Compile a Monkey file to bytecode
+    Compile {
+        /// Monkey file to compile
+        filename: String,
+
+        /// File to write the bytecode to, by default the input file with the
+        /// `.mkc` extension
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<String>,
+    },
+
+    /// Run a bytecode file produced by `compile`
+    Run {
+        /// Bytecode file to run
+        filename: String,
+    },
+
+    /// Run a Monkey file in the VM one instruction or statement at a time
+    Debug {
+        /// Monkey file to debug
+        filename: String,
+    },
+
+    /// Run a Monkey file several times in the interpreter and in the VM, and
+    /// compare their times
+    Bench {
+        /// Monkey file to run
+        filename: String,
+
+        /// Number of runs of every engine
+        #[arg(short = 'n', long, default_value_t = 10)]
+        runs: usize,
+    },
+
+    /// Serve the debugger over the Debug Adapter Protocol, for editors such as
+    /// VS Code, on a local TCP port
+    #[cfg(feature = "serde")]
+    Dap {
+        /// Port to listen on, the client connects to it
+        #[arg(long, default_value_t = 4711)]
+        port: u16,
+    },
+
+    /// Lex, parse and compile Monkey files without running them, failing if
+    /// one of them has an error
+    Check {
+        /// Monkey files to check
+        #[arg(required = true)]
+        filenames: Vec<String>,
+    },
+
+    /// Format Monkey files in place, and the `.monkey` files of directories
+    Fmt {
+        /// Files and directories to format, or `-` to format the standard
+        /// input to the standard output
+        #[arg(required = true)]
+        paths: Vec<String>,
+
+        /// Print the diffs formatting the files instead of writing them, and
+        /// fail if any file is not formatted
+        #[arg(long)]
+        check: bool,
+    },
+
+    /// Check a Monkey file for likely mistakes, without running it
+    Lint {
+        /// Monkey file to check
+        filename: String,
+
+        /// Do not check RULE
+        #[arg(long, value_name = "RULE")]
+        allow: Vec<Rule>,
+
+        /// Report RULE as a warning, which is the default
+        #[arg(long, value_name = "RULE")]
+        warn: Vec<Rule>,
+
+        /// Report RULE as an error, making the command fail
+        #[arg(long, value_name = "RULE")]
+        deny: Vec<Rule>,
+    },
+
+    /// Print a Monkey file with syntax highlighting, colored for a terminal,
+    /// or as HTML with `--format html`
+    Highlight {
+        /// Monkey file to highlight
+        filename: String,
+    },
+
+    /// Print the documentation of the functions of a Monkey file, from their
+    /// `///` comments, as Markdown
+    Doc {
+        filename: String,
+
+        /// Print a standalone HTML page instead of Markdown
+        #[clap(long)]
+        html: bool,
+    },
 }
 
 #[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true)]
+#[allow(clippy::struct_excessive_bools)] // the flags of the command line
 pub struct ReplCli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Input file, if not specified, the REPL will be launched
     filename: Option<String>,
 
@@ -46,6 +241,130 @@ pub struct ReplCli {
     /// Show the logo
     #[clap(long)]
     logo: bool,
+
+    /// Machine running the program in compiler mode
+    #[arg(long, value_name = "ENGINE", value_enum, default_value_t = Engine::Stack)]
+    engine: Engine,
+
+    /// Number of slots the stack of the VM can grow to, in compiler mode
+    #[arg(long, global = true, value_name = "SLOTS", default_value_t = DEFAULT_MAX_STACK_SIZE)]
+    max_stack_size: usize,
+
+    /// Number of nested function calls a program can make
+    #[arg(long, global = true, value_name = "CALLS", default_value_t = DEFAULT_MAX_DEPTH)]
+    max_depth: usize,
+
+    /// Number of steps a program can run before failing: instructions in
+    /// compiler mode, statements and expressions in interpreter mode
+    #[arg(long, global = true, value_name = "STEPS")]
+    fuel: Option<u64>,
+
+    /// Time after which a program is stopped, such as `5s`, `500ms` or `2m`
+    #[arg(long, global = true, value_name = "DURATION", value_parser = parse_duration)]
+    timeout: Option<Duration>,
+
+    /// Print every instruction executed by the VM to the standard error, in
+    /// compiler mode
+    #[clap(long, global = true)]
+    trace: bool,
+
+    /// Compile every instruction on its own, without fusing the frequent
+    /// sequences into superinstructions, to follow them in a trace or a
+    /// disassembly
+    #[clap(long, global = true)]
+    no_superinstructions: bool,
+
+    /// Print the calls and time of every function to the standard error when
+    /// the program ends, and in compiler mode the runs and time of every opcode
+    #[clap(long, global = true)]
+    profile: bool,
+
+    /// Write the calls of the program to FILE when it ends, in the trace event
+    /// format of Chrome, to view them in Perfetto or as a flamegraph
+    #[arg(
+        long,
+        global = true,
+        value_name = "FILE",
+        num_args = 0..=1,
+        default_missing_value = "trace.json"
+    )]
+    profile_trace: Option<String>,
+
+    /// Print the memory used by the program to the standard error when it
+    /// ends, in compiler mode: the peak number of objects, the constants, the
+    /// globals and the maximum stack size and call depth
+    #[clap(long, global = true)]
+    stats: bool,
+
+    /// Write the lines and functions the program ran to FILE in the LCOV
+    /// format when it ends, and print a summary of them to the standard
+    /// error, in compiler mode
+    #[arg(long, global = true, value_name = "FILE")]
+    coverage: Option<String>,
+
+    /// Print every symbol the program defines to the standard error once it
+    /// is compiled, with its scope, its index and the statement defining it
+    #[clap(long, global = true)]
+    dump_symbols: bool,
+
+    /// Print the parsed program instead of running it, like the parser mode,
+    /// in the format given by `--format`
+    #[clap(long, conflicts_with = "dump_bytecode")]
+    dump_ast: bool,
+
+    /// Print the tokens of the program, with their positions, instead of
+    /// running it, like the lexer mode, in the format given by `--format`
+    #[clap(long, conflicts_with_all = ["dump_ast", "dump_bytecode"])]
+    dump_tokens: bool,
+
+    /// Print the disassembly of the compiled program, its main program and
+    /// every function, instead of running it, like the disassemble mode. With
+    /// `run`, print the disassembly of the bytecode file
+    #[clap(long, global = true)]
+    dump_bytecode: bool,
+
+    /// Format of the programs printed by `--dump-ast`, the parser mode and
+    /// the `:ast` command of the REPL, of the tokens printed by
+    /// `--dump-tokens` and the lexer mode, and of the files printed by
+    /// `highlight`
+    #[arg(long, global = true, value_name = "FORMAT", value_enum, default_value_t = DumpFormat::Display)]
+    format: DumpFormat,
+
+    /// Reject the programs relying on surprising behaviours: identifiers
+    /// must be defined even in the branches which never run, conditions must
+    /// be booleans and a `let` cannot redefine a name of the same function
+    #[clap(long, global = true)]
+    strict: bool,
+
+    /// Warn about every `let` rebinding a name already defined in the same
+    /// function or an outer one, in compiler mode
+    #[clap(long, global = true)]
+    warn_shadowing: bool,
+
+    #[cfg(feature = "hooks")]
+    #[arg(skip)]
+    profiler: Rc<RefCell<Profiler>>,
+
+    /// Number of global variables a program can define, in compiler mode
+    #[arg(
+        long,
+        global = true,
+        value_name = "GLOBALS",
+        default_value_t = GLOBALS_SIZE,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..=GLOBALS_SIZE as u64)
+    )]
+    max_globals: usize,
+
+    /// Arguments given to the program, returned by `argv()`. Everything after
+    /// `--args` is passed to the program
+    #[arg(
+        long,
+        global = true,
+        num_args = 0..,
+        allow_hyphen_values = true,
+        value_name = "ARGS"
+    )]
+    args: Vec<String>,
 }
 
 impl ReplCli {
@@ -57,36 +376,173 @@ impl ReplCli {
     }
     fn get_mode(&self) -> Mode {
         match &self.mode {
+            _ if self.dump_ast => Mode::Parser,
+            _ if self.dump_tokens => Mode::Lexer,
+            _ if self.dump_bytecode => Mode::Disassemble,
             Some(mode) => *mode,
             None => Mode::Compiler,
         }
     }
 
-    pub fn run(&self) -> Result<(), Box<dyn Error>> {
+    /// Runs the file or the REPL. Returns the exit code given to `exit`, or
+    /// success if the program did not call it.
+    pub fn run(&self) -> Result<ExitCode, Box<dyn Error>> {
+        if let Some(command) = &self.command {
+            return self.run_command(command);
+        }
+
+        #[cfg(not(feature = "serde"))]
+        if matches!(self.get_mode(), Mode::Lexer | Mode::Parser) && self.format == DumpFormat::Json
+        {
+            return Err(dump::JSON_UNAVAILABLE.into());
+        }
+
         //TODO: Implement our own editor for competition
-        let mut rl = DefaultEditor::new()?;
+        let mut rl = ReplHelper::editor()?;
         match &self.get_input_type() {
             InputType::Repl => {
                 self.greeting_message();
                 match self.get_mode() {
                     // TODO: Simplify this handling, its always more or less the same,
                     // we should have an abstraction for this
-                    Mode::Lexer => Ok(self.rlpl(&mut rl)?),
-                    Mode::Parser => Ok(self.rppl(&mut rl)?),
-                    Mode::Interpreter => self.interpreter(&mut rl),
-                    Mode::Compiler => self.compiler(&mut rl),
+                    Mode::Lexer => {
+                        self.rlpl(&mut rl)?;
+                        Ok(ExitCode::SUCCESS)
+                    }
+                    Mode::Parser => {
+                        self.rppl(&mut rl)?;
+                        Ok(ExitCode::SUCCESS)
+                    }
+                    Mode::Interpreter => self.engine_session(&mut rl, Mode::Interpreter),
+                    Mode::Compiler if self.engine == Engine::Register => {
+                        Err("The register engine only runs files".into())
+                    }
+                    Mode::Compiler => self.engine_session(&mut rl, Mode::Compiler),
+                    Mode::Disassemble => {
+                        self.rdpl(&mut rl)?;
+                        Ok(ExitCode::SUCCESS)
+                    }
                 }
             }
-            InputType::File(filename) => self.run_file(filename),
+            InputType::File(filename) => {
+                let result = self.run_file(filename);
+                self.end_profile()?;
+                result
+            }
         }
     }
 
-    fn rlpl(&self, rl: &mut DefaultEditor) -> Result<(), LexerErrors> {
+    /// Runs a subcommand.
+    fn run_command(&self, command: &Command) -> Result<ExitCode, Box<dyn Error>> {
+        match command {
+            Command::Compile { filename, output } => {
+                self.compile_file(filename, output.as_deref())?;
+                Ok(ExitCode::SUCCESS)
+            }
+            Command::Run { filename } => {
+                let result = self.run_bytecode_file(filename);
+                self.end_profile()?;
+                result
+            }
+            Command::Debug { filename } => self.debug_file(filename),
+            Command::Bench { filename, runs } => {
+                self.bench_file(filename, *runs)?;
+                Ok(ExitCode::SUCCESS)
+            }
+            #[cfg(feature = "serde")]
+            Command::Dap { port } => {
+                self.serve_dap(*port)?;
+                Ok(ExitCode::SUCCESS)
+            }
+            Command::Check { filenames } => Ok(self.check_files(filenames)),
+            Command::Fmt { paths, check } => {
+                if paths.len() == 1 && paths[0] == "-" {
+                    format_stream(&mut io::stdin().lock(), &mut io::stdout().lock(), *check)
+                } else if *check {
+                    check_formatting(paths)
+                } else {
+                    format_in_place(paths)
+                }
+            }
+            Command::Lint {
+                filename,
+                allow,
+                warn,
+                deny,
+            } => {
+                let mut config = LintConfig::default();
+                for (rules, severity) in [
+                    (allow, Severity::Allow),
+                    (warn, Severity::Warning),
+                    (deny, Severity::Error),
+                ] {
+                    for rule in rules {
+                        config.set(*rule, severity);
+                    }
+                }
+                lint_file(filename, config)
+            }
+            Command::Highlight { filename } => {
+                let contents = ReplCli::read_file_contents(filename)?;
+                match self.format {
+                    DumpFormat::Display | DumpFormat::Ansi => {
+                        print!("{}", highlight::ansi(&contents));
+                    }
+                    DumpFormat::Html => print!("{}", highlight::html(&contents)),
+                    DumpFormat::Json => {
+                        return Err("Files are highlighted in the ansi or html format".into())
+                    }
+                }
+                Ok(ExitCode::SUCCESS)
+            }
+            Command::Doc { filename, html } => {
+                document_file(filename, *html)?;
+                Ok(ExitCode::SUCCESS)
+            }
+        }
+    }
+
+    /// Returns the hooks collecting the statistics of `--profile` and the
+    /// trace of `--profile-trace`, if one of them was given.
+    #[cfg(feature = "hooks")]
+    fn profiler_hooks(&self) -> Option<Box<dyn Hooks>> {
+        if !self.profile && self.profile_trace.is_none() {
+            return None;
+        }
+        if self.profile_trace.is_some() {
+            self.profiler.borrow_mut().record_trace();
+        }
+        Some(Box::new(Rc::clone(&self.profiler)))
+    }
+
+    /// Prints the statistics of `--profile` and writes the trace of
+    /// `--profile-trace`, if they were given.
+    fn end_profile(&self) -> Result<(), Box<dyn Error>> {
+        if !self.profile && self.profile_trace.is_none() {
+            return Ok(());
+        }
+        #[cfg(not(feature = "hooks"))]
+        eprintln!("Profiling needs the `hooks` feature");
+        #[cfg(feature = "hooks")]
+        {
+            let mut profiler = self.profiler.borrow_mut();
+            profiler.reset_stack();
+            if self.profile {
+                eprint!("{}", profiler.report());
+            }
+            if let (Some(path), Some(trace)) = (&self.profile_trace, profiler.trace_json()) {
+                fs::write(path, trace)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn rlpl(&self, rl: &mut ReplEditor) -> Result<(), LexerErrors> {
         let mut errors = LexerErrors::new();
         loop {
             match rl.readline(self.get_prompt().as_str()) {
                 Ok(line) => {
-                    let new_error = lex(&line);
+                    let new_error = lex(&line, self.format);
                     if let Err(err) = new_error {
                         errors.add_errors(err);
                     }
@@ -108,12 +564,12 @@ impl ReplCli {
         }
     }
 
-    pub fn rppl(&self, rl: &mut DefaultEditor) -> Result<(), ParserErrors> {
+    pub fn rppl(&self, rl: &mut ReplEditor) -> Result<(), ParserErrors> {
         let mut errors = ParserErrors::new();
         loop {
             match rl.readline(self.get_prompt().as_str()) {
                 Ok(line) => {
-                    let new_error = parse(&line);
+                    let new_error = parse(&line, self.format);
                     if let Err(err) = new_error {
                         errors.add_errors(err.errors);
                     }
@@ -135,99 +591,392 @@ impl ReplCli {
         }
     }
 
-    pub fn interpreter(&self, rl: &mut DefaultEditor) -> Result<(), Box<dyn Error>> {
+    /// Prints the disassembly of every line, compiled on its own.
+    fn rdpl(&self, rl: &mut ReplEditor) -> Result<(), ReadlineError> {
+        loop {
+            match rl.readline(self.get_prompt().as_str()) {
+                Ok(line) => match self.compile(&Source::new(REPL_SOURCE_NAME, &line)) {
+                    Ok(bytecode) => print!("{}", disassemble_bytecode(&bytecode)),
+                    Err(err) => eprintln!("{err}"),
+                },
+                Err(ReadlineError::Interrupted | ReadlineError::Eof) => return Ok(()),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Runs the REPL in the interpreter or the compiler, starting with `mode`,
+    /// until the session ends. `:mode` switches to the other engine, which gets
+    /// the bindings of the session and its settings.
+    fn engine_session(
+        &self,
+        rl: &mut ReplEditor,
+        mut mode: Mode,
+    ) -> Result<ExitCode, Box<dyn Error>> {
+        let mut settings = ReplSettings::default();
+        let mut bindings = Vec::new();
+        loop {
+            let end = match mode {
+                Mode::Interpreter => self.interpreter(rl, &mut settings, &bindings),
+                _ => self.compiler(rl, &mut settings, &bindings)?,
+            };
+            match end {
+                SessionEnd::Exit(code) => return Ok(code),
+                SessionEnd::Switch(new_mode, new_bindings) => {
+                    match new_mode {
+                        Mode::Interpreter => println!("Switched to interpreter mode"),
+                        _ => println!("Switched to compiler mode"),
+                    }
+                    mode = new_mode;
+                    bindings = new_bindings;
+                }
+            }
+        }
+    }
+
+    /// Runs the REPL in the interpreter, after defining `bindings` again.
+    fn interpreter(
+        &self,
+        rl: &mut ReplEditor,
+        settings: &mut ReplSettings,
+        bindings: &[(String, Object)],
+    ) -> SessionEnd {
         let mut evaluator = Evaluator::new();
+        self.configure_evaluator(&mut evaluator);
+        let interrupt = ctrl_c();
+        if let Some(interrupt) = &interrupt {
+            // The last line of the previous session may have been interrupted
+            interrupt.clear();
+            evaluator.set_interrupt(interrupt.clone());
+        }
+        let (statements, skipped) = rebind(bindings);
+        if !statements.is_empty() {
+            if let Err(err) = interpret(&mut evaluator, &Source::new(REPL_SOURCE_NAME, &statements))
+            {
+                eprintln!("{err}");
+            }
+        }
+        print_skipped_bindings(&skipped);
+        let profiler = Rc::new(RefCell::new(Profiler::default()));
         loop {
             match rl.readline(self.get_prompt().as_str()) {
-                Ok(line) => match interpret(&mut evaluator, &line) {
-                    Ok(str) => {
-                        if str != Object::NULL.to_string() {
-                            println!("{str}");
+                Ok(line) => {
+                    let (source, timed) = match repl_input(&line) {
+                        Some(ReplInput::Code(source, timed)) => (source, timed),
+                        Some(ReplInput::Command(ReplCommand::Mode(mode)))
+                            if mode != Mode::Interpreter =>
+                        {
+                            return SessionEnd::Switch(mode, evaluator.bindings());
+                        }
+                        Some(ReplInput::Command(command)) => {
+                            self.interpreter_command(command, &evaluator, settings, &profiler);
+                            continue;
+                        }
+                        None => continue,
+                    };
+
+                    // The bindings are only compared when they are shown
+                    let before = settings
+                        .show_bindings
+                        .then(|| Bindings::from_environment(&evaluator.environment().borrow()));
+                    // Every line gets the whole fuel and time, like in compiler mode
+                    if let Some(fuel) = self.fuel {
+                        evaluator.set_fuel(fuel);
+                    }
+                    if let Some(timeout) = self.timeout {
+                        evaluator.set_timeout(timeout);
+                    }
+                    // Ctrl-C stops the line running when it is pressed
+                    if let Some(interrupt) = &interrupt {
+                        interrupt.clear();
+                    }
+                    #[cfg(feature = "hooks")]
+                    if settings.profile {
+                        evaluator.set_hooks(Box::new(Rc::clone(&profiler)));
+                    }
+                    // The evaluation steps of a timed line are counted with
+                    // the fuel
+                    let fuel = timed.then(|| {
+                        let fuel = self.fuel.unwrap_or(u64::MAX);
+                        evaluator.set_fuel(fuel);
+                        fuel
+                    });
+                    let start = Instant::now();
+                    let result = interpret(&mut evaluator, &source);
+                    let elapsed = start.elapsed();
+                    match result {
+                        Ok(Outcome::Value(str)) => {
+                            if str != evaluator.output_style().format(&NULL) {
+                                println!("{str}");
+                            }
+                        }
+                        Ok(Outcome::Exit(code)) => return SessionEnd::Exit(exit_code(code)),
+                        Err(err) => eprintln!("{err}"),
+                    }
+                    if let Some(fuel) = fuel {
+                        let left = match self.fuel {
+                            Some(_) => evaluator.fuel(),
+                            None => evaluator.take_fuel(),
+                        };
+                        let steps = fuel - left.unwrap_or_default();
+                        println!("{}", time_report(elapsed, steps, "evaluation steps"));
+                    }
+                    #[cfg(feature = "hooks")]
+                    if evaluator.take_hooks().is_some() {
+                        profiler.borrow_mut().reset_stack();
+                    }
+                    if let Some(before) = before {
+                        let after = Bindings::from_environment(&evaluator.environment().borrow());
+                        for change in before.diff(&after) {
+                            println!("{change}");
                         }
                     }
-                    Err(err) => eprintln!("{err}",),
-                },
-                Err(ReadlineError::Interrupted | ReadlineError::Eof) => {
-                    break;
                 }
+                // Ctrl-C discards the line being typed, Ctrl-D ends the session
+                Err(ReadlineError::Interrupted) => {}
+                Err(ReadlineError::Eof) => break,
                 Err(err) => {
                     println!("Error: {err:?}");
                     break;
                 }
             }
         }
-        Ok(())
+        SessionEnd::Exit(ExitCode::SUCCESS)
     }
 
-    pub fn compiler(&self, rl: &mut DefaultEditor) -> Result<(), Box<dyn Error>> {
-        let mut symbol_table = SymbolTable::new();
-        for (i, builtin) in BuiltinFunction::get_builtins_names().iter().enumerate() {
-            symbol_table.define_builtin(i, builtin.clone());
+    /// Runs the REPL in the compiler, after defining `bindings` again.
+    fn compiler(
+        &self,
+        rl: &mut ReplEditor,
+        settings: &mut ReplSettings,
+        bindings: &[(String, Object)],
+    ) -> Result<SessionEnd, Box<dyn Error>> {
+        // One VM runs all the lines, keeping the globals, symbols and
+        // constants they define
+        let mut vm = VM::new_with_global_store(
+            Compiler::new().into_bytecode(),
+            new_global_store(self.max_globals),
+        );
+        let interrupt = ctrl_c();
+        if let Some(interrupt) = &interrupt {
+            // The last line of the previous session may have been interrupted
+            interrupt.clear();
+            vm.set_interrupt(interrupt.clone());
         }
-        let mut constants = Vec::new();
-        let mut globals = {
-            let mut v = Vec::with_capacity(GLOBALS_SIZE);
-            (0..GLOBALS_SIZE).for_each(|_| v.push(Rc::new(NULL)));
-            v
-        };
+
+        let profiler = Rc::new(RefCell::new(Profiler::default()));
+        // Symbols defined by the lines compiled so far
+        let mut definitions = Vec::new();
+        let (statements, skipped) = rebind(bindings);
+        if !statements.is_empty() {
+            self.run_statements(&mut vm, &statements, &mut definitions)?;
+        }
+        print_skipped_bindings(&skipped);
+        // Bytecode of the last line, with the index of its first constant
+        let mut last_input: Option<(Bytecode, usize)> = None;
 
         loop {
             match rl.readline(self.get_prompt().as_str()) {
                 Ok(line) => {
-                    let lexer = Lexer::new(&line);
-                    let mut parser = Parser::new(lexer);
-                    let program = parser.parse_program();
-                    if !parser.errors.is_empty() {
-                        eprintln!("{}", parser.errors);
+                    let (source, timed) = match repl_input(&line) {
+                        Some(ReplInput::Code(source, timed)) => (source, timed),
+                        Some(ReplInput::Command(ReplCommand::Mode(mode)))
+                            if mode != Mode::Compiler =>
+                        {
+                            let bindings = global_bindings(vm.symbol_table(), &vm.globals);
+                            return Ok(SessionEnd::Switch(mode, bindings));
+                        }
+                        Some(ReplInput::Command(command)) => {
+                            self.compiler_command(
+                                command,
+                                &vm,
+                                &definitions,
+                                last_input.as_ref(),
+                                settings,
+                                &profiler,
+                            );
+                            continue;
+                        }
+                        None => continue,
+                    };
+
+                    // The bindings are only compared when they are shown
+                    let before = settings
+                        .show_bindings
+                        .then(|| Bindings::from_globals(vm.symbol_table(), &vm.globals));
+                    let start = Instant::now();
+                    let first_constant = vm.constants().len();
+                    let bytecode = self.compile_line(&mut vm, source, &mut definitions);
+                    last_input = Some((bytecode.clone(), first_constant));
+                    vm.load(bytecode);
+                    // The limits apply to each line
+                    self.configure_vm(&mut vm);
+                    #[cfg(feature = "hooks")]
+                    if settings.profile {
+                        vm.set_hooks(Box::new(Rc::clone(&profiler)));
+                    } else {
+                        vm.take_hooks();
                     }
-                    let mut compiler =
-                        Compiler::new_with_state(symbol_table.clone(), constants.clone());
-                    if let Err(err) = compiler.compile(program) {
-                        let err = CompilerError::new(err);
-                        eprintln!("{err}",);
+                    // The instructions of a timed line are counted with the
+                    // fuel
+                    let fuel = timed.then(|| {
+                        let fuel = self.fuel.unwrap_or(u64::MAX);
+                        vm.set_fuel(fuel);
+                        fuel
+                    });
+                    // Ctrl-C stops the line running when it is pressed
+                    if let Some(interrupt) = &interrupt {
+                        interrupt.clear();
                     }
-
-                    let mut vm = VM::new_with_global_store(compiler.bytecode(), globals.clone());
-                    if let Err(err) = vm.run() {
-                        eprintln!("{err}",);
+                    let result = vm.run();
+                    let elapsed = start.elapsed();
+                    profiler.borrow_mut().reset_stack();
+                    if let Some(code) = vm.exit_code() {
+                        return Ok(SessionEnd::Exit(exit_code(code)));
                     }
-                    constants = compiler.constants;
-                    symbol_table = compiler.symbol_table;
-
-                    let vm_result: Result<String, Box<dyn Error>> = match vm
-                        .last_popped_stack_element()
-                    {
-                        Ok(obj) => match obj.as_ref() {
-                            Object::ERROR(error) => Err(Box::new(RuntimeError::new(error.clone()))),
-                            x => Ok(x.to_string()),
-                        },
-                        Err(_) => Err(Box::new(RuntimeError::new(String::from(
-                            "No object returned from VM",
-                        )))),
-                    };
-
-                    globals = vm.globals;
-                    match vm_result {
-                        Ok(str) => {
-                            if str != Object::NULL.to_string() {
-                                println!("{str}");
-                            }
+                    print_vm_result(&vm, result);
+                    if let Some(fuel) = fuel {
+                        let left = match self.fuel {
+                            Some(_) => vm.fuel(),
+                            None => vm.take_fuel(),
+                        };
+                        let instructions = fuel - left.unwrap_or_default();
+                        println!("{}", time_report(elapsed, instructions, "instructions"));
+                    }
+                    if let Some(before) = before {
+                        let after = Bindings::from_globals(vm.symbol_table(), &vm.globals);
+                        for change in before.diff(&after) {
+                            println!("{change}");
                         }
-                        Err(err) => eprintln!("{err}",),
                     }
                 }
-                Err(ReadlineError::Interrupted | ReadlineError::Eof) => {
-                    break;
-                }
+                // Ctrl-C discards the line being typed, Ctrl-D ends the session
+                Err(ReadlineError::Interrupted) => {}
+                Err(ReadlineError::Eof) => break,
                 Err(err) => {
                     println!("Error: {err:?}");
                     break;
                 }
             }
         }
+        Ok(SessionEnd::Exit(ExitCode::SUCCESS))
+    }
+
+    /// Runs the statements defining again the bindings of the previous
+    /// session in `vm`.
+    fn run_statements(
+        &self,
+        vm: &mut VM,
+        statements: &str,
+        definitions: &mut Vec<Definition>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut parser = Parser::new(Lexer::new(statements));
+        let program = parser
+            .parse_program()
+            .with_source(Source::new(REPL_SOURCE_NAME, statements));
+        let mut compiler = vm.new_compiler();
+        self.configure_compiler(&mut compiler);
+        compiler.compile(&program).map_err(CompilerError::new)?;
+        definitions.extend_from_slice(compiler.definitions());
+        vm.load(compiler.into_bytecode());
+        self.configure_vm(vm);
+        vm.run().map_err(|err| vm_error_message(&err))?;
         Ok(())
     }
 
+    /// Compiles a line of the REPL with the symbols and constants of `vm`,
+    /// printing its errors and warnings, and returns its bytecode.
+    fn compile_line(
+        &self,
+        vm: &mut VM,
+        source: Source,
+        definitions: &mut Vec<Definition>,
+    ) -> Bytecode {
+        let mut parser = Parser::new(Lexer::new(&source.text));
+        let program = parser.parse_program().with_source(source);
+        if !parser.errors.is_empty() {
+            eprintln!("{}", parser.errors);
+        }
+        let mut compiler = vm.new_compiler();
+        self.configure_compiler(&mut compiler);
+        if let Err(err) = compiler.compile(&program) {
+            eprintln!("{}", CompilerError::new(err));
+        }
+        print_warnings(compiler.warnings(), program.source.as_ref().unwrap());
+        definitions.extend_from_slice(compiler.definitions());
+        compiler.into_bytecode()
+    }
+
+    /// Runs a command of the REPL in the interpreter, other than `:load`,
+    /// `:time` and the switch to the compiler.
+    fn interpreter_command(
+        &self,
+        command: ReplCommand,
+        evaluator: &Evaluator,
+        settings: &mut ReplSettings,
+        profiler: &RefCell<Profiler>,
+    ) {
+        match command {
+            ReplCommand::Env => {
+                print_bindings(&evaluator.bindings(), evaluator.output_style());
+            }
+            ReplCommand::Disas(_) | ReplCommand::Dis => {
+                eprintln!(":disas and :dis are only available in compiler mode");
+            }
+            ReplCommand::Symbols => {
+                eprintln!(":symbols is only available in compiler mode");
+            }
+            ReplCommand::ProfileReport => print!("{}", profiler.borrow().report()),
+            ReplCommand::Ast(code) => {
+                if let Err(err) = parse(&code, self.format) {
+                    eprintln!("{err}");
+                }
+            }
+            ReplCommand::Mode(_) => eprintln!("Already in interpreter mode"),
+            command => apply_setting(&command, settings, profiler),
+        }
+    }
+
+    /// Runs a command of the REPL in the compiler, other than `:load`, `:time`
+    /// and the switch to the interpreter.
+    fn compiler_command(
+        &self,
+        command: ReplCommand,
+        vm: &VM,
+        definitions: &[Definition],
+        last_input: Option<&(Bytecode, usize)>,
+        settings: &mut ReplSettings,
+        profiler: &RefCell<Profiler>,
+    ) {
+        match command {
+            ReplCommand::Env => print_bindings(
+                &global_bindings(vm.symbol_table(), &vm.globals),
+                vm.output_style(),
+            ),
+            ReplCommand::Symbols => print!("{}", dump_symbols(definitions)),
+            ReplCommand::Disas(name) => {
+                match disassemble_global(vm.symbol_table(), &vm.globals, &name) {
+                    Ok(disassembly) => print!("{disassembly}"),
+                    Err(err) => eprintln!("{err}"),
+                }
+            }
+            ReplCommand::Dis => match last_input {
+                Some((bytecode, first_constant)) => {
+                    print!("{}", disassemble_since(bytecode, *first_constant));
+                }
+                None => eprintln!("No line was compiled yet"),
+            },
+            ReplCommand::ProfileReport => print!("{}", profiler.borrow().report()),
+            ReplCommand::Ast(code) => {
+                if let Err(err) = parse(&code, self.format) {
+                    eprintln!("{err}");
+                }
+            }
+            ReplCommand::Mode(_) => eprintln!("Already in compiler mode"),
+            command => apply_setting(&command, settings, profiler),
+        }
+    }
+
     fn greeting_message(&self) {
         let greeting = r"
                                   @@@@@@@@@@@@
@@ -281,24 +1030,378 @@ impl ReplCli {
         String::from(">>")
     }
 
-    fn run_file(&self, file_path: &str) -> Result<(), Box<dyn Error>> {
+    fn run_file(&self, file_path: &str) -> Result<ExitCode, Box<dyn Error>> {
         let contents = ReplCli::read_file_contents(file_path)?;
+        let source = Source::new(file_path, &contents);
 
-        match self.get_mode() {
-            Mode::Lexer => lex(&contents)?,
-            Mode::Parser => parse(&contents)?,
+        let outcome = match self.get_mode() {
+            Mode::Lexer => {
+                lex(&contents, self.format)?;
+                return Ok(ExitCode::SUCCESS);
+            }
+            Mode::Parser => {
+                parse(&contents, self.format)?;
+                return Ok(ExitCode::SUCCESS);
+            }
             Mode::Interpreter => {
+                if self.stats {
+                    eprintln!("--stats is only available in compiler mode");
+                }
+                if self.coverage.is_some() {
+                    eprintln!("--coverage is only available in compiler mode");
+                }
+                if self.dump_symbols {
+                    eprintln!("--dump-symbols is only available in compiler mode");
+                }
                 let mut evaluator = Evaluator::new();
-                interpret(&mut evaluator, &contents)?;
+                self.configure_evaluator(&mut evaluator);
+                #[cfg(feature = "hooks")]
+                if let Some(hooks) = self.profiler_hooks() {
+                    evaluator.set_hooks(hooks);
+                }
+                interpret(&mut evaluator, &source)?
             }
+            Mode::Compiler if self.engine == Engine::Register => self.run_register(&source)?,
             Mode::Compiler => {
-                let bytecode = compile(&contents)?;
-                run_vm(bytecode)?;
+                let bytecode = self.compile(&source)?;
+                self.run_vm(bytecode)?
+            }
+            Mode::Disassemble => {
+                print!("{}", disassemble_bytecode(&self.compile(&source)?));
+                return Ok(ExitCode::SUCCESS);
+            }
+        };
+        match outcome {
+            Outcome::Value(_) => Ok(ExitCode::SUCCESS),
+            Outcome::Exit(code) => Ok(exit_code(code)),
+        }
+    }
+
+    /// Compiles a file and writes its bytecode to `output`, or next to it.
+    fn compile_file(&self, file_path: &str, output: Option<&str>) -> Result<(), Box<dyn Error>> {
+        let contents = ReplCli::read_file_contents(file_path)?;
+        let bytecode = self.compile(&Source::new(file_path, &contents))?;
+        let output = match output {
+            Some(output) => output.to_string(),
+            None => format!(
+                "{}{BYTECODE_EXTENSION}",
+                file_path.trim_end_matches(".monkey")
+            ),
+        };
+        fs::write(output, code::serialize(&bytecode))?;
+        Ok(())
+    }
+
+    fn run_bytecode_file(&self, file_path: &str) -> Result<ExitCode, Box<dyn Error>> {
+        if !file_path.ends_with(BYTECODE_EXTENSION) {
+            return Err(format!("Error: File must end with {BYTECODE_EXTENSION}").into());
+        }
+        let bytecode = code::deserialize(&fs::read(file_path)?)?;
+        if self.dump_bytecode {
+            print!("{}", disassemble_bytecode(&bytecode));
+            return Ok(ExitCode::SUCCESS);
+        }
+        match self.run_vm(bytecode)? {
+            Outcome::Value(_) => Ok(ExitCode::SUCCESS),
+            Outcome::Exit(code) => Ok(exit_code(code)),
+        }
+    }
+
+    /// Compiles every file, printing the errors of the ones which do not
+    /// compile. Fails if there is at least one of them.
+    fn check_files(&self, file_paths: &[String]) -> ExitCode {
+        let mut failed = false;
+        for file_path in file_paths {
+            let result = ReplCli::read_file_contents(file_path)
+                .and_then(|contents| self.compile(&Source::new(file_path, &contents)));
+            if let Err(err) = result {
+                eprintln!("{file_path}: {}", err.to_string().trim_end());
+                failed = true;
+            }
+        }
+        if failed {
+            ExitCode::FAILURE
+        } else {
+            ExitCode::SUCCESS
+        }
+    }
+
+    /// Compiles a file and runs it in the debugger, reading its commands from
+    /// the terminal.
+    fn debug_file(&self, file_path: &str) -> Result<ExitCode, Box<dyn Error>> {
+        let contents = ReplCli::read_file_contents(file_path)?;
+        let bytecode = self.compile(&Source::new(file_path, &contents))?;
+        let mut vm = VM::new_with_global_store(bytecode, new_global_store(self.max_globals));
+        self.configure_vm(&mut vm);
+        let mut debugger = Debugger::new(vm);
+
+        println!("Type `help` to see the commands");
+        print!("{}", debugger.location());
+        let mut rl = DefaultEditor::new()?;
+        let mut last_command = DebugCommand::Step;
+        loop {
+            match rl.readline("(debug) ") {
+                Ok(line) => {
+                    let command = if line.trim().is_empty() {
+                        Ok(last_command.clone())
+                    } else {
+                        DebugCommand::parse(&line)
+                    };
+                    match command {
+                        Ok(DebugCommand::Quit) => break,
+                        Ok(command) => {
+                            print!("{}", debugger.execute(&command));
+                            last_command = command;
+                        }
+                        Err(err) => eprintln!("{err}"),
+                    }
+                }
+                Err(ReadlineError::Interrupted | ReadlineError::Eof) => break,
+                Err(err) => return Err(Box::new(err)),
+            }
+        }
+        Ok(debugger.exit_code().map_or(ExitCode::SUCCESS, exit_code))
+    }
+
+    /// Runs a file `runs` times in the interpreter, then in the stack VM,
+    /// compiling it every time, and prints their times. With `--stats`, it
+    /// also prints the statistics of the last run of the VM.
+    fn bench_file(&self, file_path: &str, runs: usize) -> Result<(), Box<dyn Error>> {
+        let contents = ReplCli::read_file_contents(file_path)?;
+        let source = Source::new(file_path, &contents);
+
+        let mut interpreter = Timings::new("interpreter");
+        for _ in 0..runs {
+            let start = Instant::now();
+            let mut evaluator = Evaluator::new();
+            self.configure_evaluator(&mut evaluator);
+            interpret(&mut evaluator, &source)?;
+            interpreter.times.push(start.elapsed());
+        }
+
+        let mut vm_timings = Timings::new("vm");
+        let mut stats = None;
+        for _ in 0..runs {
+            let start = Instant::now();
+            let bytecode = self.compile(&source)?;
+            let mut vm = VM::new_with_global_store(bytecode, new_global_store(self.max_globals));
+            self.configure_vm(&mut vm);
+            if self.stats {
+                vm.collect_stats();
+            }
+            if let Err(e) = vm.run() {
+                return Err(runtime_error(
+                    vm_error_message(&e),
+                    vm.source(),
+                    vm.stack_trace(),
+                ));
             }
+            vm_timings.times.push(start.elapsed());
+            stats = vm.stats();
         }
+
+        print!("{}", bench::report(&[interpreter, vm_timings]));
+        if let Some(stats) = stats {
+            print!("\n{stats}");
+        }
+        Ok(())
+    }
+
+    /// Waits for a client of the Debug Adapter Protocol on `port` and serves
+    /// it. The programs print to the standard output, so the protocol uses a
+    /// socket instead.
+    #[cfg(feature = "serde")]
+    fn serve_dap(&self, port: u16) -> Result<(), Box<dyn Error>> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        eprintln!("Listening on {}", listener.local_addr()?);
+        let (stream, _) = listener.accept()?;
+        let mut server = DapServer::new(|path: &str| {
+            let contents = ReplCli::read_file_contents(path).map_err(|err| err.to_string())?;
+            let bytecode = self
+                .compile(&Source::new(path, &contents))
+                .map_err(|err| err.to_string())?;
+            let mut vm = VM::new_with_global_store(bytecode, new_global_store(self.max_globals));
+            self.configure_vm(&mut vm);
+            Ok(vm)
+        });
+        server.serve(&mut BufReader::new(stream.try_clone()?), &mut &stream)?;
         Ok(())
     }
 
+    /// Applies the options of the command line to an evaluator.
+    fn configure_evaluator(&self, evaluator: &mut Evaluator) {
+        evaluator.set_args(self.args.clone());
+        evaluator.set_strict(self.strict_config());
+        evaluator.set_max_depth(self.max_depth);
+        if let Some(fuel) = self.fuel {
+            evaluator.set_fuel(fuel);
+        }
+        if let Some(timeout) = self.timeout {
+            evaluator.set_timeout(timeout);
+        }
+    }
+
+    /// Applies the options of the command line to a compiler.
+    fn configure_compiler(&self, compiler: &mut Compiler) {
+        compiler.set_max_globals(self.max_globals);
+        compiler.set_superinstructions(!self.no_superinstructions);
+        compiler.set_shadowing_warnings(self.warn_shadowing);
+        compiler.set_strict(self.strict_config());
+    }
+
+    fn strict_config(&self) -> StrictConfig {
+        if self.strict {
+            StrictConfig::strict()
+        } else {
+            StrictConfig::default()
+        }
+    }
+
+    fn compile(&self, source: &Source) -> Result<Bytecode, Box<dyn Error>> {
+        let lexer = Lexer::new(&source.text);
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program().with_source(source.clone());
+        if !parser.errors.is_empty() {
+            return Err(Box::new(parser.errors));
+        }
+        let mut compiler = Compiler::new();
+        self.configure_compiler(&mut compiler);
+        let result = compiler.compile(&program);
+        print_warnings(compiler.warnings(), source);
+        match result {
+            Ok(()) => {
+                if self.dump_symbols {
+                    eprint!("{}", dump_symbols(compiler.definitions()));
+                }
+                Ok(compiler.bytecode())
+            }
+            Err(e) => Err(Box::new(CompilerError::new(e))),
+        }
+    }
+
+    /// Applies the options of the command line to a VM.
+    fn configure_vm(&self, vm: &mut VM) {
+        vm.set_args(self.args.clone());
+        vm.set_strict(self.strict_config());
+        vm.set_max_stack_size(self.max_stack_size);
+        vm.set_max_depth(self.max_depth);
+        if let Some(fuel) = self.fuel {
+            vm.set_fuel(fuel);
+        }
+        if let Some(timeout) = self.timeout {
+            vm.set_timeout(timeout);
+        }
+        if self.trace {
+            vm.set_trace(Box::new(std::io::stderr()));
+        }
+    }
+
+    fn run_vm(&self, bytecode: Bytecode) -> Result<Outcome, Box<dyn Error>> {
+        let mut vm = VM::new_with_global_store(bytecode, new_global_store(self.max_globals));
+        self.configure_vm(&mut vm);
+        #[cfg(feature = "hooks")]
+        if let Some(hooks) = self.profiler_hooks() {
+            vm.set_hooks(hooks);
+        }
+        if self.stats {
+            vm.collect_stats();
+        }
+        if self.coverage.is_some() {
+            vm.collect_coverage();
+        }
+        let result = vm.run();
+        if let Some(stats) = vm.stats() {
+            eprint!("{stats}");
+        }
+        if let (Some(path), Some(coverage)) = (&self.coverage, vm.coverage()) {
+            let name = vm.source().map_or("", |source| &source.name);
+            fs::write(path, coverage.lcov(name))?;
+            eprint!("{}", coverage.summary());
+        }
+        if let Some(code) = vm.exit_code() {
+            return Ok(Outcome::Exit(code));
+        }
+        match result {
+            Ok(()) => match vm.last_popped_stack_element() {
+                Ok(obj) => match obj.as_ref() {
+                    Object::ERROR(error) => Err(runtime_error(
+                        error.message.clone(),
+                        vm.source(),
+                        Vec::new(),
+                    )),
+                    x => Ok(Outcome::Value(x.inspect())),
+                },
+                Err(_) => Err(runtime_error(
+                    String::from("No object returned from VM"),
+                    vm.source(),
+                    Vec::new(),
+                )),
+            },
+            Err(e) => Err(runtime_error(
+                vm_error_message(&e),
+                vm.source(),
+                vm.stack_trace(),
+            )),
+        }
+    }
+
+    /// Compiles a file for the register machine and runs it.
+    fn run_register(&self, source: &Source) -> Result<Outcome, Box<dyn Error>> {
+        if self.trace
+            || self.stats
+            || self.profile
+            || self.profile_trace.is_some()
+            || self.coverage.is_some()
+        {
+            eprintln!(
+                "--trace, --stats, --coverage and the profiler are only available with the stack engine"
+            );
+        }
+        if self.strict {
+            eprintln!("--strict is only available with the stack engine");
+        }
+        let lexer = Lexer::new(&source.text);
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program().with_source(source.clone());
+        if !parser.errors.is_empty() {
+            return Err(Box::new(parser.errors));
+        }
+        let mut compiler = RegisterCompiler::new();
+        compiler.set_max_globals(self.max_globals);
+        if let Err(e) = compiler.compile(&program) {
+            return Err(Box::new(CompilerError::new(e)));
+        }
+
+        let mut vm = RegisterVM::new_with_global_store(
+            compiler.bytecode(),
+            new_global_store(self.max_globals),
+        );
+        vm.set_args(self.args.clone());
+        vm.set_max_registers(self.max_stack_size);
+        vm.set_max_depth(self.max_depth);
+        if let Some(fuel) = self.fuel {
+            vm.set_fuel(fuel);
+        }
+        if let Some(timeout) = self.timeout {
+            vm.set_timeout(timeout);
+        }
+        let result = vm.run();
+        if let Some(code) = vm.exit_code() {
+            return Ok(Outcome::Exit(code));
+        }
+        match result {
+            Ok(()) => match vm.result().as_ref() {
+                Object::ERROR(error) => Err(runtime_error(
+                    error.message.clone(),
+                    vm.source(),
+                    Vec::new(),
+                )),
+                x => Ok(Outcome::Value(x.inspect())),
+            },
+            Err(e) => Err(runtime_error(e, vm.source(), vm.stack_trace())),
+        }
+    }
+
     fn read_file_contents(file_path: &str) -> Result<String, Box<dyn Error>> {
         if file_path.ends_with(".monkey") {
             Ok(fs::read_to_string(file_path)?)
@@ -308,16 +1411,157 @@ impl ReplCli {
     }
 }
 
-fn lex(line: &str) -> Result<(), LexerErrors> {
-    let mut lexer = Lexer::new(line);
-    let mut token = Token::Illegal(String::new());
+/// Prints the bindings `:mode` could not define again in the new engine.
+fn print_skipped_bindings(skipped: &[&str]) {
+    if !skipped.is_empty() {
+        eprintln!(
+            "Only integers, booleans, strings, arrays and hashes are kept, not: {}",
+            skipped.join(", ")
+        );
+    }
+}
+
+/// Returns the report of `:time`: the wall time of a line, and the number of
+/// instructions or of evaluation steps it ran.
+fn time_report(elapsed: Duration, count: u64, unit: &str) -> String {
+    format!("time: {elapsed:.3?}, {count} {unit}")
+}
+
+/// Returns what a line typed in the REPL asks for, or `None` after printing
+/// why it cannot be run.
+fn repl_input(line: &str) -> Option<ReplInput> {
+    match ReplCommand::parse(line) {
+        None => Some(ReplInput::Code(Source::new(REPL_SOURCE_NAME, line), false)),
+        Some(Ok(ReplCommand::Load(path))) => match load_source(&path) {
+            Ok(source) => Some(ReplInput::Code(source, false)),
+            Err(err) => {
+                eprintln!("{err}");
+                None
+            }
+        },
+        Some(Ok(ReplCommand::Time(code))) => {
+            Some(ReplInput::Code(Source::new(REPL_SOURCE_NAME, &code), true))
+        }
+        Some(Ok(command)) => Some(ReplInput::Command(command)),
+        Some(Err(err)) => {
+            eprintln!("{err}");
+            None
+        }
+    }
+}
+
+/// Applies a command changing the settings of the REPL, such as `:profile`.
+fn apply_setting(command: &ReplCommand, settings: &mut ReplSettings, profiler: &RefCell<Profiler>) {
+    if let ReplCommand::Profile(true) = command {
+        profiler.borrow_mut().clear();
+        #[cfg(not(feature = "hooks"))]
+        eprintln!("Profiling needs the `hooks` feature");
+    }
+    settings.apply(command);
+}
+
+/// Prints the error of a REPL line run by the VM, if it failed, and the value
+/// of its last expression, unless it is null.
+fn print_vm_result(vm: &VM, result: Result<(), VmError>) {
+    if let Err(err) = result {
+        eprintln!(
+            "{}",
+            runtime_error(vm_error_message(&err), vm.source(), vm.stack_trace())
+        );
+    }
+    match vm.last_popped_stack_element() {
+        Ok(obj) => match obj.as_ref() {
+            Object::ERROR(error) => eprintln!("{}", RuntimeError::new(error.message.clone())),
+            obj => {
+                let result = vm.output_style().format(obj);
+                if result != vm.output_style().format(&NULL) {
+                    println!("{result}");
+                }
+            }
+        },
+        Err(_) => eprintln!(
+            "{}",
+            RuntimeError::new("No object returned from VM".to_string())
+        ),
+    }
+}
+
+/// Reads a file loaded in the REPL with `:load`.
+fn load_source(path: &str) -> Result<Source, Box<dyn Error>> {
+    let text =
+        ReplCli::read_file_contents(path).map_err(|err| format!("Could not load {path}: {err}"))?;
+    Ok(Source::new(path, &text))
+}
+
+/// Prints the warnings of the compiler, with the location of the statements
+/// they are about.
+fn print_warnings(warnings: &[CompilerWarning], source: &Source) {
+    for warning in warnings {
+        match warning.position() {
+            Some(position) => eprintln!("{}:{position}: {warning}", source.name),
+            None => eprintln!("{warning}"),
+        }
+    }
+}
+
+/// Prints the diagnostics of the linter for a file. Fails if one of them is an
+/// error.
+/// Prints the documentation of the functions of a file.
+fn document_file(file_path: &str, html: bool) -> Result<(), Box<dyn Error>> {
+    let contents = ReplCli::read_file_contents(file_path)?;
+    let mut parser = Parser::new(Lexer::new(&contents));
+    let program = parser.parse_program();
+    if !parser.errors.is_empty() {
+        return Err(Box::new(parser.errors));
+    }
+    let functions = doc::functions(&program);
+    if html {
+        print!("{}", doc::html(file_path, &functions));
+    } else {
+        print!("{}", doc::markdown(file_path, &functions));
+    }
+    Ok(())
+}
+
+fn lint_file(file_path: &str, config: LintConfig) -> Result<ExitCode, Box<dyn Error>> {
+    let contents = ReplCli::read_file_contents(file_path)?;
+    let source = Source::new(file_path, &contents);
+    let mut parser = Parser::new(Lexer::new(&contents));
+    let program = parser.parse_program().with_source(source.clone());
+    if !parser.errors.is_empty() {
+        return Err(Box::new(parser.errors));
+    }
+    let diagnostics = Linter::new(config).lint(&program);
+    for diagnostic in &diagnostics {
+        eprintln!("{}\n", diagnostic.render(Some(&source)));
+    }
+    let errors = diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        eprintln!(
+            "{errors} error(s), {} warning(s)",
+            diagnostics.len() - errors
+        );
+        Ok(ExitCode::FAILURE)
+    } else {
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+/// Prints the tokens of `line` in `format`.
+fn lex(line: &str, format: DumpFormat) -> Result<(), LexerErrors> {
+    let tokens = tokenize(line);
     let mut errors = LexerErrors::new();
-    while token != Token::Eof {
-        token = lexer.next_token();
-        if let Token::Illegal(ref s) = token {
+    for spanned in &tokens {
+        if let Token::Illegal(ref s) = spanned.token {
             errors.add_error(s.clone());
         }
-        println!("{token}");
+    }
+    match dump_tokens(line, &tokens, format) {
+        Ok(dump) => print!("{dump}"),
+        Err(err) => eprintln!("{err}"),
     }
     if errors.is_empty() {
         Ok(())
@@ -326,60 +1570,167 @@ fn lex(line: &str) -> Result<(), LexerErrors> {
     }
 }
 
-fn parse(line: &str) -> Result<(), ParserErrors> {
+/// Prints the syntax tree of `line` in `format`.
+fn parse(line: &str, format: DumpFormat) -> Result<(), ParserErrors> {
     let lexer = Lexer::new(line);
     let mut parser = Parser::new(lexer);
     let program = parser.parse_program();
     if parser.errors.is_empty() {
-        println!("{program}");
+        match dump_ast(&program, format) {
+            Ok(ast) => println!("{ast}"),
+            Err(err) => eprintln!("{err}"),
+        }
         Ok(())
     } else {
         Err(parser.errors)
     }
 }
 
-fn interpret(interpreter: &mut Evaluator, line: &str) -> Result<String, Box<dyn Error>> {
-    let lexer = Lexer::new(line);
+fn interpret(interpreter: &mut Evaluator, source: &Source) -> Result<Outcome, Box<dyn Error>> {
+    let lexer = Lexer::new(&source.text);
     let mut parser = Parser::new(lexer);
-    let program = parser.parse_program();
+    let program = parser.parse_program().with_source(source.clone());
     if !parser.errors.is_empty() {
         return Err(Box::new(parser.errors));
     }
     let evaluated = interpreter.eval(program);
 
-    if let Object::ERROR(error) = evaluated {
-        Err(Box::new(RuntimeError::new(error)))
+    match evaluated {
+        Object::ERROR(error) => Err(runtime_error(
+            error.message,
+            Some(source),
+            interpreter.stack_trace(),
+        )),
+        Object::EXIT(code) => Ok(Outcome::Exit(code)),
+        _ => Ok(Outcome::Value(
+            interpreter.output_style().format(&evaluated),
+        )),
+    }
+}
+
+/// Parses a duration made of a number and a unit, `ms`, `s` or `m`. A number
+/// alone is a number of seconds.
+fn parse_duration(duration: &str) -> Result<Duration, String> {
+    let (number, unit) = match duration.find(|ch: char| !ch.is_ascii_digit()) {
+        Some(i) => duration.split_at(i),
+        None => (duration, "s"),
+    };
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration `{duration}`"))?;
+    match unit {
+        "ms" => Ok(Duration::from_millis(number)),
+        "s" => Ok(Duration::from_secs(number)),
+        "m" => Ok(Duration::from_secs(number * 60)),
+        _ => Err(format!(
+            "invalid duration unit `{unit}`, expected `ms`, `s` or `m`"
+        )),
+    }
+}
+
+/// Converts the code given to `exit` to the exit code of the process. Like on
+/// Unix, only its lowest byte is kept.
+fn exit_code(code: i64) -> ExitCode {
+    ExitCode::from(code as u8)
+}
+
+/// Prints the bindings listed by `:env`, one per line.
+fn print_bindings(bindings: &[(String, Object)], output_style: &OutputStyle) {
+    for (name, value) in bindings {
+        println!("{name} = {}", output_style.format(value));
+    }
+}
+
+/// Returns the message of an error of the VM, pointing out the ones caused by
+/// invalid bytecode rather than by the program.
+fn vm_error_message(error: &VmError) -> String {
+    if error.is_internal() {
+        format!("Internal error: {error}")
     } else {
-        Ok(evaluated.to_string())
+        error.to_string()
     }
 }
 
-fn compile(line: &str) -> Result<Bytecode, Box<dyn Error>> {
-    let lexer = Lexer::new(line);
-    let mut parser = Parser::new(lexer);
-    let program = parser.parse_program();
-    if !parser.errors.is_empty() {
-        return Err(Box::new(parser.errors));
+/// Builds a runtime error with the calls which were running, naming the file
+/// and the position it happened at unless it comes from the REPL. Compiled
+/// files only know the positions.
+fn runtime_error(
+    error: String,
+    source: Option<&Source>,
+    mut stack_trace: Vec<StackFrame>,
+) -> Box<dyn Error> {
+    let mut error = RuntimeError::new(error);
+    match source {
+        // The positions would be in the line the code was typed on
+        Some(source) if &*source.name == REPL_SOURCE_NAME => {
+            for frame in &mut stack_trace {
+                frame.position = None;
+            }
+        }
+        Some(source) => error = error.in_file(Rc::clone(&source.name)),
+        None => {}
     }
-    let mut compiler = Compiler::new();
-    match compiler.compile(program) {
-        Ok(()) => Ok(compiler.bytecode()),
-        Err(e) => Err(Box::new(CompilerError::new(e))),
+    if let Some(position) = stack_trace.last().and_then(|frame| frame.position) {
+        error = error.at(position);
     }
+    Box::new(error.with_stack_trace(stack_trace))
 }
 
-fn run_vm(bytecode: Bytecode) -> Result<String, Box<dyn Error>> {
-    let mut vm = VM::new(bytecode);
-    match vm.run() {
-        Ok(()) => match vm.last_popped_stack_element() {
-            Ok(obj) => match obj.as_ref() {
-                Object::ERROR(error) => Err(Box::new(RuntimeError::new(error.clone()))),
-                x => Ok(x.to_string()),
-            },
-            Err(_) => Err(Box::new(RuntimeError::new(String::from(
-                "No object returned from VM",
-            )))),
-        },
-        Err(e) => Err(Box::new(RuntimeError::new(e))),
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("5s"), Ok(Duration::from_secs(5)));
+        assert_eq!(parse_duration("5"), Ok(Duration::from_secs(5)));
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_duration("2m"), Ok(Duration::from_secs(120)));
+        assert_eq!(
+            parse_duration("5h"),
+            Err("invalid duration unit `h`, expected `ms`, `s` or `m`".to_string())
+        );
+        assert_eq!(parse_duration("s"), Err("invalid duration `s`".to_string()));
+    }
+
+    #[test]
+    fn test_check_files() {
+        use clap::Parser;
+
+        let cli = ReplCli::try_parse_from(["monkey"]).unwrap();
+        let examples = [
+            "monkey_examples/fibonacci.monkey",
+            "monkey_examples/map.monkey",
+        ];
+        assert_eq!(
+            cli.check_files(&examples.map(String::from)),
+            ExitCode::SUCCESS
+        );
+
+        let path = std::env::temp_dir().join("chimpanzee_check_test.monkey");
+        fs::write(&path, "let x = 1;\nif (false) { y }").unwrap();
+        let files = [examples[0].to_string(), path.display().to_string()];
+        assert_eq!(cli.check_files(&files), ExitCode::FAILURE);
+        fs::remove_file(path).unwrap();
+        assert_eq!(
+            cli.check_files(&["missing.monkey".to_string()]),
+            ExitCode::FAILURE
+        );
+    }
+
+    #[test]
+    fn test_dump_flags() {
+        use clap::Parser;
+
+        let mode = |args: &[&str]| ReplCli::try_parse_from(args).unwrap().get_mode();
+        assert_eq!(mode(&["monkey", "a.monkey"]), Mode::Compiler);
+        assert_eq!(mode(&["monkey", "a.monkey", "--dump-ast"]), Mode::Parser);
+        assert_eq!(mode(&["monkey", "a.monkey", "--dump-tokens"]), Mode::Lexer);
+        assert_eq!(
+            mode(&["monkey", "a.monkey", "--dump-bytecode", "-m", "interpreter"]),
+            Mode::Disassemble
+        );
+        assert!(ReplCli::try_parse_from(["monkey", "--dump-ast", "--dump-bytecode"]).is_err());
+        assert!(ReplCli::try_parse_from(["monkey", "run", "a.mkc", "--dump-bytecode"]).is_ok());
     }
 }
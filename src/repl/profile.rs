@@ -0,0 +1,383 @@
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "hooks")]
+use crate::hooks::Hooks;
+use crate::{compiler::code::Opcode, object::Object};
+
+/// Number of calls and cumulative time spent in a function, including the
+/// time spent in the functions it calls.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CallStats {
+    pub calls: usize,
+    pub time: Duration,
+}
+
+/// Number of times the instructions of an opcode ran, and the time spent until
+/// the next instruction started.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct InstructionStats {
+    pub runs: u64,
+    pub time: Duration,
+}
+
+/// Start or end of a call, at a time from the start of the trace.
+#[derive(Debug, Clone, PartialEq)]
+struct TraceEvent {
+    name: String,
+    begin: bool,
+    time: Duration,
+}
+
+#[derive(Debug)]
+struct Trace {
+    start: Instant,
+    events: Vec<TraceEvent>,
+}
+
+/// Collects call statistics for every function called while it is installed
+/// as the hooks of an engine, and instruction statistics for the VM.
+#[derive(Debug, Default)]
+pub struct Profiler {
+    stats: HashMap<String, CallStats>,
+    stack: Vec<(String, Instant)>,
+    opcodes: HashMap<Opcode, InstructionStats>,
+
+    /// Instruction running, since when.
+    instruction: Option<(Opcode, Instant)>,
+
+    /// Every call and return, if they are recorded.
+    trace: Option<Trace>,
+}
+
+// Without hooks nothing feeds the profiler, its reports are always empty
+#[cfg_attr(not(feature = "hooks"), allow(dead_code))]
+impl Profiler {
+    /// Records every call and return from now on, to be exported with
+    /// `trace_json`.
+    pub fn record_trace(&mut self) {
+        self.trace = Some(Trace {
+            start: Instant::now(),
+            events: Vec::new(),
+        });
+    }
+
+    pub fn enter(&mut self, name: String) {
+        let now = Instant::now();
+        self.trace_event(&name, true, now);
+        self.stack.push((name, now));
+    }
+
+    pub fn exit(&mut self) {
+        if let Some((name, start)) = self.stack.pop() {
+            let now = Instant::now();
+            self.trace_event(&name, false, now);
+            let stats = self.stats.entry(name).or_default();
+            stats.calls += 1;
+            stats.time += now - start;
+        }
+    }
+
+    fn trace_event(&mut self, name: &str, begin: bool, at: Instant) {
+        if let Some(trace) = &mut self.trace {
+            trace.events.push(TraceEvent {
+                name: name.to_string(),
+                begin,
+                time: at - trace.start,
+            });
+        }
+    }
+
+    pub fn instruction(&mut self, opcode: Opcode) {
+        let now = Instant::now();
+        if let Some((previous, start)) = self.instruction.replace((opcode, now)) {
+            self.opcodes.entry(previous).or_default().time += now - start;
+        }
+        self.opcodes.entry(opcode).or_default().runs += 1;
+    }
+
+    /// Forgets the calls that never returned, which happens when the execution
+    /// stops on an error, and stops timing the last instruction. It must be
+    /// called at the end of every run.
+    pub fn reset_stack(&mut self) {
+        // The calls still count in the trace, they end with the execution
+        let now = Instant::now();
+        while let Some((name, _)) = self.stack.pop() {
+            self.trace_event(&name, false, now);
+        }
+        if let Some((opcode, start)) = self.instruction.take() {
+            self.opcodes.entry(opcode).or_default().time += start.elapsed();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.stats.clear();
+        self.stack.clear();
+        self.opcodes.clear();
+        self.instruction = None;
+        if self.trace.is_some() {
+            self.record_trace();
+        }
+    }
+
+    /// Returns the recorded calls in the trace event format of Chrome, which
+    /// can be opened in Perfetto or `chrome://tracing`, or converted to a
+    /// flamegraph. Returns `None` if the calls are not recorded.
+    pub fn trace_json(&self) -> Option<String> {
+        let trace = self.trace.as_ref()?;
+        let events: Vec<String> = trace
+            .events
+            .iter()
+            .map(|event| {
+                format!(
+                    "{{\"name\":{},\"ph\":\"{}\",\"ts\":{:.3},\"pid\":1,\"tid\":1}}",
+                    json_string(&event.name),
+                    if event.begin { "B" } else { "E" },
+                    event.time.as_secs_f64() * 1_000_000.0
+                )
+            })
+            .collect();
+        Some(format!(
+            "{{\"traceEvents\":[\n{}\n],\"displayTimeUnit\":\"ms\"}}\n",
+            events.join(",\n")
+        ))
+    }
+
+    /// Returns a table of the statistics, slowest functions first. With the
+    /// VM, it is followed by the instructions, the most run first, and by
+    /// their classes, the slowest first.
+    pub fn report(&self) -> String {
+        let mut stats: Vec<(&String, &CallStats)> = self.stats.iter().collect();
+        stats.sort_by(|a, b| b.1.time.cmp(&a.1.time).then_with(|| a.0.cmp(b.0)));
+
+        let mut report = format!("{:<24} {:>10} {:>14}\n", "function", "calls", "time");
+        for (name, stats) in stats {
+            let _ = writeln!(
+                report,
+                "{name:<24} {:>10} {:>14}",
+                stats.calls,
+                format!("{:.3?}", stats.time)
+            );
+        }
+        if self.opcodes.is_empty() {
+            return report;
+        }
+
+        let mut opcodes: Vec<(String, InstructionStats)> = self
+            .opcodes
+            .iter()
+            .map(|(opcode, stats)| (opcode.to_string(), *stats))
+            .collect();
+        opcodes.sort_by(|a, b| b.1.runs.cmp(&a.1.runs).then_with(|| a.0.cmp(&b.0)));
+        report.push_str(&instruction_table("opcode", opcodes));
+
+        let mut classes: HashMap<&str, InstructionStats> = HashMap::new();
+        for (opcode, stats) in &self.opcodes {
+            let class = classes.entry(opcode_class(*opcode)).or_default();
+            class.runs += stats.runs;
+            class.time += stats.time;
+        }
+        let mut classes: Vec<(String, InstructionStats)> = classes
+            .into_iter()
+            .map(|(class, stats)| (class.to_string(), stats))
+            .collect();
+        classes.sort_by(|a, b| b.1.time.cmp(&a.1.time).then_with(|| a.0.cmp(&b.0)));
+        report.push_str(&instruction_table("class", classes));
+
+        report
+    }
+
+    fn function_name(function: &Object) -> String {
+        let name = match function {
+            Object::FUNCTION(function) => function.name.clone(),
+            Object::CLOSURE(closure) => closure.function.name.clone(),
+            Object::COMPILEDFUNCTION(function) => function.name.clone(),
+            Object::BUILTIN(builtin) => Some(builtin.to_string()),
+            _ => None,
+        };
+        name.unwrap_or_else(|| String::from("<anonymous>"))
+    }
+}
+
+fn json_string(string: &str) -> String {
+    let mut json = String::from("\"");
+    for c in string.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+fn instruction_table(title: &str, rows: Vec<(String, InstructionStats)>) -> String {
+    let mut table = format!("\n{title:<24} {:>10} {:>14}\n", "runs", "time");
+    for (name, stats) in rows {
+        let _ = writeln!(
+            table,
+            "{name:<24} {:>10} {:>14}",
+            stats.runs,
+            format!("{:.3?}", stats.time)
+        );
+    }
+    table
+}
+
+/// Returns the kind of work done by the instructions of an opcode.
+fn opcode_class(opcode: Opcode) -> &'static str {
+    match opcode {
+        Opcode::Constant | Opcode::True | Opcode::False | Opcode::Null => "constants",
+        Opcode::Add
+        | Opcode::Sub
+        | Opcode::Mul
+        | Opcode::Div
+        | Opcode::Modulo
+        | Opcode::Minus
+        | Opcode::AddConstants => "arithmetic",
+        Opcode::GreaterThan
+        | Opcode::GreaterEqualThan
+        | Opcode::Equal
+        | Opcode::NotEqual
+        | Opcode::Or
+        | Opcode::And
+        | Opcode::Bang => "logic",
+        Opcode::JumpNotTruthy
+        | Opcode::Jump
+        | Opcode::JumpNotTruthyWide
+        | Opcode::JumpWide
+        | Opcode::JumpTable
+        | Opcode::CompareLocalConstantJump
+        | Opcode::CompareConstantLocalJump => "jumps",
+        Opcode::SetGlobal
+        | Opcode::GetGlobal
+        | Opcode::GetGlobalByName
+        | Opcode::SetLocal
+        | Opcode::GetLocal
+        | Opcode::GetFree
+        | Opcode::CurrentClosure
+        | Opcode::GetBuiltin => "variables",
+        Opcode::Array | Opcode::HashMap | Opcode::Index | Opcode::SetIndex => "collections",
+        Opcode::Call | Opcode::ReturnValue | Opcode::Return | Opcode::Closure => "calls",
+        Opcode::Pop => "stack",
+    }
+}
+
+#[cfg(feature = "hooks")]
+impl Hooks for Profiler {
+    fn on_instruction(&mut self, _ip: usize, opcode: Opcode) {
+        self.instruction(opcode);
+    }
+
+    fn on_call(&mut self, function: &Object, _args: &[Object]) {
+        self.enter(Self::function_name(function));
+    }
+
+    fn on_return(&mut self, _value: &Object) {
+        self.exit();
+    }
+}
+
+#[cfg(all(test, feature = "hooks"))]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::{compiler::Compiler, interpreter::evaluator::Evaluator, parser::parse, vm::VM};
+
+    const INPUT: &str = r"
+    let fibonacci = fn(x) {
+        if (x < 2) {
+            return x;
+        }
+        fibonacci(x - 1) + fibonacci(x - 2)
+    };
+    let numbers = map([1, 2, 3], fn(x) { fibonacci(x) });
+    len(numbers);
+    ";
+
+    fn check_stats(profiler: &Profiler) {
+        // fibonacci(1), fibonacci(2) and fibonacci(3) make 1, 3 and 5 calls
+        assert_eq!(profiler.stats["fibonacci"].calls, 9);
+        assert_eq!(profiler.stats["<anonymous>"].calls, 3);
+        assert_eq!(profiler.stats["map"].calls, 1);
+        assert_eq!(profiler.stats["len"].calls, 1);
+        assert!(profiler.stack.is_empty());
+
+        let report = profiler.report();
+        assert!(report.starts_with("function"));
+        let functions = report.lines().take_while(|line| !line.is_empty());
+        assert_eq!(functions.count(), 5);
+    }
+
+    #[test]
+    fn test_profile_evaluator() {
+        let profiler = Rc::new(RefCell::new(Profiler::default()));
+        let mut evaluator = Evaluator::new();
+        evaluator.set_hooks(Box::new(Rc::clone(&profiler)));
+
+        evaluator.eval(parse(INPUT));
+
+        check_stats(&profiler.borrow());
+        assert_eq!(profiler.borrow().report().lines().count(), 5);
+    }
+
+    #[test]
+    fn test_trace_json() {
+        let profiler = Rc::new(RefCell::new(Profiler::default()));
+        assert_eq!(profiler.borrow().trace_json(), None);
+        profiler.borrow_mut().record_trace();
+        let mut evaluator = Evaluator::new();
+        evaluator.set_hooks(Box::new(Rc::clone(&profiler)));
+
+        evaluator.eval(parse(INPUT));
+
+        let trace = profiler.borrow().trace_json().unwrap();
+        assert!(trace.starts_with("{\"traceEvents\":[\n{\"name\":\"map\",\"ph\":\"B\",\"ts\":"));
+        assert!(trace.ends_with("],\"displayTimeUnit\":\"ms\"}\n"));
+        // Every one of the 14 calls begins and ends
+        assert_eq!(trace.matches("\"ph\":\"B\"").count(), 14);
+        assert_eq!(trace.matches("\"ph\":\"E\"").count(), 14);
+
+        // The calls stopped by an error end with the execution
+        let mut profiler = Profiler::default();
+        profiler.record_trace();
+        profiler.enter(String::from("a\"b"));
+        profiler.reset_stack();
+        let trace = profiler.trace_json().unwrap();
+        assert_eq!(trace.matches("\"name\":\"a\\\"b\"").count(), 2);
+        assert_eq!(trace.matches("\"ph\":\"E\"").count(), 1);
+    }
+
+    #[test]
+    fn test_profile_vm() {
+        let profiler = Rc::new(RefCell::new(Profiler::default()));
+        let mut compiler = Compiler::new();
+        compiler.compile(&parse(INPUT)).unwrap();
+        let mut vm = VM::new(compiler.bytecode());
+        vm.set_hooks(Box::new(Rc::clone(&profiler)));
+
+        vm.run().unwrap();
+        profiler.borrow_mut().reset_stack();
+
+        let profiler = profiler.borrow();
+        check_stats(&profiler);
+        // The calls to fibonacci, map and len, the anonymous function is
+        // called by map
+        assert_eq!(profiler.opcodes[&Opcode::Call].runs, 11);
+        assert_eq!(profiler.opcodes[&Opcode::Closure].runs, 2);
+        assert!(profiler.instruction.is_none());
+
+        let report = profiler.report();
+        assert!(report.contains("\nopcode "));
+        assert!(report.contains("\nclass "));
+        assert!(report.contains("\ncalls "));
+    }
+}
@@ -0,0 +1,59 @@
+use std::fmt::Write;
+
+use crate::compiler::symbol_table::Definition;
+
+/// Returns one line for every defined symbol, in the order of definition: the
+/// function defining it, its scope and index, its name and the position of the
+/// statement defining it.
+pub fn dump_symbols(definitions: &[Definition]) -> String {
+    let width = definitions
+        .iter()
+        .map(|definition| definition.function.len())
+        .max()
+        .unwrap_or_default();
+    let mut output = String::new();
+    for Definition {
+        symbol,
+        function,
+        position,
+    } in definitions
+    {
+        let scope = format!("{:?}", symbol.scope);
+        let mut line = format!(
+            "{function:<width$}  {scope:<8}  {:>3}  {}",
+            symbol.index, symbol.name
+        );
+        if let Some(position) = position {
+            let _ = write!(line, " ({position})");
+        }
+        output.push_str(&line);
+        output.push('\n');
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compiler::Compiler, parser::parse, source::Source};
+
+    #[test]
+    fn test_dump_symbols() {
+        let input = "let x = 1;\nlet add = fn(a, b) {\n  let sum = a + b;\n  sum\n};\nlet x = fn() { let x = x + 1; x }();";
+        let mut compiler = Compiler::new();
+        compiler
+            .compile(&parse(input).with_source(Source::new("test.monkey", input)))
+            .unwrap();
+
+        let expected = "\
+main         Global      0  x (1:1)
+main         Global      1  add (2:1)
+add          Function    0  add (2:1)
+add          Local       0  a (2:1)
+add          Local       1  b (2:1)
+add          Local       2  sum (3:3)
+<anonymous>  Local       0  x (6:16)
+";
+        assert_eq!(dump_symbols(compiler.definitions()), expected);
+    }
+}
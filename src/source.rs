@@ -0,0 +1,242 @@
+use std::{fmt::Display, rc::Rc};
+
+/// The source code a program comes from, along with the name of its file.
+///
+/// It is attached to [`Program`] and [`Bytecode`] so that diagnostics can point
+/// to `file.monkey:12:3` and show the offending line without reading the file
+/// again. Cloning it is cheap, the text is shared.
+///
+/// [`Program`]: crate::parser::ast::Program
+/// [`Bytecode`]: crate::compiler::Bytecode
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Source {
+    pub name: Rc<str>,
+    pub text: Rc<str>,
+}
+
+/// A position in a [`Source`], with 1-based line and column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+impl Source {
+    pub fn new(name: &str, text: &str) -> Self {
+        Self {
+            name: Rc::from(name),
+            text: Rc::from(text),
+        }
+    }
+
+    /// Returns the position of the character at `offset`, counted in characters
+    /// like the lexer does. Offsets past the end point right after the last
+    /// character.
+    pub fn position(&self, offset: usize) -> Position {
+        let mut position = Position { line: 1, column: 1 };
+        for ch in self.text.chars().take(offset) {
+            if ch == '\n' {
+                position.line += 1;
+                position.column = 1;
+            } else {
+                position.column += 1;
+            }
+        }
+        position
+    }
+
+    /// Returns the text of the given 1-based line, without its line break.
+    pub fn line(&self, line: usize) -> Option<&str> {
+        self.text.lines().nth(line.checked_sub(1)?)
+    }
+
+    /// Formats the character at `offset` as `name:line:column`.
+    pub fn location(&self, offset: usize) -> String {
+        format!("{}:{}", self.name, self.position(offset))
+    }
+
+    /// Returns the location of `offset` followed by its line and a caret under
+    /// the character, ready to be printed with a diagnostic.
+    pub fn snippet(&self, offset: usize) -> String {
+        let position = self.position(offset);
+        let line = self.line(position.line).unwrap_or_default();
+        let gutter = position.line.to_string().len();
+        format!(
+            "{}\n{:gutter$} |\n{} | {line}\n{:gutter$} | {:>column$}",
+            self.location(offset),
+            "",
+            position.line,
+            "",
+            "^",
+            column = position.column,
+        )
+    }
+}
+
+/// Turns offsets into positions like [`Source::position`], but without going
+/// through the text every time, for the compiler which needs the position of
+/// every statement.
+pub struct LineIndex {
+    /// Offset of the first character of every line.
+    starts: Vec<usize>,
+    length: usize,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> Self {
+        let mut starts = vec![0];
+        let mut length = 0;
+        for (offset, ch) in text.chars().enumerate() {
+            if ch == '\n' {
+                starts.push(offset + 1);
+            }
+            length += 1;
+        }
+        Self { starts, length }
+    }
+
+    /// Returns the position of the character at `offset`. Offsets past the end
+    /// point right after the last character.
+    pub fn position(&self, offset: usize) -> Position {
+        let offset = offset.min(self.length);
+        let line = self.starts.partition_point(|start| *start <= offset);
+        Position {
+            line,
+            column: offset - self.starts[line - 1] + 1,
+        }
+    }
+}
+
+/// Debug symbols of a compiled function: the position in the source of the
+/// statement every instruction comes from.
+///
+/// It holds the position and the first instruction of every statement, sorted
+/// by instruction, and is shared by the clones of the function.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SourceMap {
+    entries: Rc<[(usize, Position)]>,
+}
+
+impl SourceMap {
+    pub fn new(entries: Vec<(usize, Position)>) -> Self {
+        Self {
+            entries: Rc::from(entries),
+        }
+    }
+
+    /// Returns the first instruction and the position of every statement.
+    pub fn entries(&self) -> &[(usize, Position)] {
+        &self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the position of the statement the instruction at `ip` belongs
+    /// to, if it is known.
+    pub fn position(&self, ip: usize) -> Option<Position> {
+        let index = self.entries.partition_point(|(start, _)| *start <= ip);
+        Some(self.entries.get(index.checked_sub(1)?)?.1)
+    }
+
+    /// Returns whether a statement starts at `ip`, with its position.
+    pub fn statement_at(&self, ip: usize) -> Option<Position> {
+        self.entries
+            .binary_search_by_key(&ip, |(start, _)| *start)
+            .ok()
+            .map(|index| self.entries[index].1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compiler::Compiler, parser::parse, vm::VM};
+
+    const INPUT: &str = "let a = 1;\nlet b = a + c;\n";
+
+    #[test]
+    fn test_position() {
+        let source = Source::new("test.monkey", INPUT);
+
+        assert_eq!(source.position(0), Position { line: 1, column: 1 });
+        assert_eq!(source.position(4), Position { line: 1, column: 5 });
+        assert_eq!(source.position(11), Position { line: 2, column: 1 });
+        assert_eq!(
+            source.position(23),
+            Position {
+                line: 2,
+                column: 13
+            }
+        );
+        assert_eq!(source.position(100), Position { line: 3, column: 1 });
+    }
+
+    #[test]
+    fn test_line_index() {
+        let source = Source::new("test.monkey", INPUT);
+        let index = LineIndex::new(INPUT);
+
+        for offset in [0, 4, 10, 11, 23, 25, 100] {
+            assert_eq!(index.position(offset), source.position(offset));
+        }
+    }
+
+    #[test]
+    fn test_source_map() {
+        let position = |line| Position { line, column: 1 };
+        let source_map = SourceMap::new(vec![(0, position(1)), (4, position(2)), (9, position(4))]);
+
+        assert_eq!(source_map.position(0), Some(position(1)));
+        assert_eq!(source_map.position(3), Some(position(1)));
+        assert_eq!(source_map.position(4), Some(position(2)));
+        assert_eq!(source_map.position(100), Some(position(4)));
+        assert_eq!(source_map.statement_at(4), Some(position(2)));
+        assert_eq!(source_map.statement_at(5), None);
+        assert_eq!(SourceMap::default().position(0), None);
+    }
+
+    #[test]
+    fn test_line_and_location() {
+        let source = Source::new("test.monkey", INPUT);
+
+        assert_eq!(source.line(2), Some("let b = a + c;"));
+        assert_eq!(source.line(0), None);
+        assert_eq!(source.line(3), None);
+        assert_eq!(source.location(23), "test.monkey:2:13");
+    }
+
+    #[test]
+    fn test_snippet() {
+        let source = Source::new("test.monkey", INPUT);
+
+        assert_eq!(
+            source.snippet(23),
+            "test.monkey:2:13\n  |\n2 | let b = a + c;\n  |             ^"
+        );
+    }
+
+    #[test]
+    fn test_source_is_kept_up_to_the_vm() {
+        let source = Source::new("test.monkey", "let a = 1;");
+        let program = parse(&source.text).with_source(source.clone());
+
+        let mut compiler = Compiler::new();
+        compiler.compile(&program).unwrap();
+        let bytecode = compiler.bytecode();
+        assert_eq!(bytecode.source.as_ref(), Some(&source));
+
+        let vm = VM::new(bytecode);
+        assert_eq!(vm.source(), Some(&source));
+    }
+}
@@ -0,0 +1,41 @@
+/// Checks of the strict mode, rejecting programs which are valid but rely on
+/// surprising behaviours of Monkey. They are all off by default, and
+/// [`StrictConfig::strict`] turns all of them on.
+///
+/// It is given to the evaluator and to the compiler, and to the VM running
+/// the compiled programs, with `set_strict`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StrictConfig {
+    /// Every identifier must be defined before the program runs, even in the
+    /// branches which never run. The compiler always checks it.
+    pub undefined_identifiers: bool,
+
+    /// The conditions of `if` and `while` must be booleans, instead of any
+    /// value but `false` and `null` being true.
+    pub boolean_conditions: bool,
+
+    /// A `let` cannot bind a name the same function already defined, with a
+    /// `let` or as a parameter. Functions can still define their own
+    /// variables named like the ones of the functions around them.
+    pub no_redefinitions: bool,
+}
+
+impl StrictConfig {
+    pub fn strict() -> Self {
+        Self {
+            undefined_identifiers: true,
+            boolean_conditions: true,
+            no_redefinitions: true,
+        }
+    }
+}
+
+/// Error of a condition which is not a boolean, in strict mode.
+pub fn condition_error(type_name: &str) -> String {
+    format!("condition must be a BOOLEAN, got {type_name}")
+}
+
+/// Error of a `let` binding a name which is already defined, in strict mode.
+pub fn redefinition_error(name: &str) -> String {
+    format!("`{name}` is already defined")
+}
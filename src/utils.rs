@@ -4,7 +4,10 @@ use crate::{
     lexer::Lexer,
     object::Object,
     parser::{ast::Program, Parser},
-    vm::VM,
+    vm::{
+        register::{compiler::RegisterCompiler, RegisterVM},
+        VM,
+    },
 };
 
 pub fn parse_program(input: &str) -> Program {
@@ -13,7 +16,7 @@ pub fn parse_program(input: &str) -> Program {
     parser.parse_program()
 }
 
-pub fn compile_program(program: Program) -> Compiler {
+pub fn compile_program(program: &Program) -> Compiler {
     let mut compiler = Compiler::new();
     compiler.compile(program).unwrap();
     compiler
@@ -26,6 +29,18 @@ pub fn execute_vm(compiler: &Compiler) -> Object {
     vm.last_popped_stack_element().unwrap().as_ref().clone()
 }
 
+pub fn compile_register_program(program: &Program) -> RegisterCompiler {
+    let mut compiler = RegisterCompiler::new();
+    compiler.compile(program).unwrap();
+    compiler
+}
+
+pub fn execute_register_vm(compiler: &RegisterCompiler) -> Object {
+    let mut vm = RegisterVM::new(compiler.bytecode());
+    vm.run().unwrap();
+    vm.result().as_ref().clone()
+}
+
 pub fn execute_interpreter(program: &Program) {
     let mut interpreter = Evaluator::new();
 
@@ -34,6 +49,6 @@ pub fn execute_interpreter(program: &Program) {
 
 pub fn run_input(input: &str) -> Object {
     let program = parse_program(input);
-    let compiler = compile_program(program.clone());
+    let compiler = compile_program(&program);
     execute_vm(&compiler)
 }
@@ -0,0 +1,127 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+};
+
+use crate::source::Position;
+
+/// Statements run by a program, collected once [`VM::collect_coverage`] is
+/// called, from the debug symbols of the program and of its functions.
+///
+/// [`VM::collect_coverage`]: super::VM::collect_coverage
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Coverage {
+    /// Position of every statement of the program and of its functions,
+    /// including those which never ran.
+    pub statements: Vec<Position>,
+
+    /// Name and position of the first statement of every function with a
+    /// body.
+    pub functions: Vec<(String, Position)>,
+
+    /// Number of times every statement which ran started.
+    pub hits: HashMap<Position, usize>,
+}
+
+impl Coverage {
+    /// Returns the number of times the statement at `position` started.
+    pub fn hits(&self, position: Position) -> usize {
+        self.hits.get(&position).copied().unwrap_or_default()
+    }
+
+    /// Returns the most a statement of every line ran, by line.
+    fn lines(&self) -> BTreeMap<usize, usize> {
+        let mut lines = BTreeMap::new();
+        for &position in &self.statements {
+            let hits = lines.entry(position.line).or_default();
+            *hits = self.hits(position).max(*hits);
+        }
+        lines
+    }
+
+    /// Returns the functions whose first statement never ran.
+    pub fn missed_functions(&self) -> Vec<&(String, Position)> {
+        self.functions
+            .iter()
+            .filter(|(_, position)| self.hits(*position) == 0)
+            .collect()
+    }
+
+    /// Returns the number of statements, lines and functions which ran, out
+    /// of all of them, followed by the functions which never ran.
+    pub fn summary(&self) -> String {
+        let ran = |hits: usize| usize::from(hits > 0);
+        let lines = self.lines();
+        let missed = self.missed_functions();
+        let mut summary = [
+            (
+                "statements",
+                self.statements.iter().map(|p| ran(self.hits(*p))).sum(),
+                self.statements.len(),
+            ),
+            (
+                "lines",
+                lines.values().map(|hits| ran(*hits)).sum(),
+                lines.len(),
+            ),
+            (
+                "functions",
+                self.functions.len() - missed.len(),
+                self.functions.len(),
+            ),
+        ]
+        .iter()
+        .fold(String::new(), |mut summary, (name, ran, total)| {
+            let _ = writeln!(
+                summary,
+                "{name:<12} {:>12} {}",
+                format!("{ran}/{total}"),
+                percent(*ran, *total)
+            );
+            summary
+        });
+        for (name, position) in missed {
+            let _ = writeln!(summary, "never called: {name} at {position}");
+        }
+        summary
+    }
+
+    /// Returns the coverage in the LCOV tracefile format, for the source file
+    /// at `path`. The count of a function is the number of times its first
+    /// statement ran.
+    pub fn lcov(&self, path: &str) -> String {
+        let mut lcov = format!("TN:\nSF:{path}\n");
+        for (name, position) in &self.functions {
+            let _ = writeln!(lcov, "FN:{},{name}", position.line);
+        }
+        for (name, position) in &self.functions {
+            let _ = writeln!(lcov, "FNDA:{},{name}", self.hits(*position));
+        }
+        let missed = self.missed_functions().len();
+        let _ = writeln!(
+            lcov,
+            "FNF:{}\nFNH:{}",
+            self.functions.len(),
+            self.functions.len() - missed
+        );
+        let lines = self.lines();
+        for (line, hits) in &lines {
+            let _ = writeln!(lcov, "DA:{line},{hits}");
+        }
+        let _ = writeln!(
+            lcov,
+            "LF:{}\nLH:{}\nend_of_record",
+            lines.len(),
+            lines.values().filter(|hits| **hits > 0).count()
+        );
+        lcov
+    }
+}
+
+#[allow(clippy::cast_precision_loss)] // the percentage is rounded anyway
+fn percent(part: usize, total: usize) -> String {
+    if total == 0 {
+        return "-".to_string();
+    }
+    format!("{:.1}%", part as f64 * 100.0 / total as f64)
+}
@@ -0,0 +1,159 @@
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+};
+
+use crate::object::{
+    error::{ErrorKind, ErrorObject},
+    interrupt::INTERRUPTED,
+    max_depth_error, FUEL_EXHAUSTED,
+};
+
+/// Error stopping the VM.
+///
+/// Its message is the same as the one of the string errors the VM used to
+/// return, the variants let the REPL and embedders tell the errors of the
+/// program from the bytecode the VM cannot run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VmError {
+    /// The stack needs more than `max_stack_size` slots.
+    StackOverflow {
+        max_stack_size: usize,
+    },
+    /// A call to `function` went deeper than `max_depth` calls.
+    MaxDepth {
+        max_depth: usize,
+        function: Option<String>,
+    },
+    /// An operator or an index was applied to values it does not support.
+    TypeMismatch(String),
+    DivisionByZero,
+    /// A value which is not a function was called.
+    NotAFunction,
+    WrongArguments {
+        want: usize,
+        got: usize,
+    },
+    /// The global `index` is out of the `globals` the VM has.
+    UndefinedGlobal {
+        index: usize,
+        globals: usize,
+    },
+    /// A variable read by name, which `eval` did not define.
+    UndefinedVariable(String),
+    /// An element of a collection could not be assigned.
+    IndexAssignment(String),
+    /// A builtin stopped the program, like a failed `assert`.
+    Builtin(String),
+    FuelExhausted,
+    Timeout(String),
+    /// The interrupt of the VM was set, such as by Ctrl-C in the REPL.
+    Interrupted,
+    /// Bytecode the compiler does not produce, like an unknown opcode.
+    Internal(String),
+}
+
+impl VmError {
+    /// Returns whether the error comes from invalid bytecode rather than from
+    /// the program.
+    pub fn is_internal(&self) -> bool {
+        matches!(self, VmError::Internal(_))
+    }
+}
+
+impl Display for VmError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            VmError::StackOverflow { max_stack_size } => write!(
+                f,
+                "Stack overflow: the stack is limited to {max_stack_size} slots"
+            ),
+            VmError::MaxDepth {
+                max_depth,
+                function,
+            } => f.write_str(&max_depth_error(*max_depth, function.as_deref())),
+            VmError::DivisionByZero => f.write_str("Division by zero"),
+            VmError::NotAFunction => f.write_str("Calling non-function"),
+            VmError::WrongArguments { want, got } => {
+                write!(f, "Wrong number of arguments: want={want}, got={got}")
+            }
+            VmError::UndefinedGlobal { index, globals } => {
+                write!(
+                    f,
+                    "Global {index} is out of the {globals} globals of the VM"
+                )
+            }
+            VmError::UndefinedVariable(name) => write!(f, "Undefined variable: {name}"),
+            VmError::FuelExhausted => f.write_str(FUEL_EXHAUSTED),
+            VmError::Interrupted => f.write_str(INTERRUPTED),
+            VmError::TypeMismatch(message)
+            | VmError::IndexAssignment(message)
+            | VmError::Builtin(message)
+            | VmError::Timeout(message)
+            | VmError::Internal(message) => f.write_str(message),
+        }
+    }
+}
+
+impl Error for VmError {}
+
+/// The error a builtin gets when a function it calls fails.
+impl From<&VmError> for ErrorObject {
+    fn from(error: &VmError) -> Self {
+        let kind = match error {
+            VmError::TypeMismatch(_) => ErrorKind::TypeMismatch,
+            VmError::UndefinedVariable(_) => ErrorKind::UndefinedIdentifier,
+            _ => ErrorKind::Custom,
+        };
+        ErrorObject::new(kind, error.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages() {
+        let errors = [
+            (
+                VmError::StackOverflow { max_stack_size: 8 },
+                "Stack overflow: the stack is limited to 8 slots",
+            ),
+            (
+                VmError::MaxDepth {
+                    max_depth: 3,
+                    function: Some("f".to_string()),
+                },
+                "Maximum call depth of 3 exceeded when calling `f`",
+            ),
+            (VmError::DivisionByZero, "Division by zero"),
+            (
+                VmError::WrongArguments { want: 1, got: 2 },
+                "Wrong number of arguments: want=1, got=2",
+            ),
+            (
+                VmError::UndefinedGlobal {
+                    index: 4,
+                    globals: 2,
+                },
+                "Global 4 is out of the 2 globals of the VM",
+            ),
+            (VmError::FuelExhausted, "Fuel exhausted"),
+            (VmError::Interrupted, "Interrupted"),
+            (
+                VmError::Internal("Unknown opcode 255".to_string()),
+                "Unknown opcode 255",
+            ),
+        ];
+        for (error, message) in errors {
+            assert_eq!(error.to_string(), message);
+        }
+        assert!(VmError::Internal(String::new()).is_internal());
+        assert_eq!(
+            ErrorObject::from(&VmError::TypeMismatch("unknown operator".to_string())).kind,
+            ErrorKind::TypeMismatch
+        );
+        assert!(!VmError::DivisionByZero.is_internal());
+    }
+}
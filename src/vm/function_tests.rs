@@ -1,12 +1,19 @@
 #[allow(clippy::too_many_lines)]
 #[cfg(test)]
 mod tests {
+    use std::{cell::RefCell, collections::HashMap, io::Write, rc::Rc, time::Duration};
+
     use crate::{
         compiler::Compiler,
-        object::Object,
+        interpreter::evaluator::Evaluator,
+        object::{interrupt::Interrupt, Object, StackFrame},
         parser::parse,
+        source::{Position, Source},
         vm::{
-            test_utils::{run_vm_tests, VmTestCase},
+            error::VmError,
+            new_global_store,
+            stats::Stats,
+            test_utils::{run_vm_tests, run_vm_with_error_output, VmTestCase},
             VM,
         },
     };
@@ -243,19 +250,19 @@ mod tests {
                 input: r"
                     fn() { 1; }(1);"
                     .to_string(),
-                expected: Object::ERROR("Wrong number of arguments: want=0, got=1".to_string()),
+                expected: Object::error("Wrong number of arguments: want=0, got=1".to_string()),
             },
             VmTestCase {
                 input: r"
                     fn(a) { a; }();"
                     .to_string(),
-                expected: Object::ERROR("Wrong number of arguments: want=1, got=0".to_string()),
+                expected: Object::error("Wrong number of arguments: want=1, got=0".to_string()),
             },
             VmTestCase {
                 input: r"
                     fn(a, b) { a + b; }(1);"
                     .to_string(),
-                expected: Object::ERROR("Wrong number of arguments: want=2, got=1".to_string()),
+                expected: Object::error("Wrong number of arguments: want=2, got=1".to_string()),
             },
         ];
 
@@ -263,7 +270,7 @@ mod tests {
             println!("Running test: {}", test.input);
             let program = parse(&test.input);
             let mut compiler = Compiler::new();
-            compiler.compile(program).unwrap();
+            compiler.compile(&program).unwrap();
             let bytecode = compiler.bytecode();
 
             let mut vm = VM::new(bytecode);
@@ -272,8 +279,8 @@ mod tests {
                     panic!("Expected error, but got no error");
                 }
                 Err(e) => match test.expected {
-                    Object::ERROR(msg) => {
-                        assert_eq!(e, msg);
+                    Object::ERROR(error) => {
+                        assert_eq!(e.to_string(), error.message);
                     }
                     _ => {
                         unreachable!("Poorly written test, the expected value should be an error");
@@ -300,11 +307,11 @@ mod tests {
             },
             VmTestCase {
                 input: r"len(1)".to_string(),
-                expected: Object::ERROR("argument to `len` not supported, got INTEGER".to_string()),
+                expected: Object::error("argument to `len` not supported, got INTEGER".to_string()),
             },
             VmTestCase {
                 input: r#"len("one", "two")"#.to_string(),
-                expected: Object::ERROR("wrong number of arguments. got=2, want=1".to_string()),
+                expected: Object::error("wrong number of arguments. got=2, want=1".to_string()),
             },
             VmTestCase {
                 input: r"len([1, 2, 3])".to_string(),
@@ -316,7 +323,7 @@ mod tests {
             },
             VmTestCase {
                 input: r"len([1, 2, 3], [4, 5, 6])".to_string(),
-                expected: Object::ERROR("wrong number of arguments. got=2, want=1".to_string()),
+                expected: Object::error("wrong number of arguments. got=2, want=1".to_string()),
             },
             VmTestCase {
                 input: r"first([1, 2, 3])".to_string(),
@@ -328,7 +335,7 @@ mod tests {
             },
             VmTestCase {
                 input: r"first(1)".to_string(),
-                expected: Object::ERROR(
+                expected: Object::error(
                     "argument to `first` not supported, must be ARRAY, got INTEGER".to_string(),
                 ),
             },
@@ -342,7 +349,7 @@ mod tests {
             },
             VmTestCase {
                 input: r"last(1)".to_string(),
-                expected: Object::ERROR(
+                expected: Object::error(
                     "argument to `last` not supported, must be ARRAY, got INTEGER".to_string(),
                 ),
             },
@@ -360,15 +367,807 @@ mod tests {
             },
             VmTestCase {
                 input: r"push(1, 1)".to_string(),
-                expected: Object::ERROR(
+                expected: Object::error(
                     "argument to `push` not supported, must be ARRAY, got INTEGER".to_string(),
                 ),
             },
+            VmTestCase {
+                input: r"merge({1: 1, 2: 2}, {2: 3, 4: 4})".to_string(),
+                expected: Object::HASHMAP(HashMap::from([
+                    (Object::INTEGER(1), Object::INTEGER(1)),
+                    (Object::INTEGER(2), Object::INTEGER(3)),
+                    (Object::INTEGER(4), Object::INTEGER(4)),
+                ])),
+            },
+            VmTestCase {
+                input: r"merge({}, 1)".to_string(),
+                expected: Object::error(
+                    "argument to `merge` not supported, must be HASHMAP, got INTEGER".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r"keys({1: 2})".to_string(),
+                expected: Object::ARRAY(vec![Object::INTEGER(1)]),
+            },
+            VmTestCase {
+                input: r"values({1: 2})".to_string(),
+                expected: Object::ARRAY(vec![Object::INTEGER(2)]),
+            },
+            VmTestCase {
+                input: r"delete({1: 2, 3: 4}, 1)".to_string(),
+                expected: Object::HASHMAP(HashMap::from([(
+                    Object::INTEGER(3),
+                    Object::INTEGER(4),
+                )])),
+            },
+            VmTestCase {
+                input: r"delete([], 1)".to_string(),
+                expected: Object::error(
+                    "argument to `delete` not supported, must be HASHMAP, got ARRAY".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r#"ord("a")"#.to_string(),
+                expected: Object::INTEGER(97),
+            },
+            VmTestCase {
+                input: r#"ord("ab")"#.to_string(),
+                expected: Object::error(
+                    "argument to `ord` must be a single character, got \"ab\"".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r"chr(97)".to_string(),
+                expected: Object::STRING("a".into()),
+            },
+            VmTestCase {
+                input: r#"chr(ord("a") + 1)"#.to_string(),
+                expected: Object::STRING("b".into()),
+            },
+            VmTestCase {
+                input: r"chr(-1)".to_string(),
+                expected: Object::error(
+                    "argument to `chr` is not a valid codepoint: -1".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r#"split("a,b", ",")"#.to_string(),
+                expected: Object::ARRAY(vec![
+                    Object::STRING("a".into()),
+                    Object::STRING("b".into()),
+                ]),
+            },
+            VmTestCase {
+                input: r#"join(["a", "b"], ", ")"#.to_string(),
+                expected: Object::STRING("a, b".into()),
+            },
+            VmTestCase {
+                input: r#"upper(trim(" a "))"#.to_string(),
+                expected: Object::STRING("A".into()),
+            },
+            VmTestCase {
+                input: r#"lower("AB")"#.to_string(),
+                expected: Object::STRING("ab".into()),
+            },
+            VmTestCase {
+                input: r#"replace("aba", "a", "c")"#.to_string(),
+                expected: Object::STRING("cbc".into()),
+            },
+            VmTestCase {
+                input: r#"starts_with("abc", "ab")"#.to_string(),
+                expected: Object::BOOLEAN(true),
+            },
+            VmTestCase {
+                input: r#"ends_with("abc", "ab")"#.to_string(),
+                expected: Object::BOOLEAN(false),
+            },
+            VmTestCase {
+                input: r#"contains("abc", "b")"#.to_string(),
+                expected: Object::BOOLEAN(true),
+            },
+            VmTestCase {
+                input: r#"contains(1, "b")"#.to_string(),
+                expected: Object::error(
+                    "argument to `contains` not supported, must be STRING, ARRAY or HASHMAP, got INTEGER"
+                        .to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r"abs(-3)".to_string(),
+                expected: Object::INTEGER(3),
+            },
+            VmTestCase {
+                input: r"min(1, 2) + max(1, 2)".to_string(),
+                expected: Object::INTEGER(3),
+            },
+            VmTestCase {
+                input: r"pow(3, 3)".to_string(),
+                expected: Object::INTEGER(27),
+            },
+            VmTestCase {
+                input: r"sqrt(16)".to_string(),
+                expected: Object::INTEGER(4),
+            },
+            VmTestCase {
+                input: r"floor(2) + ceil(3)".to_string(),
+                expected: Object::INTEGER(5),
+            },
+            VmTestCase {
+                input: r#"type("a")"#.to_string(),
+                expected: Object::STRING("STRING".into()),
+            },
+            VmTestCase {
+                input: r"type(fn() { 1 })".to_string(),
+                expected: Object::STRING("CLOSURE".into()),
+            },
+            VmTestCase {
+                input: r#"int("42") + int(true)"#.to_string(),
+                expected: Object::INTEGER(43),
+            },
+            VmTestCase {
+                input: r#"int("")"#.to_string(),
+                expected: Object::error("could not convert \"\" to INTEGER".to_string()),
+            },
+            VmTestCase {
+                input: r#"str(42) + str(false)"#.to_string(),
+                expected: Object::STRING("42false".into()),
+            },
+            VmTestCase {
+                input: r"range(0, 10, 4)".to_string(),
+                expected: Object::ARRAY(vec![
+                    Object::INTEGER(0),
+                    Object::INTEGER(4),
+                    Object::INTEGER(8),
+                ]),
+            },
+            VmTestCase {
+                input: r"range(1, 5, 0)".to_string(),
+                expected: Object::error("step of `range` must not be zero".to_string()),
+            },
+            VmTestCase {
+                input: r"range(1000000000000)".to_string(),
+                expected: Object::error(
+                    "`range` of 1000000000000 elements is too large, the limit is 10000000"
+                        .to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r"let start = clock(); (time() > 1700000000) == (clock() >= start)"
+                    .to_string(),
+                expected: Object::BOOLEAN(true),
+            },
+            VmTestCase {
+                input: r"seed(7); let a = rand(); let b = rand_int(-5, 5); seed(7); [a == rand(), b == rand_int(-5, 5)]"
+                    .to_string(),
+                expected: Object::ARRAY(vec![Object::BOOLEAN(true), Object::BOOLEAN(true)]),
+            },
+            VmTestCase {
+                input: r"rand_int(2, 1)".to_string(),
+                expected: Object::error("range of `rand_int` is empty, got 2 to 1".to_string()),
+            },
+            VmTestCase {
+                input: r#"format("{:<4}|{:^5}|{}", "a", [1], true)"#.to_string(),
+                expected: Object::STRING("a   | [1] |true".into()),
+            },
+            VmTestCase {
+                input: r#"format("{", 1)"#.to_string(),
+                expected: Object::error("unmatched `{` in format string".to_string()),
+            },
+            VmTestCase {
+                input: r#"[contains([1, "a"], "a"), contains({"a": 1}, "b"), index_of([1, [2]], [2])]"#
+                    .to_string(),
+                expected: Object::ARRAY(vec![
+                    Object::BOOLEAN(true),
+                    Object::BOOLEAN(false),
+                    Object::INTEGER(1),
+                ]),
+            },
+            VmTestCase {
+                input: r#"[reverse([1, "a"]), reverse("monkey")]"#.to_string(),
+                expected: Object::ARRAY(vec![
+                    Object::ARRAY(vec![Object::STRING("a".into()), Object::INTEGER(1)]),
+                    Object::STRING("yeknom".into()),
+                ]),
+            },
+            VmTestCase {
+                input: r#"[slice([1, 2, 3], -2, 5), slice("monkey", 3)]"#.to_string(),
+                expected: Object::ARRAY(vec![
+                    Object::ARRAY(vec![Object::INTEGER(2), Object::INTEGER(3)]),
+                    Object::STRING("key".into()),
+                ]),
+            },
+            VmTestCase {
+                input: r#"print("")"#.to_string(),
+                expected: Object::NULL,
+            },
+            VmTestCase {
+                input: r#"let b = bytes([104, 105]); [b[0], b[2], len(b), decode(b)]"#.to_string(),
+                expected: Object::ARRAY(vec![
+                    Object::INTEGER(104),
+                    Object::NULL,
+                    Object::INTEGER(2),
+                    Object::STRING("hi".into()),
+                ]),
+            },
+            VmTestCase {
+                input: r#"b"hi""#.to_string(),
+                expected: Object::BYTES(b"hi".to_vec()),
+            },
+            VmTestCase {
+                input: r#"let d = date(timestamp(2024, 2, 29 + 1)); format_time(timestamp(d["year"], d["month"], d["day"]), "%a %d %b")"#.to_string(),
+                expected: Object::STRING("Fri 01 Mar".into()),
+            },
+            VmTestCase {
+                input: r#"abs("a")"#.to_string(),
+                expected: Object::error(
+                    "argument to `abs` not supported, must be INTEGER, got STRING".to_string(),
+                ),
+            },
         ];
 
         run_vm_tests(tests);
     }
 
+    #[test]
+    fn test_builtin_functions_calling_closures() {
+        let tests = vec![
+            VmTestCase {
+                input: r"map([1, 2, 3], fn(x) { x * 2 })".to_string(),
+                expected: Object::ARRAY(vec![
+                    Object::INTEGER(2),
+                    Object::INTEGER(4),
+                    Object::INTEGER(6),
+                ]),
+            },
+            VmTestCase {
+                input: r"let factor = 3; map([1, 2], fn(x) { x * factor })".to_string(),
+                expected: Object::ARRAY(vec![Object::INTEGER(3), Object::INTEGER(6)]),
+            },
+            VmTestCase {
+                input: r"map([[1], [2, 3]], fn(a) { map(a, fn(x) { x + 1 }) })".to_string(),
+                expected: Object::ARRAY(vec![
+                    Object::ARRAY(vec![Object::INTEGER(2)]),
+                    Object::ARRAY(vec![Object::INTEGER(3), Object::INTEGER(4)]),
+                ]),
+            },
+            VmTestCase {
+                input: r"map([1, 2], len)".to_string(),
+                expected: Object::error("argument to `len` not supported, got INTEGER".to_string()),
+            },
+            VmTestCase {
+                input: r"filter([1, 2, 3, 4], fn(x) { x % 2 == 0 })".to_string(),
+                expected: Object::ARRAY(vec![Object::INTEGER(2), Object::INTEGER(4)]),
+            },
+            VmTestCase {
+                input: r"reduce([1, 2, 3, 4], 0, fn(acc, x) { acc + x })".to_string(),
+                expected: Object::INTEGER(10),
+            },
+            VmTestCase {
+                input: r"
+                let fibonacci = fn(x) { if (x < 2) { x } else { fibonacci(x - 1) + fibonacci(x - 2) } };
+                reduce(map([1, 2, 3, 4, 5], fibonacci), 0, fn(acc, x) { acc + x })"
+                    .to_string(),
+                expected: Object::INTEGER(12),
+            },
+            VmTestCase {
+                input: r"let total = fn(a) { reduce(a, 0, fn(acc, x) { acc + x }) }; total([1, 2]) + 1"
+                    .to_string(),
+                expected: Object::INTEGER(4),
+            },
+            VmTestCase {
+                input: r"reduce(1, 0, fn(acc, x) { acc + x })".to_string(),
+                expected: Object::error(
+                    "argument to `reduce` not supported, must be ARRAY, got INTEGER".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r"reduce(range(1, 11), 0, fn(acc, x) { acc + x })".to_string(),
+                expected: Object::INTEGER(55),
+            },
+            VmTestCase {
+                input: r"sort([3, 1, 2])".to_string(),
+                expected: Object::ARRAY(vec![
+                    Object::INTEGER(1),
+                    Object::INTEGER(2),
+                    Object::INTEGER(3),
+                ]),
+            },
+            VmTestCase {
+                input: r"sort([3, 1, 2], fn(a, b) { a > b })".to_string(),
+                expected: Object::ARRAY(vec![
+                    Object::INTEGER(3),
+                    Object::INTEGER(2),
+                    Object::INTEGER(1),
+                ]),
+            },
+        ];
+
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_argv() {
+        let program = parse("argv()");
+        let mut compiler = Compiler::new();
+        compiler.compile(&program).unwrap();
+
+        let mut vm = VM::new(compiler.bytecode());
+        vm.set_args(vec!["foo".to_string()]);
+        vm.run().unwrap();
+        assert_eq!(
+            vm.last_popped_stack_element().unwrap().as_ref(),
+            &Object::ARRAY(vec![Object::STRING("foo".into())])
+        );
+    }
+
+    #[test]
+    fn test_stack_grows_up_to_its_limit() {
+        // The stack used to be limited to 2048 slots
+        let input = "let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } }; f(1000)";
+        run_vm_tests(vec![VmTestCase {
+            input: input.to_string(),
+            expected: Object::INTEGER(1000),
+        }]);
+
+        let mut compiler = Compiler::new();
+        compiler.compile(&parse(input)).unwrap();
+        let mut vm = VM::new(compiler.bytecode());
+        vm.set_max_stack_size(1000);
+        assert_eq!(
+            vm.run(),
+            Err(VmError::StackOverflow {
+                max_stack_size: 1000
+            })
+        );
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let input = "let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } };";
+        let run = |call: &str| {
+            let mut compiler = Compiler::new();
+            compiler
+                .compile(&parse(&format!("{input} {call}")))
+                .unwrap();
+            let mut vm = VM::new(compiler.bytecode());
+            vm.set_max_depth(10);
+            vm.run().map(|()| vm.last_popped_stack_element().unwrap())
+        };
+
+        assert_eq!(run("f(9)").unwrap().as_ref(), &Object::INTEGER(9));
+        assert_eq!(
+            run("f(10)"),
+            Err(VmError::MaxDepth {
+                max_depth: 10,
+                function: Some("f".to_string())
+            })
+        );
+        assert_eq!(
+            run("let g = fn() { fn() { g() }() }; fn() { g() }()"),
+            Err(VmError::MaxDepth {
+                max_depth: 10,
+                function: None
+            })
+        );
+    }
+
+    #[test]
+    fn test_fuel() {
+        let run = |input: &str, fuel: u64| {
+            let mut compiler = Compiler::new();
+            compiler.compile(&parse(input)).unwrap();
+            let mut vm = VM::new(compiler.bytecode());
+            vm.set_fuel(fuel);
+            (vm.run(), vm.fuel())
+        };
+
+        // OpAddConstants and OpPop, a superinstruction counts as one
+        assert_eq!(run("1 + 2", 2), (Ok(()), Some(0)));
+        assert_eq!(run("1 + 2", 10), (Ok(()), Some(8)));
+        assert_eq!(run("1 + 2", 1), (Err(VmError::FuelExhausted), Some(0)));
+        assert_eq!(
+            run("while (true) { 1 }", 1000),
+            (Err(VmError::FuelExhausted), Some(0))
+        );
+        assert_eq!(
+            run(r#"eval("while (true) { 1 }")"#, 1000),
+            (Err(VmError::FuelExhausted), Some(0))
+        );
+    }
+
+    #[test]
+    fn test_timeout() {
+        let mut compiler = Compiler::new();
+        compiler.compile(&parse("while (true) { 1 }")).unwrap();
+        let mut vm = VM::new(compiler.bytecode());
+        vm.set_timeout(Duration::from_millis(10));
+        assert_eq!(
+            vm.run(),
+            Err(VmError::Timeout(
+                "Timeout: the program ran for more than 10ms".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let mut compiler = Compiler::new();
+        compiler.compile(&parse("while (true) { 1 }")).unwrap();
+        let mut vm = VM::new(compiler.bytecode());
+        let interrupt = Interrupt::default();
+        vm.set_interrupt(interrupt.clone());
+        let handle = interrupt.clone();
+        let thread = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(10));
+            handle.interrupt();
+        });
+        assert_eq!(vm.run(), Err(VmError::Interrupted));
+        thread.join().unwrap();
+
+        interrupt.clear();
+        let mut compiler = vm.new_compiler();
+        compiler.compile(&parse("1 + 1")).unwrap();
+        vm.load(compiler.into_bytecode());
+        assert_eq!(vm.run(), Ok(()));
+        assert_eq!(
+            vm.last_popped_stack_element().unwrap().as_ref(),
+            &Object::INTEGER(2)
+        );
+    }
+
+    /// Writer whose output can still be read once it is given to the VM.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_trace() {
+        let mut compiler = Compiler::new();
+        compiler
+            .compile(&parse("let add = fn(a, b) { a + b }; add(1, 2)"))
+            .unwrap();
+        let mut vm = VM::new(compiler.bytecode());
+        let trace = SharedBuffer::default();
+        vm.set_trace(Box::new(trace.clone()));
+        vm.run().unwrap();
+
+        let expected = "\
+main 0000 OpClosure 0 0 | top: <empty>
+main 0004 OpSetGlobal 0 | top: fn add
+main 0007 OpGetGlobal 0 | top: <empty>
+main 0010 OpConstant 1 | top: fn add
+main 0013 OpConstant 2 | top: 1
+main 0016 OpCall 2 | top: 2
+  add 0000 OpGetLocal 0 | top: 2
+  add 0002 OpGetLocal 1 | top: 1
+  add 0004 OpAdd | top: 2
+  add 0005 OpReturnValue | top: 3
+main 0018 OpPop | top: 3
+";
+        assert_eq!(String::from_utf8(trace.0.take()).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_stats() {
+        let input = r"
+        let sum = fn(x) { if (x == 0) { 0 } else { x + sum(x - 1) } };
+        let numbers = [sum(10), [1, 2], {1: 2}];
+        ";
+        let mut compiler = Compiler::new();
+        compiler.compile(&parse(input)).unwrap();
+        let mut vm = VM::new(compiler.bytecode());
+        assert_eq!(vm.stats(), None);
+        vm.collect_stats();
+        vm.run().unwrap();
+
+        assert_eq!(
+            vm.stats(),
+            Some(Stats {
+                // The closure sum and the 8 values of numbers
+                peak_objects: 9,
+                constants: 9,
+                globals: 2,
+                // The callee, the argument and the left operand of the
+                // addition of every call
+                max_stack_size: 33,
+                max_depth: 11,
+            })
+        );
+    }
+
+    #[test]
+    fn test_coverage() {
+        let input = "let add = fn(a, b) {\n    a + b\n};\nlet unused = fn(x) {\n    x * 2\n};\nif (add(1, 2) > 5) {\n    puts(1);\n}\nadd(3, 4);";
+        let mut compiler = Compiler::new();
+        compiler
+            .compile(&parse(input).with_source(Source::new("test.monkey", input)))
+            .unwrap();
+        let mut vm = VM::new(compiler.bytecode());
+        assert_eq!(vm.coverage(), None);
+        vm.collect_coverage();
+        vm.run().unwrap();
+
+        let coverage = vm.coverage().unwrap();
+        assert_eq!(coverage.hits(Position { line: 2, column: 5 }), 2);
+        assert_eq!(coverage.hits(Position { line: 8, column: 5 }), 0);
+        assert_eq!(
+            coverage.summary(),
+            "\
+statements            5/7 71.4%
+lines                 5/7 71.4%
+functions             1/2 50.0%
+never called: unused at 5:5
+"
+        );
+        assert_eq!(
+            coverage.lcov("test.monkey"),
+            "\
+TN:
+SF:test.monkey
+FN:2,add
+FN:5,unused
+FNDA:2,add
+FNDA:0,unused
+FNF:2
+FNH:1
+DA:1,1
+DA:2,2
+DA:4,1
+DA:5,0
+DA:7,1
+DA:8,0
+DA:10,1
+LF:7
+LH:5
+end_of_record
+"
+        );
+    }
+
+    #[test]
+    fn test_error_position() {
+        let input =
+            "let div = fn(a, b) {\n    let x = a + 1;\n    x / b\n};\ndiv(4, 2);\ndiv(1, 0);";
+        let mut compiler = Compiler::new();
+        compiler
+            .compile(&parse(input).with_source(Source::new("test.monkey", input)))
+            .unwrap();
+        let mut vm = VM::new(compiler.bytecode());
+        assert_eq!(vm.position(), Some(Position { line: 1, column: 1 }));
+
+        assert_eq!(vm.run(), Err(VmError::DivisionByZero));
+        assert_eq!(vm.function_name(), "div");
+        assert_eq!(vm.position(), Some(Position { line: 3, column: 5 }));
+    }
+
+    #[test]
+    fn test_stack_trace() {
+        let input = "let check = fn(x) {\n  x + true\n};\nlet apply = fn(f) { f(1) };\napply(fn(x) { check(x) });";
+        let mut compiler = Compiler::new();
+        compiler
+            .compile(&parse(input).with_source(Source::new("test.monkey", input)))
+            .unwrap();
+        let mut vm = VM::new(compiler.bytecode());
+        assert_eq!(
+            vm.run(),
+            Err(VmError::TypeMismatch(
+                "Unsupported types for binary operation".to_string()
+            ))
+        );
+
+        let frame = |function: &str, line, column| StackFrame {
+            function: function.to_string(),
+            position: Some(Position { line, column }),
+        };
+        assert_eq!(
+            vm.stack_trace(),
+            vec![
+                frame("main", 5, 1),
+                frame("apply", 4, 21),
+                frame("<anonymous>", 5, 15),
+                frame("check", 2, 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_global_store_size() {
+        let mut compiler = Compiler::new();
+        compiler.set_max_globals(2);
+        compiler
+            .compile(&parse(
+                r#"let a = 1; eval("let b = 2;"); eval("let c = 3;")"#,
+            ))
+            .unwrap();
+        let mut vm = VM::new_with_global_store(compiler.bytecode(), new_global_store(2));
+        vm.run().unwrap();
+        assert_eq!(
+            vm.last_popped_stack_element().unwrap().as_ref(),
+            &Object::error("Too many global variables, the limit is 2".to_string())
+        );
+
+        // A program compiled for a larger store does not index past it
+        let mut compiler = Compiler::new();
+        compiler.compile(&parse("let a = 1; let b = 2;")).unwrap();
+        let mut vm = VM::new_with_global_store(compiler.bytecode(), new_global_store(1));
+        assert_eq!(
+            vm.run(),
+            Err(VmError::UndefinedGlobal {
+                index: 1,
+                globals: 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_input_functions() {
+        let program = parse("[input(), read_all(), input()]");
+        let mut compiler = Compiler::new();
+        compiler.compile(&program).unwrap();
+
+        let mut vm = VM::new(compiler.bytecode());
+        vm.set_input(Box::new("monkey\nline 2\nline 3".as_bytes()));
+        vm.run().unwrap();
+        assert_eq!(
+            vm.last_popped_stack_element().unwrap().as_ref(),
+            &Object::ARRAY(vec![
+                Object::STRING("monkey".into()),
+                Object::STRING("line 2\nline 3".into()),
+                Object::NULL,
+            ])
+        );
+    }
+
+    #[test]
+    fn test_exit_function() {
+        let tests = vec![
+            ("1; 2", None),
+            ("exit(); 1", Some(0)),
+            ("let f = fn() { exit(2); 1 }; f() + 1", Some(2)),
+            (
+                "map([1, 2, 3], fn(x) { if (x == 2) { exit(x) } x }); 1",
+                Some(2),
+            ),
+            (
+                "let a = fn() { reduce([1], 0, fn(acc, x) { exit(4) }) }; a(); 1",
+                Some(4),
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let mut compiler = Compiler::new();
+            compiler.compile(&parse(input)).unwrap();
+            let mut vm = VM::new(compiler.bytecode());
+            vm.run().unwrap();
+            assert_eq!(vm.exit_code(), expected, "{input}");
+        }
+
+        assert_eq!(
+            run_vm_with_error_output("exit(256); 1"),
+            Err(VmError::Builtin(
+                "exit code 256 is not supported, it must be between 0 and 255".to_string()
+            ))
+        );
+        assert_eq!(
+            run_vm_with_error_output(r#"puts(exit("a")); 1"#),
+            Err(VmError::Builtin(
+                "argument to `exit` not supported, must be INTEGER, got STRING".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_eval_function() {
+        let tests = vec![
+            VmTestCase {
+                input: r#"eval("1 + 2")"#.to_string(),
+                expected: Object::INTEGER(3),
+            },
+            VmTestCase {
+                input: r#"let a = 5; eval("a * 2")"#.to_string(),
+                expected: Object::INTEGER(10),
+            },
+            VmTestCase {
+                input: r#"eval("let b = 4;"); eval("b + 1")"#.to_string(),
+                expected: Object::INTEGER(5),
+            },
+            VmTestCase {
+                input: r#"eval("return 1; 2")"#.to_string(),
+                expected: Object::INTEGER(1),
+            },
+            VmTestCase {
+                input: r#"let f = eval("fn(x) { [x, x + 1] }"); f(1)"#.to_string(),
+                expected: Object::ARRAY(vec![Object::INTEGER(1), Object::INTEGER(2)]),
+            },
+            VmTestCase {
+                input: r#"let x = 1; let f = fn(x) { eval("x") }; f(2)"#.to_string(),
+                expected: Object::INTEGER(1),
+            },
+            VmTestCase {
+                input: r#"eval("let c = 1;")"#.to_string(),
+                expected: Object::NULL,
+            },
+            VmTestCase {
+                input: r#"eval("d")"#.to_string(),
+                expected: Object::error("Undefined variable: d".to_string()),
+            },
+            VmTestCase {
+                input: r#"eval("let e = 2;"); e * 3"#.to_string(),
+                expected: Object::INTEGER(6),
+            },
+        ];
+
+        run_vm_tests(tests);
+
+        assert_eq!(
+            run_vm_with_error_output(r#"g; eval("let g = 1;")"#),
+            Err(VmError::UndefinedVariable("g".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_eval_same_results_as_the_interpreter() {
+        let inputs = [
+            r#"eval("let b = 1;"); b"#,
+            r#"let f = fn() { c * 2 }; eval("let c = 21;"); f()"#,
+            r#"eval("let d = 1;"); let d = d + 1; d"#,
+            r#"let a = 1; eval("let a = a + 1;"); a"#,
+            r#"let defs = ["let x = 2;", "let y = x * 3;"]; map(defs, eval); [x, y]"#,
+        ];
+        for input in inputs {
+            let expected = Evaluator::new().eval(parse(input));
+
+            let mut compiler = Compiler::new();
+            compiler.compile(&parse(input)).unwrap();
+            let mut vm = VM::new(compiler.bytecode());
+            vm.run().unwrap();
+            let result = vm.last_popped_stack_element().unwrap();
+            assert_eq!(result.as_ref(), &expected, "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_builtin_functions_calling_closures_errors() {
+        let program = parse("map([1, 2], fn(a, b) { a + b })");
+        let mut compiler = Compiler::new();
+        compiler.compile(&program).unwrap();
+
+        let mut vm = VM::new(compiler.bytecode());
+        assert_eq!(vm.run(), Err(VmError::WrongArguments { want: 2, got: 1 }));
+
+        assert_eq!(
+            run_vm_with_error_output(r#"sort([2, 1], fn(a, b) { a + "" })"#),
+            Err(VmError::TypeMismatch(
+                "Unsupported types for binary operation".to_string()
+            ))
+        );
+        assert_eq!(
+            run_vm_with_error_output(r#"assert(1 > 2, "1 is not greater"); 5"#),
+            Err(VmError::Builtin(
+                "assertion failed: 1 is not greater".to_string()
+            ))
+        );
+        assert_eq!(
+            run_vm_with_error_output(r#"assert(true, "fine"); 5"#),
+            Ok(())
+        );
+        assert_eq!(
+            run_vm_with_error_output(r#"eval("1 + true")"#),
+            Err(VmError::TypeMismatch(
+                "Unsupported types for binary operation".to_string()
+            ))
+        );
+    }
+
     #[test]
     fn test_closures() {
         let tests = vec![
@@ -0,0 +1,133 @@
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::{
+        compiler::Compiler,
+        object::{Object, StackFrame},
+        parser::parse,
+        vm::{error::VmError, VM},
+    };
+
+    /// Calls `f` with the integers from 0 to 199, enough for it to be compiled,
+    /// and returns the list of results.
+    const HOT_CALLS: &str =
+        "let i = 0; let results = []; while (i < 200) { let results = push(results, f(i)); let i = i + 1; } results";
+
+    fn new_vm(input: &str, jit: bool) -> VM {
+        let mut compiler = Compiler::new();
+        compiler.compile(&parse(input)).unwrap();
+        let mut vm = VM::new(compiler.bytecode());
+        vm.set_jit(jit);
+        vm
+    }
+
+    /// Runs `input` with and without the JIT, checks that the results are the
+    /// same and returns the number of functions compiled.
+    fn compiled_functions(input: &str) -> usize {
+        let mut without_jit = new_vm(input, false);
+        let expected = without_jit
+            .run()
+            .map(|()| without_jit.last_popped_stack_element());
+        let mut with_jit = new_vm(input, true);
+        let got = with_jit
+            .run()
+            .map(|()| with_jit.last_popped_stack_element());
+        assert_eq!(got, expected, "input: {input}");
+        with_jit.jit.as_ref().unwrap().compiled_functions()
+    }
+
+    #[test]
+    fn test_numeric_functions_are_compiled() {
+        let inputs = [
+            "let f = fn(n) { let m = n % 15; if (m < 2) { m } else { f(m - 1) + f(m - 2) } };",
+            "let f = fn(n) { let a = n * 3 - 7; let b = -a % 5; (a / 2 + b) * a };",
+            "let f = fn(n) { if (n == 0) { true } else { !f(n - 1) } };",
+            "let f = fn(n) { ((n > 10) && (n <= 100)) || (n == 3) };",
+            "let f = fn(n) { let i = 0; let sum = 0; while (i < n) { let i = i + 1; if (i % 3 == 0) { continue; } let sum = sum + i; } sum };",
+            "let f = fn(n) { if ((n / 3 == 1) || (n != 7)) { 10 - 2 + n } else { 1 + 1 } };",
+            "let f = fn(n) { -9223372036854775807 / -1 + n % -1 };",
+        ];
+        for input in inputs {
+            assert_eq!(
+                compiled_functions(&format!("{input} {HOT_CALLS}")),
+                1,
+                "input: {input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_other_functions_are_not_compiled() {
+        let inputs = [
+            r#"let f = fn(n) { len("a") + n };"#,
+            "let f = fn(n) { [n][0] };",
+            "let a = 1; let f = fn(n) { a + n };",
+            "let f = fn(n) { if (n > 2) { 1 } };",
+            "let f = fn(n) { if (n > 2) { let x = 1; } x };",
+            "let f = fn(n) { if (n) { 1 } else { 2 } };",
+            "let f = fn(n) { !n };",
+            "let f = fn(n) { if (n > 2) { 1 } else { true } };",
+            "let f = fn(n) { let g = fn(x) { x }; n };",
+        ];
+        for input in inputs {
+            assert_eq!(
+                compiled_functions(&format!("{input} {HOT_CALLS}")),
+                0,
+                "input: {input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_non_integer_arguments() {
+        let input = format!("let f = fn(n) {{ n == n }}; {HOT_CALLS}; [f(1), f(true)]");
+        assert_eq!(compiled_functions(&input), 1);
+    }
+
+    #[test]
+    fn test_errors_are_reported_by_the_vm() {
+        let input = format!("let f = fn(n) {{ 10 / (n - 250) }}; {HOT_CALLS};\nf(250)");
+        let mut vm = new_vm(&input, true);
+        assert_eq!(vm.run(), Err(VmError::DivisionByZero));
+        assert_eq!(vm.jit.as_ref().unwrap().compiled_functions(), 1);
+        let functions: Vec<String> = vm
+            .stack_trace()
+            .into_iter()
+            .map(|StackFrame { function, .. }| function)
+            .collect();
+        assert_eq!(functions, vec!["main", "f"]);
+
+        let input = format!(
+            "let f = fn(n) {{ if (n == 0) {{ 0 }} else {{ 1 + f(n - 1) }} }}; {HOT_CALLS}; f(50)"
+        );
+        let mut vm = new_vm(&input, true);
+        vm.set_max_depth(300);
+        assert_eq!(vm.run(), Ok(()));
+        let mut vm = new_vm(&input.replace("f(50)", "f(400)"), true);
+        vm.set_max_depth(300);
+        assert_eq!(
+            vm.run(),
+            Err(VmError::MaxDepth {
+                max_depth: 300,
+                function: Some("f".to_string())
+            })
+        );
+        assert_eq!(vm.stack_trace().len(), 301);
+    }
+
+    #[test]
+    fn test_limits_disable_the_jit() {
+        let input = format!("let f = fn(n) {{ n + 1 }}; {HOT_CALLS}");
+        let mut vm = new_vm(&input, true);
+        vm.set_fuel(1_000_000);
+        vm.run().unwrap();
+        assert_eq!(vm.jit.as_ref().unwrap().compiled_functions(), 0);
+        assert_eq!(
+            vm.last_popped_stack_element(),
+            Ok(Rc::new(Object::ARRAY(
+                (1..=200).map(Object::INTEGER).collect()
+            )))
+        );
+    }
+}
@@ -0,0 +1,166 @@
+//! Compilation of the hot functions of the VM to native code with Cranelift.
+//!
+//! The VM counts the calls to every function, and compiles it once it was
+//! called [`JIT_THRESHOLD`] times. Only numeric functions can be compiled:
+//! they take integers, compute with integers and booleans in their locals, and
+//! can call themselves, but not use globals, closures, builtins nor any other
+//! value. The VM keeps running everything else, including the calls whose
+//! arguments are not all integers.
+//!
+//! Compiled functions have no side effects. When one fails, dividing by zero
+//! or recursing too deep, the VM runs the call again, and reports the error
+//! with its stack trace.
+
+mod jit_tests;
+mod translate;
+
+use std::{collections::HashMap, mem::offset_of, rc::Rc};
+
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::default_libcall_names;
+
+use crate::{
+    object::{CompiledFunction, Object},
+    vm::{
+        jit::translate::{translate, Translation, Type},
+        value::Value,
+    },
+};
+
+/// Number of calls after which a function is compiled.
+pub const JIT_THRESHOLD: u32 = 100;
+
+/// Number of nested calls of compiled functions, which run on the native
+/// stack. The VM runs the deeper ones.
+const MAX_NATIVE_DEPTH: usize = 10_000;
+
+/// State shared by the compiled functions of a call from the VM.
+#[repr(C)]
+struct Context {
+    /// Number of compiled calls running.
+    depth: i64,
+    max_depth: i64,
+    /// Set to 1 when a call fails.
+    failed: i64,
+}
+
+const DEPTH_OFFSET: i32 = offset_of!(Context, depth) as i32;
+const MAX_DEPTH_OFFSET: i32 = offset_of!(Context, max_depth) as i32;
+const FAILED_OFFSET: i32 = offset_of!(Context, failed) as i32;
+
+/// A compiled function, called with the context and its arguments.
+type NativeFunction = unsafe extern "C" fn(*mut Context, *const i64) -> i64;
+
+#[derive(Clone, Copy)]
+enum Entry {
+    /// Number of calls to a function not compiled yet.
+    Calls(u32),
+    /// A compiled function, with the type of the values it returns and the
+    /// number of slots of the stack of the VM a call uses.
+    Compiled(NativeFunction, Type, usize),
+    /// A function which cannot be compiled.
+    Unsupported,
+}
+
+#[derive(Default)]
+pub struct Jit {
+    /// Created when the first function is compiled.
+    module: Option<JITModule>,
+    /// Functions called so far, by address. Each one is kept alive with its
+    /// entry, so that its address is not reused by another function.
+    functions: HashMap<*const CompiledFunction, (Rc<CompiledFunction>, Entry)>,
+}
+
+impl Jit {
+    /// Runs a call to `function` as native code, compiling the function if it
+    /// became hot. Returns `None` if the VM must run the call itself.
+    ///
+    /// The call can be `max_depth` calls deep, counting itself, and its frames
+    /// must fit in the `stack_left` slots of the stack of the VM, so that the
+    /// native code fails whenever the VM would.
+    pub fn call(
+        &mut self,
+        function: &Rc<CompiledFunction>,
+        args: &[Value],
+        constants: &[Object],
+        max_depth: usize,
+        stack_left: usize,
+    ) -> Option<Object> {
+        let (_, entry) = self
+            .functions
+            .entry(Rc::as_ptr(function))
+            .or_insert_with(|| (Rc::clone(function), Entry::Calls(0)));
+        if let Entry::Calls(calls) = entry {
+            *calls += 1;
+            if *calls < JIT_THRESHOLD {
+                return None;
+            }
+            *entry = match compile(&mut self.module, function, constants) {
+                Some((native, returns, frame_size)) => Entry::Compiled(native, returns, frame_size),
+                None => Entry::Unsupported,
+            };
+        }
+        let Entry::Compiled(native, returns, frame_size) = *entry else {
+            return None;
+        };
+
+        let args = args
+            .iter()
+            .map(|arg| match arg {
+                Value::Integer(i) => Some(*i),
+                _ => None,
+            })
+            .collect::<Option<Vec<i64>>>()?;
+        if args.len() != function.num_parameters {
+            return None;
+        }
+        let mut context = Context {
+            depth: 0,
+            max_depth: max_depth.min(stack_left / frame_size).min(MAX_NATIVE_DEPTH) as i64,
+            failed: 0,
+        };
+        // SAFETY: the function reads as many arguments as it has parameters
+        let value = unsafe { native(&mut context, args.as_ptr()) };
+        if context.failed != 0 {
+            return None;
+        }
+        Some(match returns {
+            Type::Boolean => Object::BOOLEAN(value != 0),
+            _ => Object::INTEGER(value),
+        })
+    }
+
+    /// Returns the number of functions compiled to native code.
+    pub fn compiled_functions(&self) -> usize {
+        self.functions
+            .values()
+            .filter(|(_, entry)| matches!(entry, Entry::Compiled(..)))
+            .count()
+    }
+}
+
+/// Compiles `function` in `module`, creating it if needed.
+fn compile(
+    module: &mut Option<JITModule>,
+    function: &CompiledFunction,
+    constants: &[Object],
+) -> Option<(NativeFunction, Type, usize)> {
+    let module = match module {
+        Some(module) => module,
+        None => {
+            let builder =
+                JITBuilder::with_flags(&[("opt_level", "speed")], default_libcall_names()).ok()?;
+            module.insert(JITModule::new(builder))
+        }
+    };
+    let Translation {
+        id,
+        returns,
+        frame_size,
+    } = translate(module, function, constants)?;
+    module.finalize_definitions().ok()?;
+    let code = module.get_finalized_function(id);
+    // SAFETY: the function was compiled with the signature of `NativeFunction`
+    let native = unsafe { std::mem::transmute::<*const u8, NativeFunction>(code) };
+    Some((native, returns, frame_size))
+}
@@ -0,0 +1,626 @@
+use std::collections::HashMap;
+
+use cranelift_codegen::ir::{
+    condcodes::IntCC, types::I64, AbiParam, Block, FuncRef, InstBuilder, MemFlagsData, StackSlot,
+    StackSlotData, StackSlotKind, Value,
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::JITModule;
+use cranelift_module::{FuncId, Module};
+use num_traits::FromPrimitive;
+
+use crate::{
+    compiler::code::{Instructions, Opcode},
+    object::{CompiledFunction, Object},
+    vm::jit::{DEPTH_OFFSET, FAILED_OFFSET, MAX_DEPTH_OFFSET},
+};
+
+/// Type of a value of a compiled function, booleans are 0 or 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    Integer,
+    Boolean,
+    /// The function itself, which can only be called.
+    Function,
+    /// The value of an `if` without `else`, which can only be dropped.
+    Null,
+}
+
+impl Type {
+    /// Returns whether the value can be stored and compared.
+    fn is_value(self) -> bool {
+        matches!(self, Type::Integer | Type::Boolean)
+    }
+}
+
+/// Types of the stack and of the locals before an instruction. A local is
+/// `None` if it may not be set yet, or may hold values of different types.
+#[derive(Debug, Clone, PartialEq)]
+struct State {
+    stack: Vec<Type>,
+    locals: Vec<Option<Type>>,
+}
+
+impl State {
+    /// Returns the state holding for both `self` and `other`, `None` if their
+    /// stacks differ.
+    fn merge(&self, other: &State) -> Option<State> {
+        if self.stack != other.stack {
+            return None;
+        }
+        let locals = self
+            .locals
+            .iter()
+            .zip(&other.locals)
+            .map(|(left, right)| if left == right { *left } else { None })
+            .collect();
+        Some(State {
+            stack: self.stack.clone(),
+            locals,
+        })
+    }
+}
+
+type Instruction = (usize, Opcode, Vec<i32>);
+
+/// A function compiled by [`translate`].
+pub struct Translation {
+    pub id: FuncId,
+    /// Type of the values the function returns.
+    pub returns: Type,
+    /// Most slots of the stack of the VM a call to the function uses, the
+    /// callee and its locals included.
+    pub frame_size: usize,
+}
+
+/// Compiles `function` to native code in `module`. Returns `None` if the
+/// function uses anything but integers, booleans, its locals and calls to
+/// itself, or if its integer parameters are not enough to know the type of
+/// its values.
+pub fn translate(
+    module: &mut JITModule,
+    function: &CompiledFunction,
+    constants: &[Object],
+) -> Option<Translation> {
+    let instructions: Vec<Instruction> = Instructions::new(function.instructions.clone())
+        .iter()
+        .collect();
+    let analysis = Analysis {
+        function,
+        constants,
+        positions: instructions
+            .iter()
+            .enumerate()
+            .map(|(index, (ip, _, _))| (*ip, index))
+            .collect(),
+        instructions: &instructions,
+    };
+    // The type of the calls of the function to itself is the one it returns
+    let (states, returns) = [Type::Integer, Type::Boolean]
+        .into_iter()
+        .find_map(|returns| Some((analysis.states(returns)?, returns)))?;
+
+    let pointer = module.target_config().pointer_type();
+    let mut signature = module.make_signature();
+    signature.params.push(AbiParam::new(pointer));
+    signature.params.push(AbiParam::new(pointer));
+    signature.returns.push(AbiParam::new(I64));
+    let id = module.declare_anonymous_function(&signature).ok()?;
+
+    let mut context = module.make_context();
+    context.func.signature = signature;
+    let mut builder_context = FunctionBuilderContext::new();
+    let mut builder = FunctionBuilder::new(&mut context.func, &mut builder_context);
+    let this = module.declare_func_in_func(id, builder.func);
+    let slot = builder.create_sized_stack_slot(StackSlotData::new(
+        StackSlotKind::ExplicitSlot,
+        8 * function.num_parameters.max(1) as u32,
+        3,
+    ));
+    let mut codegen = Codegen::new(builder, function, constants, this, slot);
+    codegen.emit(&instructions, &states, pointer);
+    codegen.builder.finalize(module.target_config());
+
+    module.define_function(id, &mut context).ok()?;
+    module.clear_context(&mut context);
+    let height = states.values().map(|state| state.stack.len()).max();
+    Some(Translation {
+        id,
+        returns,
+        frame_size: 1 + function.num_locals + height.unwrap_or(0) + 1,
+    })
+}
+
+struct Analysis<'a> {
+    function: &'a CompiledFunction,
+    constants: &'a [Object],
+    instructions: &'a [Instruction],
+    /// Index in `instructions` of the instruction at every position.
+    positions: HashMap<usize, usize>,
+}
+
+impl Analysis<'_> {
+    /// Returns the state before every reachable instruction, assuming that the
+    /// function returns values of type `returns`.
+    fn states(&self, returns: Type) -> Option<HashMap<usize, State>> {
+        let entry = State {
+            stack: Vec::new(),
+            locals: (0..self.function.num_locals)
+                .map(|local| (local < self.function.num_parameters).then_some(Type::Integer))
+                .collect(),
+        };
+        let mut states = HashMap::from([(0, entry)]);
+        let mut pending = vec![0];
+        while let Some(ip) = pending.pop() {
+            let state = states[&ip].clone();
+            for (successor, state) in self.successors(ip, state, returns)? {
+                match states.get_mut(&successor) {
+                    Some(old) => {
+                        let merged = old.merge(&state)?;
+                        if merged != *old {
+                            *old = merged;
+                            pending.push(successor);
+                        }
+                    }
+                    None => {
+                        states.insert(successor, state);
+                        pending.push(successor);
+                    }
+                }
+            }
+        }
+        Some(states)
+    }
+
+    /// Returns the instructions which can run after the one at `ip`, with the
+    /// state before them.
+    fn successors(
+        &self,
+        ip: usize,
+        mut state: State,
+        returns: Type,
+    ) -> Option<Vec<(usize, State)>> {
+        let index = *self.positions.get(&ip)?;
+        let (_, op, operands) = &self.instructions[index];
+        let next = self.instructions.get(index + 1).map(|(ip, _, _)| *ip);
+        let stack = &mut state.stack;
+        match op {
+            Opcode::Constant => stack.push(self.constant(operands[0])?),
+            Opcode::True | Opcode::False => stack.push(Type::Boolean),
+            Opcode::Null => stack.push(Type::Null),
+            Opcode::Add | Opcode::Sub | Opcode::Mul | Opcode::Div | Opcode::Modulo => {
+                pop(stack, Type::Integer)?;
+                pop(stack, Type::Integer)?;
+                stack.push(Type::Integer);
+            }
+            Opcode::Or | Opcode::And => {
+                pop(stack, Type::Boolean)?;
+                pop(stack, Type::Boolean)?;
+                stack.push(Type::Boolean);
+            }
+            Opcode::Equal | Opcode::NotEqual => {
+                let right = stack.pop()?;
+                pop(stack, right).filter(|()| right.is_value())?;
+                stack.push(Type::Boolean);
+            }
+            Opcode::GreaterThan | Opcode::GreaterEqualThan => {
+                pop(stack, Type::Integer)?;
+                pop(stack, Type::Integer)?;
+                stack.push(Type::Boolean);
+            }
+            Opcode::Minus => {
+                pop(stack, Type::Integer)?;
+                stack.push(Type::Integer);
+            }
+            // Integers are always truthy, their conditions are left to the VM
+            Opcode::Bang => {
+                pop(stack, Type::Boolean)?;
+                stack.push(Type::Boolean);
+            }
+            Opcode::Jump | Opcode::JumpWide => return Some(vec![(operands[0] as usize, state)]),
+            Opcode::JumpNotTruthy | Opcode::JumpNotTruthyWide => {
+                pop(stack, Type::Boolean)?;
+                return Some(vec![(next?, state.clone()), (operands[0] as usize, state)]);
+            }
+            Opcode::AddConstants => {
+                self.constant(operands[0])?;
+                self.constant(operands[1])?;
+                stack.push(Type::Integer);
+            }
+            Opcode::CompareLocalConstantJump | Opcode::CompareConstantLocalJump => {
+                let (local, constant) = match op {
+                    Opcode::CompareLocalConstantJump => (operands[0], operands[1]),
+                    _ => (operands[1], operands[0]),
+                };
+                self.constant(constant)?;
+                comparison(operands[2])?;
+                if state.locals[local as usize] != Some(Type::Integer) {
+                    return None;
+                }
+                return Some(vec![(next?, state.clone()), (operands[3] as usize, state)]);
+            }
+            Opcode::SetLocal => {
+                let value = stack.pop().filter(|value| value.is_value())?;
+                state.locals[operands[0] as usize] = Some(value);
+            }
+            Opcode::GetLocal => stack.push(state.locals[operands[0] as usize]?),
+            Opcode::Pop => {
+                stack.pop()?;
+            }
+            Opcode::CurrentClosure => stack.push(Type::Function),
+            Opcode::Call => {
+                let num_args = operands[0] as usize;
+                if num_args != self.function.num_parameters {
+                    return None;
+                }
+                for _ in 0..num_args {
+                    pop(stack, Type::Integer)?;
+                }
+                pop(stack, Type::Function)?;
+                stack.push(returns);
+            }
+            Opcode::ReturnValue => {
+                pop(stack, returns)?;
+                return Some(Vec::new());
+            }
+            _ => return None,
+        }
+        Some(vec![(next?, state)])
+    }
+
+    /// Returns the type of a constant, which must be an integer.
+    fn constant(&self, index: i32) -> Option<Type> {
+        match self.constants.get(index as usize)? {
+            Object::INTEGER(_) => Some(Type::Integer),
+            _ => None,
+        }
+    }
+}
+
+/// Pops a value of type `expected`, failing if the top of the stack has
+/// another type.
+fn pop(stack: &mut Vec<Type>, expected: Type) -> Option<()> {
+    (stack.pop()? == expected).then_some(())
+}
+
+/// Returns the condition code of the comparison opcode of a superinstruction.
+fn comparison(opcode: i32) -> Option<IntCC> {
+    match Opcode::from_i32(opcode)? {
+        Opcode::Equal => Some(IntCC::Equal),
+        Opcode::NotEqual => Some(IntCC::NotEqual),
+        Opcode::GreaterThan => Some(IntCC::SignedGreaterThan),
+        Opcode::GreaterEqualThan => Some(IntCC::SignedGreaterThanOrEqual),
+        _ => None,
+    }
+}
+
+struct Codegen<'a> {
+    builder: FunctionBuilder<'a>,
+    function: &'a CompiledFunction,
+    constants: &'a [Object],
+    /// The function itself, for the recursive calls.
+    this: FuncRef,
+    /// Where the arguments of the recursive calls are stored.
+    slot: StackSlot,
+    /// Variables holding the slots of the stack, from the bottom.
+    stack: Vec<Variable>,
+    sp: usize,
+    locals: Vec<Variable>,
+    /// Start of every instruction which is jumped to or follows a jump.
+    blocks: HashMap<usize, Block>,
+    /// Returns without setting the failure flag, set by a recursive call.
+    abort: Block,
+    /// Sets the failure flag and returns.
+    fail: Block,
+}
+
+impl<'a> Codegen<'a> {
+    fn new(
+        mut builder: FunctionBuilder<'a>,
+        function: &'a CompiledFunction,
+        constants: &'a [Object],
+        this: FuncRef,
+        slot: StackSlot,
+    ) -> Self {
+        let locals = (0..function.num_locals)
+            .map(|_| builder.declare_var(I64))
+            .collect();
+        let abort = builder.create_block();
+        let fail = builder.create_block();
+        Self {
+            builder,
+            function,
+            constants,
+            this,
+            slot,
+            stack: Vec::new(),
+            sp: 0,
+            locals,
+            blocks: HashMap::new(),
+            abort,
+            fail,
+        }
+    }
+
+    fn emit(
+        &mut self,
+        instructions: &[Instruction],
+        states: &HashMap<usize, State>,
+        pointer: cranelift_codegen::ir::Type,
+    ) {
+        let mut starts = vec![0];
+        for (index, (_, op, operands)) in instructions.iter().enumerate() {
+            if let Some(target) = op.jump_operand() {
+                starts.push(operands[target] as usize);
+            }
+            if op.jump_operand().is_some() || *op == Opcode::ReturnValue {
+                starts.extend(instructions.get(index + 1).map(|(ip, _, _)| *ip));
+            }
+        }
+        for start in starts {
+            if states.contains_key(&start) && !self.blocks.contains_key(&start) {
+                self.blocks.insert(start, self.builder.create_block());
+            }
+        }
+
+        let entry = self.builder.create_block();
+        self.builder.append_block_params_for_function_params(entry);
+        self.builder.switch_to_block(entry);
+        let context = self.builder.block_params(entry)[0];
+        let args = self.builder.block_params(entry)[1];
+        self.enter(context, args);
+
+        let mut reachable = false;
+        for (index, (ip, op, operands)) in instructions.iter().enumerate() {
+            if let Some(block) = self.blocks.get(ip).copied() {
+                if reachable {
+                    self.builder.ins().jump(block, &[]);
+                }
+                self.builder.switch_to_block(block);
+                reachable = true;
+            }
+            if !reachable {
+                continue;
+            }
+            self.sp = states[ip].stack.len();
+            let next = instructions.get(index + 1).map(|(ip, _, _)| *ip);
+            reachable = self.instruction(*op, operands, next, context, pointer);
+        }
+
+        self.builder.switch_to_block(self.fail);
+        let failed = self.builder.ins().iconst(I64, 1);
+        self.builder
+            .ins()
+            .store(MemFlagsData::trusted(), failed, context, FAILED_OFFSET);
+        self.builder.ins().jump(self.abort, &[]);
+        self.builder.switch_to_block(self.abort);
+        let zero = self.builder.ins().iconst(I64, 0);
+        self.builder.ins().return_(&[zero]);
+        self.builder.seal_all_blocks();
+    }
+
+    /// Checks the depth of the call and reads the arguments into the locals.
+    fn enter(&mut self, context: Value, args: Value) {
+        let flags = MemFlagsData::trusted();
+        let depth = self.builder.ins().load(I64, flags, context, DEPTH_OFFSET);
+        let max_depth = self
+            .builder
+            .ins()
+            .load(I64, flags, context, MAX_DEPTH_OFFSET);
+        let too_deep = self
+            .builder
+            .ins()
+            .icmp(IntCC::SignedGreaterThanOrEqual, depth, max_depth);
+        let body = self.builder.create_block();
+        self.builder.ins().brif(too_deep, self.fail, &[], body, &[]);
+        self.builder.switch_to_block(body);
+        let depth = self.builder.ins().iadd_imm_s(depth, 1);
+        self.builder
+            .ins()
+            .store(flags, depth, context, DEPTH_OFFSET);
+
+        for (index, local) in self.locals.clone().into_iter().enumerate() {
+            let value = if index < self.function.num_parameters {
+                self.builder.ins().load(I64, flags, args, 8 * index as i32)
+            } else {
+                self.builder.ins().iconst(I64, 0)
+            };
+            self.builder.def_var(local, value);
+        }
+        self.builder.ins().jump(self.blocks[&0], &[]);
+    }
+
+    /// Emits an instruction, returning whether the next one can follow it.
+    fn instruction(
+        &mut self,
+        op: Opcode,
+        operands: &[i32],
+        next: Option<usize>,
+        context: Value,
+        pointer: cranelift_codegen::ir::Type,
+    ) -> bool {
+        match op {
+            Opcode::Constant => {
+                let value = self.constant(operands[0]);
+                self.push(value);
+            }
+            Opcode::True | Opcode::False => {
+                let value = self
+                    .builder
+                    .ins()
+                    .iconst(I64, i64::from(op == Opcode::True));
+                self.push(value);
+            }
+            Opcode::Add | Opcode::Sub | Opcode::Mul | Opcode::Or | Opcode::And => {
+                let right = self.pop();
+                let left = self.pop();
+                let ins = self.builder.ins();
+                let value = match op {
+                    Opcode::Add => ins.iadd(left, right),
+                    Opcode::Sub => ins.isub(left, right),
+                    Opcode::Mul => ins.imul(left, right),
+                    Opcode::Or => ins.bor(left, right),
+                    _ => ins.band(left, right),
+                };
+                self.push(value);
+            }
+            Opcode::Div | Opcode::Modulo => {
+                let right = self.pop();
+                let left = self.pop();
+                let value = self.division(op, left, right);
+                self.push(value);
+            }
+            Opcode::Equal | Opcode::NotEqual | Opcode::GreaterThan | Opcode::GreaterEqualThan => {
+                let right = self.pop();
+                let left = self.pop();
+                let value = self.compare(comparison(op as i32).unwrap(), left, right);
+                self.push(value);
+            }
+            Opcode::Minus => {
+                let value = self.pop();
+                let value = self.builder.ins().ineg(value);
+                self.push(value);
+            }
+            Opcode::Bang => {
+                let value = self.pop();
+                let value = self.builder.ins().icmp_imm_s(IntCC::Equal, value, 0);
+                let value = self.builder.ins().uextend(I64, value);
+                self.push(value);
+            }
+            Opcode::Jump | Opcode::JumpWide => {
+                let target = self.blocks[&(operands[0] as usize)];
+                self.builder.ins().jump(target, &[]);
+                return false;
+            }
+            Opcode::AddConstants => {
+                let left = self.constant(operands[0]);
+                let right = self.constant(operands[1]);
+                let value = self.builder.ins().iadd(left, right);
+                self.push(value);
+            }
+            Opcode::JumpNotTruthy
+            | Opcode::JumpNotTruthyWide
+            | Opcode::CompareLocalConstantJump
+            | Opcode::CompareConstantLocalJump => {
+                let condition = match op {
+                    Opcode::CompareLocalConstantJump => {
+                        let local = self.builder.use_var(self.locals[operands[0] as usize]);
+                        let constant = self.constant(operands[1]);
+                        self.compare(comparison(operands[2]).unwrap(), local, constant)
+                    }
+                    Opcode::CompareConstantLocalJump => {
+                        let constant = self.constant(operands[0]);
+                        let local = self.builder.use_var(self.locals[operands[1] as usize]);
+                        self.compare(comparison(operands[2]).unwrap(), constant, local)
+                    }
+                    _ => self.pop(),
+                };
+                let target = self.blocks[&(operands[op.jump_operand().unwrap()] as usize)];
+                // The analysis requires an instruction after a conditional jump
+                let next = self.blocks[&next.unwrap()];
+                self.builder.ins().brif(condition, next, &[], target, &[]);
+                return false;
+            }
+            Opcode::SetLocal => {
+                let value = self.pop();
+                self.builder
+                    .def_var(self.locals[operands[0] as usize], value);
+            }
+            Opcode::GetLocal => {
+                let value = self.builder.use_var(self.locals[operands[0] as usize]);
+                self.push(value);
+            }
+            Opcode::Pop => {
+                self.pop();
+            }
+            Opcode::CurrentClosure | Opcode::Null => {
+                let value = self.builder.ins().iconst(I64, 0);
+                self.push(value);
+            }
+            Opcode::Call => {
+                let num_args = operands[0] as usize;
+                let args: Vec<Value> = (0..num_args).map(|_| self.pop()).collect();
+                self.pop();
+                for (index, arg) in args.into_iter().rev().enumerate() {
+                    self.builder
+                        .ins()
+                        .stack_store(pointer, arg, self.slot, 8 * index as i32);
+                }
+                let args = self.builder.ins().stack_addr(pointer, self.slot, 0);
+                let call = self.builder.ins().call(self.this, &[context, args]);
+                let value = self.builder.inst_results(call)[0];
+                let failed =
+                    self.builder
+                        .ins()
+                        .load(I64, MemFlagsData::trusted(), context, FAILED_OFFSET);
+                let next = self.builder.create_block();
+                self.builder.ins().brif(failed, self.abort, &[], next, &[]);
+                self.builder.switch_to_block(next);
+                self.push(value);
+            }
+            Opcode::ReturnValue => {
+                let value = self.pop();
+                let flags = MemFlagsData::trusted();
+                let depth = self.builder.ins().load(I64, flags, context, DEPTH_OFFSET);
+                let depth = self.builder.ins().iadd_imm_s(depth, -1);
+                self.builder
+                    .ins()
+                    .store(flags, depth, context, DEPTH_OFFSET);
+                self.builder.ins().return_(&[value]);
+                return false;
+            }
+            // The analysis only accepts the instructions above
+            _ => unreachable!("{op} cannot be compiled"),
+        }
+        true
+    }
+
+    /// Divides like the VM, failing on a division by zero.
+    fn division(&mut self, op: Opcode, left: Value, right: Value) -> Value {
+        let by_zero = self.builder.ins().icmp_imm_s(IntCC::Equal, right, 0);
+        let next = self.builder.create_block();
+        self.builder.ins().brif(by_zero, self.fail, &[], next, &[]);
+        self.builder.switch_to_block(next);
+
+        // The division of the smallest integer by -1 overflows, and traps
+        let by_minus_one = self.builder.ins().icmp_imm_s(IntCC::Equal, right, -1);
+        let one = self.builder.ins().iconst(I64, 1);
+        let divisor = self.builder.ins().select(by_minus_one, one, right);
+        let (value, by_minus_one_value) = if op == Opcode::Div {
+            let quotient = self.builder.ins().sdiv(left, divisor);
+            (quotient, self.builder.ins().ineg(left))
+        } else {
+            let remainder = self.builder.ins().srem(left, divisor);
+            (remainder, self.builder.ins().iconst(I64, 0))
+        };
+        self.builder
+            .ins()
+            .select(by_minus_one, by_minus_one_value, value)
+    }
+
+    fn compare(&mut self, condition: IntCC, left: Value, right: Value) -> Value {
+        let value = self.builder.ins().icmp(condition, left, right);
+        self.builder.ins().uextend(I64, value)
+    }
+
+    fn constant(&mut self, index: i32) -> Value {
+        let Object::INTEGER(value) = self.constants[index as usize] else {
+            unreachable!("the analysis only accepts integer constants");
+        };
+        self.builder.ins().iconst(I64, value)
+    }
+
+    fn push(&mut self, value: Value) {
+        if self.sp == self.stack.len() {
+            let variable = self.builder.declare_var(I64);
+            self.stack.push(variable);
+        }
+        self.builder.def_var(self.stack[self.sp], value);
+        self.sp += 1;
+    }
+
+    fn pop(&mut self) -> Value {
+        self.sp -= 1;
+        self.builder.use_var(self.stack[self.sp])
+    }
+}
@@ -1,26 +1,68 @@
+pub mod coverage;
+pub mod error;
 mod function_tests;
+#[cfg(feature = "jit")]
+pub mod jit;
+pub mod register;
 mod shadowing_tests;
+pub mod stats;
 mod test_utils;
+pub mod value;
 mod vm_tests;
 mod while_statemets_tests;
 
+#[cfg(feature = "hooks")]
+use crate::hooks::Hooks;
 use crate::{
     compiler::{
-        code::{read_u16, Opcode},
-        Bytecode,
+        code::{read_u16, read_u32, Instructions, Opcode},
+        string_constants::StringConstants,
+        symbol_table::{SymbolScope, SymbolTable},
+        Bytecode, Compiler,
     },
+    hooks::hook,
     object::{
-        builtins::BuiltinFunction,
-        {Closure, CompiledFunction, Object, FALSE, NULL, TRUE},
+        builtins::{BuiltinContext, BuiltinFunction},
+        cache,
+        deadline::Deadline,
+        error::{ErrorKind, ErrorObject},
+        interrupt::Interrupt,
+        random::Random,
+        style::OutputStyle,
+        {Closure, CompiledFunction, Object, StackFrame, DEFAULT_MAX_DEPTH},
+    },
+    parser::ast::Program,
+    source::{Position, Source, SourceMap},
+    strict::{condition_error, StrictConfig},
+    vm::{
+        coverage::Coverage,
+        error::VmError,
+        stats::{count_values, Stats, SAMPLE_INTERVAL},
+        value::Value,
     },
 };
 use num_traits::FromPrimitive;
-use std::{collections::HashMap, rc::Rc};
+use std::{
+    collections::HashMap,
+    io::{BufRead, Write},
+    ptr,
+    rc::Rc,
+    time::Duration,
+};
 
-const STACK_SIZE: usize = 2048;
-const MAX_FRAMES: usize = 1024;
+/// Number of slots the stack can grow to, unless it is set with
+/// [`VM::set_max_stack_size`].
+pub const DEFAULT_MAX_STACK_SIZE: usize = 1 << 20;
+/// Number of globals of the VM by default, as well as the most a program can
+/// define, since their indexes are two bytes operands.
 pub const GLOBALS_SIZE: usize = 65536;
 
+/// Returns a global store of `size` globals, all `null`, to give to
+/// [`VM::new_with_global_store`].
+pub fn new_global_store(size: usize) -> Vec<Value> {
+    vec![Value::Null; size]
+}
+
 #[derive(Debug)]
 struct Frame {
     function: Closure,
@@ -43,279 +85,861 @@ impl Frame {
 }
 
 pub struct VM {
-    constants: Vec<Rc<Object>>,
-
-    stack: Vec<Rc<Object>>,
+    /// Constants of the program, shared with its bytecode.
+    constants: Rc<Vec<Object>>,
+    /// Constants stored on the heap, allocated the first time they are loaded
+    /// and shared by the later loads.
+    loaded_constants: Vec<Option<Rc<Object>>>,
+    /// Index of the string constants, for the compilers of the next programs.
+    string_constants: Rc<StringConstants>,
+
+    /// Grows as values are pushed, up to `max_stack_size` slots.
+    stack: Vec<Value>,
     sp: usize, // stack pointer. Always point to the next value. Top of the stack is stack[sp -1]
+    max_stack_size: usize,
 
-    pub globals: Vec<Rc<Object>>,
+    pub globals: Vec<Value>,
+
+    /// Global symbols of the program, extended by the definitions of `eval`.
+    symbol_table: SymbolTable,
 
     frames: Vec<Frame>,
     frames_index: usize,
+
+    /// Number of nested calls, the main frame excluded.
+    max_depth: usize,
+
+    /// Number of instructions left to run, unlimited if it is `None`.
+    fuel: Option<u64>,
+
+    deadline: Option<Deadline>,
+
+    interrupt: Option<Interrupt>,
+
+    /// Writer every executed instruction is printed to, if tracing is on.
+    trace: Option<Box<dyn Write>>,
+
+    /// Memory statistics, if they are collected.
+    stats: Option<Stats>,
+    /// Instructions run since the objects were last counted.
+    stats_steps: u32,
+
+    /// Statements run, if they are collected.
+    coverage: Option<Coverage>,
+
+    /// Error raised while running a function called from a builtin, it is
+    /// returned once the builtin is done.
+    builtin_error: Option<VmError>,
+
+    /// Code given to the `exit` builtin, the execution stops once it is set.
+    exit_code: Option<i64>,
+
+    source: Option<Source>,
+
+    output_style: OutputStyle,
+
+    /// Reader used by the input builtins, the standard input if it is `None`.
+    input: Option<Box<dyn BufRead>>,
+
+    random: Random,
+
+    /// Arguments of the program, returned by `argv`.
+    args: Vec<String>,
+
+    strict: StrictConfig,
+
+    #[cfg(feature = "hooks")]
+    hooks: Option<Box<dyn Hooks>>,
+
+    /// Compiler of the hot functions to native code, if it is enabled.
+    #[cfg(feature = "jit")]
+    jit: Option<jit::Jit>,
 }
 
 impl VM {
     pub fn new(bytecode: Bytecode) -> Self {
-        let main_function = CompiledFunction {
-            instructions: bytecode.instructions.data,
-            num_locals: 0,
-            num_parameters: 0,
-        };
-        let main_closure = Closure::new(main_function);
-        let main_frame = Frame::new(main_closure, 0);
-        let mut frames = Vec::with_capacity(MAX_FRAMES);
+        let main_frame = Frame::new(main_closure(bytecode.instructions, bytecode.source_map), 0);
+        let mut frames = Vec::with_capacity(DEFAULT_MAX_DEPTH);
         frames.push(main_frame);
         Self {
-            constants: bytecode.constants.into_iter().map(Rc::new).collect(),
+            constants: bytecode.constants,
+            loaded_constants: Vec::new(),
+            string_constants: bytecode.string_constants,
 
+            stack: Vec::new(),
             sp: 0,
+            max_stack_size: DEFAULT_MAX_STACK_SIZE,
 
-            // TODO: Improve this
-            stack: {
-                let mut v = Vec::with_capacity(STACK_SIZE);
-                (0..STACK_SIZE).for_each(|_| v.push(Rc::new(NULL)));
-                v
-            },
+            globals: new_global_store(GLOBALS_SIZE),
 
-            globals: {
-                let mut v = Vec::with_capacity(GLOBALS_SIZE);
-                (0..GLOBALS_SIZE).for_each(|_| v.push(Rc::new(NULL)));
-                v
-            },
+            symbol_table: bytecode.symbol_table,
 
             frames,
             frames_index: 1,
+            max_depth: DEFAULT_MAX_DEPTH,
+            fuel: None,
+            deadline: None,
+            interrupt: None,
+            trace: None,
+            stats: None,
+            stats_steps: 0,
+            coverage: None,
+            builtin_error: None,
+            exit_code: None,
+
+            source: bytecode.source,
+
+            output_style: OutputStyle::default(),
+            input: None,
+            random: Random::default(),
+            args: Vec::new(),
+            strict: StrictConfig::default(),
+
+            #[cfg(feature = "hooks")]
+            hooks: None,
+
+            #[cfg(feature = "jit")]
+            jit: Some(jit::Jit::default()),
+        }
+    }
+
+    pub fn set_output_style(&mut self, output_style: OutputStyle) {
+        self.output_style = output_style;
+    }
+
+    pub fn output_style(&self) -> &OutputStyle {
+        &self.output_style
+    }
+
+    /// Makes `input` and `read_all` read from `input` instead of the standard
+    /// input.
+    pub fn set_input(&mut self, input: Box<dyn BufRead>) {
+        self.input = Some(input);
+    }
+
+    /// Sets the number of slots the stack can grow to, a program needing more
+    /// fails with a stack overflow.
+    pub fn set_max_stack_size(&mut self, max_stack_size: usize) {
+        self.max_stack_size = max_stack_size;
+    }
+
+    /// Sets the number of nested calls a program can make, a deeper call fails
+    /// with an error naming the function.
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    /// Limits the number of instructions the program can run, including those
+    /// of `eval`. The program fails once they are all used, so that untrusted
+    /// programs always terminate.
+    pub fn set_fuel(&mut self, fuel: u64) {
+        self.fuel = Some(fuel);
+    }
+
+    /// Returns the number of instructions left to run, if it is limited.
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    /// Removes the limit on the number of instructions, returning the ones
+    /// which were left.
+    pub fn take_fuel(&mut self) -> Option<u64> {
+        self.fuel.take()
+    }
+
+    /// Makes the program fail if it is still running `timeout` from now.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.deadline = Some(Deadline::after(timeout));
+    }
+
+    /// Makes the program fail once `interrupt` is set, until it is cleared.
+    pub fn set_interrupt(&mut self, interrupt: Interrupt) {
+        self.interrupt = Some(interrupt);
+    }
+
+    /// Prints every instruction to `trace` before executing it, with the
+    /// function it belongs to and the top of the stack.
+    pub fn set_trace(&mut self, trace: Box<dyn Write>) {
+        self.trace = Some(trace);
+    }
+
+    /// Collects memory statistics while the program runs, returned by
+    /// [`VM::stats`].
+    pub fn collect_stats(&mut self) {
+        self.stats = Some(Stats::default());
+    }
+
+    /// Returns the memory statistics of the program, if they are collected.
+    pub fn stats(&self) -> Option<Stats> {
+        let stats = self.stats.as_ref()?;
+        Some(Stats {
+            peak_objects: stats.peak_objects.max(self.count_objects()),
+            constants: self.constants.len(),
+            globals: self.symbol_table.num_definitions,
+            // The stack never shrinks
+            max_stack_size: self.stack.len(),
+            max_depth: stats.max_depth,
+        })
+    }
+
+    /// Returns the number of values alive in the stack and the globals.
+    fn count_objects(&self) -> usize {
+        let globals = self.symbol_table.num_definitions.min(self.globals.len());
+        count_values(self.stack().iter().chain(&self.globals[..globals]))
+    }
+
+    fn sample_objects(&mut self) {
+        self.stats_steps += 1;
+        if self.stats_steps < SAMPLE_INTERVAL {
+            return;
+        }
+        self.stats_steps = 0;
+        let objects = self.count_objects();
+        if let Some(stats) = &mut self.stats {
+            stats.peak_objects = stats.peak_objects.max(objects);
+        }
+    }
+
+    /// Counts the statements of the program as they run, returned by
+    /// [`VM::coverage`]. The functions are run by the VM, not as native code.
+    pub fn collect_coverage(&mut self) {
+        let mut coverage = Coverage::default();
+        let main = &self.frames[0].function.function.source_map;
+        coverage
+            .statements
+            .extend(main.entries().iter().map(|(_, position)| *position));
+        for constant in self.constants.iter() {
+            if let Object::COMPILEDFUNCTION(function) = constant {
+                let positions = function.source_map.entries().iter().map(|(_, p)| *p);
+                coverage.statements.extend(positions);
+                if let Some(&(_, position)) = function.source_map.entries().first() {
+                    let name = function.name.as_deref().unwrap_or("<anonymous>");
+                    coverage.functions.push((name.to_string(), position));
+                }
+            }
+        }
+        // A statement can have several entries, such as a conditional and
+        // the instructions after its blocks
+        coverage
+            .statements
+            .sort_unstable_by_key(|p| (p.line, p.column));
+        coverage.statements.dedup();
+        coverage.functions.sort_by_key(|(_, p)| (p.line, p.column));
+        self.coverage = Some(coverage);
+    }
+
+    /// Returns the statements the program ran, if they are collected.
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Counts the statement starting at the next instruction, if any. Only
+    /// the first entry of a statement counts, not the ones the blocks of a
+    /// conditional go back to.
+    fn count_statement(&mut self) {
+        let frame = &self.frames[self.frames_index - 1];
+        let ip = (frame.ip + 1) as usize;
+        let source_map = &frame.function.function.source_map;
+        let Some(position) = source_map.statement_at(ip) else {
+            return;
+        };
+        let first = source_map.entries().iter().find(|(_, p)| *p == position);
+        if first.is_some_and(|(start, _)| *start != ip) {
+            return;
+        }
+        if let Some(coverage) = &mut self.coverage {
+            *coverage.hits.entry(position).or_default() += 1;
+        }
+    }
+
+    /// Enables or disables the compilation of the hot functions to native code,
+    /// enabled by default.
+    #[cfg(feature = "jit")]
+    pub fn set_jit(&mut self, enabled: bool) {
+        self.jit = enabled.then(jit::Jit::default);
+    }
+
+    /// Sets the arguments returned by `argv`.
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.args = args;
+    }
+
+    /// Sets the checks of the strict mode. The VM checks that the conditions
+    /// are booleans, the compiler checks the rest.
+    pub fn set_strict(&mut self, strict: StrictConfig) {
+        self.strict = strict;
+    }
+
+    /// Returns whether the condition of an `if` or a `while` holds, failing if
+    /// it is not a boolean in strict mode.
+    fn condition_holds(&self, condition: &Value) -> Result<bool, VmError> {
+        match condition {
+            Value::Boolean(b) => Ok(*b),
+            _ if self.strict.boolean_conditions => Err(VmError::TypeMismatch(condition_error(
+                &condition.to_object().get_type(),
+            ))),
+            _ => Ok(condition.is_truthy()),
         }
     }
 
-    pub fn new_with_global_store(bytecode: Bytecode, globals: Vec<Rc<Object>>) -> Self {
+    /// Returns the global symbols, including those defined by `eval`.
+    pub fn symbol_table(&self) -> &SymbolTable {
+        &self.symbol_table
+    }
+
+    /// Returns the code the program exited with, if it called `exit`.
+    pub fn exit_code(&self) -> Option<i64> {
+        self.exit_code
+    }
+
+    /// Returns the source of the running program, if it is known.
+    pub fn source(&self) -> Option<&Source> {
+        self.source.as_ref()
+    }
+
+    #[cfg(feature = "hooks")]
+    pub fn set_hooks(&mut self, hooks: Box<dyn Hooks>) {
+        self.hooks = Some(hooks);
+    }
+
+    #[cfg(feature = "hooks")]
+    pub fn take_hooks(&mut self) -> Option<Box<dyn Hooks>> {
+        self.hooks.take()
+    }
+
+    /// Creates a VM using `globals` as its global store, from a previous VM or
+    /// from [`new_global_store`]. The program must have been compiled with at
+    /// most as many globals, see [`Compiler::set_max_globals`].
+    pub fn new_with_global_store(bytecode: Bytecode, globals: Vec<Value>) -> Self {
         let mut vm = Self::new(bytecode);
         vm.globals = globals;
         vm
     }
 
-    #[allow(clippy::too_many_lines)]
-    pub fn run(&mut self) -> Result<(), String> {
-        while self.current_frame().ip < self.current_frame().get_instructions().len() as i32 - 1 {
-            self.current_frame().ip += 1;
-            let ip = self.current_frame().ip as usize;
-            let ins = self.current_frame().get_instructions();
-            let op = Opcode::from_u8(ins[ip]).ok_or(format!("Unknown opcode {}", ins[ip]))?;
-            match op {
-                Opcode::Constant => {
-                    let const_index = read_u16(&ins[ip + 1..]);
-                    self.current_frame().ip += 2;
-                    self.push(self.constants[const_index as usize].clone())?;
-                }
-                Opcode::Add
-                | Opcode::Sub
-                | Opcode::Mul
-                | Opcode::Div
-                | Opcode::Modulo
-                | Opcode::Or
-                | Opcode::And => {
-                    self.execute_binary_operation(op)?;
-                }
-                Opcode::Equal
-                | Opcode::NotEqual
-                | Opcode::GreaterThan
-                | Opcode::GreaterEqualThan => {
-                    self.execute_comparison(op)?;
-                }
-                Opcode::Pop => {
-                    self.pop()?;
-                }
-                Opcode::True => {
-                    self.push(Rc::new(TRUE))?;
-                }
-                Opcode::False => {
-                    self.push(Rc::new(FALSE))?;
-                }
-                Opcode::Bang => {
-                    self.execute_bang_operation()?;
+    /// Returns a compiler of a new program using the symbols and constants of
+    /// the VM, which are moved to it: the VM must not run until the program
+    /// is [loaded](VM::load).
+    pub fn new_compiler(&mut self) -> Compiler {
+        let mut compiler = Compiler::new_with_indexed_state(
+            std::mem::take(&mut self.symbol_table),
+            std::mem::take(&mut self.constants),
+            std::mem::take(&mut self.string_constants),
+        );
+        compiler.set_max_globals(self.globals.len());
+        compiler
+    }
+
+    /// Replaces the program run by the VM with `bytecode`, compiled by
+    /// [`VM::new_compiler`], keeping the globals and the settings of the VM.
+    /// The REPL runs each line this way.
+    pub fn load(&mut self, bytecode: Bytecode) {
+        self.constants = bytecode.constants;
+        self.string_constants = bytecode.string_constants;
+        self.symbol_table = bytecode.symbol_table;
+        self.source = bytecode.source;
+        self.frames.truncate(1);
+        self.frames[0] = Frame::new(main_closure(bytecode.instructions, bytecode.source_map), 0);
+        self.frames_index = 1;
+        self.stack.clear();
+        self.sp = 0;
+        self.builtin_error = None;
+        self.exit_code = None;
+    }
+
+    pub fn run(&mut self) -> Result<(), VmError> {
+        let result = self.execute();
+        #[cfg(feature = "hooks")]
+        if let Err(error) = &result {
+            hook!(self.on_error(&error.to_string()));
+        }
+        result
+    }
+
+    fn execute(&mut self) -> Result<(), VmError> {
+        self.execute_until(0)
+    }
+
+    /// Executes instructions until the main program ends, or until the number of
+    /// active frames drops to `depth` (used to run a function called from a builtin).
+    fn execute_until(&mut self, depth: usize) -> Result<(), VmError> {
+        while self.frames_index > depth && !self.is_finished() {
+            if let Some(fuel) = &mut self.fuel {
+                if *fuel == 0 {
+                    return Err(VmError::FuelExhausted);
                 }
-                Opcode::Minus => {
-                    self.execute_minus_operation()?;
+                *fuel -= 1;
+            }
+            if let Some(deadline) = &mut self.deadline {
+                deadline.check().map_err(VmError::Timeout)?;
+            }
+            if self
+                .interrupt
+                .as_ref()
+                .is_some_and(Interrupt::is_interrupted)
+            {
+                return Err(VmError::Interrupted);
+            }
+            if self.stats.is_some() {
+                self.sample_objects();
+            }
+            if self.coverage.is_some() {
+                self.count_statement();
+            }
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    /// Prints the next instruction to the trace, indented by the depth of the
+    /// call it runs in.
+    fn trace_instruction(&mut self) {
+        let Some((ip, op, operands)) = self.next_instruction() else {
+            return;
+        };
+        let instruction =
+            Instructions::default().format_instruction(op, &op.lookup_widths(), &operands);
+        let top = match self.stack().last() {
+            Some(top) => describe_value(top),
+            None => "<empty>".to_string(),
+        };
+        let line = format!(
+            "{:indent$}{} {ip:04} {instruction} | top: {top}",
+            "",
+            self.function_name(),
+            indent = 2 * self.depth()
+        );
+        if let Some(trace) = self.trace.as_mut() {
+            // The trace is a debugging aid, failing to write it does not stop the program
+            let _ = writeln!(trace, "{line}");
+        }
+    }
+
+    fn has_next_instruction(&self) -> bool {
+        let frame = &self.frames[self.frames_index - 1];
+        frame.ip < frame.get_instructions().len() as i32 - 1
+    }
+
+    /// Returns true once the program ran to its end or exited.
+    pub fn is_finished(&self) -> bool {
+        self.exit_code.is_some() || !self.has_next_instruction()
+    }
+
+    /// Returns the position, the opcode and the operands of the instruction
+    /// that runs next, in the current function. Returns `None` once the
+    /// program is finished.
+    pub fn next_instruction(&self) -> Option<(usize, Opcode, Vec<i32>)> {
+        if self.is_finished() {
+            return None;
+        }
+        let frame = &self.frames[self.frames_index - 1];
+        let ip = (frame.ip + 1) as usize;
+        let instructions = frame.get_instructions();
+        let op = Opcode::from_u8(instructions[ip])?;
+        let (operands, _) = Opcode::read_operands(&op.lookup_widths(), &instructions[ip + 1..]);
+        Some((ip, op, operands))
+    }
+
+    /// Returns the name of the running function, `main` for the main program.
+    pub fn function_name(&self) -> &str {
+        self.frame_name(self.frames_index - 1)
+    }
+
+    fn frame_name(&self, index: usize) -> &str {
+        match (index, &self.frames[index].function.function.name) {
+            (0, _) => "main",
+            (_, Some(name)) => name,
+            (_, None) => "<anonymous>",
+        }
+    }
+
+    /// Returns the function calls running, from the main program to the
+    /// running function, with the statement each of them is at. After an
+    /// error, the last one is where it happened.
+    pub fn stack_trace(&self) -> Vec<StackFrame> {
+        self.frames[..self.frames_index]
+            .iter()
+            .enumerate()
+            .map(|(index, frame)| StackFrame {
+                function: self.frame_name(index).to_string(),
+                position: frame
+                    .function
+                    .function
+                    .source_map
+                    .position(frame.ip.max(0) as usize),
+            })
+            .collect()
+    }
+
+    /// Returns the debug symbols of the running function.
+    pub fn source_map(&self) -> &SourceMap {
+        &self.frames[self.frames_index - 1]
+            .function
+            .function
+            .source_map
+    }
+
+    /// Returns the position in the source of the instruction which ran last,
+    /// the one which failed if the program stopped with an error. Returns
+    /// `None` without debug symbols.
+    pub fn position(&self) -> Option<Position> {
+        let ip = self.frames[self.frames_index - 1].ip.max(0);
+        self.source_map().position(ip as usize)
+    }
+
+    /// Returns the number of function calls running, 0 in the main program.
+    pub fn depth(&self) -> usize {
+        self.frames_index - 1
+    }
+
+    /// Returns the values on the stack, from the bottom to the top.
+    pub fn stack(&self) -> &[Value] {
+        &self.stack[..self.sp]
+    }
+
+    /// Returns the locals of the running function, starting with its
+    /// parameters. The main program has none, its variables are globals.
+    pub fn locals(&self) -> &[Value] {
+        if self.depth() == 0 {
+            return &[];
+        }
+        let frame = &self.frames[self.frames_index - 1];
+        let base_pointer = frame.base_pointer;
+        &self.stack[base_pointer..base_pointer + frame.function.function.num_locals]
+    }
+
+    pub fn constants(&self) -> &[Object] {
+        &self.constants
+    }
+
+    fn load_constant(&mut self, index: usize) -> Value {
+        match &self.constants[index] {
+            Object::INTEGER(i) => Value::Integer(*i),
+            Object::BOOLEAN(b) => Value::Boolean(*b),
+            Object::NULL => Value::Null,
+            constant => {
+                if index >= self.loaded_constants.len() {
+                    self.loaded_constants.resize(index + 1, None);
                 }
-                Opcode::Jump => {
-                    let pos = i32::from(read_u16(&ins[ip + 1..]));
+                let loaded =
+                    self.loaded_constants[index].get_or_insert_with(|| Rc::new(constant.clone()));
+                Value::Object(Rc::clone(loaded))
+            }
+        }
+    }
+
+    /// Executes the next instruction of the current frame. It must not be
+    /// called once the program [is finished].
+    ///
+    /// Unlike [`run`], it does not check the fuel nor the timeout.
+    ///
+    /// [is finished]: VM::is_finished
+    /// [`run`]: VM::run
+    ///
+    /// # Errors
+    ///
+    /// If the instruction fails, such as a type mismatch or a call with the
+    /// wrong number of arguments.
+    #[allow(clippy::too_many_lines)]
+    pub fn step(&mut self) -> Result<(), VmError> {
+        if self.trace.is_some() {
+            self.trace_instruction();
+        }
+        self.current_frame().ip += 1;
+        let ip = self.current_frame().ip as usize;
+        let byte = self.current_frame().get_instructions()[ip];
+        let op = Opcode::from_u8(byte)
+            .ok_or_else(|| VmError::Internal(format!("Unknown opcode {byte}")))?;
+        hook!(self.on_instruction(ip, op));
+        let ins = self.current_frame().get_instructions();
+        match op {
+            Opcode::Constant => {
+                let const_index = read_u16(&ins[ip + 1..]);
+                self.current_frame().ip += 2;
+                let constant = self.load_constant(const_index as usize);
+                self.push(constant)?;
+            }
+            Opcode::Add
+            | Opcode::Sub
+            | Opcode::Mul
+            | Opcode::Div
+            | Opcode::Modulo
+            | Opcode::Or
+            | Opcode::And => {
+                self.execute_binary_operation(op)?;
+            }
+            Opcode::Equal | Opcode::NotEqual | Opcode::GreaterThan | Opcode::GreaterEqualThan => {
+                self.execute_comparison(op)?;
+            }
+            Opcode::Pop => {
+                self.pop()?;
+            }
+            Opcode::True => {
+                self.push(Value::Boolean(true))?;
+            }
+            Opcode::False => {
+                self.push(Value::Boolean(false))?;
+            }
+            Opcode::Bang => {
+                self.execute_bang_operation()?;
+            }
+            Opcode::Minus => {
+                self.execute_minus_operation()?;
+            }
+            Opcode::Jump => {
+                let pos = i32::from(read_u16(&ins[ip + 1..]));
+                self.current_frame().ip = pos - 1;
+            }
+            Opcode::JumpNotTruthy => {
+                let pos = i32::from(read_u16(&ins[ip + 1..]));
+                self.current_frame().ip += 2;
+                let condition = self.pop()?;
+                if !self.condition_holds(&condition)? {
                     self.current_frame().ip = pos - 1;
                 }
-                Opcode::JumpNotTruthy => {
-                    let pos = i32::from(read_u16(&ins[ip + 1..]));
-                    self.current_frame().ip += 2;
-                    let condition = self.pop()?;
-                    if !self.is_truthy(&condition) {
-                        self.current_frame().ip = pos - 1;
+            }
+            Opcode::JumpTable => {
+                let min = i64::from(read_u16(&ins[ip + 1..]));
+                let size = read_u16(&ins[ip + 3..]) as usize;
+                // The jumps of the table all have the same width
+                let first = ip + 5;
+                let jump = Opcode::from_u8(ins[first])
+                    .ok_or_else(|| VmError::Internal("Invalid jump table".to_string()))?;
+                let width = 1 + jump.lookup_widths()[0] as usize;
+                let entry = match self.pop()? {
+                    Value::Integer(i) if i >= min && i - min < size as i64 => (i - min) as usize,
+                    Value::Integer(_) => size,
+                    // Like the comparisons it replaces
+                    _ => {
+                        return Err(VmError::TypeMismatch(
+                            "Unsupported types for comparison".to_string(),
+                        ))
                     }
+                };
+                self.current_frame().ip = (first + entry * width) as i32 - 1;
+            }
+            Opcode::AddConstants => {
+                let left = read_u16(&ins[ip + 1..]) as usize;
+                let right = read_u16(&ins[ip + 3..]) as usize;
+                self.current_frame().ip += 4;
+                let (left, right) = (self.load_constant(left), self.load_constant(right));
+                self.push(left)?;
+                self.push(right)?;
+                self.execute_binary_operation(Opcode::Add)?;
+            }
+            Opcode::CompareLocalConstantJump | Opcode::CompareConstantLocalJump => {
+                let (local, constant) = match op {
+                    Opcode::CompareLocalConstantJump => (ins[ip + 1], read_u16(&ins[ip + 2..])),
+                    _ => (ins[ip + 3], read_u16(&ins[ip + 1..])),
+                };
+                let comparison = ins[ip + 4];
+                let pos = i32::from(read_u16(&ins[ip + 5..]));
+                self.current_frame().ip += 6;
+                let base_pointer = self.current_frame().base_pointer;
+                let local = self.stack[base_pointer + local as usize].clone();
+                let constant = self.load_constant(constant as usize);
+                let holds = match op {
+                    Opcode::CompareLocalConstantJump => {
+                        self.compare(local, constant, comparison)?
+                    }
+                    _ => self.compare(constant, local, comparison)?,
+                };
+                if !holds {
+                    self.current_frame().ip = pos - 1;
                 }
-                Opcode::Null => {
-                    self.push(Rc::new(NULL))?;
-                }
-                Opcode::SetGlobal => {
-                    let global_index = read_u16(&ins[ip + 1..]) as usize;
-                    self.current_frame().ip += 2;
-                    let value = self.pop()?;
-                    self.globals[global_index] = value;
-                }
-
-                Opcode::GetGlobal => {
-                    let global_index = read_u16(&ins[ip + 1..]) as usize;
-                    self.current_frame().ip += 2;
-                    self.push(self.globals[global_index].clone())?;
-                }
-                Opcode::SetLocal => {
-                    let local_index = ins[ip + 1] as usize;
-                    self.current_frame().ip += 1;
-                    let value = self.pop()?;
-                    let base_pointer = self.current_frame().base_pointer;
-                    self.stack[base_pointer + local_index] = value;
-                }
-                Opcode::GetLocal => {
-                    let local_index = ins[ip + 1] as usize;
-                    self.current_frame().ip += 1;
-                    let base_pointer = self.current_frame().base_pointer;
-                    let value = Rc::clone(&self.stack[base_pointer + local_index]);
-                    self.push(value)?;
+            }
+            Opcode::JumpWide => {
+                let pos = read_u32(&ins[ip + 1..]) as i32;
+                self.current_frame().ip = pos - 1;
+            }
+            Opcode::JumpNotTruthyWide => {
+                let pos = read_u32(&ins[ip + 1..]) as i32;
+                self.current_frame().ip += 4;
+                let condition = self.pop()?;
+                if !self.condition_holds(&condition)? {
+                    self.current_frame().ip = pos - 1;
                 }
+            }
+            Opcode::Null => {
+                self.push(Value::Null)?;
+            }
+            Opcode::SetGlobal => {
+                let global_index = read_u16(&ins[ip + 1..]) as usize;
+                self.current_frame().ip += 2;
+                let value = self.pop()?;
+                *self.global(global_index)? = value;
+            }
 
-                Opcode::GetBuiltin => {
-                    let builtin_index = ins[ip + 1] as usize;
-                    self.current_frame().ip += 1;
+            Opcode::GetGlobal => {
+                let global_index = read_u16(&ins[ip + 1..]) as usize;
+                self.current_frame().ip += 2;
+                let value = self.global(global_index)?.clone();
+                self.push(value)?;
+            }
+            Opcode::GetGlobalByName => {
+                let constant = read_u16(&ins[ip + 1..]) as usize;
+                self.current_frame().ip += 2;
+                let value = self.global_by_name(constant)?.clone();
+                self.push(value)?;
+            }
+            Opcode::SetLocal => {
+                let local_index = ins[ip + 1] as usize;
+                self.current_frame().ip += 1;
+                let value = self.pop()?;
+                let base_pointer = self.current_frame().base_pointer;
+                self.stack[base_pointer + local_index] = value;
+            }
+            Opcode::SetIndex => {
+                let depth = ins[ip + 1] as usize;
+                self.current_frame().ip += 1;
+                self.execute_set_index(depth)?;
+            }
+            Opcode::GetLocal => {
+                let local_index = ins[ip + 1] as usize;
+                self.current_frame().ip += 1;
+                let base_pointer = self.current_frame().base_pointer;
+                let value = self.stack[base_pointer + local_index].clone();
+                self.push(value)?;
+            }
 
-                    let def = BuiltinFunction::get_builtin_by_id(builtin_index)
-                        .ok_or(format!("Unknown builtin function id {builtin_index}"))?;
+            Opcode::GetBuiltin => {
+                let builtin_index = ins[ip + 1] as usize;
+                self.current_frame().ip += 1;
 
-                    self.push(Rc::new(def))?;
-                }
-                Opcode::Array => {
-                    let num_elements = read_u16(&ins[ip + 1..]) as usize;
-                    self.current_frame().ip += 2;
-                    let array = self.build_array(self.sp - num_elements, self.sp)?;
-                    self.sp -= num_elements;
-                    self.push(array)?;
-                }
-                Opcode::HashMap => {
-                    let num_elements = read_u16(&ins[ip + 1..]) as usize;
-                    self.current_frame().ip += 2;
-                    let hashmap = self.build_hashmap(self.sp - num_elements, self.sp)?;
-                    self.sp -= num_elements;
-                    self.push(hashmap)?;
-                }
-                Opcode::Index => {
-                    let index = self.pop()?;
-                    let left = self.pop()?;
-                    self.execute_index_expression(&left, &index)?;
-                }
-                Opcode::Call => {
-                    let num_args = ins[ip + 1] as usize;
-                    self.current_frame().ip += 1;
+                let def = BuiltinFunction::get_builtin_by_id(builtin_index).ok_or_else(|| {
+                    VmError::Internal(format!("Unknown builtin function id {builtin_index}"))
+                })?;
 
-                    self.execute_call(num_args)?;
-                }
-                Opcode::ReturnValue => {
-                    let return_value = self.pop()?;
+                self.push(Value::Object(Rc::new(def)))?;
+            }
+            Opcode::Array => {
+                let num_elements = read_u16(&ins[ip + 1..]) as usize;
+                self.current_frame().ip += 2;
+                let array = self.build_array(self.sp - num_elements, self.sp)?;
+                self.sp -= num_elements;
+                self.push(array)?;
+            }
+            Opcode::HashMap => {
+                let num_elements = read_u16(&ins[ip + 1..]) as usize;
+                self.current_frame().ip += 2;
+                let hashmap = self.build_hashmap(self.sp - num_elements, self.sp)?;
+                self.sp -= num_elements;
+                self.push(hashmap)?;
+            }
+            Opcode::Index => {
+                let index = self.pop()?;
+                let left = self.pop()?;
+                self.execute_index_expression(&left, &index)?;
+            }
+            Opcode::Call => {
+                let num_args = ins[ip + 1] as usize;
+                self.current_frame().ip += 1;
 
-                    match self.pop_frame() {
-                        Some(frame) => self.sp = frame.base_pointer - 1,
-                        None => Err("There was no frame")?,
-                    }
+                self.execute_call(num_args)?;
+            }
+            Opcode::ReturnValue => {
+                let return_value = self.pop()?;
+                hook!(self.on_return(&return_value.to_object()));
 
-                    self.push(return_value)?;
+                match self.pop_frame() {
+                    Some(frame) => self.sp = frame.base_pointer - 1,
+                    None => Err(VmError::Internal("There was no frame".to_string()))?,
                 }
-                Opcode::Return => {
-                    match self.pop_frame() {
-                        Some(frame) => self.sp = frame.base_pointer - 1,
-                        None => Err("There was no frame")?,
-                    }
 
-                    self.push(Rc::new(NULL))?;
+                self.push(return_value)?;
+            }
+            Opcode::Return => {
+                hook!(self.on_return(&Object::NULL));
+                match self.pop_frame() {
+                    Some(frame) => self.sp = frame.base_pointer - 1,
+                    None => Err(VmError::Internal("There was no frame".to_string()))?,
                 }
-                Opcode::Closure => {
-                    let const_index = read_u16(&ins[ip + 1..]) as usize;
-                    let num_free = ins[ip + 3] as usize;
 
-                    self.current_frame().ip += 3;
+                self.push(Value::Null)?;
+            }
+            Opcode::Closure => {
+                let const_index = read_u16(&ins[ip + 1..]) as usize;
+                let num_free = ins[ip + 3] as usize;
 
-                    self.push_closure(const_index, num_free)?;
-                }
-                Opcode::GetFree => {
-                    let free_index = ins[ip + 1] as usize;
-                    self.current_frame().ip += 1;
+                self.current_frame().ip += 3;
 
-                    let free = self.current_frame().function.free[free_index].clone();
-                    self.push(Rc::new(free))?;
-                }
-                Opcode::CurrentClosure => {
-                    let current_closure = self.current_frame().function.clone();
-                    self.push(Rc::new(Object::CLOSURE(current_closure)))?;
-                }
+                self.push_closure(const_index, num_free)?;
+            }
+            Opcode::GetFree => {
+                let free_index = ins[ip + 1] as usize;
+                self.current_frame().ip += 1;
+
+                let free = self.current_frame().function.free[free_index].clone();
+                self.push(Value::from(free))?;
+            }
+            Opcode::CurrentClosure => {
+                let current_closure = self.current_frame().function.clone();
+                self.push(Value::Object(Rc::new(Object::CLOSURE(current_closure))))?;
             }
         }
         Ok(())
     }
 
-    fn execute_binary_operation(&mut self, op: Opcode) -> Result<(), String> {
+    fn execute_binary_operation(&mut self, op: Opcode) -> Result<(), VmError> {
         let right = self.pop()?;
         let left = self.pop()?;
 
-        match (&*left, &*right) {
-            (Object::INTEGER(_), Object::INTEGER(_)) => {
-                self.execute_bianary_integer_operation(&left, &right, op)?;
+        match (&left, &right) {
+            (Value::Integer(left), Value::Integer(right)) => {
+                self.execute_bianary_integer_operation(*left, *right, op)?;
             }
-            (Object::BOOLEAN(left), Object::BOOLEAN(right)) => {
+            (Value::Boolean(left), Value::Boolean(right)) => {
                 let result = match op {
                     Opcode::Or => *left || *right,
                     Opcode::And => *left && *right,
                     _ => {
-                        return Err("Unsupported types for binary operation".to_string());
+                        return Err(VmError::TypeMismatch(
+                            "Unsupported types for binary operation".to_string(),
+                        ));
                     }
                 };
 
-                if result {
-                    self.push(Rc::new(TRUE))?;
-                } else {
-                    self.push(Rc::new(FALSE))?;
-                }
+                self.push(Value::Boolean(result))?;
             }
-            (Object::STRING(s1), Object::STRING(s2)) => {
-                let result = match op {
-                    Opcode::Add => s1.to_string() + s2,
-                    _ => {
-                        return Err("Unsupported types for binary operation".to_string());
-                    }
-                };
-
-                self.push(Rc::new(Object::STRING(result)))?;
+            (Value::Object(left), Value::Object(right)) => match (&**left, &**right) {
+                (Object::STRING(s1), Object::STRING(s2)) if op == Opcode::Add => {
+                    let result = [&**s1, &**s2].concat();
+                    self.push(Value::Object(Rc::new(Object::STRING(result.into()))))?;
+                }
+                _ => {
+                    return Err(VmError::TypeMismatch(
+                        "Unsupported types for binary operation".to_string(),
+                    ))
+                }
+            },
+            _ => {
+                return Err(VmError::TypeMismatch(
+                    "Unsupported types for binary operation".to_string(),
+                ))
             }
-            _ => return Err("Unsupported types for binary operation".to_string()),
         }
         Ok(())
     }
 
     fn execute_bianary_integer_operation(
         &mut self,
-        left: &Rc<Object>,
-        right: &Rc<Object>,
+        left: i64,
+        right: i64,
         op: Opcode,
-    ) -> Result<(), String> {
-        let left = self.cast_to_integer(left)?;
-        let right = self.cast_to_integer(right)?;
-
+    ) -> Result<(), VmError> {
         let result = match op {
             Opcode::Add => left + right,
             Opcode::Sub => left - right,
             Opcode::Mul => left * right,
             Opcode::Div => {
                 if right == 0 {
-                    Err("Division by zero".to_string())?
+                    Err(VmError::DivisionByZero)?
                 } else {
                     left / right
                 }
             }
             Opcode::Modulo => {
                 if right == 0 {
-                    Err("Division by zero".to_string())?
+                    Err(VmError::DivisionByZero)?
                 } else {
                     left % right
                 }
@@ -323,41 +947,64 @@ impl VM {
             _ => unreachable!(),
         };
 
-        self.push(Rc::new(Object::INTEGER(result)))?;
+        self.push(Value::Integer(result))?;
         Ok(())
     }
 
-    fn execute_comparison(&mut self, op: Opcode) -> Result<(), String> {
+    fn execute_comparison(&mut self, op: Opcode) -> Result<(), VmError> {
         let right = self.pop()?;
         let left = self.pop()?;
 
-        match (&*left, &*right) {
-            (Object::INTEGER(_), Object::INTEGER(_)) => {
-                self.execute_integer_comparison(&left, &right, op)?;
+        match (&left, &right) {
+            (Value::Integer(left), Value::Integer(right)) => {
+                self.execute_integer_comparison(*left, *right, op)?;
             }
-            (Object::BOOLEAN(_), Object::BOOLEAN(_)) => match op {
+            (Value::Boolean(_), Value::Boolean(_)) => match op {
                 Opcode::Equal => {
-                    self.push(self.native_boolean_to_boolean_object(left == right))?;
+                    self.push(Value::Boolean(left == right))?;
                 }
                 Opcode::NotEqual => {
-                    self.push(self.native_boolean_to_boolean_object(left != right))?;
+                    self.push(Value::Boolean(left != right))?;
                 }
-                _ => Err("Unsupported types for comparison".to_string())?,
+                _ => Err(VmError::TypeMismatch(
+                    "Unsupported types for comparison".to_string(),
+                ))?,
             },
-            _ => Err("Unsupported types for comparison".to_string())?,
+            _ => Err(VmError::TypeMismatch(
+                "Unsupported types for comparison".to_string(),
+            ))?,
         }
         Ok(())
     }
 
+    /// Compares two values with the comparison opcode of a superinstruction,
+    /// without pushing the result when they are integers.
+    fn compare(&mut self, left: Value, right: Value, comparison: u8) -> Result<bool, VmError> {
+        let comparison = Opcode::from_u8(comparison)
+            .ok_or_else(|| VmError::Internal(format!("Unknown opcode {comparison}")))?;
+        if let (Value::Integer(left), Value::Integer(right)) = (&left, &right) {
+            return match comparison {
+                Opcode::Equal => Ok(left == right),
+                Opcode::NotEqual => Ok(left != right),
+                Opcode::GreaterThan => Ok(left > right),
+                Opcode::GreaterEqualThan => Ok(left >= right),
+                _ => Err(VmError::Internal(format!(
+                    "{comparison} is not a comparison"
+                ))),
+            };
+        }
+        self.push(left)?;
+        self.push(right)?;
+        self.execute_comparison(comparison)?;
+        Ok(self.pop()?.is_truthy())
+    }
+
     fn execute_integer_comparison(
         &mut self,
-        left: &Rc<Object>,
-        right: &Rc<Object>,
+        left: i64,
+        right: i64,
         op: Opcode,
-    ) -> Result<(), String> {
-        let left = self.cast_to_integer(left)?;
-        let right = self.cast_to_integer(right)?;
-
+    ) -> Result<(), VmError> {
         let result = match op {
             Opcode::Equal => left == right,
             Opcode::NotEqual => left != right,
@@ -366,216 +1013,395 @@ impl VM {
             _ => unreachable!(),
         };
 
-        if result {
-            self.push(Rc::new(TRUE))?;
-        } else {
-            self.push(Rc::new(FALSE))?;
-        }
+        self.push(Value::Boolean(result))?;
         Ok(())
     }
 
-    fn execute_bang_operation(&mut self) -> Result<(), String> {
+    fn execute_bang_operation(&mut self) -> Result<(), VmError> {
         let operand = self.pop()?;
-        let value = self.native_boolean_to_boolean_object(!self.is_truthy(&operand));
-        self.push(value)?;
+        self.push(Value::Boolean(!operand.is_truthy()))?;
         Ok(())
     }
 
-    fn execute_minus_operation(&mut self) -> Result<(), String> {
+    fn execute_minus_operation(&mut self) -> Result<(), VmError> {
         let operand = self.pop()?;
 
-        match &*operand {
-            Object::INTEGER(i) => {
-                self.push(Rc::new(Object::INTEGER(-i)))?;
+        match operand {
+            Value::Integer(i) => {
+                self.push(Value::Integer(-i))?;
             }
             _ => {
-                return Err("Unsupported type for minus operation".to_string());
+                return Err(VmError::TypeMismatch(
+                    "Unsupported type for minus operation".to_string(),
+                ));
             }
         }
         Ok(())
     }
 
-    fn build_array(&self, start_index: usize, end_index: usize) -> Result<Rc<Object>, String> {
+    fn build_array(&self, start_index: usize, end_index: usize) -> Result<Value, VmError> {
         let mut elements: Vec<Object> = Vec::new();
         for i in start_index..end_index {
-            elements
-                .push((**(self.stack.get(i).ok_or("Unable to get element".to_string()))?).clone());
+            elements.push(
+                self.stack
+                    .get(i)
+                    .ok_or_else(|| VmError::Internal("Unable to get element".to_string()))?
+                    .to_object(),
+            );
         }
-        Ok(Rc::new(Object::ARRAY(elements)))
+        Ok(Value::Object(Rc::new(Object::ARRAY(elements))))
     }
 
-    fn build_hashmap(&self, start_index: usize, end_index: usize) -> Result<Rc<Object>, String> {
+    fn build_hashmap(&self, start_index: usize, end_index: usize) -> Result<Value, VmError> {
+        #[allow(clippy::mutable_key_type)]
         let mut elements: HashMap<Object, Object> = HashMap::new();
         for i in (start_index..end_index).step_by(2) {
-            let key = (**(self.stack.get(i).ok_or("Unable to get element".to_string()))?).clone();
-            let value = (**(self
+            let key = self
+                .stack
+                .get(i)
+                .ok_or_else(|| VmError::Internal("Unable to get element".to_string()))?
+                .to_object();
+            let value = self
                 .stack
                 .get(i + 1)
-                .ok_or("Unable to get element".to_string()))?)
-            .clone();
+                .ok_or_else(|| VmError::Internal("Unable to get element".to_string()))?
+                .to_object();
             if !Object::is_hashable(&key) {
-                return Ok(Rc::new(Object::ERROR(format!(
-                    "Unusable as hashmap key: {key:?}"
-                ))));
+                return Ok(Value::Object(Rc::new(Object::ERROR(ErrorObject::new(
+                    ErrorKind::TypeMismatch,
+                    format!("Unusable as hashmap key: {key:?}"),
+                )))));
             }
             elements.insert(key, value);
         }
-        Ok(Rc::new(Object::HASHMAP(elements)))
+        Ok(Value::Object(Rc::new(Object::HASHMAP(elements))))
     }
 
-    fn execute_index_expression(
-        &mut self,
-        left: &Rc<Object>,
-        index: &Rc<Object>,
-    ) -> Result<(), String> {
-        match (&**left, &**index) {
-            (Object::ARRAY(elements), Object::INTEGER(i)) => {
+    fn execute_index_expression(&mut self, left: &Value, index: &Value) -> Result<(), VmError> {
+        let Some(left) = left.as_object() else {
+            return Err(VmError::TypeMismatch(
+                "Unsupported types for index operation".to_string(),
+            ));
+        };
+        match (left, index) {
+            (Object::ARRAY(elements), Value::Integer(i)) => {
                 if *i < 0 || *i >= elements.len() as i64 {
-                    self.push(Rc::new(Object::NULL))?;
+                    self.push(Value::Null)?;
                 } else {
                     let result = elements
                         .get(*i as usize)
-                        .ok_or("Index out of bounds".to_string())?;
-                    self.push(Rc::new(result.clone()))?;
+                        .ok_or_else(|| VmError::Internal("Index out of bounds".to_string()))?;
+                    self.push(Value::from(result.clone()))?;
                 }
             }
+            (Object::BYTES(bytes), Value::Integer(i)) => match usize::try_from(*i) {
+                Ok(i) if i < bytes.len() => self.push(Value::Integer(i64::from(bytes[i])))?,
+                _ => self.push(Value::Null)?,
+            },
             (Object::HASHMAP(elements), _) => {
-                if !Object::is_hashable(index) {
-                    return Err("Unusable as hashmap key".to_string());
+                let index = index.to_object();
+                if !Object::is_hashable(&index) {
+                    return Err(VmError::TypeMismatch("Unusable as hashmap key".to_string()));
                 }
-                match elements.get(index) {
+                match elements.get(&index) {
                     Some(value) => {
-                        self.push(Rc::new(value.clone()))?;
+                        self.push(Value::from(value.clone()))?;
                     }
                     None => {
-                        self.push(Rc::new(Object::NULL))?;
+                        self.push(Value::Null)?;
                     }
                 }
             }
 
             _ => {
-                return Err("Unsupported types for index operation".to_string());
+                return Err(VmError::TypeMismatch(
+                    "Unsupported types for index operation".to_string(),
+                ));
             }
         }
         Ok(())
     }
 
-    fn execute_call(&mut self, num_args: usize) -> Result<(), String> {
+    /// Pops the value, the `depth` indexes and the collection, and pushes the
+    /// collection with the element replaced.
+    ///
+    /// The collection is copied only if something else holds it. The variable
+    /// it was read from does not count: it is cleared, since the next
+    /// instruction stores the updated collection in it.
+    fn execute_set_index(&mut self, depth: usize) -> Result<(), VmError> {
+        let value = self.pop()?.to_object();
+        let indexes: Vec<Object> = self.stack[self.sp - depth..self.sp]
+            .iter()
+            .map(Value::to_object)
+            .collect();
+        self.sp -= depth;
+
+        let mut collection = std::mem::replace(&mut self.stack[self.sp - 1], Value::Null).into_rc();
+        let cleared = match self.assigned_variable() {
+            Some(variable)
+                if variable
+                    .as_object()
+                    .is_some_and(|held| ptr::eq(held, &raw const *collection)) =>
+            {
+                *variable = Value::Null;
+                true
+            }
+            _ => false,
+        };
+        let result = Rc::make_mut(&mut collection).set_index(&indexes, value);
+        if result.is_err() && cleared {
+            // The collection did not change, the variable gets it back
+            if let Some(variable) = self.assigned_variable() {
+                *variable = Value::Object(Rc::clone(&collection));
+            }
+        }
+        self.stack[self.sp - 1] = Value::from(collection);
+        result.map_err(|error| VmError::IndexAssignment(error.message))
+    }
+
+    /// Returns the variable set by the next instruction, if it sets one.
+    fn assigned_variable(&mut self) -> Option<&mut Value> {
+        let frame = &self.frames[self.frames_index - 1];
+        let ip = (frame.ip + 1) as usize;
+        let instructions = frame.get_instructions();
+        match Opcode::from_u8(*instructions.get(ip)?)? {
+            Opcode::SetGlobal => {
+                let index = read_u16(instructions.get(ip + 1..)?) as usize;
+                self.globals.get_mut(index)
+            }
+            Opcode::SetLocal => {
+                let index = frame.base_pointer + *instructions.get(ip + 1)? as usize;
+                self.stack.get_mut(index)
+            }
+            _ => None,
+        }
+    }
+
+    fn execute_call(&mut self, num_args: usize) -> Result<(), VmError> {
         let callee = self
             .stack
             .get(self.sp - 1 - num_args)
-            .ok_or("Stack underflow")?;
+            .ok_or_else(|| VmError::Internal("Stack underflow".to_string()))?;
 
-        match callee.as_ref().clone() {
-            Object::CLOSURE(func) => self.call_closure(func, num_args),
-            Object::BUILTIN(func) => self.call_builtin_function(&func, num_args),
-            _ => Err("Calling non-function".to_string()),
+        match callee.as_object() {
+            Some(Object::CLOSURE(func)) => self.call_closure(func.clone(), num_args),
+            Some(Object::BUILTIN(func)) => self.call_builtin_function(&func.clone(), num_args),
+            _ => Err(VmError::NotAFunction),
         }
     }
 
-    fn call_closure(&mut self, func: Closure, num_args: usize) -> Result<(), String> {
+    fn call_closure(&mut self, func: Closure, num_args: usize) -> Result<(), VmError> {
         if num_args != func.function.num_parameters {
-            return Err(format!(
-                "Wrong number of arguments: want={}, got={}",
-                func.function.num_parameters, num_args
-            ));
+            return Err(VmError::WrongArguments {
+                want: func.function.num_parameters,
+                got: num_args,
+            });
+        }
+        if self.frames_index > self.max_depth {
+            return Err(VmError::MaxDepth {
+                max_depth: self.max_depth,
+                function: func.function.name.clone(),
+            });
+        }
+
+        #[cfg(feature = "hooks")]
+        if let Some(hooks) = self.hooks.as_mut() {
+            let args: Vec<Object> = self.stack[self.sp - num_args..self.sp]
+                .iter()
+                .map(Value::to_object)
+                .collect();
+            hooks.on_call(&Object::CLOSURE(func.clone()), &args);
+        }
+
+        #[cfg(feature = "jit")]
+        if let Some(result) = self.call_native(&func, num_args) {
+            self.sp -= num_args + 1;
+            return self.push(Value::from(result));
         }
 
         let num_locals = func.function.num_locals;
         let frame = Frame::new(func, self.sp - num_args);
+        self.grow_stack(frame.base_pointer + num_locals)?;
         self.sp = frame.base_pointer + num_locals;
         self.push_frame(frame);
         Ok(())
     }
 
+    /// Runs the call as native code if the function is hot and can be
+    /// compiled, returning `None` if the VM must run it.
+    #[cfg(feature = "jit")]
+    fn call_native(&mut self, func: &Closure, num_args: usize) -> Option<Object> {
+        // Native code does not use the fuel, check the timeout and the
+        // interrupt nor report what it runs
+        if self.fuel.is_some()
+            || self.deadline.is_some()
+            || self.interrupt.is_some()
+            || self.trace.is_some()
+            || self.stats.is_some()
+            || self.coverage.is_some()
+        {
+            return None;
+        }
+        #[cfg(feature = "hooks")]
+        if self.hooks.is_some() {
+            return None;
+        }
+        let max_depth = self.max_depth + 1 - self.frames_index;
+        let stack_left = self.max_stack_size.saturating_sub(self.sp - num_args - 1);
+        self.jit.as_mut()?.call(
+            &func.function,
+            &self.stack[self.sp - num_args..self.sp],
+            &self.constants,
+            max_depth,
+            stack_left,
+        )
+    }
+
     fn call_builtin_function(
         &mut self,
         callee: &BuiltinFunction,
         num_args: usize,
-    ) -> Result<(), String> {
+    ) -> Result<(), VmError> {
         let mut args: Vec<Object> = Vec::new();
         for _ in 0..num_args {
-            args.push(self.pop()?.as_ref().clone());
+            args.push(self.pop()?.to_object());
         }
         args.reverse();
 
-        let result = callee.call(args);
+        hook!(self.on_call(&Object::BUILTIN(callee.clone()), &args));
+        let result = callee.call(args, self);
+        if let Some(error) = self.builtin_error.take() {
+            return Err(error);
+        }
+        if let Object::EXIT(code) = result {
+            self.exit_code = Some(code);
+        }
+        // The errors of the other builtins are values, a failed assertion or
+        // exit must stop the program
+        if let (BuiltinFunction::ASSERT | BuiltinFunction::EXIT, Object::ERROR(error)) =
+            (callee, &result)
+        {
+            return Err(VmError::Builtin(error.message.clone()));
+        }
+        hook!(self.on_return(&result));
+        #[cfg(feature = "hooks")]
+        if let Object::ERROR(error) = &result {
+            hook!(self.on_error(&error.message));
+        }
 
         self.sp -= 1;
-        self.push(Rc::new(result))?;
+        self.push(Value::from(result))?;
         Ok(())
     }
 
-    fn push_closure(&mut self, const_index: usize, num_free: usize) -> Result<(), String> {
-        match (*self.constants[const_index]).clone() {
+    /// Calls a function from outside of the run loop and runs it to completion.
+    fn call_function(&mut self, function: &Object, args: Vec<Object>) -> Result<Object, VmError> {
+        match function {
+            Object::CLOSURE(closure) => {
+                let num_args = args.len();
+                let depth = self.frames_index;
+                self.push(Value::from(function.clone()))?;
+                for arg in args {
+                    self.push(Value::from(arg))?;
+                }
+                self.call_closure(closure.clone(), num_args)?;
+                self.execute_until(depth)?;
+                if let Some(code) = self.exit_code {
+                    // The function did not return, it exited the whole program
+                    return Ok(Object::EXIT(code));
+                }
+                Ok(self.pop()?.to_object())
+            }
+            Object::BUILTIN(builtin) => Ok(builtin.call(args, self)),
+            _ => Err(VmError::NotAFunction),
+        }
+    }
+
+    fn push_closure(&mut self, const_index: usize, num_free: usize) -> Result<(), VmError> {
+        match &self.constants[const_index] {
             Object::COMPILEDFUNCTION(func) => {
-                let mut closure = Closure::new(func);
+                let mut closure = Closure::new(func.clone());
 
-                for obj in &self.stack[self.sp - num_free..self.sp] {
-                    closure.add_free_variable(obj.as_ref().clone());
+                for value in &self.stack[self.sp - num_free..self.sp] {
+                    closure.add_free_variable(value.to_object());
                 }
 
                 self.sp -= num_free;
-                self.push(Rc::new(Object::CLOSURE(closure)))
+                self.push(Value::Object(Rc::new(Object::CLOSURE(closure))))
             }
-            x => Err(format!["Function expected, got {}", x.get_type()]),
+            _ => Err(VmError::Internal(format!(
+                "Function expected, got {}",
+                self.constants[const_index].get_type()
+            ))),
         }
     }
 
-    fn native_boolean_to_boolean_object(&self, input: bool) -> Rc<Object> {
-        if input {
-            Rc::new(TRUE)
-        } else {
-            Rc::new(FALSE)
-        }
+    fn push(&mut self, value: Value) -> Result<(), VmError> {
+        self.grow_stack(self.sp + 1)?;
+        self.stack[self.sp] = value;
+        self.sp += 1;
+        Ok(())
     }
 
-    fn is_truthy(&self, obj: &Rc<Object>) -> bool {
-        match &**obj {
-            Object::NULL => false,
-            Object::BOOLEAN(b) => *b,
-            _ => true,
+    /// Makes the stack at least `size` slots long, the new slots are `null`.
+    /// Its capacity doubles when it is full, like the one of a `Vec`.
+    fn grow_stack(&mut self, size: usize) -> Result<(), VmError> {
+        if size > self.max_stack_size {
+            return Err(VmError::StackOverflow {
+                max_stack_size: self.max_stack_size,
+            });
         }
-    }
-
-    fn push(&mut self, obj: Rc<Object>) -> Result<(), String> {
-        if self.sp >= STACK_SIZE {
-            Err("Stack overflow :(, you gotta fix this".to_string())
-        } else {
-            self.stack[self.sp] = obj;
-            self.sp += 1;
-            Ok(())
+        if size > self.stack.len() {
+            self.stack.resize(size, Value::Null);
         }
+        Ok(())
     }
 
-    fn pop(&mut self) -> Result<Rc<Object>, String> {
+    fn pop(&mut self) -> Result<Value, VmError> {
         if self.sp == 0 {
-            Err("Stack underflow".to_string())
+            Err(VmError::Internal("Stack underflow".to_string()))
         } else {
             self.sp -= 1;
             self.stack
                 .get(self.sp)
-                .ok_or("Stack underflow".to_string())
+                .ok_or_else(|| VmError::Internal("Stack underflow".to_string()))
                 .cloned()
         }
     }
 
     pub fn stack_top(&self) -> Option<Rc<Object>> {
-        self.stack.get(self.sp - 1).cloned()
-    }
-
-    fn cast_to_integer(&self, obj: &Rc<Object>) -> Result<i64, String> {
-        match **obj {
-            Object::INTEGER(i) => Ok(i),
-            _ => Err("Unable to cast to integer".to_string()),
-        }
+        self.stack.get(self.sp - 1).cloned().map(Value::into_rc)
     }
 
     pub fn last_popped_stack_element(&self) -> Result<Rc<Object>, String> {
-        self.stack
+        // Nothing was ever pushed there if the stack did not grow that far
+        Ok(self
+            .stack
             .get(self.sp)
-            .ok_or("Stack underflow".to_string())
             .cloned()
+            .map_or_else(cache::null, Value::into_rc))
+    }
+
+    fn global(&mut self, index: usize) -> Result<&mut Value, VmError> {
+        let size = self.globals.len();
+        self.globals.get_mut(index).ok_or(VmError::UndefinedGlobal {
+            index,
+            globals: size,
+        })
+    }
+
+    /// Returns the global named by the string constant `constant`, defined by
+    /// a call to `eval` after the program was compiled.
+    fn global_by_name(&mut self, constant: usize) -> Result<&mut Value, VmError> {
+        let Some(Object::STRING(name)) = self.constants.get(constant) else {
+            return Err(VmError::Internal(format!(
+                "Constant {constant} is not the name of a global"
+            )));
+        };
+        match self.symbol_table.resolve(name) {
+            Some(symbol) if symbol.scope == SymbolScope::Global => self.global(symbol.index),
+            _ => Err(VmError::UndefinedVariable(name.to_string())),
+        }
     }
 
     fn current_frame(&mut self) -> &mut Frame {
@@ -585,6 +1411,9 @@ impl VM {
     fn push_frame(&mut self, frame: Frame) {
         self.frames.push(frame);
         self.frames_index += 1;
+        if let Some(stats) = &mut self.stats {
+            stats.max_depth = stats.max_depth.max(self.frames_index - 1);
+        }
     }
 
     fn pop_frame(&mut self) -> Option<Frame> {
@@ -592,3 +1421,92 @@ impl VM {
         self.frames.pop()
     }
 }
+
+/// Returns the closure running the main program.
+fn main_closure(instructions: Rc<Instructions>, source_map: SourceMap) -> Closure {
+    Closure::new(Rc::new(CompiledFunction {
+        instructions: Rc::unwrap_or_clone(instructions).data,
+        num_locals: 0,
+        num_parameters: 0,
+        name: None,
+        source_map,
+    }))
+}
+
+/// Returns a short form of a value for debugging output. Functions are shown
+/// by name, their instructions would make it unreadable.
+pub fn describe_value(value: &Value) -> String {
+    let function = match value.as_object() {
+        Some(Object::CLOSURE(closure)) => &closure.function,
+        Some(Object::COMPILEDFUNCTION(function)) => function,
+        Some(object) => return object.inspect(),
+        None => return value.to_string(),
+    };
+    format!("fn {}", function.name.as_deref().unwrap_or("<anonymous>"))
+}
+
+impl BuiltinContext for VM {
+    fn apply(&mut self, function: &Object, args: Vec<Object>) -> Object {
+        match self.call_function(function, args) {
+            Ok(result) => result,
+            Err(error) => {
+                let object = Object::ERROR(ErrorObject::from(&error));
+                self.builtin_error = Some(error);
+                object
+            }
+        }
+    }
+
+    fn output_style(&self) -> &OutputStyle {
+        &self.output_style
+    }
+
+    fn input(&mut self) -> Option<&mut dyn BufRead> {
+        self.input
+            .as_mut()
+            .map(|input| input.as_mut() as &mut dyn BufRead)
+    }
+
+    fn random(&mut self) -> &mut Random {
+        &mut self.random
+    }
+
+    fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    fn eval(&mut self, program: Program) -> Object {
+        let mut compiler = Compiler::new_with_indexed_state(
+            self.symbol_table.clone(),
+            Rc::clone(&self.constants),
+            Rc::clone(&self.string_constants),
+        );
+        compiler.set_max_globals(self.globals.len());
+        if let Err(error) = compiler.compile_returning(&program) {
+            return Object::error(error);
+        }
+
+        let Bytecode {
+            instructions,
+            constants,
+            symbol_table,
+            string_constants,
+            ..
+        } = compiler.bytecode();
+        // The constants already loaded keep their indexes
+        self.constants = constants;
+        self.string_constants = string_constants;
+        self.symbol_table = symbol_table;
+
+        // The program runs as a function without parameters nor locals, its
+        // variables are globals
+        let function = CompiledFunction {
+            instructions: Rc::unwrap_or_clone(instructions).data,
+            num_locals: 0,
+            num_parameters: 0,
+            name: None,
+            source_map: SourceMap::default(),
+        };
+        self.apply(&Object::CLOSURE(Closure::new(Rc::new(function))), vec![])
+    }
+}
@@ -0,0 +1,229 @@
+use std::fmt::Write;
+
+use byteorder::{BigEndian, WriteBytesExt};
+use enum_stringify::EnumStringify;
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
+
+use crate::compiler::code::{read_u16, read_u32};
+
+/// Instructions of the register machine.
+///
+/// Unlike the opcodes of the stack VM, the operands name the registers the
+/// instruction reads and writes, relative to the window of the running
+/// function, the destination always coming first. Registers, constants,
+/// globals and counts are 2 bytes operands, jump targets 4 bytes ones.
+#[derive(Debug, PartialEq, Eq, Hash, FromPrimitive, Clone, Copy, EnumStringify)]
+#[enum_stringify(prefix = "Op")]
+pub enum RegisterOpcode {
+    // dst, constant
+    LoadConstant,
+    // dst
+    LoadTrue,
+    LoadFalse,
+    LoadNull,
+    // dst, src
+    Move,
+
+    // dst, global
+    GetGlobal,
+    // dst, constant of the name of the global
+    GetGlobalByName,
+    // global, src
+    SetGlobal,
+    // dst, builtin
+    GetBuiltin,
+    // dst, free
+    GetFree,
+    // dst
+    CurrentClosure,
+
+    // dst, left, right
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Modulo,
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    GreaterThan,
+    GreaterEqualThan,
+
+    // dst, src
+    Minus,
+    Bang,
+
+    // target
+    Jump,
+    // condition, target
+    JumpNotTruthy,
+
+    // dst, first, count
+    Array,
+    HashMap,
+    // dst, collection, index
+    Index,
+    // collection, first index, number of indexes, value
+    SetIndex,
+    // global, first index, number of indexes, value
+    SetIndexGlobal,
+
+    // dst, callee, first argument, number of arguments
+    Call,
+    // src
+    Return,
+    // dst, constant, first free variable, number of free variables
+    Closure,
+}
+
+impl RegisterOpcode {
+    pub fn lookup_widths(&self) -> &'static [u32] {
+        match self {
+            RegisterOpcode::LoadTrue
+            | RegisterOpcode::LoadFalse
+            | RegisterOpcode::LoadNull
+            | RegisterOpcode::CurrentClosure
+            | RegisterOpcode::Return => &[2],
+            RegisterOpcode::LoadConstant
+            | RegisterOpcode::Move
+            | RegisterOpcode::GetGlobal
+            | RegisterOpcode::GetGlobalByName
+            | RegisterOpcode::SetGlobal
+            | RegisterOpcode::GetBuiltin
+            | RegisterOpcode::GetFree
+            | RegisterOpcode::Minus
+            | RegisterOpcode::Bang => &[2, 2],
+            RegisterOpcode::Add
+            | RegisterOpcode::Sub
+            | RegisterOpcode::Mul
+            | RegisterOpcode::Div
+            | RegisterOpcode::Modulo
+            | RegisterOpcode::Or
+            | RegisterOpcode::And
+            | RegisterOpcode::Equal
+            | RegisterOpcode::NotEqual
+            | RegisterOpcode::GreaterThan
+            | RegisterOpcode::GreaterEqualThan
+            | RegisterOpcode::Array
+            | RegisterOpcode::HashMap
+            | RegisterOpcode::Index => &[2, 2, 2],
+            RegisterOpcode::SetIndex
+            | RegisterOpcode::SetIndexGlobal
+            | RegisterOpcode::Call
+            | RegisterOpcode::Closure => &[2, 2, 2, 2],
+            RegisterOpcode::Jump => &[4],
+            RegisterOpcode::JumpNotTruthy => &[2, 4],
+        }
+    }
+
+    /// Returns the index of the operand holding the target of the instruction,
+    /// if it can jump.
+    pub fn jump_operand(&self) -> Option<usize> {
+        match self {
+            RegisterOpcode::Jump => Some(0),
+            RegisterOpcode::JumpNotTruthy => Some(1),
+            _ => None,
+        }
+    }
+
+    /// Returns the number of bytes of the instruction, its opcode included.
+    pub fn size(&self) -> usize {
+        1 + self
+            .lookup_widths()
+            .iter()
+            .map(|width| *width as usize)
+            .sum::<usize>()
+    }
+
+    /// Encodes the instruction, failing if an operand does not fit in its
+    /// width.
+    ///
+    /// # Errors
+    ///
+    /// If an operand does not fit in its width.
+    pub fn make(&self, operands: &[usize]) -> Result<Vec<u8>, String> {
+        let mut instruction = vec![*self as u8];
+        for (operand, width) in operands.iter().zip(self.lookup_widths()) {
+            match width {
+                4 => match u32::try_from(*operand) {
+                    Ok(operand) => instruction.write_u32::<BigEndian>(operand).unwrap(),
+                    Err(_) => return Err(format!("{self} operand {operand} is too large")),
+                },
+                _ => match u16::try_from(*operand) {
+                    Ok(operand) => instruction.write_u16::<BigEndian>(operand).unwrap(),
+                    Err(_) => {
+                        return Err(format!(
+                            "{self} operand {operand} is too large, the limit is {}",
+                            u16::MAX
+                        ))
+                    }
+                },
+            }
+        }
+        Ok(instruction)
+    }
+
+    /// Reads the operands of an instruction from the bytes following its
+    /// opcode.
+    pub fn read_operands(&self, data: &[u8]) -> Vec<usize> {
+        let mut offset = 0;
+        self.lookup_widths()
+            .iter()
+            .map(|width| {
+                let operand = match width {
+                    4 => read_u32(&data[offset..]) as usize,
+                    _ => read_u16(&data[offset..]) as usize,
+                };
+                offset += *width as usize;
+                operand
+            })
+            .collect()
+    }
+}
+
+/// Returns the listing of register instructions, one per line with its
+/// position, registers being written `rN`.
+pub fn disassemble(instructions: &[u8]) -> String {
+    let mut output = String::new();
+    let mut i = 0;
+    while i < instructions.len() {
+        let Some(op) = RegisterOpcode::from_u8(instructions[i]) else {
+            let _ = writeln!(output, "{i:04} ERROR: Unknown opcode {}", instructions[i]);
+            break;
+        };
+        let operands = op.read_operands(&instructions[i + 1..]);
+        let _ = write!(output, "{i:04} {op}");
+        for (index, operand) in operands.iter().enumerate() {
+            if is_register(op, index) {
+                let _ = write!(output, " r{operand}");
+            } else {
+                let _ = write!(output, " {operand}");
+            }
+        }
+        output.push('\n');
+        i += op.size();
+    }
+    output
+}
+
+/// Returns whether the operand at `index` of `op` is a register.
+fn is_register(op: RegisterOpcode, index: usize) -> bool {
+    match op {
+        RegisterOpcode::Jump => false,
+        RegisterOpcode::JumpNotTruthy
+        | RegisterOpcode::LoadConstant
+        | RegisterOpcode::GetGlobal
+        | RegisterOpcode::GetGlobalByName
+        | RegisterOpcode::GetBuiltin
+        | RegisterOpcode::GetFree => index == 0,
+        RegisterOpcode::SetGlobal => index == 1,
+        RegisterOpcode::Array | RegisterOpcode::HashMap => index < 2,
+        RegisterOpcode::SetIndex => index != 2,
+        RegisterOpcode::SetIndexGlobal => index == 1 || index == 3,
+        RegisterOpcode::Call => index != 3,
+        RegisterOpcode::Closure => index == 0 || index == 2,
+        _ => true,
+    }
+}
@@ -0,0 +1,738 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use crate::{
+    compiler::{
+        symbol_table::{Symbol, SymbolScope, SymbolTable},
+        uses_eval,
+    },
+    lexer::token::Token,
+    object::{builtins::BuiltinFunction, CompiledFunction, Object},
+    parser::ast::{
+        BlockStatement, Conditional, Expression, FunctionLiteral, IndexAssignment, LoopStatement,
+        Primitive, Program, Statement, WhileStatement,
+    },
+    source::{LineIndex, Position, Source, SourceMap},
+    vm::{register::code::RegisterOpcode, GLOBALS_SIZE},
+};
+
+/// Register of the main program holding the value of the last expression
+/// statement, which it returns.
+const RESULT_REGISTER: usize = 0;
+
+/// A program compiled for the register machine.
+pub struct RegisterBytecode {
+    /// The main program, its registers are `num_locals`.
+    pub main: CompiledFunction,
+    pub constants: Vec<Object>,
+
+    /// Global symbols of the program, needed to compile code at runtime.
+    pub symbol_table: SymbolTable,
+
+    /// Source of the compiled program, if known.
+    pub source: Option<Source>,
+}
+
+struct LoopScope {
+    start: usize,
+    breaks: Vec<usize>,
+}
+
+/// Instructions and registers of the function being compiled.
+///
+/// Registers are handed out like a stack: the temporaries of an expression are
+/// freed once it is compiled. The locals are never freed, the temporaries
+/// always go above the last one.
+struct FunctionScope {
+    instructions: Vec<u8>,
+
+    /// Register of every local, by index of its symbol.
+    locals: Vec<usize>,
+    next_register: usize,
+    /// First register which is not a local.
+    first_temporary: usize,
+    num_registers: usize,
+
+    loops: Vec<LoopScope>,
+
+    /// First instruction and position of the statements compiled so far, it
+    /// becomes the [`SourceMap`] of the function.
+    source_map: Vec<(usize, Position)>,
+}
+
+impl FunctionScope {
+    fn new(reserved: usize) -> Self {
+        Self {
+            instructions: Vec::new(),
+            locals: Vec::new(),
+            next_register: reserved,
+            first_temporary: reserved,
+            num_registers: reserved,
+            loops: Vec::new(),
+            source_map: Vec::new(),
+        }
+    }
+}
+
+/// Compiles a program to the instructions of the register machine, see
+/// [`RegisterOpcode`].
+///
+/// It shares the symbol table of the stack compiler, locals being registers
+/// instead of slots of the stack.
+pub struct RegisterCompiler {
+    pub constants: Vec<Object>,
+
+    /// Index of every string constant, so that identical strings share one
+    /// constant.
+    string_constants: HashMap<Rc<str>, usize>,
+
+    pub symbol_table: SymbolTable,
+
+    scopes: Vec<FunctionScope>,
+
+    /// Number of globals the program can define.
+    max_globals: usize,
+
+    source: Option<Source>,
+    lines: Option<LineIndex>,
+
+    /// Whether the undefined variables are looked up by name when they are
+    /// read, like [`Compiler`](crate::compiler::Compiler) does for programs
+    /// calling `eval`.
+    late_globals: bool,
+}
+
+impl Default for RegisterCompiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RegisterCompiler {
+    pub fn new() -> Self {
+        let mut symbol_table = SymbolTable::new();
+        for (i, builtin) in BuiltinFunction::get_builtins_names().iter().enumerate() {
+            symbol_table.define_builtin(i, builtin.clone());
+        }
+
+        RegisterCompiler {
+            constants: vec![],
+            string_constants: HashMap::new(),
+            symbol_table,
+            scopes: vec![FunctionScope::new(RESULT_REGISTER + 1)],
+            max_globals: GLOBALS_SIZE,
+            source: None,
+            lines: None,
+            late_globals: false,
+        }
+    }
+
+    pub fn new_with_state(symbol_table: SymbolTable, constants: Vec<Object>) -> Self {
+        let mut compiler = RegisterCompiler::new();
+        compiler.symbol_table = symbol_table;
+        compiler.string_constants = constants
+            .iter()
+            .enumerate()
+            .filter_map(|(i, constant)| match constant {
+                Object::STRING(s) => Some((Rc::clone(s), i)),
+                _ => None,
+            })
+            .collect();
+        compiler.constants = constants;
+        compiler
+    }
+
+    /// Sets the number of globals the program can define, at most
+    /// [`GLOBALS_SIZE`].
+    pub fn set_max_globals(&mut self, max_globals: usize) {
+        self.max_globals = max_globals.min(GLOBALS_SIZE);
+    }
+
+    /// Compiles the program, which returns the value of its last statement if
+    /// it is an expression, null otherwise.
+    ///
+    /// # Errors
+    ///
+    /// If the program cannot be compiled, such as a `break` outside of a loop,
+    /// or if it needs more registers or globals than the instructions can
+    /// refer to.
+    pub fn compile(&mut self, program: &Program) -> Result<(), String> {
+        self.source.clone_from(&program.source);
+        self.lines = self
+            .source
+            .as_ref()
+            .map(|source| LineIndex::new(&source.text));
+        let ends_with_expression =
+            matches!(program.statements.last(), Some(Statement::Expression(_)));
+        self.late_globals = uses_eval(program);
+        self.compile_statements(&program.statements, &program.offsets)?;
+        if !ends_with_expression {
+            self.emit(RegisterOpcode::LoadNull, &[RESULT_REGISTER])?;
+        }
+        self.emit(RegisterOpcode::Return, &[RESULT_REGISTER])?;
+        Ok(())
+    }
+
+    pub fn bytecode(&self) -> RegisterBytecode {
+        let scope = self.scope();
+        RegisterBytecode {
+            main: CompiledFunction {
+                instructions: scope.instructions.clone(),
+                num_locals: scope.num_registers,
+                num_parameters: 0,
+                name: None,
+                source_map: SourceMap::new(scope.source_map.clone()),
+            },
+            constants: self.constants.clone(),
+            symbol_table: self.symbol_table.clone(),
+            source: self.source.clone(),
+        }
+    }
+
+    fn scope(&self) -> &FunctionScope {
+        self.scopes.last().expect("the main scope is never left")
+    }
+
+    fn scope_mut(&mut self) -> &mut FunctionScope {
+        self.scopes
+            .last_mut()
+            .expect("the main scope is never left")
+    }
+
+    fn is_main(&self) -> bool {
+        self.scopes.len() == 1
+    }
+
+    fn compile_statements(
+        &mut self,
+        statements: &[Statement],
+        offsets: &[usize],
+    ) -> Result<(), String> {
+        self.compile_block(statements, offsets, None)
+    }
+
+    /// Compiles statements, writing the value of the last one to `dst` if it is
+    /// given: the value of the expression, null for the other statements.
+    fn compile_block(
+        &mut self,
+        statements: &[Statement],
+        offsets: &[usize],
+        dst: Option<usize>,
+    ) -> Result<(), String> {
+        // The instructions following a block belong to the statement around it
+        let enclosing = self
+            .scope()
+            .source_map
+            .last()
+            .map(|(_, position)| *position);
+
+        let count = statements.len();
+        let mut has_value = false;
+        for (i, statement) in statements.iter().enumerate() {
+            if let (Some(offset), Some(lines)) = (offsets.get(i), &self.lines) {
+                let position = lines.position(*offset);
+                self.add_position(position);
+            }
+            let mark = self.scope().next_register;
+            match (statement, dst) {
+                (Statement::Expression(expression), Some(dst)) if i + 1 == count => {
+                    self.compile_expression(expression, dst)?;
+                    has_value = true;
+                }
+                (statement, _) => self.compile_statement(statement)?,
+            }
+            self.free_registers(mark);
+        }
+        if let (Some(dst), false) = (dst, has_value) {
+            self.emit(RegisterOpcode::LoadNull, &[dst])?;
+        }
+
+        if let Some(position) = enclosing {
+            self.add_position(position);
+        }
+        Ok(())
+    }
+
+    fn compile_block_statement(
+        &mut self,
+        block: &BlockStatement,
+        dst: Option<usize>,
+    ) -> Result<(), String> {
+        self.compile_block(&block.statements, &block.offsets, dst)
+    }
+
+    /// Records that the next instructions come from the statement at
+    /// `position`.
+    fn add_position(&mut self, position: Position) {
+        let scope = self.scope_mut();
+        let start = scope.instructions.len();
+        match scope.source_map.last_mut() {
+            // The previous statement did not emit anything
+            Some(last) if last.0 == start => last.1 = position,
+            Some(last) if last.1 == position => {}
+            _ => scope.source_map.push((start, position)),
+        }
+    }
+
+    fn compile_statement(&mut self, statement: &Statement) -> Result<(), String> {
+        match statement {
+            Statement::Expression(expression) => {
+                // The main program keeps the value of its expressions
+                let dst = if self.is_main() {
+                    RESULT_REGISTER
+                } else {
+                    self.allocate_registers(1)?
+                };
+                self.compile_expression(expression, dst)?;
+            }
+            Statement::Let(statement) => {
+                let symbol = self.define_variable(&statement.name.value)?;
+                if symbol.scope == SymbolScope::Local {
+                    let register = self.scope().locals[symbol.index];
+                    self.compile_expression(&statement.value, register)?;
+                } else {
+                    let value = self.compile_operand(&statement.value)?;
+                    self.emit(RegisterOpcode::SetGlobal, &[symbol.index, value])?;
+                }
+            }
+            Statement::Return(statement) => {
+                let value = self.compile_operand(&statement.return_value)?;
+                self.emit(RegisterOpcode::Return, &[value])?;
+            }
+            Statement::While(statement) => self.compile_while_statement(statement)?,
+            Statement::LoopStatements(statement) => self.compile_loop_statement(statement)?,
+            Statement::IndexAssignment(statement) => self.compile_index_assignment(statement)?,
+        }
+        Ok(())
+    }
+
+    /// Returns the symbol a `let` of `name` assigns to, defining it if needed,
+    /// like the stack compiler does.
+    fn define_variable(&mut self, name: &str) -> Result<Symbol, String> {
+        let symbol = match self.symbol_table.resolve(name) {
+            // A local variable never replaces a global one
+            Some(symbol)
+                if symbol.scope == SymbolScope::Global && !self.symbol_table.has_outer() =>
+            {
+                symbol
+            }
+            Some(symbol) if symbol.scope == SymbolScope::Local => symbol,
+            _ => self.symbol_table.define(name.to_string()),
+        };
+        match symbol.scope {
+            SymbolScope::Global if symbol.index >= self.max_globals => {
+                return Err(format!(
+                    "Too many global variables, the limit is {}",
+                    self.max_globals
+                ))
+            }
+            SymbolScope::Local => self.allocate_local(&symbol)?,
+            _ => {}
+        }
+        Ok(symbol)
+    }
+
+    /// Gives a register to a local symbol, unless it already has one.
+    fn allocate_local(&mut self, symbol: &Symbol) -> Result<(), String> {
+        if symbol.index < self.scope().locals.len() {
+            return Ok(());
+        }
+        let register = self.allocate_registers(1)?;
+        let scope = self.scope_mut();
+        scope.locals.push(register);
+        scope.first_temporary = register + 1;
+        Ok(())
+    }
+
+    /// Compiles `a[i][j] = v` to an `OpSetIndex` replacing the element of the
+    /// register of `a`, or an `OpSetIndexGlobal` if it is a global. The
+    /// collection is read before the variable is defined, which makes a local
+    /// one in a function.
+    fn compile_index_assignment(&mut self, statement: &IndexAssignment) -> Result<(), String> {
+        let source = self
+            .symbol_table
+            .resolve(&statement.name.value)
+            .ok_or_else(|| format!("Undefined variable: {}", statement.name.value))?;
+        let depth = statement.indexes.len();
+        let first = self.allocate_registers(depth)?;
+        for (i, index) in statement.indexes.iter().enumerate() {
+            self.compile_expression(index, first + i)?;
+        }
+        let value = self.compile_operand(&statement.value)?;
+
+        let target = self.define_variable(&statement.name.value)?;
+        if target.scope == SymbolScope::Local {
+            let register = self.scope().locals[target.index];
+            if source != target {
+                self.load_symbol(&source, register)?;
+            }
+            self.emit(RegisterOpcode::SetIndex, &[register, first, depth, value])?;
+        } else {
+            if source != target {
+                let register = self.allocate_registers(1)?;
+                self.load_symbol(&source, register)?;
+                self.emit(RegisterOpcode::SetGlobal, &[target.index, register])?;
+            }
+            self.emit(
+                RegisterOpcode::SetIndexGlobal,
+                &[target.index, first, depth, value],
+            )?;
+        }
+        Ok(())
+    }
+
+    fn compile_while_statement(&mut self, statement: &WhileStatement) -> Result<(), String> {
+        let start = self.scope().instructions.len();
+        self.scope_mut().loops.push(LoopScope {
+            start,
+            breaks: Vec::new(),
+        });
+
+        let mark = self.scope().next_register;
+        let condition = self.compile_operand(&statement.condition)?;
+        let exit = self.emit(RegisterOpcode::JumpNotTruthy, &[condition, 0])?;
+        self.free_registers(mark);
+        self.compile_block_statement(&statement.body, None)?;
+        self.emit(RegisterOpcode::Jump, &[start])?;
+
+        let end = self.scope().instructions.len();
+        self.change_jump(exit, end)?;
+        let scope = self.scope_mut().loops.pop().expect("the loop was pushed");
+        for jump in scope.breaks {
+            self.change_jump(jump, end)?;
+        }
+        Ok(())
+    }
+
+    fn compile_loop_statement(&mut self, statement: &LoopStatement) -> Result<(), String> {
+        let Some(start) = self.scope().loops.last().map(|scope| scope.start) else {
+            return Err(format!("`{statement}` outside of a loop"));
+        };
+        match statement {
+            LoopStatement::Break => {
+                let jump = self.emit(RegisterOpcode::Jump, &[0])?;
+                if let Some(scope) = self.scope_mut().loops.last_mut() {
+                    scope.breaks.push(jump);
+                }
+            }
+            LoopStatement::Continue => {
+                self.emit(RegisterOpcode::Jump, &[start])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Compiles an expression to the register holding its value: the register
+    /// of a local variable, read in place, or a new temporary.
+    fn compile_operand(&mut self, expression: &Expression) -> Result<usize, String> {
+        if let Expression::Identifier(identifier) = expression {
+            if let Some(symbol) = self.symbol_table.resolve(&identifier.value) {
+                if symbol.scope == SymbolScope::Local {
+                    return Ok(self.scope().locals[symbol.index]);
+                }
+            }
+        }
+        let register = self.allocate_registers(1)?;
+        self.compile_expression(expression, register)?;
+        Ok(register)
+    }
+
+    /// Compiles the left operand of an operation like [`compile_operand`],
+    /// unless evaluating `rest` can assign its local, which must then be read
+    /// first.
+    ///
+    /// [`compile_operand`]: RegisterCompiler::compile_operand
+    fn compile_left_operand(
+        &mut self,
+        expression: &Expression,
+        rest: &[&Expression],
+    ) -> Result<usize, String> {
+        if rest.iter().any(|expression| has_block(expression)) {
+            let register = self.allocate_registers(1)?;
+            self.compile_expression(expression, register)?;
+            return Ok(register);
+        }
+        self.compile_operand(expression)
+    }
+
+    /// Compiles an expression, writing its value to `dst`.
+    fn compile_expression(&mut self, expression: &Expression, dst: usize) -> Result<(), String> {
+        let mark = self.scope().next_register;
+        match expression {
+            Expression::Infix(infix) => {
+                // `a < b` is `b > a`, the operands are still evaluated in order
+                let (op, swapped) = match &infix.token {
+                    Token::Plus => (RegisterOpcode::Add, false),
+                    Token::Minus => (RegisterOpcode::Sub, false),
+                    Token::Asterisk => (RegisterOpcode::Mul, false),
+                    Token::Slash => (RegisterOpcode::Div, false),
+                    Token::Modulo => (RegisterOpcode::Modulo, false),
+                    Token::Or => (RegisterOpcode::Or, false),
+                    Token::And => (RegisterOpcode::And, false),
+                    Token::Equal => (RegisterOpcode::Equal, false),
+                    Token::NotEqual => (RegisterOpcode::NotEqual, false),
+                    Token::GT => (RegisterOpcode::GreaterThan, false),
+                    Token::GTE => (RegisterOpcode::GreaterEqualThan, false),
+                    Token::LT => (RegisterOpcode::GreaterThan, true),
+                    Token::LTE => (RegisterOpcode::GreaterEqualThan, true),
+                    token => return Err(format!("Unknown operator: {token}")),
+                };
+                let left = self.compile_left_operand(&infix.left, &[&infix.right])?;
+                let right = self.compile_operand(&infix.right)?;
+                let (left, right) = if swapped {
+                    (right, left)
+                } else {
+                    (left, right)
+                };
+                self.emit(op, &[dst, left, right])?;
+            }
+            Expression::Prefix(prefix) => {
+                let op = match &prefix.token {
+                    Token::Bang => RegisterOpcode::Bang,
+                    Token::Minus => RegisterOpcode::Minus,
+                    token => return Err(format!("Unknown operator: {token}")),
+                };
+                let operand = self.compile_operand(&prefix.right)?;
+                self.emit(op, &[dst, operand])?;
+            }
+            Expression::Primitive(primitive) => self.compile_primitive(primitive, dst)?,
+            Expression::Conditional(conditional) => self.compile_conditional(conditional, dst)?,
+            Expression::Identifier(identifier) => {
+                match self.symbol_table.resolve(&identifier.value) {
+                    Some(symbol) => self.load_symbol(&symbol, dst)?,
+                    None if self.late_globals => {
+                        let constant = self.add_string_constant(&identifier.value);
+                        self.emit(RegisterOpcode::GetGlobalByName, &[dst, constant])?;
+                    }
+                    None => return Err(format!("Undefined variable: {}", identifier.value)),
+                }
+            }
+            Expression::ArrayLiteral(array) => {
+                let count = array.elements.len();
+                let first = self.allocate_registers(count)?;
+                for (i, element) in array.elements.iter().enumerate() {
+                    self.compile_expression(element, first + i)?;
+                }
+                self.emit(RegisterOpcode::Array, &[dst, first, count])?;
+            }
+            Expression::HashMapLiteral(hashmap) => {
+                let count = 2 * hashmap.pairs.len();
+                let first = self.allocate_registers(count)?;
+                for (i, (key, value)) in hashmap.pairs.iter().enumerate() {
+                    self.compile_expression(key, first + 2 * i)?;
+                    self.compile_expression(value, first + 2 * i + 1)?;
+                }
+                self.emit(RegisterOpcode::HashMap, &[dst, first, count])?;
+            }
+            Expression::IndexExpression(index) => {
+                let left = self.compile_left_operand(&index.left, &[&index.index])?;
+                let index = self.compile_operand(&index.index)?;
+                self.emit(RegisterOpcode::Index, &[dst, left, index])?;
+            }
+            Expression::FunctionLiteral(function) => {
+                self.compile_function_literal(function, dst)?;
+            }
+            Expression::FunctionCall(call) => {
+                let arguments: Vec<&Expression> = call.arguments.iter().collect();
+                let callee = self.compile_left_operand(&call.function, &arguments)?;
+                let count = call.arguments.len();
+                let first = self.allocate_registers(count)?;
+                for (i, argument) in call.arguments.iter().enumerate() {
+                    self.compile_expression(argument, first + i)?;
+                }
+                self.emit(RegisterOpcode::Call, &[dst, callee, first, count])?;
+            }
+        }
+        self.free_registers(mark);
+        Ok(())
+    }
+
+    fn compile_primitive(&mut self, primitive: &Primitive, dst: usize) -> Result<(), String> {
+        let constant = match primitive {
+            Primitive::BooleanLiteral(true) => {
+                self.emit(RegisterOpcode::LoadTrue, &[dst])?;
+                return Ok(());
+            }
+            Primitive::BooleanLiteral(false) => {
+                self.emit(RegisterOpcode::LoadFalse, &[dst])?;
+                return Ok(());
+            }
+            Primitive::IntegerLiteral(i) => self.add_constant(Object::INTEGER(*i)),
+            Primitive::StringLiteral(s) => self.add_string_constant(s),
+            Primitive::BytesLiteral(s) => self.add_constant(Object::BYTES(s.clone().into_bytes())),
+        };
+        self.emit(RegisterOpcode::LoadConstant, &[dst, constant])?;
+        Ok(())
+    }
+
+    fn compile_conditional(&mut self, conditional: &Conditional, dst: usize) -> Result<(), String> {
+        let mark = self.scope().next_register;
+        let condition = self.compile_operand(&conditional.condition)?;
+        let to_alternative = self.emit(RegisterOpcode::JumpNotTruthy, &[condition, 0])?;
+        self.free_registers(mark);
+
+        self.compile_block_statement(&conditional.consequence, Some(dst))?;
+        let to_end = self.emit(RegisterOpcode::Jump, &[0])?;
+
+        let alternative = self.scope().instructions.len();
+        self.change_jump(to_alternative, alternative)?;
+        match &conditional.alternative {
+            Some(block) => self.compile_block_statement(block, Some(dst))?,
+            None => {
+                self.emit(RegisterOpcode::LoadNull, &[dst])?;
+            }
+        }
+
+        let end = self.scope().instructions.len();
+        self.change_jump(to_end, end)
+    }
+
+    fn compile_function_literal(
+        &mut self,
+        function: &FunctionLiteral,
+        dst: usize,
+    ) -> Result<(), String> {
+        self.scopes.push(FunctionScope::new(0));
+        self.symbol_table =
+            SymbolTable::new_enclosed(Rc::new(RefCell::new(self.symbol_table.clone())));
+
+        if let Some(name) = &function.name {
+            self.symbol_table.define_function_name(name.clone());
+        }
+        let num_parameters = function.parameters.len();
+        for parameter in &function.parameters {
+            let symbol = self.symbol_table.define(parameter.value.to_string());
+            self.allocate_local(&symbol)?;
+        }
+
+        let result = self.allocate_registers(1)?;
+        self.compile_block_statement(&function.body, Some(result))?;
+        self.emit(RegisterOpcode::Return, &[result])?;
+
+        let free_symbols = self.symbol_table.free_symbols.clone();
+        let scope = self.scopes.pop().expect("the scope was pushed");
+        self.symbol_table = self
+            .symbol_table
+            .outer
+            .take()
+            .expect("the symbol table was enclosed")
+            .as_ref()
+            .clone()
+            .into_inner();
+
+        let num_free = free_symbols.len();
+        let first = self.allocate_registers(num_free)?;
+        for (i, symbol) in free_symbols.iter().enumerate() {
+            self.load_symbol(symbol, first + i)?;
+        }
+
+        let constant = self.add_constant(Object::COMPILEDFUNCTION(Rc::new(CompiledFunction {
+            instructions: scope.instructions,
+            num_locals: scope.num_registers,
+            num_parameters,
+            name: function.name.clone(),
+            source_map: SourceMap::new(scope.source_map),
+        })));
+        self.emit(RegisterOpcode::Closure, &[dst, constant, first, num_free])?;
+        Ok(())
+    }
+
+    fn load_symbol(&mut self, symbol: &Symbol, dst: usize) -> Result<(), String> {
+        match symbol.scope {
+            SymbolScope::Global => self.emit(RegisterOpcode::GetGlobal, &[dst, symbol.index])?,
+            SymbolScope::Local => {
+                let register = self.scope().locals[symbol.index];
+                if register == dst {
+                    return Ok(());
+                }
+                self.emit(RegisterOpcode::Move, &[dst, register])?
+            }
+            SymbolScope::Builtin => self.emit(RegisterOpcode::GetBuiltin, &[dst, symbol.index])?,
+            SymbolScope::Free => self.emit(RegisterOpcode::GetFree, &[dst, symbol.index])?,
+            SymbolScope::Function => self.emit(RegisterOpcode::CurrentClosure, &[dst])?,
+        };
+        Ok(())
+    }
+
+    /// Returns the first of `count` consecutive new registers.
+    fn allocate_registers(&mut self, count: usize) -> Result<usize, String> {
+        let scope = self.scope_mut();
+        let first = scope.next_register;
+        scope.next_register += count;
+        scope.num_registers = scope.num_registers.max(scope.next_register);
+        if scope.num_registers > usize::from(u16::MAX) + 1 {
+            return Err(format!(
+                "Too many registers, a function can use at most {}",
+                usize::from(u16::MAX) + 1
+            ));
+        }
+        Ok(first)
+    }
+
+    /// Frees the temporaries allocated since `mark`.
+    fn free_registers(&mut self, mark: usize) {
+        let scope = self.scope_mut();
+        scope.next_register = mark.max(scope.first_temporary);
+    }
+
+    fn add_constant(&mut self, object: Object) -> usize {
+        self.constants.push(object);
+        self.constants.len() - 1
+    }
+
+    /// Adds a string constant, or returns the one with the same string.
+    fn add_string_constant(&mut self, s: &Rc<str>) -> usize {
+        if let Some(constant) = self.string_constants.get(s) {
+            *constant
+        } else {
+            let constant = self.add_constant(Object::STRING(Rc::clone(s)));
+            self.string_constants.insert(Rc::clone(s), constant);
+            constant
+        }
+    }
+
+    /// Emits an instruction and returns its position.
+    fn emit(&mut self, op: RegisterOpcode, operands: &[usize]) -> Result<usize, String> {
+        let instruction = op.make(operands)?;
+        let instructions = &mut self.scope_mut().instructions;
+        let position = instructions.len();
+        instructions.extend(instruction);
+        Ok(position)
+    }
+
+    /// Sets the target of the jump at `position`.
+    fn change_jump(&mut self, position: usize, target: usize) -> Result<(), String> {
+        let instructions = &mut self.scope_mut().instructions;
+        let op = match instructions[position] {
+            byte if byte == RegisterOpcode::Jump as u8 => RegisterOpcode::Jump,
+            _ => RegisterOpcode::JumpNotTruthy,
+        };
+        let mut operands = op.read_operands(&instructions[position + 1..]);
+        let jump_operand = op.jump_operand().expect("the instruction is a jump");
+        operands[jump_operand] = target;
+        let instruction = op.make(&operands)?;
+        instructions[position..position + instruction.len()].copy_from_slice(&instruction);
+        Ok(())
+    }
+}
+
+/// Returns whether evaluating the expression runs a block, which can assign
+/// variables. The bodies of functions only run when they are called.
+fn has_block(expression: &Expression) -> bool {
+    match expression {
+        Expression::Conditional(_) => true,
+        Expression::Identifier(_) | Expression::Primitive(_) | Expression::FunctionLiteral(_) => {
+            false
+        }
+        Expression::Prefix(prefix) => has_block(&prefix.right),
+        Expression::Infix(infix) => has_block(&infix.left) || has_block(&infix.right),
+        Expression::ArrayLiteral(array) => array.elements.iter().any(has_block),
+        Expression::HashMapLiteral(hashmap) => hashmap
+            .pairs
+            .iter()
+            .any(|(key, value)| has_block(key) || has_block(value)),
+        Expression::IndexExpression(index) => has_block(&index.left) || has_block(&index.index),
+        Expression::FunctionCall(call) => {
+            has_block(&call.function) || call.arguments.iter().any(has_block)
+        }
+    }
+}
@@ -0,0 +1,720 @@
+//! An experimental register machine, to compare against the stack VM.
+//!
+//! Programs are compiled by [`RegisterCompiler`] to instructions naming the
+//! registers they read and write, so that `a + b` with two locals is one
+//! instruction instead of four. Every call gets a window of registers above the
+//! one of its caller, starting with its parameters and locals. The parser, the
+//! objects and the builtins are the same as for the stack VM.
+
+pub mod code;
+pub mod compiler;
+mod register_tests;
+
+use std::{collections::HashMap, io::BufRead, rc::Rc, time::Duration};
+
+use num_traits::FromPrimitive;
+
+use crate::{
+    compiler::{
+        code::{read_u16, read_u32},
+        symbol_table::{SymbolScope, SymbolTable},
+    },
+    object::{
+        builtins::{BuiltinContext, BuiltinFunction},
+        cache,
+        deadline::Deadline,
+        max_depth_error,
+        random::Random,
+        style::OutputStyle,
+        Closure, CompiledFunction, Object, StackFrame, DEFAULT_MAX_DEPTH, FUEL_EXHAUSTED, NULL,
+    },
+    parser::ast::Program,
+    source::{Source, SourceMap},
+    vm::{
+        new_global_store,
+        register::{
+            code::RegisterOpcode,
+            compiler::{RegisterBytecode, RegisterCompiler},
+        },
+        value::Value,
+        DEFAULT_MAX_STACK_SIZE, GLOBALS_SIZE,
+    },
+};
+
+struct Frame {
+    /// The closure running, always an `Object::CLOSURE`.
+    function: Rc<Object>,
+    ip: usize,
+    /// First register of the window of the function.
+    base: usize,
+    /// Register of the caller the result is written to, `None` when it is
+    /// returned to the VM itself: for the main program and the functions
+    /// called by builtins.
+    return_register: Option<usize>,
+}
+
+impl Frame {
+    fn closure(&self) -> &Closure {
+        match self.function.as_ref() {
+            Object::CLOSURE(closure) => closure,
+            _ => unreachable!("frames only run closures"),
+        }
+    }
+}
+
+pub struct RegisterVM {
+    constants: Vec<Rc<Object>>,
+
+    /// Windows of all the running functions, it grows up to `max_registers`.
+    registers: Vec<Rc<Object>>,
+    max_registers: usize,
+
+    pub globals: Vec<Value>,
+
+    /// Global symbols of the program, extended by the definitions of `eval`.
+    symbol_table: SymbolTable,
+
+    frames: Vec<Frame>,
+
+    /// Number of nested calls, the main frame excluded.
+    max_depth: usize,
+
+    /// Number of instructions left to run, unlimited if it is `None`.
+    fuel: Option<u64>,
+
+    deadline: Option<Deadline>,
+
+    /// Value of the last function which returned to the VM.
+    returned: Rc<Object>,
+
+    /// Error raised while running a function called from a builtin, it is
+    /// returned once the builtin is done.
+    builtin_error: Option<String>,
+
+    /// Code given to the `exit` builtin, the execution stops once it is set.
+    exit_code: Option<i64>,
+
+    // The shared objects of the cache, kept at hand
+    null: Rc<Object>,
+    true_object: Rc<Object>,
+    false_object: Rc<Object>,
+
+    source: Option<Source>,
+
+    output_style: OutputStyle,
+
+    /// Reader used by the input builtins, the standard input if it is `None`.
+    input: Option<Box<dyn BufRead>>,
+
+    random: Random,
+
+    /// Arguments of the program, returned by `argv`.
+    args: Vec<String>,
+}
+
+impl RegisterVM {
+    pub fn new(bytecode: RegisterBytecode) -> Self {
+        let null = cache::null();
+        let registers = vec![Rc::clone(&null); bytecode.main.num_locals];
+        let main = Rc::new(Object::CLOSURE(Closure::new(Rc::new(bytecode.main))));
+        let mut vm = Self {
+            constants: bytecode.constants.into_iter().map(cache::share).collect(),
+
+            registers,
+            max_registers: DEFAULT_MAX_STACK_SIZE,
+
+            globals: new_global_store(GLOBALS_SIZE),
+            symbol_table: bytecode.symbol_table,
+
+            frames: Vec::with_capacity(DEFAULT_MAX_DEPTH),
+            max_depth: DEFAULT_MAX_DEPTH,
+            fuel: None,
+            deadline: None,
+
+            returned: Rc::clone(&null),
+            builtin_error: None,
+            exit_code: None,
+
+            null,
+            true_object: cache::boolean(true),
+            false_object: cache::boolean(false),
+
+            source: bytecode.source,
+
+            output_style: OutputStyle::default(),
+            input: None,
+            random: Random::default(),
+            args: Vec::new(),
+        };
+        vm.frames.push(Frame {
+            function: main,
+            ip: 0,
+            base: 0,
+            return_register: None,
+        });
+        vm
+    }
+
+    /// Creates a VM using `globals` as its global store, like
+    /// [`VM::new_with_global_store`].
+    ///
+    /// [`VM::new_with_global_store`]: crate::vm::VM::new_with_global_store
+    pub fn new_with_global_store(bytecode: RegisterBytecode, globals: Vec<Value>) -> Self {
+        let mut vm = Self::new(bytecode);
+        vm.globals = globals;
+        vm
+    }
+
+    pub fn set_output_style(&mut self, output_style: OutputStyle) {
+        self.output_style = output_style;
+    }
+
+    pub fn output_style(&self) -> &OutputStyle {
+        &self.output_style
+    }
+
+    /// Makes `input` and `read_all` read from `input` instead of the standard
+    /// input.
+    pub fn set_input(&mut self, input: Box<dyn BufRead>) {
+        self.input = Some(input);
+    }
+
+    /// Sets the number of registers the windows of the running functions can
+    /// use together, a program needing more fails with a stack overflow.
+    pub fn set_max_registers(&mut self, max_registers: usize) {
+        self.max_registers = max_registers;
+    }
+
+    /// Sets the number of nested calls a program can make, a deeper call fails
+    /// with an error naming the function.
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    /// Limits the number of instructions the program can run, including those
+    /// of `eval`.
+    pub fn set_fuel(&mut self, fuel: u64) {
+        self.fuel = Some(fuel);
+    }
+
+    /// Makes the program fail if it is still running `timeout` from now.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.deadline = Some(Deadline::after(timeout));
+    }
+
+    /// Sets the arguments returned by `argv`.
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.args = args;
+    }
+
+    /// Returns the code the program exited with, if it called `exit`.
+    pub fn exit_code(&self) -> Option<i64> {
+        self.exit_code
+    }
+
+    /// Returns the source of the running program, if it is known.
+    pub fn source(&self) -> Option<&Source> {
+        self.source.as_ref()
+    }
+
+    /// Returns the value of the last statement of the program once it ran, if
+    /// it is an expression, null otherwise.
+    pub fn result(&self) -> Rc<Object> {
+        Rc::clone(&self.returned)
+    }
+
+    /// Runs the program to the end, or until it exits.
+    ///
+    /// # Errors
+    ///
+    /// If the program fails.
+    pub fn run(&mut self) -> Result<(), String> {
+        self.execute_until(0)
+    }
+
+    /// Executes instructions until the number of active frames drops to
+    /// `depth`, or until the program exits.
+    fn execute_until(&mut self, depth: usize) -> Result<(), String> {
+        while self.frames.len() > depth && self.exit_code.is_none() {
+            if let Some(fuel) = &mut self.fuel {
+                if *fuel == 0 {
+                    return Err(FUEL_EXHAUSTED.to_string());
+                }
+                *fuel -= 1;
+            }
+            if let Some(deadline) = &mut self.deadline {
+                deadline.check()?;
+            }
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    /// Returns the function calls running, from the main program to the
+    /// running function, like [`VM::stack_trace`].
+    ///
+    /// [`VM::stack_trace`]: crate::vm::VM::stack_trace
+    pub fn stack_trace(&self) -> Vec<StackFrame> {
+        self.frames
+            .iter()
+            .enumerate()
+            .map(|(index, frame)| {
+                let function = &frame.closure().function;
+                StackFrame {
+                    function: match (index, &function.name) {
+                        (0, _) => "main".to_string(),
+                        (_, Some(name)) => name.clone(),
+                        (_, None) => "<anonymous>".to_string(),
+                    },
+                    // The instruction pointer is already past the instruction
+                    position: function.source_map.position(frame.ip.saturating_sub(1)),
+                }
+            })
+            .collect()
+    }
+
+    #[allow(clippy::too_many_lines)]
+    fn step(&mut self) -> Result<(), String> {
+        let frame = self.frames.last_mut().ok_or("There was no frame")?;
+        let function = Rc::clone(&frame.function);
+        let base = frame.base;
+        let ip = frame.ip;
+        let ins = match function.as_ref() {
+            Object::CLOSURE(closure) => &closure.function.instructions,
+            _ => unreachable!("frames only run closures"),
+        };
+        let op = RegisterOpcode::from_u8(ins[ip]).ok_or(format!("Unknown opcode {}", ins[ip]))?;
+        frame.ip = ip + op.size();
+        // The register operands, relative to the window of the function
+        let r = |i: usize| base + read_u16(&ins[ip + 1 + 2 * i..]) as usize;
+        let operand = |i: usize| read_u16(&ins[ip + 1 + 2 * i..]) as usize;
+
+        match op {
+            RegisterOpcode::LoadConstant => {
+                self.registers[r(0)] = Rc::clone(&self.constants[operand(1)]);
+            }
+            RegisterOpcode::LoadTrue => self.registers[r(0)] = Rc::clone(&self.true_object),
+            RegisterOpcode::LoadFalse => self.registers[r(0)] = Rc::clone(&self.false_object),
+            RegisterOpcode::LoadNull => self.registers[r(0)] = Rc::clone(&self.null),
+            RegisterOpcode::Move => self.registers[r(0)] = Rc::clone(&self.registers[r(1)]),
+            RegisterOpcode::GetGlobal => {
+                let value = self.global(operand(1))?.clone().into_rc();
+                self.registers[r(0)] = value;
+            }
+            RegisterOpcode::GetGlobalByName => {
+                let value = self.global_by_name(operand(1))?.clone().into_rc();
+                self.registers[r(0)] = value;
+            }
+            RegisterOpcode::SetGlobal => {
+                let value = Value::from(Rc::clone(&self.registers[r(1)]));
+                *self.global(operand(0))? = value;
+            }
+            RegisterOpcode::GetBuiltin => {
+                let builtin = BuiltinFunction::get_builtin_by_id(operand(1))
+                    .ok_or(format!("Unknown builtin function id {}", operand(1)))?;
+                self.registers[r(0)] = Rc::new(builtin);
+            }
+            RegisterOpcode::GetFree => {
+                let free = match function.as_ref() {
+                    Object::CLOSURE(closure) => closure.free[operand(1)].clone(),
+                    _ => unreachable!("frames only run closures"),
+                };
+                self.registers[r(0)] = self.share(free);
+            }
+            RegisterOpcode::CurrentClosure => self.registers[r(0)] = Rc::clone(&function),
+            RegisterOpcode::Add
+            | RegisterOpcode::Sub
+            | RegisterOpcode::Mul
+            | RegisterOpcode::Div
+            | RegisterOpcode::Modulo
+            | RegisterOpcode::Or
+            | RegisterOpcode::And => {
+                let result = binary_operation(op, &self.registers[r(1)], &self.registers[r(2)])?;
+                self.registers[r(0)] = self.share(result);
+            }
+            RegisterOpcode::Equal
+            | RegisterOpcode::NotEqual
+            | RegisterOpcode::GreaterThan
+            | RegisterOpcode::GreaterEqualThan => {
+                let result = comparison(op, &self.registers[r(1)], &self.registers[r(2)])?;
+                self.registers[r(0)] = self.boolean(result);
+            }
+            RegisterOpcode::Minus => match self.registers[r(1)].as_ref() {
+                Object::INTEGER(i) => self.registers[r(0)] = cache::share(Object::INTEGER(-i)),
+                _ => return Err("Unsupported type for minus operation".to_string()),
+            },
+            RegisterOpcode::Bang => {
+                let result = !self.registers[r(1)].is_truthy();
+                self.registers[r(0)] = self.boolean(result);
+            }
+            RegisterOpcode::Jump => self.jump(read_u32(&ins[ip + 1..]) as usize),
+            RegisterOpcode::JumpNotTruthy => {
+                if !self.registers[r(0)].is_truthy() {
+                    self.jump(read_u32(&ins[ip + 3..]) as usize);
+                }
+            }
+            RegisterOpcode::Array => {
+                let elements = self.registers[r(1)..r(1) + operand(2)]
+                    .iter()
+                    .map(|element| element.as_ref().clone())
+                    .collect();
+                self.registers[r(0)] = Rc::new(Object::ARRAY(elements));
+            }
+            RegisterOpcode::HashMap => {
+                #[allow(clippy::mutable_key_type)]
+                let mut pairs = HashMap::new();
+                for pair in self.registers[r(1)..r(1) + operand(2)].chunks(2) {
+                    if !pair[0].is_hashable() {
+                        return Err(format!("Unusable as hashmap key: {}", pair[0].get_type()));
+                    }
+                    pairs.insert(pair[0].as_ref().clone(), pair[1].as_ref().clone());
+                }
+                self.registers[r(0)] = Rc::new(Object::HASHMAP(pairs));
+            }
+            RegisterOpcode::Index => {
+                let result = index(&self.registers[r(1)], &self.registers[r(2)])?;
+                self.registers[r(0)] = self.share(result);
+            }
+            RegisterOpcode::SetIndex | RegisterOpcode::SetIndexGlobal => {
+                let indexes: Vec<Object> = self.registers[r(1)..r(1) + operand(2)]
+                    .iter()
+                    .map(|index| index.as_ref().clone())
+                    .collect();
+                let value = self.registers[r(3)].as_ref().clone();
+                // The collection is only copied if something else holds it
+                if op == RegisterOpcode::SetIndex {
+                    Rc::make_mut(&mut self.registers[r(0)])
+                        .set_index(&indexes, value)
+                        .map_err(|error| error.message)?;
+                } else {
+                    let global = self.global(operand(0))?;
+                    let mut collection = std::mem::replace(global, Value::Null).into_rc();
+                    let result = Rc::make_mut(&mut collection).set_index(&indexes, value);
+                    *self.global(operand(0))? = Value::from(collection);
+                    result.map_err(|error| error.message)?;
+                }
+            }
+            RegisterOpcode::Call => {
+                let callee = Rc::clone(&self.registers[r(1)]);
+                match callee.as_ref() {
+                    Object::CLOSURE(_) => {
+                        self.call_closure(callee, r(2), operand(3), Some(r(0)))?;
+                    }
+                    Object::BUILTIN(builtin) => {
+                        let args = self.registers[r(2)..r(2) + operand(3)]
+                            .iter()
+                            .map(|arg| arg.as_ref().clone())
+                            .collect();
+                        let result = self.call_builtin(builtin, args)?;
+                        self.registers[r(0)] = self.share(result);
+                    }
+                    _ => return Err("Calling non-function".to_string()),
+                }
+            }
+            RegisterOpcode::Return => {
+                let value = Rc::clone(&self.registers[r(0)]);
+                let frame = self.frames.pop().ok_or("There was no frame")?;
+                match frame.return_register {
+                    Some(register) => self.registers[register] = value,
+                    None => self.returned = value,
+                }
+            }
+            RegisterOpcode::Closure => {
+                let Object::COMPILEDFUNCTION(function) = self.constants[operand(1)].as_ref() else {
+                    return Err(format!(
+                        "Function expected, got {}",
+                        self.constants[operand(1)].get_type()
+                    ));
+                };
+                let mut closure = Closure::new(function.clone());
+                closure.extend_free_varaibles(
+                    self.registers[r(2)..r(2) + operand(3)]
+                        .iter()
+                        .map(|free| free.as_ref().clone())
+                        .collect(),
+                );
+                self.registers[r(0)] = Rc::new(Object::CLOSURE(closure));
+            }
+        }
+        Ok(())
+    }
+
+    fn jump(&mut self, target: usize) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.ip = target;
+        }
+    }
+
+    /// Returns the first register above the window of the running function.
+    fn next_window(&self) -> usize {
+        self.frames
+            .last()
+            .map_or(0, |frame| frame.base + frame.closure().function.num_locals)
+    }
+
+    /// Makes the registers at least `size` long, the new ones are `null`.
+    fn grow_registers(&mut self, size: usize) -> Result<(), String> {
+        if size > self.max_registers {
+            return Err(format!(
+                "Stack overflow: the registers are limited to {} slots",
+                self.max_registers
+            ));
+        }
+        if size > self.registers.len() {
+            let null = Rc::clone(&self.null);
+            self.registers.resize(size, null);
+        }
+        Ok(())
+    }
+
+    /// Calls `function`, a closure, with the `count` arguments starting at the
+    /// register `first`, in a new window above the running function.
+    fn call_closure(
+        &mut self,
+        function: Rc<Object>,
+        first: usize,
+        count: usize,
+        return_register: Option<usize>,
+    ) -> Result<(), String> {
+        let Object::CLOSURE(closure) = function.as_ref() else {
+            return Err("Calling non-function".to_string());
+        };
+        let compiled = &closure.function;
+        if count != compiled.num_parameters {
+            return Err(format!(
+                "Wrong number of arguments: want={}, got={}",
+                compiled.num_parameters, count
+            ));
+        }
+        if self.frames.len() > self.max_depth {
+            return Err(max_depth_error(self.max_depth, compiled.name.as_deref()));
+        }
+
+        let base = self.next_window();
+        self.grow_registers(base + compiled.num_locals)?;
+        for i in 0..count {
+            self.registers[base + i] = Rc::clone(&self.registers[first + i]);
+        }
+        for register in &mut self.registers[base + count..base + compiled.num_locals] {
+            *register = Rc::clone(&self.null);
+        }
+        self.frames.push(Frame {
+            function,
+            ip: 0,
+            base,
+            return_register,
+        });
+        Ok(())
+    }
+
+    fn call_builtin(
+        &mut self,
+        builtin: &BuiltinFunction,
+        args: Vec<Object>,
+    ) -> Result<Object, String> {
+        let result = builtin.call(args, self);
+        if let Some(error) = self.builtin_error.take() {
+            return Err(error);
+        }
+        if let Object::EXIT(code) = result {
+            self.exit_code = Some(code);
+        }
+        // The errors of the other builtins are values, a failed assertion or
+        // exit must stop the program
+        if let (BuiltinFunction::ASSERT | BuiltinFunction::EXIT, Object::ERROR(error)) =
+            (builtin, &result)
+        {
+            return Err(error.message.clone());
+        }
+        Ok(result)
+    }
+
+    /// Calls a function from outside of the run loop and runs it to completion.
+    fn call_function(&mut self, function: &Object, args: Vec<Object>) -> Result<Object, String> {
+        match function {
+            Object::CLOSURE(_) => {
+                let depth = self.frames.len();
+                // The arguments are written where the window of the function
+                // starts, which is where they are copied to
+                let first = self.next_window();
+                self.grow_registers(first + args.len())?;
+                let count = args.len();
+                for (i, arg) in args.into_iter().enumerate() {
+                    self.registers[first + i] = cache::share(arg);
+                }
+                self.call_closure(Rc::new(function.clone()), first, count, None)?;
+                self.execute_until(depth)?;
+                if let Some(code) = self.exit_code {
+                    // The function did not return, it exited the whole program
+                    return Ok(Object::EXIT(code));
+                }
+                Ok(self.returned.as_ref().clone())
+            }
+            Object::BUILTIN(builtin) => Ok(builtin.call(args, self)),
+            _ => Err("Calling non-function".to_string()),
+        }
+    }
+
+    /// Returns the value in an `Rc`, shared with the other registers for the
+    /// small integers, the booleans and null.
+    fn share(&self, object: Object) -> Rc<Object> {
+        match object {
+            Object::BOOLEAN(b) => self.boolean(b),
+            Object::NULL => Rc::clone(&self.null),
+            object => cache::share(object),
+        }
+    }
+
+    fn boolean(&self, value: bool) -> Rc<Object> {
+        if value {
+            Rc::clone(&self.true_object)
+        } else {
+            Rc::clone(&self.false_object)
+        }
+    }
+
+    fn global(&mut self, index: usize) -> Result<&mut Value, String> {
+        let size = self.globals.len();
+        self.globals
+            .get_mut(index)
+            .ok_or_else(|| format!("Global {index} is out of the {size} globals of the VM"))
+    }
+
+    /// Returns the global named by the string constant `constant`, defined by
+    /// a call to `eval` after the program was compiled.
+    fn global_by_name(&mut self, constant: usize) -> Result<&mut Value, String> {
+        let Object::STRING(name) = self.constants[constant].as_ref() else {
+            return Err(format!("Constant {constant} is not the name of a global"));
+        };
+        match self.symbol_table.resolve(name) {
+            Some(symbol) if symbol.scope == SymbolScope::Global => self.global(symbol.index),
+            _ => Err(format!("Undefined variable: {name}")),
+        }
+    }
+}
+
+fn binary_operation(op: RegisterOpcode, left: &Object, right: &Object) -> Result<Object, String> {
+    let result = match (left, right) {
+        (Object::INTEGER(left), Object::INTEGER(right)) => match op {
+            RegisterOpcode::Add => left + right,
+            RegisterOpcode::Sub => left - right,
+            RegisterOpcode::Mul => left * right,
+            RegisterOpcode::Div | RegisterOpcode::Modulo if *right == 0 => {
+                return Err("Division by zero".to_string())
+            }
+            RegisterOpcode::Div => left / right,
+            RegisterOpcode::Modulo => left % right,
+            _ => return Err("Unsupported types for binary operation".to_string()),
+        },
+        (Object::BOOLEAN(left), Object::BOOLEAN(right)) => match op {
+            RegisterOpcode::Or => return Ok(Object::BOOLEAN(*left || *right)),
+            RegisterOpcode::And => return Ok(Object::BOOLEAN(*left && *right)),
+            _ => return Err("Unsupported types for binary operation".to_string()),
+        },
+        (Object::STRING(left), Object::STRING(right)) if op == RegisterOpcode::Add => {
+            return Ok(Object::STRING([&**left, &**right].concat().into()))
+        }
+        _ => return Err("Unsupported types for binary operation".to_string()),
+    };
+    Ok(Object::INTEGER(result))
+}
+
+fn comparison(op: RegisterOpcode, left: &Object, right: &Object) -> Result<bool, String> {
+    match (left, right, op) {
+        (Object::INTEGER(left), Object::INTEGER(right), _) => match op {
+            RegisterOpcode::Equal => Ok(left == right),
+            RegisterOpcode::NotEqual => Ok(left != right),
+            RegisterOpcode::GreaterThan => Ok(left > right),
+            _ => Ok(left >= right),
+        },
+        (Object::BOOLEAN(left), Object::BOOLEAN(right), RegisterOpcode::Equal) => Ok(left == right),
+        (Object::BOOLEAN(left), Object::BOOLEAN(right), RegisterOpcode::NotEqual) => {
+            Ok(left != right)
+        }
+        _ => Err("Unsupported types for comparison".to_string()),
+    }
+}
+
+fn index(left: &Object, index: &Object) -> Result<Object, String> {
+    match (left, index) {
+        (Object::ARRAY(elements), Object::INTEGER(i)) => Ok(usize::try_from(*i)
+            .ok()
+            .and_then(|i| elements.get(i))
+            .cloned()
+            .unwrap_or(NULL)),
+        (Object::BYTES(bytes), Object::INTEGER(i)) => Ok(usize::try_from(*i)
+            .ok()
+            .and_then(|i| bytes.get(i))
+            .map_or(NULL, |byte| Object::INTEGER(i64::from(*byte)))),
+        (Object::HASHMAP(pairs), _) => {
+            if !index.is_hashable() {
+                return Err("Unusable as hashmap key".to_string());
+            }
+            Ok(pairs.get(index).cloned().unwrap_or(NULL))
+        }
+        _ => Err("Unsupported types for index operation".to_string()),
+    }
+}
+
+impl BuiltinContext for RegisterVM {
+    fn apply(&mut self, function: &Object, args: Vec<Object>) -> Object {
+        match self.call_function(function, args) {
+            Ok(result) => result,
+            Err(error) => {
+                let object = Object::error(error.clone());
+                self.builtin_error = Some(error);
+                object
+            }
+        }
+    }
+
+    fn output_style(&self) -> &OutputStyle {
+        &self.output_style
+    }
+
+    fn input(&mut self) -> Option<&mut dyn BufRead> {
+        self.input
+            .as_mut()
+            .map(|input| input.as_mut() as &mut dyn BufRead)
+    }
+
+    fn random(&mut self) -> &mut Random {
+        &mut self.random
+    }
+
+    fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    fn eval(&mut self, program: Program) -> Object {
+        let constants = self.constants.iter().map(|c| c.as_ref().clone()).collect();
+        let mut compiler = RegisterCompiler::new_with_state(self.symbol_table.clone(), constants);
+        compiler.set_max_globals(self.globals.len());
+        if let Err(error) = compiler.compile(&program) {
+            return Object::error(error);
+        }
+
+        let RegisterBytecode {
+            main,
+            constants,
+            symbol_table,
+            ..
+        } = compiler.bytecode();
+        let first_new_constant = self.constants.len();
+        self.constants.extend(
+            constants
+                .into_iter()
+                .skip(first_new_constant)
+                .map(cache::share),
+        );
+        self.symbol_table = symbol_table;
+
+        // The program runs as a function without parameters, its variables
+        // are globals
+        let function = CompiledFunction {
+            source_map: SourceMap::default(),
+            ..main
+        };
+        self.apply(&Object::CLOSURE(Closure::new(Rc::new(function))), vec![])
+    }
+}
@@ -0,0 +1,272 @@
+#[allow(clippy::too_many_lines)]
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, rc::Rc};
+
+    use crate::{
+        compiler::Compiler,
+        object::{Object, StackFrame},
+        parser::parse,
+        source::{Position, Source},
+        vm::{
+            register::{
+                code::disassemble,
+                compiler::{RegisterBytecode, RegisterCompiler},
+                RegisterVM,
+            },
+            VM,
+        },
+    };
+
+    fn compile(input: &str) -> RegisterBytecode {
+        let mut compiler = RegisterCompiler::new();
+        compiler.compile(&parse(input)).unwrap();
+        compiler.bytecode()
+    }
+
+    fn run(input: &str) -> Result<Rc<Object>, String> {
+        let mut vm = RegisterVM::new(compile(input));
+        vm.run().map(|()| vm.result())
+    }
+
+    fn run_tests(tests: &[(&str, Object)]) {
+        for (input, expected) in tests {
+            assert_eq!(run(input).as_deref(), Ok(expected), "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_expressions() {
+        run_tests(&[
+            ("1 + 2 * 3 - 4 / 2", Object::INTEGER(5)),
+            ("-5 + 10 % 3", Object::INTEGER(-4)),
+            ("1 < 2", Object::BOOLEAN(true)),
+            ("2 <= 1", Object::BOOLEAN(false)),
+            ("(1 > 2) == false", Object::BOOLEAN(true)),
+            ("!true || !!5", Object::BOOLEAN(true)),
+            ("true && false", Object::BOOLEAN(false)),
+            (r#""mon" + "key""#, Object::STRING("monkey".into())),
+            (r#"b"ab"[1]"#, Object::INTEGER(98)),
+            ("if (1 > 2) { 10 }", Object::NULL),
+            ("if (1 < 2) { 10 } else { 20 }", Object::INTEGER(10)),
+            ("[1, 2 * 2, 3][1]", Object::INTEGER(4)),
+            ("[1, 2][5]", Object::NULL),
+            (r#"{"a": 1, 2: true}["a"]"#, Object::INTEGER(1)),
+            (
+                "{1: 2}",
+                Object::HASHMAP(HashMap::from([(Object::INTEGER(1), Object::INTEGER(2))])),
+            ),
+        ]);
+    }
+
+    #[test]
+    fn test_variables() {
+        run_tests(&[
+            ("let a = 1; let b = a + 1; a + b", Object::INTEGER(3)),
+            ("let a = 1; let a = a + 1; a", Object::INTEGER(2)),
+            ("let a = 1;", Object::NULL),
+            (
+                "let a = [1, [2, 3]]; a[1][0] = 5; a",
+                Object::ARRAY(vec![
+                    Object::INTEGER(1),
+                    Object::ARRAY(vec![Object::INTEGER(5), Object::INTEGER(3)]),
+                ]),
+            ),
+            (
+                "let a = [1, 2]; let b = a; b[0] = 3; a[0] + b[0]",
+                Object::INTEGER(4),
+            ),
+        ]);
+    }
+
+    #[test]
+    fn test_functions() {
+        run_tests(&[
+            ("let add = fn(a, b) { a + b }; add(1, 2)", Object::INTEGER(3)),
+            ("fn() { return 1; 2 }()", Object::INTEGER(1)),
+            ("fn() { let a = 1; }()", Object::NULL),
+            ("fn() { }()", Object::NULL),
+            (
+                "let adder = fn(a) { fn(b) { a + b } }; adder(1)(2)",
+                Object::INTEGER(3),
+            ),
+            (
+                "let f = fn(a) { let b = a; fn() { fn() { a + b } } }; f(2)()()",
+                Object::INTEGER(4),
+            ),
+            (
+                "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(15)",
+                Object::INTEGER(610),
+            ),
+            (
+                "let f = fn() { let count = fn(n) { if (n == 0) { 0 } else { count(n - 1) + 1 } }; count(5) }; f()",
+                Object::INTEGER(5),
+            ),
+            (
+                "let f = fn(a) { a[0] = 2; a }; let a = [1]; f(a)[0] + a[0]",
+                Object::INTEGER(3),
+            ),
+            (
+                "let f = fn(x) { x + if (true) { let x = 10; x } else { 0 } }; f(1)",
+                Object::INTEGER(11),
+            ),
+        ]);
+    }
+
+    #[test]
+    fn test_loops() {
+        run_tests(&[
+            (
+                "let i = 0; let sum = 0; while (i < 10) { let i = i + 1; if (i == 5) { continue; } let sum = sum + i; } sum",
+                Object::INTEGER(50),
+            ),
+            (
+                "let f = fn() { let i = 0; while (true) { let i = i + 1; if (i > 3) { break; } } i }; f()",
+                Object::INTEGER(4),
+            ),
+        ]);
+    }
+
+    #[test]
+    fn test_builtins() {
+        run_tests(&[
+            ("len([1, 2, 3])", Object::INTEGER(3)),
+            (
+                "map([1, 2], fn(x) { x * 2 })",
+                Object::ARRAY(vec![Object::INTEGER(2), Object::INTEGER(4)]),
+            ),
+            (
+                "reduce([1, 2, 3], 0, fn(a, b) { a + b })",
+                Object::INTEGER(6),
+            ),
+            (r#"let a = 2; eval("a * 3")"#, Object::INTEGER(6)),
+            (r#"eval("let b = 4;"); b + 1"#, Object::INTEGER(5)),
+        ]);
+    }
+
+    #[test]
+    fn test_same_results_as_the_stack_vm() {
+        let inputs = [
+            "let a = [1, 2, 3]; let i = 0; while (i < 3) { a[i] = a[i] * a[i]; let i = i + 1; } a",
+            r#"let h = {"x": {"y": 1}}; h["x"]["y"] = 2; h["x"]["z"] = 3; h"#,
+            "let f = fn(n, acc) { if (n == 0) { acc } else { f(n - 1, acc * n) } }; f(10, 1)",
+            "filter(map([1, 2, 3, 4], fn(x) { x * x }), fn(x) { x % 2 == 0 })",
+            r#"let greet = fn(name) { "hello " + name }; [greet("a"), greet("b")]"#,
+        ];
+        for input in inputs {
+            let mut compiler = Compiler::new();
+            compiler.compile(&parse(input)).unwrap();
+            let mut vm = VM::new(compiler.bytecode());
+            vm.run().unwrap();
+            let expected = vm.last_popped_stack_element().unwrap();
+            assert_eq!(run(input), Ok(expected), "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let tests = [
+            ("1 / 0", "Division by zero"),
+            ("1 + true", "Unsupported types for binary operation"),
+            ("-true", "Unsupported type for minus operation"),
+            ("1()", "Calling non-function"),
+            ("fn(a) { a }()", "Wrong number of arguments: want=1, got=0"),
+            ("{[1]: 2}", "Unusable as hashmap key: ARRAY"),
+            ("assert(false)", "assertion failed"),
+            ("map([1], fn(x) { x / 0 })", "Division by zero"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(run(input), Err(expected.to_string()), "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_exit() {
+        let mut vm = RegisterVM::new(compile("let a = 1; exit(3); let a = 2;"));
+        assert_eq!(vm.run(), Ok(()));
+        assert_eq!(vm.exit_code(), Some(3));
+
+        let mut vm = RegisterVM::new(compile("exit(300); 1"));
+        assert_eq!(
+            vm.run(),
+            Err("exit code 300 is not supported, it must be between 0 and 255".to_string())
+        );
+    }
+
+    #[test]
+    fn test_limits() {
+        let input = "let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } };";
+        let mut vm = RegisterVM::new(compile(&format!("{input} f(10)")));
+        vm.set_max_depth(10);
+        assert_eq!(
+            vm.run(),
+            Err("Maximum call depth of 10 exceeded when calling `f`".to_string())
+        );
+
+        let mut vm = RegisterVM::new(compile(&format!("{input} f(100)")));
+        vm.set_max_registers(50);
+        assert_eq!(
+            vm.run(),
+            Err("Stack overflow: the registers are limited to 50 slots".to_string())
+        );
+
+        let mut vm = RegisterVM::new(compile("while (true) { 1 }"));
+        vm.set_fuel(1000);
+        assert_eq!(vm.run(), Err("Fuel exhausted".to_string()));
+    }
+
+    #[test]
+    fn test_stack_trace() {
+        let input = "let check = fn(x) {\n  x + true\n};\nlet apply = fn(f) { f(1) };\napply(fn(x) { check(x) });";
+        let mut compiler = RegisterCompiler::new();
+        compiler
+            .compile(&parse(input).with_source(Source::new("test.monkey", input)))
+            .unwrap();
+        let mut vm = RegisterVM::new(compiler.bytecode());
+        assert_eq!(
+            vm.run(),
+            Err("Unsupported types for binary operation".to_string())
+        );
+
+        let frame = |function: &str, line, column| StackFrame {
+            function: function.to_string(),
+            position: Some(Position { line, column }),
+        };
+        assert_eq!(
+            vm.stack_trace(),
+            vec![
+                frame("main", 5, 1),
+                frame("apply", 4, 21),
+                frame("<anonymous>", 5, 15),
+                frame("check", 2, 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_instructions() {
+        let bytecode = compile("let a = 1; a + 2");
+        assert_eq!(
+            disassemble(&bytecode.main.instructions),
+            "0000 OpLoadConstant r1 0\n\
+             0005 OpSetGlobal 0 r1\n\
+             0010 OpGetGlobal r1 0\n\
+             0015 OpLoadConstant r2 1\n\
+             0020 OpAdd r0 r1 r2\n\
+             0027 OpReturn r0\n"
+        );
+
+        // The locals are read in place
+        let bytecode = compile("fn(a, b) { let c = a * b; c + a }");
+        let Object::COMPILEDFUNCTION(function) = &bytecode.constants[0] else {
+            panic!("expected a function, got {}", bytecode.constants[0]);
+        };
+        assert_eq!(
+            disassemble(&function.instructions),
+            "0000 OpMul r3 r0 r1\n\
+             0007 OpAdd r2 r3 r0\n\
+             0014 OpReturn r2\n"
+        );
+        assert_eq!(function.num_locals, 4);
+    }
+}
@@ -39,7 +39,7 @@ mod tests {
                     let d = a + b + c;
                     d"#
                 .to_string(),
-                expected: Object::STRING("helloworldhelloworld".to_string()),
+                expected: Object::STRING("helloworldhelloworld".into()),
             },
         ];
 
@@ -119,7 +119,7 @@ mod tests {
                     let x = "string";
                     x"#
                 .to_string(),
-                expected: Object::STRING("string".to_string()),
+                expected: Object::STRING("string".into()),
             },
             VmTestCase {
                 input: r#"
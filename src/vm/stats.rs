@@ -0,0 +1,88 @@
+use std::fmt::{self, Display};
+
+use crate::{object::Object, vm::value::Value};
+
+/// Number of instructions between two countings of the objects alive, which
+/// goes through all of them.
+pub(super) const SAMPLE_INTERVAL: u32 = 1024;
+
+/// Memory used by a run of the VM, collected once [`VM::collect_stats`] is
+/// called.
+///
+/// [`VM::collect_stats`]: super::VM::collect_stats
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Stats {
+    /// Most values alive at once in the stack and the globals, with the
+    /// elements of arrays and hashes and the free variables of closures. A
+    /// value referenced twice counts twice. They are counted every few
+    /// instructions and at the end of the run.
+    pub peak_objects: usize,
+    pub constants: usize,
+    /// Globals defined by the program.
+    pub globals: usize,
+    /// Most slots of the stack used at once.
+    pub max_stack_size: usize,
+    /// Most nested calls, the main program excluded.
+    pub max_depth: usize,
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:<24} {:>10}", "peak objects", self.peak_objects)?;
+        writeln!(f, "{:<24} {:>10}", "constants", self.constants)?;
+        writeln!(f, "{:<24} {:>10}", "globals", self.globals)?;
+        writeln!(f, "{:<24} {:>10}", "max stack size", self.max_stack_size)?;
+        writeln!(f, "{:<24} {:>10}", "max call depth", self.max_depth)
+    }
+}
+
+/// Returns the number of values making up `values`, like [`count_objects`].
+pub(super) fn count_values<'a>(values: impl Iterator<Item = &'a Value>) -> usize {
+    values
+        .map(|value| match value {
+            Value::Object(object) => count_objects(object),
+            _ => 1,
+        })
+        .sum()
+}
+
+/// Returns the number of values making up `object`, itself included.
+pub(super) fn count_objects(object: &Object) -> usize {
+    1 + match object {
+        Object::ARRAY(elements) => elements.iter().map(count_objects).sum(),
+        Object::HASHMAP(pairs) => pairs
+            .iter()
+            .map(|(key, value)| count_objects(key) + count_objects(value))
+            .sum(),
+        Object::CLOSURE(closure) => closure.free.iter().map(count_objects).sum(),
+        Object::RETURN(value) => count_objects(value),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn test_count_objects() {
+        assert_eq!(count_objects(&Object::INTEGER(1)), 1);
+        assert_eq!(count_objects(&Object::STRING("monkey".into())), 1);
+        assert_eq!(
+            count_objects(&Object::ARRAY(vec![
+                Object::INTEGER(1),
+                Object::ARRAY(vec![Object::INTEGER(2), Object::INTEGER(3)]),
+            ])),
+            5
+        );
+        assert_eq!(
+            count_objects(&Object::HASHMAP(HashMap::from([(
+                Object::STRING("a".into()),
+                Object::ARRAY(vec![Object::NULL]),
+            )]))),
+            4
+        );
+    }
+}
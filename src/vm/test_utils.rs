@@ -2,7 +2,7 @@ use crate::{
     compiler::{code::Instructions, Compiler},
     object::{test_utils::check_constants, Object},
     parser::parse,
-    vm::VM,
+    vm::{error::VmError, VM},
 };
 
 #[allow(dead_code)]
@@ -17,7 +17,7 @@ pub(crate) fn run_vm_tests(tests: Vec<VmTestCase>) {
         println!("Running test: {}", test.input);
         let program = parse(&test.input);
         let mut compiler = Compiler::new();
-        compiler.compile(program).unwrap();
+        compiler.compile(&program).unwrap();
         let bytecode = compiler.bytecode();
 
         for (i, constant) in bytecode.constants.iter().enumerate() {
@@ -39,10 +39,10 @@ pub(crate) fn run_vm_tests(tests: Vec<VmTestCase>) {
 }
 
 #[allow(dead_code)]
-pub(crate) fn run_vm_with_error_output(input: &str) -> Result<(), String> {
+pub(crate) fn run_vm_with_error_output(input: &str) -> Result<(), VmError> {
     let program = parse(input);
     let mut compiler = Compiler::new();
-    compiler.compile(program).unwrap();
+    compiler.compile(&program).unwrap();
     let bytecode = compiler.bytecode();
 
     let mut vm = VM::new(bytecode);
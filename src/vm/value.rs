@@ -0,0 +1,126 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    rc::Rc,
+};
+
+use crate::object::{cache, Object};
+
+/// Value of a slot of the stack, a global or a constant of the VM.
+///
+/// Integers, booleans and `null` are stored in the slot itself, so computing
+/// with them neither allocates nor follows a pointer. The other objects live
+/// on the heap, shared between the slots holding them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Integer(i64),
+    Boolean(bool),
+    Null,
+    Object(Rc<Object>),
+}
+
+impl Value {
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Null => false,
+            Value::Boolean(b) => *b,
+            Value::Object(object) => object.is_truthy(),
+            Value::Integer(_) => true,
+        }
+    }
+
+    /// Returns a copy of the value as an object.
+    pub fn to_object(&self) -> Object {
+        match self {
+            Value::Integer(i) => Object::INTEGER(*i),
+            Value::Boolean(b) => Object::BOOLEAN(*b),
+            Value::Null => Object::NULL,
+            Value::Object(object) => object.as_ref().clone(),
+        }
+    }
+
+    /// Returns the value as a shared object, taken from the cache or
+    /// allocated if it is stored in the slot.
+    pub fn into_rc(self) -> Rc<Object> {
+        match self {
+            Value::Object(object) => object,
+            value => cache::share(value.to_object()),
+        }
+    }
+
+    /// Returns the object the value points to, `None` for the values stored
+    /// in the slot.
+    pub fn as_object(&self) -> Option<&Object> {
+        match self {
+            Value::Object(object) => Some(object),
+            _ => None,
+        }
+    }
+}
+
+impl From<Object> for Value {
+    fn from(object: Object) -> Self {
+        match object {
+            Object::INTEGER(i) => Value::Integer(i),
+            Object::BOOLEAN(b) => Value::Boolean(b),
+            Object::NULL => Value::Null,
+            object => Value::Object(Rc::new(object)),
+        }
+    }
+}
+
+impl From<Rc<Object>> for Value {
+    fn from(object: Rc<Object>) -> Self {
+        match *object {
+            Object::INTEGER(i) => Value::Integer(i),
+            Object::BOOLEAN(b) => Value::Boolean(b),
+            Object::NULL => Value::Null,
+            _ => Value::Object(object),
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Object(object) => write!(f, "{object}"),
+            value => write!(f, "{}", value.to_object()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_immediate_values() {
+        assert_eq!(Value::from(Object::INTEGER(5)), Value::Integer(5));
+        assert_eq!(
+            Value::from(Rc::new(Object::BOOLEAN(true))),
+            Value::Boolean(true)
+        );
+        assert_eq!(Value::from(Object::NULL), Value::Null);
+
+        let array = Rc::new(Object::ARRAY(vec![Object::INTEGER(1)]));
+        let Value::Object(object) = Value::from(Rc::clone(&array)) else {
+            panic!("arrays are shared");
+        };
+        assert!(Rc::ptr_eq(&object, &array));
+    }
+
+    #[test]
+    fn test_conversions() {
+        let values = [
+            Object::INTEGER(-3),
+            Object::BOOLEAN(false),
+            Object::NULL,
+            Object::STRING("a".into()),
+        ];
+        for object in values {
+            let value = Value::from(object.clone());
+            assert_eq!(value.to_string(), object.to_string());
+            assert_eq!(value.is_truthy(), object.is_truthy());
+            assert_eq!(*value.into_rc(), object);
+        }
+    }
+}
@@ -4,8 +4,17 @@ mod tests {
     use std::collections::HashMap;
 
     use crate::{
+        compiler::{
+            code::{Instructions, Opcode},
+            Compiler,
+        },
         object::Object,
-        vm::test_utils::{run_vm_tests, run_vm_with_error_output, VmTestCase},
+        parser::parse,
+        vm::{
+            error::VmError,
+            test_utils::{run_vm_tests, run_vm_with_error_output, VmTestCase},
+            VM,
+        },
     };
 
     #[test]
@@ -288,15 +297,15 @@ mod tests {
         let tests = vec![
             VmTestCase {
                 input: "\"monkey\"".to_string(),
-                expected: Object::STRING("monkey".to_string()),
+                expected: Object::STRING("monkey".into()),
             },
             VmTestCase {
                 input: "\"mon\" + \"key\"".to_string(),
-                expected: Object::STRING("monkey".to_string()),
+                expected: Object::STRING("monkey".into()),
             },
             VmTestCase {
                 input: "\"mon\" + \"key\" + \"banana\"".to_string(),
-                expected: Object::STRING("monkeybanana".to_string()),
+                expected: Object::STRING("monkeybanana".into()),
             },
         ];
 
@@ -329,7 +338,7 @@ mod tests {
             VmTestCase {
                 input: "[\"yes\", false, [1,2]]".to_string(),
                 expected: Object::ARRAY(vec![
-                    Object::STRING("yes".to_string()),
+                    Object::STRING("yes".into()),
                     Object::BOOLEAN(false),
                     Object::ARRAY(vec![Object::INTEGER(1), Object::INTEGER(2)]),
                 ]),
@@ -419,4 +428,306 @@ mod tests {
 
         run_vm_tests(tests);
     }
+
+    #[test]
+    fn test_index_assignment() {
+        let tests = vec![
+            ("let a = [1, 2, 3]; a[0] = 4; a", "[4, 2, 3]"),
+            // The other copies of the collection do not change
+            (
+                "let a = [1, 2]; let b = a; a[0] = 3; [a, b]",
+                "[[3, 2], [1, 2]]",
+            ),
+            (
+                r#"let h = {"a": 1}; h["b"] = 2; h["a"] = 3; [h["a"], h["b"]]"#,
+                "[3, 2]",
+            ),
+            (
+                "let grid = [[0, 0], [0, 0]]; grid[1][0] = 5; grid",
+                "[[0, 0], [5, 0]]",
+            ),
+            (
+                "let a = [0, 0, 0]; let i = 0; while (i < 3) { a[i] = i * i; let i = i + 1; } a",
+                "[0, 1, 4]",
+            ),
+            // Like a `let`, it defines a local variable in a function
+            (
+                "let a = [1]; let f = fn() { a[0] = 2; a }; [f(), a]",
+                "[[2], [1]]",
+            ),
+            (
+                "let f = fn(a) { a[0] = a[0] + 1; a }; let a = [1]; [f(a), a]",
+                "[[2], [1]]",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse(input);
+            let mut compiler = Compiler::new();
+            compiler.compile(&program).unwrap();
+            let mut vm = VM::new(compiler.bytecode());
+            vm.run().unwrap();
+            assert_eq!(
+                vm.last_popped_stack_element().unwrap().to_string(),
+                expected
+            );
+        }
+
+        for (input, error) in [
+            (
+                "let a = [1]; a[1] = 2",
+                "index 1 out of range for an array of length 1",
+            ),
+            ("let a = {}; a[[]] = 2", "unusable as hash key: ARRAY"),
+            (
+                "let a = 1; a[0] = 2",
+                "index assignment not supported: INTEGER[INTEGER]",
+            ),
+        ] {
+            assert_eq!(
+                run_vm_with_error_output(input),
+                Err(VmError::IndexAssignment(error.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_jump_table() {
+        let name = r#"
+        let name = fn(x) {
+            if (x == 1) { "one" } else {
+                if (x == 2) { "two" } else {
+                    if (x == 4) { "four" } else {
+                        if (x == 2) { "two again" } else { "other" }
+                    }
+                }
+            }
+        };
+        "#;
+        let tests = [
+            ("name(1)", "one"),
+            ("name(2)", "two"),
+            ("name(3)", "other"),
+            ("name(4)", "four"),
+            ("name(0)", "other"),
+            ("name(5)", "other"),
+            ("name(-1)", "other"),
+        ];
+        for (call, expected) in tests {
+            let mut compiler = Compiler::new();
+            compiler.compile(&parse(&format!("{name}{call}"))).unwrap();
+            let instructions = compiler.bytecode().constants.iter().any(|constant| {
+                matches!(constant, Object::COMPILEDFUNCTION(function)
+                    if function.instructions.contains(&(Opcode::JumpTable as u8)))
+            });
+            assert!(instructions);
+            let mut vm = VM::new(compiler.bytecode());
+            vm.run().unwrap();
+            assert_eq!(
+                vm.last_popped_stack_element().unwrap().as_ref(),
+                &Object::STRING(expected.into())
+            );
+        }
+
+        assert_eq!(
+            run_vm_with_error_output(&format!("{name}name(true)")),
+            Err(VmError::TypeMismatch(
+                "Unsupported types for comparison".to_string()
+            ))
+        );
+        // Without a default arm
+        let tests = vec![VmTestCase {
+            input:
+                "let x = 7; if (x == 5) { 1 } else { if (x == 6) { 2 } else { if (x == 8) { 3 } } }"
+                    .to_string(),
+            expected: Object::NULL,
+        }];
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_superinstructions() {
+        let run = |input: &str, superinstructions: bool| {
+            let mut compiler = Compiler::new();
+            compiler.set_superinstructions(superinstructions);
+            compiler.compile(&parse(input)).unwrap();
+            let opcodes: Vec<Opcode> = compiler
+                .bytecode()
+                .constants
+                .iter()
+                .filter_map(|constant| match constant {
+                    Object::COMPILEDFUNCTION(function) => Some(function.instructions.clone()),
+                    _ => None,
+                })
+                .chain([compiler.bytecode().instructions.data.clone()])
+                .flat_map(|instructions| {
+                    Instructions::new(instructions)
+                        .iter()
+                        .map(|(_, op, _)| op)
+                        .collect::<Vec<_>>()
+                })
+                .collect();
+            let mut vm = VM::new(compiler.bytecode());
+            let result = vm.run().map(|()| vm.last_popped_stack_element().unwrap());
+            (result, opcodes)
+        };
+
+        let tests = [
+            ("1 + 2", Opcode::AddConstants),
+            (r#""mon" + "key""#, Opcode::AddConstants),
+            (
+                "fn(x) { if (x == 1) { 10 } else { 20 } }(1)",
+                Opcode::CompareLocalConstantJump,
+            ),
+            (
+                "fn(x) { if (x != 1) { 10 } else { 20 } }(1)",
+                Opcode::CompareLocalConstantJump,
+            ),
+            (
+                "fn(x) { if (x > 1) { 10 } }(1)",
+                Opcode::CompareLocalConstantJump,
+            ),
+            (
+                "fn(x) { if (x >= 1) { 10 } }(1)",
+                Opcode::CompareLocalConstantJump,
+            ),
+            (
+                "fn(x) { if (x < 2) { 10 } else { 20 } }(1)",
+                Opcode::CompareConstantLocalJump,
+            ),
+            (
+                "fn(x) { if (x <= 0) { 10 } else { 20 } }(1)",
+                Opcode::CompareConstantLocalJump,
+            ),
+            (
+                r#"fn(x) { if (x == "one") { 10 } else { 20 } }("one")"#,
+                Opcode::CompareLocalConstantJump,
+            ),
+            (
+                "let f = fn() { let i = 0; while (i < 5) { let i = i + 1; }; i }; f()",
+                Opcode::CompareConstantLocalJump,
+            ),
+        ];
+        for (input, superinstruction) in tests {
+            let (fused, opcodes) = run(input, true);
+            assert!(opcodes.contains(&superinstruction), "{input}");
+            let (unfused, opcodes) = run(input, false);
+            assert!(!opcodes.contains(&superinstruction), "{input}");
+            assert_eq!(fused, unfused, "{input}");
+        }
+
+        assert_eq!(
+            run_vm_with_error_output(r#"fn(x) { if (x > 1) { 10 } }("one")"#),
+            Err(VmError::TypeMismatch(
+                "Unsupported types for comparison".to_string()
+            ))
+        );
+        assert_eq!(
+            run_vm_with_error_output("1 + true"),
+            Err(VmError::TypeMismatch(
+                "Unsupported types for binary operation".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_index_assignment_in_place() {
+        let mut compiler = Compiler::new();
+        compiler
+            .compile(&parse(
+                "let a = [1, 2]; let b = [a]; a[0] = 3; b[0][1] = 4;",
+            ))
+            .unwrap();
+        let mut vm = VM::new(compiler.bytecode());
+        // OpConstant, OpConstant, OpArray and OpSetGlobal
+        for _ in 0..4 {
+            vm.step().unwrap();
+        }
+        let a: *const Object = vm.globals[0].as_object().unwrap();
+        vm.run().unwrap();
+
+        // b holds a copy of the array, a could be updated in place
+        assert!(std::ptr::eq(vm.globals[0].as_object().unwrap(), a));
+        assert_eq!(vm.globals[0].to_string(), "[3, 2]");
+        assert_eq!(vm.globals[1].to_string(), "[[1, 4]]");
+    }
+
+    #[test]
+    fn test_constants_are_loaded_once() {
+        let mut compiler = Compiler::new();
+        compiler
+            .compile(&parse(
+                r#"let f = fn() { "monkey" }; let a = f(); let b = f();"#,
+            ))
+            .unwrap();
+        let bytecode = compiler.bytecode();
+        for _ in 0..2 {
+            let mut vm = VM::new(bytecode.clone());
+            vm.run().unwrap();
+            assert!(std::ptr::eq(
+                vm.globals[1].as_object().unwrap(),
+                vm.globals[2].as_object().unwrap()
+            ));
+            assert!(std::ptr::eq(vm.constants(), bytecode.constants.as_slice()));
+        }
+    }
+
+    #[test]
+    fn test_programs_loaded_in_one_vm() {
+        let lines = [
+            (r#"let a = "mon"; a"#, Ok(Object::STRING("mon".into()))),
+            (
+                r#"let f = fn(x) { a + x }; f("key")"#,
+                Ok(Object::STRING("monkey".into())),
+            ),
+            (r#"eval("let b = 2; 5")"#, Ok(Object::INTEGER(5))),
+            ("b * 3", Ok(Object::INTEGER(6))),
+            ("1 / 0", Err(VmError::DivisionByZero)),
+            (r#"f("s") + "!""#, Ok(Object::STRING("mons!".into()))),
+        ];
+        let mut vm = VM::new(Compiler::new().into_bytecode());
+        for (input, expected) in lines {
+            let mut compiler = vm.new_compiler();
+            compiler.compile(&parse(input)).unwrap();
+            vm.load(compiler.into_bytecode());
+            let got = vm
+                .run()
+                .map(|()| vm.last_popped_stack_element().unwrap().as_ref().clone());
+            assert_eq!(got, expected, "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_strict_conditions() {
+        use crate::strict::StrictConfig;
+
+        let run_strict = |input: &str| {
+            let mut compiler = Compiler::new();
+            compiler.compile(&parse(input)).unwrap();
+            let mut vm = VM::new(compiler.bytecode());
+            vm.set_strict(StrictConfig::strict());
+            vm.run()
+                .map(|()| vm.last_popped_stack_element().unwrap().as_ref().clone())
+        };
+        assert_eq!(
+            run_strict("let n = 1; if (n < 2) { 3 } else { 4 }"),
+            Ok(Object::INTEGER(3))
+        );
+        assert_eq!(
+            run_strict("let i = 0; while (i != 3) { let i = i + 1; } i"),
+            Ok(Object::INTEGER(3))
+        );
+        assert_eq!(
+            run_strict("let n = 1; if (n) { 3 }"),
+            Err(VmError::TypeMismatch(
+                "condition must be a BOOLEAN, got INTEGER".to_string()
+            ))
+        );
+        assert_eq!(
+            run_strict(r#"while ("a") { 1 }"#),
+            Err(VmError::TypeMismatch(
+                "condition must be a BOOLEAN, got STRING".to_string()
+            ))
+        );
+    }
 }
@@ -7,6 +7,16 @@ mod tests {
         vm::test_utils::{run_vm_tests, VmTestCase},
     };
 
+    #[test]
+    fn test_while_statements_with_large_bodies() {
+        // The body is larger than 65535 bytes, so the jumps are wide
+        let body = "let a = a + 1;".repeat(7000);
+        run_vm_tests(vec![VmTestCase {
+            input: format!("let i = 0; let a = 0; while (i < 2) {{ let i = i + 1; {body} }} a"),
+            expected: Object::INTEGER(14000),
+        }]);
+    }
+
     #[test]
     fn test_while_statements_without_break_or_continue() {
         let tests = vec![
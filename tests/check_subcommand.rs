@@ -0,0 +1,49 @@
+// Tests of the `check` subcommand, run through the binary for its exit status
+// and its diagnostics.
+
+use std::process::Command;
+
+fn check(name: &str, source: &str) -> std::process::Output {
+    let path = std::env::temp_dir().join(name);
+    std::fs::write(&path, source).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_monkey"))
+        .arg("check")
+        .arg(&path)
+        .output()
+        .unwrap();
+    std::fs::remove_file(path).unwrap();
+    output
+}
+
+#[test]
+fn test_check_valid_file() {
+    let output = check("chimpanzee_check_valid.monkey", "while (true) { break; }");
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn test_check_loop_statements_outside_of_loops() {
+    for (name, source, expected) in [
+        (
+            "chimpanzee_check_break.monkey",
+            "let x = 1;\nbreak;",
+            "`break` outside of a loop",
+        ),
+        (
+            "chimpanzee_check_continue.monkey",
+            "continue;",
+            "`continue` outside of a loop",
+        ),
+        (
+            "chimpanzee_check_closure.monkey",
+            "while (true) { let f = fn() { break; }; f(); }",
+            "`break` outside of a loop",
+        ),
+    ] {
+        let output = check(name, source);
+        assert_eq!(output.status.code(), Some(1), "{source}");
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains(expected), "{stderr}");
+    }
+}